@@ -4,6 +4,8 @@ fn main() -> Result<()> {
     // TODO(Ian): This doesnt actually guarentee correct naming per the issue but at least useful for debugging
     config.type_attribute(".", "#[derive(serde::Serialize)]");
     config.type_attribute(".", "#[serde(rename_all = \"camelCase\")]");
+    // Map fields are ordered so that serialized mappings are identical across runs
+    config.btree_map(&["."]);
     config.compile_protos(
         &[
             "data_formats/ctypes.proto",
@@ -0,0 +1,45 @@
+#![no_main]
+use std::collections::BTreeSet;
+
+use binary_type_inference::{
+    constraints::{parse_constraint_set, TyConstraint, VariableManager},
+    solver::constraint_graph::{RuleContext, FSA},
+    util::FileDebugLogger,
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+
+    let cons = match parse_constraint_set(text) {
+        Ok((_, cons)) => cons,
+        Err(_) => return,
+    };
+
+    // the variables of the first constraint are solved for so that the interesting set depends on the input
+    let interesting = cons
+        .iter()
+        .filter_map(|c| match c {
+            TyConstraint::SubTy(sub) => Some(vec![
+                sub.lhs.get_base_variable().clone(),
+                sub.rhs.get_base_variable().clone(),
+            ]),
+            _ => None,
+        })
+        .next()
+        .unwrap_or_default()
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+
+    if let Ok(mut fsa) = FSA::new(&cons, &RuleContext::new(interesting)) {
+        let _ = fsa.simplify_graph(
+            "",
+            &mut FileDebugLogger::default(),
+            &mut VariableManager::new(),
+        );
+        let _ = fsa.walk_constraints();
+    }
+});
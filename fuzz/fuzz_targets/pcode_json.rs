@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+// The binary only provides the base address, so a fixed fixture is used and the IR is fuzzed.
+const BINARY: &[u8] = include_bytes!("../../test_data/cwe_560_aarch64_gcc.out");
+
+fuzz_target!(|data: &[u8]| {
+    let _ = binary_type_inference::util::get_intermediate_representation_for_reader(data, BINARY);
+});
@@ -0,0 +1,84 @@
+#![no_main]
+use libfuzzer_sys::{arbitrary::Arbitrary, fuzz_target};
+use serde_json::Value;
+
+const BINARY: &[u8] = include_bytes!("../../test_data/cwe_560_aarch64_gcc.out");
+const IR_JSON: &str = include_str!("../../test_data/cwe_560_aarch64_gcc_ir.json");
+
+// Random bytes are almost never valid json, so this target mutates a well formed export instead to reach the
+// normalization and conversion of the IR.
+#[derive(Arbitrary, Debug)]
+enum Replacement {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    EmptyArray,
+    EmptyObject,
+    Remove,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Mutation {
+    // at each level, the index of the child to descend into, modulo the number of children
+    path: Vec<u16>,
+    replacement: Replacement,
+}
+
+fn apply(value: &mut Value, path: &[u16], replacement: &Replacement) {
+    let (step, rest) = match path.split_first() {
+        Some(x) => x,
+        None => {
+            *value = match replacement {
+                Replacement::Null | Replacement::Remove => Value::Null,
+                Replacement::Bool(b) => Value::Bool(*b),
+                Replacement::Number(n) => Value::from(*n),
+                Replacement::String(s) => Value::String(s.clone()),
+                Replacement::EmptyArray => Value::Array(Vec::new()),
+                Replacement::EmptyObject => Value::Object(serde_json::Map::new()),
+            };
+            return;
+        }
+    };
+
+    match value {
+        Value::Array(children) if !children.is_empty() => {
+            let idx = *step as usize % children.len();
+            if rest.is_empty() && matches!(replacement, Replacement::Remove) {
+                children.remove(idx);
+            } else {
+                apply(&mut children[idx], rest, replacement);
+            }
+        }
+        Value::Object(children) if !children.is_empty() => {
+            let key = children
+                .keys()
+                .nth(*step as usize % children.len())
+                .expect("index is in bounds")
+                .clone();
+            if rest.is_empty() && matches!(replacement, Replacement::Remove) {
+                children.remove(&key);
+            } else {
+                apply(
+                    children.get_mut(&key).expect("key exists"),
+                    rest,
+                    replacement,
+                );
+            }
+        }
+        _ => apply(value, &[], replacement),
+    }
+}
+
+fuzz_target!(|mutations: Vec<Mutation>| {
+    let mut ir: Value = serde_json::from_str(IR_JSON).expect("fixture is valid json");
+    for mutation in mutations.iter() {
+        apply(&mut ir, &mutation.path, &mutation.replacement);
+    }
+
+    let bytes = serde_json::to_vec(&ir).expect("values serialize");
+    let _ = binary_type_inference::util::get_intermediate_representation_for_reader(
+        bytes.as_slice(),
+        BINARY,
+    );
+});
@@ -0,0 +1,356 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cwe_checker_lib::intermediate_representation::{ExternSymbol, Jmp, Program, Sub, Term, Tid};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constraint_generation::tid_to_tvar,
+    constraints::{
+        ConstraintSet, DerivedTypeVar, FieldLabel, SubtypeConstraint, TyConstraint, TypeVariable,
+    },
+};
+
+/// The largest number of blocks a subprocedure can have and still be considered a thin allocation wrapper.
+pub const MAX_WRAPPER_BLOCKS: usize = 4;
+
+/// A symbol that allocates a heap object and returns a pointer to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllocatorSpec {
+    /// The name of the allocating symbol
+    pub symbol: String,
+    /// The indices of the parameters that determine the size of the allocation
+    #[serde(default)]
+    pub size_params: Vec<usize>,
+}
+
+impl AllocatorSpec {
+    /// Creates an allocator with the given size parameters.
+    pub fn new(symbol: &str, size_params: Vec<usize>) -> AllocatorSpec {
+        AllocatorSpec {
+            symbol: symbol.to_owned(),
+            size_params,
+        }
+    }
+}
+
+/// The allocators recognized when none are configured.
+pub fn default_allocators() -> Vec<AllocatorSpec> {
+    vec![
+        AllocatorSpec::new("malloc", vec![0]),
+        AllocatorSpec::new("calloc", vec![0, 1]),
+        AllocatorSpec::new("xmalloc", vec![0]),
+        AllocatorSpec::new("realloc", vec![1]),
+    ]
+}
+
+pub(crate) fn called_targets(sub: &Sub) -> impl Iterator<Item = &Tid> {
+    sub.blocks.iter().flat_map(|blk| {
+        blk.term.jmps.iter().filter_map(|jmp| {
+            if let Jmp::Call { target, .. } = &jmp.term {
+                Some(target)
+            } else {
+                None
+            }
+        })
+    })
+}
+
+fn target_name<'a>(program: &'a Program, target: &Tid) -> Option<&'a str> {
+    program
+        .extern_symbols
+        .get(target)
+        .map(|ext| ext.name.as_str())
+        .or_else(|| program.subs.get(target).map(|sub| sub.term.name.as_str()))
+}
+
+/// Checks if the subprocedure is a thin wrapper around one of the allocators. A thin wrapper is small, returns a value,
+/// calls exactly one allocator, and otherwise only calls external functions (ie. to report allocation failure).
+/// Returns the allocator the wrapper calls.
+fn wrapped_allocator<'a>(
+    program: &Program,
+    sub: &Term<Sub>,
+    allocators: &'a BTreeMap<String, AllocatorSpec>,
+) -> Option<&'a AllocatorSpec> {
+    if sub.term.blocks.len() > MAX_WRAPPER_BLOCKS || sub.term.formal_rets.is_empty() {
+        return None;
+    }
+
+    let mut wrapped = None;
+    for target in called_targets(&sub.term) {
+        match target_name(program, target).and_then(|nm| allocators.get(nm)) {
+            Some(_) if wrapped.is_some() => return None,
+            Some(alloc) => wrapped = Some(alloc),
+            None if program.subs.contains_key(target) => return None,
+            None => (),
+        }
+    }
+
+    wrapped
+}
+
+/// Finds thin wrappers around the given allocators, including wrappers of wrappers.
+/// A wrapper is assumed to forward the size parameters of the allocator it wraps at the same positions.
+pub fn find_malloc_wrappers(program: &Program, allocators: &[AllocatorSpec]) -> Vec<AllocatorSpec> {
+    let mut known: BTreeMap<String, AllocatorSpec> = allocators
+        .iter()
+        .map(|alloc| (alloc.symbol.clone(), alloc.clone()))
+        .collect();
+    let mut wrappers = Vec::new();
+
+    loop {
+        let found = program
+            .subs
+            .values()
+            .filter(|sub| !known.contains_key(&sub.term.name))
+            .filter_map(|sub| {
+                wrapped_allocator(program, sub, &known).map(|alloc| {
+                    AllocatorSpec::new(
+                        &sub.term.name,
+                        alloc
+                            .size_params
+                            .iter()
+                            .cloned()
+                            .filter(|idx| *idx < sub.term.formal_args.len())
+                            .collect(),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if found.is_empty() {
+            return wrappers;
+        }
+
+        for wrapper in found {
+            known.insert(wrapper.symbol.clone(), wrapper.clone());
+            wrappers.push(wrapper);
+        }
+    }
+}
+
+/// The tid of the extern symbol stubbing the subprocedure with the given tid.
+pub fn stub_tid(sub: &Tid) -> Tid {
+    Tid::create(format!("{}_stub", sub.get_str_repr()), sub.address.clone())
+}
+
+/// Inserts an extern symbol for each wrapper subprocedure and retargets the calls to the wrapper to the symbol. Calls to the
+/// wrapper then become call stubs so the points-to analysis treats the wrapper as an allocator. The stub has its own tid,
+/// see [stub_tid], so that the type of the wrapper's body stays separate from the allocations at its callsites.
+/// Returns the number of retargeted calls.
+pub fn stub_allocator_wrappers(program: &mut Program, wrappers: &[AllocatorSpec]) -> usize {
+    let names = wrappers
+        .iter()
+        .map(|wrapper| wrapper.symbol.as_str())
+        .collect::<BTreeSet<_>>();
+
+    let stubs = program
+        .subs
+        .values()
+        .filter(|sub| names.contains(sub.term.name.as_str()))
+        .map(|sub| {
+            (
+                sub.tid.clone(),
+                ExternSymbol {
+                    tid: stub_tid(&sub.tid),
+                    addresses: vec![sub.tid.address.clone()],
+                    name: sub.term.name.clone(),
+                    calling_convention: sub.term.calling_convention.clone(),
+                    parameters: sub.term.formal_args.clone(),
+                    return_values: sub.term.formal_rets.clone(),
+                    no_return: false,
+                    has_var_args: false,
+                },
+            )
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let mut retargeted = 0;
+    for sub in program.subs.values_mut() {
+        for blk in sub.term.blocks.iter_mut() {
+            for jmp in blk.term.jmps.iter_mut() {
+                if let Jmp::Call { target, .. } = &mut jmp.term {
+                    if let Some(stub) = stubs.get(target) {
+                        *target = stub.tid.clone();
+                        retargeted += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    for (_, stub) in stubs {
+        program.extern_symbols.insert(stub.tid.clone(), stub);
+    }
+    retargeted
+}
+
+/// Constrains the size parameters of each call to an allocator to be integral. The constraints are keyed by the calling subprocedure.
+pub fn size_parameter_constraints(
+    program: &Program,
+    allocators: &[AllocatorSpec],
+    integral_type: &TypeVariable,
+) -> BTreeMap<Tid, ConstraintSet> {
+    let by_name = allocators
+        .iter()
+        .map(|alloc| (alloc.symbol.as_str(), alloc))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut total: BTreeMap<Tid, ConstraintSet> = BTreeMap::new();
+    for sub in program.subs.values() {
+        for target in called_targets(&sub.term) {
+            if let Some(alloc) = target_name(program, target).and_then(|nm| by_name.get(nm)) {
+                let cons = total
+                    .entry(sub.tid.clone())
+                    .or_insert_with(ConstraintSet::default);
+                for idx in alloc.size_params.iter() {
+                    cons.insert(TyConstraint::SubTy(SubtypeConstraint::new(
+                        DerivedTypeVar::create_with_label(
+                            &DerivedTypeVar::new(tid_to_tvar(target)),
+                            FieldLabel::In(*idx),
+                        ),
+                        DerivedTypeVar::new(integral_type.clone()),
+                    )));
+                }
+            }
+        }
+    }
+
+    total
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use cwe_checker_lib::intermediate_representation::{
+        Arg, Blk, ByteSize, Expression, ExternSymbol, Jmp, Program, Sub, Term, Tid, Variable,
+    };
+
+    use crate::{
+        constraint_generation::tid_to_tvar,
+        constraints::{DerivedTypeVar, FieldLabel, SubtypeConstraint, TyConstraint, TypeVariable},
+    };
+
+    use super::{
+        default_allocators, find_malloc_wrappers, size_parameter_constraints,
+        stub_allocator_wrappers, stub_tid, AllocatorSpec,
+    };
+
+    fn register(name: &str) -> Arg {
+        Arg::Register {
+            expr: Expression::Var(Variable {
+                name: name.to_owned(),
+                size: ByteSize::new(8),
+                is_temp: false,
+            }),
+            data_type: None,
+        }
+    }
+
+    fn call(address: &str, target: &Tid) -> Term<Blk> {
+        Term {
+            tid: Tid::create(format!("blk_{}", address), address.to_owned()),
+            term: Blk {
+                defs: Vec::new(),
+                jmps: vec![Term {
+                    tid: Tid::create(format!("instr_{}_jmp", address), address.to_owned()),
+                    term: Jmp::Call {
+                        target: target.clone(),
+                        return_: None,
+                    },
+                }],
+                indirect_jmp_targets: Vec::new(),
+            },
+        }
+    }
+
+    fn sub(name: &str, address: &str, blocks: Vec<Term<Blk>>) -> Term<Sub> {
+        Term {
+            tid: Tid::create(format!("sub_{}", address), address.to_owned()),
+            term: Sub {
+                name: name.to_owned(),
+                blocks,
+                formal_args: vec![register("RDI")],
+                formal_rets: vec![register("RAX")],
+                calling_convention: None,
+            },
+        }
+    }
+
+    #[test]
+    fn calls_to_wrappers_are_retargeted_to_their_own_stub() {
+        let malloc = ExternSymbol {
+            tid: Tid::create("malloc".to_owned(), "00401000".to_owned()),
+            addresses: vec!["00401000".to_owned()],
+            name: "malloc".to_owned(),
+            calling_convention: None,
+            parameters: vec![register("RDI")],
+            return_values: vec![register("RAX")],
+            no_return: false,
+            has_var_args: false,
+        };
+        // arena_alloc(sz) { return malloc(sz); } and main calling it twice
+        let wrapper = sub(
+            "arena_alloc",
+            "00402000",
+            vec![call("00402000", &malloc.tid)],
+        );
+        let main = sub(
+            "main",
+            "00403000",
+            vec![
+                call("00403000", &wrapper.tid),
+                call("00403010", &wrapper.tid),
+            ],
+        );
+        let mut program = Program {
+            subs: BTreeMap::from([
+                (wrapper.tid.clone(), wrapper.clone()),
+                (main.tid.clone(), main.clone()),
+            ]),
+            extern_symbols: BTreeMap::from([(malloc.tid.clone(), malloc)]),
+            entry_points: BTreeSet::new(),
+            address_base_offset: 0,
+            global_variables: BTreeMap::new(),
+        };
+
+        let wrappers = find_malloc_wrappers(&program, &default_allocators());
+        assert_eq!(wrappers, vec![AllocatorSpec::new("arena_alloc", vec![0])]);
+
+        assert_eq!(stub_allocator_wrappers(&mut program, &wrappers), 2);
+        let stub = stub_tid(&wrapper.tid);
+        assert_ne!(stub, wrapper.tid);
+        assert_eq!(program.extern_symbols[&stub].name, "arena_alloc");
+        // the wrapper keeps calling malloc and main calls the stub instead of the wrapper
+        assert!(program.subs.contains_key(&wrapper.tid));
+        let targets = |tid: &Tid| {
+            program.subs[tid]
+                .term
+                .blocks
+                .iter()
+                .flat_map(|blk| blk.term.jmps.iter())
+                .filter_map(|jmp| match &jmp.term {
+                    Jmp::Call { target, .. } => Some(target.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(targets(&main.tid), vec![stub.clone(), stub.clone()]);
+        assert_eq!(
+            targets(&wrapper.tid),
+            vec![Tid::create("malloc".to_owned(), "00401000".to_owned())]
+        );
+
+        // the size passed to the stub is integral
+        let int = TypeVariable::new("int".to_owned());
+        let sizes = size_parameter_constraints(&program, &wrappers, &int);
+        let size_of_stub = TyConstraint::SubTy(SubtypeConstraint::new(
+            DerivedTypeVar::create_with_label(
+                &DerivedTypeVar::new(tid_to_tvar(&stub)),
+                FieldLabel::In(0),
+            ),
+            DerivedTypeVar::new(int),
+        ));
+        assert!(sizes[&main.tid].contains(&size_of_stub));
+    }
+}
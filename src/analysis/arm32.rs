@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+
+use cwe_checker_lib::intermediate_representation::{Jmp, Project, Tid};
+
+/// Checks whether the architecture of a project is 32 bit ARM, whose code addresses may carry the Thumb bit.
+pub fn is_arm32(cpu_architecture: &str) -> bool {
+    let arch = cpu_architecture.to_ascii_lowercase();
+    arch.contains("arm") && !arch.contains("64")
+}
+
+/// Converts an address to the entry of the code it refers to. An odd address of a 32 bit ARM function, ie. a
+/// function pointer, a symbol value or the target of a `blx`, is its entry with the Thumb bit set.
+pub fn code_address(cpu_architecture: &str, address: u64) -> u64 {
+    if is_arm32(cpu_architecture) {
+        address & !1
+    } else {
+        address
+    }
+}
+
+/// Retargets the calls of a 32 bit ARM project whose target is an interworking address, ie. a tid at the odd address
+/// of a Thumb function that is neither a subprocedure nor an extern symbol, to the subprocedure at the entry of that
+/// function, so both share the type variable of the function. Returns the number of retargeted calls.
+pub fn normalize_interworking_calls(proj: &mut Project) -> usize {
+    if !is_arm32(&proj.cpu_architecture) {
+        return 0;
+    }
+
+    let entries: BTreeMap<u64, Tid> = proj
+        .program
+        .term
+        .subs
+        .values()
+        .filter_map(|sub| {
+            let address = u64::from_str_radix(&sub.tid.address, 16).ok()?;
+            Some((address, sub.tid.clone()))
+        })
+        .collect();
+    let known = |tid: &Tid| {
+        proj.program.term.subs.contains_key(tid)
+            || proj.program.term.extern_symbols.contains_key(tid)
+    };
+    let retargets: BTreeMap<Tid, Tid> = proj
+        .program
+        .term
+        .subs
+        .values()
+        .flat_map(|sub| sub.term.blocks.iter())
+        .flat_map(|blk| blk.term.jmps.iter())
+        .filter_map(|jmp| match &jmp.term {
+            Jmp::Call { target, .. } if !known(target) => {
+                let address = u64::from_str_radix(&target.address, 16).ok()?;
+                let entry = entries.get(&interworking_entry(address)?)?;
+                Some((target.clone(), entry.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut retargeted = 0;
+    for sub in proj.program.term.subs.values_mut() {
+        for blk in sub.term.blocks.iter_mut() {
+            for jmp in blk.term.jmps.iter_mut() {
+                if let Jmp::Call { target, .. } = &mut jmp.term {
+                    if let Some(entry) = retargets.get(target) {
+                        *target = entry.clone();
+                        retargeted += 1;
+                    }
+                }
+            }
+        }
+    }
+    retargeted
+}
+
+fn interworking_entry(address: u64) -> Option<u64> {
+    (address & 1 == 1).then(|| address & !1)
+}
+
+#[cfg(test)]
+mod test {
+    use cwe_checker_lib::intermediate_representation::{BinOpType, ByteSize, Expression, Variable};
+
+    use crate::constraint_generation::argument_registers;
+
+    use super::{code_address, interworking_entry, is_arm32};
+
+    #[test]
+    fn thumb_addresses_and_register_pairs() {
+        assert!(is_arm32("ARM:LE:32:v8T"));
+        assert!(!is_arm32("AARCH64:LE:64:v8A"));
+        assert_eq!(code_address("ARM:LE:32:Cortex", 0x0800_1a3d), 0x0800_1a3c);
+        assert_eq!(code_address("x86:LE:32:default", 0x401001), 0x401001);
+        assert_eq!(interworking_entry(0x10451), Some(0x10450));
+        assert_eq!(interworking_entry(0x10450), None);
+
+        let register = |name: &str| Variable {
+            name: name.to_owned(),
+            size: ByteSize::new(4),
+            is_temp: false,
+        };
+        // A 64 bit return in r0:r1
+        let pair = Expression::BinOp {
+            op: BinOpType::Piece,
+            lhs: Box::new(Expression::Var(register("r1"))),
+            rhs: Box::new(Expression::Var(register("r0"))),
+        };
+        let names = |expr: &Expression, cpu_architecture: &str| {
+            argument_registers(expr, cpu_architecture)
+                .into_iter()
+                .map(|var| var.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&pair, "ARM:LE:32:v8T"), vec!["r1", "r0"]);
+        assert_eq!(names(&pair, "x86:LE:32:default"), vec!["r1", "r0"]);
+
+        // A 32 bit half of a pair is held by its register only on 32 bit ARM
+        let low_half = Expression::Subpiece {
+            low_byte: ByteSize::new(0),
+            size: ByteSize::new(4),
+            arg: Box::new(pair),
+        };
+        assert_eq!(names(&low_half, "ARM:LE:32:v8T"), vec!["r1", "r0"]);
+        assert!(names(&low_half, "x86:LE:32:default").is_empty());
+    }
+}
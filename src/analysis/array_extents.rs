@@ -0,0 +1,367 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+
+use cwe_checker_lib::{
+    analysis::graph::{Graph, Node},
+    intermediate_representation::{
+        BinOpType, Blk, ByteSize, Def, Expression, Jmp, Project, Term, Tid, Variable,
+    },
+};
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::loops,
+    constraint_generation::{NodeContextMapping, PointsToMapping},
+    constraints::TypeVariable,
+};
+
+// Bounds how many block local assignments are inlined into an address or condition.
+const MAX_INLINE_DEPTH: usize = 4;
+
+/// A run of equally sized elements within an abstract object.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArrayLayout {
+    /// The byte offset of the first element within the object
+    pub offset: i64,
+    /// The size of each element in bytes
+    pub stride: usize,
+    /// The number of elements
+    pub count: usize,
+}
+
+/// An array layout inferred for an abstract memory object.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArrayExtent {
+    /// The type variable of the abstract object
+    pub object: TypeVariable,
+    /// The layout of the array within the object
+    pub layout: ArrayLayout,
+}
+
+/// Replaces variables assigned earlier in the block by their values.
+fn inline_assignments(
+    expr: &Expression,
+    assigned: &BTreeMap<Variable, Expression>,
+    depth: usize,
+) -> Expression {
+    if depth > MAX_INLINE_DEPTH {
+        return expr.clone();
+    }
+
+    match expr {
+        Expression::Var(v) => assigned
+            .get(v)
+            .map(|value| inline_assignments(value, assigned, depth + 1))
+            .unwrap_or_else(|| expr.clone()),
+        Expression::BinOp { op, lhs, rhs } => Expression::BinOp {
+            op: *op,
+            lhs: Box::new(inline_assignments(lhs, assigned, depth + 1)),
+            rhs: Box::new(inline_assignments(rhs, assigned, depth + 1)),
+        },
+        Expression::UnOp { op, arg } => Expression::UnOp {
+            op: *op,
+            arg: Box::new(inline_assignments(arg, assigned, depth + 1)),
+        },
+        Expression::Cast { op, size, arg } => Expression::Cast {
+            op: *op,
+            size: *size,
+            arg: Box::new(inline_assignments(arg, assigned, depth + 1)),
+        },
+        Expression::Subpiece {
+            low_byte,
+            size,
+            arg,
+        } => Expression::Subpiece {
+            low_byte: *low_byte,
+            size: *size,
+            arg: Box::new(inline_assignments(arg, assigned, depth + 1)),
+        },
+        _ => expr.clone(),
+    }
+}
+
+fn constant(expr: &Expression) -> Option<u64> {
+    match expr {
+        Expression::Const(bv) => bv.try_to_u64().ok(),
+        _ => None,
+    }
+}
+
+/// Strips casts of the index, ie. sign extension of a 32 bit counter.
+fn index_variable(expr: &Expression) -> Option<&Variable> {
+    match expr {
+        Expression::Var(v) => Some(v),
+        Expression::Cast { arg, .. } | Expression::Subpiece { arg, .. } => index_variable(arg),
+        _ => None,
+    }
+}
+
+/// Gets the variable bounded by a loop guard and the number of iterations the bound allows.
+fn bounded_index(condition: &Expression) -> Option<(Variable, usize)> {
+    match condition {
+        Expression::UnOp { arg, .. } => bounded_index(arg),
+        Expression::BinOp { op, lhs, rhs } => {
+            let bound = constant(rhs)?;
+            let idx = index_variable(lhs)?;
+            let count = match op {
+                BinOpType::IntLess | BinOpType::IntSLess | BinOpType::IntNotEqual => bound,
+                BinOpType::IntLessEqual | BinOpType::IntSLessEqual => bound.checked_add(1)?,
+                _ => return None,
+            };
+
+            // counts that do not fit the array count of the ctypes schema are not arrays worth lowering
+            u32::try_from(count)
+                .ok()
+                .and_then(|count| usize::try_from(count).ok())
+                .filter(|count| *count > 0)
+                .map(|count| (idx.clone(), count))
+        }
+        _ => None,
+    }
+}
+
+/// Splits an address of the form base + index * stride (or base + (index << k)) into the base, index and stride.
+fn indexed_address(address: &Expression) -> Option<(&Expression, &Variable, usize)> {
+    let scaled = |expr: &Expression| -> Option<(&Variable, u64)> {
+        match expr {
+            Expression::BinOp {
+                op: BinOpType::IntMult,
+                lhs,
+                rhs,
+            } => index_variable(lhs)
+                .zip(constant(rhs))
+                .or_else(|| index_variable(rhs).zip(constant(lhs))),
+            Expression::BinOp {
+                op: BinOpType::IntLeft,
+                lhs,
+                rhs,
+            } => index_variable(lhs).zip(constant(rhs).filter(|k| *k < 16).map(|k| 1 << k)),
+            _ => None,
+        }
+    };
+
+    match address {
+        Expression::BinOp {
+            op: BinOpType::IntAdd,
+            lhs,
+            rhs,
+        } => {
+            let (base, (idx, stride)) = scaled(rhs)
+                .map(|scaled| (lhs.as_ref(), scaled))
+                .or_else(|| scaled(lhs).map(|scaled| (rhs.as_ref(), scaled)))?;
+            usize::try_from(stride)
+                .ok()
+                .filter(|stride| *stride > 0)
+                .map(|stride| (base, idx, stride))
+        }
+        _ => None,
+    }
+}
+
+fn guard_bounds(blk: &Term<Blk>) -> Vec<(Variable, usize)> {
+    let mut assigned = BTreeMap::new();
+    for df in blk.term.defs.iter() {
+        match &df.term {
+            Def::Assign { var, value } => {
+                assigned.insert(var.clone(), value.clone());
+            }
+            Def::Load { var, .. } => {
+                assigned.remove(var);
+            }
+            Def::Store { .. } => (),
+        }
+    }
+
+    blk.term
+        .jmps
+        .iter()
+        .filter_map(|jmp| match &jmp.term {
+            Jmp::CBranch { condition, .. } => {
+                bounded_index(&inline_assignments(condition, &assigned, 0))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn indexed_accesses<P: PointsToMapping + Clone>(
+    start: &P,
+    blk: &Term<Blk>,
+    bounds: &BTreeMap<Variable, usize>,
+) -> Vec<ArrayExtent> {
+    let mut ctx = start.clone();
+    let mut assigned = BTreeMap::new();
+    let mut res = Vec::new();
+
+    for df in blk.term.defs.iter() {
+        let address = match &df.term {
+            Def::Load { address, .. } | Def::Store { address, .. } => Some(address),
+            Def::Assign { .. } => None,
+        };
+
+        if let Some(address) = address {
+            let address = inline_assignments(address, &assigned, 0);
+            if let Some((base, idx, stride)) = indexed_address(&address) {
+                if let Some(count) = bounds.get(idx) {
+                    for acc in ctx.points_to(base, ByteSize::new(stride as u64)) {
+                        if let Some(offset) = acc.offset.filter(|off| *off >= 0) {
+                            res.push(ArrayExtent {
+                                object: acc.ty_var,
+                                layout: ArrayLayout {
+                                    offset,
+                                    stride,
+                                    count: *count,
+                                },
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        match &df.term {
+            Def::Assign { var, value } => {
+                assigned.insert(var.clone(), value.clone());
+            }
+            Def::Load { var, .. } => {
+                assigned.remove(var);
+            }
+            Def::Store { .. } => (),
+        }
+        ctx = ctx.apply_def(df);
+    }
+
+    res
+}
+
+/// Infers array extents from loops that index an object with a constant stride by a variable that the loop guard compares
+/// against a constant. The guard is any conditional branch within the loop, and the index is matched by register so the
+/// counter must live in the same variable in the guard and the access. When several loops index the same array the largest count is kept.
+pub fn find_array_extents<P: PointsToMapping + Clone>(
+    proj: &Project,
+    graph: &Graph,
+    points_to: &HashMap<NodeIndex, P>,
+) -> Vec<ArrayExtent> {
+    let block_starts: HashMap<Tid, (NodeIndex, &Term<Blk>)> = graph
+        .node_indices()
+        .filter_map(|idx| match graph[idx] {
+            Node::BlkStart(blk, _) => Some((blk.tid.clone(), (idx, blk))),
+            _ => None,
+        })
+        .collect();
+
+    let mut extents: BTreeMap<(TypeVariable, i64, usize), usize> = BTreeMap::new();
+    for sub in proj.program.term.subs.values() {
+        for lp in loops::find_loops(sub) {
+            let body = lp
+                .body
+                .iter()
+                .filter_map(|tid| block_starts.get(tid))
+                .collect::<Vec<_>>();
+
+            let bounds = body
+                .iter()
+                .flat_map(|(_, blk)| guard_bounds(blk))
+                .collect::<BTreeMap<_, _>>();
+            if bounds.is_empty() {
+                continue;
+            }
+
+            for (idx, blk) in body.iter() {
+                if let Some(start) = points_to.get(idx) {
+                    for extent in indexed_accesses(start, blk, &bounds) {
+                        let count = extents
+                            .entry((extent.object, extent.layout.offset, extent.layout.stride))
+                            .or_insert(0);
+                        *count = std::cmp::max(*count, extent.layout.count);
+                    }
+                }
+            }
+        }
+    }
+
+    // a single array per object and offset, preferring the stride of the largest array
+    let mut by_start: BTreeMap<(TypeVariable, i64), ArrayLayout> = BTreeMap::new();
+    for ((object, offset, stride), count) in extents.into_iter() {
+        let layout = ArrayLayout {
+            offset,
+            stride,
+            count,
+        };
+        match by_start.get(&(object.clone(), offset)) {
+            Some(prev) if prev.stride * prev.count >= stride * count => (),
+            _ => {
+                by_start.insert((object, offset), layout);
+            }
+        }
+    }
+
+    by_start
+        .into_iter()
+        .map(|((object, _), layout)| ArrayExtent { object, layout })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use cwe_checker_lib::intermediate_representation::{
+        BinOpType, Bitvector, ByteSize, Expression, Variable,
+    };
+
+    use super::{bounded_index, indexed_address, inline_assignments};
+
+    fn reg(name: &str) -> Variable {
+        Variable {
+            name: name.to_owned(),
+            size: ByteSize::new(8),
+            is_temp: false,
+        }
+    }
+
+    #[test]
+    fn recovers_guard_and_scaled_index() {
+        let rax = reg("RAX");
+        let cf = Variable {
+            name: "CF".to_owned(),
+            size: ByteSize::new(1),
+            is_temp: false,
+        };
+
+        let mut assigned = BTreeMap::new();
+        assigned.insert(
+            cf.clone(),
+            Expression::BinOp {
+                op: BinOpType::IntLess,
+                lhs: Box::new(Expression::Var(rax.clone())),
+                rhs: Box::new(Expression::Const(Bitvector::from_i64(10))),
+            },
+        );
+        let cond = inline_assignments(&Expression::Var(cf), &assigned, 0);
+        assert_eq!(bounded_index(&cond), Some((rax.clone(), 10)));
+
+        // a bound too large for an array count is not an extent
+        let huge = Expression::BinOp {
+            op: BinOpType::IntLess,
+            lhs: Box::new(Expression::Var(rax.clone())),
+            rhs: Box::new(Expression::Const(Bitvector::from_i64(1 << 40))),
+        };
+        assert_eq!(bounded_index(&huge), None);
+
+        let address = Expression::BinOp {
+            op: BinOpType::IntAdd,
+            lhs: Box::new(Expression::Var(reg("RDI"))),
+            rhs: Box::new(Expression::BinOp {
+                op: BinOpType::IntLeft,
+                lhs: Box::new(Expression::Var(rax.clone())),
+                rhs: Box::new(Expression::Const(Bitvector::from_i64(2))),
+            }),
+        };
+        let (base, idx, stride) = indexed_address(&address).unwrap();
+        assert_eq!(base, &Expression::Var(reg("RDI")));
+        assert_eq!(idx, &rax);
+        assert_eq!(stride, 4);
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+
+use cwe_checker_lib::intermediate_representation::{Bitvector, Project};
+use petgraph::{graph::NodeIndex, visit::EdgeRef, EdgeDirection};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::array_extents::ArrayLayout,
+    constraint_generation::tid_to_tvar,
+    constraints::{DerivedTypeVar, FieldLabel, TypeVariable},
+    solver::{
+        type_lattice::NamedLatticeElement,
+        type_sketch::{LatticeBounds, SketchGraph},
+    },
+};
+
+/// The character type of the elements of a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CharacterClass {
+    /// The elements of a buffer passed to a wide string function
+    WideChar,
+    /// The elements of a buffer indexed with a 2 byte stride
+    Char16,
+    /// The elements of a read only buffer holding a printable, null terminated literal
+    Char,
+    /// The elements of a read only buffer holding anything else
+    Byte,
+}
+
+impl CharacterClass {
+    /// The C spelling of the character type.
+    pub fn c_name(&self) -> &'static str {
+        match self {
+            CharacterClass::WideChar => "wchar_t",
+            CharacterClass::Char16 => "char16_t",
+            CharacterClass::Char => "char",
+            CharacterClass::Byte => "uint8_t",
+        }
+    }
+
+    // wchar_t is 2 bytes on windows and 4 bytes elsewhere
+    fn element_bits(&self) -> &'static [usize] {
+        match self {
+            CharacterClass::WideChar => &[16, 32],
+            CharacterClass::Char16 => &[16],
+            CharacterClass::Char | CharacterClass::Byte => &[8],
+        }
+    }
+
+    // Only a call to a wide string function is strong enough evidence to override the lattice bounds of the elements.
+    fn overrides_bounds(&self) -> bool {
+        matches!(self, CharacterClass::WideChar)
+    }
+}
+
+/// The wide string functions and the indices of their wide string parameters.
+pub const WIDE_STRING_FUNCTIONS: &[(&str, &[usize])] = &[
+    ("wcslen", &[0]),
+    ("wcsnlen", &[0]),
+    ("wcscpy", &[0, 1]),
+    ("wcsncpy", &[0, 1]),
+    ("wcscat", &[0, 1]),
+    ("wcsncat", &[0, 1]),
+    ("wcscmp", &[0, 1]),
+    ("wcsncmp", &[0, 1]),
+    ("wcschr", &[0]),
+    ("wcsrchr", &[0]),
+    ("wcsstr", &[0, 1]),
+    ("wcsdup", &[0]),
+    ("lstrlenW", &[0]),
+    ("lstrcpyW", &[0, 1]),
+    ("lstrcatW", &[0, 1]),
+    ("lstrcmpW", &[0, 1]),
+    ("MultiByteToWideChar", &[4]),
+    ("WideCharToMultiByte", &[2]),
+];
+
+/// The indices of the wide string parameters of a function, if it is a wide string function.
+pub fn wide_string_parameters(name: &str) -> Option<&'static [usize]> {
+    let name = name.trim_start_matches('_');
+    WIDE_STRING_FUNCTIONS
+        .iter()
+        .find(|(func, _)| *func == name)
+        .map(|(_, params)| *params)
+}
+
+/// Classifies a read only buffer by the null terminated literal at its address, if one could be read.
+pub fn classify_literal(literal: Option<&str>) -> CharacterClass {
+    match literal {
+        Some(lit)
+            if !lit.is_empty()
+                && lit
+                    .chars()
+                    .all(|c| !c.is_control() || c.is_ascii_whitespace()) =>
+        {
+            CharacterClass::Char
+        }
+        _ => CharacterClass::Byte,
+    }
+}
+
+fn follow<U: std::cmp::PartialEq>(
+    grph: &SketchGraph<U>,
+    nd: NodeIndex,
+    label: &FieldLabel,
+) -> Option<NodeIndex> {
+    grph.get_graph()
+        .get_graph()
+        .edges_directed(nd, EdgeDirection::Outgoing)
+        .find(|e| e.weight() == label)
+        .map(|e| e.target())
+}
+
+/// Finds the buffers passed to wide string functions, the pointees of their wide string parameters.
+pub fn wide_string_buffers<U: std::cmp::PartialEq>(
+    proj: &Project,
+    grph: &SketchGraph<U>,
+) -> BTreeMap<NodeIndex, CharacterClass> {
+    let subs = proj
+        .program
+        .term
+        .subs
+        .values()
+        .map(|sub| (&sub.tid, sub.term.name.as_str()));
+    let externs = proj
+        .program
+        .term
+        .extern_symbols
+        .values()
+        .map(|ext| (&ext.tid, ext.name.as_str()));
+
+    subs.chain(externs)
+        .filter_map(|(tid, name)| wide_string_parameters(name).map(|params| (tid, params)))
+        .filter_map(|(tid, params)| {
+            grph.get_node_index_for_variable(&DerivedTypeVar::new(tid_to_tvar(tid)))
+                .map(|nd| (nd, params))
+        })
+        .flat_map(|(nd, params)| {
+            params
+                .iter()
+                .filter_map(move |idx| follow(grph, nd, &FieldLabel::In(*idx)))
+        })
+        .flat_map(|param| {
+            [FieldLabel::Load, FieldLabel::Store]
+                .iter()
+                .filter_map(|label| follow(grph, param, label))
+                .collect::<Vec<_>>()
+        })
+        .map(|buffer| (buffer, CharacterClass::WideChar))
+        .collect()
+}
+
+/// Finds the objects holding an array with a 2 byte stride.
+pub fn strided_buffers(
+    array_extents: &BTreeMap<NodeIndex, Vec<ArrayLayout>>,
+) -> BTreeMap<NodeIndex, CharacterClass> {
+    array_extents
+        .iter()
+        .filter(|(_, layouts)| layouts.iter().any(|layout| layout.stride == 2))
+        .map(|(nd, _)| (*nd, CharacterClass::Char16))
+        .collect()
+}
+
+/// Finds the global variables in read only memory and classifies them by the literal stored at their address.
+pub fn literal_buffers<U: std::cmp::PartialEq>(
+    proj: &Project,
+    grph: &SketchGraph<U>,
+) -> BTreeMap<NodeIndex, CharacterClass> {
+    let rt_mem = &proj.runtime_memory_image;
+    proj.program
+        .term
+        .global_variables
+        .iter()
+        .filter_map(|(addr, term)| {
+            let address = Bitvector::from_u64(*addr);
+            if rt_mem.is_address_writeable(&address).unwrap_or(true) {
+                return None;
+            }
+
+            let nd = grph.get_node_index_for_variable(&DerivedTypeVar::new(
+                TypeVariable::new_global(term.tid.get_str_repr().to_owned()),
+            ))?;
+            let literal = rt_mem.read_string_until_null_terminator(&address).ok();
+            Some((nd, classify_literal(literal)))
+        })
+        .collect()
+}
+
+/// Maps the character elements of the classified buffers to their character type. The elements of a buffer are the
+/// terminal nodes of its fields sized like the character type. Elements of buffers classified by their stride or literal
+/// are only typed if nothing bounds them from above, so a buffer used arithmetically keeps its integral type.
+pub fn character_elements<U: NamedLatticeElement>(
+    grph: &SketchGraph<LatticeBounds<U>>,
+    buffers: &BTreeMap<NodeIndex, CharacterClass>,
+) -> BTreeMap<NodeIndex, CharacterClass> {
+    let graph = grph.get_graph().get_graph();
+    let mut elements = BTreeMap::new();
+    for (buffer, class) in buffers.iter() {
+        for e in graph.edges_directed(*buffer, EdgeDirection::Outgoing) {
+            let is_element = match e.weight() {
+                FieldLabel::Field(fld) => class.element_bits().contains(&fld.size),
+                _ => false,
+            };
+            let elem = e.target();
+            let is_terminal = graph
+                .edges_directed(elem, EdgeDirection::Outgoing)
+                .next()
+                .is_none();
+
+            if is_element
+                && is_terminal
+                && (class.overrides_bounds() || graph[elem].get_upper().is_top())
+            {
+                elements
+                    .entry(elem)
+                    .and_modify(|prev: &mut CharacterClass| *prev = (*prev).min(*class))
+                    .or_insert(*class);
+            }
+        }
+    }
+    elements
+}
+
+#[cfg(test)]
+mod test {
+    use super::{classify_literal, wide_string_parameters, CharacterClass};
+
+    #[test]
+    fn buffers_are_classified_by_functions_and_literals() {
+        assert_eq!(wide_string_parameters("_wcscpy"), Some(&[0, 1][..]));
+        assert_eq!(
+            wide_string_parameters("MultiByteToWideChar"),
+            Some(&[4][..])
+        );
+        assert_eq!(wide_string_parameters("strlen"), None);
+
+        assert_eq!(classify_literal(Some("hello\n")), CharacterClass::Char);
+        assert_eq!(classify_literal(Some("\u{1}\u{2}")), CharacterClass::Byte);
+        assert_eq!(classify_literal(Some("")), CharacterClass::Byte);
+        assert_eq!(classify_literal(None), CharacterClass::Byte);
+        assert_eq!(CharacterClass::Char16.c_name(), "char16_t");
+    }
+}
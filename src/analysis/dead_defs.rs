@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cwe_checker_lib::intermediate_representation::{Blk, Def, Expression, Jmp, Project, Term, Tid};
+
+use crate::analysis::mips::read_registers;
+
+fn remove_read(expr: &Expression, dead: &mut BTreeSet<String>) {
+    let mut registers = Vec::new();
+    read_registers(expr, &mut registers);
+    for var in registers {
+        dead.remove(&var.name);
+    }
+}
+
+/// The registers dead at the end of a block, ie. redefined before being read on every path from it. Any register may
+/// be read after a call, a return, or an indirect or interprocedural jump, so nothing is dead past them.
+fn dead_out(blk: &Term<Blk>, dead_in: &BTreeMap<Tid, BTreeSet<String>>) -> BTreeSet<String> {
+    let mut successors = Vec::new();
+    for jmp in blk.term.jmps.iter() {
+        match &jmp.term {
+            Jmp::Branch(target) | Jmp::CBranch { target, .. } => match dead_in.get(target) {
+                Some(dead) => successors.push(dead),
+                None => return BTreeSet::new(),
+            },
+            _ => return BTreeSet::new(),
+        }
+    }
+
+    let mut successors = successors.into_iter();
+    let first = match successors.next() {
+        Some(first) => first.clone(),
+        None => return BTreeSet::new(),
+    };
+    successors.fold(first, |acc, dead| acc.intersection(dead).cloned().collect())
+}
+
+/// Walks a block backwards from the registers dead at its end, returning the registers dead at its start and the
+/// indices of the assignments to dead registers.
+fn dead_in_block(blk: &Term<Blk>, mut dead: BTreeSet<String>) -> (BTreeSet<String>, Vec<usize>) {
+    for jmp in blk.term.jmps.iter().rev() {
+        match &jmp.term {
+            Jmp::CBranch { condition, .. } => remove_read(condition, &mut dead),
+            Jmp::BranchInd(target) | Jmp::CallInd { target, .. } => remove_read(target, &mut dead),
+            Jmp::Return(expr) => remove_read(expr, &mut dead),
+            _ => (),
+        }
+    }
+
+    let mut unused = Vec::new();
+    for (idx, def) in blk.term.defs.iter().enumerate().rev() {
+        match &def.term {
+            Def::Assign { var, value } => {
+                if dead.contains(&var.name) {
+                    unused.push(idx);
+                    // The value of a dead assignment is never computed, so it reads nothing
+                    continue;
+                }
+                dead.insert(var.name.clone());
+                remove_read(value, &mut dead);
+            }
+            Def::Load { var, address } => {
+                dead.insert(var.name.clone());
+                remove_read(address, &mut dead);
+            }
+            Def::Store { address, value } => {
+                remove_read(address, &mut dead);
+                remove_read(value, &mut dead);
+            }
+        }
+    }
+    (dead, unused)
+}
+
+/// The indices of the assignments to dead registers in each block of a subprocedure. Dead register sets only shrink
+/// from all registers the subprocedure defines until they reach the fixpoint.
+fn unused_assignments(blocks: &[Term<Blk>]) -> BTreeMap<Tid, Vec<usize>> {
+    let defined: BTreeSet<String> = blocks
+        .iter()
+        .flat_map(|blk| blk.term.defs.iter())
+        .filter_map(|def| match &def.term {
+            Def::Assign { var, .. } | Def::Load { var, .. } => Some(var.name.clone()),
+            Def::Store { .. } => None,
+        })
+        .collect();
+    let mut dead_in: BTreeMap<Tid, BTreeSet<String>> = blocks
+        .iter()
+        .map(|blk| (blk.tid.clone(), defined.clone()))
+        .collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for blk in blocks.iter().rev() {
+            let (dead, _) = dead_in_block(blk, dead_out(blk, &dead_in));
+            if dead_in.get(&blk.tid) != Some(&dead) {
+                dead_in.insert(blk.tid.clone(), dead);
+                changed = true;
+            }
+        }
+    }
+
+    blocks
+        .iter()
+        .filter_map(|blk| {
+            let (_, unused) = dead_in_block(blk, dead_out(blk, &dead_in));
+            (!unused.is_empty()).then(|| (blk.tid.clone(), unused))
+        })
+        .collect()
+}
+
+/// Removes the assignments to registers that are redefined before being read on every path, ie. dead stores of
+/// optimized code and register shuffles, so they generate no constraints. Loads are kept even when their result is
+/// unused since their address is still a pointer, and registers stay live across calls, returns, and indirect jumps.
+/// Blocks in `kept_blocks` are not pruned so that def indices into them stay valid. Returns the number of removed
+/// defs.
+pub fn prune_dead_defs(proj: &mut Project, kept_blocks: &BTreeSet<Tid>) -> usize {
+    let mut pruned = 0;
+    for sub in proj.program.term.subs.values_mut() {
+        let unused = unused_assignments(&sub.term.blocks);
+        for blk in sub.term.blocks.iter_mut() {
+            if kept_blocks.contains(&blk.tid) {
+                continue;
+            }
+            if let Some(indices) = unused.get(&blk.tid) {
+                let indices: BTreeSet<usize> = indices.iter().cloned().collect();
+                let mut idx = 0;
+                blk.term.defs.retain(|_| {
+                    idx += 1;
+                    !indices.contains(&(idx - 1))
+                });
+                pruned += indices.len();
+            }
+        }
+    }
+    pruned
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use cwe_checker_lib::intermediate_representation::{
+        Bitvector, Blk, ByteSize, Def, Expression, Jmp, Term, Tid, Variable,
+    };
+
+    use super::unused_assignments;
+
+    fn register(name: &str) -> Variable {
+        Variable {
+            name: name.to_owned(),
+            size: ByteSize::new(8),
+            is_temp: false,
+        }
+    }
+
+    fn assign(address: &str, var: &str, value: Expression) -> Term<Def> {
+        Term {
+            tid: Tid::create(format!("instr_{}_0", address), address.to_owned()),
+            term: Def::Assign {
+                var: register(var),
+                value,
+            },
+        }
+    }
+
+    fn block(address: &str, defs: Vec<Term<Def>>, jmps: Vec<Jmp>) -> Term<Blk> {
+        Term {
+            tid: Tid::create(format!("blk_{}", address), address.to_owned()),
+            term: Blk {
+                defs,
+                jmps: jmps
+                    .into_iter()
+                    .map(|jmp| Term {
+                        tid: Tid::create(format!("instr_{}_1", address), address.to_owned()),
+                        term: jmp,
+                    })
+                    .collect(),
+                indirect_jmp_targets: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn assignments_overwritten_on_every_path() {
+        let var = |name: &str| Expression::Var(register(name));
+        let exit = Tid::create("blk_00401010".to_owned(), "00401010".to_owned());
+        // mov rcx, rdi; mov rax, 0; mov rdx, rsi; jmp exit
+        let entry = block(
+            "00401000",
+            vec![
+                assign("00401000", "RCX", var("RDI")),
+                assign("00401003", "RAX", Expression::Const(Bitvector::from_i64(0))),
+                assign("00401008", "RDX", var("RSI")),
+            ],
+            vec![Jmp::Branch(exit)],
+        );
+        // mov rax, rcx; mov rdx, 1; ret
+        let exit = block(
+            "00401010",
+            vec![
+                assign("00401010", "RAX", var("RCX")),
+                assign("00401013", "RDX", Expression::Const(Bitvector::from_i64(1))),
+            ],
+            vec![Jmp::Return(var("RSP"))],
+        );
+
+        // RCX is read by the exit block and nothing is dead at the return
+        assert_eq!(
+            unused_assignments(&[entry, exit]),
+            BTreeMap::from([(
+                Tid::create("blk_00401000".to_owned(), "00401000".to_owned()),
+                vec![2, 1]
+            )])
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use cwe_checker_lib::{
+    analysis::graph::{Graph, Node},
+    intermediate_representation::{
+        BinOpType, Blk, Def, Expression, Jmp, Term, Tid, UnOpType, Variable,
+    },
+};
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constraint_generation::{NodeContextMapping, PointsToMapping, TypeVariableAccess},
+    constraints::{Field, TypeVariable},
+};
+
+// Bounds how many assignments are followed from a branch condition back to the compared load.
+const MAX_CONDITION_DEPTH: usize = 4;
+
+/// The layout of a tagged union: a discriminant field and sets of fields that are accessed exclusively
+/// depending on the value of the discriminant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaggedUnionLayout {
+    /// The field compared against constants
+    pub discriminant: Field,
+    /// The fields accessed in each branch of a discriminant check
+    pub variants: BTreeSet<BTreeSet<Field>>,
+}
+
+/// A tagged union layout detected for an abstract memory object.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaggedUnionHint {
+    /// The type variable of the abstract object
+    pub object: TypeVariable,
+    /// The layout of the object
+    pub layout: TaggedUnionLayout,
+}
+
+struct BlockAccesses {
+    loaded: BTreeMap<Variable, BTreeSet<TypeVariableAccess>>,
+    assigned: BTreeMap<Variable, Expression>,
+    accessed: BTreeSet<TypeVariableAccess>,
+}
+
+fn collect_accesses<P: PointsToMapping + Clone>(start: &P, blk: &Term<Blk>) -> BlockAccesses {
+    let mut ctx = start.clone();
+    let mut res = BlockAccesses {
+        loaded: BTreeMap::new(),
+        assigned: BTreeMap::new(),
+        accessed: BTreeSet::new(),
+    };
+
+    for df in blk.term.defs.iter() {
+        match &df.term {
+            Def::Load { var, address } => {
+                let accs = ctx.points_to(address, var.size);
+                res.accessed.extend(accs.iter().cloned());
+                res.loaded.insert(var.clone(), accs);
+                res.assigned.remove(var);
+            }
+            Def::Store { address, value } => {
+                res.accessed
+                    .extend(ctx.points_to(address, value.bytesize()));
+            }
+            Def::Assign { var, value } => {
+                res.loaded.remove(var);
+                res.assigned.insert(var.clone(), value.clone());
+            }
+        }
+        ctx = ctx.apply_def(df);
+    }
+
+    res
+}
+
+fn is_const(expr: &Expression) -> bool {
+    matches!(expr, Expression::Const(_))
+}
+
+/// Follows a branch condition back to a loaded variable that is compared for equality with a constant.
+fn compared_load<'a>(
+    expr: &Expression,
+    accesses: &'a BlockAccesses,
+    depth: usize,
+) -> Option<&'a BTreeSet<TypeVariableAccess>> {
+    if depth > MAX_CONDITION_DEPTH {
+        return None;
+    }
+
+    match expr {
+        Expression::Var(v) => accesses.loaded.get(v).or_else(|| {
+            accesses
+                .assigned
+                .get(v)
+                .and_then(|value| compared_load(value, accesses, depth + 1))
+        }),
+        Expression::UnOp {
+            op: UnOpType::BoolNegate,
+            arg,
+        }
+        | Expression::Cast { arg, .. }
+        | Expression::Subpiece { arg, .. } => compared_load(arg, accesses, depth + 1),
+        Expression::BinOp {
+            op: BinOpType::IntEqual | BinOpType::IntNotEqual,
+            lhs,
+            rhs,
+        } => {
+            if is_const(rhs) {
+                compared_load(lhs, accesses, depth + 1)
+            } else if is_const(lhs) {
+                compared_load(rhs, accesses, depth + 1)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn fields_of(accesses: &BTreeSet<TypeVariableAccess>, object: &TypeVariable) -> BTreeSet<Field> {
+    accesses
+        .iter()
+        .filter(|acc| &acc.ty_var == object)
+        .filter_map(|acc| {
+            acc.offset
+                .map(|off| Field::new(off, acc.sz.as_bit_length()))
+        })
+        .collect()
+}
+
+fn overlaps(x: &Field, y: &Field) -> bool {
+    let x_end = x.offset + (x.size / 8) as i64;
+    let y_end = y.offset + (y.size / 8) as i64;
+    x.offset < y_end && y.offset < x_end
+}
+
+/// Branches access a union if they access disjoint fields that overlap in memory.
+fn is_union_access(left: &BTreeSet<Field>, right: &BTreeSet<Field>) -> bool {
+    !left.is_empty()
+        && !right.is_empty()
+        && left.is_disjoint(right)
+        && left.iter().any(|x| right.iter().any(|y| overlaps(x, y)))
+}
+
+/// Finds tagged unions by looking for blocks that branch on a loaded field compared against a constant, where the successors
+/// access disjoint, overlapping fields of the same object. Only the immediate successor blocks of the check are considered.
+pub fn find_tagged_unions<P: PointsToMapping + Clone>(
+    graph: &Graph,
+    points_to: &HashMap<NodeIndex, P>,
+) -> Vec<TaggedUnionHint> {
+    let block_starts: HashMap<Tid, (NodeIndex, &Term<Blk>)> = graph
+        .node_indices()
+        .filter_map(|idx| match graph[idx] {
+            Node::BlkStart(blk, _) => Some((blk.tid.clone(), (idx, blk))),
+            _ => None,
+        })
+        .collect();
+
+    let accesses_for = |tid: &Tid| {
+        block_starts
+            .get(tid)
+            .and_then(|(idx, blk)| points_to.get(idx).map(|start| collect_accesses(start, blk)))
+    };
+
+    let mut layouts: BTreeMap<(TypeVariable, Field), BTreeSet<BTreeSet<Field>>> = BTreeMap::new();
+    for (tid, (_, blk)) in block_starts.iter() {
+        let condition = blk.term.jmps.iter().find_map(|jmp| match &jmp.term {
+            Jmp::CBranch { condition, .. } => Some(condition),
+            _ => None,
+        });
+        let successors = blk
+            .term
+            .jmps
+            .iter()
+            .filter_map(|jmp| match &jmp.term {
+                Jmp::CBranch { target, .. } | Jmp::Branch(target) => Some(target),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let condition = match condition {
+            Some(condition) if successors.len() == 2 => condition,
+            _ => continue,
+        };
+
+        let accesses = match accesses_for(tid) {
+            Some(accesses) => accesses,
+            None => continue,
+        };
+
+        let discriminants = match compared_load(condition, &accesses, 0) {
+            Some(discriminants) => discriminants,
+            None => continue,
+        };
+
+        let (left, right) = match (accesses_for(successors[0]), accesses_for(successors[1])) {
+            (Some(left), Some(right)) => (left, right),
+            _ => continue,
+        };
+
+        for disc in discriminants.iter() {
+            let offset = match disc.offset {
+                Some(offset) => offset,
+                None => continue,
+            };
+
+            let discriminant = Field::new(offset, disc.sz.as_bit_length());
+            let without_disc = |flds: BTreeSet<Field>| {
+                flds.into_iter()
+                    .filter(|fld| !overlaps(fld, &discriminant))
+                    .collect::<BTreeSet<_>>()
+            };
+            let left_fields = without_disc(fields_of(&left.accessed, &disc.ty_var));
+            let right_fields = without_disc(fields_of(&right.accessed, &disc.ty_var));
+
+            if is_union_access(&left_fields, &right_fields) {
+                let variants = layouts
+                    .entry((disc.ty_var.clone(), discriminant.clone()))
+                    .or_insert_with(BTreeSet::new);
+                variants.insert(left_fields);
+                variants.insert(right_fields);
+            }
+        }
+    }
+
+    layouts
+        .into_iter()
+        .map(|((object, discriminant), variants)| TaggedUnionHint {
+            object,
+            layout: TaggedUnionLayout {
+                discriminant,
+                variants,
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use crate::constraints::Field;
+
+    use super::is_union_access;
+
+    #[test]
+    fn union_access_requires_overlap() {
+        let int_variant = vec![Field::new(8, 32)].into_iter().collect::<BTreeSet<_>>();
+        let ptr_variant = vec![Field::new(8, 64)].into_iter().collect::<BTreeSet<_>>();
+        let next_field = vec![Field::new(16, 64)]
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+
+        assert!(is_union_access(&int_variant, &ptr_variant));
+        assert!(!is_union_access(&int_variant, &next_field));
+        assert!(!is_union_access(&int_variant, &int_variant));
+        assert!(!is_union_access(&int_variant, &BTreeSet::new()));
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cwe_checker_lib::intermediate_representation::{Project, Sub, Term, Tid};
+
+use crate::analysis::noreturn::successors;
+
+/// The blocks on the exception paths of the subprocedures of a project: the landing pads found in the call site tables
+/// of C++ functions, and the blocks only reachable from a landing pad, ie. the destructor calls of a cleanup ending in
+/// `_Unwind_Resume` or a catch handler up to where it rejoins the normal flow. The personality routine enters a landing
+/// pad with the exception object and the selector in registers, which would otherwise read as parameters of the
+/// subprocedure.
+#[derive(Debug, Clone, Default)]
+pub struct ExceptionPaths {
+    blocks: BTreeSet<Tid>,
+}
+
+/// Finds the blocks of a subprocedure on the exception paths starting at landing_pads, stopping at the blocks reachable
+/// from the entry of the subprocedure without passing a landing pad.
+pub fn exception_blocks(sub: &Term<Sub>, landing_pads: &BTreeSet<Tid>) -> BTreeSet<Tid> {
+    if !sub
+        .term
+        .blocks
+        .iter()
+        .any(|blk| landing_pads.contains(&blk.tid))
+    {
+        return BTreeSet::new();
+    }
+    let no_calls = BTreeSet::new();
+    let by_tid = sub
+        .term
+        .blocks
+        .iter()
+        .map(|blk| (&blk.tid, blk))
+        .collect::<BTreeMap<_, _>>();
+    let walk = |starts: Vec<&Tid>, excluded: &BTreeSet<Tid>| {
+        let mut reached = BTreeSet::new();
+        let mut worklist = starts;
+        while let Some(tid) = worklist.pop() {
+            if excluded.contains(tid) || !reached.insert(tid.clone()) {
+                continue;
+            }
+            if let Some(blk) = by_tid.get(tid) {
+                worklist.extend(successors(blk, &no_calls));
+            }
+        }
+        reached
+    };
+
+    let normal = walk(
+        sub.term.blocks.iter().take(1).map(|blk| &blk.tid).collect(),
+        landing_pads,
+    );
+    walk(
+        sub.term
+            .blocks
+            .iter()
+            .map(|blk| &blk.tid)
+            .filter(|tid| landing_pads.contains(tid))
+            .collect(),
+        &normal,
+    )
+}
+
+impl ExceptionPaths {
+    /// Finds the exception paths starting at landing_pads, see [exception_blocks]. A subprocedure whose entry block is a
+    /// landing pad, like the cold part of a function split by the compiler, is on an exception path as a whole.
+    pub fn new(proj: &Project, landing_pads: &BTreeSet<Tid>) -> ExceptionPaths {
+        ExceptionPaths {
+            blocks: proj
+                .program
+                .term
+                .subs
+                .values()
+                .flat_map(|sub| exception_blocks(sub, landing_pads))
+                .collect(),
+        }
+    }
+
+    /// Checks if the block is on an exception path.
+    pub fn contains(&self, blk: &Tid) -> bool {
+        self.blocks.contains(blk)
+    }
+
+    /// Gets the number of blocks on exception paths.
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Checks if there are no exception paths.
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// The namespace the variables of the exception paths of a subprocedure are renamed into, so the constraints
+    /// generated on them do not reach the signature and locals of the subprocedure.
+    pub fn namespace(sub: &Tid) -> String {
+        format!("landing_pad${}$", sub.get_str_repr())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use cwe_checker_lib::intermediate_representation::{
+        Blk, ByteSize, Expression, Jmp, Sub, Term, Tid,
+    };
+
+    use super::exception_blocks;
+
+    fn tid(address: &str) -> Tid {
+        Tid::create(format!("blk_{}", address), address.to_owned())
+    }
+
+    fn block(address: &str, jmp: Jmp) -> Term<Blk> {
+        Term {
+            tid: tid(address),
+            term: Blk {
+                defs: Vec::new(),
+                jmps: vec![Term {
+                    tid: Tid::create(format!("instr_{}_jmp", address), address.to_owned()),
+                    term: jmp,
+                }],
+                indirect_jmp_targets: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn catch_handlers_end_where_they_rejoin_the_normal_flow() {
+        let ret = || {
+            Jmp::Return(Expression::Unknown {
+                description: "ret".to_owned(),
+                size: ByteSize::new(8),
+            })
+        };
+        let func = Tid::create("sub_00401000".to_owned(), "00401000".to_owned());
+        let sub = Term {
+            tid: func,
+            term: Sub {
+                name: "f".to_owned(),
+                blocks: vec![
+                    // the try block falls through to the continuation
+                    block("00401000", Jmp::Branch(tid("00401030"))),
+                    // the landing pad runs the catch handler
+                    block("00401010", Jmp::Branch(tid("00401020"))),
+                    block("00401020", Jmp::Branch(tid("00401030"))),
+                    block("00401030", ret()),
+                ],
+                formal_args: Vec::new(),
+                formal_rets: Vec::new(),
+                calling_convention: None,
+            },
+        };
+        let paths = exception_blocks(&sub, &BTreeSet::from([tid("00401010")]));
+        assert!(paths.contains(&tid("00401010")));
+        assert!(paths.contains(&tid("00401020")));
+        assert!(!paths.contains(&tid("00401000")));
+        assert!(!paths.contains(&tid("00401030")));
+    }
+}
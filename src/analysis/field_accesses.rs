@@ -0,0 +1,102 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use cwe_checker_lib::{
+    analysis::graph::{Graph, Node},
+    intermediate_representation::{Blk, Def, Term, Tid},
+};
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constraint_generation::{NodeContextMapping, PointsToMapping, TypeVariableAccess},
+    constraints::{Field, TypeVariable},
+};
+
+/// The loads and stores that access a field of an abstract memory object.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldAccesses {
+    /// The type variable of the abstract object
+    pub object: TypeVariable,
+    /// The accessed field
+    pub field: Field,
+    /// The terms of the loads and stores accessing the field
+    pub defs: BTreeSet<Tid>,
+}
+
+/// The number of distinct loads and stores that access a field of an abstract memory object.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldAccessCount {
+    /// The type variable of the abstract object
+    pub object: TypeVariable,
+    /// The accessed field
+    pub field: Field,
+    /// The number of program points accessing the field
+    pub count: usize,
+}
+
+// Records the def of each load and store of blk under the fields it accesses, with the points-to state at the start of blk.
+fn record_accesses<P: PointsToMapping + Clone>(
+    start: &P,
+    blk: &Term<Blk>,
+    accessed_by: &mut BTreeMap<(TypeVariable, Field), BTreeSet<Tid>>,
+) {
+    let mut ctx = start.clone();
+    for df in blk.term.defs.iter() {
+        let accs = match &df.term {
+            Def::Load { var, address } => ctx.points_to(address, var.size),
+            Def::Store { address, value } => ctx.points_to(address, value.bytesize()),
+            Def::Assign { .. } => BTreeSet::new(),
+        };
+        for TypeVariableAccess { ty_var, sz, offset } in accs.into_iter() {
+            // accesses at an unknown offset type the object as a region of elements rather than a field
+            if let Some(off) = offset {
+                accessed_by
+                    .entry((ty_var, Field::new(off, sz.as_bit_length())))
+                    .or_default()
+                    .insert(df.tid.clone());
+            }
+        }
+        ctx = ctx.apply_def(df);
+    }
+}
+
+/// Collects the loads and stores accessing each field of the abstract objects of the program. A def is collected once
+/// per field even if its block is reached from several subprocedures.
+pub fn collect_field_accesses<P: PointsToMapping + Clone>(
+    graph: &Graph,
+    points_to: &HashMap<NodeIndex, P>,
+) -> Vec<FieldAccesses> {
+    let mut accessed_by = BTreeMap::new();
+    for idx in graph.node_indices() {
+        if let Node::BlkStart(blk, _) = graph[idx] {
+            if let Some(start) = points_to.get(&idx) {
+                record_accesses(start, blk, &mut accessed_by);
+            }
+        }
+    }
+
+    accessed_by
+        .into_iter()
+        .map(|((object, field), defs)| FieldAccesses {
+            object,
+            field,
+            defs,
+        })
+        .collect()
+}
+
+/// Counts the loads and stores accessing each field of the abstract objects of the program, see
+/// [collect_field_accesses].
+pub fn count_field_accesses<P: PointsToMapping + Clone>(
+    graph: &Graph,
+    points_to: &HashMap<NodeIndex, P>,
+) -> Vec<FieldAccessCount> {
+    collect_field_accesses(graph, points_to)
+        .into_iter()
+        .map(|accesses| FieldAccessCount {
+            object: accesses.object,
+            field: accesses.field,
+            count: accesses.defs.len(),
+        })
+        .collect()
+}
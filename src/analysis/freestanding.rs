@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+
+use cwe_checker_lib::intermediate_representation::{Project, Tid};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{
+        allocators::AllocatorSpec,
+        arm32::code_address,
+        name_heuristics::constraints_by_injecting_sub,
+        platform::{slot_constraints, PlatformProfile, SignatureSlot, TypedefKind},
+    },
+    constraints::ConstraintSet,
+};
+
+/// The signature of a function of the SDK of a firmware, matched by name or by the address of its entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SdkSignature {
+    /// The name of the function
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The hex address of the entry of the function, ie. `0x08001a3c`
+    #[serde(default)]
+    pub address: Option<String>,
+    /// The typed slots of the signature, typed by lattice elements or typedefs of the profile
+    pub slots: Vec<(SignatureSlot, String)>,
+}
+
+fn parse_address(address: &str) -> Option<u64> {
+    let digits = address
+        .trim()
+        .trim_start_matches("0x")
+        .trim_start_matches("0X");
+    u64::from_str_radix(digits, 16).ok()
+}
+
+impl SdkSignature {
+    /// Checks whether the signature describes the function with the given tid and name. On 32 bit ARM the address
+    /// of a Thumb function may be given with the Thumb bit set, as symbol tables list it.
+    pub fn matches(&self, tid: &Tid, name: &str, cpu_architecture: &str) -> bool {
+        let by_name = self.name.as_deref() == Some(name);
+        let by_address = match (
+            self.address
+                .as_deref()
+                .and_then(parse_address)
+                .map(|address| code_address(cpu_architecture, address)),
+            parse_address(&tid.address),
+        ) {
+            (Some(expected), Some(actual)) => expected == actual,
+            _ => false,
+        };
+        by_name || by_address
+    }
+}
+
+/// An analysis profile for kernels and firmware that have no extern symbols. The bundled libc knowledge (allocators,
+/// platform and syscall typedefs) does not apply to them, instead the profile provides the signatures of the SDK of
+/// the firmware and its allocation routines.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FreestandingProfile {
+    /// The signatures of the functions of the SDK
+    pub signatures: Vec<SdkSignature>,
+    /// The allocation routines of the firmware
+    pub allocators: Vec<AllocatorSpec>,
+    /// The typedefs used by the signatures that are added to the lattice
+    pub typedefs: BTreeMap<String, TypedefKind>,
+}
+
+impl FreestandingProfile {
+    /// Reads a profile from a json file.
+    pub fn from_file(pth: &str) -> anyhow::Result<FreestandingProfile> {
+        let fl = std::fs::File::open(pth)?;
+        serde_json::from_reader(fl)
+            .map_err(|e| anyhow::Error::from(e).context("freestanding profile json"))
+    }
+
+    /// A profile holding only the typedefs, whose [PlatformProfile::lattice_definition] places them in the lattice.
+    pub fn typedef_profile(&self) -> PlatformProfile {
+        PlatformProfile {
+            typedefs: self.typedefs.clone(),
+            signatures: BTreeMap::new(),
+        }
+    }
+
+    /// Creates the constraints typing the SDK functions of a project, keyed by the subprocedure they are injected
+    /// into. A function matched by several signatures gets the slots of each. Elements for which is_element is false,
+    /// ie. that are missing from the lattice, are skipped.
+    pub fn sdk_constraints(
+        &self,
+        proj: &Project,
+        is_element: impl Fn(&str) -> bool,
+    ) -> BTreeMap<Tid, ConstraintSet> {
+        constraints_by_injecting_sub(proj, |callable, name| {
+            let slots = self
+                .signatures
+                .iter()
+                .filter(|sig| sig.matches(callable, name, &proj.cpu_architecture))
+                .flat_map(|sig| sig.slots.iter().cloned())
+                .collect::<Vec<_>>();
+            slot_constraints(callable, &slots, &is_element)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use super::FreestandingProfile;
+
+    #[test]
+    fn sdk_signatures_match_by_name_or_address() {
+        let profile: FreestandingProfile = serde_json::from_str(
+            r#"{
+                "signatures": [
+                    {"name": "HAL_UART_Transmit", "slots": [[{"Parameter": 2}, "uint16_t"]]},
+                    {"address": "0x08001a3c", "slots": [["Return", "status_t"]]}
+                ],
+                "allocators": [{"symbol": "pvPortMalloc", "size_params": [0]}],
+                "typedefs": {"status_t": "Integral"}
+            }"#,
+        )
+        .unwrap();
+
+        let uart = Tid::create("FUN_08000100".to_owned(), "08000100".to_owned());
+        let at_address = Tid::create("FUN_08001a3c".to_owned(), "08001a3c".to_owned());
+        assert!(profile.signatures[0].matches(&uart, "HAL_UART_Transmit", "ARM:LE:32:Cortex"));
+        assert!(!profile.signatures[1].matches(&uart, "HAL_UART_Transmit", "ARM:LE:32:Cortex"));
+        assert!(profile.signatures[1].matches(&at_address, "FUN_08001a3c", "ARM:LE:32:Cortex"));
+        assert!(profile.typedef_profile().typedefs.contains_key("status_t"));
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::BTreeMap;
+use std::convert::{TryFrom, TryInto};
+
+use cwe_checker_lib::intermediate_representation::{
+    Arg, BinOpType, Bitvector, ByteSize, Expression, Project, Variable,
+};
+use serde::{Deserialize, Serialize};
+
+// Bounds the number of functions read from a candidate table so that a spurious magic does not allocate unboundedly.
+const MAX_FUNCTIONS: u64 = 1 << 22;
+
+/// The layout of the pclntab, identified by its magic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PclntabVersion {
+    /// Go 1.2 to 1.15
+    Go12,
+    /// Go 1.16 and 1.17
+    Go116,
+    /// Go 1.18 and 1.19
+    Go118,
+    /// Go 1.20 and later
+    Go120,
+}
+
+impl PclntabVersion {
+    fn from_magic(magic: u32) -> Option<PclntabVersion> {
+        match magic {
+            0xfffffffb => Some(PclntabVersion::Go12),
+            0xfffffffa => Some(PclntabVersion::Go116),
+            0xfffffff0 => Some(PclntabVersion::Go118),
+            0xfffffff1 => Some(PclntabVersion::Go120),
+            _ => None,
+        }
+    }
+}
+
+/// A function recorded in the pclntab.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GoFunction {
+    /// The address of the entry of the function
+    pub entry: u64,
+    /// The name of the function, ie. `main.main`
+    pub name: String,
+    /// The size in bytes of the arguments and results of the function, if it is known
+    pub args: Option<u32>,
+}
+
+/// The function table of a Go binary.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pclntab {
+    /// The layout of the table
+    pub version: PclntabVersion,
+    /// The size of a pointer in bytes
+    pub ptr_size: u8,
+    /// The functions of the binary ordered by entry
+    pub functions: Vec<GoFunction>,
+}
+
+fn read_u32(data: &[u8], off: u64) -> Option<u32> {
+    let off: usize = off.try_into().ok()?;
+    data.get(off..off.checked_add(4)?)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_i32(data: &[u8], off: u64) -> Option<i32> {
+    read_u32(data, off).map(|x| x as i32)
+}
+
+fn read_uint(data: &[u8], off: u64, ptr_size: u8) -> Option<u64> {
+    match ptr_size {
+        4 => read_u32(data, off).map(u64::from),
+        _ => {
+            let off: usize = off.try_into().ok()?;
+            data.get(off..off.checked_add(8)?)
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+        }
+    }
+}
+
+fn read_name(data: &[u8], off: u64) -> Option<String> {
+    let start: usize = off.try_into().ok()?;
+    let len = data.get(start..)?.iter().position(|b| *b == 0)?;
+    let name = std::str::from_utf8(&data[start..start + len]).ok()?;
+    (!name.is_empty() && name.chars().all(|c| c.is_ascii_graphic())).then(|| name.to_owned())
+}
+
+impl Pclntab {
+    /// Parses a little endian pclntab starting at the beginning of data.
+    pub fn parse(data: &[u8]) -> anyhow::Result<Pclntab> {
+        let malformed = || anyhow::anyhow!("malformed pclntab");
+        let version = read_u32(data, 0)
+            .and_then(PclntabVersion::from_magic)
+            .ok_or_else(|| anyhow::anyhow!("not a pclntab"))?;
+        if data.len() < 8 {
+            return Err(malformed());
+        }
+        let (pad, quantum, ptr_size) = (&data[4..6], data[6], data[7]);
+        if pad != [0, 0] || ![1, 2, 4].contains(&quantum) || ![4, 8].contains(&ptr_size) {
+            return Err(malformed());
+        }
+
+        let p = u64::from(ptr_size);
+        let header = |idx: u64| read_uint(data, 8 + idx * p, ptr_size).ok_or_else(malformed);
+        let nfunc = header(0)?;
+        if nfunc == 0 || nfunc > MAX_FUNCTIONS {
+            return Err(malformed());
+        }
+
+        // Where the function table starts, the size of its entries, where names are relative to, and the text base
+        let (functab, entry_size, names, text_start) = match version {
+            PclntabVersion::Go12 => (8 + p, 2 * p, 0, 0),
+            PclntabVersion::Go116 => (header(6)?, 2 * p, header(2)?, 0),
+            PclntabVersion::Go118 | PclntabVersion::Go120 => {
+                (header(7)?, 8, header(3)?, header(2)?)
+            }
+        };
+        let func_base = match version {
+            PclntabVersion::Go12 => 0,
+            _ => functab,
+        };
+
+        let mut functions = Vec::new();
+        for idx in 0..nfunc {
+            let entry_off = functab + idx * entry_size;
+            let (entry, funcoff) = match version {
+                PclntabVersion::Go118 | PclntabVersion::Go120 => (
+                    text_start + u64::from(read_u32(data, entry_off).ok_or_else(malformed)?),
+                    u64::from(read_u32(data, entry_off + 4).ok_or_else(malformed)?),
+                ),
+                _ => (
+                    read_uint(data, entry_off, ptr_size).ok_or_else(malformed)?,
+                    read_uint(data, entry_off + p, ptr_size).ok_or_else(malformed)?,
+                ),
+            };
+
+            // _func starts with the entry, a uintptr before 1.18 and a 32 bit offset from the text start after
+            let func = func_base + funcoff;
+            let fields = match version {
+                PclntabVersion::Go118 | PclntabVersion::Go120 => func + 4,
+                _ => func + p,
+            };
+            let nameoff = read_i32(data, fields).ok_or_else(malformed)?;
+            let args = read_i32(data, fields + 4).ok_or_else(malformed)?;
+            let name = u64::try_from(i64::from(nameoff))
+                .ok()
+                .and_then(|off| read_name(data, names + off))
+                .ok_or_else(malformed)?;
+
+            functions.push(GoFunction {
+                entry,
+                name,
+                args: u32::try_from(args).ok(),
+            });
+        }
+
+        functions.sort_by_key(|func| func.entry);
+        Ok(Pclntab {
+            version,
+            ptr_size,
+            functions,
+        })
+    }
+
+    /// Finds and parses the pclntab of a Go binary by scanning for a table header that parses.
+    pub fn find(bytes: &[u8]) -> Option<Pclntab> {
+        (0..bytes.len().saturating_sub(8))
+            .step_by(4)
+            .filter(|off| {
+                read_u32(bytes, *off as u64)
+                    .and_then(PclntabVersion::from_magic)
+                    .is_some()
+            })
+            .find_map(|off| Pclntab::parse(&bytes[off..]).ok())
+    }
+}
+
+/// Finds the minor version of Go a binary was built with from the version string the runtime embeds, ie. 17 for `go1.17.2`.
+pub fn go_minor_version(bytes: &[u8]) -> Option<u32> {
+    bytes.windows(4).enumerate().find_map(|(pos, window)| {
+        if window != b"go1." {
+            return None;
+        }
+        let digits = bytes[pos + 4..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .map(|b| char::from(*b))
+            .collect::<String>();
+        digits.parse().ok()
+    })
+}
+
+/// How a Go function receives its arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GoAbi {
+    /// ABI0, every argument and result is passed on the stack above the return address
+    Stack,
+    /// ABIInternal, integer arguments and results are passed in the given registers
+    Register(Vec<String>),
+}
+
+impl GoAbi {
+    /// The ABI used by Go functions of a binary. The register ABI was introduced for x86-64 by Go 1.17 and for AArch64
+    /// by Go 1.18. Pclntabs of 1.16 and 1.17 share a layout, so minor_version decides between them.
+    pub fn for_binary(
+        version: PclntabVersion,
+        minor_version: Option<u32>,
+        cpu_architecture: &str,
+    ) -> GoAbi {
+        let arch = cpu_architecture.to_ascii_lowercase();
+        let minor = match version {
+            PclntabVersion::Go12 => 15,
+            PclntabVersion::Go116 => minor_version.unwrap_or(16).clamp(16, 17),
+            PclntabVersion::Go118 => 18,
+            PclntabVersion::Go120 => 20,
+        };
+
+        let registers: &[&str] = if arch.contains("x86_64") || arch.contains("x86-64") {
+            if minor >= 17 {
+                &["RAX", "RBX", "RCX", "RDI", "RSI", "R8", "R9", "R10", "R11"]
+            } else {
+                &[]
+            }
+        } else if (arch.contains("aarch64") || arch.contains("arm64")) && minor >= 18 {
+            &[
+                "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12",
+                "x13", "x14", "x15",
+            ]
+        } else {
+            &[]
+        };
+
+        if registers.is_empty() {
+            GoAbi::Stack
+        } else {
+            GoAbi::Register(registers.iter().map(|reg| (*reg).to_owned()).collect())
+        }
+    }
+
+    /// The formal arguments and returns of a function with the given size of arguments and results. The size does not
+    /// separate arguments from results, so every word is treated as an argument. Results of the stack ABI are stored to
+    /// the argument area and the register ABI returns the first result in the first argument register.
+    pub fn formals(&self, args: u32, stack_pointer: &Variable) -> (Vec<Arg>, Vec<Arg>) {
+        let word = stack_pointer.size;
+        let words = (args as usize) / usize::from(word).max(1);
+        let register = |name: &str| Arg::Register {
+            expr: Expression::Var(Variable {
+                name: name.to_owned(),
+                size: word,
+                is_temp: false,
+            }),
+            data_type: None,
+        };
+
+        match self {
+            GoAbi::Stack => {
+                let stack_slot = |idx: usize| {
+                    let offset = ((idx + 1) * usize::from(word)) as i64;
+                    Arg::Stack {
+                        address: Expression::BinOp {
+                            op: BinOpType::IntAdd,
+                            lhs: Box::new(Expression::Var(stack_pointer.clone())),
+                            rhs: Box::new(Expression::Const(if word == ByteSize::new(4) {
+                                Bitvector::from_i32(offset as i32)
+                            } else {
+                                Bitvector::from_i64(offset)
+                            })),
+                        },
+                        size: word,
+                        data_type: None,
+                    }
+                };
+                ((0..words).map(stack_slot).collect(), Vec::new())
+            }
+            GoAbi::Register(registers) => (
+                registers
+                    .iter()
+                    .take(words)
+                    .map(|reg| register(reg))
+                    .collect(),
+                registers.iter().take(1).map(|reg| register(reg)).collect(),
+            ),
+        }
+    }
+}
+
+/// Names the subprocedures of a project after the Go functions at their entry and replaces their formal arguments and
+/// returns with those of the Go ABI. Functions whose argument size is unknown, ie. assembly functions, keep the formals
+/// of the IR. The IR's function boundaries are kept, so Go functions without a subprocedure at their entry are only
+/// reported. Returns the number of subprocedures that matched a Go function.
+pub fn apply_pclntab(proj: &mut Project, pclntab: &Pclntab, abi: &GoAbi) -> usize {
+    let by_entry = pclntab
+        .functions
+        .iter()
+        .map(|func| (func.entry, func))
+        .collect::<BTreeMap<_, _>>();
+    let stack_pointer = proj.stack_pointer_register.clone();
+
+    let mut matched = 0;
+    for sub in proj.program.term.subs.values_mut() {
+        let func = match u64::from_str_radix(&sub.tid.address, 16)
+            .ok()
+            .and_then(|addr| by_entry.get(&addr))
+        {
+            Some(func) => func,
+            None => continue,
+        };
+
+        matched += 1;
+        sub.term.name = func.name.clone();
+        if let Some(args) = func.args {
+            let (formal_args, formal_rets) = abi.formals(args, &stack_pointer);
+            sub.term.formal_args = formal_args;
+            sub.term.formal_rets = formal_rets;
+        }
+    }
+
+    if matched < pclntab.functions.len() {
+        log::warn!(
+            "{} of {} Go functions have no subprocedure at their entry",
+            pclntab.functions.len() - matched,
+            pclntab.functions.len()
+        );
+    }
+    matched
+}
+
+#[cfg(test)]
+mod test {
+    use super::{go_minor_version, GoAbi, Pclntab, PclntabVersion};
+
+    #[test]
+    fn parses_go118_function_table() {
+        // header: magic, pad, quantum, ptrsize, then nfunc, nfiles, textStart, funcnameOffset, cuOffset,
+        // filetabOffset, pctabOffset, pclnOffset
+        let mut data = vec![0xf0, 0xff, 0xff, 0xff, 0, 0, 1, 8];
+        let names_off = 72u64;
+        let pcln_off = 96u64;
+        for field in [2u64, 0, 0x401000, names_off, 0, 0, 0, pcln_off] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        data.extend_from_slice(b"main.main\0main.f\0");
+        data.resize(pcln_off as usize, 0);
+        // functab of (entryoff, funcoff) followed by the _func of each function
+        for (entryoff, funcoff) in [(0x20u32, 24u32), (0x10, 36)] {
+            data.extend_from_slice(&entryoff.to_le_bytes());
+            data.extend_from_slice(&funcoff.to_le_bytes());
+        }
+        data.extend_from_slice(&[0; 8]);
+        for (entryoff, nameoff, args) in [(0x20u32, 0i32, 16i32), (0x10, 10, i32::MIN)] {
+            data.extend_from_slice(&entryoff.to_le_bytes());
+            data.extend_from_slice(&nameoff.to_le_bytes());
+            data.extend_from_slice(&args.to_le_bytes());
+        }
+
+        let mut binary = vec![0x90; 12];
+        binary.extend_from_slice(&data);
+        let tab = Pclntab::find(&binary).unwrap();
+        assert_eq!(tab.version, PclntabVersion::Go118);
+        assert_eq!(tab.functions[0].entry, 0x401010);
+        assert_eq!(tab.functions[0].name, "main.f");
+        assert_eq!(tab.functions[0].args, None);
+        assert_eq!(tab.functions[1].name, "main.main");
+        assert_eq!(tab.functions[1].args, Some(16));
+
+        assert_eq!(go_minor_version(b"\0go1.17.2\0"), Some(17));
+        assert_eq!(
+            GoAbi::for_binary(PclntabVersion::Go116, Some(16), "x86_64"),
+            GoAbi::Stack
+        );
+        assert!(matches!(
+            GoAbi::for_binary(PclntabVersion::Go116, Some(17), "x86_64"),
+            GoAbi::Register(_)
+        ));
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use cwe_checker_lib::{
+    analysis::graph::{Graph, Node},
+    intermediate_representation::{Arg, Blk, Def, Jmp, Project, Sub, Term, Tid},
+};
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::noreturn::successors,
+    constraint_generation::{NodeContextMapping, PointsToMapping},
+    constraints::TypeVariable,
+};
+
+/// The symbols that release a heap object passed as their first parameter when none are configured.
+pub fn default_deallocation_symbols() -> Vec<String> {
+    [
+        "free", "cfree", "xfree", "_ZdlPv", "_ZdaPv", "_ZdlPvm", "_ZdaPvm",
+    ]
+    .iter()
+    .map(|sym| sym.to_string())
+    .collect()
+}
+
+/// A lifetime of an abstract heap object within a subprocedure: the blocks reached after the same number of calls freeing
+/// the object since the entry of the subprocedure. The abstract object stands for every object allocated at its
+/// allocation site, so an object freed and then allocated again at the same site, ie. by calling its constructor
+/// again, is a single abstract object with several lifetimes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeapLifetime {
+    /// The type variable of the abstract object
+    pub object: TypeVariable,
+    /// The subprocedure the lifetime is in
+    pub sub: Tid,
+    /// The number of frees of the object preceding the lifetime
+    pub epoch: usize,
+    /// The calls freeing the object that end the lifetime
+    pub freed_by: BTreeSet<Tid>,
+    /// The blocks of the lifetime accessing the object
+    pub blocks: BTreeSet<Tid>,
+}
+
+impl HeapLifetime {
+    /// The type variable the accesses to the object during this lifetime are renamed to.
+    pub fn type_variable(&self) -> TypeVariable {
+        TypeVariable::new(format!(
+            "{}$lifetime{}${}",
+            self.object.get_name(),
+            self.epoch,
+            self.sub.get_str_repr()
+        ))
+    }
+}
+
+/// The lifetimes of the heap objects that are accessed between several pairs of allocation and free within a
+/// subprocedure. The constraints generated for the accesses of each lifetime are renamed to a variable of their own,
+/// so the unrelated uses of a recycled object do not merge into a union.
+#[derive(Debug, Clone, Default)]
+pub struct HeapLifetimes {
+    lifetimes: Vec<HeapLifetime>,
+    renaming: BTreeMap<Tid, BTreeMap<TypeVariable, TypeVariable>>,
+}
+
+struct BlockEffects {
+    accessed: BTreeSet<TypeVariable>,
+    freed: Option<(Tid, TypeVariable)>,
+}
+
+// Gets the pointer passed as the first parameter of a call to a deallocator, only register parameters are supported.
+fn freed_pointer<'a>(
+    proj: &'a Project,
+    target: &Tid,
+) -> Option<&'a cwe_checker_lib::intermediate_representation::Expression> {
+    let params = match proj.program.term.extern_symbols.get(target) {
+        Some(ext) => &ext.parameters,
+        None => &proj.program.term.subs.get(target)?.term.formal_args,
+    };
+    match params.first()? {
+        Arg::Register { expr, .. } => Some(expr),
+        Arg::Stack { .. } => None,
+    }
+}
+
+fn target_name<'a>(proj: &'a Project, target: &Tid) -> Option<&'a str> {
+    proj.program
+        .term
+        .extern_symbols
+        .get(target)
+        .map(|ext| ext.name.as_str())
+        .or_else(|| {
+            proj.program
+                .term
+                .subs
+                .get(target)
+                .map(|sub| sub.term.name.as_str())
+        })
+}
+
+// Collects the objects accessed by the loads and stores of blk, and the object freed by the call ending blk if the freed
+// pointer points to the start of a single object.
+fn block_effects<P: PointsToMapping + Clone>(
+    proj: &Project,
+    deallocators: &BTreeSet<String>,
+    start: &P,
+    blk: &Term<Blk>,
+) -> BlockEffects {
+    let mut ctx = start.clone();
+    let mut accessed = BTreeSet::new();
+    for df in blk.term.defs.iter() {
+        let accs = match &df.term {
+            Def::Load { var, address } => ctx.points_to(address, var.size),
+            Def::Store { address, value } => ctx.points_to(address, value.bytesize()),
+            Def::Assign { .. } => BTreeSet::new(),
+        };
+        accessed.extend(accs.into_iter().map(|acc| acc.ty_var));
+        ctx = ctx.apply_def(df);
+    }
+
+    let freed = blk.term.jmps.iter().find_map(|jmp| match &jmp.term {
+        Jmp::Call { target, .. }
+            if target_name(proj, target).map_or(false, |nm| deallocators.contains(nm)) =>
+        {
+            let ptr = freed_pointer(proj, target)?;
+            let pointed = ctx.points_to(ptr, ptr.bytesize());
+            match pointed.into_iter().collect::<Vec<_>>().as_slice() {
+                [acc] if acc.offset == Some(0) => Some((jmp.tid.clone(), acc.ty_var.clone())),
+                _ => None,
+            }
+        }
+        _ => None,
+    });
+
+    BlockEffects { accessed, freed }
+}
+
+// Numbers the blocks of sub by the frees of object on the paths reaching them. A block reached by paths passing a
+// different number of frees, ie. through a loop freeing the object, has no epoch.
+fn epochs(
+    sub: &Term<Sub>,
+    effects: &HashMap<Tid, BlockEffects>,
+    object: &TypeVariable,
+) -> BTreeMap<Tid, Option<usize>> {
+    let blocks = sub
+        .term
+        .blocks
+        .iter()
+        .map(|blk| (&blk.tid, blk))
+        .collect::<BTreeMap<_, _>>();
+    let frees = |tid: &Tid| {
+        effects
+            .get(tid)
+            .and_then(|eff| eff.freed.as_ref())
+            .map_or(false, |(_, freed)| freed == object)
+    };
+
+    let mut epochs: BTreeMap<Tid, Option<usize>> = BTreeMap::new();
+    let mut worklist = sub
+        .term
+        .blocks
+        .first()
+        .map(|blk| vec![(&blk.tid, Some(0))])
+        .unwrap_or_default();
+    let no_calls = BTreeSet::new();
+    while let Some((tid, epoch)) = worklist.pop() {
+        let joined = match epochs.get(tid) {
+            None => epoch,
+            Some(prev) if *prev == epoch => continue,
+            Some(None) => continue,
+            Some(Some(_)) => None,
+        };
+        epochs.insert(tid.clone(), joined);
+        if let Some(blk) = blocks.get(tid) {
+            let out = joined.map(|ep| if frees(tid) { ep + 1 } else { ep });
+            worklist.extend(
+                successors(blk, &no_calls)
+                    .into_iter()
+                    .map(|succ| (succ, out)),
+            );
+        }
+    }
+    epochs
+}
+
+// Splits the accesses to object in sub by lifetime if every accessing block has an epoch and they are not all the same.
+fn split_lifetimes(
+    sub: &Term<Sub>,
+    effects: &HashMap<Tid, BlockEffects>,
+    object: &TypeVariable,
+) -> Vec<HeapLifetime> {
+    let epochs = epochs(sub, effects, object);
+    let mut lifetimes: BTreeMap<usize, HeapLifetime> = BTreeMap::new();
+    for (tid, epoch) in epochs.iter() {
+        let eff = match effects.get(tid) {
+            Some(eff) => eff,
+            None => continue,
+        };
+        let accesses = eff.accessed.contains(object);
+        let freed_by = eff
+            .freed
+            .as_ref()
+            .filter(|(_, freed)| freed == object)
+            .map(|(call, _)| call.clone());
+        if !accesses && freed_by.is_none() {
+            continue;
+        }
+        let epoch = match epoch {
+            Some(epoch) => *epoch,
+            None if accesses => return Vec::new(),
+            None => continue,
+        };
+
+        let lifetime = lifetimes.entry(epoch).or_insert_with(|| HeapLifetime {
+            object: object.clone(),
+            sub: sub.tid.clone(),
+            epoch,
+            freed_by: BTreeSet::new(),
+            blocks: BTreeSet::new(),
+        });
+        if accesses {
+            lifetime.blocks.insert(tid.clone());
+        }
+        lifetime.freed_by.extend(freed_by);
+    }
+
+    lifetimes.retain(|_, lifetime| !lifetime.blocks.is_empty());
+    if lifetimes.len() < 2 {
+        return Vec::new();
+    }
+    lifetimes.into_values().collect()
+}
+
+impl HeapLifetimes {
+    /// Pairs the allocations of the heap objects of each subprocedure with the calls to deallocation_symbols freeing
+    /// them, using the points-to state at the start of each block of graph. An object accessed in several lifetimes of a
+    /// subprocedure gets a type variable per lifetime, unless a loop frees it so that its lifetimes overlap. Only
+    /// deallocators taking the freed pointer in a register are recognized.
+    pub fn new<P: PointsToMapping + Clone>(
+        proj: &Project,
+        graph: &Graph,
+        points_to: &HashMap<NodeIndex, P>,
+        deallocation_symbols: &[String],
+    ) -> HeapLifetimes {
+        let deallocators = deallocation_symbols.iter().cloned().collect();
+        let mut by_sub: BTreeMap<Tid, HashMap<Tid, BlockEffects>> = BTreeMap::new();
+        for idx in graph.node_indices() {
+            if let Node::BlkStart(blk, sub) = graph[idx] {
+                if let Some(start) = points_to.get(&idx) {
+                    by_sub.entry(sub.tid.clone()).or_default().insert(
+                        blk.tid.clone(),
+                        block_effects(proj, &deallocators, start, blk),
+                    );
+                }
+            }
+        }
+
+        let mut lifetimes = Vec::new();
+        for (tid, effects) in by_sub.iter() {
+            let sub = match proj.program.term.subs.get(tid) {
+                Some(sub) => sub,
+                None => continue,
+            };
+            let freed = effects
+                .values()
+                .filter_map(|eff| eff.freed.as_ref().map(|(_, object)| object))
+                .collect::<BTreeSet<_>>();
+            for object in freed {
+                lifetimes.extend(split_lifetimes(sub, effects, object));
+            }
+        }
+
+        let mut renaming: BTreeMap<Tid, BTreeMap<TypeVariable, TypeVariable>> = BTreeMap::new();
+        for lifetime in lifetimes.iter() {
+            for blk in lifetime.blocks.iter() {
+                renaming
+                    .entry(blk.clone())
+                    .or_default()
+                    .insert(lifetime.object.clone(), lifetime.type_variable());
+            }
+        }
+
+        HeapLifetimes {
+            lifetimes,
+            renaming,
+        }
+    }
+
+    /// Gets the lifetimes of the objects accessed in several lifetimes.
+    pub fn lifetimes(&self) -> &[HeapLifetime] {
+        &self.lifetimes
+    }
+
+    /// Gets the renaming of the objects accessed in the block to the variables of their lifetime, if any.
+    pub fn renaming(&self, blk: &Tid) -> Option<&BTreeMap<TypeVariable, TypeVariable>> {
+        self.renaming.get(blk)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeSet, HashMap};
+
+    use cwe_checker_lib::intermediate_representation::{
+        Blk, ByteSize, Expression, Jmp, Sub, Term, Tid,
+    };
+
+    use crate::constraints::TypeVariable;
+
+    use super::{split_lifetimes, BlockEffects};
+
+    fn tid(address: &str) -> Tid {
+        Tid::create(format!("blk_{}", address), address.to_owned())
+    }
+
+    fn block(address: &str, jmp: Jmp) -> Term<Blk> {
+        Term {
+            tid: tid(address),
+            term: Blk {
+                defs: Vec::new(),
+                jmps: vec![Term {
+                    tid: Tid::create(format!("instr_{}_jmp", address), address.to_owned()),
+                    term: jmp,
+                }],
+                indirect_jmp_targets: Vec::new(),
+            },
+        }
+    }
+
+    fn call(address: &str, return_: &str) -> Term<Blk> {
+        block(
+            address,
+            Jmp::Call {
+                target: Tid::create("free".to_owned(), "00409000".to_owned()),
+                return_: Some(tid(return_)),
+            },
+        )
+    }
+
+    #[test]
+    fn recycled_objects_are_split_by_lifetime() {
+        let object = TypeVariable::new("instr_00402010_call@RAX".to_owned());
+        let ret = Jmp::Return(Expression::Unknown {
+            description: "ret".to_owned(),
+            size: ByteSize::new(8),
+        });
+        // use the object as one type then free it, use it as another type then free it
+        let sub = Term {
+            tid: Tid::create("sub_00401000".to_owned(), "00401000".to_owned()),
+            term: Sub {
+                name: "f".to_owned(),
+                blocks: vec![
+                    call("00401000", "00401010"),
+                    call("00401010", "00401020"),
+                    block("00401020", ret),
+                ],
+                formal_args: Vec::new(),
+                formal_rets: Vec::new(),
+                calling_convention: None,
+            },
+        };
+        let effects = |freed: bool| BlockEffects {
+            accessed: BTreeSet::from([object.clone()]),
+            freed: freed.then(|| {
+                (
+                    Tid::create("free_call".to_owned(), "0".to_owned()),
+                    object.clone(),
+                )
+            }),
+        };
+        let mut by_block = HashMap::new();
+        by_block.insert(tid("00401000"), effects(true));
+        by_block.insert(tid("00401010"), effects(true));
+        by_block.insert(tid("00401020"), effects(false));
+
+        let lifetimes = split_lifetimes(&sub, &by_block, &object);
+        assert_eq!(
+            lifetimes.iter().map(|lt| lt.epoch).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert_ne!(lifetimes[0].type_variable(), lifetimes[1].type_variable());
+        assert_eq!(lifetimes[0].freed_by.len(), 1);
+        assert!(lifetimes[2].freed_by.is_empty());
+
+        // a single lifetime is not split
+        by_block.insert(tid("00401010"), effects(false));
+        by_block.get_mut(&tid("00401000")).unwrap().freed = None;
+        assert!(split_lifetimes(&sub, &by_block, &object).is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::{BTreeSet, HashMap};
+
+use cwe_checker_lib::intermediate_representation::{Jmp, Sub, Term, Tid};
+use petgraph::{
+    algo::dominators,
+    graph::{DiGraph, NodeIndex},
+    EdgeDirection,
+};
+
+/// A natural loop of a subprocedure, identified by a back edge to the loop header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NaturalLoop {
+    /// The block that dominates every block in the loop
+    pub header: Tid,
+    /// The blocks of the loop, including the header
+    pub body: BTreeSet<Tid>,
+}
+
+fn block_graph(sub: &Sub) -> (DiGraph<Tid, ()>, HashMap<Tid, NodeIndex>) {
+    let mut grph = DiGraph::new();
+    let nodes: HashMap<Tid, NodeIndex> = sub
+        .blocks
+        .iter()
+        .map(|blk| (blk.tid.clone(), grph.add_node(blk.tid.clone())))
+        .collect();
+
+    for blk in sub.blocks.iter() {
+        for jmp in blk.term.jmps.iter() {
+            let target = match &jmp.term {
+                Jmp::Branch(target) | Jmp::CBranch { target, .. } => Some(target),
+                Jmp::Call {
+                    return_: Some(ret), ..
+                }
+                | Jmp::CallInd {
+                    return_: Some(ret), ..
+                }
+                | Jmp::CallOther {
+                    return_: Some(ret), ..
+                } => Some(ret),
+                _ => None,
+            };
+
+            if let Some(dst) = target.and_then(|tgt| nodes.get(tgt)) {
+                grph.add_edge(nodes[&blk.tid], *dst, ());
+            }
+        }
+    }
+
+    (grph, nodes)
+}
+
+/// Finds the natural loops of a subprocedure. The first block of the subprocedure is treated as the entry,
+/// calls are treated as falling through to their return block.
+pub fn find_loops(sub: &Term<Sub>) -> Vec<NaturalLoop> {
+    let entry = match sub.term.blocks.first() {
+        Some(entry) => entry.tid.clone(),
+        None => return Vec::new(),
+    };
+
+    let (grph, nodes) = block_graph(&sub.term);
+    let doms = dominators::simple_fast(&grph, nodes[&entry]);
+
+    let mut loops = Vec::new();
+    for edge in grph.raw_edges() {
+        let (tail, header) = (edge.source(), edge.target());
+        let is_back_edge = doms
+            .dominators(tail)
+            .map(|mut it| it.any(|dom| dom == header))
+            .unwrap_or(false);
+        if !is_back_edge {
+            continue;
+        }
+
+        // the body is every block that reaches the tail without passing through the header
+        let mut body = BTreeSet::new();
+        body.insert(header);
+        let mut worklist = vec![tail];
+        while let Some(nd) = worklist.pop() {
+            if body.insert(nd) {
+                worklist.extend(grph.neighbors_directed(nd, EdgeDirection::Incoming));
+            }
+        }
+
+        loops.push(NaturalLoop {
+            header: grph[header].clone(),
+            body: body.into_iter().map(|nd| grph[nd].clone()).collect(),
+        });
+    }
+
+    loops
+}
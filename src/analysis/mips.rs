@@ -0,0 +1,394 @@
+use std::{collections::BTreeMap, convert::TryFrom};
+
+use cwe_checker_lib::intermediate_representation::{
+    BinOpType, Bitvector, ByteSize, Def, Expression, Jmp, Project, Term, Tid, Variable,
+};
+
+use crate::analysis::object_file::ElfFile;
+
+/// The register holding the global pointer.
+const GP_REGISTER: &str = "gp";
+
+/// The offset of the global pointer from the start of the GOT, so 16 bit offsets from it reach 64 KiB of small data
+/// and GOT entries.
+const GP_GOT_OFFSET: u64 = 0x7ff0;
+
+/// Checks whether the architecture of a project is MIPS.
+pub fn is_mips(cpu_architecture: &str) -> bool {
+    cpu_architecture.to_ascii_lowercase().contains("mips")
+}
+
+/// The calling conventions of MIPS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MipsAbi {
+    /// The 32 bit ABI passing four words in `a0`-`a3`, where the caller reserves 16 bytes of stack for them
+    O32,
+    /// The 64 bit ABI passing eight words in `a0`-`a3` and `t0`-`t3` (`a4`-`a7`)
+    N64,
+}
+
+impl MipsAbi {
+    /// The ABI of the architecture of a project, if it is MIPS.
+    pub fn for_architecture(cpu_architecture: &str) -> Option<MipsAbi> {
+        if !is_mips(cpu_architecture) {
+            None
+        } else if cpu_architecture.contains("64") {
+            Some(MipsAbi::N64)
+        } else {
+            Some(MipsAbi::O32)
+        }
+    }
+
+    /// The registers holding the first words of the arguments.
+    pub fn parameter_registers(&self) -> &'static [&'static str] {
+        match self {
+            MipsAbi::O32 => &["a0", "a1", "a2", "a3"],
+            MipsAbi::N64 => &["a0", "a1", "a2", "a3", "t0", "t1", "t2", "t3"],
+        }
+    }
+
+    /// The registers preserved across calls. Under the o32 ABI PIC callers restore `gp` from their frame after a
+    /// call, so it is not preserved by the callee.
+    pub fn callee_saved_registers(&self) -> &'static [&'static str] {
+        match self {
+            MipsAbi::O32 => &["s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "sp"],
+            MipsAbi::N64 => &[
+                "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "sp", "gp",
+            ],
+        }
+    }
+}
+
+fn parse_address(tid: &Tid) -> Option<u64> {
+    u64::from_str_radix(&tid.address, 16).ok()
+}
+
+pub(crate) fn read_registers<'a>(expr: &'a Expression, registers: &mut Vec<&'a Variable>) {
+    match expr {
+        Expression::Var(var) => registers.push(var),
+        Expression::BinOp { lhs, rhs, .. } => {
+            read_registers(lhs, registers);
+            read_registers(rhs, registers);
+        }
+        Expression::UnOp { arg, .. }
+        | Expression::Cast { arg, .. }
+        | Expression::Subpiece { arg, .. } => read_registers(arg, registers),
+        _ => (),
+    }
+}
+
+fn defined_register(def: &Def) -> Option<&Variable> {
+    match def {
+        Def::Assign { var, .. } | Def::Load { var, .. } => Some(var),
+        Def::Store { .. } => None,
+    }
+}
+
+/// Orders the defs of the delay slots of a MIPS project after the defs of their branch. The instruction in the delay
+/// slot of a branch executes after the branch instruction, ie. after `jal` sets `ra` and after a conditional branch
+/// evaluates its condition, so reaching definitions have to see its defs last. When the delay slot overwrites a
+/// register the condition of its branch reads, the condition is computed into a temporary before the delay slot.
+/// Returns the number of reordered blocks.
+pub fn order_delay_slots(proj: &mut Project) -> usize {
+    if !is_mips(&proj.cpu_architecture) {
+        return 0;
+    }
+
+    let mut reordered = 0;
+    for sub in proj.program.term.subs.values_mut() {
+        for blk in sub.term.blocks.iter_mut() {
+            let branch = match blk.term.jmps.first() {
+                Some(jmp) if !jmp.tid.get_str_repr().starts_with("artificial") => jmp,
+                _ => continue,
+            };
+            let slot = match parse_address(&branch.tid) {
+                Some(address) => address + 4,
+                None => continue,
+            };
+            let is_slot = |def: &Term<Def>| parse_address(&def.tid) == Some(slot);
+            if !blk.term.defs.iter().any(is_slot) {
+                continue;
+            }
+
+            // The delay slot is already last unless a def of the branch instruction follows it
+            let first_slot = blk.term.defs.iter().position(is_slot).unwrap_or(0);
+            let mut changed = blk.term.defs[first_slot..].iter().any(|def| !is_slot(def));
+            let (slot_defs, mut defs): (Vec<_>, Vec<_>) =
+                blk.term.defs.drain(..).partition(|def| is_slot(def));
+            let branch_tid = branch.tid.clone();
+            if let Some(Term {
+                term: Jmp::CBranch { condition, .. },
+                ..
+            }) = blk.term.jmps.first_mut()
+            {
+                let mut read = Vec::new();
+                read_registers(condition, &mut read);
+                let clobbered = slot_defs
+                    .iter()
+                    .filter_map(|def| defined_register(&def.term))
+                    .any(|var| read.contains(&var));
+                if clobbered {
+                    let temp = Variable {
+                        name: "$delay_slot_condition".to_owned(),
+                        size: condition.bytesize(),
+                        is_temp: true,
+                    };
+                    defs.push(Term {
+                        tid: Tid::create(
+                            format!("{}_condition", branch_tid.get_str_repr()),
+                            branch_tid.address.clone(),
+                        ),
+                        term: Def::Assign {
+                            var: temp.clone(),
+                            value: condition.clone(),
+                        },
+                    });
+                    *condition = Expression::Var(temp);
+                    changed = true;
+                }
+            }
+
+            defs.extend(slot_defs);
+            blk.term.defs = defs;
+            if changed {
+                reordered += 1;
+            }
+        }
+    }
+    reordered
+}
+
+/// Finds the value of the global pointer of a MIPS ELF binary, the `_gp` symbol or else 0x7ff0 past the start of the
+/// GOT. Binaries with several GOTs change the global pointer between functions, and are resolved against the first.
+pub fn global_pointer(elf: &ElfFile) -> Option<u64> {
+    // EM_MIPS and EM_MIPS_RS3_LE
+    if elf.machine != 8 && elf.machine != 10 {
+        return None;
+    }
+    elf.symbol_value("_gp").or_else(|| {
+        elf.section(".got")
+            .map(|got| got.address.wrapping_add(GP_GOT_OFFSET))
+    })
+}
+
+fn sign_extend(value: u64, size: ByteSize) -> i64 {
+    let shift = 64 - (size.as_bit_length() as u32).min(64);
+    ((value << shift) as i64) >> shift
+}
+
+/// Gets the address of an expression relative to a base register, `reg + c` or `reg - c`.
+pub(crate) fn register_relative_address(
+    expr: &Expression,
+    register: &str,
+    base: u64,
+) -> Option<u64> {
+    let (op, lhs, rhs) = match expr {
+        Expression::BinOp { op, lhs, rhs } => (op, lhs.as_ref(), rhs.as_ref()),
+        _ => return None,
+    };
+    let (c, negated) = match (op, lhs, rhs) {
+        (BinOpType::IntAdd, Expression::Var(var), Expression::Const(c))
+        | (BinOpType::IntAdd, Expression::Const(c), Expression::Var(var))
+            if var.name == register =>
+        {
+            (c, false)
+        }
+        (BinOpType::IntSub, Expression::Var(var), Expression::Const(c)) if var.name == register => {
+            (c, true)
+        }
+        _ => return None,
+    };
+    let offset = sign_extend(
+        c.try_to_u64().ok()?,
+        Expression::Const(c.clone()).bytesize(),
+    );
+    let offset = if negated {
+        offset.wrapping_neg()
+    } else {
+        offset
+    };
+    Some(base.wrapping_add(offset as u64))
+}
+
+fn constant(value: u64, size: ByteSize) -> Expression {
+    Expression::Const(if size == ByteSize::new(4) {
+        Bitvector::from_i32(value as u32 as i32)
+    } else {
+        Bitvector::from_u64(value)
+    })
+}
+
+/// Resolves the accesses relative to a register holding a fixed base address to the concrete addresses the constant
+/// resolver maps to globals. Loads of an entry of one of the given address tables, ie. the GOT, become the address
+/// the entry holds; other accesses and computed addresses become constant addresses. Registers resolved this way are
+/// followed through the rest of their block, so accesses relative to them are resolved as well and indirect calls
+/// through them become calls of the subprocedure at the address. Returns the number of resolved defs and calls.
+pub(crate) fn resolve_register_relative(
+    proj: &mut Project,
+    elf: &ElfFile,
+    register: &str,
+    base: u64,
+    tables: &[&str],
+) -> usize {
+    let tables = tables
+        .iter()
+        .filter_map(|name| elf.section(name))
+        .filter_map(|table| {
+            elf.contents(table)
+                .map(|contents| (table.address, contents))
+        })
+        .collect::<Vec<_>>();
+    let table_entry = |address: u64, size: ByteSize| {
+        tables.iter().find_map(|(start, contents)| {
+            let within = usize::try_from(address.checked_sub(*start)?).ok()?;
+            elf.reader(contents, within)
+                .uint(usize::from(size))
+                .filter(|value| *value != 0)
+        })
+    };
+    let entries: BTreeMap<u64, Tid> = proj
+        .program
+        .term
+        .subs
+        .values()
+        .filter_map(|sub| Some((parse_address(&sub.tid)?, sub.tid.clone())))
+        .collect();
+
+    let mut resolved = 0;
+    for sub in proj.program.term.subs.values_mut() {
+        for blk in sub.term.blocks.iter_mut() {
+            // The addresses held by the registers resolved so far in the block
+            let mut known: BTreeMap<String, u64> = BTreeMap::new();
+            for def in blk.term.defs.iter_mut() {
+                let address_of = |expr: &Expression| {
+                    register_relative_address(expr, register, base).or_else(|| match expr {
+                        Expression::Var(var) => known.get(&var.name).copied(),
+                        _ => known
+                            .iter()
+                            .find_map(|(reg, value)| register_relative_address(expr, reg, *value)),
+                    })
+                };
+                let replacement = match &def.term {
+                    Def::Load { var, address } => {
+                        address_of(address).map(|addr| match table_entry(addr, var.size) {
+                            Some(entry) => Def::Assign {
+                                var: var.clone(),
+                                value: constant(entry, var.size),
+                            },
+                            None => Def::Load {
+                                var: var.clone(),
+                                address: constant(addr, address.bytesize()),
+                            },
+                        })
+                    }
+                    Def::Store { address, value } => address_of(address).map(|addr| Def::Store {
+                        address: constant(addr, address.bytesize()),
+                        value: value.clone(),
+                    }),
+                    Def::Assign { var, value } => address_of(value).map(|addr| Def::Assign {
+                        var: var.clone(),
+                        value: constant(addr, var.size),
+                    }),
+                };
+
+                let defined = defined_register(&def.term).map(|var| var.name.clone());
+                let was_resolved = replacement.is_some();
+                if let Some(replacement) = replacement {
+                    def.term = replacement;
+                    resolved += 1;
+                }
+                if let Some(name) = defined {
+                    match &def.term {
+                        Def::Assign {
+                            value: Expression::Const(c),
+                            ..
+                        } if was_resolved && name != register => {
+                            if let Ok(value) = c.try_to_u64() {
+                                known.insert(name, value);
+                            }
+                        }
+                        _ => {
+                            known.remove(&name);
+                        }
+                    }
+                }
+            }
+
+            for jmp in blk.term.jmps.iter_mut() {
+                if let Jmp::CallInd {
+                    target: Expression::Var(var),
+                    return_,
+                } = &jmp.term
+                {
+                    if let Some(callee) = known.get(&var.name).and_then(|addr| entries.get(addr)) {
+                        jmp.term = Jmp::Call {
+                            target: callee.clone(),
+                            return_: return_.clone(),
+                        };
+                        resolved += 1;
+                    }
+                }
+            }
+        }
+    }
+    resolved
+}
+
+/// Resolves the `gp` relative accesses of a MIPS project to the concrete addresses the constant resolver maps to
+/// globals. Loads of a GOT entry become the address the entry holds, so the pointer loaded from the GOT is the address
+/// of its global and PIC calls through `$t9` call the function the entry holds; accesses to small data (`.sdata`,
+/// `.sbss`) and computed small data addresses become constant addresses. Returns the number of resolved defs and calls.
+pub fn resolve_gp_relative(proj: &mut Project, elf: &ElfFile) -> usize {
+    match global_pointer(elf) {
+        Some(gp) if is_mips(&proj.cpu_architecture) => {
+            resolve_register_relative(proj, elf, GP_REGISTER, gp, &[".got"])
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cwe_checker_lib::intermediate_representation::{
+        BinOpType, Bitvector, ByteSize, Expression, Variable,
+    };
+
+    use super::{register_relative_address, MipsAbi};
+
+    #[test]
+    fn gp_relative_addresses_resolve() {
+        let gp = Variable {
+            name: "gp".to_owned(),
+            size: ByteSize::new(4),
+            is_temp: false,
+        };
+        let access = |offset: i32| Expression::BinOp {
+            op: BinOpType::IntAdd,
+            lhs: Box::new(Expression::Var(gp.clone())),
+            rhs: Box::new(Expression::Const(Bitvector::from_i32(offset))),
+        };
+
+        assert_eq!(
+            register_relative_address(&access(-0x7fe0), "gp", 0x418ff0),
+            Some(0x411010)
+        );
+        assert_eq!(
+            register_relative_address(&access(0x10), "gp", 0x418ff0),
+            Some(0x419000)
+        );
+        assert_eq!(
+            register_relative_address(&Expression::Var(gp.clone()), "gp", 0x418ff0),
+            None
+        );
+
+        assert_eq!(
+            MipsAbi::for_architecture("MIPS:BE:32:default"),
+            Some(MipsAbi::O32)
+        );
+        assert_eq!(
+            MipsAbi::for_architecture("MIPS:LE:64:default"),
+            Some(MipsAbi::N64)
+        );
+        assert!(!MipsAbi::O32.callee_saved_registers().contains(&"gp"));
+    }
+}
@@ -1,8 +1,67 @@
+/// Recognizes heap allocators and thin wrappers around them. Calls to a wrapper are stubbed so that each call allocates a distinct heap object
+/// rather than all callers sharing the object allocated inside the wrapper.
+pub mod allocators;
+/// Normalizes the Thumb interworking addresses of 32 bit ARM code.
+pub mod arm32;
+/// Infers the element counts of arrays indexed in loops bounded by a constant.
+pub mod array_extents;
 /// Builds a callgraph of TIDs (only considering direct/ resolved control flow).
 pub mod callgraph;
+/// Detects wide string and character buffers from the functions they are passed to, their strides, and read only literals.
+pub mod character_types;
+/// Prunes assignments to registers that are never read before constraint generation.
+pub mod dead_defs;
+/// Detects tagged unions from branches on a field compared against constants.
+pub mod discriminants;
+/// Finds the landing pads and exception paths of C++ functions, whose constraints are kept apart from the normal flow.
+pub mod exceptions;
+/// Counts the loads and stores accessing each field of the abstract memory objects.
+pub mod field_accesses;
 /// Adds returns to the formal return parameters of procedures that tail call procedures with a return value.
 pub mod fixup_returns;
+/// Analysis profiles for kernels and firmware without extern symbols, typed from user provided SDK signatures.
+pub mod freestanding;
+/// Recovers the names and ABI of the functions of Go binaries from their pclntab.
+pub mod golang;
+/// Pairs the allocations of heap objects with the calls freeing them, so recycled objects are typed per lifetime.
+pub mod lifetimes;
+/// Finds natural loops in the control flow graph of a subprocedure.
+pub mod loops;
+/// Orders MIPS delay slots and resolves `$gp` relative accesses to globals.
+pub mod mips;
+/// Guesses low confidence types from the naming conventions of symbols and parameters.
+pub mod name_heuristics;
+/// Finds the functions that never return and the code that is only reached by falling through calls to them.
+pub mod noreturn;
+/// Infers whether pointer parameters and returns may be null.
+pub mod nullability;
+/// Parses the headers and sections of ELF and PE binaries.
+pub mod object_file;
+/// Bundled typedefs and wrapper function signatures of operating systems.
+pub mod platform;
+/// Models the PowerPC calling conventions and resolves TOC and small data accesses to globals.
+pub mod ppc;
 /// Analyzes the reaching definitions for variables in this project. Maps Tids to register contexts.
 pub mod reaching_definitions;
+/// Seeds the relocated words of global variables as pointers from the ELF and PE relocations of the binary.
+pub mod relocations;
+/// Removes the returns of subprocedures whose callers never read the return registers after calling them.
+pub mod return_usage;
+/// Models the RISC-V calling convention for subprocedures exported without one.
+pub mod riscv;
+/// Demangles Rust symbols, seeds the allocators and signatures of core and alloc, and finds fat pointer parameters.
+pub mod rust_binaries;
 /// Currently unused but finds the maximum stack depth of a given procedure.
 pub mod stack_depth_analysis;
+/// Finds the stack slot a stack protector stores its canary to from the load of the guard it copies.
+pub mod stack_protector;
+/// Replaces syscall instructions with calls to stubs typed from a bundled table of Linux syscalls.
+pub mod syscalls;
+/// Collapses PLT stubs and tail call thunks into the functions they forward to.
+pub mod thunks;
+/// Recognizes accesses to thread-local storage relative to the thread pointer of each ABI.
+pub mod tls;
+/// Recovers stack frame layouts and saved register slots from the call frame information of `.eh_frame`.
+pub mod unwind;
+/// Types the imports of PE binaries from bundled Windows API signatures with their SAL annotations.
+pub mod windows_api;
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+
+use cwe_checker_lib::intermediate_representation::{Project, Tid};
+
+use crate::{
+    analysis::allocators::called_targets,
+    constraint_generation::tid_to_tvar,
+    constraints::{
+        ConstraintOrigin, ConstraintReason, ConstraintSet, DerivedTypeVar, FieldLabel,
+        SubtypeConstraint, TyConstraint, TypeVariable,
+    },
+};
+
+/// The names of the parameters of each symbol, keyed by the symbol name, ie. loaded from headers or debug info.
+/// The IR does not record parameter names so parameter hints are only available for symbols listed here.
+pub type ParameterNames = BTreeMap<String, Vec<String>>;
+
+/// The kind of value an identifier conventionally names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameHint {
+    /// A length or size, ie. `len`, `buf_size`
+    Size,
+    /// A file descriptor, ie. `fd`, `out_fd`
+    FileDescriptor,
+}
+
+/// The lattice elements name hints are seeded with.
+#[derive(Debug, Clone)]
+pub struct HintElements {
+    /// The element of sizes, size_t when the lattice has it
+    pub size: TypeVariable,
+    /// The element of file descriptors, int when the lattice has it
+    pub file_descriptor: TypeVariable,
+}
+
+impl HintElements {
+    fn element(&self, hint: NameHint) -> &TypeVariable {
+        match hint {
+            NameHint::Size => &self.size,
+            NameHint::FileDescriptor => &self.file_descriptor,
+        }
+    }
+}
+
+/// Classifies an identifier by its naming convention.
+pub fn classify_identifier(name: &str) -> Option<NameHint> {
+    let name = name.to_ascii_lowercase();
+    if ["len", "size", "length"].contains(&name.as_str())
+        || name.ends_with("_len")
+        || name.ends_with("_size")
+        || name.ends_with("_length")
+    {
+        Some(NameHint::Size)
+    } else if name == "fd" || name.ends_with("_fd") {
+        Some(NameHint::FileDescriptor)
+    } else {
+        None
+    }
+}
+
+/// The structure a function conventionally constructs, ie. `foo_new` returns a pointer to a `foo`.
+pub fn constructed_structure_name(name: &str) -> Option<&str> {
+    name.strip_suffix("_new")
+        .map(|prefix| prefix.trim_start_matches('_'))
+        .filter(|prefix| !prefix.is_empty())
+}
+
+/// Creates the hinted constraints of the signature of a subprocedure or extern symbol.
+///
+/// Every hint is a lower bound, `size_t ⊑ f.in_0`, rather than an equality like a type assumption. Lowering prefers
+/// the upper bound of a node, which comes from the uses of the value, so a hint only decides the type of a value that
+/// is otherwise unconstrained and real evidence overrides it. Constructors are not hinted here since a load of their
+/// return would make it a pointer regardless of the evidence; [constructors] only names the structure they return
+/// when lowering.
+pub fn signature_hints(
+    callable: &Tid,
+    name: &str,
+    parameter_names: &ParameterNames,
+    elems: &HintElements,
+) -> ConstraintSet {
+    let base = DerivedTypeVar::new(tid_to_tvar(callable));
+    let with_label = |labels: &[FieldLabel]| {
+        let mut dtv = base.clone();
+        labels
+            .iter()
+            .for_each(|label| dtv.add_field_label(label.clone()));
+        dtv
+    };
+    let lower_bound = |elem: &TypeVariable, dtv: DerivedTypeVar| {
+        TyConstraint::SubTy(SubtypeConstraint::new(
+            DerivedTypeVar::new(elem.clone()),
+            dtv,
+        ))
+    };
+
+    let mut cons = ConstraintSet::default();
+    if let Some(hint) = classify_identifier(name) {
+        cons.insert(lower_bound(
+            elems.element(hint),
+            with_label(&[FieldLabel::Out(0)]),
+        ));
+    }
+    for (idx, param) in parameter_names.get(name).into_iter().flatten().enumerate() {
+        if let Some(hint) = classify_identifier(param) {
+            cons.insert(lower_bound(
+                elems.element(hint),
+                with_label(&[FieldLabel::In(idx)]),
+            ));
+        }
+    }
+
+    cons.with_origin(&ConstraintOrigin::new(
+        callable.clone(),
+        ConstraintReason::Heuristic,
+    ))
+}
+
+/// Creates the hinted constraints for a project keyed by the subprocedure they are injected into. Hints on a
+/// subprocedure are injected into the subprocedure itself, hints on an extern symbol into each of its callers.
+pub fn name_hint_constraints(
+    proj: &Project,
+    parameter_names: &ParameterNames,
+    elems: &HintElements,
+) -> BTreeMap<Tid, ConstraintSet> {
+    constraints_by_injecting_sub(proj, |callable, name| {
+        signature_hints(callable, name, parameter_names, elems)
+    })
+}
+
+/// Creates the constraints of the signature of each subprocedure and extern symbol, keyed by the subprocedure they
+/// are injected into: the subprocedure itself, or each caller of the extern symbol.
+pub(crate) fn constraints_by_injecting_sub(
+    proj: &Project,
+    signature: impl Fn(&Tid, &str) -> ConstraintSet,
+) -> BTreeMap<Tid, ConstraintSet> {
+    let mut total: BTreeMap<Tid, ConstraintSet> = BTreeMap::new();
+    for sub in proj.program.term.subs.values() {
+        let mut cons = signature(&sub.tid, &sub.term.name);
+        for target in called_targets(&sub.term) {
+            if let Some(ext) = proj.program.term.extern_symbols.get(target) {
+                cons.insert_all(&signature(target, &ext.name));
+            }
+        }
+
+        if !cons.is_empty() {
+            total
+                .entry(sub.tid.clone())
+                .or_insert_with(ConstraintSet::default)
+                .insert_all(&cons);
+        }
+    }
+    total
+}
+
+/// Finds the subprocedures and extern symbols that conventionally construct a structure, mapped to the name of the
+/// structure.
+pub fn constructors(proj: &Project) -> BTreeMap<Tid, String> {
+    let subs = proj
+        .program
+        .term
+        .subs
+        .values()
+        .map(|sub| (&sub.tid, sub.term.name.as_str()));
+    let externs = proj
+        .program
+        .term
+        .extern_symbols
+        .values()
+        .map(|ext| (&ext.tid, ext.name.as_str()));
+
+    subs.chain(externs)
+        .filter_map(|(tid, name)| {
+            constructed_structure_name(name).map(|structure| (tid.clone(), structure.to_owned()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use crate::constraints::{parse_constraint_set, TypeVariable};
+
+    use super::{
+        classify_identifier, constructed_structure_name, signature_hints, HintElements, NameHint,
+    };
+
+    #[test]
+    fn naming_conventions_seed_lower_bounds() {
+        assert_eq!(classify_identifier("buf_len"), Some(NameHint::Size));
+        assert_eq!(classify_identifier("FD"), Some(NameHint::FileDescriptor));
+        assert_eq!(classify_identifier("strlen"), None);
+        assert_eq!(constructed_structure_name("_list_new"), Some("list"));
+        assert_eq!(constructed_structure_name("_new"), None);
+
+        let elems = HintElements {
+            size: TypeVariable::new("size_t".to_owned()),
+            file_descriptor: TypeVariable::new("int".to_owned()),
+        };
+        let parameter_names = BTreeMap::from([(
+            "list_new".to_owned(),
+            vec!["capacity".to_owned(), "elem_size".to_owned()],
+        )]);
+        let cons = signature_hints(
+            &Tid::create("list_new".to_owned(), "1000".to_owned()),
+            "list_new",
+            &parameter_names,
+            &elems,
+        );
+
+        let (_, expected) = parse_constraint_set("size_t <= list_new.in_1").unwrap();
+        assert_eq!(cons, expected);
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cwe_checker_lib::{
+    analysis::graph::{Graph, Node},
+    intermediate_representation::{Blk, Jmp, Project, Sub, Term, Tid},
+};
+use petgraph::graph::NodeIndex;
+
+/// The names of library functions that never return to their caller, for extern symbols that the lifter did not mark as
+/// non-returning and for statically linked copies of them.
+pub const NORETURN_FUNCTIONS: &[&str] = &[
+    "exit",
+    "_exit",
+    "_Exit",
+    "quick_exit",
+    "abort",
+    "longjmp",
+    "_longjmp",
+    "siglongjmp",
+    "__longjmp_chk",
+    "pthread_exit",
+    "err",
+    "errx",
+    "verr",
+    "verrx",
+    "__assert_fail",
+    "__assert_rtn",
+    "_assert",
+    "__stack_chk_fail",
+    "__fortify_fail",
+    "__chk_fail",
+    "__cxa_throw",
+    "__cxa_rethrow",
+    "_Unwind_Resume",
+    "ExitProcess",
+    "ExitThread",
+    "FatalExit",
+];
+
+/// The subprocedures and extern symbols that never return, and the nodes of the control flow graph that are only reached
+/// by returning from one of them. Constraints generated at those nodes would relate the state before the call to the
+/// fall-through successors the lifter attaches to every call, which are dead code.
+#[derive(Debug, Clone, Default)]
+pub struct NoreturnCalls {
+    functions: BTreeSet<Tid>,
+    unreachable: BTreeSet<NodeIndex>,
+}
+
+fn is_noreturn_name(name: &str) -> bool {
+    NORETURN_FUNCTIONS.contains(&name)
+}
+
+// The successors of a block within its subprocedure, leaving out the return block of calls to noreturn functions.
+pub(crate) fn successors<'a>(blk: &'a Term<Blk>, noreturn: &BTreeSet<Tid>) -> Vec<&'a Tid> {
+    let mut succs = Vec::new();
+    for jmp in blk.term.jmps.iter() {
+        match &jmp.term {
+            Jmp::Branch(target) | Jmp::CBranch { target, .. } => succs.push(target),
+            Jmp::BranchInd(_) => succs.extend(blk.term.indirect_jmp_targets.iter()),
+            Jmp::Call { target, return_ } if !noreturn.contains(target) => {
+                succs.extend(return_.iter())
+            }
+            Jmp::Call { .. } | Jmp::Return(_) => (),
+            Jmp::CallInd { return_, .. } | Jmp::CallOther { return_, .. } => {
+                succs.extend(return_.iter())
+            }
+        }
+    }
+    succs
+}
+
+/// Finds the blocks of a subprocedure reachable from its entry block when calls to noreturn functions do not fall through
+/// to their return block.
+pub fn reachable_blocks(sub: &Term<Sub>, noreturn: &BTreeSet<Tid>) -> BTreeSet<Tid> {
+    let blocks = sub
+        .term
+        .blocks
+        .iter()
+        .map(|blk| (&blk.tid, blk))
+        .collect::<BTreeMap<_, _>>();
+    let mut reached = BTreeSet::new();
+    let mut worklist = sub
+        .term
+        .blocks
+        .first()
+        .map(|blk| vec![&blk.tid])
+        .unwrap_or_default();
+    while let Some(tid) = worklist.pop() {
+        if !reached.insert(tid.clone()) {
+            continue;
+        }
+        if let Some(blk) = blocks.get(tid) {
+            worklist.extend(successors(blk, noreturn));
+        }
+    }
+    reached
+}
+
+// A subprocedure returns if one of its reachable blocks returns, tail calls a function that may return, or leaves through
+// an unresolved indirect jump. Subprocedures that never call a noreturn function are assumed to return, so that infinite
+// loops and lifting errors are left alone.
+fn may_return(sub: &Term<Sub>, noreturn: &BTreeSet<Tid>) -> bool {
+    let reached = reachable_blocks(sub, noreturn);
+    let mut calls_noreturn = false;
+    for blk in sub
+        .term
+        .blocks
+        .iter()
+        .filter(|blk| reached.contains(&blk.tid))
+    {
+        for jmp in blk.term.jmps.iter() {
+            match &jmp.term {
+                Jmp::Return(_) | Jmp::CallInd { return_: None, .. } => return true,
+                Jmp::BranchInd(_) if blk.term.indirect_jmp_targets.is_empty() => return true,
+                Jmp::Call { target, .. } if noreturn.contains(target) => calls_noreturn = true,
+                Jmp::Call { return_: None, .. } => return true,
+                _ => (),
+            }
+        }
+    }
+    !calls_noreturn
+}
+
+impl NoreturnCalls {
+    /// Collects the extern symbols that cwe_checker marks as non-returning or that are known by name not to return, and
+    /// the subprocedures whose every path ends in a call to a noreturn function, ie. `usage()` or `die()` wrappers around
+    /// `exit`. Then finds the nodes of graph that are not reachable within their subprocedure because of those calls.
+    pub fn new(proj: &Project, graph: &Graph) -> NoreturnCalls {
+        let program = &proj.program.term;
+        let mut functions: BTreeSet<Tid> = program
+            .extern_symbols
+            .iter()
+            .filter(|(_, ext)| ext.no_return || is_noreturn_name(&ext.name))
+            .map(|(tid, _)| tid.clone())
+            .chain(
+                program
+                    .subs
+                    .iter()
+                    .filter(|(_, sub)| is_noreturn_name(&sub.term.name))
+                    .map(|(tid, _)| tid.clone()),
+            )
+            .collect();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (tid, sub) in program.subs.iter() {
+                if !functions.contains(tid)
+                    && !sub.term.blocks.is_empty()
+                    && !may_return(sub, &functions)
+                {
+                    functions.insert(tid.clone());
+                    changed = true;
+                }
+            }
+        }
+
+        let mut reachable: BTreeMap<Tid, BTreeSet<Tid>> = BTreeMap::new();
+        let mut is_reachable = |blk: &Tid, sub: &Term<Sub>| {
+            reachable
+                .entry(sub.tid.clone())
+                .or_insert_with(|| reachable_blocks(sub, &functions))
+                .contains(blk)
+        };
+        let unreachable = graph
+            .node_indices()
+            .filter(|idx| match graph[*idx] {
+                Node::BlkStart(blk, sub) | Node::BlkEnd(blk, sub) => !is_reachable(&blk.tid, sub),
+                Node::CallSource {
+                    source: (blk, sub), ..
+                } => !is_reachable(&blk.tid, sub),
+                Node::CallReturn {
+                    call: (blk, sub),
+                    return_: (_, callee),
+                } => functions.contains(&callee.tid) || !is_reachable(&blk.tid, sub),
+            })
+            .collect();
+
+        NoreturnCalls {
+            functions,
+            unreachable,
+        }
+    }
+
+    /// Checks if a call to the subprocedure or extern symbol tid may return.
+    pub fn returns(&self, tid: &Tid) -> bool {
+        !self.functions.contains(tid)
+    }
+
+    /// Checks if the node of the control flow graph is reachable.
+    pub fn is_reachable(&self, nd: NodeIndex) -> bool {
+        !self.unreachable.contains(&nd)
+    }
+
+    /// Gets the noreturn subprocedures and extern symbols.
+    pub fn functions(&self) -> &BTreeSet<Tid> {
+        &self.functions
+    }
+
+    /// Gets the number of nodes that are unreachable because of noreturn calls.
+    pub fn unreachable_count(&self) -> usize {
+        self.unreachable.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use cwe_checker_lib::intermediate_representation::{
+        Blk, ByteSize, Expression, Jmp, Sub, Term, Tid,
+    };
+
+    use super::{may_return, reachable_blocks};
+
+    fn block(address: &str, jmp: Jmp) -> Term<Blk> {
+        Term {
+            tid: Tid::create(format!("blk_{}", address), address.to_owned()),
+            term: Blk {
+                defs: Vec::new(),
+                jmps: vec![Term {
+                    tid: Tid::create(format!("instr_{}_jmp", address), address.to_owned()),
+                    term: jmp,
+                }],
+                indirect_jmp_targets: Vec::new(),
+            },
+        }
+    }
+
+    fn sub(name: &str, blocks: Vec<Term<Blk>>) -> Term<Sub> {
+        Term {
+            tid: Tid::create(name.to_owned(), "00401000".to_owned()),
+            term: Sub {
+                name: name.to_owned(),
+                blocks,
+                formal_args: Vec::new(),
+                formal_rets: Vec::new(),
+                calling_convention: None,
+            },
+        }
+    }
+
+    #[test]
+    fn calls_to_noreturn_functions_do_not_fall_through() {
+        let exit = Tid::create("exit".to_owned(), "00409000".to_owned());
+        let puts = Tid::create("puts".to_owned(), "00409010".to_owned());
+        let noreturn = BTreeSet::from([exit.clone()]);
+        let fall_through =
+            |address: &str| Tid::create(format!("blk_{}", address), address.to_owned());
+
+        // call puts; call exit; <dead fall-through>
+        let die = sub(
+            "die",
+            vec![
+                block(
+                    "00401000",
+                    Jmp::Call {
+                        target: puts,
+                        return_: Some(fall_through("00401010")),
+                    },
+                ),
+                block(
+                    "00401010",
+                    Jmp::Call {
+                        target: exit,
+                        return_: Some(fall_through("00401020")),
+                    },
+                ),
+                block(
+                    "00401020",
+                    Jmp::Return(Expression::Unknown {
+                        description: "ret".to_owned(),
+                        size: ByteSize::new(8),
+                    }),
+                ),
+            ],
+        );
+
+        let reached = reachable_blocks(&die, &noreturn);
+        assert!(reached.contains(&fall_through("00401010")));
+        assert!(!reached.contains(&fall_through("00401020")));
+        assert!(!may_return(&die, &noreturn));
+        assert!(may_return(&die, &BTreeSet::new()));
+    }
+}
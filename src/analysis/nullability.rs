@@ -0,0 +1,495 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use cwe_checker_lib::{
+    analysis::graph::{Graph, Node},
+    intermediate_representation::{
+        Arg, BinOpType, Def, Expression, Jmp, Project, Tid, UnOpType, Variable,
+    },
+};
+use petgraph::{
+    graph::NodeIndex,
+    visit::{EdgeRef, IntoEdgeReferences},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::reaching_definitions::Definition,
+    constraint_generation::{tid_to_tvar, NodeContextMapping},
+    constraints::{
+        ConstraintSet, CustomLabel, DerivedTypeVar, FieldLabel, SubtypeConstraint, TyConstraint,
+        TypeVariable,
+    },
+    node_context::register_map::RegisterContext,
+    solver::type_sketch::SketchGraph,
+};
+
+// Bounds how many assignments are followed from a branch condition back to the compared variable.
+const MAX_CONDITION_DEPTH: usize = 4;
+
+/// Whether a pointer may be null.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Nullability {
+    /// Nothing is known about the pointer
+    Unknown,
+    /// The pointer is compared against null or may carry the constant 0
+    Nullable,
+    /// The pointer is dereferenced without being compared against null
+    NonNull,
+}
+
+impl Default for Nullability {
+    fn default() -> Self {
+        Nullability::Unknown
+    }
+}
+
+impl Nullability {
+    /// The capability that records this nullability on a type variable in the constraints, if anything is known.
+    pub fn to_label(self) -> Option<CustomLabel> {
+        match self {
+            Nullability::Unknown => None,
+            Nullability::Nullable => Some(CustomLabel::new("nullable".to_owned(), Vec::new())),
+            Nullability::NonNull => Some(CustomLabel::new("nonnull".to_owned(), Vec::new())),
+        }
+    }
+
+    /// The nullability recorded by a capability created with [Nullability::to_label].
+    pub fn from_label(lbl: &CustomLabel) -> Option<Nullability> {
+        match lbl.kind.as_str() {
+            "nullable" if lbl.args.is_empty() => Some(Nullability::Nullable),
+            "nonnull" if lbl.args.is_empty() => Some(Nullability::NonNull),
+            _ => None,
+        }
+    }
+
+    // Nullable wins over non-null since a single path that may carry null makes the pointer nullable.
+    fn join(self, other: Nullability) -> Nullability {
+        match (self, other) {
+            (Nullability::Nullable, _) | (_, Nullability::Nullable) => Nullability::Nullable,
+            (Nullability::NonNull, _) | (_, Nullability::NonNull) => Nullability::NonNull,
+            _ => Nullability::Unknown,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Facts {
+    null_checked: BTreeSet<(Tid, usize)>,
+    dereferenced: BTreeSet<(Tid, usize)>,
+    // the calling subprocedure along with the callee and the index of the argument
+    passed_null: BTreeSet<(Tid, Tid, usize)>,
+    returns_null: BTreeSet<(Tid, usize)>,
+}
+
+fn is_zero(expr: &Expression) -> bool {
+    matches!(expr, Expression::Const(bv) if bv.is_zero())
+}
+
+fn register_of(arg: &Arg) -> Option<&Variable> {
+    match arg {
+        Arg::Register {
+            expr: Expression::Var(v),
+            ..
+        } => Some(v),
+        _ => None,
+    }
+}
+
+/// Follows copies and casts back to the variable holding the value.
+fn operand_variable(
+    expr: &Expression,
+    assigned: &BTreeMap<Variable, Expression>,
+    depth: usize,
+) -> Option<Variable> {
+    if depth > MAX_CONDITION_DEPTH {
+        return None;
+    }
+
+    match expr {
+        Expression::Var(v) => match assigned.get(v) {
+            Some(value @ Expression::Var(_))
+            | Some(value @ Expression::Cast { .. })
+            | Some(value @ Expression::Subpiece { .. }) => {
+                operand_variable(value, assigned, depth + 1)
+            }
+            Some(_) => None,
+            None => Some(v.clone()),
+        },
+        Expression::Cast { arg, .. } | Expression::Subpiece { arg, .. } => {
+            operand_variable(arg, assigned, depth + 1)
+        }
+        _ => None,
+    }
+}
+
+/// Follows a branch condition back to a variable compared for equality with zero.
+fn null_checked_variable(
+    expr: &Expression,
+    assigned: &BTreeMap<Variable, Expression>,
+    depth: usize,
+) -> Option<Variable> {
+    if depth > MAX_CONDITION_DEPTH {
+        return None;
+    }
+
+    match expr {
+        Expression::Var(v) => assigned
+            .get(v)
+            .and_then(|value| null_checked_variable(value, assigned, depth + 1)),
+        Expression::UnOp {
+            op: UnOpType::BoolNegate,
+            arg,
+        } => null_checked_variable(arg, assigned, depth + 1),
+        Expression::BinOp {
+            op: BinOpType::IntEqual | BinOpType::IntNotEqual,
+            lhs,
+            rhs,
+        } => {
+            if is_zero(rhs) {
+                operand_variable(lhs, assigned, depth + 1)
+            } else if is_zero(lhs) {
+                operand_variable(rhs, assigned, depth + 1)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The variable an address is based on, ie. the pointer being dereferenced.
+fn dereferenced_variable(address: &Expression) -> Option<&Variable> {
+    match address {
+        Expression::Var(v) => Some(v),
+        Expression::BinOp {
+            op: BinOpType::IntAdd | BinOpType::IntSub,
+            lhs,
+            rhs,
+        } if matches!(rhs.as_ref(), Expression::Const(_)) => dereferenced_variable(lhs),
+        _ => None,
+    }
+}
+
+// The index of the formal parameter the variable holds if the variable still holds its value from the entry of the subprocedure.
+fn parameter_index(ctx: &RegisterContext, formals: &[Arg], var: &Variable) -> Option<usize> {
+    let holds_entry_value = ctx
+        .get_register_context()
+        .get(var)
+        .map(|defs| {
+            !defs.0.is_empty()
+                && defs
+                    .0
+                    .iter()
+                    .all(|def| matches!(def, Definition::EntryFresh(_)))
+        })
+        .unwrap_or(false);
+
+    if !holds_entry_value {
+        return None;
+    }
+
+    formals.iter().position(|arg| register_of(arg) == Some(var))
+}
+
+fn may_hold_null(ctx: &RegisterContext, zero_defs: &HashSet<Tid>, var: &Variable) -> bool {
+    ctx.get_register_context()
+        .get(var)
+        .map(|defs| {
+            defs.0
+                .iter()
+                .any(|def| matches!(def, Definition::Normal(tid) if zero_defs.contains(tid)))
+        })
+        .unwrap_or(false)
+}
+
+fn callee_parameters<'a>(proj: &'a Project, target: &Tid) -> Option<&'a [Arg]> {
+    proj.program
+        .term
+        .subs
+        .get(target)
+        .map(|sub| sub.term.formal_args.as_slice())
+        .or_else(|| {
+            proj.program
+                .term
+                .extern_symbols
+                .get(target)
+                .map(|ext| ext.parameters.as_slice())
+        })
+}
+
+fn parameter_dtv(sub: &Tid, index: usize, label: fn(usize) -> FieldLabel) -> DerivedTypeVar {
+    DerivedTypeVar::create_with_label(&DerivedTypeVar::new(tid_to_tvar(sub)), label(index))
+}
+
+// Maps the derived type variables of parameters and returns to their nullability, along with the subprocedure whose
+// constraints should carry it: the subprocedure itself, or the caller when a null argument is passed to an external symbol.
+fn observed_nullability(
+    proj: &Project,
+    graph: &Graph,
+    reg_contexts: &HashMap<NodeIndex, RegisterContext>,
+) -> BTreeMap<DerivedTypeVar, (Tid, Nullability)> {
+    let zero_defs = proj
+        .program
+        .term
+        .subs
+        .values()
+        .flat_map(|sub| sub.term.blocks.iter())
+        .flat_map(|blk| blk.term.defs.iter())
+        .filter(|df| matches!(&df.term, Def::Assign { value, .. } if is_zero(value)))
+        .map(|df| df.tid.clone())
+        .collect::<HashSet<_>>();
+
+    let mut facts = Facts::default();
+    for idx in graph.node_indices() {
+        let (blk, sub) = match graph[idx] {
+            Node::BlkStart(blk, sub) => (blk, sub),
+            _ => continue,
+        };
+        let mut ctx = match reg_contexts.get(&idx) {
+            Some(ctx) => ctx.clone(),
+            None => continue,
+        };
+
+        let formals = &sub.term.formal_args;
+        let mut assigned = BTreeMap::new();
+        for df in blk.term.defs.iter() {
+            let address = match &df.term {
+                Def::Load { address, .. } | Def::Store { address, .. } => Some(address),
+                Def::Assign { .. } => None,
+            };
+
+            if let Some(ptr) = address.and_then(dereferenced_variable) {
+                if let Some(param) = parameter_index(&ctx, formals, ptr) {
+                    facts.dereferenced.insert((sub.tid.clone(), param));
+                }
+            }
+
+            match &df.term {
+                Def::Assign { var, value } => {
+                    assigned.insert(var.clone(), value.clone());
+                }
+                Def::Load { var, .. } => {
+                    assigned.remove(var);
+                }
+                Def::Store { .. } => (),
+            }
+            ctx = ctx.apply_def(df);
+        }
+
+        for jmp in blk.term.jmps.iter() {
+            match &jmp.term {
+                Jmp::CBranch { condition, .. } => {
+                    if let Some(param) = null_checked_variable(condition, &assigned, 0)
+                        .and_then(|checked| parameter_index(&ctx, formals, &checked))
+                    {
+                        facts.null_checked.insert((sub.tid.clone(), param));
+                    }
+                }
+                Jmp::Call { target, .. } => {
+                    for (i, arg) in callee_parameters(proj, target)
+                        .unwrap_or_default()
+                        .iter()
+                        .enumerate()
+                    {
+                        if register_of(arg).map_or(false, |v| may_hold_null(&ctx, &zero_defs, v)) {
+                            facts
+                                .passed_null
+                                .insert((sub.tid.clone(), target.clone(), i));
+                        }
+                    }
+                }
+                Jmp::Return(_) => {
+                    for (i, arg) in sub.term.formal_rets.iter().enumerate() {
+                        if register_of(arg).map_or(false, |v| may_hold_null(&ctx, &zero_defs, v)) {
+                            facts.returns_null.insert((sub.tid.clone(), i));
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    let mut res = BTreeMap::new();
+    for (sub, i) in facts.dereferenced.iter() {
+        res.insert(
+            parameter_dtv(sub, *i, FieldLabel::In),
+            (sub.clone(), Nullability::NonNull),
+        );
+    }
+    let mut nullable = |observer: &Tid, dtv: DerivedTypeVar| {
+        res.insert(dtv, (observer.clone(), Nullability::Nullable));
+    };
+    // a null check or a null argument anywhere makes the parameter nullable even if it is dereferenced elsewhere
+    for (sub, i) in facts.null_checked.iter() {
+        nullable(sub, parameter_dtv(sub, *i, FieldLabel::In));
+    }
+    for (caller, target, i) in facts.passed_null.iter() {
+        let observer = if proj.program.term.subs.contains_key(target) {
+            target
+        } else {
+            caller
+        };
+        nullable(observer, parameter_dtv(target, *i, FieldLabel::In));
+    }
+    for (sub, i) in facts.returns_null.iter() {
+        nullable(sub, parameter_dtv(sub, *i, FieldLabel::Out));
+    }
+
+    res
+}
+
+/// Infers the nullability of pointer parameters and returns from null checks, dereferences, and constant zeros that reach
+/// call arguments or returns. Reaching definitions determine whether a checked or dereferenced variable still holds a parameter,
+/// and whether an argument or return may be defined by an assignment of 0.
+/// The result maps the derived type variables of parameters (sub.in_i) and returns (sub.out_i) to their nullability.
+pub fn infer_nullability(
+    proj: &Project,
+    graph: &Graph,
+    reg_contexts: &HashMap<NodeIndex, RegisterContext>,
+) -> BTreeMap<DerivedTypeVar, Nullability> {
+    observed_nullability(proj, graph, reg_contexts)
+        .into_iter()
+        .map(|(dtv, (_, nullability))| (dtv, nullability))
+        .collect()
+}
+
+// Constrains each variable to have the capability of its nullability, ie. sub.in_0.@nonnull() <= top.
+fn constraints_for(
+    observed: BTreeMap<DerivedTypeVar, (Tid, Nullability)>,
+    top: &TypeVariable,
+) -> BTreeMap<Tid, ConstraintSet> {
+    let mut total: BTreeMap<Tid, ConstraintSet> = BTreeMap::new();
+    for (dtv, (observer, nullability)) in observed {
+        if let Some(lbl) = nullability.to_label() {
+            total
+                .entry(observer)
+                .or_insert_with(ConstraintSet::default)
+                .insert(TyConstraint::SubTy(SubtypeConstraint::new(
+                    dtv.create_with_label(FieldLabel::Custom(lbl)),
+                    DerivedTypeVar::new(top.clone()),
+                )));
+        }
+    }
+    total
+}
+
+/// Records the inferred nullability as metadata constraints so that it flows through the solver with the rest of the
+/// constraints. Each parameter or return with a known nullability gets a `@nullable()` or `@nonnull()` capability bounded
+/// by the top element. The constraints are keyed by the subprocedure the parameter belongs to, or by the caller for
+/// arguments of external symbols.
+pub fn nullability_constraints(
+    proj: &Project,
+    graph: &Graph,
+    reg_contexts: &HashMap<NodeIndex, RegisterContext>,
+    top: &TypeVariable,
+) -> BTreeMap<Tid, ConstraintSet> {
+    constraints_for(observed_nullability(proj, graph, reg_contexts), top)
+}
+
+/// Reads the nullability capabilities added by [nullability_constraints] back out of a solved sketch. A node that ends up
+/// with both capabilities, ie. because aliases were unified, is nullable.
+pub fn nullability_of_nodes<U: std::cmp::PartialEq>(
+    sg: &SketchGraph<U>,
+) -> BTreeMap<NodeIndex, Nullability> {
+    let mut res: BTreeMap<NodeIndex, Nullability> = BTreeMap::new();
+    for e in sg.get_graph().get_graph().edge_references() {
+        if let FieldLabel::Custom(lbl) = e.weight() {
+            if let Some(nullability) = Nullability::from_label(lbl) {
+                let entry = res.entry(e.source()).or_default();
+                *entry = entry.join(nullability);
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use cwe_checker_lib::intermediate_representation::{
+        BinOpType, Bitvector, ByteSize, Expression, Tid, UnOpType, Variable,
+    };
+
+    use crate::constraints::{parse_constraint_set, TypeVariable};
+
+    use super::{constraints_for, null_checked_variable, parameter_dtv, FieldLabel, Nullability};
+
+    #[test]
+    fn follows_flag_to_checked_pointer() {
+        let rdi = Variable {
+            name: "RDI".to_owned(),
+            size: ByteSize::new(8),
+            is_temp: false,
+        };
+        let zf = Variable {
+            name: "ZF".to_owned(),
+            size: ByteSize::new(1),
+            is_temp: false,
+        };
+
+        let mut assigned = BTreeMap::new();
+        assigned.insert(
+            zf.clone(),
+            Expression::BinOp {
+                op: BinOpType::IntEqual,
+                lhs: Box::new(Expression::Var(rdi.clone())),
+                rhs: Box::new(Expression::Const(Bitvector::from_i64(0))),
+            },
+        );
+
+        let cond = Expression::UnOp {
+            op: UnOpType::BoolNegate,
+            arg: Box::new(Expression::Var(zf)),
+        };
+        assert_eq!(null_checked_variable(&cond, &assigned, 0), Some(rdi));
+    }
+
+    #[test]
+    fn observed_nullability_becomes_capability_constraints() {
+        let sub = |addr: &str| Tid::create(format!("sub_{}", addr), addr.to_owned());
+        let (callee, caller) = (sub("00401000"), sub("00402000"));
+        let malloc = Tid::create("malloc".to_owned(), "00403000".to_owned());
+
+        let mut observed = BTreeMap::new();
+        observed.insert(
+            parameter_dtv(&callee, 0, FieldLabel::In),
+            (callee.clone(), Nullability::NonNull),
+        );
+        observed.insert(
+            parameter_dtv(&callee, 0, FieldLabel::Out),
+            (callee.clone(), Nullability::Nullable),
+        );
+        observed.insert(
+            parameter_dtv(&malloc, 1, FieldLabel::In),
+            (caller.clone(), Nullability::Nullable),
+        );
+        observed.insert(
+            parameter_dtv(&caller, 1, FieldLabel::In),
+            (caller.clone(), Nullability::Unknown),
+        );
+
+        let cons = constraints_for(observed, &TypeVariable::new("top".to_owned()));
+        let expected = |text: &str| parse_constraint_set(text).unwrap().1;
+        assert_eq!(cons.len(), 2);
+        assert_eq!(
+            cons[&callee],
+            expected("sub_00401000.in_0.@nonnull() <= top\nsub_00401000.out.@nullable() <= top")
+        );
+        assert_eq!(cons[&caller], expected("malloc.in_1.@nullable() <= top"));
+    }
+
+    #[test]
+    fn nullability_round_trips_through_its_label() {
+        for nullability in [Nullability::Nullable, Nullability::NonNull].iter() {
+            let lbl = nullability.to_label().unwrap();
+            assert_eq!(Nullability::from_label(&lbl), Some(*nullability));
+        }
+        assert_eq!(Nullability::Unknown.to_label(), None);
+        assert_eq!(
+            Nullability::NonNull.join(Nullability::Nullable),
+            Nullability::Nullable
+        );
+    }
+}
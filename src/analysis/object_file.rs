@@ -0,0 +1,486 @@
+use std::convert::TryFrom;
+
+/// A cursor over the bytes of a binary reading integers of either endianness.
+#[derive(Clone, Copy)]
+pub(crate) struct Reader<'a> {
+    pub(crate) data: &'a [u8],
+    pub(crate) pos: usize,
+    pub(crate) big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8], pos: usize, big_endian: bool) -> Reader<'a> {
+        Reader {
+            data,
+            pos,
+            big_endian,
+        }
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    pub(crate) fn uint(&mut self, len: usize) -> Option<u64> {
+        let bytes = self.bytes(len)?;
+        let fold = |acc: u64, b: &u8| (acc << 8) | u64::from(*b);
+        Some(if self.big_endian {
+            bytes.iter().fold(0, fold)
+        } else {
+            bytes.iter().rev().fold(0, fold)
+        })
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    pub(crate) fn uleb(&mut self) -> Option<u64> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                result |= u64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(result);
+            }
+        }
+    }
+
+    pub(crate) fn sleb(&mut self) -> Option<i64> {
+        let mut result = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                result |= i64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= -1i64 << shift;
+                }
+                return Some(result);
+            }
+        }
+    }
+
+    pub(crate) fn cstr(&mut self) -> Option<&'a [u8]> {
+        let len = self.data.get(self.pos..)?.iter().position(|b| *b == 0)?;
+        let s = self.bytes(len)?;
+        self.pos += 1;
+        Some(s)
+    }
+}
+
+fn slice(bytes: &[u8], offset: u64, size: u64) -> Option<&[u8]> {
+    let start = usize::try_from(offset).ok()?;
+    bytes.get(start..start.checked_add(usize::try_from(size).ok()?)?)
+}
+
+/// A section of an ELF binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfSection {
+    /// The name of the section, ie. `.eh_frame`
+    pub name: String,
+    /// The type of the section, ie. 4 for `SHT_RELA`
+    pub kind: u32,
+    /// The virtual address the section is loaded at
+    pub address: u64,
+    /// The file offset of the contents of the section
+    pub offset: u64,
+    /// The size of the section in bytes
+    pub size: u64,
+    /// The index of the section this section refers to, ie. the symbol table of a relocation section
+    pub link: u32,
+    /// The size of the entries of a section holding a table
+    pub entsize: u64,
+}
+
+/// A symbol of the symbol table of an ELF binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfSymbol {
+    /// The name of the symbol
+    pub name: String,
+    /// The value of the symbol, ie. the address of a function, or 0 for an undefined symbol
+    pub value: u64,
+    /// The size of the object the symbol refers to
+    pub size: u64,
+}
+
+/// The section headers of an ELF binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfFile<'a> {
+    /// The bytes of the binary
+    pub bytes: &'a [u8],
+    /// Whether the binary is ELF64
+    pub is_64: bool,
+    /// Whether the binary is big endian
+    pub big_endian: bool,
+    /// The machine of the binary, ie. 62 for x86-64
+    pub machine: u16,
+    /// The sections of the binary in header order
+    pub sections: Vec<ElfSection>,
+}
+
+impl<'a> ElfFile<'a> {
+    /// Parses the section headers of an ELF binary.
+    pub fn parse(bytes: &'a [u8]) -> Option<ElfFile<'a>> {
+        if bytes.get(0..4)? != b"\x7fELF" {
+            return None;
+        }
+        let is_64 = *bytes.get(4)? == 2;
+        let big_endian = *bytes.get(5)? == 2;
+        let word = if is_64 { 8 } else { 4 };
+        let header = |off: usize, len: usize| Reader::new(bytes, off, big_endian).uint(len);
+
+        let machine = u16::try_from(header(0x12, 2)?).ok()?;
+        let (shoff, shentsize, shnum, shstrndx) = if is_64 {
+            (
+                header(0x28, 8)?,
+                header(0x3a, 2)?,
+                header(0x3c, 2)?,
+                header(0x3e, 2)?,
+            )
+        } else {
+            (
+                header(0x20, 4)?,
+                header(0x2e, 2)?,
+                header(0x30, 2)?,
+                header(0x32, 2)?,
+            )
+        };
+        let section = |idx: u64| -> Option<(u64, ElfSection)> {
+            let base = usize::try_from(shoff.checked_add(idx.checked_mul(shentsize)?)?).ok()?;
+            let mut rdr = Reader::new(bytes, base, big_endian);
+            let name = rdr.uint(4)?;
+            let kind = u32::try_from(rdr.uint(4)?).ok()?;
+            rdr.bytes(word)?;
+            let address = rdr.uint(word)?;
+            let offset = rdr.uint(word)?;
+            let size = rdr.uint(word)?;
+            let link = u32::try_from(rdr.uint(4)?).ok()?;
+            rdr.bytes(4)?;
+            rdr.bytes(word)?;
+            let entsize = rdr.uint(word)?;
+            Some((
+                name,
+                ElfSection {
+                    name: String::new(),
+                    kind,
+                    address,
+                    offset,
+                    size,
+                    link,
+                    entsize,
+                },
+            ))
+        };
+
+        let (_, strtab) = section(shstrndx)?;
+        let strtab = slice(bytes, strtab.offset, strtab.size)?;
+        let sections = (0..shnum)
+            .map(|idx| {
+                let (name, mut sect) = section(idx)?;
+                let name = Reader::new(strtab, usize::try_from(name).ok()?, big_endian).cstr()?;
+                sect.name = String::from_utf8_lossy(name).into_owned();
+                Some(sect)
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(ElfFile {
+            bytes,
+            is_64,
+            big_endian,
+            machine,
+            sections,
+        })
+    }
+
+    /// The size of an address in bytes.
+    pub fn word_size(&self) -> u8 {
+        if self.is_64 {
+            8
+        } else {
+            4
+        }
+    }
+
+    /// Finds the first section with a name.
+    pub fn section(&self, name: &str) -> Option<&ElfSection> {
+        self.sections.iter().find(|sect| sect.name == name)
+    }
+
+    /// The contents of a section, nothing for sections without contents in the file like `.bss`.
+    pub fn contents(&self, section: &ElfSection) -> Option<&'a [u8]> {
+        // SHT_NOBITS
+        if section.kind == 8 {
+            return None;
+        }
+        slice(self.bytes, section.offset, section.size)
+    }
+
+    /// Reads the symbol at an index of a symbol table section, ie. the section linked by a relocation section.
+    pub fn symbol(&self, table: &ElfSection, index: u64) -> Option<ElfSymbol> {
+        let contents = self.contents(table)?;
+        let entry_size = if self.is_64 { 24 } else { 16 };
+        let mut rdr = self.reader(
+            contents,
+            usize::try_from(index.checked_mul(entry_size)?).ok()?,
+        );
+        let name = rdr.uint(4)?;
+        let (value, size) = if self.is_64 {
+            rdr.bytes(4)?;
+            (rdr.uint(8)?, rdr.uint(8)?)
+        } else {
+            (rdr.uint(4)?, rdr.uint(4)?)
+        };
+
+        let strtab = self.contents(self.sections.get(table.link as usize)?)?;
+        let name = self.reader(strtab, usize::try_from(name).ok()?).cstr()?;
+        Some(ElfSymbol {
+            name: String::from_utf8_lossy(name).into_owned(),
+            value,
+            size,
+        })
+    }
+
+    /// Reads the symbols of the static (`.symtab`) and dynamic (`.dynsym`) symbol tables.
+    pub fn symbols(&self) -> Vec<ElfSymbol> {
+        self.sections
+            .iter()
+            // SHT_SYMTAB and SHT_DYNSYM
+            .filter(|sect| sect.kind == 2 || sect.kind == 11)
+            .flat_map(|table| {
+                let count = if self.is_64 {
+                    table.size / 24
+                } else {
+                    table.size / 16
+                };
+                (0..count).filter_map(move |idx| self.symbol(table, idx))
+            })
+            .collect()
+    }
+
+    /// Finds the value of the first defined symbol with a name.
+    pub fn symbol_value(&self, name: &str) -> Option<u64> {
+        self.symbols()
+            .into_iter()
+            .find(|sym| sym.name == name && sym.value != 0)
+            .map(|sym| sym.value)
+    }
+
+    pub(crate) fn reader(&self, data: &'a [u8], pos: usize) -> Reader<'a> {
+        Reader::new(data, pos, self.big_endian)
+    }
+}
+
+/// A section of a PE binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeSection {
+    /// The name of the section, ie. `.reloc`
+    pub name: String,
+    /// The address of the section relative to the image base
+    pub virtual_address: u32,
+    /// The size of the section in memory
+    pub virtual_size: u32,
+    /// The file offset of the contents of the section
+    pub raw_offset: u32,
+    /// The size of the contents of the section in the file
+    pub raw_size: u32,
+}
+
+/// A function imported by a PE binary from a DLL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeImport {
+    /// The lowercase name of the DLL, ie. `kernel32.dll`
+    pub dll: String,
+    /// The name of the function, nothing when it is imported by ordinal
+    pub name: Option<String>,
+    /// The ordinal the function is imported by
+    pub ordinal: Option<u16>,
+    /// The address of the slot of the import address table holding the address of the function
+    pub address: u64,
+}
+
+/// The headers of a PE binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeFile<'a> {
+    /// The bytes of the binary
+    pub bytes: &'a [u8],
+    /// Whether the binary is PE32+
+    pub is_64: bool,
+    /// The preferred address of the image, which the addresses of the project are relative to
+    pub image_base: u64,
+    /// The relative address and size of each data directory, ie. index 1 for imports and 5 for base relocations
+    pub data_directories: Vec<(u32, u32)>,
+    /// The sections of the binary in header order
+    pub sections: Vec<PeSection>,
+}
+
+impl<'a> PeFile<'a> {
+    /// Parses the headers of a PE binary.
+    pub fn parse(bytes: &'a [u8]) -> Option<PeFile<'a>> {
+        if bytes.get(0..2)? != b"MZ" {
+            return None;
+        }
+        let read = |off: usize, len: usize| Reader::new(bytes, off, false).uint(len);
+        let pe = usize::try_from(read(0x3c, 4)?).ok()?;
+        if bytes.get(pe..pe.checked_add(4)?)? != b"PE\0\0" {
+            return None;
+        }
+
+        let coff = pe + 4;
+        let nsections = read(coff + 2, 2)?;
+        let optional_size = usize::try_from(read(coff + 16, 2)?).ok()?;
+        let optional = coff + 20;
+        let is_64 = match read(optional, 2)? {
+            0x10b => false,
+            0x20b => true,
+            _ => return None,
+        };
+        let (image_base, ndirs_at, dirs_at) = if is_64 {
+            (read(optional + 24, 8)?, optional + 108, optional + 112)
+        } else {
+            (read(optional + 28, 4)?, optional + 92, optional + 96)
+        };
+
+        let ndirs = read(ndirs_at, 4)?.min(16);
+        let data_directories = (0..ndirs)
+            .map(|idx| {
+                let at = dirs_at + usize::try_from(idx).ok()? * 8;
+                Some((
+                    u32::try_from(read(at, 4)?).ok()?,
+                    u32::try_from(read(at + 4, 4)?).ok()?,
+                ))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let sections_at = optional.checked_add(optional_size)?;
+        let sections = (0..nsections)
+            .map(|idx| {
+                let at = sections_at + usize::try_from(idx).ok()? * 40;
+                let name = bytes.get(at..at + 8)?;
+                let name_len = name.iter().position(|b| *b == 0).unwrap_or(8);
+                Some(PeSection {
+                    name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+                    virtual_size: u32::try_from(read(at + 8, 4)?).ok()?,
+                    virtual_address: u32::try_from(read(at + 12, 4)?).ok()?,
+                    raw_size: u32::try_from(read(at + 16, 4)?).ok()?,
+                    raw_offset: u32::try_from(read(at + 20, 4)?).ok()?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(PeFile {
+            bytes,
+            is_64,
+            image_base,
+            data_directories,
+            sections,
+        })
+    }
+
+    /// The size of an address in bytes.
+    pub fn word_size(&self) -> u8 {
+        if self.is_64 {
+            8
+        } else {
+            4
+        }
+    }
+
+    /// Converts an address relative to the image base to a file offset.
+    pub fn rva_to_offset(&self, rva: u32) -> Option<usize> {
+        self.sections.iter().find_map(|sect| {
+            let within = rva.checked_sub(sect.virtual_address)?;
+            if within >= sect.raw_size {
+                return None;
+            }
+            usize::try_from(sect.raw_offset.checked_add(within)?).ok()
+        })
+    }
+
+    /// The bytes starting at an address relative to the image base.
+    pub fn data_at(&self, rva: u32) -> Option<&'a [u8]> {
+        self.bytes.get(self.rva_to_offset(rva)?..)
+    }
+
+    /// Parses the import directory into the functions imported by the binary, in the order of the import address
+    /// table.
+    pub fn imports(&self) -> Vec<PeImport> {
+        let mut imports = Vec::new();
+        let rva = match self.data_directories.get(1) {
+            Some((rva, _)) if *rva != 0 => *rva,
+            _ => return imports,
+        };
+        let word = usize::from(self.word_size());
+        let ordinal_flag = 1u64 << (word * 8 - 1);
+        let read_u32 = |rva: u32| {
+            self.data_at(rva)
+                .and_then(|data| self.reader(data, 0).uint(4))
+                .and_then(|value| u32::try_from(value).ok())
+        };
+
+        for descriptor in (0u32..).map(|idx| rva.wrapping_add(idx * 20)) {
+            let (lookup, name, iat) = match (
+                read_u32(descriptor),
+                read_u32(descriptor + 12),
+                read_u32(descriptor + 16),
+            ) {
+                (Some(lookup), Some(name), Some(iat)) if name != 0 && iat != 0 => {
+                    (lookup, name, iat)
+                }
+                _ => break,
+            };
+            let dll = match self
+                .data_at(name)
+                .and_then(|data| self.reader(data, 0).cstr())
+            {
+                Some(dll) => String::from_utf8_lossy(dll).to_ascii_lowercase(),
+                None => continue,
+            };
+            // Bound imports overwrite the import address table, the lookup table keeps the names
+            let lookup = if lookup != 0 { lookup } else { iat };
+
+            for idx in 0u32.. {
+                let offset = idx * word as u32;
+                let thunk = match self
+                    .data_at(lookup.wrapping_add(offset))
+                    .and_then(|data| self.reader(data, 0).uint(word))
+                {
+                    Some(thunk) if thunk != 0 => thunk,
+                    _ => break,
+                };
+                let (name, ordinal) = if thunk & ordinal_flag != 0 {
+                    (None, Some(thunk as u16))
+                } else {
+                    // The hint precedes the name
+                    let name = self
+                        .data_at((thunk as u32 & 0x7fff_ffff).wrapping_add(2))
+                        .and_then(|data| self.reader(data, 0).cstr())
+                        .map(|name| String::from_utf8_lossy(name).into_owned());
+                    (name, None)
+                };
+                imports.push(PeImport {
+                    dll: dll.clone(),
+                    name,
+                    ordinal,
+                    address: self
+                        .image_base
+                        .wrapping_add(u64::from(iat.wrapping_add(offset))),
+                });
+            }
+        }
+        imports
+    }
+
+    pub(crate) fn reader(&self, data: &'a [u8], pos: usize) -> Reader<'a> {
+        Reader::new(data, pos, false)
+    }
+}
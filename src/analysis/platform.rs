@@ -0,0 +1,407 @@
+use std::collections::BTreeMap;
+
+use cwe_checker_lib::intermediate_representation::{Project, Tid};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::name_heuristics::constraints_by_injecting_sub,
+    constraint_generation::tid_to_tvar,
+    constraints::{
+        ConstraintOrigin, ConstraintReason, ConstraintSet, DerivedTypeVar, FieldLabel,
+        SubtypeConstraint, TyConstraint, TypeVariable,
+    },
+    solver::type_lattice::LatticeDefinition,
+};
+
+/// An operating system whose typedefs and API signatures are bundled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    /// Linux and glibc
+    Linux,
+    /// Win32
+    Windows,
+    /// macOS and its libc
+    MacOs,
+}
+
+impl std::str::FromStr for Platform {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linux" => Ok(Platform::Linux),
+            "windows" => Ok(Platform::Windows),
+            "macos" => Ok(Platform::MacOs),
+            _ => Err(anyhow::anyhow!(
+                "unknown platform {}, expected linux, windows or macos",
+                s
+            )),
+        }
+    }
+}
+
+/// Where a typedef is placed in the lattice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TypedefKind {
+    /// An integer, placed below the weakest integral type
+    Integral,
+    /// An opaque value that is not used arithmetically, placed below top
+    Opaque,
+}
+
+/// A value in the signature of a symbol that has a typedef.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignatureSlot {
+    /// The return value
+    Return,
+    /// The value the returned pointer points to
+    ReturnPointee,
+    /// The parameter at an index
+    Parameter(usize),
+    /// The value the callee stores through the pointer parameter at an index, ie. an `_Out_` parameter
+    ParameterPointee(usize),
+    /// The value the callee loads through the pointer parameter at an index, ie. an `_In_` parameter
+    ParameterInPointee(usize),
+}
+
+impl SignatureSlot {
+    fn labels(&self) -> Vec<FieldLabel> {
+        match self {
+            SignatureSlot::Return => vec![FieldLabel::Out(0)],
+            SignatureSlot::ReturnPointee => vec![FieldLabel::Out(0), FieldLabel::Load],
+            SignatureSlot::Parameter(idx) => vec![FieldLabel::In(*idx)],
+            SignatureSlot::ParameterPointee(idx) => vec![FieldLabel::In(*idx), FieldLabel::Store],
+            SignatureSlot::ParameterInPointee(idx) => vec![FieldLabel::In(*idx), FieldLabel::Load],
+        }
+    }
+
+    // Parameters and what the callee loads through them are consumed by the callee so the typedef bounds them from
+    // above, every other slot is produced by the callee so the typedef bounds it from below.
+    fn is_consumed(&self) -> bool {
+        matches!(
+            self,
+            SignatureSlot::Parameter(_) | SignatureSlot::ParameterInPointee(_)
+        )
+    }
+}
+
+/// A symbol name and its typed slots.
+pub(crate) type BundledSignature = (&'static str, &'static [(SignatureSlot, &'static str)]);
+
+pub(crate) const POSIX_TYPEDEFS: &[(&str, TypedefKind)] = &[
+    ("file_descriptor", TypedefKind::Integral),
+    ("time_t", TypedefKind::Integral),
+    ("pid_t", TypedefKind::Integral),
+    ("ssize_t", TypedefKind::Integral),
+    ("off_t", TypedefKind::Integral),
+    ("errno_t", TypedefKind::Integral),
+];
+
+const POSIX_SIGNATURES: &[BundledSignature] = &[
+    ("open", &[(SignatureSlot::Return, "file_descriptor")]),
+    ("open64", &[(SignatureSlot::Return, "file_descriptor")]),
+    ("creat", &[(SignatureSlot::Return, "file_descriptor")]),
+    (
+        "openat",
+        &[
+            (SignatureSlot::Return, "file_descriptor"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+        ],
+    ),
+    ("socket", &[(SignatureSlot::Return, "file_descriptor")]),
+    (
+        "accept",
+        &[
+            (SignatureSlot::Return, "file_descriptor"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+        ],
+    ),
+    (
+        "dup",
+        &[
+            (SignatureSlot::Return, "file_descriptor"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+        ],
+    ),
+    (
+        "dup2",
+        &[
+            (SignatureSlot::Return, "file_descriptor"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+            (SignatureSlot::Parameter(1), "file_descriptor"),
+        ],
+    ),
+    ("close", &[(SignatureSlot::Parameter(0), "file_descriptor")]),
+    ("fsync", &[(SignatureSlot::Parameter(0), "file_descriptor")]),
+    ("fstat", &[(SignatureSlot::Parameter(0), "file_descriptor")]),
+    ("ioctl", &[(SignatureSlot::Parameter(0), "file_descriptor")]),
+    ("fcntl", &[(SignatureSlot::Parameter(0), "file_descriptor")]),
+    (
+        "read",
+        &[
+            (SignatureSlot::Return, "ssize_t"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+        ],
+    ),
+    (
+        "write",
+        &[
+            (SignatureSlot::Return, "ssize_t"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+        ],
+    ),
+    (
+        "lseek",
+        &[
+            (SignatureSlot::Return, "off_t"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+            (SignatureSlot::Parameter(1), "off_t"),
+        ],
+    ),
+    (
+        "time",
+        &[
+            (SignatureSlot::Return, "time_t"),
+            (SignatureSlot::ParameterPointee(0), "time_t"),
+        ],
+    ),
+    ("getpid", &[(SignatureSlot::Return, "pid_t")]),
+    ("getppid", &[(SignatureSlot::Return, "pid_t")]),
+    ("fork", &[(SignatureSlot::Return, "pid_t")]),
+    ("kill", &[(SignatureSlot::Parameter(0), "pid_t")]),
+    (
+        "waitpid",
+        &[
+            (SignatureSlot::Return, "pid_t"),
+            (SignatureSlot::Parameter(0), "pid_t"),
+        ],
+    ),
+];
+
+const LINUX_SIGNATURES: &[BundledSignature] = &[(
+    "__errno_location",
+    &[(SignatureSlot::ReturnPointee, "errno_t")],
+)];
+
+const MACOS_SIGNATURES: &[BundledSignature] =
+    &[("__error", &[(SignatureSlot::ReturnPointee, "errno_t")])];
+
+const WINDOWS_TYPEDEFS: &[(&str, TypedefKind)] = &[
+    ("HANDLE", TypedefKind::Opaque),
+    ("time_t", TypedefKind::Integral),
+    ("errno_t", TypedefKind::Integral),
+];
+
+const WINDOWS_SIGNATURES: &[BundledSignature] = &[
+    ("CreateFileA", &[(SignatureSlot::Return, "HANDLE")]),
+    ("CreateFileW", &[(SignatureSlot::Return, "HANDLE")]),
+    ("CreateFileMappingA", &[(SignatureSlot::Return, "HANDLE")]),
+    ("CreateFileMappingW", &[(SignatureSlot::Return, "HANDLE")]),
+    ("CreateEventA", &[(SignatureSlot::Return, "HANDLE")]),
+    ("CreateEventW", &[(SignatureSlot::Return, "HANDLE")]),
+    ("CreateMutexA", &[(SignatureSlot::Return, "HANDLE")]),
+    ("CreateMutexW", &[(SignatureSlot::Return, "HANDLE")]),
+    ("CreateThread", &[(SignatureSlot::Return, "HANDLE")]),
+    ("OpenProcess", &[(SignatureSlot::Return, "HANDLE")]),
+    ("GetCurrentProcess", &[(SignatureSlot::Return, "HANDLE")]),
+    ("GetStdHandle", &[(SignatureSlot::Return, "HANDLE")]),
+    ("CloseHandle", &[(SignatureSlot::Parameter(0), "HANDLE")]),
+    ("ReadFile", &[(SignatureSlot::Parameter(0), "HANDLE")]),
+    ("WriteFile", &[(SignatureSlot::Parameter(0), "HANDLE")]),
+    ("GetFileSize", &[(SignatureSlot::Parameter(0), "HANDLE")]),
+    ("SetEvent", &[(SignatureSlot::Parameter(0), "HANDLE")]),
+    (
+        "WaitForSingleObject",
+        &[(SignatureSlot::Parameter(0), "HANDLE")],
+    ),
+    (
+        "time",
+        &[
+            (SignatureSlot::Return, "time_t"),
+            (SignatureSlot::ParameterPointee(0), "time_t"),
+        ],
+    ),
+    (
+        "_time64",
+        &[
+            (SignatureSlot::Return, "time_t"),
+            (SignatureSlot::ParameterPointee(0), "time_t"),
+        ],
+    ),
+    ("_errno", &[(SignatureSlot::ReturnPointee, "errno_t")]),
+];
+
+/// Creates the constraints typing the slots of the signature of a callable with their lattice element. Parameters are
+/// bounded from above and every other slot from below. Elements for which is_element is false are skipped.
+pub fn slot_constraints(
+    callable: &Tid,
+    slots: &[(SignatureSlot, String)],
+    is_element: &impl Fn(&str) -> bool,
+) -> ConstraintSet {
+    let mut cons = ConstraintSet::default();
+    for (slot, typedef) in slots.iter() {
+        if !is_element(typedef) {
+            continue;
+        }
+
+        let mut dtv = DerivedTypeVar::new(tid_to_tvar(callable));
+        slot.labels()
+            .into_iter()
+            .for_each(|label| dtv.add_field_label(label));
+        let elem = DerivedTypeVar::new(TypeVariable::new(typedef.clone()));
+        cons.insert(TyConstraint::SubTy(if slot.is_consumed() {
+            SubtypeConstraint::new(dtv, elem)
+        } else {
+            SubtypeConstraint::new(elem, dtv)
+        }));
+    }
+
+    cons.with_origin(&ConstraintOrigin::new(
+        callable.clone(),
+        ConstraintReason::Platform,
+    ))
+}
+
+/// The typedefs of a platform and the signatures of the wrapper functions that produce and consume them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlatformProfile {
+    /// The typedefs added to the lattice
+    pub typedefs: BTreeMap<String, TypedefKind>,
+    /// The typed slots of each symbol, keyed by symbol name
+    pub signatures: BTreeMap<String, Vec<(SignatureSlot, String)>>,
+}
+
+impl PlatformProfile {
+    /// The bundled profile of a platform.
+    pub fn for_platform(platform: Platform) -> PlatformProfile {
+        let (typedefs, signatures): (_, Vec<&[BundledSignature]>) = match platform {
+            Platform::Linux => (POSIX_TYPEDEFS, vec![POSIX_SIGNATURES, LINUX_SIGNATURES]),
+            Platform::MacOs => (POSIX_TYPEDEFS, vec![POSIX_SIGNATURES, MACOS_SIGNATURES]),
+            Platform::Windows => (WINDOWS_TYPEDEFS, vec![WINDOWS_SIGNATURES]),
+        };
+        Self::from_bundled(typedefs, &signatures)
+    }
+
+    /// Creates a profile from bundled typedefs and tables of signatures.
+    pub(crate) fn from_bundled(
+        typedefs: &[(&str, TypedefKind)],
+        signatures: &[&[BundledSignature]],
+    ) -> PlatformProfile {
+        PlatformProfile {
+            typedefs: typedefs
+                .iter()
+                .map(|(name, kind)| ((*name).to_owned(), *kind))
+                .collect(),
+            signatures: signatures
+                .iter()
+                .copied()
+                .flatten()
+                .map(|(symbol, slots)| {
+                    (
+                        (*symbol).to_owned(),
+                        slots
+                            .iter()
+                            .map(|(slot, typedef)| (*slot, (*typedef).to_owned()))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Creates the relations placing the typedefs of the profile in a base lattice, to be merged with it. Typedefs the
+    /// base lattice already defines keep their place.
+    pub fn lattice_definition(&self, base: &LatticeDefinition) -> LatticeDefinition {
+        let relations = self
+            .typedefs
+            .iter()
+            .filter(|(name, _)| !base.has_element(name))
+            .flat_map(|(name, kind)| {
+                let parent = match kind {
+                    TypedefKind::Integral => base.get_weakest_integral_type(),
+                    TypedefKind::Opaque => base.get_top_handle(),
+                };
+                vec![
+                    (base.get_bottom_handle().to_owned(), name.clone()),
+                    (name.clone(), parent.to_owned()),
+                ]
+            })
+            .collect();
+
+        LatticeDefinition::new(
+            relations,
+            base.get_top_handle().to_owned(),
+            base.get_bottom_handle().to_owned(),
+            base.get_weakest_integral_type().to_owned(),
+        )
+    }
+
+    /// Creates the constraints typing the signature of a subprocedure or extern symbol. Typedefs for which is_element
+    /// is false, ie. that are missing from the lattice, are skipped.
+    pub fn signature_constraints(
+        &self,
+        callable: &Tid,
+        name: &str,
+        is_element: &impl Fn(&str) -> bool,
+    ) -> ConstraintSet {
+        let slots = self
+            .signatures
+            .get(name.trim_start_matches('_'))
+            .or_else(|| self.signatures.get(name));
+        slot_constraints(
+            callable,
+            slots.map(Vec::as_slice).unwrap_or_default(),
+            is_element,
+        )
+    }
+
+    /// Creates the constraints of the profile for a project keyed by the subprocedure they are injected into, like
+    /// [crate::analysis::name_heuristics::name_hint_constraints].
+    pub fn platform_constraints(
+        &self,
+        proj: &Project,
+        is_element: impl Fn(&str) -> bool,
+    ) -> BTreeMap<Tid, ConstraintSet> {
+        constraints_by_injecting_sub(proj, |callable, name| {
+            self.signature_constraints(callable, name, &is_element)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use crate::{constraints::parse_constraint_set, solver::type_lattice::LatticeDefinition};
+
+    use super::{Platform, PlatformProfile};
+
+    #[test]
+    fn profile_types_wrappers_with_typedefs() {
+        let profile = PlatformProfile::for_platform(Platform::Linux);
+        let cons = profile.signature_constraints(
+            &Tid::create("lseek".to_owned(), "1000".to_owned()),
+            "lseek",
+            &|name| name != "file_descriptor",
+        );
+        let (_, expected) =
+            parse_constraint_set("off_t <= lseek.out\nlseek.in_1 <= off_t").unwrap();
+        assert_eq!(cons, expected);
+
+        let base = LatticeDefinition::new(
+            vec![
+                ("bottom".to_owned(), "int".to_owned()),
+                ("bottom".to_owned(), "time_t".to_owned()),
+                ("int".to_owned(), "T".to_owned()),
+            ],
+            "T".to_owned(),
+            "bottom".to_owned(),
+            "int".to_owned(),
+        );
+        let windows = PlatformProfile::for_platform(Platform::Windows).lattice_definition(&base);
+        assert!(windows.has_element("HANDLE"));
+        assert!(windows.has_element("errno_t"));
+        assert!(!windows.has_element("time_t"));
+    }
+}
@@ -0,0 +1,170 @@
+use cwe_checker_lib::intermediate_representation::Project;
+
+use crate::analysis::{mips::resolve_register_relative, object_file::ElfFile, riscv};
+
+/// The offset of the TOC base from the start of the TOC, so 16 bit offsets from `r2` reach 64 KiB of entries.
+const TOC_BASE_OFFSET: u64 = 0x8000;
+
+/// Checks whether the architecture of a project is 32 or 64 bit PowerPC.
+pub fn is_ppc(cpu_architecture: &str) -> bool {
+    let arch = cpu_architecture.to_ascii_lowercase();
+    arch.contains("powerpc") || arch.contains("ppc")
+}
+
+/// The ELF calling conventions of PowerPC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PpcAbi {
+    /// The 32 bit System V ABI, with small data addressed relative to `r13` and `r2`
+    SysV32,
+    /// The 64 bit ELFv1 and ELFv2 ABIs, with globals addressed through the TOC in `r2`
+    Elf64,
+}
+
+impl PpcAbi {
+    /// The ABI of the architecture of a project, if it is PowerPC.
+    pub fn for_architecture(cpu_architecture: &str) -> Option<PpcAbi> {
+        if !is_ppc(cpu_architecture) {
+            None
+        } else if cpu_architecture.contains("64") {
+            Some(PpcAbi::Elf64)
+        } else {
+            Some(PpcAbi::SysV32)
+        }
+    }
+
+    /// The registers holding the first words of the arguments.
+    pub fn parameter_registers(&self) -> &'static [&'static str] {
+        &["r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10"]
+    }
+
+    /// The registers holding the return value, a 64 bit value is returned in the pair `r3`:`r4` on 32 bit PowerPC.
+    pub fn return_registers(&self) -> &'static [&'static str] {
+        match self {
+            PpcAbi::SysV32 => &["r3", "r4"],
+            PpcAbi::Elf64 => &["r3"],
+        }
+    }
+
+    /// The registers preserved across calls. `r13` holds the small data anchor or the thread pointer and the TOC
+    /// pointer in `r2` is restored by the caller after calls into other modules.
+    pub fn callee_saved_registers(&self) -> &'static [&'static str] {
+        &[
+            "r1", "r2", "r13", "r14", "r15", "r16", "r17", "r18", "r19", "r20", "r21", "r22",
+            "r23", "r24", "r25", "r26", "r27", "r28", "r29", "r30", "r31",
+        ]
+    }
+}
+
+/// Finds the TOC base of a 64 bit PowerPC ELF binary, the `.TOC.` symbol or else 0x8000 past the start of the GOT
+/// or the TOC.
+pub fn toc_base(elf: &ElfFile) -> Option<u64> {
+    // EM_PPC64
+    if elf.machine != 21 {
+        return None;
+    }
+    elf.symbol_value(".TOC.").or_else(|| {
+        elf.section(".got")
+            .or_else(|| elf.section(".toc"))
+            .map(|toc| toc.address.wrapping_add(TOC_BASE_OFFSET))
+    })
+}
+
+/// Resolves the TOC and small data relative accesses of a PowerPC project to the concrete addresses the constant
+/// resolver maps to globals. On 64 bit PowerPC, loads of TOC entries become the address the entry holds and loads of
+/// the entry of an ELFv1 function descriptor in `.opd` become the address of the function, so calls through a
+/// function pointer from the TOC call the function. On 32 bit PowerPC, accesses relative to `r13` and `r2` are
+/// resolved against `_SDA_BASE_` and `_SDA2_BASE_`. Returns the number of resolved defs and calls.
+pub fn resolve_toc_relative(proj: &mut Project, elf: &ElfFile) -> usize {
+    match PpcAbi::for_architecture(&proj.cpu_architecture) {
+        Some(PpcAbi::Elf64) => toc_base(elf)
+            .map(|toc| resolve_register_relative(proj, elf, "r2", toc, &[".toc", ".got", ".opd"]))
+            .unwrap_or(0),
+        // EM_PPC
+        Some(PpcAbi::SysV32) if elf.machine == 20 => {
+            let anchors = [("r13", "_SDA_BASE_"), ("r2", "_SDA2_BASE_")];
+            anchors
+                .iter()
+                .filter_map(|(register, anchor)| {
+                    let base = elf.symbol_value(anchor)?;
+                    Some(resolve_register_relative(proj, elf, register, base, &[]))
+                })
+                .sum()
+        }
+        _ => 0,
+    }
+}
+
+/// Models the arguments and returns of the subprocedures of a PowerPC project Ghidra exported without a calling
+/// convention: the arguments in `r3`-`r10` the entry block reads, and the return `r3`, or the pair `r3`:`r4` on 32
+/// bit PowerPC. Returns the number of subprocedures given formal arguments or returns.
+pub fn model_calling_convention(proj: &mut Project) -> usize {
+    match PpcAbi::for_architecture(&proj.cpu_architecture) {
+        Some(abi) => {
+            riscv::model_register_formals(proj, abi.parameter_registers(), abi.return_registers())
+        }
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analysis::object_file::ElfFile;
+
+    use super::{toc_base, PpcAbi};
+
+    /// A big endian EM_PPC64 ELF with the sections `.shstrtab`, `.got` at 0x10020000 and `.opd` at 0x10030000.
+    fn ppc64_elf() -> Vec<u8> {
+        let names = b"\0.shstrtab\0.got\0.opd\0";
+        let mut elf = vec![0u8; 64];
+        elf[..4].copy_from_slice(b"\x7fELF");
+        // ELFCLASS64, ELFDATA2MSB, EV_CURRENT
+        elf[4] = 2;
+        elf[5] = 2;
+        elf[6] = 1;
+        elf[18..20].copy_from_slice(&21u16.to_be_bytes());
+        let names_offset = elf.len() as u64;
+        elf.extend_from_slice(names);
+        let shoff = elf.len() as u64;
+        elf[0x28..0x30].copy_from_slice(&shoff.to_be_bytes());
+        elf[0x3a..0x3c].copy_from_slice(&64u16.to_be_bytes());
+        elf[0x3c..0x3e].copy_from_slice(&4u16.to_be_bytes());
+        elf[0x3e..0x40].copy_from_slice(&1u16.to_be_bytes());
+
+        // name, type, address, offset, size
+        let headers: [(u32, u32, u64, u64, u64); 4] = [
+            (0, 0, 0, 0, 0),
+            (1, 3, 0, names_offset, names.len() as u64),
+            (11, 1, 0x1002_0000, 0, 0),
+            (16, 1, 0x1003_0000, 0, 0),
+        ];
+        for (name, kind, address, offset, size) in headers.iter() {
+            let mut header = vec![0u8; 64];
+            header[0..4].copy_from_slice(&name.to_be_bytes());
+            header[4..8].copy_from_slice(&kind.to_be_bytes());
+            header[16..24].copy_from_slice(&address.to_be_bytes());
+            header[24..32].copy_from_slice(&offset.to_be_bytes());
+            header[32..40].copy_from_slice(&size.to_be_bytes());
+            elf.extend_from_slice(&header);
+        }
+        elf
+    }
+
+    #[test]
+    fn toc_base_of_elf64() {
+        let bytes = ppc64_elf();
+        let elf = ElfFile::parse(&bytes).unwrap();
+        assert!(elf.section(".opd").is_some());
+        // No .TOC. symbol, so the TOC base is 0x8000 past the GOT
+        assert_eq!(toc_base(&elf), Some(0x1002_8000));
+
+        assert_eq!(
+            PpcAbi::for_architecture("PowerPC:BE:64:default"),
+            Some(PpcAbi::Elf64)
+        );
+        assert_eq!(
+            PpcAbi::for_architecture("PowerPC:BE:32:e500"),
+            Some(PpcAbi::SysV32)
+        );
+        assert_eq!(PpcAbi::SysV32.return_registers(), &["r3", "r4"]);
+    }
+}
@@ -19,6 +19,11 @@ use std::{
     ops::{Deref, DerefMut},
 };
 
+use crate::{
+    analysis::{mips::MipsAbi, ppc::PpcAbi, riscv},
+    constraint_generation::argument_registers,
+};
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 /// New types a domain map with a union merging strategy to insert the implicit bottom values for unmapped keys
 /// during comparison in order to match the partial order we are actually trying to ascend
@@ -290,6 +295,17 @@ fn get_jump_target(call_term: &Term<Jmp>) -> Option<&Tid> {
     }
 }
 
+/// The registers the ABI of an architecture preserves across calls. Ghidra exports no calling convention for some
+/// MIPS, RISC-V and PowerPC variants, so calls fall back to these.
+fn abi_callee_saved_registers(cpu_architecture: &str) -> Option<&'static [&'static str]> {
+    MipsAbi::for_architecture(cpu_architecture)
+        .map(|abi| abi.callee_saved_registers())
+        .or_else(|| {
+            PpcAbi::for_architecture(cpu_architecture).map(|abi| abi.callee_saved_registers())
+        })
+        .or_else(|| riscv::is_riscv(cpu_architecture).then(|| riscv::CALLEE_SAVED_REGISTERS))
+}
+
 fn get_cc_for_jmp<'a>(proj: &'a Project, call_term: &Term<Jmp>) -> Option<&'a CallingConvention> {
     get_jump_target(call_term).and_then(|target| {
         proj.get_specific_calling_convention(
@@ -316,15 +332,12 @@ pub fn apply_return(
     let register_returns = get_function_returns(&call_term.term, &project.program)
         .into_iter()
         .enumerate()
-        .filter_map(|(idx, x)| match x {
-            Arg::Register { expr, .. } => {
-                if let Expression::Var(var) = expr {
-                    Some((idx, var))
-                } else {
-                    None
-                }
-            }
-            Arg::Stack { .. } => None,
+        .flat_map(|(idx, x)| match x {
+            Arg::Register { expr, .. } => argument_registers(expr, &project.cpu_architecture)
+                .into_iter()
+                .map(|var| (idx, var))
+                .collect(),
+            Arg::Stack { .. } => Vec::new(),
         })
         .collect::<BTreeSet<_>>();
 
@@ -351,6 +364,12 @@ pub fn apply_return(
         for killed in cc_killed {
             kill_definition_of_variable(&mut new_value, killed)
         }
+    } else if let Some(saves) = abi_callee_saved_registers(&project.cpu_architecture) {
+        for killed in project.register_set.iter().filter(|reg| {
+            !saves.contains(&reg.name.as_str()) && !register_returns.iter().any(|(_, v)| *v == *reg)
+        }) {
+            kill_definition_of_variable(&mut new_value, killed)
+        }
     }
 
     for (idx, var) in register_returns.iter() {
@@ -397,7 +416,7 @@ impl<'a> cwe_checker_lib::analysis::forward_interprocedural_fixpoint::Context<'a
             for (idx, arg) in extern_symb.return_values.iter().enumerate() {
                 match arg {
                     Arg::Register { expr, data_type: _ } => {
-                        if let Expression::Var(var) = expr {
+                        for var in argument_registers(expr, &self.project.cpu_architecture) {
                             apply_definition_of_variable(
                                 &mut new_value,
                                 var.clone(),
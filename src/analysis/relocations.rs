@@ -0,0 +1,434 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+};
+
+use cwe_checker_lib::intermediate_representation::{Def, Expression, Project, Tid};
+
+use crate::{
+    analysis::{
+        object_file::{ElfFile, PeFile, Reader},
+        windows_api::import_symbol_name,
+    },
+    constraints::{
+        ConstraintOrigin, ConstraintReason, ConstraintSet, DerivedTypeVar, Field, FieldLabel,
+        SubtypeConstraint, TyConstraint, TypeVariable,
+    },
+};
+
+/// The largest offset of a relocated word into the global variable containing it. Relocations further from the
+/// closest preceding global are in data Ghidra did not define a global for and are dropped.
+const MAX_GLOBAL_OFFSET: u64 = 1 << 16;
+
+/// A word of a binary that the loader relocates, so it holds a pointer at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PointerRelocation {
+    /// The address of the relocated word
+    pub address: u64,
+    /// The address the word points to, if the relocation determines it
+    pub target: Option<u64>,
+    /// The size of the relocated word in bytes
+    pub size: u8,
+}
+
+/// An entry of an ELF `SHT_REL` or `SHT_RELA` section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ElfRelocation {
+    offset: u64,
+    kind: u32,
+    symbol: u64,
+    addend: Option<i64>,
+}
+
+/// How an ELF relocation type of a machine produces a pointer sized word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PointerKind {
+    /// The address of a symbol plus the addend
+    Absolute,
+    /// The load base plus the addend
+    Relative,
+}
+
+fn pointer_kind(machine: u16, kind: u32) -> Option<PointerKind> {
+    let (absolute, relative): (&[u32], &[u32]) = match machine {
+        // x86: R_386_32, R_386_GLOB_DAT, R_386_JMP_SLOT, R_386_RELATIVE
+        3 => (&[1, 6, 7], &[8]),
+        // MIPS: R_MIPS_32, R_MIPS_64, R_MIPS_REL32
+        8 => (&[2, 18], &[3]),
+        // PowerPC: R_PPC_ADDR32, R_PPC_GLOB_DAT, R_PPC_JMP_SLOT, R_PPC_RELATIVE
+        20 => (&[1, 20, 21], &[22]),
+        // PowerPC64: R_PPC64_ADDR64, R_PPC64_GLOB_DAT, R_PPC64_JMP_SLOT, R_PPC64_RELATIVE
+        21 => (&[38, 20, 21], &[22]),
+        // ARM: R_ARM_ABS32, R_ARM_GLOB_DAT, R_ARM_JUMP_SLOT, R_ARM_RELATIVE
+        40 => (&[2, 21, 22], &[23]),
+        // x86-64: R_X86_64_64, R_X86_64_GLOB_DAT, R_X86_64_JUMP_SLOT, R_X86_64_RELATIVE
+        62 => (&[1, 6, 7], &[8]),
+        // AArch64: R_AARCH64_ABS64, R_AARCH64_GLOB_DAT, R_AARCH64_JUMP_SLOT, R_AARCH64_RELATIVE
+        183 => (&[257, 1025, 1026], &[1027]),
+        // RISC-V: R_RISCV_32, R_RISCV_64, R_RISCV_JUMP_SLOT, R_RISCV_RELATIVE
+        243 => (&[1, 2, 5], &[3]),
+        _ => return None,
+    };
+
+    if absolute.contains(&kind) {
+        Some(PointerKind::Absolute)
+    } else if relative.contains(&kind) {
+        Some(PointerKind::Relative)
+    } else {
+        None
+    }
+}
+
+fn parse_elf_relocations(
+    data: &[u8],
+    is_64: bool,
+    big_endian: bool,
+    has_addend: bool,
+) -> Vec<ElfRelocation> {
+    let word = if is_64 { 8 } else { 4 };
+    let entry_size = if has_addend { 3 * word } else { 2 * word };
+    let mut relocations = Vec::new();
+    for entry in data.chunks_exact(entry_size) {
+        let mut rdr = Reader::new(entry, 0, big_endian);
+        let (offset, info) = match (rdr.uint(word), rdr.uint(word)) {
+            (Some(offset), Some(info)) => (offset, info),
+            _ => continue,
+        };
+        let (kind, symbol) = if is_64 {
+            (info & 0xffff_ffff, info >> 32)
+        } else {
+            (info & 0xff, info >> 8)
+        };
+        let addend = if has_addend {
+            rdr.uint(word).map(|addend| {
+                if is_64 {
+                    addend as i64
+                } else {
+                    i64::from(addend as u32 as i32)
+                }
+            })
+        } else {
+            None
+        };
+
+        relocations.push(ElfRelocation {
+            offset,
+            kind: kind as u32,
+            symbol,
+            addend,
+        });
+    }
+    relocations
+}
+
+fn elf_word_at(elf: &ElfFile, address: u64) -> Option<u64> {
+    let word = usize::from(elf.word_size());
+    elf.sections.iter().find_map(|sect| {
+        let within = address.checked_sub(sect.address)?;
+        if sect.address == 0 || within >= sect.size {
+            return None;
+        }
+        let contents = elf.contents(sect)?;
+        elf.reader(contents, usize::try_from(within).ok()?)
+            .uint(word)
+    })
+}
+
+fn elf_symbol_value(elf: &ElfFile, symtab: u32, symbol: u64) -> Option<u64> {
+    let table = elf.sections.get(symtab as usize)?;
+    elf.symbol(table, symbol)
+        .map(|sym| sym.value)
+        .filter(|value| *value != 0)
+}
+
+/// Finds the pointer sized relocations of an ELF binary. The target of a relative relocation is its addend, the
+/// target of a relocation against a symbol is the value of the symbol plus the addend, unless the symbol is
+/// undefined, ie. imported from a shared library.
+pub fn elf_pointer_relocations(elf: &ElfFile) -> Vec<PointerRelocation> {
+    let mut relocations = Vec::new();
+    for sect in elf.sections.iter() {
+        // SHT_RELA and SHT_REL
+        let has_addend = match sect.kind {
+            4 => true,
+            9 => false,
+            _ => continue,
+        };
+        let data = match elf.contents(sect) {
+            Some(data) => data,
+            None => continue,
+        };
+
+        for reloc in parse_elf_relocations(data, elf.is_64, elf.big_endian, has_addend) {
+            let kind = match pointer_kind(elf.machine, reloc.kind) {
+                Some(kind) => kind,
+                None => continue,
+            };
+            // Without an explicit addend the addend is the word being relocated
+            let addend = reloc
+                .addend
+                .or_else(|| elf_word_at(elf, reloc.offset).map(|word| word as i64));
+            let target = match kind {
+                PointerKind::Relative => addend.map(|addend| addend as u64),
+                PointerKind::Absolute => elf_symbol_value(elf, sect.link, reloc.symbol)
+                    .map(|value| value.wrapping_add(reloc.addend.unwrap_or(0) as u64)),
+            };
+
+            relocations.push(PointerRelocation {
+                address: reloc.offset,
+                target: target.filter(|target| *target != 0),
+                size: elf.word_size(),
+            });
+        }
+    }
+    relocations
+}
+
+/// Parses the blocks of a PE base relocation directory into the relative address and type of each entry.
+fn parse_base_relocations(data: &[u8]) -> Vec<(u32, u16)> {
+    let mut entries = Vec::new();
+    let mut rdr = Reader::new(data, 0, false);
+    while let (Some(page), Some(block_size)) = (rdr.uint(4), rdr.uint(4)) {
+        if block_size < 8 {
+            break;
+        }
+        for _ in 0..(block_size - 8) / 2 {
+            let entry = match rdr.uint(2) {
+                Some(entry) => entry as u16,
+                None => return entries,
+            };
+            entries.push((
+                (page as u32).wrapping_add(u32::from(entry & 0xfff)),
+                entry >> 12,
+            ));
+        }
+    }
+    entries
+}
+
+/// Finds the pointer sized base relocations of a PE binary. The relocated words hold the address of their target
+/// relative to the preferred image base, which is read from the binary.
+pub fn pe_pointer_relocations(pe: &PeFile) -> Vec<PointerRelocation> {
+    let (rva, size) = match pe.data_directories.get(5) {
+        Some(dir) if dir.0 != 0 => *dir,
+        _ => return Vec::new(),
+    };
+    let data = match pe.data_at(rva) {
+        Some(data) => &data[..data.len().min(size as usize)],
+        None => return Vec::new(),
+    };
+
+    parse_base_relocations(data)
+        .into_iter()
+        .filter_map(|(rva, kind)| {
+            // IMAGE_REL_BASED_HIGHLOW and IMAGE_REL_BASED_DIR64
+            let size = match kind {
+                3 => 4,
+                10 => 8,
+                _ => return None,
+            };
+            let target = pe
+                .data_at(rva)
+                .and_then(|data| pe.reader(data, 0).uint(size))
+                .filter(|target| *target != 0);
+            Some(PointerRelocation {
+                address: pe.image_base.wrapping_add(u64::from(rva)),
+                target,
+                size: size as u8,
+            })
+        })
+        .collect()
+}
+
+/// Finds the pointer sized relocations of an ELF or PE binary, nothing for other formats.
+pub fn pointer_relocations(bytes: &[u8]) -> Vec<PointerRelocation> {
+    if let Some(elf) = ElfFile::parse(bytes) {
+        elf_pointer_relocations(&elf)
+    } else if let Some(pe) = PeFile::parse(bytes) {
+        pe_pointer_relocations(&pe)
+    } else {
+        Vec::new()
+    }
+}
+
+/// Finds the slots of an ELF binary the dynamic linker fills with the address of an imported function or variable,
+/// ie. the GOT entries relocated against undefined symbols, keyed by their address.
+pub fn elf_import_slots(elf: &ElfFile) -> BTreeMap<u64, String> {
+    let mut slots = BTreeMap::new();
+    for sect in elf.sections.iter() {
+        // SHT_RELA and SHT_REL
+        let has_addend = match sect.kind {
+            4 => true,
+            9 => false,
+            _ => continue,
+        };
+        let (data, table) = match (elf.contents(sect), elf.sections.get(sect.link as usize)) {
+            (Some(data), Some(table)) => (data, table),
+            _ => continue,
+        };
+
+        for reloc in parse_elf_relocations(data, elf.is_64, elf.big_endian, has_addend) {
+            if pointer_kind(elf.machine, reloc.kind) != Some(PointerKind::Absolute) {
+                continue;
+            }
+            if let Some(symbol) = elf.symbol(table, reloc.symbol) {
+                if symbol.value == 0 && !symbol.name.is_empty() {
+                    slots.insert(reloc.offset, symbol.name);
+                }
+            }
+        }
+    }
+    slots
+}
+
+/// Finds the slots of an ELF or PE binary the loader fills with the address of an imported function, keyed by their
+/// address, with the name of the extern symbol Ghidra gives the import. Nothing for other formats.
+pub fn import_slots(bytes: &[u8]) -> BTreeMap<u64, String> {
+    if let Some(elf) = ElfFile::parse(bytes) {
+        elf_import_slots(&elf)
+    } else if let Some(pe) = PeFile::parse(bytes) {
+        pe.imports()
+            .iter()
+            .filter_map(|import| Some((import.address, import_symbol_name(import)?)))
+            .collect()
+    } else {
+        BTreeMap::new()
+    }
+}
+
+fn collect_constants(expr: &Expression, constants: &mut BTreeSet<u64>) {
+    match expr {
+        Expression::Const(bv) => {
+            if let Ok(value) = bv.try_to_u64() {
+                constants.insert(value);
+            }
+        }
+        Expression::BinOp { lhs, rhs, .. } => {
+            collect_constants(lhs, constants);
+            collect_constants(rhs, constants);
+        }
+        Expression::UnOp { arg, .. }
+        | Expression::Cast { arg, .. }
+        | Expression::Subpiece { arg, .. } => collect_constants(arg, constants),
+        _ => (),
+    }
+}
+
+/// Finds the constants used by the defs of each subprocedure, which are the addresses of the globals it references.
+fn referenced_addresses(proj: &Project) -> BTreeMap<Tid, BTreeSet<u64>> {
+    proj.program
+        .term
+        .subs
+        .values()
+        .map(|sub| {
+            let mut constants = BTreeSet::new();
+            for def in sub.term.blocks.iter().flat_map(|blk| blk.term.defs.iter()) {
+                match &def.term {
+                    Def::Load { address, .. } => collect_constants(address, &mut constants),
+                    Def::Store { address, value } => {
+                        collect_constants(address, &mut constants);
+                        collect_constants(value, &mut constants);
+                    }
+                    Def::Assign { value, .. } => collect_constants(value, &mut constants),
+                }
+            }
+            (sub.tid.clone(), constants)
+        })
+        .collect()
+}
+
+/// Creates the constraints seeding the relocated words of global variables as pointers, keyed by the subprocedures
+/// referencing the globals. A word pointing to the start of a global is the pointer to that global, other words
+/// point to something, which is bounded by `bottom` so it does not constrain its type.
+pub fn relocation_constraints(
+    proj: &Project,
+    relocations: &[PointerRelocation],
+    bottom: &TypeVariable,
+) -> BTreeMap<Tid, ConstraintSet> {
+    let globals = &proj.program.term.global_variables;
+    let mut by_global: BTreeMap<u64, ConstraintSet> = BTreeMap::new();
+    for reloc in relocations.iter() {
+        let (start, global) = match globals.range(..=reloc.address).next_back() {
+            Some((start, global)) if reloc.address - start < MAX_GLOBAL_OFFSET => (*start, global),
+            _ => continue,
+        };
+
+        let mut word = DerivedTypeVar::new(TypeVariable::new_global(
+            global.tid.get_str_repr().to_owned(),
+        ));
+        word.add_field_label(FieldLabel::Load);
+        word.add_field_label(FieldLabel::Field(Field::new(
+            (reloc.address - start) as i64,
+            usize::from(reloc.size) * 8,
+        )));
+
+        let constraint = match reloc.target.and_then(|target| globals.get(&target)) {
+            Some(target) => SubtypeConstraint::new(
+                DerivedTypeVar::new(TypeVariable::new_global(
+                    target.tid.get_str_repr().to_owned(),
+                )),
+                word,
+            ),
+            None => {
+                word.add_field_label(FieldLabel::Load);
+                SubtypeConstraint::new(DerivedTypeVar::new(bottom.clone()), word)
+            }
+        };
+        by_global
+            .entry(start)
+            .or_insert_with(ConstraintSet::default)
+            .insert(TyConstraint::SubTy(constraint));
+    }
+
+    referenced_addresses(proj)
+        .into_iter()
+        .filter_map(|(sub, constants)| {
+            let mut cons = ConstraintSet::default();
+            for addr in constants.iter() {
+                if let Some(seeds) = by_global.get(addr) {
+                    cons.insert_all(seeds);
+                }
+            }
+            (!cons.is_empty()).then(|| {
+                let origin = ConstraintOrigin::new(sub.clone(), ConstraintReason::Relocation);
+                (sub, cons.with_origin(&origin))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_base_relocations, parse_elf_relocations, pointer_kind, PointerKind};
+
+    #[test]
+    fn relocation_tables_are_parsed() {
+        #[rustfmt::skip]
+        let rela: Vec<u8> = vec![
+            // R_X86_64_RELATIVE at 0x4010 to 0x2000
+            0x10, 0x40, 0, 0, 0, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0x00, 0x20, 0, 0, 0, 0, 0, 0,
+            // R_X86_64_GLOB_DAT at 0x3ff8 against symbol 2
+            0xf8, 0x3f, 0, 0, 0, 0, 0, 0, 6, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let relocs = parse_elf_relocations(&rela, true, false, true);
+        assert_eq!(relocs.len(), 2);
+        assert_eq!(
+            (relocs[0].offset, relocs[0].kind, relocs[0].addend),
+            (0x4010, 8, Some(0x2000))
+        );
+        assert_eq!((relocs[1].kind, relocs[1].symbol), (6, 2));
+        assert_eq!(pointer_kind(62, 8), Some(PointerKind::Relative));
+        assert_eq!(pointer_kind(62, 6), Some(PointerKind::Absolute));
+        // R_X86_64_PC32 is not a pointer
+        assert_eq!(pointer_kind(62, 2), None);
+
+        #[rustfmt::skip]
+        let base_relocs: Vec<u8> = vec![
+            // page 0x3000, block of 12 bytes: DIR64 at 0x3008, padding
+            0x00, 0x30, 0, 0, 12, 0, 0, 0, 0x08, 0xa0, 0x00, 0x00,
+        ];
+        assert_eq!(
+            parse_base_relocations(&base_relocs),
+            vec![(0x3008, 10), (0x3000, 0)]
+        );
+    }
+}
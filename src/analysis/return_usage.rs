@@ -0,0 +1,253 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cwe_checker_lib::intermediate_representation::{
+    Arg, Blk, Def, Expression, Jmp, Project, Term, Tid,
+};
+
+use crate::{analysis::mips::read_registers, constraint_generation::argument_registers};
+
+/// How the callsites of a subprocedure use its return registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReturnUsage {
+    /// The number of callsites calling the subprocedure
+    pub callsites: usize,
+    /// The number of callsites that read a return register after the call returns
+    pub reads: usize,
+}
+
+fn return_registers(rets: &[Arg], cpu_architecture: &str) -> BTreeSet<String> {
+    rets.iter()
+        .flat_map(|arg| match arg {
+            Arg::Register { expr, .. } => argument_registers(expr, cpu_architecture)
+                .into_iter()
+                .map(|var| var.name.clone())
+                .collect(),
+            Arg::Stack { .. } => Vec::new(),
+        })
+        .collect()
+}
+
+fn reads_any(expr: &Expression, live: &BTreeSet<String>) -> bool {
+    let mut registers = Vec::new();
+    read_registers(expr, &mut registers);
+    registers.iter().any(|var| live.contains(&var.name))
+}
+
+/// Checks if a register of live is read on some path from the start of a block before it is redefined. A return with a
+/// live register forwards it to the caller of the caller so it counts as a read, while another call clobbers the return
+/// registers and ends the path.
+fn read_before_redefined(
+    blocks: &BTreeMap<&Tid, &Term<Blk>>,
+    start: &Tid,
+    live: BTreeSet<String>,
+) -> bool {
+    let mut visited = BTreeSet::new();
+    let mut worklist = vec![(start.clone(), live)];
+    while let Some((tid, mut live)) = worklist.pop() {
+        if live.is_empty() || !visited.insert((tid.clone(), live.clone())) {
+            continue;
+        }
+        let blk = match blocks.get(&tid) {
+            Some(blk) => blk,
+            None => continue,
+        };
+
+        for def in blk.term.defs.iter() {
+            let (defined, reads) = match &def.term {
+                Def::Assign { var, value } => (Some(var), reads_any(value, &live)),
+                Def::Load { var, address } => (Some(var), reads_any(address, &live)),
+                Def::Store { address, value } => {
+                    (None, reads_any(address, &live) || reads_any(value, &live))
+                }
+            };
+            if reads {
+                return true;
+            }
+            if let Some(var) = defined {
+                live.remove(&var.name);
+            }
+        }
+        if live.is_empty() {
+            continue;
+        }
+
+        for jmp in blk.term.jmps.iter() {
+            match &jmp.term {
+                Jmp::Branch(target) => worklist.push((target.clone(), live.clone())),
+                Jmp::CBranch { target, condition } => {
+                    if reads_any(condition, &live) {
+                        return true;
+                    }
+                    worklist.push((target.clone(), live.clone()));
+                }
+                Jmp::BranchInd(target) => {
+                    if reads_any(target, &live) {
+                        return true;
+                    }
+                    worklist.extend(
+                        blk.term
+                            .indirect_jmp_targets
+                            .iter()
+                            .map(|target| (target.clone(), live.clone())),
+                    );
+                }
+                Jmp::CallInd { target, .. } => {
+                    if reads_any(target, &live) {
+                        return true;
+                    }
+                }
+                Jmp::Return(_) => return true,
+                Jmp::Call { .. } => (),
+                Jmp::CallOther { return_, .. } => {
+                    worklist.extend(return_.iter().map(|target| (target.clone(), live.clone())))
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Counts the callsites of each subprocedure with return registers, and how many of them read a return register
+/// after the call before redefining it.
+pub fn count_return_uses(proj: &Project) -> BTreeMap<Tid, ReturnUsage> {
+    let subs = &proj.program.term.subs;
+    let mut usage: BTreeMap<Tid, ReturnUsage> = BTreeMap::new();
+    for caller in subs.values() {
+        let blocks = caller
+            .term
+            .blocks
+            .iter()
+            .map(|blk| (&blk.tid, blk))
+            .collect::<BTreeMap<_, _>>();
+        let caller_returns = !caller.term.formal_rets.is_empty();
+        for blk in caller.term.blocks.iter() {
+            for jmp in blk.term.jmps.iter() {
+                let (target, return_) = match &jmp.term {
+                    Jmp::Call { target, return_ } => (target, return_),
+                    _ => continue,
+                };
+                let registers = match subs.get(target) {
+                    Some(callee) => {
+                        return_registers(&callee.term.formal_rets, &proj.cpu_architecture)
+                    }
+                    None => continue,
+                };
+                if registers.is_empty() {
+                    continue;
+                }
+
+                let reads = match return_ {
+                    Some(return_) => read_before_redefined(&blocks, return_, registers),
+                    // A tail call returns whatever the callee returns
+                    None => caller_returns,
+                };
+                let entry = usage.entry(target.clone()).or_default();
+                entry.callsites += 1;
+                entry.reads += usize::from(reads);
+            }
+        }
+    }
+    usage
+}
+
+/// Removes the formal returns of subprocedures read after fewer than threshold of their callsites, ie. the value left
+/// in the return register by an internal call, so they are typed as returning void. Subprocedures without callsites
+/// keep their returns since there is no evidence against them. Returns the subprocedures whose returns were removed.
+pub fn suppress_unused_returns(proj: &mut Project, threshold: usize) -> Vec<Tid> {
+    let usage = count_return_uses(proj);
+    let suppressed = usage
+        .into_iter()
+        .filter(|(_, usage)| usage.reads < threshold)
+        .map(|(tid, _)| tid)
+        .collect::<Vec<_>>();
+    for tid in suppressed.iter() {
+        if let Some(sub) = proj.program.term.subs.get_mut(tid) {
+            sub.term.formal_rets.clear();
+        }
+    }
+    suppressed
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use cwe_checker_lib::intermediate_representation::{
+        Bitvector, Blk, ByteSize, Def, Expression, Jmp, Term, Tid, Variable,
+    };
+
+    use super::read_before_redefined;
+
+    fn register(name: &str) -> Variable {
+        Variable {
+            name: name.to_owned(),
+            size: ByteSize::new(8),
+            is_temp: false,
+        }
+    }
+
+    fn block(address: &str, defs: Vec<(&str, Expression)>, jmp: Jmp) -> Term<Blk> {
+        Term {
+            tid: Tid::create(format!("blk_{}", address), address.to_owned()),
+            term: Blk {
+                defs: defs
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, (var, value))| Term {
+                        tid: Tid::create(format!("instr_{}_{}", address, idx), address.to_owned()),
+                        term: Def::Assign {
+                            var: register(var),
+                            value,
+                        },
+                    })
+                    .collect(),
+                jmps: vec![Term {
+                    tid: Tid::create(format!("instr_{}_jmp", address), address.to_owned()),
+                    term: jmp,
+                }],
+                indirect_jmp_targets: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn return_is_read_only_when_used_before_redefinition() {
+        let var = |name: &str| Expression::Var(register(name));
+        let zero = || Expression::Const(Bitvector::from_i64(0));
+        let callee = Tid::create("sub_callee".to_owned(), "00402000".to_owned());
+        let live = BTreeSet::from(["RAX".to_owned()]);
+
+        // mov rdi, rax; call callee
+        let used = block(
+            "00401000",
+            vec![("RDI", var("RAX"))],
+            Jmp::Call {
+                target: callee.clone(),
+                return_: None,
+            },
+        );
+        // mov rax, 0; ret
+        let overwritten = block("00401010", vec![("RAX", zero())], Jmp::Return(var("RAX")));
+        // mov rdi, 0; call callee
+        let clobbered = block(
+            "00401020",
+            vec![("RDI", zero())],
+            Jmp::Call {
+                target: callee,
+                return_: None,
+            },
+        );
+        let blocks = [&used, &overwritten, &clobbered]
+            .iter()
+            .map(|blk| (&blk.tid, *blk))
+            .collect::<BTreeMap<_, _>>();
+
+        assert!(read_before_redefined(&blocks, &used.tid, live.clone()));
+        assert!(!read_before_redefined(
+            &blocks,
+            &overwritten.tid,
+            live.clone()
+        ));
+        assert!(!read_before_redefined(&blocks, &clobbered.tid, live));
+    }
+}
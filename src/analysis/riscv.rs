@@ -0,0 +1,245 @@
+use std::collections::BTreeSet;
+
+use cwe_checker_lib::intermediate_representation::{
+    Arg, Blk, Def, Expression, Jmp, Project, Term, Variable,
+};
+
+use crate::analysis::mips::read_registers;
+
+/// The registers holding the first words of the arguments.
+pub const PARAMETER_REGISTERS: &[&str] = &["a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7"];
+
+/// The registers holding the return value, a value of two words is returned in the pair `a0`:`a1`.
+pub const RETURN_REGISTERS: &[&str] = &["a0", "a1"];
+
+/// The registers preserved across calls. `gp` and `tp` are fixed for the whole program and thread.
+pub const CALLEE_SAVED_REGISTERS: &[&str] = &[
+    "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "sp", "gp", "tp",
+];
+
+/// Checks whether the architecture of a project is RISC-V. Instructions of the compressed extension are two bytes
+/// long, so tids of RISC-V code are only two byte aligned and no instruction stride may be assumed from them.
+pub fn is_riscv(cpu_architecture: &str) -> bool {
+    cpu_architecture.to_ascii_lowercase().contains("riscv")
+}
+
+fn register(proj: &Project, name: &str) -> Variable {
+    proj.register_set
+        .iter()
+        .find(|reg| reg.name == name)
+        .cloned()
+        .unwrap_or_else(|| Variable {
+            name: name.to_owned(),
+            size: proj.stack_pointer_register.size,
+            is_temp: false,
+        })
+}
+
+fn register_arg(var: Variable) -> Arg {
+    Arg::Register {
+        expr: Expression::Var(var),
+        data_type: None,
+    }
+}
+
+fn defined_register(def: &Def) -> Option<&Variable> {
+    match def {
+        Def::Assign { var, .. } | Def::Load { var, .. } => Some(var),
+        Def::Store { .. } => None,
+    }
+}
+
+/// The number of argument registers a block reads before defining them, up to the last one read since arguments
+/// are assigned to registers in order.
+fn read_parameter_count(blk: &Term<Blk>, parameters: &[&str]) -> usize {
+    let mut defined = BTreeSet::new();
+    let mut live_in = BTreeSet::new();
+    let mut read = |expr: &Expression, defined: &BTreeSet<String>| {
+        let mut registers = Vec::new();
+        read_registers(expr, &mut registers);
+        for var in registers {
+            if !defined.contains(&var.name) {
+                live_in.insert(var.name.clone());
+            }
+        }
+    };
+
+    for def in blk.term.defs.iter() {
+        match &def.term {
+            Def::Assign { value, .. } => read(value, &defined),
+            Def::Load { address, .. } => read(address, &defined),
+            Def::Store { address, value } => {
+                read(address, &defined);
+                read(value, &defined);
+            }
+        }
+        if let Some(var) = defined_register(&def.term) {
+            defined.insert(var.name.clone());
+        }
+    }
+    for jmp in blk.term.jmps.iter() {
+        match &jmp.term {
+            Jmp::CBranch { condition, .. } => read(condition, &defined),
+            Jmp::BranchInd(target) | Jmp::CallInd { target, .. } => read(target, &defined),
+            _ => (),
+        }
+    }
+
+    parameters
+        .iter()
+        .rposition(|reg| live_in.contains(*reg))
+        .map(|idx| idx + 1)
+        .unwrap_or(0)
+}
+
+/// The number of return registers every returning block of a subprocedure defines, in order.
+fn defined_return_count(blocks: &[Term<Blk>], returns: &[&str]) -> usize {
+    let returning = blocks
+        .iter()
+        .filter(|blk| {
+            blk.term
+                .jmps
+                .iter()
+                .any(|jmp| matches!(jmp.term, Jmp::Return(_)))
+        })
+        .collect::<Vec<_>>();
+    if returning.is_empty() {
+        return 0;
+    }
+
+    returns
+        .iter()
+        .take_while(|reg| {
+            returning.iter().all(|blk| {
+                blk.term
+                    .defs
+                    .iter()
+                    .filter_map(|def| defined_register(&def.term))
+                    .any(|var| var.name == **reg)
+            })
+        })
+        .count()
+}
+
+/// Models the arguments and returns of the subprocedures Ghidra exported without a calling convention for an ABI
+/// passing arguments and returns in the given registers. The arguments are the argument registers the entry block
+/// reads before defining them, the returns the return registers every returning block defines. Returns the number of
+/// subprocedures given formal arguments or returns.
+pub(crate) fn model_register_formals(
+    proj: &mut Project,
+    parameter_registers: &[&str],
+    return_registers: &[&str],
+) -> usize {
+    let parameters = parameter_registers
+        .iter()
+        .map(|name| register(proj, name))
+        .collect::<Vec<_>>();
+    let returns = return_registers
+        .iter()
+        .map(|name| register(proj, name))
+        .collect::<Vec<_>>();
+
+    let mut modeled = 0;
+    for sub in proj.program.term.subs.values_mut() {
+        if sub.term.calling_convention.is_some() {
+            continue;
+        }
+
+        let mut changed = false;
+        if sub.term.formal_args.is_empty() {
+            let count = sub
+                .term
+                .blocks
+                .first()
+                .map_or(0, |blk| read_parameter_count(blk, parameter_registers));
+            sub.term.formal_args = parameters[..count]
+                .iter()
+                .cloned()
+                .map(register_arg)
+                .collect();
+            changed |= count > 0;
+        }
+        if sub.term.formal_rets.is_empty() {
+            let count = defined_return_count(&sub.term.blocks, return_registers);
+            sub.term.formal_rets = returns[..count].iter().cloned().map(register_arg).collect();
+            changed |= count > 0;
+        }
+        if changed {
+            modeled += 1;
+        }
+    }
+    modeled
+}
+
+/// Models the arguments and returns of the subprocedures of a RISC-V project Ghidra exported without a calling
+/// convention: the arguments in `a0`-`a7` the entry block reads, and the return `a0`, or the pair `a0`:`a1` for a two
+/// word value. Returns the number of subprocedures given formal arguments or returns.
+pub fn model_calling_convention(proj: &mut Project) -> usize {
+    if !is_riscv(&proj.cpu_architecture) {
+        return 0;
+    }
+    model_register_formals(proj, PARAMETER_REGISTERS, RETURN_REGISTERS)
+}
+
+#[cfg(test)]
+mod test {
+    use cwe_checker_lib::intermediate_representation::{
+        BinOpType, Bitvector, Blk, ByteSize, Def, Expression, Jmp, Term, Tid, Variable,
+    };
+
+    use super::{
+        defined_return_count, is_riscv, read_parameter_count, PARAMETER_REGISTERS, RETURN_REGISTERS,
+    };
+    use crate::analysis::arm32::code_address;
+
+    fn register(name: &str) -> Variable {
+        Variable {
+            name: name.to_owned(),
+            size: ByteSize::new(8),
+            is_temp: false,
+        }
+    }
+
+    fn assign(address: &str, var: &str, value: Expression) -> Term<Def> {
+        Term {
+            tid: Tid::create(format!("instr_{}_0", address), address.to_owned()),
+            term: Def::Assign {
+                var: register(var),
+                value,
+            },
+        }
+    }
+
+    #[test]
+    fn argument_and_return_registers_of_compressed_code() {
+        assert!(is_riscv("RISCV:LE:64:RV64GC"));
+        assert!(!is_riscv("ARM:LE:32:v8T"));
+        // The entry of a function following a compressed instruction is only two byte aligned
+        assert_eq!(code_address("RISCV:LE:64:RV64GC", 0x1_0a2e), 0x1_0a2e);
+
+        let add = |lhs: &str, rhs: &str| Expression::BinOp {
+            op: BinOpType::IntAdd,
+            lhs: Box::new(Expression::Var(register(lhs))),
+            rhs: Box::new(Expression::Var(register(rhs))),
+        };
+        // c.mv a5, a2; c.add a0, a1; c.li a1, 0; c.jr ra
+        let blk = Term {
+            tid: Tid::create("blk_00010a2e".to_owned(), "00010a2e".to_owned()),
+            term: Blk {
+                defs: vec![
+                    assign("00010a2e", "a5", Expression::Var(register("a2"))),
+                    assign("00010a30", "a0", add("a0", "a1")),
+                    assign("00010a32", "a1", Expression::Const(Bitvector::from_i64(0))),
+                ],
+                jmps: vec![Term {
+                    tid: Tid::create("instr_00010a34_0".to_owned(), "00010a34".to_owned()),
+                    term: Jmp::Return(Expression::Var(register("ra"))),
+                }],
+                indirect_jmp_targets: Vec::new(),
+            },
+        };
+
+        assert_eq!(read_parameter_count(&blk, PARAMETER_REGISTERS), 3);
+        assert_eq!(defined_return_count(&[blk], RETURN_REGISTERS), 2);
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::BTreeMap;
+
+use cwe_checker_lib::intermediate_representation::Project;
+use petgraph::{graph::NodeIndex, visit::EdgeRef, EdgeDirection};
+
+use crate::{
+    analysis::{
+        allocators::AllocatorSpec,
+        platform::{BundledSignature, PlatformProfile, SignatureSlot, TypedefKind},
+    },
+    constraint_generation::tid_to_tvar,
+    constraints::{DerivedTypeVar, FieldLabel},
+    solver::type_sketch::SketchGraph,
+};
+
+const RUST_TYPEDEFS: &[(&str, TypedefKind)] = &[("usize", TypedefKind::Integral)];
+
+const RUST_SIGNATURES: &[BundledSignature] = &[
+    (
+        "__rust_alloc",
+        &[
+            (SignatureSlot::Parameter(0), "usize"),
+            (SignatureSlot::Parameter(1), "usize"),
+        ],
+    ),
+    (
+        "__rust_alloc_zeroed",
+        &[
+            (SignatureSlot::Parameter(0), "usize"),
+            (SignatureSlot::Parameter(1), "usize"),
+        ],
+    ),
+    (
+        "__rust_dealloc",
+        &[
+            (SignatureSlot::Parameter(1), "usize"),
+            (SignatureSlot::Parameter(2), "usize"),
+        ],
+    ),
+    (
+        "__rust_realloc",
+        &[
+            (SignatureSlot::Parameter(1), "usize"),
+            (SignatureSlot::Parameter(2), "usize"),
+            (SignatureSlot::Parameter(3), "usize"),
+        ],
+    ),
+    (
+        "alloc::alloc::exchange_malloc",
+        &[
+            (SignatureSlot::Parameter(0), "usize"),
+            (SignatureSlot::Parameter(1), "usize"),
+        ],
+    ),
+    (
+        "alloc::alloc::handle_alloc_error",
+        &[
+            (SignatureSlot::Parameter(0), "usize"),
+            (SignatureSlot::Parameter(1), "usize"),
+        ],
+    ),
+    (
+        "core::panicking::panic",
+        &[(SignatureSlot::Parameter(1), "usize")],
+    ),
+    (
+        "core::panicking::panic_bounds_check",
+        &[
+            (SignatureSlot::Parameter(0), "usize"),
+            (SignatureSlot::Parameter(1), "usize"),
+        ],
+    ),
+    (
+        "core::slice::index::slice_start_index_len_fail",
+        &[
+            (SignatureSlot::Parameter(0), "usize"),
+            (SignatureSlot::Parameter(1), "usize"),
+        ],
+    ),
+    (
+        "core::slice::index::slice_end_index_len_fail",
+        &[
+            (SignatureSlot::Parameter(0), "usize"),
+            (SignatureSlot::Parameter(1), "usize"),
+        ],
+    ),
+    (
+        "core::slice::index::slice_index_order_fail",
+        &[
+            (SignatureSlot::Parameter(0), "usize"),
+            (SignatureSlot::Parameter(1), "usize"),
+        ],
+    ),
+    (
+        "core::option::expect_failed",
+        &[(SignatureSlot::Parameter(1), "usize")],
+    ),
+    (
+        "core::result::unwrap_failed",
+        &[(SignatureSlot::Parameter(1), "usize")],
+    ),
+    (
+        "core::fmt::Formatter::write_str",
+        &[(SignatureSlot::Parameter(2), "usize")],
+    ),
+    (
+        "core::fmt::Formatter::pad",
+        &[(SignatureSlot::Parameter(2), "usize")],
+    ),
+];
+
+/// The allocation functions of the Rust standard library: the global allocator shims (`__rust_*`), the default
+/// allocator (`__rdl_*`) and a `#[global_allocator]` (`__rg_*`), and the `Box` allocation path.
+pub fn rust_allocators() -> Vec<AllocatorSpec> {
+    let mut allocators = Vec::new();
+    for prefix in ["__rust", "__rdl", "__rg"].iter() {
+        allocators.push(AllocatorSpec::new(&format!("{}_alloc", prefix), vec![0]));
+        allocators.push(AllocatorSpec::new(
+            &format!("{}_alloc_zeroed", prefix),
+            vec![0],
+        ));
+        allocators.push(AllocatorSpec::new(&format!("{}_realloc", prefix), vec![3]));
+    }
+    allocators.push(AllocatorSpec::new("alloc::alloc::exchange_malloc", vec![0]));
+    allocators
+}
+
+/// The profile typing the sizes and lengths taken by functions of core and alloc. Its `usize` typedef needs to be
+/// part of the lattice, like the typedefs of [PlatformProfile::for_platform].
+pub fn rust_profile() -> PlatformProfile {
+    PlatformProfile::from_bundled(RUST_TYPEDEFS, &[RUST_SIGNATURES])
+}
+
+fn decode_escape(escape: &str) -> Option<String> {
+    let decoded = match escape {
+        "SP" => "@",
+        "BP" => "*",
+        "RF" => "&",
+        "LT" => "<",
+        "GT" => ">",
+        "LP" => "(",
+        "RP" => ")",
+        "C" => ",",
+        _ => {
+            let code = u32::from_str_radix(escape.strip_prefix('u')?, 16).ok()?;
+            return std::char::from_u32(code).map(String::from);
+        }
+    };
+    Some(decoded.to_owned())
+}
+
+fn decode_component(component: &str) -> Option<String> {
+    // A component starting with an escape is prefixed with an underscore to make it an identifier
+    let mut rest = if component.starts_with("_$") {
+        &component[1..]
+    } else {
+        component
+    };
+
+    let mut decoded = String::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('$') {
+            let end = after.find('$')?;
+            decoded.push_str(&decode_escape(&after[..end])?);
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix("..") {
+            decoded.push_str("::");
+            rest = after;
+        } else {
+            let c = rest.chars().next()?;
+            decoded.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    Some(decoded)
+}
+
+fn is_hash(component: &str) -> bool {
+    component.len() == 17
+        && component.starts_with('h')
+        && component[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Demangles a symbol in the legacy Rust mangling, ie. `_ZN4core9panicking5panic17h0123456789abcdefE` to
+/// `core::panicking::panic`, dropping the hash. C++ symbols share the `_ZN` prefix but have no hash component and
+/// are left alone, as are symbols in the v0 mangling. The extra leading underscore of macOS symbols is accepted.
+pub fn demangle(symbol: &str) -> Option<String> {
+    let mut rest = symbol
+        .strip_prefix("__ZN")
+        .or_else(|| symbol.strip_prefix("_ZN"))?;
+
+    let mut components = Vec::new();
+    while !rest.starts_with('E') {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let len = rest[..digits].parse::<usize>().ok()?;
+        let end = digits.checked_add(len)?;
+        let component = rest.get(digits..end)?;
+        components.push(component);
+        rest = &rest[end..];
+    }
+
+    // Anything after the terminator, ie. the parameter types of a C++ function, means it is not a Rust symbol
+    if rest != "E" || !components.last().map_or(false, |last| is_hash(last)) {
+        return None;
+    }
+
+    components.pop();
+    let path = components
+        .into_iter()
+        .map(decode_component)
+        .collect::<Option<Vec<_>>>()?;
+    (!path.is_empty()).then(|| path.join("::"))
+}
+
+/// Checks whether a (demangled) symbol names a Rust item rather than a C function.
+pub fn is_rust_path(name: &str) -> bool {
+    name.contains("::")
+}
+
+/// Renames the subprocedures and extern symbols with a legacy Rust mangled name to their demangled path. Returns the
+/// number of renamed symbols.
+pub fn demangle_symbols(proj: &mut Project) -> usize {
+    let mut renamed = 0;
+    for sub in proj.program.term.subs.values_mut() {
+        if let Some(name) = demangle(&sub.term.name) {
+            sub.term.name = name;
+            renamed += 1;
+        }
+    }
+    for ext in proj.program.term.extern_symbols.values_mut() {
+        if let Some(name) = demangle(&ext.name) {
+            ext.name = name;
+            renamed += 1;
+        }
+    }
+    renamed
+}
+
+/// A slice or string reference passed as two adjacent parameters, the pointer to its data followed by its length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FatPointer {
+    /// The index of the pointer parameter, the length is the next parameter
+    pub pointer_index: usize,
+    /// The size of the pointer and of the length in bits
+    pub word_bits: usize,
+}
+
+fn is_pointer<U: std::cmp::PartialEq>(grph: &SketchGraph<U>, nd: NodeIndex) -> bool {
+    grph.get_graph()
+        .get_graph()
+        .edges_directed(nd, EdgeDirection::Outgoing)
+        .any(|e| matches!(e.weight(), FieldLabel::Load | FieldLabel::Store))
+}
+
+fn is_terminal<U: std::cmp::PartialEq>(grph: &SketchGraph<U>, nd: NodeIndex) -> bool {
+    grph.get_graph()
+        .get_graph()
+        .edges_directed(nd, EdgeDirection::Outgoing)
+        .next()
+        .is_none()
+}
+
+/// Finds the fat pointers among the parameters of the Rust functions of a project, keyed by the node of the function.
+/// A parameter that is loaded from or stored through and is followed by a parameter that is only a value, ie. never
+/// dereferenced, is taken to be a fat pointer. Parameters are paired from left to right so a parameter belongs to at
+/// most one fat pointer. Functions whose name is not a Rust path are skipped, C functions like `memcpy` take
+/// a pointer and a length that are unrelated arguments.
+pub fn fat_pointer_parameters<U: std::cmp::PartialEq>(
+    proj: &Project,
+    grph: &SketchGraph<U>,
+) -> BTreeMap<NodeIndex, Vec<FatPointer>> {
+    let word_bits = proj.stack_pointer_register.size.as_bit_length();
+    let subs = proj
+        .program
+        .term
+        .subs
+        .values()
+        .map(|sub| (&sub.tid, sub.term.name.as_str()));
+    let externs = proj
+        .program
+        .term
+        .extern_symbols
+        .values()
+        .map(|ext| (&ext.tid, ext.name.as_str()));
+
+    subs.chain(externs)
+        .filter(|(_, name)| is_rust_path(name))
+        .filter_map(|(tid, _)| {
+            grph.get_node_index_for_variable(&DerivedTypeVar::new(tid_to_tvar(tid)))
+        })
+        .filter_map(|nd| {
+            let params: BTreeMap<usize, NodeIndex> = grph
+                .get_graph()
+                .get_graph()
+                .edges_directed(nd, EdgeDirection::Outgoing)
+                .filter_map(|e| match e.weight() {
+                    FieldLabel::In(idx) => Some((*idx, e.target())),
+                    _ => None,
+                })
+                .collect();
+
+            let mut fat_pointers = Vec::new();
+            let last_idx = params.keys().next_back().copied().unwrap_or(0);
+            let mut idx = 0;
+            while idx < last_idx {
+                match (params.get(&idx), params.get(&(idx + 1))) {
+                    (Some(ptr), Some(len)) if is_pointer(grph, *ptr) && is_terminal(grph, *len) => {
+                        fat_pointers.push(FatPointer {
+                            pointer_index: idx,
+                            word_bits,
+                        });
+                        idx += 2;
+                    }
+                    _ => idx += 1,
+                }
+            }
+
+            (!fat_pointers.is_empty()).then(|| (nd, fat_pointers))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{demangle, rust_allocators, rust_profile};
+
+    #[test]
+    fn legacy_symbols_are_demangled() {
+        assert_eq!(
+            demangle("_ZN4core9panicking18panic_bounds_check17h1a2b3c4d5e6f7a8bE").as_deref(),
+            Some("core::panicking::panic_bounds_check")
+        );
+        assert_eq!(
+            demangle("_ZN60_$LT$alloc..string..String$u20$as$u20$core..fmt..Display$GT$3fmt17h0123456789abcdefE")
+                .as_deref(),
+            Some("<alloc::string::String as core::fmt::Display>::fmt")
+        );
+        // C++ symbols have no hash
+        assert_eq!(demangle("_ZN3foo3barEv"), None);
+        assert_eq!(demangle("_ZN3foo3barE"), None);
+        assert_eq!(demangle("_ZN18446744073709551615fooE"), None);
+        assert_eq!(demangle("main"), None);
+
+        assert!(rust_allocators()
+            .iter()
+            .any(|alloc| alloc.symbol == "__rdl_realloc" && alloc.size_params == vec![3]));
+        assert!(rust_profile()
+            .signatures
+            .contains_key("core::fmt::Formatter::write_str"));
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cwe_checker_lib::intermediate_representation::{
+    BinOpType, ByteSize, Def, Expression, Sub, Term, Variable,
+};
+
+use super::{object_file::ElfFile, tls::TlsModel};
+
+/// The offsets from the thread pointer the guard is read at: `fs:0x28` on x86-64 and `gs:0x14` on x86.
+const TLS_GUARD_OFFSETS: &[i64] = &[0x28, 0x14];
+
+/// The symbol of the global guard on targets that do not keep it in thread-local storage.
+const GLOBAL_GUARD_SYMBOL: &str = "__stack_chk_guard";
+
+/// Where the guard value a stack protector copies into the frame of a function is read from: the thread-local guard
+/// (`fs:0x28` on x86-64), the `__stack_chk_guard` global, or on Windows a global cookie that is xored with the stack
+/// pointer before it is stored.
+#[derive(Debug, Clone)]
+pub struct StackGuard {
+    tls: Option<TlsModel>,
+    globals: BTreeSet<u64>,
+}
+
+impl StackGuard {
+    /// Creates the guard model of an architecture, finding the global guard among the symbols of the binary.
+    pub fn new(cpu_architecture: &str, binary: &[u8]) -> StackGuard {
+        StackGuard {
+            tls: TlsModel::for_architecture(cpu_architecture),
+            globals: ElfFile::parse(binary)
+                .and_then(|elf| elf.symbol_value(GLOBAL_GUARD_SYMBOL))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    fn is_guard_load(&self, address: &Expression) -> bool {
+        let tls_guard = self
+            .tls
+            .as_ref()
+            .and_then(|tls| tls.access(address, ByteSize::new(8)))
+            .and_then(|access| access.offset)
+            .map_or(false, |offset| TLS_GUARD_OFFSETS.contains(&offset));
+        tls_guard || constant(address).map_or(false, |addr| self.globals.contains(&(addr as u64)))
+    }
+
+    /// Finds the offset from the stack pointer on entry of the slot the entry block of sub stores the guard to. The
+    /// stack pointer and the registers copied from it are followed through constant additions, so the slot is found
+    /// whether it is addressed relative to the stack or the frame pointer.
+    pub fn canary_offset(&self, sub: &Term<Sub>, stack_pointer: &Variable) -> Option<i64> {
+        let entry = sub.term.blocks.first()?;
+        let mut stack_offsets = BTreeMap::from([(stack_pointer.name.clone(), 0)]);
+        let mut guards = BTreeSet::new();
+        let mut cookies = BTreeSet::new();
+        for def in entry.term.defs.iter() {
+            match &def.term {
+                Def::Load { var, address } => {
+                    stack_offsets.remove(&var.name);
+                    guards.remove(&var.name);
+                    cookies.remove(&var.name);
+                    if self.is_guard_load(address) {
+                        guards.insert(var.name.clone());
+                    } else if constant(address).is_some() {
+                        cookies.insert(var.name.clone());
+                    }
+                }
+                Def::Assign { var, value } => {
+                    let offset = stack_offset(&stack_offsets, value);
+                    let is_guard = match value {
+                        Expression::Var(src) => guards.contains(&src.name),
+                        // the security cookie is xored with the stack pointer so it differs per frame
+                        Expression::BinOp {
+                            op: BinOpType::IntXOr,
+                            lhs,
+                            rhs,
+                        } => [(lhs, rhs), (rhs, lhs)].iter().any(|(cookie, sp)| {
+                            matches!(&***cookie, Expression::Var(v) if cookies.contains(&v.name))
+                                && stack_offset(&stack_offsets, sp).is_some()
+                        }),
+                        _ => false,
+                    };
+                    stack_offsets.remove(&var.name);
+                    guards.remove(&var.name);
+                    cookies.remove(&var.name);
+                    if let Some(offset) = offset {
+                        stack_offsets.insert(var.name.clone(), offset);
+                    }
+                    if is_guard {
+                        guards.insert(var.name.clone());
+                    }
+                }
+                Def::Store { address, value } => match value {
+                    Expression::Var(src) if guards.contains(&src.name) => {
+                        return stack_offset(&stack_offsets, address)
+                    }
+                    _ => (),
+                },
+            }
+        }
+        None
+    }
+}
+
+fn constant(expr: &Expression) -> Option<i64> {
+    match expr {
+        Expression::Const(bv) => bv.try_to_i64().ok(),
+        _ => None,
+    }
+}
+
+// The offset from the stack pointer on entry of an expression adding a constant to a register holding a stack address.
+fn stack_offset(stack_offsets: &BTreeMap<String, i64>, expr: &Expression) -> Option<i64> {
+    match expr {
+        Expression::Var(var) => stack_offsets.get(&var.name).copied(),
+        Expression::BinOp {
+            op: BinOpType::IntAdd,
+            lhs,
+            rhs,
+        } => match (stack_offset(stack_offsets, lhs), constant(rhs)) {
+            (Some(base), Some(off)) => base.checked_add(off),
+            _ => stack_offset(stack_offsets, rhs)?.checked_add(constant(lhs)?),
+        },
+        Expression::BinOp {
+            op: BinOpType::IntSub,
+            lhs,
+            rhs,
+        } => stack_offset(stack_offsets, lhs)?.checked_sub(constant(rhs)?),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cwe_checker_lib::intermediate_representation::{
+        BinOpType, Bitvector, Blk, ByteSize, Def, Expression, Sub, Term, Tid, Variable,
+    };
+
+    use super::StackGuard;
+
+    fn var(name: &str) -> Variable {
+        Variable {
+            name: name.to_owned(),
+            size: ByteSize::new(8),
+            is_temp: false,
+        }
+    }
+
+    fn binop(op: BinOpType, lhs: Expression, rhs: Expression) -> Expression {
+        Expression::BinOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    fn constant(value: i64) -> Expression {
+        Expression::Const(Bitvector::from_i64(value))
+    }
+
+    fn sub(defs: Vec<Def>) -> Term<Sub> {
+        let defs = defs
+            .into_iter()
+            .enumerate()
+            .map(|(idx, def)| Term {
+                tid: Tid::create(format!("instr_00401000_{}", idx), "00401000".to_owned()),
+                term: def,
+            })
+            .collect();
+        Term {
+            tid: Tid::create("sub_00401000".to_owned(), "00401000".to_owned()),
+            term: Sub {
+                name: "protected".to_owned(),
+                blocks: vec![Term {
+                    tid: Tid::create("blk_00401000".to_owned(), "00401000".to_owned()),
+                    term: Blk {
+                        defs,
+                        jmps: Vec::new(),
+                        indirect_jmp_targets: Vec::new(),
+                    },
+                }],
+                formal_args: Vec::new(),
+                formal_rets: Vec::new(),
+                calling_convention: None,
+            },
+        }
+    }
+
+    #[test]
+    fn canaries_are_found_by_the_store_of_the_guard() {
+        let guard = StackGuard::new("x86_64", &[]);
+        // push rbp; mov rbp, rsp; sub rsp, 0x20; mov rax, fs:[0x28]; mov [rbp - 8], rax; mov [rbp - 0x10], rdi
+        let linux = sub(vec![
+            Def::Assign {
+                var: var("RSP"),
+                value: binop(BinOpType::IntSub, Expression::Var(var("RSP")), constant(8)),
+            },
+            Def::Store {
+                address: Expression::Var(var("RSP")),
+                value: Expression::Var(var("RBP")),
+            },
+            Def::Assign {
+                var: var("RBP"),
+                value: Expression::Var(var("RSP")),
+            },
+            Def::Assign {
+                var: var("RSP"),
+                value: binop(
+                    BinOpType::IntSub,
+                    Expression::Var(var("RSP")),
+                    constant(0x20),
+                ),
+            },
+            Def::Load {
+                var: var("RAX"),
+                address: binop(
+                    BinOpType::IntAdd,
+                    Expression::Var(var("FS_OFFSET")),
+                    constant(0x28),
+                ),
+            },
+            Def::Store {
+                address: binop(BinOpType::IntAdd, Expression::Var(var("RBP")), constant(-8)),
+                value: Expression::Var(var("RAX")),
+            },
+            Def::Store {
+                address: binop(
+                    BinOpType::IntAdd,
+                    Expression::Var(var("RBP")),
+                    constant(-0x10),
+                ),
+                value: Expression::Var(var("RDI")),
+            },
+        ]);
+        assert_eq!(guard.canary_offset(&linux, &var("RSP")), Some(-0x10));
+
+        // mov rax, [__security_cookie]; xor rax, rsp; mov [rsp + 0x18], rax
+        let windows = sub(vec![
+            Def::Assign {
+                var: var("RSP"),
+                value: binop(
+                    BinOpType::IntSub,
+                    Expression::Var(var("RSP")),
+                    constant(0x28),
+                ),
+            },
+            Def::Load {
+                var: var("RAX"),
+                address: constant(0x140003000),
+            },
+            Def::Assign {
+                var: var("RAX"),
+                value: binop(
+                    BinOpType::IntXOr,
+                    Expression::Var(var("RAX")),
+                    Expression::Var(var("RSP")),
+                ),
+            },
+            Def::Store {
+                address: binop(
+                    BinOpType::IntAdd,
+                    Expression::Var(var("RSP")),
+                    constant(0x18),
+                ),
+                value: Expression::Var(var("RAX")),
+            },
+        ]);
+        assert_eq!(guard.canary_offset(&windows, &var("RSP")), Some(-0x10));
+
+        // a global loaded without being xored with the stack pointer is not the cookie
+        let unprotected = sub(vec![
+            Def::Load {
+                var: var("RAX"),
+                address: constant(0x140003000),
+            },
+            Def::Store {
+                address: binop(BinOpType::IntAdd, Expression::Var(var("RSP")), constant(-8)),
+                value: Expression::Var(var("RAX")),
+            },
+        ]);
+        assert_eq!(guard.canary_offset(&unprotected, &var("RSP")), None);
+    }
+}
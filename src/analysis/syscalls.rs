@@ -0,0 +1,560 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cwe_checker_lib::intermediate_representation::{
+    Arg, ByteSize, Def, Expression, ExternSymbol, Jmp, Project, Term, Tid, Variable,
+};
+
+use crate::{
+    analysis::{
+        name_heuristics::constraints_by_injecting_sub,
+        platform::{BundledSignature, PlatformProfile, SignatureSlot, TypedefKind, POSIX_TYPEDEFS},
+    },
+    constraints::ConstraintSet,
+};
+
+/// The prefix of the tids of the extern symbols syscalls are stubbed with.
+pub const SYSCALL_STUB_PREFIX: &str = "syscall_";
+
+/// The registers holding the syscall number, arguments, and return value of an architecture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyscallConvention {
+    /// The register holding the syscall number
+    pub number: &'static str,
+    /// The registers holding the arguments, in order
+    pub parameters: &'static [&'static str],
+    /// The register holding the return value
+    pub ret: &'static str,
+    /// The syscall numbers of the architecture
+    pub table: &'static [(u64, &'static str)],
+}
+
+const X86_64_SYSCALLS: &[(u64, &str)] = &[
+    (0, "read"),
+    (1, "write"),
+    (2, "open"),
+    (3, "close"),
+    (4, "stat"),
+    (5, "fstat"),
+    (8, "lseek"),
+    (9, "mmap"),
+    (11, "munmap"),
+    (12, "brk"),
+    (16, "ioctl"),
+    (22, "pipe"),
+    (32, "dup"),
+    (33, "dup2"),
+    (39, "getpid"),
+    (41, "socket"),
+    (42, "connect"),
+    (43, "accept"),
+    (44, "sendto"),
+    (45, "recvfrom"),
+    (57, "fork"),
+    (59, "execve"),
+    (60, "exit"),
+    (61, "wait4"),
+    (62, "kill"),
+    (72, "fcntl"),
+    (74, "fsync"),
+    (110, "getppid"),
+    (201, "time"),
+    (231, "exit_group"),
+    (257, "openat"),
+];
+
+const X86_SYSCALLS: &[(u64, &str)] = &[
+    (1, "exit"),
+    (2, "fork"),
+    (3, "read"),
+    (4, "write"),
+    (5, "open"),
+    (6, "close"),
+    (7, "waitpid"),
+    (8, "creat"),
+    (11, "execve"),
+    (13, "time"),
+    (19, "lseek"),
+    (20, "getpid"),
+    (37, "kill"),
+    (41, "dup"),
+    (42, "pipe"),
+    (45, "brk"),
+    (54, "ioctl"),
+    (55, "fcntl"),
+    (63, "dup2"),
+    (64, "getppid"),
+    (91, "munmap"),
+    (108, "fstat"),
+    (118, "fsync"),
+    (252, "exit_group"),
+    (295, "openat"),
+];
+
+const ARM_SYSCALLS: &[(u64, &str)] = &[
+    (1, "exit"),
+    (2, "fork"),
+    (3, "read"),
+    (4, "write"),
+    (5, "open"),
+    (6, "close"),
+    (8, "creat"),
+    (11, "execve"),
+    (19, "lseek"),
+    (20, "getpid"),
+    (37, "kill"),
+    (41, "dup"),
+    (42, "pipe"),
+    (45, "brk"),
+    (54, "ioctl"),
+    (55, "fcntl"),
+    (63, "dup2"),
+    (64, "getppid"),
+    (91, "munmap"),
+    (108, "fstat"),
+    (118, "fsync"),
+    (248, "exit_group"),
+    (281, "socket"),
+    (283, "connect"),
+    (285, "accept"),
+    (322, "openat"),
+];
+
+const AARCH64_SYSCALLS: &[(u64, &str)] = &[
+    (23, "dup"),
+    (25, "fcntl"),
+    (29, "ioctl"),
+    (56, "openat"),
+    (57, "close"),
+    (62, "lseek"),
+    (63, "read"),
+    (64, "write"),
+    (80, "fstat"),
+    (82, "fsync"),
+    (93, "exit"),
+    (94, "exit_group"),
+    (129, "kill"),
+    (172, "getpid"),
+    (173, "getppid"),
+    (198, "socket"),
+    (202, "accept"),
+    (203, "connect"),
+    (214, "brk"),
+    (215, "munmap"),
+    (221, "execve"),
+    (222, "mmap"),
+    (260, "wait4"),
+];
+
+// The number of arguments of each syscall.
+const SYSCALL_ARITY: &[(&str, usize)] = &[
+    ("accept", 3),
+    ("brk", 1),
+    ("close", 1),
+    ("connect", 3),
+    ("creat", 2),
+    ("dup", 1),
+    ("dup2", 2),
+    ("execve", 3),
+    ("exit", 1),
+    ("exit_group", 1),
+    ("fcntl", 3),
+    ("fork", 0),
+    ("fstat", 2),
+    ("fsync", 1),
+    ("getpid", 0),
+    ("getppid", 0),
+    ("ioctl", 3),
+    ("kill", 2),
+    ("lseek", 3),
+    ("mmap", 6),
+    ("munmap", 2),
+    ("open", 3),
+    ("openat", 4),
+    ("pipe", 1),
+    ("read", 3),
+    ("recvfrom", 6),
+    ("sendto", 6),
+    ("socket", 3),
+    ("stat", 2),
+    ("time", 1),
+    ("wait4", 4),
+    ("waitpid", 3),
+    ("write", 3),
+];
+
+const NO_RETURN_SYSCALLS: &[&str] = &["exit", "exit_group"];
+
+const SYSCALL_TYPEDEFS: &[(&str, TypedefKind)] = &[("size_t", TypedefKind::Integral)];
+
+const SYSCALL_SIGNATURES: &[BundledSignature] = &[
+    (
+        "read",
+        &[
+            (SignatureSlot::Return, "ssize_t"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+            (SignatureSlot::Parameter(2), "size_t"),
+        ],
+    ),
+    (
+        "write",
+        &[
+            (SignatureSlot::Return, "ssize_t"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+            (SignatureSlot::Parameter(2), "size_t"),
+        ],
+    ),
+    ("open", &[(SignatureSlot::Return, "file_descriptor")]),
+    ("creat", &[(SignatureSlot::Return, "file_descriptor")]),
+    (
+        "openat",
+        &[
+            (SignatureSlot::Return, "file_descriptor"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+        ],
+    ),
+    ("close", &[(SignatureSlot::Parameter(0), "file_descriptor")]),
+    ("fstat", &[(SignatureSlot::Parameter(0), "file_descriptor")]),
+    ("fsync", &[(SignatureSlot::Parameter(0), "file_descriptor")]),
+    ("ioctl", &[(SignatureSlot::Parameter(0), "file_descriptor")]),
+    ("fcntl", &[(SignatureSlot::Parameter(0), "file_descriptor")]),
+    (
+        "lseek",
+        &[
+            (SignatureSlot::Return, "off_t"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+            (SignatureSlot::Parameter(1), "off_t"),
+        ],
+    ),
+    (
+        "mmap",
+        &[
+            (SignatureSlot::Parameter(1), "size_t"),
+            (SignatureSlot::Parameter(4), "file_descriptor"),
+            (SignatureSlot::Parameter(5), "off_t"),
+        ],
+    ),
+    ("munmap", &[(SignatureSlot::Parameter(1), "size_t")]),
+    (
+        "dup",
+        &[
+            (SignatureSlot::Return, "file_descriptor"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+        ],
+    ),
+    (
+        "dup2",
+        &[
+            (SignatureSlot::Return, "file_descriptor"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+            (SignatureSlot::Parameter(1), "file_descriptor"),
+        ],
+    ),
+    ("socket", &[(SignatureSlot::Return, "file_descriptor")]),
+    (
+        "connect",
+        &[(SignatureSlot::Parameter(0), "file_descriptor")],
+    ),
+    (
+        "accept",
+        &[
+            (SignatureSlot::Return, "file_descriptor"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+        ],
+    ),
+    (
+        "sendto",
+        &[
+            (SignatureSlot::Return, "ssize_t"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+            (SignatureSlot::Parameter(2), "size_t"),
+        ],
+    ),
+    (
+        "recvfrom",
+        &[
+            (SignatureSlot::Return, "ssize_t"),
+            (SignatureSlot::Parameter(0), "file_descriptor"),
+            (SignatureSlot::Parameter(2), "size_t"),
+        ],
+    ),
+    ("getpid", &[(SignatureSlot::Return, "pid_t")]),
+    ("getppid", &[(SignatureSlot::Return, "pid_t")]),
+    ("fork", &[(SignatureSlot::Return, "pid_t")]),
+    ("kill", &[(SignatureSlot::Parameter(0), "pid_t")]),
+    (
+        "wait4",
+        &[
+            (SignatureSlot::Return, "pid_t"),
+            (SignatureSlot::Parameter(0), "pid_t"),
+        ],
+    ),
+    (
+        "waitpid",
+        &[
+            (SignatureSlot::Return, "pid_t"),
+            (SignatureSlot::Parameter(0), "pid_t"),
+        ],
+    ),
+    (
+        "time",
+        &[
+            (SignatureSlot::Return, "time_t"),
+            (SignatureSlot::ParameterPointee(0), "time_t"),
+        ],
+    ),
+];
+
+/// The syscall convention of the architecture of a project, if it is a supported Linux architecture.
+pub fn syscall_convention(cpu_architecture: &str) -> Option<SyscallConvention> {
+    let arch = cpu_architecture.to_ascii_lowercase();
+    if arch.contains("x86_64") || arch.contains("x86-64") || arch.contains("amd64") {
+        Some(SyscallConvention {
+            number: "RAX",
+            parameters: &["RDI", "RSI", "RDX", "R10", "R8", "R9"],
+            ret: "RAX",
+            table: X86_64_SYSCALLS,
+        })
+    } else if arch.contains("x86") || arch.contains("i386") {
+        Some(SyscallConvention {
+            number: "EAX",
+            parameters: &["EBX", "ECX", "EDX", "ESI", "EDI", "EBP"],
+            ret: "EAX",
+            table: X86_SYSCALLS,
+        })
+    } else if arch.contains("aarch64") || arch.contains("arm64") || arch.contains("arm_64") {
+        Some(SyscallConvention {
+            number: "x8",
+            parameters: &["x0", "x1", "x2", "x3", "x4", "x5"],
+            ret: "x0",
+            table: AARCH64_SYSCALLS,
+        })
+    } else if arch.contains("riscv") {
+        // RISC-V uses the generic syscall numbers of AArch64
+        Some(SyscallConvention {
+            number: "a7",
+            parameters: &["a0", "a1", "a2", "a3", "a4", "a5"],
+            ret: "a0",
+            table: AARCH64_SYSCALLS,
+        })
+    } else if arch.contains("arm") {
+        Some(SyscallConvention {
+            number: "r7",
+            parameters: &["r0", "r1", "r2", "r3", "r4", "r5"],
+            ret: "r0",
+            table: ARM_SYSCALLS,
+        })
+    } else {
+        None
+    }
+}
+
+/// Checks whether a user defined pcode operation is a syscall instruction.
+pub fn is_syscall_instruction(description: &str) -> bool {
+    let description = description.to_ascii_lowercase();
+    [
+        "syscall",
+        "swi",
+        "software_interrupt",
+        "callsupervisor",
+        "svc",
+        "ecall",
+    ]
+    .iter()
+    .any(|op| description.contains(op))
+}
+
+/// Decodes the number of a syscall following the defs of a block from the last definition of the number register, if
+/// it assigns a constant.
+pub fn syscall_number(defs: &[Term<Def>], convention: &SyscallConvention) -> Option<u64> {
+    defs.iter()
+        .rev()
+        .find(|df| match &df.term {
+            Def::Assign { var, .. } | Def::Load { var, .. } => {
+                var.name.eq_ignore_ascii_case(convention.number)
+            }
+            _ => false,
+        })
+        .and_then(|df| match &df.term {
+            Def::Assign {
+                value: Expression::Const(bv),
+                ..
+            } => bv.try_to_u64().ok(),
+            _ => None,
+        })
+}
+
+/// The tid of the extern symbol stubbing a syscall.
+pub fn syscall_stub_tid(name: &str) -> Tid {
+    Tid::create(
+        format!("{}{}", SYSCALL_STUB_PREFIX, name),
+        "UNKNOWN".to_owned(),
+    )
+}
+
+/// Checks whether a tid is the tid of a syscall stub.
+pub fn is_syscall_stub(tid: &Tid) -> bool {
+    tid.get_str_repr().starts_with(SYSCALL_STUB_PREFIX)
+}
+
+fn syscall_stub(
+    name: &str,
+    convention: &SyscallConvention,
+    register_size: ByteSize,
+) -> ExternSymbol {
+    let register = |name: &str| Arg::Register {
+        expr: Expression::Var(Variable {
+            name: name.to_owned(),
+            size: register_size,
+            is_temp: false,
+        }),
+        data_type: None,
+    };
+    let arity = SYSCALL_ARITY
+        .iter()
+        .find(|(syscall, _)| *syscall == name)
+        .map(|(_, arity)| *arity)
+        .unwrap_or(convention.parameters.len());
+
+    let tid = syscall_stub_tid(name);
+    ExternSymbol {
+        addresses: vec![tid.address.clone()],
+        tid,
+        name: name.to_owned(),
+        calling_convention: None,
+        parameters: convention.parameters[..arity]
+            .iter()
+            .map(|reg| register(reg))
+            .collect(),
+        return_values: vec![register(convention.ret)],
+        no_return: NO_RETURN_SYSCALLS.contains(&name),
+        has_var_args: false,
+    }
+}
+
+/// Replaces the syscall instructions with a constant syscall number by calls to an extern symbol named after the
+/// syscall, so the arguments and return of the syscall get the type variables of the parameters of the stub. Returns
+/// the number of replaced syscall instructions, syscalls of unsupported architectures or with an unknown number are
+/// left as is.
+pub fn stub_syscalls(proj: &mut Project) -> usize {
+    let convention = match syscall_convention(&proj.cpu_architecture) {
+        Some(convention) => convention,
+        None => return 0,
+    };
+    let register_size = proj.stack_pointer_register.size;
+    let by_number = convention
+        .table
+        .iter()
+        .copied()
+        .collect::<BTreeMap<u64, &str>>();
+
+    let mut used = BTreeSet::new();
+    let mut replaced = 0;
+    for sub in proj.program.term.subs.values_mut() {
+        for blk in sub.term.blocks.iter_mut() {
+            let name =
+                match syscall_number(&blk.term.defs, &convention).and_then(|n| by_number.get(&n)) {
+                    Some(name) => *name,
+                    None => continue,
+                };
+
+            for jmp in blk.term.jmps.iter_mut() {
+                if let Jmp::CallOther {
+                    description,
+                    return_,
+                } = &jmp.term
+                {
+                    if is_syscall_instruction(description) {
+                        jmp.term = Jmp::Call {
+                            target: syscall_stub_tid(name),
+                            return_: return_.clone(),
+                        };
+                        used.insert(name);
+                        replaced += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    for name in used {
+        let stub = syscall_stub(name, &convention, register_size);
+        proj.program
+            .term
+            .extern_symbols
+            .insert(stub.tid.clone(), stub);
+    }
+    replaced
+}
+
+/// The profile typing the arguments and returns of syscalls. Its typedefs need to be part of the lattice, like the
+/// typedefs of [PlatformProfile::for_platform].
+pub fn syscall_profile() -> PlatformProfile {
+    let typedefs = POSIX_TYPEDEFS
+        .iter()
+        .chain(SYSCALL_TYPEDEFS.iter())
+        .copied()
+        .collect::<Vec<_>>();
+    PlatformProfile::from_bundled(&typedefs, &[SYSCALL_SIGNATURES])
+}
+
+/// Creates the constraints typing the syscall stubs, keyed by the subprocedures making the syscalls. Extern symbols
+/// that are not syscall stubs are not typed, even if they share the name of a syscall.
+pub fn syscall_constraints(
+    proj: &Project,
+    is_element: impl Fn(&str) -> bool,
+) -> BTreeMap<Tid, ConstraintSet> {
+    let profile = syscall_profile();
+    constraints_by_injecting_sub(proj, |callable, name| {
+        if is_syscall_stub(callable) {
+            profile.signature_constraints(callable, name, &is_element)
+        } else {
+            ConstraintSet::default()
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use cwe_checker_lib::intermediate_representation::{
+        Bitvector, ByteSize, Def, Expression, Term, Tid, Variable,
+    };
+
+    use super::{is_syscall_instruction, syscall_convention, syscall_number};
+
+    #[test]
+    fn decodes_constant_syscall_numbers() {
+        let convention = syscall_convention("x86_64").unwrap();
+        let assign = |name: &str, value: Expression| Term {
+            tid: Tid::create(format!("def_{}", name), "1000".to_owned()),
+            term: Def::Assign {
+                var: Variable {
+                    name: name.to_owned(),
+                    size: ByteSize::new(8),
+                    is_temp: false,
+                },
+                value,
+            },
+        };
+        let write = vec![
+            assign("RAX", Expression::Const(Bitvector::from_u64(3))),
+            assign("RDI", Expression::Const(Bitvector::from_u64(1))),
+            assign("RAX", Expression::Const(Bitvector::from_u64(1))),
+        ];
+        assert_eq!(syscall_number(&write, &convention), Some(1));
+
+        let unknown = vec![assign(
+            "RAX",
+            Expression::Var(Variable {
+                name: "RBX".to_owned(),
+                size: ByteSize::new(8),
+                is_temp: false,
+            }),
+        )];
+        assert_eq!(syscall_number(&unknown, &convention), None);
+
+        assert_eq!(syscall_convention("ARM_32").unwrap().number, "r7");
+        assert!(syscall_convention("MIPS_32").is_none());
+        assert!(is_syscall_instruction("syscall"));
+        assert!(!is_syscall_instruction("LOCK"));
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cwe_checker_lib::intermediate_representation::{Blk, Def, Expression, Jmp, Project, Term, Tid};
+
+/// The address of the slot a register holding a jump target was loaded from in a block, if it is a constant.
+fn loaded_slot(blk: &Term<Blk>, target: &Expression) -> Option<u64> {
+    let var = match target {
+        Expression::Var(var) => var,
+        _ => return None,
+    };
+    let def = blk.term.defs.iter().rev().find(|def| match &def.term {
+        Def::Load { var: defined, .. } | Def::Assign { var: defined, .. } => defined == var,
+        Def::Store { .. } => false,
+    })?;
+    match &def.term {
+        Def::Load {
+            address: Expression::Const(bv),
+            ..
+        } => bv.try_to_u64().ok(),
+        _ => None,
+    }
+}
+
+/// The function a subprocedure forwards to if it is a thunk: a single block without stores that ends in a tail call,
+/// a branch to the entry of another subprocedure, or an indirect jump through an import slot, ie. a PLT stub or an
+/// import thunk.
+fn thunk_target(
+    blocks: &[Term<Blk>],
+    entries: &BTreeMap<Tid, Tid>,
+    imports: &BTreeMap<u64, Tid>,
+) -> Option<Tid> {
+    let blk = match blocks {
+        [blk] => blk,
+        _ => return None,
+    };
+    if blk
+        .term
+        .defs
+        .iter()
+        .any(|def| matches!(def.term, Def::Store { .. }))
+    {
+        return None;
+    }
+
+    match blk.term.jmps.as_slice() {
+        [jmp] => match &jmp.term {
+            Jmp::Call {
+                target,
+                return_: None,
+            } => Some(target.clone()),
+            Jmp::Branch(target) => entries.get(target).cloned(),
+            Jmp::BranchInd(target)
+            | Jmp::CallInd {
+                target,
+                return_: None,
+            } => imports.get(&loaded_slot(blk, target)?).cloned(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Follows a chain of thunks to the function it ends in, nothing if the chain is a cycle.
+fn final_target(thunk: &Tid, targets: &BTreeMap<Tid, Tid>) -> Option<Tid> {
+    let mut visited = BTreeSet::new();
+    let mut current = thunk;
+    while let Some(next) = targets.get(current) {
+        if !visited.insert(current.clone()) {
+            return None;
+        }
+        current = next;
+    }
+    Some(current.clone())
+}
+
+/// Collapses PLT stubs, import thunks, and tail call thunks into the functions they forward to. Calls to a thunk are
+/// retargeted to the end of its chain of thunks, so the constraints of callsites apply to the type variables of the
+/// extern symbol or subprocedure itself rather than being merged into a separate signature for each thunk.
+/// `import_slots` maps the addresses of the slots the loader fills with imported functions to the names of their
+/// extern symbols. Returns the number of retargeted calls.
+pub fn collapse_thunks(proj: &mut Project, import_slots: &BTreeMap<u64, String>) -> usize {
+    let program = &proj.program.term;
+    let entries: BTreeMap<Tid, Tid> = program
+        .subs
+        .values()
+        .filter_map(|sub| Some((sub.term.blocks.first()?.tid.clone(), sub.tid.clone())))
+        .collect();
+    let externs_by_name: BTreeMap<&str, &Tid> = program
+        .extern_symbols
+        .values()
+        .map(|sym| (sym.name.as_str(), &sym.tid))
+        .collect();
+    let imports: BTreeMap<u64, Tid> = import_slots
+        .iter()
+        .filter_map(|(address, name)| {
+            Some((*address, (*externs_by_name.get(name.as_str())?).clone()))
+        })
+        .collect();
+    let known =
+        |tid: &Tid| program.subs.contains_key(tid) || program.extern_symbols.contains_key(tid);
+
+    let targets: BTreeMap<Tid, Tid> = program
+        .subs
+        .values()
+        .filter_map(|sub| {
+            let target = thunk_target(&sub.term.blocks, &entries, &imports)?;
+            (target != sub.tid && known(&target)).then(|| (sub.tid.clone(), target))
+        })
+        .collect();
+    let retargets: BTreeMap<Tid, Tid> = targets
+        .keys()
+        .filter_map(|thunk| Some((thunk.clone(), final_target(thunk, &targets)?)))
+        .collect();
+
+    let mut retargeted = 0;
+    for sub in proj.program.term.subs.values_mut() {
+        for blk in sub.term.blocks.iter_mut() {
+            for jmp in blk.term.jmps.iter_mut() {
+                if let Jmp::Call { target, .. } = &mut jmp.term {
+                    if let Some(entry) = retargets.get(target) {
+                        *target = entry.clone();
+                        retargeted += 1;
+                    }
+                }
+            }
+        }
+    }
+    retargeted
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use cwe_checker_lib::intermediate_representation::{
+        Bitvector, Blk, ByteSize, Def, Expression, Jmp, Term, Tid, Variable,
+    };
+
+    use super::{final_target, thunk_target};
+
+    fn block(address: &str, defs: Vec<Term<Def>>, jmp: Jmp) -> Term<Blk> {
+        Term {
+            tid: Tid::create(format!("blk_{}", address), address.to_owned()),
+            term: Blk {
+                defs,
+                jmps: vec![Term {
+                    tid: Tid::create(format!("instr_{}_1", address), address.to_owned()),
+                    term: jmp,
+                }],
+                indirect_jmp_targets: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn plt_stubs_and_tail_call_thunks() {
+        let tmp = Variable {
+            name: "$U1".to_owned(),
+            size: ByteSize::new(8),
+            is_temp: true,
+        };
+        let malloc = Tid::create("malloc".to_owned(), "EXTERNAL".to_owned());
+        let imports = BTreeMap::from([(0x4018, malloc.clone())]);
+        let worker = Tid::create("FUN_00401200".to_owned(), "00401200".to_owned());
+        let entries = BTreeMap::from([(
+            Tid::create("blk_00401200".to_owned(), "00401200".to_owned()),
+            worker.clone(),
+        )]);
+
+        // jmp qword ptr [0x4018]
+        let plt = block(
+            "00401030",
+            vec![Term {
+                tid: Tid::create("instr_00401030_0".to_owned(), "00401030".to_owned()),
+                term: Def::Load {
+                    var: tmp.clone(),
+                    address: Expression::Const(Bitvector::from_u64(0x4018)),
+                },
+            }],
+            Jmp::BranchInd(Expression::Var(tmp.clone())),
+        );
+        assert_eq!(thunk_target(&[plt], &entries, &imports), Some(malloc));
+
+        // jmp FUN_00401200
+        let thunk = block(
+            "00401100",
+            Vec::new(),
+            Jmp::Branch(Tid::create(
+                "blk_00401200".to_owned(),
+                "00401200".to_owned(),
+            )),
+        );
+        assert_eq!(
+            thunk_target(&[thunk], &entries, &imports),
+            Some(worker.clone())
+        );
+
+        // An indirect jump through a register not loaded from an import slot is not a thunk
+        let dispatch = block("00401300", Vec::new(), Jmp::BranchInd(Expression::Var(tmp)));
+        assert_eq!(thunk_target(&[dispatch], &entries, &imports), None);
+
+        let first = Tid::create("FUN_00401100".to_owned(), "00401100".to_owned());
+        let second = Tid::create("FUN_00401110".to_owned(), "00401110".to_owned());
+        let chain = BTreeMap::from([
+            (first.clone(), second.clone()),
+            (second.clone(), worker.clone()),
+        ]);
+        assert_eq!(final_target(&first, &chain), Some(worker));
+        let cycle = BTreeMap::from([
+            (first.clone(), second.clone()),
+            (second.clone(), first.clone()),
+        ]);
+        assert_eq!(final_target(&first, &cycle), None);
+    }
+}
@@ -0,0 +1,144 @@
+use cwe_checker_lib::intermediate_representation::{BinOpType, ByteSize, Expression};
+
+use crate::{constraint_generation::TypeVariableAccess, constraints::TypeVariable};
+
+/// The name of the global type variable of the thread-local storage block. The block is lowered like any other global
+/// when the tid `glb_tls_block` is interesting.
+pub const TLS_BLOCK: &str = "glb_tls_block";
+
+/// The registers holding the thread pointer under the ABIs of an architecture. Loads and stores relative to a thread
+/// pointer access the thread-local storage block of the current thread, which the points-to analysis does not track.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsModel {
+    thread_pointers: Vec<String>,
+}
+
+impl TlsModel {
+    /// The thread pointers of an architecture as they are named by the lifter: the `fs` and `gs` segment bases on x86
+    /// (`fs` on Linux x86-64 and Windows x86, `gs` on Linux x86 and Windows x86-64), `tpidr_el0` on AArch64, `tp` on
+    /// RISC-V, and `r13` and `r2` under the 64 and 32 bit PowerPC ABIs. Architectures that read the thread pointer
+    /// through a coprocessor or a helper function, like 32 bit ARM and MIPS, have no model.
+    pub fn for_architecture(cpu_architecture: &str) -> Option<TlsModel> {
+        let arch = cpu_architecture.to_ascii_lowercase();
+        let thread_pointers: &[&str] = if arch.contains("x86") || arch.contains("amd64") {
+            &["FS_OFFSET", "GS_OFFSET"]
+        } else if arch.contains("aarch64") || arch.contains("arm64") || arch.contains("arm_64") {
+            &["tpidr_el0"]
+        } else if arch.contains("riscv") {
+            &["tp"]
+        } else if arch.contains("ppc64") || arch.contains("powerpc64") {
+            &["r13"]
+        } else if arch.contains("ppc") || arch.contains("powerpc") {
+            &["r2"]
+        } else {
+            return None;
+        };
+
+        Some(TlsModel {
+            thread_pointers: thread_pointers.iter().map(|reg| reg.to_string()).collect(),
+        })
+    }
+
+    /// The type variable of the thread-local storage block.
+    pub fn block(&self) -> TypeVariable {
+        TypeVariable::new_global(TLS_BLOCK.to_owned())
+    }
+
+    fn is_thread_pointer(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Var(var) => self
+                .thread_pointers
+                .iter()
+                .any(|reg| reg.eq_ignore_ascii_case(&var.name)),
+            _ => false,
+        }
+    }
+
+    /// Gets the access to the thread-local storage block made by a load or store of sz bytes at an address relative to
+    /// a thread pointer, ie. `FS_OFFSET + 0x28`. The offset of the access is unknown when it is not a constant, as for
+    /// the initial-exec model that loads the offset of a variable from the GOT.
+    pub fn access(&self, address: &Expression, sz: ByteSize) -> Option<TypeVariableAccess> {
+        let constant = |expr: &Expression| match expr {
+            Expression::Const(bv) => bv.try_to_i64().ok(),
+            _ => None,
+        };
+        let offset = match address {
+            _ if self.is_thread_pointer(address) => Some(0),
+            Expression::BinOp {
+                op: BinOpType::IntAdd,
+                lhs,
+                rhs,
+            } if self.is_thread_pointer(lhs) => constant(rhs),
+            Expression::BinOp {
+                op: BinOpType::IntAdd,
+                lhs,
+                rhs,
+            } if self.is_thread_pointer(rhs) => constant(lhs),
+            Expression::BinOp {
+                op: BinOpType::IntSub,
+                lhs,
+                rhs,
+            } if self.is_thread_pointer(lhs) => constant(rhs).and_then(i64::checked_neg),
+            _ => return None,
+        };
+
+        Some(TypeVariableAccess {
+            ty_var: self.block(),
+            sz,
+            offset,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cwe_checker_lib::intermediate_representation::{
+        BinOpType, Bitvector, ByteSize, Expression, Variable,
+    };
+
+    use super::TlsModel;
+
+    fn var(name: &str) -> Expression {
+        Expression::Var(Variable {
+            name: name.to_owned(),
+            size: ByteSize::new(8),
+            is_temp: false,
+        })
+    }
+
+    fn add(lhs: Expression, rhs: Expression) -> Expression {
+        Expression::BinOp {
+            op: BinOpType::IntAdd,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    #[test]
+    fn accesses_relative_to_the_thread_pointer_are_tls() {
+        let tls = TlsModel::for_architecture("x86_64").unwrap();
+        let offset = |address: &Expression| {
+            tls.access(address, ByteSize::new(4))
+                .map(|access| access.offset)
+        };
+
+        // local-exec: mov eax, fs:[-0x10]
+        let local_exec = add(
+            var("FS_OFFSET"),
+            Expression::Const(Bitvector::from_i64(-0x10)),
+        );
+        assert_eq!(offset(&local_exec), Some(Some(-0x10)));
+        // initial-exec: mov rax, [rip + errno@gottpoff]; mov eax, fs:[rax]
+        assert_eq!(offset(&add(var("FS_OFFSET"), var("RAX"))), Some(None));
+        assert_eq!(offset(&var("FS_OFFSET")), Some(Some(0)));
+        assert_eq!(offset(&add(var("RSP"), var("RAX"))), None);
+
+        let access = tls.access(&local_exec, ByteSize::new(4)).unwrap();
+        assert!(access.ty_var.is_global());
+        assert!(TlsModel::for_architecture("mips").is_none());
+        assert!(TlsModel::for_architecture("aarch64")
+            .unwrap()
+            .access(&var("tpidr_el0"), ByteSize::new(8))
+            .is_some());
+    }
+}
@@ -0,0 +1,635 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+
+use cwe_checker_lib::intermediate_representation::{Project, Tid};
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::object_file::{ElfFile, Reader};
+
+// Bounds the number of nested remember_state instructions so a malformed program cannot allocate unboundedly.
+const MAX_REMEMBERED_STATES: usize = 64;
+
+/// The stack frame of a function recovered from its frame description entry. Offsets are relative to the canonical
+/// frame address (CFA), the value of the stack pointer before the call that entered the function.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameLayout {
+    /// The address of the entry of the function
+    pub start: u64,
+    /// The address after the end of the function
+    pub end: u64,
+    /// The offset of the CFA from the stack pointer at the entry, ie. 8 on x86-64 where the call pushed the return
+    /// address
+    pub entry_cfa_offset: i64,
+    /// The largest offset of the CFA from the stack pointer in the function, the size of the frame
+    pub frame_size: i64,
+    /// The CFA offsets of the slots the return address and the callee-saved registers are saved in, keyed by DWARF
+    /// register number
+    pub saved_registers: BTreeMap<u16, i64>,
+    /// The address of the language specific data area of the function, which holds the call site table of C++
+    /// functions that catch exceptions or run destructors while unwinding
+    #[serde(default)]
+    pub lsda: Option<u64>,
+}
+
+impl FrameLayout {
+    /// Converts an offset from the stack pointer at the entry of the function to an offset from the CFA.
+    pub fn cfa_offset(&self, entry_sp_offset: i64) -> i64 {
+        entry_sp_offset - self.entry_cfa_offset
+    }
+
+    /// Checks if an access of size bytes at a CFA offset overlaps the slot of a saved register.
+    pub fn is_save_slot(&self, cfa_offset: i64, size: u64, word_size: u64) -> bool {
+        let size = i64::try_from(size).unwrap_or(i64::MAX);
+        let word_size = i64::try_from(word_size).unwrap_or(i64::MAX);
+        self.saved_registers.values().any(|slot| {
+            cfa_offset < slot.saturating_add(word_size) && *slot < cfa_offset.saturating_add(size)
+        })
+    }
+}
+
+/// The DWARF register number of the stack pointer of an architecture.
+pub fn stack_pointer_dwarf_register(cpu_architecture: &str) -> Option<u16> {
+    let arch = cpu_architecture.to_ascii_lowercase();
+    if arch.contains("x86_64") || arch.contains("x86-64") || arch.contains("amd64") {
+        Some(7)
+    } else if arch.contains("x86") || arch.contains("i386") {
+        Some(4)
+    } else if arch.contains("aarch64") || arch.contains("arm64") || arch.contains("arm_64") {
+        Some(31)
+    } else if arch.contains("arm") {
+        Some(13)
+    } else if arch.contains("mips") {
+        Some(29)
+    } else if arch.contains("riscv") {
+        Some(2)
+    } else if arch.contains("ppc") || arch.contains("powerpc") {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// The `.eh_frame` section of an ELF binary, or the `.gcc_except_table` section holding the language specific data
+/// areas its entries point to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EhFrameSection<'a> {
+    /// The contents of the section
+    pub data: &'a [u8],
+    /// The virtual address the section is loaded at
+    pub address: u64,
+    /// The size of an address in bytes
+    pub address_size: u8,
+    /// Whether the binary is big endian
+    pub big_endian: bool,
+}
+
+impl<'a> EhFrameSection<'a> {
+    /// Finds the `.eh_frame` section of an ELF binary from its section headers.
+    pub fn find(bytes: &'a [u8]) -> Option<EhFrameSection<'a>> {
+        Self::find_named(bytes, ".eh_frame")
+    }
+
+    /// Finds the `.gcc_except_table` section of an ELF binary from its section headers.
+    pub fn find_except_table(bytes: &'a [u8]) -> Option<EhFrameSection<'a>> {
+        Self::find_named(bytes, ".gcc_except_table")
+    }
+
+    fn find_named(bytes: &'a [u8], name: &str) -> Option<EhFrameSection<'a>> {
+        let elf = ElfFile::parse(bytes)?;
+        let section = elf.section(name)?;
+        Some(EhFrameSection {
+            data: elf.contents(section)?,
+            address: section.address,
+            address_size: elf.word_size(),
+            big_endian: elf.big_endian,
+        })
+    }
+
+    fn encoded_pointer(&self, rdr: &mut Reader, encoding: u8) -> Option<u64> {
+        let field_address = self.address.wrapping_add(rdr.pos as u64);
+        let value = match encoding & 0x0f {
+            0x00 => rdr.uint(usize::from(self.address_size))?,
+            0x01 => rdr.uleb()?,
+            0x02 => rdr.uint(2)?,
+            0x03 => rdr.uint(4)?,
+            0x04 => rdr.uint(8)?,
+            0x09 => rdr.sleb()? as u64,
+            0x0a => rdr.uint(2)? as i16 as u64,
+            0x0b => rdr.uint(4)? as i32 as u64,
+            0x0c => rdr.uint(8)?,
+            _ => return None,
+        };
+        Some(match encoding & 0x70 {
+            0x10 => field_address.wrapping_add(value),
+            _ => value,
+        })
+    }
+}
+
+#[derive(Clone)]
+struct Cie {
+    code_align: u64,
+    data_align: i64,
+    fde_encoding: u8,
+    lsda_encoding: Option<u8>,
+    has_augmentation_data: bool,
+    instructions: (usize, usize),
+}
+
+#[derive(Clone, PartialEq, Eq)]
+struct Rules {
+    cfa_register: Option<u16>,
+    cfa_offset: i64,
+    saved: BTreeMap<u16, i64>,
+}
+
+struct CfaProgram<'s> {
+    cie: &'s Cie,
+    sp: u16,
+    initial: Option<Rules>,
+    rules: Rules,
+    remembered: Vec<Rules>,
+    frame_size: i64,
+    // Every slot a register is saved in by the program, the restore before a return resets the rule but not the slot
+    saves: BTreeMap<u16, i64>,
+}
+
+impl<'s> CfaProgram<'s> {
+    fn offset(&mut self, reg: u64, factored: i64) -> Option<()> {
+        let reg = u16::try_from(reg).ok()?;
+        let off = factored.checked_mul(self.cie.data_align)?;
+        self.rules.saved.insert(reg, off);
+        self.saves.insert(reg, off);
+        Some(())
+    }
+
+    fn restore(&mut self, reg: u64) -> Option<()> {
+        let reg = u16::try_from(reg).ok()?;
+        match self.initial.as_ref().and_then(|init| init.saved.get(&reg)) {
+            Some(off) => self.rules.saved.insert(reg, *off),
+            None => self.rules.saved.remove(&reg),
+        };
+        Some(())
+    }
+
+    // Runs instructions until the end of the program or the first instruction that is not understood. Only rules
+    // whose CFA is the stack pointer plus an offset describe the frame, rules moving the CFA to a frame pointer keep
+    // the last stack pointer offset.
+    fn run(&mut self, section: &EhFrameSection, (start, end): (usize, usize)) {
+        let mut rdr = Reader::new(&section.data[..end], start, section.big_endian);
+        while rdr.pos < end {
+            if self.step(section, &mut rdr).is_none() {
+                return;
+            }
+            if self.rules.cfa_register == Some(self.sp) {
+                self.frame_size = self.frame_size.max(self.rules.cfa_offset);
+            }
+        }
+    }
+
+    fn step(&mut self, section: &EhFrameSection, rdr: &mut Reader) -> Option<()> {
+        let op = rdr.u8()?;
+        match (op & 0xc0, op & 0x3f) {
+            (0x40, _) => (),
+            (0x80, reg) => {
+                let off = rdr.uleb()?;
+                self.offset(u64::from(reg), i64::try_from(off).ok()?)?;
+            }
+            (0xc0, reg) => self.restore(u64::from(reg))?,
+            _ => match op {
+                0x00 => (),
+                0x01 => {
+                    section.encoded_pointer(rdr, self.cie.fde_encoding)?;
+                }
+                0x02 => {
+                    rdr.uint(1)?;
+                }
+                0x03 => {
+                    rdr.uint(2)?;
+                }
+                0x04 => {
+                    rdr.uint(4)?;
+                }
+                0x05 => {
+                    let reg = rdr.uleb()?;
+                    let off = rdr.uleb()?;
+                    self.offset(reg, i64::try_from(off).ok()?)?;
+                }
+                0x06 => self.restore(rdr.uleb()?)?,
+                0x07 | 0x08 => {
+                    let reg = u16::try_from(rdr.uleb()?).ok()?;
+                    self.rules.saved.remove(&reg);
+                }
+                0x09 => {
+                    let reg = u16::try_from(rdr.uleb()?).ok()?;
+                    rdr.uleb()?;
+                    self.rules.saved.remove(&reg);
+                }
+                0x0a => {
+                    if self.remembered.len() >= MAX_REMEMBERED_STATES {
+                        return None;
+                    }
+                    self.remembered.push(self.rules.clone());
+                }
+                0x0b => self.rules = self.remembered.pop()?,
+                0x0c => {
+                    self.rules.cfa_register = Some(u16::try_from(rdr.uleb()?).ok()?);
+                    self.rules.cfa_offset = i64::try_from(rdr.uleb()?).ok()?;
+                }
+                0x0d => self.rules.cfa_register = Some(u16::try_from(rdr.uleb()?).ok()?),
+                0x0e => self.rules.cfa_offset = i64::try_from(rdr.uleb()?).ok()?,
+                0x0f => {
+                    let len = usize::try_from(rdr.uleb()?).ok()?;
+                    rdr.bytes(len)?;
+                    self.rules.cfa_register = None;
+                }
+                0x10 | 0x16 => {
+                    let reg = u16::try_from(rdr.uleb()?).ok()?;
+                    let len = usize::try_from(rdr.uleb()?).ok()?;
+                    rdr.bytes(len)?;
+                    self.rules.saved.remove(&reg);
+                }
+                0x11 => {
+                    let reg = rdr.uleb()?;
+                    let off = rdr.sleb()?;
+                    self.offset(reg, off)?;
+                }
+                0x12 => {
+                    self.rules.cfa_register = Some(u16::try_from(rdr.uleb()?).ok()?);
+                    self.rules.cfa_offset = rdr.sleb()?.checked_mul(self.cie.data_align)?;
+                }
+                0x13 => self.rules.cfa_offset = rdr.sleb()?.checked_mul(self.cie.data_align)?,
+                0x14 | 0x15 => {
+                    let reg = u16::try_from(rdr.uleb()?).ok()?;
+                    if op == 0x14 {
+                        rdr.uleb()?;
+                    } else {
+                        rdr.sleb()?;
+                    }
+                    self.rules.saved.remove(&reg);
+                }
+                0x2e => {
+                    rdr.uleb()?;
+                }
+                0x2f => {
+                    let reg = rdr.uleb()?;
+                    let off = i64::try_from(rdr.uleb()?).ok()?;
+                    self.offset(reg, off.checked_neg()?)?;
+                }
+                _ => return None,
+            },
+        }
+        Some(())
+    }
+}
+
+fn parse_cie(section: &EhFrameSection, mut rdr: Reader, end: usize) -> Option<Cie> {
+    let version = rdr.u8()?;
+    let augmentation = rdr.cstr()?;
+    // Augmentations other than the ones gcc and clang emit have data we do not know how to skip
+    if augmentation.contains(&b'h') {
+        return None;
+    }
+    let code_align = rdr.uleb()?;
+    let data_align = rdr.sleb()?;
+    if version == 1 {
+        rdr.u8()?;
+    } else {
+        rdr.uleb()?;
+    }
+
+    let mut fde_encoding = 0;
+    let mut lsda_encoding = None;
+    let has_augmentation_data = augmentation.first() == Some(&b'z');
+    if has_augmentation_data {
+        let len = usize::try_from(rdr.uleb()?).ok()?;
+        let data_end = rdr.pos.checked_add(len)?;
+        for c in augmentation[1..].iter() {
+            match c {
+                b'R' => fde_encoding = rdr.u8()?,
+                b'P' => {
+                    let encoding = rdr.u8()?;
+                    section.encoded_pointer(&mut rdr, encoding)?;
+                }
+                b'L' => lsda_encoding = Some(rdr.u8()?),
+                _ => break,
+            }
+        }
+        rdr.pos = data_end;
+    }
+
+    Some(Cie {
+        code_align,
+        data_align,
+        fde_encoding,
+        lsda_encoding,
+        has_augmentation_data,
+        instructions: (rdr.pos, end),
+    })
+}
+
+/// Parses the frame description entries of an `.eh_frame` section into the frame layouts of their functions. Entries
+/// that cannot be parsed are skipped. The stack pointer is given as a DWARF register number, see
+/// [stack_pointer_dwarf_register].
+pub fn parse_eh_frame(section: &EhFrameSection, sp: u16) -> Vec<FrameLayout> {
+    let mut cies: BTreeMap<usize, Option<Cie>> = BTreeMap::new();
+    let mut layouts = Vec::new();
+    let mut rdr = Reader::new(section.data, 0, section.big_endian);
+
+    while rdr.pos < section.data.len() {
+        let entry_start = rdr.pos;
+        let (len, is_64) = match rdr.uint(4) {
+            Some(0xffff_ffff) => match rdr.uint(8) {
+                Some(len) => (len, true),
+                None => break,
+            },
+            Some(len) => (len, false),
+            None => break,
+        };
+        if len == 0 {
+            break;
+        }
+        let body = rdr.pos;
+        let end = match usize::try_from(len)
+            .ok()
+            .and_then(|len| body.checked_add(len))
+            .filter(|end| *end <= section.data.len())
+        {
+            Some(end) => end,
+            None => break,
+        };
+        rdr.pos = end;
+
+        let mut entry = Reader::new(&section.data[..end], body, section.big_endian);
+        let id = match entry.uint(if is_64 { 8 } else { 4 }) {
+            Some(id) => id,
+            None => continue,
+        };
+        if id == 0 {
+            cies.insert(entry_start, parse_cie(section, entry, end));
+            continue;
+        }
+
+        // The CIE pointer of an FDE is the distance back from the pointer to its CIE
+        let cie = usize::try_from(id)
+            .ok()
+            .and_then(|id| body.checked_sub(id))
+            .and_then(|cie_start| {
+                cies.entry(cie_start)
+                    .or_insert_with(|| {
+                        let mut cie_rdr = Reader::new(section.data, cie_start, section.big_endian);
+                        let cie_len = usize::try_from(cie_rdr.uint(4)?).ok()?;
+                        let cie_end = cie_rdr.pos.checked_add(cie_len)?;
+                        cie_rdr.uint(4)?;
+                        parse_cie(
+                            section,
+                            Reader::new(
+                                section.data.get(..cie_end)?,
+                                cie_rdr.pos,
+                                section.big_endian,
+                            ),
+                            cie_end,
+                        )
+                    })
+                    .clone()
+            });
+        if let Some(layout) = cie.and_then(|cie| parse_fde(section, &cie, entry, end, sp)) {
+            layouts.push(layout);
+        }
+    }
+
+    layouts.sort_by_key(|layout| layout.start);
+    layouts
+}
+
+fn parse_fde(
+    section: &EhFrameSection,
+    cie: &Cie,
+    mut rdr: Reader,
+    end: usize,
+    sp: u16,
+) -> Option<FrameLayout> {
+    let start = section.encoded_pointer(&mut rdr, cie.fde_encoding)?;
+    // The range is an unsigned length encoded like the start but never relative to it
+    let range = section.encoded_pointer(&mut rdr, cie.fde_encoding & 0x0f)?;
+    let mut lsda = None;
+    if cie.has_augmentation_data {
+        let len = usize::try_from(rdr.uleb()?).ok()?;
+        let mut data = rdr;
+        rdr.bytes(len)?;
+        // An omitted pointer is encoded as 0xff, and a null pointer means the function has no call site table
+        lsda = cie
+            .lsda_encoding
+            .filter(|encoding| *encoding != 0xff && len > 0)
+            .and_then(|encoding| section.encoded_pointer(&mut data, encoding))
+            .filter(|lsda| *lsda != 0);
+    }
+
+    let mut program = CfaProgram {
+        cie,
+        sp,
+        initial: None,
+        rules: Rules {
+            cfa_register: None,
+            cfa_offset: 0,
+            saved: BTreeMap::new(),
+        },
+        remembered: Vec::new(),
+        frame_size: 0,
+        saves: BTreeMap::new(),
+    };
+    program.run(section, cie.instructions);
+    if program.rules.cfa_register != Some(sp) {
+        return None;
+    }
+    let entry_cfa_offset = program.rules.cfa_offset;
+    program.initial = Some(program.rules.clone());
+    program.run(section, (rdr.pos, end));
+
+    Some(FrameLayout {
+        start,
+        end: start.checked_add(range)?,
+        entry_cfa_offset,
+        frame_size: program.frame_size,
+        saved_registers: program.saves,
+        lsda,
+    })
+}
+
+/// Parses the call site table of the language specific data area at lsda, returning the addresses of the landing pads
+/// of the call sites. Landing pads are relative to the start of the function unless the table gives another base.
+pub fn parse_landing_pads(
+    table: &EhFrameSection,
+    lsda: u64,
+    function_start: u64,
+) -> Option<Vec<u64>> {
+    let pos = usize::try_from(lsda.checked_sub(table.address)?).ok()?;
+    let mut rdr = Reader::new(table.data, pos, table.big_endian);
+    let lpstart_encoding = rdr.u8()?;
+    let lpstart = if lpstart_encoding == 0xff {
+        function_start
+    } else {
+        table.encoded_pointer(&mut rdr, lpstart_encoding)?
+    };
+    if rdr.u8()? != 0xff {
+        // the offset of the type table, which only matters to the personality routine
+        rdr.uleb()?;
+    }
+
+    let call_site_encoding = rdr.u8()?;
+    let len = usize::try_from(rdr.uleb()?).ok()?;
+    let end = rdr.pos.checked_add(len)?;
+    let mut landing_pads = Vec::new();
+    while rdr.pos < end {
+        // the start and length of the call site, the landing pad, and the first action
+        table.encoded_pointer(&mut rdr, call_site_encoding)?;
+        table.encoded_pointer(&mut rdr, call_site_encoding)?;
+        let landing_pad = table.encoded_pointer(&mut rdr, call_site_encoding)?;
+        rdr.uleb()?;
+        if landing_pad != 0 {
+            landing_pads.push(lpstart.wrapping_add(landing_pad));
+        }
+    }
+    Some(landing_pads)
+}
+
+/// Recovers the frame layouts of the subprocedures of a project from the `.eh_frame` section of the binary, keyed by
+/// the subprocedure whose entry starts a frame description entry.
+pub fn frame_layouts(proj: &Project, bytes: &[u8]) -> BTreeMap<Tid, FrameLayout> {
+    let (section, sp) = match (
+        EhFrameSection::find(bytes),
+        stack_pointer_dwarf_register(&proj.cpu_architecture),
+    ) {
+        (Some(section), Some(sp)) => (section, sp),
+        _ => return BTreeMap::new(),
+    };
+
+    let by_start = parse_eh_frame(&section, sp)
+        .into_iter()
+        .map(|layout| (layout.start, layout))
+        .collect::<BTreeMap<_, _>>();
+    proj.program
+        .term
+        .subs
+        .values()
+        .filter_map(|sub| {
+            let entry = u64::from_str_radix(&sub.tid.address, 16).ok()?;
+            by_start
+                .get(&entry)
+                .map(|layout| (sub.tid.clone(), layout.clone()))
+        })
+        .collect()
+}
+
+/// Finds the blocks of the project that are landing pads of a call site table in the `.gcc_except_table` section, keyed
+/// by the subprocedure whose frame description entry points to the table. The landing pad of a function split into a
+/// hot and a cold part may be the entry block of the subprocedure lifted from the cold part.
+pub fn landing_pads(proj: &Project, bytes: &[u8]) -> BTreeMap<Tid, BTreeSet<Tid>> {
+    let table = match EhFrameSection::find_except_table(bytes) {
+        Some(table) => table,
+        None => return BTreeMap::new(),
+    };
+    let pads = frame_layouts(proj, bytes)
+        .into_iter()
+        .filter_map(|(sub, layout)| {
+            Some((sub, parse_landing_pads(&table, layout.lsda?, layout.start)?))
+        })
+        .collect::<BTreeMap<_, _>>();
+    if pads.is_empty() {
+        return BTreeMap::new();
+    }
+
+    // Blocks split at an instruction share its address, the landing pad is the first of them
+    let mut blocks = BTreeMap::new();
+    for blk in proj
+        .program
+        .term
+        .subs
+        .values()
+        .flat_map(|sub| sub.term.blocks.iter())
+    {
+        if let Ok(address) = u64::from_str_radix(&blk.tid.address, 16) {
+            blocks.entry(address).or_insert(&blk.tid);
+        }
+    }
+    pads.into_iter()
+        .map(|(sub, addresses)| {
+            (
+                sub,
+                addresses
+                    .iter()
+                    .filter_map(|address| blocks.get(address).map(|blk| (*blk).clone()))
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_eh_frame, parse_landing_pads, EhFrameSection};
+
+    #[test]
+    fn fde_frames_record_save_slots() {
+        #[rustfmt::skip]
+        let data: Vec<u8> = vec![
+            // CIE: length, id, version 1, "zR", code align 1, data align -8, ra register 16, aug data len 1, pcrel sdata4
+            0x14, 0, 0, 0, 0, 0, 0, 0, 1, b'z', b'R', 0, 1, 0x78, 16, 1, 0x1b,
+            // def_cfa rsp+8, offset r16 at cfa-8, nops
+            0x0c, 7, 8, 0x90, 1, 0, 0,
+            // FDE: length, cie pointer 0x1c back to 0
+            0x18, 0, 0, 0, 0x1c, 0, 0, 0,
+            // pc begin relative to the field at 0x20 is 0x1000 - 0x20, range 0x40, aug data len 0
+            0xe0, 0x0f, 0, 0, 0x40, 0, 0, 0, 0,
+            // advance 1, def_cfa_offset 16, offset rbp at cfa-16, advance 4, def_cfa_offset 48, restore rbp
+            0x41, 0x0e, 16, 0x86, 2, 0x44, 0x0e, 48, 0xc6, 0, 0,
+            // terminator
+            0, 0, 0, 0,
+        ];
+        let section = EhFrameSection {
+            data: &data,
+            address: 0,
+            address_size: 8,
+            big_endian: false,
+        };
+
+        let layouts = parse_eh_frame(&section, 7);
+        assert_eq!(layouts.len(), 1);
+        let layout = &layouts[0];
+        assert_eq!((layout.start, layout.end), (0x1000, 0x1040));
+        assert_eq!(layout.entry_cfa_offset, 8);
+        assert_eq!(layout.frame_size, 48);
+        assert_eq!(layout.saved_registers.get(&16), Some(&-8));
+        assert_eq!(layout.saved_registers.get(&6), Some(&-16));
+        assert_eq!(layout.cfa_offset(-8), -16);
+        assert!(layout.is_save_slot(-16, 8, 8));
+        assert!(!layout.is_save_slot(-24, 8, 8));
+        assert_eq!(layout.lsda, None);
+    }
+
+    #[test]
+    fn call_site_tables_give_landing_pads() {
+        #[rustfmt::skip]
+        let data: Vec<u8> = vec![
+            // padding before the table
+            0, 0, 0, 0,
+            // lpstart omitted, no type table, uleb128 call sites in 8 bytes
+            0xff, 0xff, 0x01, 8,
+            // the call at 0x10 lands at 0x30, the call at 0x20 has no landing pad
+            0x10, 0x05, 0x30, 0x01,
+            0x20, 0x05, 0x00, 0x00,
+        ];
+        let table = EhFrameSection {
+            data: &data,
+            address: 0x2000,
+            address_size: 8,
+            big_endian: false,
+        };
+
+        assert_eq!(
+            parse_landing_pads(&table, 0x2004, 0x1000),
+            Some(vec![0x1030])
+        );
+        assert_eq!(parse_landing_pads(&table, 0x1000, 0x1000), None);
+    }
+}
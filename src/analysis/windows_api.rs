@@ -0,0 +1,545 @@
+use std::collections::BTreeMap;
+
+use cwe_checker_lib::intermediate_representation::{Project, Tid};
+
+use crate::{
+    analysis::{
+        name_heuristics::constraints_by_injecting_sub,
+        object_file::PeImport,
+        platform::{BundledSignature, PlatformProfile, SignatureSlot, TypedefKind},
+    },
+    constraints::ConstraintSet,
+};
+
+const WINDOWS_API_TYPEDEFS: &[(&str, TypedefKind)] = &[
+    ("HANDLE", TypedefKind::Opaque),
+    ("HMODULE", TypedefKind::Opaque),
+    ("HKEY", TypedefKind::Opaque),
+    ("SOCKET", TypedefKind::Opaque),
+    ("BOOL", TypedefKind::Integral),
+    ("DWORD", TypedefKind::Integral),
+    ("LONG", TypedefKind::Integral),
+    ("SIZE_T", TypedefKind::Integral),
+    ("HRESULT", TypedefKind::Integral),
+    ("LSTATUS", TypedefKind::Integral),
+];
+
+const KERNEL32_SIGNATURES: &[BundledSignature] = &[
+    (
+        "CreateFileA",
+        &[
+            (SignatureSlot::Return, "HANDLE"),
+            (SignatureSlot::Parameter(1), "DWORD"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+            (SignatureSlot::Parameter(4), "DWORD"),
+            (SignatureSlot::Parameter(5), "DWORD"),
+            (SignatureSlot::Parameter(6), "HANDLE"),
+        ],
+    ),
+    (
+        "CreateFileW",
+        &[
+            (SignatureSlot::Return, "HANDLE"),
+            (SignatureSlot::Parameter(1), "DWORD"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+            (SignatureSlot::Parameter(4), "DWORD"),
+            (SignatureSlot::Parameter(5), "DWORD"),
+            (SignatureSlot::Parameter(6), "HANDLE"),
+        ],
+    ),
+    (
+        "ReadFile",
+        &[
+            (SignatureSlot::Return, "BOOL"),
+            (SignatureSlot::Parameter(0), "HANDLE"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+            (SignatureSlot::ParameterPointee(3), "DWORD"),
+        ],
+    ),
+    (
+        "WriteFile",
+        &[
+            (SignatureSlot::Return, "BOOL"),
+            (SignatureSlot::Parameter(0), "HANDLE"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+            (SignatureSlot::ParameterPointee(3), "DWORD"),
+        ],
+    ),
+    (
+        "CloseHandle",
+        &[
+            (SignatureSlot::Return, "BOOL"),
+            (SignatureSlot::Parameter(0), "HANDLE"),
+        ],
+    ),
+    (
+        "GetFileSize",
+        &[
+            (SignatureSlot::Return, "DWORD"),
+            (SignatureSlot::Parameter(0), "HANDLE"),
+            (SignatureSlot::ParameterPointee(1), "DWORD"),
+        ],
+    ),
+    (
+        "GetStdHandle",
+        &[
+            (SignatureSlot::Return, "HANDLE"),
+            (SignatureSlot::Parameter(0), "DWORD"),
+        ],
+    ),
+    ("GetLastError", &[(SignatureSlot::Return, "DWORD")]),
+    ("SetLastError", &[(SignatureSlot::Parameter(0), "DWORD")]),
+    ("GetModuleHandleA", &[(SignatureSlot::Return, "HMODULE")]),
+    ("GetModuleHandleW", &[(SignatureSlot::Return, "HMODULE")]),
+    ("LoadLibraryA", &[(SignatureSlot::Return, "HMODULE")]),
+    ("LoadLibraryW", &[(SignatureSlot::Return, "HMODULE")]),
+    (
+        "LoadLibraryExA",
+        &[
+            (SignatureSlot::Return, "HMODULE"),
+            (SignatureSlot::Parameter(1), "HANDLE"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+        ],
+    ),
+    (
+        "LoadLibraryExW",
+        &[
+            (SignatureSlot::Return, "HMODULE"),
+            (SignatureSlot::Parameter(1), "HANDLE"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+        ],
+    ),
+    (
+        "GetProcAddress",
+        &[(SignatureSlot::Parameter(0), "HMODULE")],
+    ),
+    (
+        "FreeLibrary",
+        &[
+            (SignatureSlot::Return, "BOOL"),
+            (SignatureSlot::Parameter(0), "HMODULE"),
+        ],
+    ),
+    (
+        "GetModuleFileNameA",
+        &[
+            (SignatureSlot::Return, "DWORD"),
+            (SignatureSlot::Parameter(0), "HMODULE"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+        ],
+    ),
+    (
+        "GetModuleFileNameW",
+        &[
+            (SignatureSlot::Return, "DWORD"),
+            (SignatureSlot::Parameter(0), "HMODULE"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+        ],
+    ),
+    (
+        "VirtualAlloc",
+        &[
+            (SignatureSlot::Parameter(1), "SIZE_T"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+            (SignatureSlot::Parameter(3), "DWORD"),
+        ],
+    ),
+    (
+        "VirtualFree",
+        &[
+            (SignatureSlot::Return, "BOOL"),
+            (SignatureSlot::Parameter(1), "SIZE_T"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+        ],
+    ),
+    (
+        "VirtualProtect",
+        &[
+            (SignatureSlot::Return, "BOOL"),
+            (SignatureSlot::Parameter(1), "SIZE_T"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+            (SignatureSlot::ParameterPointee(3), "DWORD"),
+        ],
+    ),
+    ("GetProcessHeap", &[(SignatureSlot::Return, "HANDLE")]),
+    (
+        "HeapAlloc",
+        &[
+            (SignatureSlot::Parameter(0), "HANDLE"),
+            (SignatureSlot::Parameter(1), "DWORD"),
+            (SignatureSlot::Parameter(2), "SIZE_T"),
+        ],
+    ),
+    (
+        "HeapFree",
+        &[
+            (SignatureSlot::Return, "BOOL"),
+            (SignatureSlot::Parameter(0), "HANDLE"),
+            (SignatureSlot::Parameter(1), "DWORD"),
+        ],
+    ),
+    (
+        "CreateThread",
+        &[
+            (SignatureSlot::Return, "HANDLE"),
+            (SignatureSlot::Parameter(1), "SIZE_T"),
+            (SignatureSlot::Parameter(4), "DWORD"),
+            (SignatureSlot::ParameterPointee(5), "DWORD"),
+        ],
+    ),
+    (
+        "CreateEventA",
+        &[
+            (SignatureSlot::Return, "HANDLE"),
+            (SignatureSlot::Parameter(1), "BOOL"),
+            (SignatureSlot::Parameter(2), "BOOL"),
+        ],
+    ),
+    (
+        "CreateEventW",
+        &[
+            (SignatureSlot::Return, "HANDLE"),
+            (SignatureSlot::Parameter(1), "BOOL"),
+            (SignatureSlot::Parameter(2), "BOOL"),
+        ],
+    ),
+    (
+        "SetEvent",
+        &[
+            (SignatureSlot::Return, "BOOL"),
+            (SignatureSlot::Parameter(0), "HANDLE"),
+        ],
+    ),
+    (
+        "WaitForSingleObject",
+        &[
+            (SignatureSlot::Return, "DWORD"),
+            (SignatureSlot::Parameter(0), "HANDLE"),
+            (SignatureSlot::Parameter(1), "DWORD"),
+        ],
+    ),
+    (
+        "WaitForMultipleObjects",
+        &[
+            (SignatureSlot::Return, "DWORD"),
+            (SignatureSlot::Parameter(0), "DWORD"),
+            (SignatureSlot::ParameterInPointee(1), "HANDLE"),
+            (SignatureSlot::Parameter(2), "BOOL"),
+            (SignatureSlot::Parameter(3), "DWORD"),
+        ],
+    ),
+    ("GetCurrentProcess", &[(SignatureSlot::Return, "HANDLE")]),
+    ("GetCurrentProcessId", &[(SignatureSlot::Return, "DWORD")]),
+    ("GetCurrentThreadId", &[(SignatureSlot::Return, "DWORD")]),
+    (
+        "OpenProcess",
+        &[
+            (SignatureSlot::Return, "HANDLE"),
+            (SignatureSlot::Parameter(0), "DWORD"),
+            (SignatureSlot::Parameter(1), "BOOL"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+        ],
+    ),
+    (
+        "GetExitCodeProcess",
+        &[
+            (SignatureSlot::Return, "BOOL"),
+            (SignatureSlot::Parameter(0), "HANDLE"),
+            (SignatureSlot::ParameterPointee(1), "DWORD"),
+        ],
+    ),
+    (
+        "TerminateProcess",
+        &[
+            (SignatureSlot::Return, "BOOL"),
+            (SignatureSlot::Parameter(0), "HANDLE"),
+            (SignatureSlot::Parameter(1), "DWORD"),
+        ],
+    ),
+    (
+        "DuplicateHandle",
+        &[
+            (SignatureSlot::Return, "BOOL"),
+            (SignatureSlot::Parameter(0), "HANDLE"),
+            (SignatureSlot::Parameter(1), "HANDLE"),
+            (SignatureSlot::Parameter(2), "HANDLE"),
+            (SignatureSlot::ParameterPointee(3), "HANDLE"),
+            (SignatureSlot::Parameter(4), "DWORD"),
+            (SignatureSlot::Parameter(5), "BOOL"),
+            (SignatureSlot::Parameter(6), "DWORD"),
+        ],
+    ),
+    (
+        "InterlockedIncrement",
+        &[
+            (SignatureSlot::Return, "LONG"),
+            (SignatureSlot::ParameterInPointee(0), "LONG"),
+            (SignatureSlot::ParameterPointee(0), "LONG"),
+        ],
+    ),
+    (
+        "InterlockedDecrement",
+        &[
+            (SignatureSlot::Return, "LONG"),
+            (SignatureSlot::ParameterInPointee(0), "LONG"),
+            (SignatureSlot::ParameterPointee(0), "LONG"),
+        ],
+    ),
+    ("Sleep", &[(SignatureSlot::Parameter(0), "DWORD")]),
+    ("GetTickCount", &[(SignatureSlot::Return, "DWORD")]),
+];
+
+const ADVAPI32_SIGNATURES: &[BundledSignature] = &[
+    (
+        "RegOpenKeyExA",
+        &[
+            (SignatureSlot::Return, "LSTATUS"),
+            (SignatureSlot::Parameter(0), "HKEY"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+            (SignatureSlot::Parameter(3), "DWORD"),
+            (SignatureSlot::ParameterPointee(4), "HKEY"),
+        ],
+    ),
+    (
+        "RegOpenKeyExW",
+        &[
+            (SignatureSlot::Return, "LSTATUS"),
+            (SignatureSlot::Parameter(0), "HKEY"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+            (SignatureSlot::Parameter(3), "DWORD"),
+            (SignatureSlot::ParameterPointee(4), "HKEY"),
+        ],
+    ),
+    (
+        "RegQueryValueExA",
+        &[
+            (SignatureSlot::Return, "LSTATUS"),
+            (SignatureSlot::Parameter(0), "HKEY"),
+            (SignatureSlot::ParameterPointee(3), "DWORD"),
+            (SignatureSlot::ParameterInPointee(5), "DWORD"),
+            (SignatureSlot::ParameterPointee(5), "DWORD"),
+        ],
+    ),
+    (
+        "RegQueryValueExW",
+        &[
+            (SignatureSlot::Return, "LSTATUS"),
+            (SignatureSlot::Parameter(0), "HKEY"),
+            (SignatureSlot::ParameterPointee(3), "DWORD"),
+            (SignatureSlot::ParameterInPointee(5), "DWORD"),
+            (SignatureSlot::ParameterPointee(5), "DWORD"),
+        ],
+    ),
+    (
+        "RegSetValueExA",
+        &[
+            (SignatureSlot::Return, "LSTATUS"),
+            (SignatureSlot::Parameter(0), "HKEY"),
+            (SignatureSlot::Parameter(3), "DWORD"),
+            (SignatureSlot::Parameter(5), "DWORD"),
+        ],
+    ),
+    (
+        "RegSetValueExW",
+        &[
+            (SignatureSlot::Return, "LSTATUS"),
+            (SignatureSlot::Parameter(0), "HKEY"),
+            (SignatureSlot::Parameter(3), "DWORD"),
+            (SignatureSlot::Parameter(5), "DWORD"),
+        ],
+    ),
+    (
+        "RegCloseKey",
+        &[
+            (SignatureSlot::Return, "LSTATUS"),
+            (SignatureSlot::Parameter(0), "HKEY"),
+        ],
+    ),
+];
+
+const WS2_32_SIGNATURES: &[BundledSignature] = &[
+    ("socket", &[(SignatureSlot::Return, "SOCKET")]),
+    (
+        "accept",
+        &[
+            (SignatureSlot::Return, "SOCKET"),
+            (SignatureSlot::Parameter(0), "SOCKET"),
+        ],
+    ),
+    ("bind", &[(SignatureSlot::Parameter(0), "SOCKET")]),
+    ("connect", &[(SignatureSlot::Parameter(0), "SOCKET")]),
+    ("listen", &[(SignatureSlot::Parameter(0), "SOCKET")]),
+    ("recv", &[(SignatureSlot::Parameter(0), "SOCKET")]),
+    ("recvfrom", &[(SignatureSlot::Parameter(0), "SOCKET")]),
+    ("send", &[(SignatureSlot::Parameter(0), "SOCKET")]),
+    ("sendto", &[(SignatureSlot::Parameter(0), "SOCKET")]),
+    ("ioctlsocket", &[(SignatureSlot::Parameter(0), "SOCKET")]),
+    ("closesocket", &[(SignatureSlot::Parameter(0), "SOCKET")]),
+];
+
+const OLE32_SIGNATURES: &[BundledSignature] = &[
+    ("CoInitialize", &[(SignatureSlot::Return, "HRESULT")]),
+    (
+        "CoInitializeEx",
+        &[
+            (SignatureSlot::Return, "HRESULT"),
+            (SignatureSlot::Parameter(1), "DWORD"),
+        ],
+    ),
+    (
+        "CoCreateInstance",
+        &[
+            (SignatureSlot::Return, "HRESULT"),
+            (SignatureSlot::Parameter(2), "DWORD"),
+        ],
+    ),
+];
+
+/// The signatures of the Win32 APIs, keyed by the DLL exporting them. `_In_` pointer parameters type what the callee
+/// loads through them, `_Out_` parameters what it stores, and `_Inout_` parameters both.
+const WINDOWS_API_SIGNATURES: &[(&str, &[BundledSignature])] = &[
+    ("kernel32.dll", KERNEL32_SIGNATURES),
+    ("advapi32.dll", ADVAPI32_SIGNATURES),
+    ("ws2_32.dll", WS2_32_SIGNATURES),
+    ("ole32.dll", OLE32_SIGNATURES),
+];
+
+/// The functions of Winsock commonly imported by ordinal.
+const WS2_32_ORDINALS: &[(u16, &str)] = &[
+    (1, "accept"),
+    (2, "bind"),
+    (3, "closesocket"),
+    (4, "connect"),
+    (10, "ioctlsocket"),
+    (13, "listen"),
+    (16, "recv"),
+    (17, "recvfrom"),
+    (19, "send"),
+    (20, "sendto"),
+    (23, "socket"),
+];
+
+/// Finds the name of the DLL the signatures of an imported DLL are bundled under: kernelbase and the API sets of the
+/// core APIs forward to kernel32, and wsock32 to ws2_32.
+fn bundled_dll(dll: &str) -> &str {
+    if dll == "kernelbase.dll" || dll.starts_with("api-ms-win-core-") {
+        "kernel32.dll"
+    } else if dll == "wsock32.dll" {
+        "ws2_32.dll"
+    } else {
+        dll
+    }
+}
+
+/// The name Ghidra gives the extern symbol of an import.
+pub fn import_symbol_name(import: &PeImport) -> Option<String> {
+    import
+        .name
+        .clone()
+        .or_else(|| import.ordinal.map(|ordinal| format!("Ordinal_{}", ordinal)))
+}
+
+/// Resolves an import to the bundled API it imports, resolving Winsock ordinals to their name. Imports from DLLs
+/// without bundled signatures, or of functions the DLL's signatures do not cover, are not resolved.
+pub fn resolve_import(import: &PeImport) -> Option<&'static str> {
+    let dll = bundled_dll(&import.dll);
+    let (_, signatures) = WINDOWS_API_SIGNATURES
+        .iter()
+        .find(|(bundled, _)| *bundled == dll)?;
+    let name = match (&import.name, import.ordinal) {
+        (Some(name), _) => name.as_str(),
+        (None, Some(ordinal)) if dll == "ws2_32.dll" => WS2_32_ORDINALS
+            .iter()
+            .find(|(ord, _)| *ord == ordinal)
+            .map(|(_, name)| *name)?,
+        _ => return None,
+    };
+    signatures
+        .iter()
+        .find(|(api, _)| *api == name)
+        .map(|(api, _)| *api)
+}
+
+/// The profile of the bundled Windows API signatures, whose typedefs need to be part of the lattice like the typedefs
+/// of [PlatformProfile::for_platform].
+pub fn windows_api_profile() -> PlatformProfile {
+    let signatures = WINDOWS_API_SIGNATURES
+        .iter()
+        .map(|(_, signatures)| *signatures)
+        .collect::<Vec<_>>();
+    PlatformProfile::from_bundled(WINDOWS_API_TYPEDEFS, &signatures)
+}
+
+/// Creates the constraints typing the imports of a PE binary from the bundled Windows API signatures, keyed by the
+/// subprocedures calling them. Imports are matched to extern symbols by the name Ghidra gives them, and to signatures
+/// by the DLL they are imported from, so a local function sharing the name of an API is not typed. Typedefs for which
+/// is_element is false are skipped.
+pub fn import_constraints(
+    proj: &Project,
+    imports: &[PeImport],
+    is_element: impl Fn(&str) -> bool,
+) -> BTreeMap<Tid, ConstraintSet> {
+    let profile = windows_api_profile();
+    let apis: BTreeMap<String, &str> = imports
+        .iter()
+        .filter_map(|import| Some((import_symbol_name(import)?, resolve_import(import)?)))
+        .collect();
+
+    constraints_by_injecting_sub(proj, |callable, name| {
+        let is_extern = proj.program.term.extern_symbols.contains_key(callable);
+        match apis.get(name) {
+            Some(api) if is_extern => profile.signature_constraints(callable, api, &is_element),
+            _ => ConstraintSet::default(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use crate::{analysis::object_file::PeImport, constraints::parse_constraint_set};
+
+    use super::{import_symbol_name, resolve_import, windows_api_profile};
+
+    #[test]
+    fn imports_resolve_to_bundled_signatures() {
+        let import = |dll: &str, name: Option<&str>, ordinal: Option<u16>| PeImport {
+            dll: dll.to_owned(),
+            name: name.map(str::to_owned),
+            ordinal,
+            address: 0x140002000,
+        };
+
+        let by_ordinal = import("wsock32.dll", None, Some(23));
+        assert_eq!(
+            import_symbol_name(&by_ordinal).as_deref(),
+            Some("Ordinal_23")
+        );
+        assert_eq!(resolve_import(&by_ordinal), Some("socket"));
+        assert_eq!(
+            resolve_import(&import(
+                "api-ms-win-core-file-l1-1-0.dll",
+                Some("ReadFile"),
+                None
+            )),
+            Some("ReadFile")
+        );
+        // Not exported by the DLL the signature is bundled for
+        assert_eq!(
+            resolve_import(&import("mylib.dll", Some("ReadFile"), None)),
+            None
+        );
+
+        let cons = windows_api_profile().signature_constraints(
+            &Tid::create("ReadFile".to_owned(), "1000".to_owned()),
+            "ReadFile",
+            &|name| name != "BOOL",
+        );
+        let (_, expected) = parse_constraint_set(
+            "ReadFile.in_0 <= HANDLE\nReadFile.in_2 <= DWORD\nDWORD <= ReadFile.in_3.store",
+        )
+        .unwrap();
+        assert_eq!(cons, expected);
+    }
+}
@@ -1,15 +1,46 @@
 use binary_type_inference::{
-    inference_job::{InferenceJob, JobDefinition, JsonDef, ProtobufDef},
-    solver::type_lattice::NamedLatticeElement,
+    analysis::{
+        freestanding::FreestandingProfile,
+        platform::{Platform, PlatformProfile},
+        rust_binaries, syscalls, windows_api,
+    },
+    ctypes::CTypeMapping,
+    diagnostics::{self, Diagnostics},
+    inference_job::{
+        config::BtiConfig,
+        selection::RuleContextBuilder,
+        server::Server,
+        source::{PcodeJsonSource, ProjectSource},
+        InferenceJob, JobDefinition, JsonDef, ProtobufDef,
+    },
+    lowering::{
+        accessors::FieldNameTable, callsites::Callsite, linked::LinkedStructure, locals::FrameSlot,
+        primitives::PrimitiveTable, CType, TypeId,
+    },
+    solver::{
+        passes::PassPipeline,
+        transform::DropVariables,
+        type_lattice::{CustomLatticeElement, NamedLatticeElement},
+        type_scheme::TypeScheme,
+        type_sketch::{LatticeBounds, SketchGraph},
+    },
+    util::{OutputFile, ZSTD_EXTENSION},
 };
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches};
+use cwe_checker_lib::intermediate_representation::Tid;
+use petgraph::graph::NodeIndex;
 
 use petgraph::dot::Dot;
 use prost::Message;
+use regex::Regex;
+use serde::Serialize;
 
 use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet},
+    hash::Hasher,
     io::Write,
     path::{Path, PathBuf},
+    rc::Rc,
 };
 
 pub fn immutably_push<P>(pb: &Path, new_path: P) -> PathBuf
@@ -21,48 +52,966 @@ where
     npath
 }
 
+/// The boolean settings of the command line. `--<name>` turns a setting on and `--no_<name>` turns it off, either overrides
+/// the configuration file, and the last of the two given wins.
+const SWITCHES: &[(&str, &str)] = &[
+    ("human_readable_input", "no_human_readable_input"),
+    ("human_readable_output", "no_human_readable_output"),
+    (
+        "use_aggressive_shared_returns",
+        "no_use_aggressive_shared_returns",
+    ),
+    ("unions", "no_unions"),
+    ("linked_structures", "no_linked_structures"),
+    ("single_type", "no_single_type"),
+    ("synthesize_padding", "no_synthesize_padding"),
+    ("lower_reachable_only", "no_lower_reachable_only"),
+    ("strict_lowering", "no_strict_lowering"),
+    ("callsite_signatures", "no_callsite_signatures"),
+    ("local_variable_types", "no_local_variable_types"),
+    ("name_heuristics", "no_name_heuristics"),
+    ("syscalls", "no_syscalls"),
+    ("go_metadata", "no_go_metadata"),
+    ("rust", "no_rust"),
+    ("unwind_frames", "no_unwind_frames"),
+    ("windows_imports", "no_windows_imports"),
+    ("tls", "no_tls"),
+    ("landing_pads", "no_landing_pads"),
+    ("pointer_integers", "no_pointer_integers"),
+    ("heap_lifetimes", "no_heap_lifetimes"),
+    ("relocations", "no_relocations"),
+    ("compress_aliases", "no_compress_aliases"),
+    ("canonical_primitives", "no_canonical_primitives"),
+    ("c_primitive_names", "no_c_primitive_names"),
+    ("tagged_unions", "no_tagged_unions"),
+    ("nullability", "no_nullability"),
+    ("const_parameters", "no_const_parameters"),
+    ("array_extents", "no_array_extents"),
+    ("character_types", "no_character_types"),
+    ("field_access_counts", "no_field_access_counts"),
+    ("detect_allocator_wrappers", "no_detect_allocator_wrappers"),
+    (
+        "allocation_size_constraints",
+        "no_allocation_size_constraints",
+    ),
+    ("collapse_thunks", "no_collapse_thunks"),
+    ("trace_rules", "no_trace_rules"),
+    ("fail_fast", "no_fail_fast"),
+    ("prune_dead_defs", "no_prune_dead_defs"),
+    ("combined_context", "no_combined_context"),
+    ("function_scoped_variables", "no_function_scoped_variables"),
+    ("all_subs", "no_all_subs"),
+    ("all_externs", "no_all_externs"),
+    ("only_interesting_bodies", "no_only_interesting_bodies"),
+    ("deterministic", "no_deterministic"),
+    ("stream_output", "no_stream_output"),
+    ("compress_output", "no_compress_output"),
+    ("compress_debug", "no_compress_debug"),
+    ("structural_names", "no_structural_names"),
+    ("canonical_names", "no_canonical_names"),
+];
+
+/// Adds the flags turning each of the [SWITCHES] on and off.
+fn with_switches<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    SWITCHES.iter().fold(app, |app, &(name, negated)| {
+        app.arg(
+            Arg::with_name(name)
+                .long(name)
+                .takes_value(false)
+                .overrides_with(negated),
+        )
+        .arg(
+            Arg::with_name(negated)
+                .long(negated)
+                .takes_value(false)
+                .overrides_with(name),
+        )
+    })
+}
+
+/// Resolves one of the [SWITCHES], falling back to the value from the configuration file or the default if neither
+/// `--<name>` nor `--no_<name>` is given.
+fn switch(matches: &ArgMatches, name: &str, fallback: bool) -> bool {
+    if matches.is_present(name) {
+        true
+    } else if matches.is_present(&format!("no_{}", name)) {
+        false
+    } else {
+        fallback
+    }
+}
+
+/// The values of a repeatable option, or the values from the configuration file if the option is not given.
+fn values_or<'a>(
+    matches: &'a ArgMatches,
+    name: &str,
+    configured: &'a Option<Vec<String>>,
+) -> Vec<&'a str> {
+    match matches.values_of(name) {
+        Some(values) => values.collect(),
+        None => configured.iter().flatten().map(String::as_str).collect(),
+    }
+}
+
+/// Parses the job and applies the command line flags and configuration to it. Checkpointing is skipped if use_checkpoints is false.
+fn build_job(
+    matches: &ArgMatches,
+    config: &BtiConfig,
+    job_def: &JobDefinition,
+    dbg_dir: Option<String>,
+    use_checkpoints: bool,
+) -> anyhow::Result<InferenceJob> {
+    let use_aggressive_shared_returns = switch(
+        matches,
+        "use_aggressive_shared_returns",
+        config.use_aggressive_shared_returns.unwrap_or(false),
+    );
+    let human_readable_input = switch(
+        matches,
+        "human_readable_input",
+        config.human_readable_input.unwrap_or(false),
+    );
+
+    let selection = interesting_selection(matches, config)?;
+    let mut source = PcodeJsonSource::from_definition(job_def)?;
+    if switch(
+        matches,
+        "only_interesting_bodies",
+        config.only_interesting_bodies.unwrap_or(false),
+    ) {
+        let interesting_tids = if human_readable_input {
+            InferenceJob::parse_tid_set::<JsonDef>(&job_def.interesting_tids)
+        } else {
+            InferenceJob::parse_tid_set::<ProtobufDef>(&job_def.interesting_tids)
+        }?;
+        let mut bodies = interesting_tids
+            .iter()
+            .map(|tid| tid.get_str_repr().to_owned())
+            .collect::<BTreeSet<_>>();
+        // the selected symbols are interesting too, resolving them only needs the symbols of the project
+        if let Some(selection) = &selection {
+            let symbols = source.clone().with_selected_bodies(BTreeSet::new());
+            let symbol_proj = symbols.project(&symbols.binary_bytes()?)?;
+            bodies.extend(
+                selection
+                    .resolve_tids(&symbol_proj)
+                    .iter()
+                    .map(|tid| tid.get_str_repr().to_owned()),
+            );
+        }
+        source = source.with_selected_bodies(bodies);
+    }
+
+    let platform_profile = matches
+        .value_of("platform")
+        .map(str::parse::<Platform>)
+        .transpose()?
+        .or(config.platform)
+        .map(PlatformProfile::for_platform);
+    let type_syscalls = switch(matches, "syscalls", config.syscalls.unwrap_or(false));
+    let rust_heuristics = switch(matches, "rust", config.rust.unwrap_or(false));
+    let windows_imports = switch(
+        matches,
+        "windows_imports",
+        config.windows_imports.unwrap_or(false),
+    );
+    let freestanding_profile = matches
+        .value_of("freestanding")
+        .or_else(|| config.freestanding.as_deref())
+        .map(FreestandingProfile::from_file)
+        .transpose()?;
+    // Firmware has no libc, so only the typedefs of its own profile are added to the lattice
+    let profiles = match &freestanding_profile {
+        Some(freestanding) => vec![freestanding.typedef_profile()],
+        None => platform_profile
+            .iter()
+            .cloned()
+            .chain(type_syscalls.then(syscalls::syscall_profile))
+            .chain(rust_heuristics.then(rust_binaries::rust_profile))
+            .chain(windows_imports.then(windows_api::windows_api_profile))
+            .collect::<Vec<_>>(),
+    };
+    let additional_lattices = if profiles.is_empty() {
+        vec![]
+    } else {
+        let base = InferenceJob::parse_lattice_json_to_lattice_def(&job_def.lattice_json)?;
+        profiles
+            .iter()
+            .map(|profile| profile.lattice_definition(&base))
+            .collect()
+    };
+
+    let mut if_job = if human_readable_input {
+        InferenceJob::parse_with_source::<JsonDef>(
+            Box::new(source),
+            job_def,
+            dbg_dir.clone(),
+            additional_lattices,
+            use_aggressive_shared_returns,
+        )
+    } else {
+        InferenceJob::parse_with_source::<ProtobufDef>(
+            Box::new(source),
+            job_def,
+            dbg_dir,
+            additional_lattices,
+            use_aggressive_shared_returns,
+        )
+    }?;
+    if_job.set_platform_profile(platform_profile);
+    if_job.set_syscall_typing(type_syscalls);
+
+    if let Some(selection) = &selection {
+        if_job.select_interesting(selection);
+    }
+
+    if let Some(allocators) = &config.allocators {
+        if_job.set_allocators(allocators.clone());
+    }
+    if let Some(deallocation_symbols) = &config.deallocation_symbols {
+        if_job.set_deallocation_symbols(deallocation_symbols.clone());
+    }
+    if_job.set_heap_lifetime_pairing(switch(
+        matches,
+        "heap_lifetimes",
+        config.heap_lifetimes.unwrap_or(false),
+    ));
+    if_job.set_freestanding_profile(freestanding_profile);
+    if_job.set_go_metadata(switch(
+        matches,
+        "go_metadata",
+        config.go_metadata.unwrap_or(false),
+    ));
+    if_job.set_rust_heuristics(rust_heuristics);
+    if_job.set_unwind_frames(switch(
+        matches,
+        "unwind_frames",
+        config.unwind_frames.unwrap_or(false),
+    ));
+    if_job.set_windows_imports(windows_imports);
+    if_job.set_tls_modeling(switch(matches, "tls", config.tls.unwrap_or(false)));
+    if_job.set_landing_pad_isolation(switch(
+        matches,
+        "landing_pads",
+        config.landing_pads.unwrap_or(false),
+    ));
+    if_job.set_pointer_integer_typing(switch(
+        matches,
+        "pointer_integers",
+        config.pointer_integers.unwrap_or(false),
+    ));
+    if_job.set_relocation_typing(switch(
+        matches,
+        "relocations",
+        config.relocations.unwrap_or(false),
+    ));
+    if_job.set_detect_allocator_wrappers(switch(
+        matches,
+        "detect_allocator_wrappers",
+        config.detect_allocator_wrappers.unwrap_or(false),
+    ));
+    if_job.set_allocation_size_constraints(switch(
+        matches,
+        "allocation_size_constraints",
+        config.allocation_size_constraints.unwrap_or(false),
+    ));
+    if_job.set_thunk_collapsing(switch(
+        matches,
+        "collapse_thunks",
+        config.collapse_thunks.unwrap_or(false),
+    ));
+    if_job.set_dead_def_pruning(switch(
+        matches,
+        "prune_dead_defs",
+        config.prune_dead_defs.unwrap_or(false),
+    ));
+    let return_usage_threshold = matches
+        .value_of("return_usage_threshold")
+        .map(str::parse::<usize>)
+        .transpose()?
+        .or(config.return_usage_threshold);
+    if_job.set_return_usage_threshold(return_usage_threshold);
+    if_job.set_combined_context(switch(
+        matches,
+        "combined_context",
+        config.combined_context.unwrap_or(false),
+    ));
+    if_job.set_function_scoped_variables(switch(
+        matches,
+        "function_scoped_variables",
+        config.function_scoped_variables.unwrap_or(false),
+    ));
+
+    if_job.set_rule_traces(switch(
+        matches,
+        "trace_rules",
+        config.trace_rules.unwrap_or(false),
+    ));
+
+    let check_entailment = matches
+        .value_of("check_entailment")
+        .map(str::parse::<usize>)
+        .transpose()?
+        .or(config.check_entailment);
+    if_job.set_entailment_checks(check_entailment);
+    if_job.set_solve_failure_isolation(!switch(
+        matches,
+        "fail_fast",
+        config.fail_fast.unwrap_or(false),
+    ));
+
+    let memory_budget = matches
+        .value_of("memory_budget")
+        .map(str::parse::<usize>)
+        .transpose()?
+        .or(config.memory_budget);
+    let memory_budget = memory_budget
+        .map(|megabytes| {
+            megabytes.checked_mul(1024 * 1024).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "--memory_budget of {} MB does not fit in the address space",
+                    megabytes
+                )
+            })
+        })
+        .transpose()?;
+    if_job.set_memory_budget(memory_budget);
+
+    let mut offset_clustering = config.offset_clustering.unwrap_or_default();
+    if let Some(max_offset) = matches.value_of("max_field_offset") {
+        offset_clustering.max_offset = Some(max_offset.parse::<i64>()?);
+    }
+    if let Some(stride) = matches.value_of("offset_stride") {
+        offset_clustering.stride = Some(stride.parse::<i64>()?);
+    }
+    if_job.set_offset_clustering(offset_clustering);
+
+    if let Some(passes) = matches.value_of("simplification_passes") {
+        if_job.set_simplification_passes(PassPipeline::parse(passes)?);
+    } else if let Some(passes) = &config.simplification_passes {
+        if_job.set_simplification_passes(PassPipeline::from_names(passes)?);
+    }
+
+    match matches.value_of("context_depth") {
+        Some(k) => if_job.set_context_depth(k.parse()?),
+        None => if_job.set_context_depth(config.context_depth.unwrap_or(0)),
+    }
+
+    for pattern in values_or(matches, "drop_constraints_on", &config.drop_constraints_on) {
+        if_job.add_transformer(Rc::new(DropVariables::new(Regex::new(pattern)?)));
+    }
+
+    if_job.set_compress_debug_output(compress_debug(matches, config));
+    if_job.set_canonical_constraint_names(switch(
+        matches,
+        "canonical_names",
+        config.canonical_names.unwrap_or(false),
+    ));
+
+    match matches.value_of("dump_constraints") {
+        Some(granularity) => if_job.set_constraint_dumps(Some(granularity.parse()?)),
+        None => if_job.set_constraint_dumps(config.dump_constraints),
+    }
+
+    match matches
+        .value_of("checkpoint_dir")
+        .or_else(|| config.checkpoint_dir.as_deref())
+    {
+        Some(checkpoint_dir) if use_checkpoints => {
+            if_job.set_checkpoint_dir(checkpoint_dir, matches.is_present("resume"))?
+        }
+        Some(_) => (),
+        None if matches.is_present("resume") => {
+            return Err(anyhow::anyhow!("--resume requires a checkpoint directory"));
+        }
+        None => (),
+    }
+
+    let mut lowering_options = config
+        .lowering
+        .clone()
+        .unwrap_or_else(|| if_job.get_lowering_options().clone());
+    lowering_options.emit_unions = switch(matches, "unions", lowering_options.emit_unions);
+    lowering_options.detect_linked_structures = switch(
+        matches,
+        "linked_structures",
+        lowering_options.detect_linked_structures,
+    );
+    if let Some(max_union_arity) = matches.value_of("max_union_arity") {
+        lowering_options.max_union_arity = Some(max_union_arity.parse::<usize>()?);
+    }
+    lowering_options.single_type = switch(matches, "single_type", lowering_options.single_type);
+    if let Some(overflow) = matches.value_of("union_overflow") {
+        lowering_options.union_overflow = overflow.parse()?;
+    }
+    lowering_options.synthesize_padding = switch(
+        matches,
+        "synthesize_padding",
+        lowering_options.synthesize_padding,
+    );
+    lowering_options.compress_aliases = switch(
+        matches,
+        "compress_aliases",
+        lowering_options.compress_aliases,
+    );
+    lowering_options.canonical_primitives = switch(
+        matches,
+        "canonical_primitives",
+        lowering_options.canonical_primitives,
+    );
+    lowering_options.detect_tagged_unions = switch(
+        matches,
+        "tagged_unions",
+        lowering_options.detect_tagged_unions,
+    );
+    lowering_options.infer_nullability =
+        switch(matches, "nullability", lowering_options.infer_nullability);
+    lowering_options.infer_const_parameters = switch(
+        matches,
+        "const_parameters",
+        lowering_options.infer_const_parameters,
+    );
+    lowering_options.infer_array_extents = switch(
+        matches,
+        "array_extents",
+        lowering_options.infer_array_extents,
+    );
+    lowering_options.infer_character_types = switch(
+        matches,
+        "character_types",
+        lowering_options.infer_character_types,
+    );
+    lowering_options.count_field_accesses = switch(
+        matches,
+        "field_access_counts",
+        lowering_options.count_field_accesses,
+    );
+    if let Some(max_offsets) = matches.value_of("max_field_offsets") {
+        lowering_options.max_field_offsets = Some(max_offsets.parse::<usize>()?);
+    }
+    if let Some(strategy) = matches.value_of("conflict_strategy") {
+        lowering_options.conflict_strategy = strategy.parse()?;
+    }
+    lowering_options.lower_reachable_only = switch(
+        matches,
+        "lower_reachable_only",
+        lowering_options.lower_reachable_only,
+    );
+    lowering_options.strict_invariants = switch(
+        matches,
+        "strict_lowering",
+        lowering_options.strict_invariants,
+    );
+    if let Some(table_file) = matches.value_of("primitive_table") {
+        lowering_options.primitive_table =
+            serde_json::from_reader(std::fs::File::open(table_file)?)?;
+    } else if switch(matches, "c_primitive_names", false) {
+        lowering_options.primitive_table = PrimitiveTable::for_data_model(if_job.get_data_model());
+    } else if lowering_options.primitive_table == PrimitiveTable::default() {
+        // lattice names are kept but sizes follow the target
+        lowering_options.primitive_table =
+            PrimitiveTable::for_data_model(if_job.get_data_model()).keeping_lattice_names();
+    }
+    if_job.set_lowering_options(lowering_options);
+    if_job.set_callsite_signatures(switch(
+        matches,
+        "callsite_signatures",
+        config.callsite_signatures.unwrap_or(false),
+    ));
+    if_job.set_local_variable_types(switch(
+        matches,
+        "local_variable_types",
+        config.local_variable_types.unwrap_or(false),
+    ));
+    if_job.set_register_types(matches.is_present("register_types_out"));
+
+    let parameter_names_file = matches
+        .value_of("parameter_names")
+        .or_else(|| config.parameter_names.as_deref());
+    if let Some(names_file) = parameter_names_file {
+        if_job.set_name_heuristics(Some(serde_json::from_reader(std::fs::File::open(
+            names_file,
+        )?)?));
+    } else if switch(
+        matches,
+        "name_heuristics",
+        config.name_heuristics.unwrap_or(false),
+    ) {
+        if_job.set_name_heuristics(Some(BTreeMap::new()));
+    }
+
+    if let Some(assumptions_file) = matches
+        .value_of("assumptions")
+        .or_else(|| config.assumptions.as_deref())
+    {
+        let assumptions = InferenceJob::parse_assumptions(assumptions_file)?;
+        if_job.add_assumptions(&assumptions)?;
+    }
+
+    Ok(if_job)
+}
+
+/// Converts the lowered types to the output mapping. When include_repr_nodes is set the type of each labeled term is also
+/// recorded in the type variable representations.
+fn build_output_mapping(
+    node_to_type_id: &BTreeMap<NodeIndex, TypeId>,
+    mut type_id_to_type: BTreeMap<TypeId, CType>,
+    mapping: &BTreeMap<Tid, NodeIndex>,
+    callsites: &BTreeMap<Callsite, NodeIndex>,
+    linked_structures: &BTreeMap<NodeIndex, LinkedStructure>,
+    structure_names: &BTreeMap<NodeIndex, String>,
+    field_names: &FieldNameTable,
+    stack_frames: &BTreeMap<Tid, BTreeMap<i64, TypeId>>,
+    frame_slots: &BTreeMap<Tid, Vec<FrameSlot>>,
+    include_repr_nodes: bool,
+) -> anyhow::Result<CTypeMapping> {
+    let frame_structures = binary_type_inference::lowering::locals::add_frame_structures(
+        &mut type_id_to_type,
+        frame_slots,
+    );
+    let mut pb = binary_type_inference::lowering::convert_mapping_to_profobuf(
+        type_id_to_type,
+        node_to_type_id,
+        mapping,
+    )?;
+    binary_type_inference::lowering::linked::annotate_linked_structures(
+        &mut pb,
+        linked_structures,
+        node_to_type_id,
+    )?;
+    binary_type_inference::lowering::callsites::annotate_callsite_signatures(
+        &mut pb,
+        callsites,
+        node_to_type_id,
+    )?;
+    binary_type_inference::lowering::naming::annotate_structure_names(
+        &mut pb,
+        structure_names,
+        node_to_type_id,
+    )?;
+    binary_type_inference::lowering::accessors::annotate_field_names(
+        &mut pb,
+        field_names,
+        node_to_type_id,
+    )?;
+    binary_type_inference::lowering::locals::annotate_stack_frames(&mut pb, stack_frames)?;
+    binary_type_inference::lowering::locals::annotate_frame_structures(&mut pb, &frame_structures)?;
+
+    if include_repr_nodes {
+        for (k, v) in mapping {
+            let tid = binary_type_inference::ctypes::Tid {
+                name: k.get_str_repr().to_owned(),
+                address: k.address.clone(),
+            };
+
+            if let Some(tgt_type_id) = node_to_type_id.get(v) {
+                let tid_to_node_idx = binary_type_inference::ctypes::TidToTypeId {
+                    type_id: Some(binary_type_inference::lowering::convert_typeid(
+                        *tgt_type_id,
+                    )?),
+                    tid: Some(tid),
+                };
+
+                pb.type_variable_repr_nodes.push(tid_to_node_idx);
+            }
+        }
+    }
+
+    Ok(pb)
+}
+
+/// Builds the selection of additional interesting symbols from the command line, if any selector was given.
+fn interesting_selection(
+    matches: &ArgMatches,
+    config: &BtiConfig,
+) -> anyhow::Result<Option<RuleContextBuilder>> {
+    let mut selection = RuleContextBuilder::new();
+    let mut selected = false;
+    for glob in values_or(matches, "interesting_glob", &config.interesting_globs) {
+        selection = selection.with_name_glob(glob)?;
+        selected = true;
+    }
+    for regex in values_or(matches, "interesting_regex", &config.interesting_regexes) {
+        selection = selection.with_name_regex(regex)?;
+        selected = true;
+    }
+    for range in values_or(matches, "interesting_range", &config.interesting_ranges) {
+        let (start, end) = RuleContextBuilder::parse_address_range(range)?;
+        selection = selection.with_address_range(start, end);
+        selected = true;
+    }
+    if switch(matches, "all_subs", config.all_subs.unwrap_or(false)) {
+        selection = selection.with_all_subs();
+        selected = true;
+    }
+    if switch(matches, "all_externs", config.all_externs.unwrap_or(false)) {
+        selection = selection.with_all_externs();
+        selected = true;
+    }
+    Ok(if selected { Some(selection) } else { None })
+}
+
+fn compress_debug(matches: &ArgMatches, config: &BtiConfig) -> bool {
+    switch(
+        matches,
+        "compress_debug",
+        config.compress_debug.unwrap_or(false),
+    )
+}
+
+/// Creates a file in the debug directory, appending the zstd extension to its name if debug artifacts are compressed.
+fn create_debug_file(dbg_dir: &str, fname: &str, compress: bool) -> anyhow::Result<OutputFile> {
+    let fname = if compress {
+        format!("{}.{}", fname, ZSTD_EXTENSION)
+    } else {
+        fname.to_owned()
+    };
+    OutputFile::create(immutably_push(Path::new(dbg_dir), fname), compress)
+}
+
+fn output_hash(pb: &CTypeMapping) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&pb.encode_to_vec());
+    hasher.finish()
+}
+
+fn write_conflicts(
+    matches: &ArgMatches,
+    if_job: &InferenceJob,
+    grph: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+) -> anyhow::Result<()> {
+    if let Some(conflicts_file) = matches.value_of("conflicts_out") {
+        serde_json::to_writer_pretty(
+            std::fs::File::create(conflicts_file)?,
+            &if_job.get_lattice_conflicts(grph)?,
+        )?;
+    }
+    Ok(())
+}
+
+fn write_rejected_alternatives(
+    matches: &ArgMatches,
+    if_job: &InferenceJob,
+    grph: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+) -> anyhow::Result<()> {
+    if let Some(alternatives_file) = matches.value_of("rejected_alternatives_out") {
+        serde_json::to_writer_pretty(
+            std::fs::File::create(alternatives_file)?,
+            &if_job.get_rejected_alternatives(grph)?,
+        )?;
+    }
+    Ok(())
+}
+
+fn write_diagnostics(matches: &ArgMatches, diagnostics: &Diagnostics) -> anyhow::Result<()> {
+    if let Some(diagnostics_file) = matches.value_of("diagnostics_out") {
+        serde_json::to_writer_pretty(std::fs::File::create(diagnostics_file)?, diagnostics)?;
+    }
+    Ok(())
+}
+
+fn write_register_bindings(
+    matches: &ArgMatches,
+    if_job: &InferenceJob,
+    grph: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    node_to_type_id: &BTreeMap<NodeIndex, TypeId>,
+) -> anyhow::Result<()> {
+    if let Some(bindings_file) = matches.value_of("register_types_out") {
+        let bindings = if_job.get_register_bindings(grph, node_to_type_id)?;
+        serde_json::to_writer_pretty(std::fs::File::create(bindings_file)?, &bindings)?;
+    }
+    Ok(())
+}
+
+fn write_xrefs(
+    matches: &ArgMatches,
+    if_job: &InferenceJob,
+    grph: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    node_to_type_id: &BTreeMap<NodeIndex, TypeId>,
+    type_id_to_type: &BTreeMap<TypeId, CType>,
+    human_readable_output: bool,
+) -> anyhow::Result<()> {
+    if let Some(xrefs_file) = matches.value_of("xrefs_out") {
+        let table = binary_type_inference::lowering::xrefs::build_xref_table(
+            node_to_type_id,
+            type_id_to_type,
+            &if_job.get_field_xrefs(grph)?,
+        )?;
+        if human_readable_output {
+            serde_json::to_writer_pretty(std::fs::File::create(xrefs_file)?, &table)?;
+        } else {
+            std::fs::write(xrefs_file, table.encode_to_vec())?;
+        }
+    }
+    Ok(())
+}
+
+/// A type scheme written to `--schemes_out` alongside the lowered monomorphic type of its function.
+#[derive(Serialize)]
+struct SchemeOutput<'a> {
+    #[serde(flatten)]
+    scheme: &'a TypeScheme,
+    lowered_type_id: Option<TypeId>,
+}
+
+// The type id each labeled term was lowered to.
+fn lowered_term_types(
+    mapping: &BTreeMap<Tid, NodeIndex>,
+    node_to_type_id: &BTreeMap<NodeIndex, TypeId>,
+) -> BTreeMap<Tid, TypeId> {
+    mapping
+        .iter()
+        .filter_map(|(tid, nd)| node_to_type_id.get(nd).map(|ty| (tid.clone(), *ty)))
+        .collect()
+}
+
+fn write_schemes(
+    matches: &ArgMatches,
+    if_job: &InferenceJob,
+    lowered_types: &BTreeMap<Tid, TypeId>,
+) -> anyhow::Result<()> {
+    if let Some(schemes_file) = matches.value_of("schemes_out") {
+        let schemes = if_job.get_type_schemes()?;
+        let output = schemes
+            .iter()
+            .map(|scheme| SchemeOutput {
+                scheme,
+                lowered_type_id: lowered_types.get(&scheme.function).cloned(),
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_writer_pretty(std::fs::File::create(schemes_file)?, &output)?;
+    }
+    Ok(())
+}
+
 fn main() -> anyhow::Result<()> {
     env_logger::init();
-    let matches = App::new("binary_to_types")
+    let app = App::new("binary_to_types")
         .arg(Arg::with_name("input_bin").required(true).index(1))
         .arg(Arg::with_name("input_json").required(true).index(2))
         .arg(Arg::with_name("lattice_json").required(true))
         .arg(Arg::with_name("additional_constraints_file").required(true))
         .arg(Arg::with_name("interesting_tids").required(true))
         .arg(
-            Arg::with_name("human_readable_input")
-                .long("human_readable_input")
-                .takes_value(false),
+            Arg::with_name("out")
+                .long("out")
+                .required_unless("serve")
+                .takes_value(true),
         )
         .arg(
-            Arg::with_name("human_readable_output")
-                .long("human_readable_output")
-                .takes_value(false),
+            Arg::with_name("debug_out_dir")
+                .long("debug_out_dir")
+                .required(false)
+                .takes_value(true),
         )
         .arg(
-            Arg::with_name("use_aggressive_shared_returns")
-                .long("use_aggressive_shared_returns")
-                .takes_value(false),
+            Arg::with_name("max_field_offset")
+                .long("max_field_offset")
+                .required(false)
+                .takes_value(true),
         )
         .arg(
-            Arg::with_name("out")
-                .long("out")
-                .required(true)
+            Arg::with_name("offset_stride")
+                .long("offset_stride")
+                .required(false)
                 .takes_value(true),
         )
         .arg(
-            Arg::with_name("debug_out_dir")
-                .long("debug_out_dir")
+            Arg::with_name("max_union_arity")
+                .long("max_union_arity")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rejected_alternatives_out")
+                .long("rejected_alternatives_out")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("union_overflow")
+                .long("union_overflow")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("conflict_strategy")
+                .long("conflict_strategy")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("context_depth")
+                .long("context_depth")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("parameter_names")
+                .long("parameter_names")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("platform")
+                .long("platform")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("freestanding")
+                .long("freestanding")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("schemes_out")
+                .long("schemes_out")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("xrefs_out")
+                .long("xrefs_out")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("register_types_out")
+                .long("register_types_out")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("conflicts_out")
+                .long("conflicts_out")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("diagnostics_out")
+                .long("diagnostics_out")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max_field_offsets")
+                .long("max_field_offsets")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("primitive_table")
+                .long("primitive_table")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ida_script")
+                .long("ida_script")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ida_json")
+                .long("ida_json")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("html_report")
+                .long("html_report")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sketch_out")
+                .long("sketch_out")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("assumptions")
+                .long("assumptions")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("memory_budget")
+                .long("memory_budget")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("check_entailment")
+                .long("check_entailment")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("return_usage_threshold")
+                .long("return_usage_threshold")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("bare-metal")
+                .long("bare-metal")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("interesting_glob")
+                .long("interesting_glob")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("interesting_regex")
+                .long("interesting_regex")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("interesting_range")
+                .long("interesting_range")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("simplification_passes")
+                .long("simplification_passes")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("drop_constraints_on")
+                .long("drop_constraints_on")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("dump_constraints")
+                .long("dump_constraints")
                 .required(false)
                 .takes_value(true),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("checkpoint_dir")
+                .long("checkpoint_dir")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(Arg::with_name("resume").long("resume").takes_value(false))
+        .arg(Arg::with_name("serve").long("serve").takes_value(false))
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .required(false)
+                .takes_value(true),
+        );
+    let matches = with_switches(app).get_matches();
+
+    // Command line flags take precedence over the configuration file
+    let config = matches
+        .value_of("config")
+        .map(BtiConfig::from_file)
+        .transpose()?
+        .unwrap_or_default();
 
     let input_bin = matches.value_of("input_bin").unwrap();
     let input_json = matches.value_of("input_json").unwrap();
     let lattice_json = matches.value_of("lattice_json").unwrap();
     let tids_file = matches.value_of("interesting_tids").unwrap();
-    let out_file = matches.value_of("out").unwrap();
     let additional_constraints_file = matches.value_of("additional_constraints_file").unwrap();
 
     let job_def = JobDefinition {
@@ -71,18 +1020,164 @@ fn main() -> anyhow::Result<()> {
         lattice_json: lattice_json.to_owned(),
         interesting_tids: tids_file.to_owned(),
         additional_constraints_file: additional_constraints_file.to_owned(),
+        bare_metal_layout: matches
+            .value_of("bare-metal")
+            .or_else(|| config.bare_metal.as_deref())
+            .map(|x| x.to_owned()),
     };
 
-    let use_aggressive_shared_returns = matches.is_present("use_aggressive_shared_returns");
+    let human_readable_output = switch(
+        &matches,
+        "human_readable_output",
+        config.human_readable_output.unwrap_or(false),
+    );
+    let compress_output = switch(
+        &matches,
+        "compress_output",
+        config.compress_output.unwrap_or(false),
+    );
 
-    let dbg_dir = matches.value_of("debug_out_dir").map(|x| x.to_owned());
-    let mut if_job = if matches.is_present("human_readable_input") {
-        InferenceJob::parse::<JsonDef>(&job_def, dbg_dir, vec![], use_aggressive_shared_returns)
-    } else {
-        InferenceJob::parse::<ProtobufDef>(&job_def, dbg_dir, vec![], use_aggressive_shared_returns)
-    }?;
+    let dbg_dir = matches
+        .value_of("debug_out_dir")
+        .map(|x| x.to_owned())
+        .or_else(|| config.debug_out_dir.clone());
+    if matches.is_present("diagnostics_out") {
+        diagnostics::start_collecting();
+    }
+    let mut if_job = build_job(&matches, &config, &job_def, dbg_dir.clone(), true)?;
+
+    if matches.is_present("serve") {
+        // Responses are written to stdout, so nothing else may be printed there while serving
+        let stdin = std::io::stdin();
+        let stdout = std::io::stdout();
+        return Server::new(if_job).serve(stdin.lock(), stdout.lock());
+    }
+    let out_file = matches
+        .value_of("out")
+        .expect("the output file is required unless serving");
+
+    if switch(
+        &matches,
+        "stream_output",
+        config.stream_output.unwrap_or(false),
+    ) {
+        if human_readable_output {
+            return Err(anyhow::anyhow!(
+                "--stream_output only supports protobuf output"
+            ));
+        }
+        // Streaming never holds the whole mapping so outputs derived from it are unavailable
+        let needs_mapping = [
+            ("ida_script", matches.is_present("ida_script")),
+            ("ida_json", matches.is_present("ida_json")),
+            ("html_report", matches.is_present("html_report")),
+            (
+                "deterministic",
+                switch(
+                    &matches,
+                    "deterministic",
+                    config.deterministic.unwrap_or(false),
+                ),
+            ),
+            (
+                "callsite_signatures",
+                switch(
+                    &matches,
+                    "callsite_signatures",
+                    config.callsite_signatures.unwrap_or(false),
+                ),
+            ),
+            (
+                "local_variable_types",
+                switch(
+                    &matches,
+                    "local_variable_types",
+                    config.local_variable_types.unwrap_or(false),
+                ),
+            ),
+            (
+                "register_types_out",
+                matches.is_present("register_types_out"),
+            ),
+            ("xrefs_out", matches.is_present("xrefs_out")),
+        ]
+        .iter()
+        .find(|(_, given)| *given)
+        .map(|(flag, _)| *flag);
+        if let Some(flag) = needs_mapping {
+            return Err(anyhow::anyhow!(
+                "--stream_output cannot be combined with --{}",
+                flag
+            ));
+        }
+
+        let grph = if_job.infer_labeled_graph()?;
+        if let Some(sketch_file) = matches.value_of("sketch_out") {
+            let mut out = OutputFile::create(sketch_file, compress_output)?;
+            serde_json::to_writer(&mut out, &grph.to_checkpoint())?;
+            out.finish()?;
+        }
+        write_conflicts(&matches, &if_job, &grph)?;
+        let (out, node_to_type_id) = if_job
+            .stream_labeled_sketch_graph(&grph, OutputFile::create(out_file, compress_output)?)?;
+        out.finish()?;
+        write_schemes(
+            &matches,
+            &if_job,
+            &lowered_term_types(&if_job.get_graph_labeling(&grph), &node_to_type_id),
+        )?;
+        write_diagnostics(&matches, &diagnostics::take())?;
+        return Ok(());
+    }
 
     let (grph, (node_to_type_id, type_id_to_type)) = if_job.infer_ctypes()?;
+    let mapping = if_job.get_graph_labeling(&grph);
+    let pb = build_output_mapping(
+        &node_to_type_id,
+        type_id_to_type.clone(),
+        &mapping,
+        &if_job.get_callsite_labeling(&grph),
+        &if_job.get_linked_structure_labeling(&grph),
+        &if_job.get_structure_name_labeling(&grph),
+        &if_job.get_field_name_table(&grph)?,
+        &if_job.get_stack_frame_types(&grph, &node_to_type_id)?,
+        &if_job.get_stack_frame_slots(&grph, &node_to_type_id)?,
+        !human_readable_output,
+    )?;
+    // Taken before the reference run so its warnings are not counted twice
+    let run_diagnostics = diagnostics::take();
+
+    if switch(
+        &matches,
+        "deterministic",
+        config.deterministic.unwrap_or(false),
+    ) {
+        // The reference run does not log or checkpoint so that it cannot observe state left by the first run
+        let mut reference_job = build_job(&matches, &config, &job_def, None, false)?;
+        let (reference_grph, (reference_node_to_type_id, reference_type_id_to_type)) =
+            reference_job.infer_ctypes()?;
+        let reference_pb = build_output_mapping(
+            &reference_node_to_type_id,
+            reference_type_id_to_type,
+            &reference_job.get_graph_labeling(&reference_grph),
+            &reference_job.get_callsite_labeling(&reference_grph),
+            &reference_job.get_linked_structure_labeling(&reference_grph),
+            &reference_job.get_structure_name_labeling(&reference_grph),
+            &reference_job.get_field_name_table(&reference_grph)?,
+            &reference_job.get_stack_frame_types(&reference_grph, &reference_node_to_type_id)?,
+            &reference_job.get_stack_frame_slots(&reference_grph, &reference_node_to_type_id)?,
+            !human_readable_output,
+        )?;
+
+        let (fst, snd) = (output_hash(&pb), output_hash(&reference_pb));
+        if fst != snd {
+            return Err(anyhow::anyhow!(
+                "Output is not deterministic: two runs produced mappings with hashes {:016x} and {:016x}",
+                fst,
+                snd
+            ));
+        }
+    }
 
     let mapped_graph = grph.get_graph().get_graph().map(
         |idx, nd_elem| {
@@ -101,54 +1196,141 @@ fn main() -> anyhow::Result<()> {
         |_e, fld_label| format!("{}", fld_label),
     );
 
-    if let Some(debug_graph_file) = matches.value_of("debug_out_dir") {
-        let mut pbuf = PathBuf::new();
-        pbuf.push(debug_graph_file);
-
-        let mut fl = std::fs::File::create(immutably_push(&pbuf, "debug_graph.dot"))?;
+    if let Some(debug_graph_file) = &dbg_dir {
+        let mut fl = create_debug_file(
+            debug_graph_file,
+            "debug_graph.dot",
+            compress_debug(&matches, &config),
+        )?;
         write!(&mut fl, "{}", Dot::new(&mapped_graph))?;
+        fl.finish()?;
     }
 
-    let mut out_file = std::fs::File::create(out_file)?;
-    let mut pb = binary_type_inference::lowering::convert_mapping_to_profobuf(
-        type_id_to_type,
-        &node_to_type_id,
+    let structural_names = switch(
+        &matches,
+        "structural_names",
+        config.structural_names.unwrap_or(false),
     );
-    if !matches.is_present("human_readable_output") {
-        let mapping = if_job.get_graph_labeling(&grph);
-        for (k, v) in mapping {
-            let tid = binary_type_inference::ctypes::Tid {
-                name: k.get_str_repr().to_owned(),
-                address: k.address.clone(),
-            };
+    if matches.is_present("ida_script") || matches.is_present("ida_json") {
+        let ida_export = binary_type_inference::lowering::ida::build_ida_export(
+            &type_id_to_type,
+            &node_to_type_id,
+            &mapping,
+            structural_names,
+        );
 
-            if let Some(tgt_type_id) = node_to_type_id.get(&v) {
-                let tid_to_node_idx = binary_type_inference::ctypes::TidToTypeId {
-                    type_id: Some(binary_type_inference::lowering::convert_typeid(
-                        *tgt_type_id,
-                    )),
-                    tid: Some(tid),
-                };
+        if let Some(script_file) = matches.value_of("ida_script") {
+            std::fs::write(script_file, ida_export.to_idapython())?;
+        }
 
-                pb.type_variable_repr_nodes.push(tid_to_node_idx);
-            }
+        if let Some(json_file) = matches.value_of("ida_json") {
+            serde_json::to_writer(std::fs::File::create(json_file)?, &ida_export)?;
         }
+    }
+
+    if let Some(report_file) = matches.value_of("html_report") {
+        std::fs::write(
+            report_file,
+            binary_type_inference::lowering::html::build_html_report(
+                &grph,
+                &type_id_to_type,
+                &node_to_type_id,
+                &mapping,
+                structural_names,
+            ),
+        )?;
+    }
+
+    if let Some(sketch_file) = matches.value_of("sketch_out") {
+        let mut out = OutputFile::create(sketch_file, compress_output)?;
+        serde_json::to_writer(&mut out, &grph.to_checkpoint())?;
+        out.finish()?;
+    }
+
+    write_conflicts(&matches, &if_job, &grph)?;
+    write_rejected_alternatives(&matches, &if_job, &grph)?;
+    write_register_bindings(&matches, &if_job, &grph, &node_to_type_id)?;
+    write_xrefs(
+        &matches,
+        &if_job,
+        &grph,
+        &node_to_type_id,
+        &type_id_to_type,
+        human_readable_output,
+    )?;
+    write_diagnostics(&matches, &run_diagnostics)?;
+    write_schemes(
+        &matches,
+        &if_job,
+        &lowered_term_types(&mapping, &node_to_type_id),
+    )?;
 
+    let mut out_file = OutputFile::create(out_file, compress_output)?;
+    if !human_readable_output {
         let mut buf = Vec::new();
         pb.encode(&mut buf)?;
         out_file.write_all(&buf)?;
     } else {
-        serde_json::to_writer(out_file, &pb)?;
+        serde_json::to_writer(&mut out_file, &pb)?;
     }
+    out_file.finish()?;
 
-    if let Some(debug_graph_file) = matches.value_of("debug_out_dir") {
-        let mut pbuf = PathBuf::new();
-        pbuf.push(debug_graph_file);
-
-        let debug_ctype_fl =
-            std::fs::File::create(immutably_push(&pbuf, "debug_ctype_output.json"))?;
-        serde_json::to_writer(debug_ctype_fl, &pb)?;
+    if let Some(debug_graph_file) = &dbg_dir {
+        let mut debug_ctype_fl = create_debug_file(
+            debug_graph_file,
+            "debug_ctype_output.json",
+            compress_debug(&matches, &config),
+        )?;
+        serde_json::to_writer(&mut debug_ctype_fl, &pb)?;
+        debug_ctype_fl.finish()?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use clap::App;
+
+    use super::{switch, with_switches};
+
+    #[test]
+    fn switches_override_the_config_both_ways() {
+        let matches =
+            |args: &[&str]| with_switches(App::new("binary_to_types")).get_matches_from(args);
+
+        // without a flag the config decides
+        let none = matches(&["binary_to_types"]);
+        assert!(switch(&none, "tls", true));
+        assert!(!switch(&none, "tls", false));
+
+        // either flag overrides the config, and the last one given wins
+        assert!(!switch(
+            &matches(&["binary_to_types", "--no_tls"]),
+            "tls",
+            true
+        ));
+        assert!(switch(
+            &matches(&["binary_to_types", "--tls"]),
+            "tls",
+            false
+        ));
+        assert!(switch(
+            &matches(&["binary_to_types", "--no_tls", "--tls"]),
+            "tls",
+            false
+        ));
+        assert!(!switch(
+            &matches(&["binary_to_types", "--tls", "--no_tls"]),
+            "tls",
+            true
+        ));
+
+        // settings that default to on are turned off the same way
+        assert!(!switch(
+            &matches(&["binary_to_types", "--no_unions"]),
+            "unions",
+            true
+        ));
+    }
+}
@@ -0,0 +1,121 @@
+use binary_type_inference::{
+    ghidra::{self, GhidraHeadless},
+    inference_job::{InferenceJob, JsonDef},
+};
+use clap::{App, Arg, SubCommand};
+
+use std::path::{Path, PathBuf};
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let matches = App::new("bti")
+        .subcommand(
+            SubCommand::with_name("analyze")
+                .arg(Arg::with_name("input_bin").required(true).index(1))
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .required(false)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("lattice_json")
+                        .long("lattice_json")
+                        .required(false)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("ghidra_install_dir")
+                        .long("ghidra_install_dir")
+                        .required(false)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("script_dir")
+                        .long("script_dir")
+                        .required(false)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("work_dir")
+                        .long("work_dir")
+                        .required(false)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("bare-metal")
+                        .long("bare-metal")
+                        .required(false)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("debug_out_dir")
+                        .long("debug_out_dir")
+                        .required(false)
+                        .takes_value(true),
+                ),
+        )
+        .get_matches();
+
+    let matches = match matches.subcommand_matches("analyze") {
+        Some(matches) => matches,
+        None => {
+            return Err(anyhow::anyhow!(
+                "expected a subcommand, ie. bti analyze ./binary"
+            ))
+        }
+    };
+
+    let input_bin = Path::new(matches.value_of("input_bin").unwrap());
+    let out_file = matches
+        .value_of("out")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| input_bin.with_extension("types.json"));
+
+    let ghidra = match matches.value_of("ghidra_install_dir") {
+        Some(install_dir) => GhidraHeadless::new(
+            Path::new(install_dir),
+            Path::new(
+                matches
+                    .value_of("script_dir")
+                    .unwrap_or(ghidra::BUNDLED_SCRIPT_DIR),
+            ),
+        ),
+        None => GhidraHeadless::from_env()?,
+    };
+
+    // intermediate files are kept if a work directory is given, otherwise they are removed on exit
+    let temp_dir = tempdir::TempDir::new("bti")?;
+    let work_dir = matches
+        .value_of("work_dir")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| temp_dir.path().to_path_buf());
+    std::fs::create_dir_all(&work_dir)?;
+
+    let job_def = ghidra::prepare_job(
+        &ghidra,
+        input_bin,
+        matches.value_of("lattice_json").map(Path::new),
+        &work_dir,
+        matches.value_of("bare-metal").map(Path::new),
+    )?;
+
+    let mut if_job = InferenceJob::parse::<JsonDef>(
+        &job_def,
+        matches.value_of("debug_out_dir").map(|x| x.to_owned()),
+        vec![],
+        false,
+    )?;
+
+    let (grph, (node_to_type_id, type_id_to_type)) = if_job.infer_ctypes()?;
+    let mapping = if_job.get_graph_labeling(&grph);
+    let pb = binary_type_inference::lowering::convert_mapping_to_profobuf(
+        type_id_to_type,
+        &node_to_type_id,
+        &mapping,
+    )?;
+
+    serde_json::to_writer(std::fs::File::create(&out_file)?, &pb)?;
+    log::info!("Wrote types to {}", out_file.display());
+    Ok(())
+}
@@ -0,0 +1,437 @@
+use binary_type_inference::{
+    diagnostics::{self, DiagnosticKind, Diagnostics},
+    ghidra,
+    inference_job::{InferenceJob, JsonDef},
+    util,
+};
+use clap::{App, Arg};
+use serde::Deserialize;
+
+use std::{
+    collections::BTreeSet,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    time::Instant,
+};
+
+/// A binary of the batch and its Pcode json IR.
+#[derive(Deserialize)]
+struct BatchEntry {
+    binary: PathBuf,
+    ir_json: PathBuf,
+}
+
+impl BatchEntry {
+    /// The name of the binary, which names the directory of its outputs.
+    fn name(&self) -> String {
+        self.binary
+            .file_name()
+            .map(|nm| nm.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.binary.to_string_lossy().into_owned())
+    }
+}
+
+/// The statistics of a binary that was analyzed.
+struct BinaryStats {
+    subprocedures: usize,
+    sketch_nodes: usize,
+    lowered_types: usize,
+}
+
+/// The outcome of analyzing one binary of the batch.
+struct BatchResult {
+    name: String,
+    seconds: f64,
+    outcome: anyhow::Result<BinaryStats>,
+    diagnostics: Diagnostics,
+}
+
+/// Pairs each binary in a directory with the IR next to it, named after the binary with a json extension appended
+/// (ie. `ls` and `ls.json`).
+fn entries_from_directory(dir: &Path) -> anyhow::Result<Vec<BatchEntry>> {
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.sort();
+
+    Ok(paths
+        .into_iter()
+        .filter(|pth| pth.is_file() && pth.extension().map_or(true, |ext| ext != "json"))
+        .filter_map(|binary| {
+            let mut ir_json = binary.clone().into_os_string();
+            ir_json.push(".json");
+            let ir_json = PathBuf::from(ir_json);
+            if ir_json.is_file() {
+                Some(BatchEntry { binary, ir_json })
+            } else {
+                log::warn!("Skipping {} which has no IR", binary.display());
+                None
+            }
+        })
+        .collect())
+}
+
+/// Reads a csv manifest with a `binary` and an `ir_json` column. Relative paths are relative to the manifest.
+fn entries_from_manifest(manifest: &Path) -> anyhow::Result<Vec<BatchEntry>> {
+    let base = manifest.parent().unwrap_or_else(|| Path::new("."));
+    csv::Reader::from_path(manifest)?
+        .deserialize::<BatchEntry>()
+        .map(|entry| {
+            let entry = entry?;
+            Ok(BatchEntry {
+                binary: base.join(entry.binary),
+                ir_json: base.join(entry.ir_json),
+            })
+        })
+        .collect()
+}
+
+fn analyze(
+    entry: &BatchEntry,
+    out_dir: &Path,
+    lattice_json: Option<&Path>,
+    bare_metal_layout: Option<&Path>,
+) -> anyhow::Result<BinaryStats> {
+    std::fs::create_dir_all(out_dir)?;
+    let job_def = ghidra::prepare_lifted_job(
+        &entry.binary,
+        &entry.ir_json,
+        lattice_json,
+        out_dir,
+        bare_metal_layout,
+    )?;
+
+    let mut if_job = InferenceJob::parse::<JsonDef>(&job_def, None, vec![], false)?;
+    let (grph, (node_to_type_id, type_id_to_type)) = if_job.infer_ctypes()?;
+    let stats = BinaryStats {
+        subprocedures: if_job.get_interesting_tids().len(),
+        sketch_nodes: grph.get_graph().get_graph().node_count(),
+        lowered_types: type_id_to_type.len(),
+    };
+
+    let mapping = if_job.get_graph_labeling(&grph);
+    let pb = binary_type_inference::lowering::convert_mapping_to_profobuf(
+        type_id_to_type,
+        &node_to_type_id,
+        &mapping,
+    )?;
+    serde_json::to_writer(std::fs::File::create(out_dir.join("types.json"))?, &pb)?;
+    Ok(stats)
+}
+
+/// Analyzes an entry on the current thread, turning a panic into an error so the rest of the batch keeps going.
+fn run_entry(
+    entry: &BatchEntry,
+    out_root: &Path,
+    lattice_json: Option<&Path>,
+    bare_metal_layout: Option<&Path>,
+) -> BatchResult {
+    let name = entry.name();
+    let out_dir = out_root.join(&name);
+    let start = Instant::now();
+
+    diagnostics::start_collecting();
+    let outcome = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        analyze(entry, &out_dir, lattice_json, bare_metal_layout)
+    }))
+    .unwrap_or_else(|payload| {
+        Err(anyhow::anyhow!(
+            "panicked: {}",
+            util::panic_message(payload.as_ref())
+        ))
+    });
+    let diagnostics = diagnostics::take();
+
+    let written = std::fs::create_dir_all(&out_dir)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| {
+            serde_json::to_writer_pretty(
+                std::fs::File::create(out_dir.join("diagnostics.json"))?,
+                &diagnostics,
+            )
+            .map_err(anyhow::Error::from)
+        });
+    if let Err(err) = written {
+        log::warn!("Could not write the diagnostics of {}: {}", name, err);
+    }
+
+    BatchResult {
+        name,
+        seconds: start.elapsed().as_secs_f64(),
+        outcome,
+        diagnostics,
+    }
+}
+
+fn write_summary(summary_file: &Path, results: &[BatchResult]) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_path(summary_file)?;
+    let mut header = vec![
+        "binary",
+        "status",
+        "error",
+        "seconds",
+        "subprocedures",
+        "sketch_nodes",
+        "lowered_types",
+    ];
+    header.extend(DiagnosticKind::ALL.iter().map(DiagnosticKind::name));
+    writer.write_record(&header)?;
+
+    for result in results {
+        let (status, error, stats) = match &result.outcome {
+            Ok(stats) => ("ok", String::new(), Some(stats)),
+            Err(err) => ("failed", format!("{:#}", err), None),
+        };
+        let stat = |get: fn(&BinaryStats) -> usize| {
+            stats
+                .map(|stats| get(stats).to_string())
+                .unwrap_or_default()
+        };
+
+        let mut record = vec![
+            result.name.clone(),
+            status.to_owned(),
+            error,
+            format!("{:.3}", result.seconds),
+            stat(|stats| stats.subprocedures),
+            stat(|stats| stats.sketch_nodes),
+            stat(|stats| stats.lowered_types),
+        ];
+        record.extend(DiagnosticKind::ALL.iter().map(|kind| {
+            result
+                .diagnostics
+                .counts
+                .get(kind)
+                .cloned()
+                .unwrap_or(0)
+                .to_string()
+        }));
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let matches = App::new("bti_batch")
+        .arg(Arg::with_name("input").required(true).index(1))
+        .arg(
+            Arg::with_name("out_dir")
+                .long("out_dir")
+                .required(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .long("jobs")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("lattice_json")
+                .long("lattice_json")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("bare-metal")
+                .long("bare-metal")
+                .required(false)
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let input = Path::new(matches.value_of("input").unwrap());
+    let entries = if input.is_dir() {
+        entries_from_directory(input)?
+    } else {
+        entries_from_manifest(input)?
+    };
+
+    let mut names = BTreeSet::new();
+    if let Some(duplicate) = entries.iter().find(|entry| !names.insert(entry.name())) {
+        return Err(anyhow::anyhow!(
+            "Two binaries of the batch are named {}, their outputs would collide",
+            duplicate.name()
+        ));
+    }
+
+    let out_root = PathBuf::from(matches.value_of("out_dir").unwrap());
+    std::fs::create_dir_all(&out_root)?;
+    let jobs = matches
+        .value_of("jobs")
+        .map(str::parse::<usize>)
+        .transpose()?
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        })
+        .max(1);
+    let lattice_json = matches.value_of("lattice_json").map(PathBuf::from);
+    let bare_metal_layout = matches.value_of("bare-metal").map(PathBuf::from);
+
+    let total = entries.len();
+    let queue = Arc::new(Mutex::new(entries.into_iter().enumerate()));
+    let (sender, receiver) = mpsc::channel();
+    let workers = (0..jobs.min(total))
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            let sender = sender.clone();
+            let out_root = out_root.clone();
+            let lattice_json = lattice_json.clone();
+            let bare_metal_layout = bare_metal_layout.clone();
+            std::thread::spawn(move || loop {
+                // the lock is released before the entry is analyzed
+                let next = queue.lock().unwrap().next();
+                let (index, entry) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let result = run_entry(
+                    &entry,
+                    &out_root,
+                    lattice_json.as_deref(),
+                    bare_metal_layout.as_deref(),
+                );
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(sender);
+
+    let mut results = Vec::with_capacity(total);
+    for (index, result) in receiver {
+        match &result.outcome {
+            Ok(_) => log::info!("[{}/{}] Analyzed {}", results.len() + 1, total, result.name),
+            Err(err) => log::error!(
+                "[{}/{}] Failed to analyze {}: {:#}",
+                results.len() + 1,
+                total,
+                result.name,
+                err
+            ),
+        }
+        results.push((index, result));
+    }
+    for worker in workers {
+        worker
+            .join()
+            .map_err(|_| anyhow::anyhow!("a batch worker panicked"))?;
+    }
+
+    // the summary follows the order of the inputs rather than the order the workers finished in
+    results.sort_by_key(|(index, _)| *index);
+    let results = results
+        .into_iter()
+        .map(|(_, result)| result)
+        .collect::<Vec<_>>();
+    write_summary(&out_root.join("summary.csv"), &results)?;
+
+    let failed = results.iter().filter(|res| res.outcome.is_err()).count();
+    log::info!(
+        "Analyzed {} of {} binaries, {} failed",
+        total - failed,
+        total,
+        failed
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use super::{entries_from_directory, entries_from_manifest, run_entry, write_summary};
+
+    fn test_data(pth: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test_data")
+            .join(pth)
+    }
+
+    #[test]
+    fn batches_continue_past_failed_binaries() {
+        let dir = tempdir::TempDir::new("batch").expect("should create a temporary directory");
+        let input = dir.path().join("input");
+        std::fs::create_dir_all(&input).unwrap();
+        std::fs::copy(
+            test_data("tagged_union/tagged_union"),
+            input.join("tagged_union"),
+        )
+        .unwrap();
+        std::fs::copy(
+            test_data("tagged_union/ir.json"),
+            input.join("tagged_union.json"),
+        )
+        .unwrap();
+        std::fs::write(input.join("broken"), b"\x7fELF").unwrap();
+        std::fs::write(input.join("broken.json"), "not an ir").unwrap();
+        // a binary without an IR next to it is skipped
+        std::fs::write(input.join("unlifted"), b"\x7fELF").unwrap();
+
+        let entries = entries_from_directory(&input).unwrap();
+        assert_eq!(
+            entries.iter().map(|entry| entry.name()).collect::<Vec<_>>(),
+            vec!["broken".to_owned(), "tagged_union".to_owned()]
+        );
+        assert_eq!(entries[1].ir_json, input.join("tagged_union.json"));
+
+        let manifest = input.join("manifest.csv");
+        std::fs::write(
+            &manifest,
+            "binary,ir_json\nbroken,broken.json\ntagged_union,tagged_union.json\n",
+        )
+        .unwrap();
+        let from_manifest = entries_from_manifest(&manifest).unwrap();
+        assert_eq!(
+            from_manifest
+                .iter()
+                .map(|entry| (entry.binary.clone(), entry.ir_json.clone()))
+                .collect::<Vec<_>>(),
+            entries
+                .iter()
+                .map(|entry| (entry.binary.clone(), entry.ir_json.clone()))
+                .collect::<Vec<_>>()
+        );
+
+        let out_root = dir.path().join("out");
+        let results = entries
+            .iter()
+            .map(|entry| run_entry(entry, &out_root, None, None))
+            .collect::<Vec<_>>();
+        assert!(results[0].outcome.is_err());
+        assert!(results[1].outcome.is_ok());
+        for name in ["broken", "tagged_union"].iter() {
+            assert!(out_root.join(name).join("diagnostics.json").is_file());
+        }
+        assert!(out_root.join("tagged_union").join("types.json").is_file());
+
+        let summary = out_root.join("summary.csv");
+        write_summary(&summary, &results).unwrap();
+        let mut reader = csv::Reader::from_path(&summary).unwrap();
+        let header = reader.headers().unwrap().clone();
+        assert_eq!(&header[0], "binary");
+        assert!(header.iter().any(|column| column == "solve_failure"));
+        let rows = reader
+            .records()
+            .map(|record| record.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows.iter()
+                .map(|row| (row[0].to_owned(), row[1].to_owned()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("broken".to_owned(), "failed".to_owned()),
+                ("tagged_union".to_owned(), "ok".to_owned())
+            ]
+        );
+        assert!(!rows[0][2].is_empty());
+        assert!(rows[0][4].is_empty());
+        assert!(rows[1][4].parse::<usize>().unwrap() > 0);
+    }
+}
@@ -0,0 +1,507 @@
+use binary_type_inference::{
+    constraint_generation::tid_to_tvar,
+    constraints::{DerivedTypeVar, FieldLabel},
+    inference_job::InferenceJob,
+    lowering::{
+        mapping::{decode_mapping, TypeMapping},
+        naming::{TypeNamer, UNKNOWN_TYPE_NAME},
+        CType, TypeId,
+    },
+    solver::{
+        type_lattice::CustomLatticeElement,
+        type_sketch::{LatticeBounds, SketchGraph, SketchGraphCheckpoint},
+    },
+    util::open_input,
+};
+use clap::{App, Arg};
+use cwe_checker_lib::intermediate_representation::Tid;
+use petgraph::{graph::NodeIndex, visit::EdgeRef, EdgeDirection::Outgoing};
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    io::{BufRead, Read, Write},
+};
+
+type Sketches = SketchGraph<LatticeBounds<CustomLatticeElement>>;
+
+/// A saved solve: the sketch graph written with --sketch_out and the protobuf mapping written with --out.
+struct Session {
+    sketches: Sketches,
+    mapping: TypeMapping,
+    type_to_node: BTreeMap<TypeId, NodeIndex>,
+    no_aliases: BTreeMap<NodeIndex, TypeId>,
+}
+
+fn term_node(sketches: &Sketches, term: &str) -> Option<NodeIndex> {
+    sketches.get_node_index_for_variable(&DerivedTypeVar::new(tid_to_tvar(&Tid::create(
+        term.to_owned(),
+        String::new(),
+    ))))
+}
+
+fn node_target(
+    sketches: &Sketches,
+    nd: NodeIndex,
+    pred: impl Fn(&FieldLabel) -> bool,
+) -> Option<NodeIndex> {
+    sketches
+        .get_graph()
+        .get_graph()
+        .edges_directed(nd, Outgoing)
+        .find(|e| pred(e.weight()))
+        .map(|e| e.target())
+}
+
+// The mapping does not record which sketch node a type was lowered from, so types are matched to nodes by walking
+// each term's type and sketch together, following the capability each part of a type was lowered from.
+fn link_types_to_nodes(sketches: &Sketches, mapping: &TypeMapping) -> BTreeMap<TypeId, NodeIndex> {
+    let mut linked = BTreeMap::new();
+    let mut worklist = mapping
+        .term_types
+        .iter()
+        .filter_map(|(term, ty)| term_node(sketches, term).map(|nd| (*ty, nd)))
+        .collect::<VecDeque<_>>();
+
+    while let Some((ty, nd)) = worklist.pop_front() {
+        if linked.contains_key(&ty) {
+            continue;
+        }
+        linked.insert(ty, nd);
+
+        let children: Vec<(TypeId, Option<NodeIndex>)> = match mapping.types.get(&ty) {
+            Some(CType::Pointer { target, .. }) => vec![(
+                *target,
+                node_target(sketches, nd, |lbl| {
+                    matches!(lbl, FieldLabel::Load | FieldLabel::Store)
+                }),
+            )],
+            Some(CType::Structure(fields)) => fields
+                .iter()
+                .map(|fld| {
+                    (
+                        fld.type_index(),
+                        node_target(sketches, nd, |lbl| {
+                            matches!(lbl, FieldLabel::Field(f) if f.offset == fld.byte_offset() as i64)
+                        }),
+                    )
+                })
+                .collect(),
+            Some(CType::Function { params, return_ty }) => params
+                .iter()
+                .map(|param| {
+                    (
+                        param.type_index(),
+                        node_target(sketches, nd, |lbl| {
+                            matches!(lbl, FieldLabel::In(idx) if *idx == param.index())
+                        }),
+                    )
+                })
+                .chain(return_ty.iter().map(|ret| {
+                    (
+                        *ret,
+                        node_target(sketches, nd, |lbl| matches!(lbl, FieldLabel::Out(0))),
+                    )
+                }))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        worklist.extend(
+            children
+                .into_iter()
+                .filter_map(|(child, child_nd)| child_nd.map(|child_nd| (child, child_nd))),
+        );
+    }
+
+    linked
+}
+
+fn load_mapping(pth: &str) -> anyhow::Result<TypeMapping> {
+    let mut bytes = Vec::new();
+    open_input(pth)?.read_to_end(&mut bytes)?;
+    decode_mapping(&bytes)
+}
+
+impl Session {
+    fn load(sketch_file: &str, types_file: &str, lattice_json: &str) -> anyhow::Result<Session> {
+        let (lattice, _) = InferenceJob::parse_lattice_json(lattice_json, vec![])?;
+        let checkpoint: SketchGraphCheckpoint = serde_json::from_reader(open_input(sketch_file)?)?;
+        let sketches = SketchGraph::from_checkpoint(&checkpoint, &lattice)?;
+        let mapping = load_mapping(types_file)?;
+        Ok(Session {
+            type_to_node: link_types_to_nodes(&sketches, &mapping),
+            sketches,
+            mapping,
+            no_aliases: BTreeMap::new(),
+        })
+    }
+
+    fn namer(&self) -> TypeNamer {
+        TypeNamer::new(&self.mapping.types, &self.no_aliases)
+    }
+
+    fn parse_type(&self, arg: Option<&str>) -> anyhow::Result<TypeId> {
+        let ty = arg
+            .ok_or_else(|| anyhow::anyhow!("expected a type id"))?
+            .parse::<TypeId>()?;
+        if !self.mapping.types.contains_key(&ty) {
+            return Err(anyhow::anyhow!("no type with id {}", ty));
+        }
+        Ok(ty)
+    }
+
+    fn describe(&self, ty: TypeId) -> String {
+        format!("{} ({})", ty, self.namer().type_name(ty))
+    }
+
+    fn type_of_term(&self, term: Option<&str>, out: &mut impl Write) -> anyhow::Result<()> {
+        let term = term.ok_or_else(|| anyhow::anyhow!("expected a term, ie. type sub_401000"))?;
+        let ty = self
+            .mapping
+            .term_types
+            .get(term)
+            .ok_or_else(|| anyhow::anyhow!("no type for {}", term))?;
+        match self
+            .namer()
+            .function_prototype(*ty, term, UNKNOWN_TYPE_NAME)
+        {
+            Some(proto) => writeln!(out, "{}: {}\n{}", term, ty, proto)?,
+            None => writeln!(out, "{}: {}", term, self.describe(*ty))?,
+        }
+        Ok(())
+    }
+
+    fn fields(&self, ty: TypeId, out: &mut impl Write) -> anyhow::Result<()> {
+        let ct = self
+            .mapping
+            .types
+            .get(&ty)
+            .ok_or_else(|| anyhow::anyhow!("no type with id {}", ty))?;
+        match ct {
+            CType::Pointer { target, .. } => {
+                writeln!(out, "{} points to {}", ty, self.describe(*target))?;
+                // only one level of pointers is followed so that recursive types terminate
+                if !matches!(self.mapping.types.get(target), Some(CType::Pointer { .. })) {
+                    self.fields(*target, out)?;
+                }
+            }
+            CType::Structure(fields) => {
+                for fld in fields.iter() {
+                    writeln!(
+                        out,
+                        "  +0x{:x} ({} bits): {}",
+                        fld.byte_offset(),
+                        fld.bit_size(),
+                        self.describe(fld.type_index())
+                    )?;
+                }
+            }
+            CType::Union(members) => {
+                for member in members.iter() {
+                    writeln!(out, "  | {}", self.describe(*member))?;
+                }
+            }
+            CType::Function { params, return_ty } => {
+                for param in params.iter() {
+                    writeln!(
+                        out,
+                        "  in_{}{}: {}",
+                        param.index(),
+                        if param.is_const() { " (const)" } else { "" },
+                        self.describe(param.type_index())
+                    )?;
+                }
+                if let Some(ret) = return_ty {
+                    writeln!(out, "  out: {}", self.describe(*ret))?;
+                }
+            }
+            CType::Array { element, count } => {
+                writeln!(out, "  [{}] of {}", count, self.describe(*element))?
+            }
+            CType::Primitive(_) | CType::Alias(_) => {
+                writeln!(out, "{} has no fields", self.describe(ty))?
+            }
+        }
+        Ok(())
+    }
+
+    fn explain(&self, ty: TypeId, out: &mut impl Write) -> anyhow::Result<()> {
+        writeln!(out, "{}", self.describe(ty))?;
+        let terms = self
+            .mapping
+            .term_types
+            .iter()
+            .filter(|(_, term_ty)| **term_ty == ty)
+            .map(|(term, _)| term.as_str())
+            .collect::<Vec<_>>();
+        if !terms.is_empty() {
+            writeln!(out, "type of: {}", terms.join(", "))?;
+        }
+
+        let nd = match self.type_to_node.get(&ty) {
+            Some(nd) => *nd,
+            None => {
+                writeln!(out, "not reachable from a term in the sketches")?;
+                return Ok(());
+            }
+        };
+        let grph = self.sketches.get_graph();
+        writeln!(out, "sketch node {}: {}", nd.index(), grph.get_graph()[nd])?;
+        for repr in grph.get_group_for_node(nd).iter() {
+            writeln!(out, "  represents {}", repr)?;
+        }
+        for e in grph.get_graph().edges_directed(nd, Outgoing) {
+            writeln!(
+                out,
+                "  .{} -> node {} {}",
+                e.weight(),
+                e.target().index(),
+                grph.get_graph()[e.target()]
+            )?;
+        }
+        Ok(())
+    }
+
+    // Whether ty is reached from root only through pointers and arrays, ie. root is a way of passing ty
+    fn passes_type(&self, root: TypeId, ty: TypeId) -> bool {
+        let mut seen = BTreeSet::new();
+        let mut curr = root;
+        while seen.insert(curr) {
+            if curr == ty {
+                return true;
+            }
+            curr = match self.mapping.types.get(&curr) {
+                Some(CType::Pointer { target, .. }) => *target,
+                Some(CType::Array { element, .. }) => *element,
+                _ => return false,
+            };
+        }
+        false
+    }
+
+    fn callers_of_type(&self, ty: TypeId, out: &mut impl Write) -> anyhow::Result<()> {
+        for (term, term_ty) in self.mapping.term_types.iter() {
+            if let Some(CType::Function { params, return_ty }) = self.mapping.types.get(term_ty) {
+                for param in params.iter() {
+                    if self.passes_type(param.type_index(), ty) {
+                        writeln!(
+                            out,
+                            "{} in_{}: {}",
+                            term,
+                            param.index(),
+                            self.describe(param.type_index())
+                        )?;
+                    }
+                }
+                if let Some(ret) = return_ty {
+                    if self.passes_type(*ret, ty) {
+                        writeln!(out, "{} out: {}", term, self.describe(*ret))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn decl(&self, ty: TypeId, name: Option<&str>, out: &mut impl Write) -> anyhow::Result<()> {
+        let ct = self
+            .mapping
+            .types
+            .get(&ty)
+            .ok_or_else(|| anyhow::anyhow!("no type with id {}", ty))?;
+        writeln!(
+            out,
+            "{}",
+            ct.to_c_decl(&self.mapping, name.unwrap_or(&format!("value_{}", ty)))
+        )?;
+        Ok(())
+    }
+
+    fn paths(&self, term: Option<&str>, out: &mut impl Write) -> anyhow::Result<()> {
+        let term = term.ok_or_else(|| anyhow::anyhow!("expected a term, ie. paths sub_401000"))?;
+        let dtv = DerivedTypeVar::new(tid_to_tvar(&Tid::create(term.to_owned(), String::new())));
+        write!(out, "{}", self.sketches.paths_of(&dtv))?;
+        Ok(())
+    }
+
+    fn run_command(&self, line: &str, out: &mut impl Write) -> anyhow::Result<bool> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            None => (),
+            Some("type") => self.type_of_term(words.next(), out)?,
+            Some("fields") => self.fields(self.parse_type(words.next())?, out)?,
+            Some("decl") => {
+                let ty = self.parse_type(words.next())?;
+                self.decl(ty, words.next(), out)?
+            }
+            Some("paths") => self.paths(words.next(), out)?,
+            Some("explain") => self.explain(self.parse_type(words.next())?, out)?,
+            Some("callers-of-type") => {
+                self.callers_of_type(self.parse_type(words.next())?, out)?
+            }
+            Some("help") => writeln!(
+                out,
+                "type <term>             the type of a labeled term\n\
+                 fields <typeid>         the fields, members, or parameters of a type\n\
+                 explain <typeid>        the terms and sketch node a type was lowered from\n\
+                 decl <typeid> [name]    a C declaration of a value of the type\n\
+                 paths <term>            the label paths of a term's sketch with their bounds\n\
+                 callers-of-type <typeid> functions taking or returning the type (or a pointer to it)\n\
+                 quit                    exit"
+            )?,
+            Some("quit") | Some("exit") => return Ok(false),
+            Some(cmd) => writeln!(out, "unknown command {}, try help", cmd)?,
+        }
+        Ok(true)
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let matches = App::new("bti_repl")
+        .arg(Arg::with_name("sketches").required(true).index(1))
+        .arg(Arg::with_name("types").required(true).index(2))
+        .arg(
+            Arg::with_name("lattice_json")
+                .long("lattice_json")
+                .required(true)
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let session = Session::load(
+        matches.value_of("sketches").unwrap(),
+        matches.value_of("types").unwrap(),
+        matches.value_of("lattice_json").unwrap(),
+    )?;
+
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    write!(out, "bti> ")?;
+    out.flush()?;
+    for line in stdin.lock().lines() {
+        match session.run_command(&line?, &mut out) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(err) => writeln!(out, "error: {}", err)?,
+        }
+        write!(out, "bti> ")?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, HashMap};
+
+    use binary_type_inference::{
+        constraints::{DerivedTypeVar, TypeVariable},
+        lowering::{
+            convert_mapping_to_profobuf, mapping::read_mapping, CType, LoweringContext,
+            LoweringOptions, TypeId,
+        },
+        solver::{
+            type_lattice::LatticeDefinition,
+            type_sketch::{identity_element, SketchGraph},
+        },
+    };
+    use cwe_checker_lib::intermediate_representation::Tid;
+    use petgraph::graph::NodeIndex;
+
+    use super::{link_types_to_nodes, Session};
+
+    // sub_a takes a pointer to a structure with an int at offset 0, lowered and read back as a saved solve would be
+    fn session() -> Session {
+        let lattice = LatticeDefinition::new(
+            vec![
+                ("int".to_owned(), "top".to_owned()),
+                ("bottom".to_owned(), "int".to_owned()),
+            ],
+            "top".to_owned(),
+            "bottom".to_owned(),
+            "int".to_owned(),
+        )
+        .generate_lattice();
+        let checkpoint = serde_json::from_str(&format!(
+            r#"{{"default_label":["bottom","top"],"nodes":[["bottom","top"],["bottom","top"],["bottom","top"],["bottom","int"]],"edges":[[0,1,{{"In":0}}],[1,2,"Load"],[2,3,{{"Field":{{"offset":0,"size":32}}}}]],"representations":[[{},0]]}}"#,
+            serde_json::to_string(&DerivedTypeVar::new(TypeVariable::new("sub_a".to_owned())))
+                .unwrap(),
+        ))
+        .unwrap();
+        let sketches = SketchGraph::from_checkpoint(&checkpoint, &lattice).unwrap();
+
+        let (node_types, types) = LoweringContext::new(
+            &sketches,
+            &BTreeMap::new(),
+            &HashMap::new(),
+            identity_element(&lattice),
+            LoweringOptions::default(),
+        )
+        .collect_ctypes()
+        .unwrap();
+        let mut tid_to_node = BTreeMap::new();
+        tid_to_node.insert(
+            Tid::create("sub_a".to_owned(), "00001000".to_owned()),
+            NodeIndex::new(0),
+        );
+        let mapping =
+            read_mapping(&convert_mapping_to_profobuf(types, &node_types, &tid_to_node).unwrap())
+                .unwrap();
+
+        Session {
+            type_to_node: link_types_to_nodes(&sketches, &mapping),
+            sketches,
+            mapping,
+            no_aliases: BTreeMap::new(),
+        }
+    }
+
+    fn run(session: &Session, line: &str) -> anyhow::Result<String> {
+        let mut out = Vec::new();
+        session.run_command(line, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn find_type(session: &Session, pred: impl Fn(&CType) -> bool) -> TypeId {
+        session
+            .mapping
+            .types
+            .iter()
+            .find(|(_, ct)| pred(ct))
+            .map(|(ty, _)| *ty)
+            .unwrap()
+    }
+
+    #[test]
+    fn commands_describe_the_saved_solve() {
+        let session = session();
+        let ptr = find_type(&session, |ct| matches!(ct, CType::Pointer { .. }));
+        let structure = find_type(&session, |ct| matches!(ct, CType::Structure(_)));
+
+        assert!(run(&session, "type sub_a").unwrap().starts_with("sub_a: "));
+
+        let fields = run(&session, &format!("fields {}", ptr)).unwrap();
+        assert!(fields.contains(&format!("points to {}", structure)));
+        assert!(fields.contains("+0x0 (32 bits)"));
+
+        let explained = run(&session, &format!("explain {}", structure)).unwrap();
+        assert!(explained.contains("sketch node 2"));
+        assert!(explained.contains(".σ32@0 -> node 3"));
+
+        let callers = run(&session, &format!("callers-of-type {}", structure)).unwrap();
+        assert!(callers.starts_with("sub_a in_0: "));
+    }
+
+    #[test]
+    fn bad_commands_are_reported() {
+        let session = session();
+        assert!(run(&session, "fields 999").is_err());
+        assert!(run(&session, "type").is_err());
+        assert!(run(&session, "frobnicate")
+            .unwrap()
+            .contains("unknown command frobnicate"));
+        assert!(!session.run_command("quit", &mut Vec::new()).unwrap());
+    }
+}
@@ -0,0 +1,42 @@
+use binary_type_inference::{
+    solver::{sketch_diff::diff_sketches, type_sketch::SketchGraphCheckpoint},
+    util::open_input,
+};
+use clap::{App, Arg};
+
+use anyhow::Context;
+
+fn load_sketches(path: &str) -> anyhow::Result<SketchGraphCheckpoint> {
+    serde_json::from_reader(open_input(path)?)
+        .with_context(|| format!("parsing sketch graph {}", path))
+}
+
+fn main() -> anyhow::Result<()> {
+    let matches = App::new("sketch_diff")
+        .arg(Arg::with_name("old_sketches").required(true).index(1))
+        .arg(Arg::with_name("new_sketches").required(true).index(2))
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .required(false)
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let old = load_sketches(matches.value_of("old_sketches").unwrap())?;
+    let new = load_sketches(matches.value_of("new_sketches").unwrap())?;
+    let filter = matches.value_of("filter");
+
+    let diffs = diff_sketches(&old, &new)
+        .context("diffing sketch graphs")?
+        .into_iter()
+        .filter(|diff| filter.map_or(true, |f| diff.variable.to_string().contains(f)))
+        .collect::<Vec<_>>();
+
+    for diff in diffs.iter() {
+        print!("{}", diff);
+    }
+    println!("{} variables differ", diffs.len());
+
+    Ok(())
+}
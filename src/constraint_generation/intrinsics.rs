@@ -0,0 +1,178 @@
+use cwe_checker_lib::intermediate_representation::{BinOpType, Expression};
+
+/// The names of intrinsics whose result is an integer of the width of their operand: byte swaps, rotates and bit
+/// reversals permute the bits of the operand, while the bit counts count or locate them.
+const WIDTH_PRESERVING_INTRINSICS: &[&str] = &[
+    "bswap",
+    "byteswap",
+    "bitreverse",
+    "rotl",
+    "rotr",
+    "rol",
+    "ror",
+    "popcount",
+    "popcnt",
+    "lzcount",
+    "lzcnt",
+    "tzcount",
+    "tzcnt",
+    "clz",
+    "ctz",
+    "ffs",
+    "parity",
+];
+
+/// Checks if the name of a pseudo-op or builtin, ie. `__builtin_bswap32`, `_byteswap_ulong`, or `POPCOUNT`, is a width
+/// preserving intrinsic. Case, leading underscores, the `builtin_` prefix, and trailing widths are ignored.
+pub fn is_width_preserving_intrinsic(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    let name = name.trim_start_matches('_');
+    let name = name.strip_prefix("builtin_").unwrap_or(name);
+    WIDTH_PRESERVING_INTRINSICS
+        .iter()
+        .any(|intrinsic| name.starts_with(intrinsic))
+}
+
+// Walks the shifts, masks and ors of a permutation, collecting the shifted or masked operand and counting the shifts.
+fn collect_permuted<'a>(
+    expr: &'a Expression,
+    operand: &mut Option<&'a Expression>,
+    shifts: &mut usize,
+) -> bool {
+    match expr {
+        Expression::BinOp {
+            op: BinOpType::IntOr,
+            lhs,
+            rhs,
+        } => collect_permuted(lhs, operand, shifts) && collect_permuted(rhs, operand, shifts),
+        Expression::BinOp {
+            op: BinOpType::IntLeft | BinOpType::IntRight,
+            lhs,
+            ..
+        } => {
+            *shifts += 1;
+            collect_permuted(lhs, operand, shifts)
+        }
+        Expression::BinOp {
+            op: BinOpType::IntAnd,
+            lhs,
+            rhs,
+        } => match (lhs.as_ref(), rhs.as_ref()) {
+            (Expression::Const(_), masked) | (masked, Expression::Const(_)) => {
+                collect_permuted(masked, operand, shifts)
+            }
+            _ => false,
+        },
+        Expression::Var(_) | Expression::Subpiece { .. } => match operand {
+            Some(prev) => **prev == *expr,
+            None => {
+                *operand = Some(expr);
+                true
+            }
+        },
+        _ => false,
+    }
+}
+
+/// Finds the operand of an expression that only permutes the bits of that operand, as rotates and byte swaps are
+/// expanded by the lifter: an or of shifted and masked copies of a single operand, ie. `(x << 8) | (x >> 24)`. The
+/// amounts of the shifts are not inspected so that rotates by a variable amount are found as well.
+pub fn permuted_operand(expr: &Expression) -> Option<&Expression> {
+    if !matches!(
+        expr,
+        Expression::BinOp {
+            op: BinOpType::IntOr,
+            ..
+        }
+    ) {
+        return None;
+    }
+
+    let mut operand = None;
+    let mut shifts = 0;
+    if collect_permuted(expr, &mut operand, &mut shifts) && shifts >= 2 {
+        operand
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cwe_checker_lib::intermediate_representation::{
+        BinOpType, Bitvector, ByteSize, Expression, Variable,
+    };
+
+    use super::{is_width_preserving_intrinsic, permuted_operand};
+
+    fn var(name: &str) -> Expression {
+        Expression::Var(Variable {
+            name: name.to_owned(),
+            size: ByteSize::new(4),
+            is_temp: false,
+        })
+    }
+
+    fn binop(op: BinOpType, lhs: Expression, rhs: Expression) -> Expression {
+        Expression::BinOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    fn constant(value: i64) -> Expression {
+        Expression::Const(Bitvector::from_i64(value))
+    }
+
+    #[test]
+    fn intrinsics_are_recognized_by_name() {
+        assert!(is_width_preserving_intrinsic("__builtin_bswap32"));
+        assert!(is_width_preserving_intrinsic("_byteswap_ulong"));
+        assert!(is_width_preserving_intrinsic("POPCOUNT"));
+        assert!(is_width_preserving_intrinsic("__builtin_ctzll"));
+        assert!(!is_width_preserving_intrinsic("__builtin_memcpy"));
+        assert!(!is_width_preserving_intrinsic("strlen"));
+    }
+
+    #[test]
+    fn rotates_and_byte_swaps_permute_their_operand() {
+        // rol eax, 8
+        let rotate = binop(
+            BinOpType::IntOr,
+            binop(BinOpType::IntLeft, var("EAX"), constant(8)),
+            binop(BinOpType::IntRight, var("EAX"), constant(24)),
+        );
+        assert_eq!(permuted_operand(&rotate), Some(&var("EAX")));
+
+        // the outer bytes of a byte swap of eax
+        let swap = binop(
+            BinOpType::IntOr,
+            binop(
+                BinOpType::IntOr,
+                binop(BinOpType::IntLeft, var("EAX"), constant(24)),
+                binop(
+                    BinOpType::IntAnd,
+                    binop(BinOpType::IntRight, var("EAX"), constant(8)),
+                    constant(0xff00),
+                ),
+            ),
+            binop(BinOpType::IntRight, var("EAX"), constant(24)),
+        );
+        assert_eq!(permuted_operand(&swap), Some(&var("EAX")));
+
+        // combining two operands or a single shift is not a permutation
+        let combined = binop(
+            BinOpType::IntOr,
+            binop(BinOpType::IntLeft, var("EAX"), constant(8)),
+            binop(BinOpType::IntRight, var("EBX"), constant(24)),
+        );
+        assert_eq!(permuted_operand(&combined), None);
+        let flag = binop(
+            BinOpType::IntOr,
+            binop(BinOpType::IntLeft, var("EAX"), constant(8)),
+            constant(1),
+        );
+        assert_eq!(permuted_operand(&flag), None);
+    }
+}
@@ -12,16 +12,28 @@ use cwe_checker_lib::intermediate_representation::{ByteSize, Expression, Variabl
 
 use cwe_checker_lib::intermediate_representation::Tid;
 
+use crate::analysis::arm32;
+use crate::analysis::exceptions::ExceptionPaths;
+use crate::analysis::lifetimes::HeapLifetimes;
+use crate::analysis::noreturn::NoreturnCalls;
+use crate::analysis::tls::TlsModel;
+use crate::diagnostics::{self, DiagnosticKind};
+use crate::util::FileDebugLogger;
+use serde::{Deserialize, Serialize};
+
 use crate::constraints::{
-    AddConstraint, ConstraintSet, DerivedTypeVar, Field, FieldLabel, SubtypeConstraint,
-    TyConstraint, TypeVariable, VariableManager,
+    AddConstraint, ConstraintOrigin, ConstraintReason, ConstraintSet, DerivedTypeVar, Field,
+    FieldLabel, SubtypeConstraint, TyConstraint, TypeVariable, VariableManager,
 };
 
 use std::{
-    collections::{btree_set::BTreeSet, BTreeMap, HashMap, HashSet},
+    collections::{btree_set::BTreeSet, BTreeMap, HashSet},
     convert::TryInto,
 };
 
+/// Recognition of the intrinsics and compiler builtins that appear in lifted code.
+pub mod intrinsics;
+
 /// Gets a type variable for a [Tid] where multiple type variables need to exist at that [Tid] which are distinguished by which [Variable] they operate over.
 pub fn tid_indexed_by_variable(tid: &Tid, var: &Variable) -> TypeVariable {
     TypeVariable::new(tid.get_str_repr().to_owned() + "_" + &var.name)
@@ -57,6 +69,28 @@ pub fn arg_tvar(index: usize, target_sub: &Tid) -> TypeVariable {
     TypeVariable::new(format!("arg_{}_{}", target_sub.get_str_repr(), index))
 }
 
+/// Gets the registers holding a register argument. A 64 bit value passed in a pair of 32 bit registers, ie. `r0:r1`
+/// under the AAPCS, is the piece of both registers. On 32 bit ARM a part of a register, ie. the low half of a pair
+/// passed as a 32 bit value, is held by that register; elsewhere a part of a register holds no whole register.
+pub fn argument_registers<'a>(expr: &'a Expression, cpu_architecture: &str) -> Vec<&'a Variable> {
+    match expr {
+        Expression::Var(var) => vec![var],
+        Expression::BinOp {
+            op: BinOpType::Piece,
+            lhs,
+            rhs,
+        } => {
+            let mut registers = argument_registers(lhs, cpu_architecture);
+            registers.extend(argument_registers(rhs, cpu_architecture));
+            registers
+        }
+        Expression::Subpiece { arg, .. } if arm32::is_arm32(cpu_architecture) => {
+            argument_registers(arg, cpu_architecture)
+        }
+        _ => Vec::new(),
+    }
+}
+
 /// A [NodeContextMapping] provides information about the program state at a given CFG node. Because basic blocks contain multiple defs
 /// contexts must be capable of reapplying def terms to compute the intermediate states.
 pub trait NodeContextMapping: Clone {
@@ -97,6 +131,12 @@ pub trait PointsToMapping: NodeContextMapping {
         address: &Expression,
         constant_resolver: &impl ConstantResolver,
     ) -> Option<DerivedTypeVar>;
+
+    /// Gets the slot of the stack frame of the current function an address must point to, if load and store
+    /// constraints should type the slot directly rather than through the registers computing the address.
+    fn frame_slot(&self, _address: &Expression, _sz: ByteSize) -> Option<TypeVariableAccess> {
+        None
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -249,6 +289,19 @@ pub struct NodeContext<
     subprocedure_locators: S,
     constant_resolver: C,
     weakest_integral_type: TypeVariable,
+    pointer_integers: Option<PointerIntegerTypes>,
+    tls: Option<TlsModel>,
+}
+
+/// The lattice elements of integers derived from pointers, along with the size of a pointer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PointerIntegerTypes {
+    /// The element of indices scaled by the size of a pointer, ie. size_t
+    pub size: TypeVariable,
+    /// The element of differences between pointers, ie. ptrdiff_t
+    pub difference: TypeVariable,
+    /// The size of a pointer in bytes
+    pub word_size: u64,
 }
 
 /// Stores information about a given invocation of a subprocedure, either formal or actual (represented by a present callsite)
@@ -267,6 +320,8 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
         let s = self.subprocedure_locators.apply_def(term);
         let c = self.constant_resolver.apply_def(term);
         NodeContext::new(r, p, s, c, self.weakest_integral_type.clone())
+            .with_pointer_integer_types(self.pointer_integers.clone())
+            .with_tls_model(self.tls.clone())
     }
 
     fn apply_return_node(&self, call_term: &Term<Jmp>, return_term: &Term<Jmp>) -> Self {
@@ -279,6 +334,8 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
             .constant_resolver
             .apply_return_node(call_term, return_term);
         NodeContext::new(r, p, s, c, self.weakest_integral_type.clone())
+            .with_pointer_integer_types(self.pointer_integers.clone())
+            .with_tls_model(self.tls.clone())
     }
 }
 
@@ -299,9 +356,28 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
             subprocedure_locators: s,
             constant_resolver: c,
             weakest_integral_type,
+            pointer_integers: None,
+            tls: None,
         }
     }
 
+    /// Recognizes indices scaled by the size of a pointer and differences of pointers as the given lattice elements
+    /// rather than generic integers. Disabled when None.
+    pub fn with_pointer_integer_types(
+        mut self,
+        pointer_integers: Option<PointerIntegerTypes>,
+    ) -> NodeContext<R, P, S, C> {
+        self.pointer_integers = pointer_integers;
+        self
+    }
+
+    /// Types loads and stores relative to the thread pointers of the model as fields of the thread-local storage block.
+    /// Disabled when None.
+    pub fn with_tls_model(mut self, tls: Option<TlsModel>) -> NodeContext<R, P, S, C> {
+        self.tls = tls;
+        self
+    }
+
     fn generate_const_add_repr(bv: Bitvector, mut expr_repr: BaseValueDomain) -> BaseValueDomain {
         let constant = bv
             .try_to_i128()
@@ -372,6 +448,124 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
         }
     }
 
+    fn constant_value(expr: &Expression) -> Option<u64> {
+        match expr {
+            Expression::Const(bv) => bv.try_to_u64().ok(),
+            _ => None,
+        }
+    }
+
+    /// Finds the index of an expression that scales an index by the size of a pointer, ie. `idx * 8` or `idx << 3`.
+    fn scaled_index<'b>(
+        &self,
+        op: &BinOpType,
+        lhs: &'b Expression,
+        rhs: &'b Expression,
+    ) -> Option<&'b Expression> {
+        let word_size = self.pointer_integers.as_ref()?.word_size;
+        match op {
+            BinOpType::IntMult if Self::constant_value(rhs) == Some(word_size) => Some(lhs),
+            BinOpType::IntMult if Self::constant_value(lhs) == Some(word_size) => Some(rhs),
+            BinOpType::IntLeft
+                if word_size.is_power_of_two()
+                    && Self::constant_value(rhs) == Some(u64::from(word_size.trailing_zeros())) =>
+            {
+                Some(lhs)
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks whether there is points-to evidence that an expression holds a pointer, either to a global variable or
+    /// into an abstract object.
+    fn is_pointer(&self, expr: &Expression) -> bool {
+        self.points_to
+            .get_pointer_variable(expr, &self.constant_resolver)
+            .is_some()
+            || !self.points_to.points_to(expr, expr.bytesize()).is_empty()
+    }
+
+    /// Checks whether an expression is a difference of two pointers, possibly divided by a constant element size, ie.
+    /// `(end - begin) >> 3`. Both operands of the subtraction must be known to be pointers.
+    fn is_pointer_difference(&self, op: &BinOpType, lhs: &Expression, rhs: &Expression) -> bool {
+        match (op, lhs) {
+            (BinOpType::IntSub, _) => self.is_pointer(lhs) && self.is_pointer(rhs),
+            (
+                BinOpType::IntSRight | BinOpType::IntSDiv,
+                Expression::BinOp {
+                    op: BinOpType::IntSub,
+                    lhs: sub_lhs,
+                    rhs: sub_rhs,
+                },
+            ) => {
+                Self::constant_value(rhs).is_some()
+                    && self.is_pointer(sub_lhs)
+                    && self.is_pointer(sub_rhs)
+            }
+            _ => false,
+        }
+    }
+
+    fn evaluate_pointer_integer(
+        &self,
+        op: &BinOpType,
+        lhs: &Expression,
+        rhs: &Expression,
+        defining_tvars_are_subtype_of_repr: bool,
+        vman: &mut VariableManager,
+    ) -> Option<(DerivedTypeVar, ConstraintSet)> {
+        let types = self.pointer_integers.as_ref()?;
+        if let Some(idx) = self.scaled_index(op, lhs, rhs) {
+            // the index is used as a size_t, the scaled offset is only known to be integral
+            let (idx_repr, mut cons) =
+                self.evaluate_expression(idx, defining_tvars_are_subtype_of_repr, vman);
+            cons.insert(TyConstraint::SubTy(SubtypeConstraint::new(
+                idx_repr,
+                DerivedTypeVar::new(types.size.clone()),
+            )));
+            let (repr, weak_cons) = self.assume_weak_integral(vman);
+            cons.insert_all(&weak_cons);
+            Some((repr, cons))
+        } else if self.is_pointer_difference(op, lhs, rhs) {
+            // the operands keep their constraints, only the difference itself is bounded by ptrdiff_t
+            let (repr, mut cons) = match op {
+                BinOpType::IntSub => {
+                    self.evaluate_subtraction(lhs, rhs, defining_tvars_are_subtype_of_repr, vman)
+                }
+                _ => {
+                    let (_, cons) =
+                        self.evaluate_expression(lhs, defining_tvars_are_subtype_of_repr, vman);
+                    (DerivedTypeVar::new(vman.fresh()), cons)
+                }
+            };
+            cons.insert(TyConstraint::SubTy(SubtypeConstraint::new(
+                repr.clone(),
+                DerivedTypeVar::new(types.difference.clone()),
+            )));
+            Some((repr, cons))
+        } else {
+            None
+        }
+    }
+
+    fn evaluate_subtraction(
+        &self,
+        lhs: &Expression,
+        rhs: &Expression,
+        defining_tvars_are_subtype_of_repr: bool,
+        vman: &mut VariableManager,
+    ) -> (DerivedTypeVar, ConstraintSet) {
+        self.eval_add(
+            lhs,
+            &Expression::UnOp {
+                op: UnOpType::IntNegate,
+                arg: Box::new(rhs.clone()),
+            },
+            defining_tvars_are_subtype_of_repr,
+            vman,
+        )
+    }
+
     fn evaluate_binop(
         &self,
         op: &BinOpType,
@@ -380,6 +574,12 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
         defining_tvars_are_subtype_of_repr: bool,
         vman: &mut VariableManager,
     ) -> (DerivedTypeVar, ConstraintSet) {
+        if let Some(res) =
+            self.evaluate_pointer_integer(op, lhs, rhs, defining_tvars_are_subtype_of_repr, vman)
+        {
+            return res;
+        }
+
         match op {
             // TODO(Ian): Think about this case a bit more
             // Should probably at a minimum propogate something to the operands
@@ -393,17 +593,15 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
                 }
             }
             BinOpType::IntAdd => self.eval_add(lhs, rhs, defining_tvars_are_subtype_of_repr, vman),
-            BinOpType::IntSub => self.eval_add(
-                lhs,
-                &Expression::UnOp {
-                    op: UnOpType::IntNegate,
-                    arg: Box::new(rhs.clone()),
-                },
-                defining_tvars_are_subtype_of_repr,
-                vman,
-            ),
+            BinOpType::IntSub => {
+                self.evaluate_subtraction(lhs, rhs, defining_tvars_are_subtype_of_repr, vman)
+            }
             _ => {
                 let repr = vman.fresh();
+                diagnostics::report(
+                    DiagnosticKind::UnhandledExpression,
+                    format!("Unhandled binop type: {:?}", op),
+                );
                 warn!("Unhandled binop type: {:?}, representing with {}", op, repr);
                 (DerivedTypeVar::new(repr), ConstraintSet::default())
             }
@@ -415,6 +613,10 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
         vman: &mut VariableManager,
     ) -> (DerivedTypeVar, ConstraintSet) {
         let repr = vman.fresh();
+        diagnostics::report(
+            DiagnosticKind::UnhandledExpression,
+            format!("Unhandled expression: {:?}", value),
+        );
         warn!(
             "Unhandled expression: {:?} representing with {}",
             value, repr
@@ -422,15 +624,35 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
         (DerivedTypeVar::new(repr), ConstraintSet::empty())
     }
 
+    /// Types a rotate or byte swap of an operand like a copy of the operand that is known to be integral, so the type
+    /// of the operand flows across the permutation rather than being lost.
+    fn evaluate_permutation(
+        &self,
+        operand: &Expression,
+        defining_tvars_are_subtype_of_repr: bool,
+        vman: &mut VariableManager,
+    ) -> (DerivedTypeVar, ConstraintSet) {
+        let (operand_repr, mut cons) =
+            self.evaluate_expression(operand, defining_tvars_are_subtype_of_repr, vman);
+        let (repr, weak_cons) = self.assume_weak_integral(vman);
+        cons.insert_all(&weak_cons);
+        cons.insert(TyConstraint::SubTy(if defining_tvars_are_subtype_of_repr {
+            SubtypeConstraint::new(operand_repr, repr.clone())
+        } else {
+            SubtypeConstraint::new(repr.clone(), operand_repr)
+        }));
+        (repr, cons)
+    }
+
     fn assume_weak_integral(&self, vman: &mut VariableManager) -> (DerivedTypeVar, ConstraintSet) {
         // we dont know the exact type of the expr but we know it's at least subtype of a weak int
         let f = vman.fresh();
         let dtv = DerivedTypeVar::new(f);
         let mut cs = ConstraintSet::default();
-        cs.insert(TyConstraint::SubTy(SubtypeConstraint {
-            lhs: dtv.clone(),
-            rhs: DerivedTypeVar::new(self.weakest_integral_type.clone()),
-        }));
+        cs.insert(TyConstraint::SubTy(SubtypeConstraint::new(
+            dtv.clone(),
+            DerivedTypeVar::new(self.weakest_integral_type.clone()),
+        )));
         (dtv, cs)
     }
 
@@ -477,9 +699,12 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
                     (repr, ConstraintSet::from(cons))
                 }
             }
-            Expression::BinOp { op, lhs, rhs } => {
-                self.evaluate_binop(op, lhs, rhs, defining_tvars_are_subtype_of_repr, vman)
-            }
+            Expression::BinOp { op, lhs, rhs } => match intrinsics::permuted_operand(value) {
+                Some(operand) => {
+                    self.evaluate_permutation(operand, defining_tvars_are_subtype_of_repr, vman)
+                }
+                None => self.evaluate_binop(op, lhs, rhs, defining_tvars_are_subtype_of_repr, vman),
+            },
             Expression::Cast {
                 op,
                 size: _,
@@ -500,8 +725,9 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
                 cwe_checker_lib::intermediate_representation::CastOpType::Trunc => {
                     self.assume_weak_integral(vman)
                 }
+                // the count of the set bits is an integer of the width of the cast
                 cwe_checker_lib::intermediate_representation::CastOpType::PopCount => {
-                    Self::unhandled_expr(value, vman)
+                    self.assume_weak_integral(vman)
                 }
             },
             Expression::Const(value) => (
@@ -509,6 +735,12 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
                     .resolve_constant_to_variable(value, vman),
                 ConstraintSet::default(),
             ),
+            // pseudo-ops the lifter does not expand, ie. a call to a byte swap or bit count builtin
+            Expression::Unknown { description, .. }
+                if intrinsics::is_width_preserving_intrinsic(description) =>
+            {
+                self.assume_weak_integral(vman)
+            }
             _ => Self::unhandled_expr(value, vman), // TODO(ian) handle additional constraints, add/sub
         }
     }
@@ -568,6 +800,40 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
         address_is_subtype: bool,
         vman: &mut VariableManager,
     ) -> BaseValueDomain {
+        if let Some(TypeVariableAccess {
+            ty_var,
+            sz,
+            offset: Some(offset),
+        }) = self.points_to.frame_slot(adressing_expr, sz)
+        {
+            // The slot itself is loaded from or stored to, so no pointer type variable is made for the registers
+            // computing a stack address
+            let mut representation = DerivedTypeVar::new(ty_var);
+            representation
+                .add_field_label(FieldLabel::Field(Field::new(offset, sz.as_bit_length())));
+            return BaseValueDomain {
+                repr_var: representation,
+                additional_constriants: ConstraintSet::default(),
+            };
+        }
+
+        if let Some(access) = self
+            .tls
+            .as_ref()
+            .and_then(|tls| tls.access(adressing_expr, sz))
+        {
+            // The thread pointer is not tracked by the points-to analysis, so the block itself is loaded from or stored to
+            let mut representation = DerivedTypeVar::new(access.ty_var);
+            representation.add_field_label(match access.offset {
+                Some(off) => FieldLabel::Field(Field::new(off, access.sz.as_bit_length())),
+                None => FieldLabel::Element(access.sz.as_bit_length()),
+            });
+            return BaseValueDomain {
+                repr_var: representation,
+                additional_constriants: ConstraintSet::default(),
+            };
+        }
+
         let tv_access = self.points_to.points_to(adressing_expr, sz);
         let (reg_repr, mut cons) = self.evaluate_expression(adressing_expr, true, vman);
 
@@ -578,18 +844,20 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
         representation = simplify_path(&representation);
 
         for acc in tv_access.iter() {
-            if let Some(off) = acc.offset {
-                let mut dt_repr = DerivedTypeVar::new(acc.ty_var.clone());
-
-                dt_repr.add_field_label(FieldLabel::Field(Field::new(off, acc.sz.as_bit_length())));
-
-                let new_cons = if address_is_subtype {
-                    SubtypeConstraint::new(representation.clone(), dt_repr)
-                } else {
-                    SubtypeConstraint::new(dt_repr, representation.clone())
-                };
-                cons.insert(TyConstraint::SubTy(new_cons));
-            }
+            let mut dt_repr = DerivedTypeVar::new(acc.ty_var.clone());
+
+            // an access at an unknown offset still types the object as a region of elements of the accessed size
+            dt_repr.add_field_label(match acc.offset {
+                Some(off) => FieldLabel::Field(Field::new(off, acc.sz.as_bit_length())),
+                None => FieldLabel::Element(acc.sz.as_bit_length()),
+            });
+
+            let new_cons = if address_is_subtype {
+                SubtypeConstraint::new(representation.clone(), dt_repr)
+            } else {
+                SubtypeConstraint::new(dt_repr, representation.clone())
+            };
+            cons.insert(TyConstraint::SubTy(new_cons));
         }
 
         BaseValueDomain {
@@ -654,12 +922,31 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
         self.memupdate(address_into, value_from, vman)
     }
 
-    fn handle_def(&self, df: &Term<Def>, vman: &mut VariableManager) -> ConstraintSet {
-        match &df.term {
-            Def::Load { var, address } => self.apply_load(&df.tid, var, address, vman),
-            Def::Store { address, value } => self.apply_store(&df.tid, value, address, vman),
-            Def::Assign { var, value } => self.apply_assign(&df.tid, var, value, vman),
-        }
+    fn handle_def(
+        &self,
+        df: &Term<Def>,
+        def_index: usize,
+        vman: &mut VariableManager,
+    ) -> ConstraintSet {
+        let (cons, reason) = diagnostics::with_location(&df.tid, || match &df.term {
+            Def::Load { var, address } => (
+                self.apply_load(&df.tid, var, address, vman),
+                ConstraintReason::Load,
+            ),
+            Def::Store { address, value } => (
+                self.apply_store(&df.tid, value, address, vman),
+                ConstraintReason::Store,
+            ),
+            Def::Assign { var, value } => (
+                self.apply_assign(&df.tid, var, value, vman),
+                ConstraintReason::Assign,
+            ),
+        });
+        cons.with_origin(&ConstraintOrigin::for_def(
+            df.tid.clone(),
+            def_index,
+            reason,
+        ))
     }
 
     fn argtvar_to_dtv(tvar: ArgTvar, displacement: i64) -> DerivedTypeVar {
@@ -752,6 +1039,10 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
             0,
             vman,
         )
+        .with_origin(&ConstraintOrigin::new(
+            sub.tid.clone(),
+            ConstraintReason::FormalParameter,
+        ))
     }
 
     /// make each formal the subtype of the addressing info for this parameter within the current state
@@ -767,6 +1058,10 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
             0,
             vman,
         )
+        .with_origin(&ConstraintOrigin::new(
+            sub.tid.clone(),
+            ConstraintReason::FormalReturn,
+        ))
     }
 
     /*
@@ -791,6 +1086,10 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
             return_address_displacement,
             vman,
         )
+        .with_origin(&ConstraintOrigin::new(
+            calling_blk.tid.clone(),
+            ConstraintReason::CallActualReturn,
+        ))
     }
 
     //TODO(Ian): implement callsite cloning
@@ -812,6 +1111,10 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
             return_address_displacement,
             vman,
         )
+        .with_origin(&ConstraintOrigin::new(
+            calling_blk.tid.clone(),
+            ConstraintReason::CallActualParameter,
+        ))
     }
 
     fn handle_extern_actual_params(
@@ -832,6 +1135,10 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
             return_address_displacement,
             vman,
         )
+        .with_origin(&ConstraintOrigin::new(
+            calling_blk.tid.clone(),
+            ConstraintReason::CallActualParameter,
+        ))
     }
 
     fn handle_extern_actual_rets(
@@ -852,6 +1159,10 @@ impl<R: RegisterMapping, P: PointsToMapping, S: SubprocedureLocators, C: Constan
             return_address_displacement,
             vman,
         )
+        .with_origin(&ConstraintOrigin::new(
+            calling_blk.tid.clone(),
+            ConstraintReason::CallActualReturn,
+        ))
     }
 }
 
@@ -875,6 +1186,31 @@ pub fn fold_over_definition_states<C: NodeContextMapping, I>(
     final_inner_state
 }
 
+/// How generated constraints are split into debug files when constraint dumps are enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConstraintDumpGranularity {
+    /// One file per cfg node, named by the block (and call target) the node belongs to
+    Node,
+    /// One file per function, named by the subprocedure's tid
+    Function,
+}
+
+impl std::str::FromStr for ConstraintDumpGranularity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "node" => Ok(ConstraintDumpGranularity::Node),
+            "function" => Ok(ConstraintDumpGranularity::Function),
+            _ => Err(anyhow::anyhow!(
+                "unknown constraint dump granularity {}, expected node or function",
+                s
+            )),
+        }
+    }
+}
+
 /// Holds a mapping between the nodes and their flow-sensitive analysis results, which
 /// are needed for constraint generation
 pub struct Context<'a, R, P, S, C>
@@ -885,9 +1221,13 @@ where
     C: ConstantResolver,
 {
     graph: &'a Graph<'a>,
-    node_contexts: &'a HashMap<NodeIndex, NodeContext<R, P, S, C>>,
+    node_contexts: &'a BTreeMap<NodeIndex, NodeContext<R, P, S, C>>,
     extern_symbols: &'a BTreeMap<Tid, ExternSymbol>,
     function_filter: Option<HashSet<Tid>>,
+    constraint_dumps: Option<(ConstraintDumpGranularity, FileDebugLogger)>,
+    noreturn_calls: Option<&'a NoreturnCalls>,
+    exception_paths: Option<&'a ExceptionPaths>,
+    heap_lifetimes: Option<&'a HeapLifetimes>,
 }
 
 impl<'a, R, P, S, C> Context<'a, R, P, S, C>
@@ -900,7 +1240,7 @@ where
     /// Creates a new context for type constraint generation.
     pub fn new(
         graph: &'a Graph<'a>,
-        node_contexts: &'a HashMap<NodeIndex, NodeContext<R, P, S, C>>,
+        node_contexts: &'a BTreeMap<NodeIndex, NodeContext<R, P, S, C>>,
         extern_symbols: &'a BTreeMap<Tid, ExternSymbol>,
         function_filter: Option<HashSet<Tid>>,
     ) -> Context<'a, R, P, S, C> {
@@ -909,6 +1249,65 @@ where
             node_contexts,
             extern_symbols,
             function_filter,
+            constraint_dumps: None,
+            noreturn_calls: None,
+            exception_paths: None,
+            heap_lifetimes: None,
+        }
+    }
+
+    /// Renames the variables of the constraints generated on the exception paths of each subprocedure into the
+    /// namespace of its landing pads, see [ExceptionPaths].
+    pub fn with_exception_paths(mut self, exception_paths: &'a ExceptionPaths) -> Self {
+        self.exception_paths = Some(exception_paths);
+        self
+    }
+
+    /// Renames the heap objects accessed in several lifetimes of a subprocedure to a variable per lifetime in the
+    /// constraints generated for the blocks of each lifetime, see [HeapLifetimes].
+    pub fn with_heap_lifetimes(mut self, heap_lifetimes: &'a HeapLifetimes) -> Self {
+        self.heap_lifetimes = Some(heap_lifetimes);
+        self
+    }
+
+    /// Skips the nodes that are only reached by falling through a call to a noreturn function, and the returns of
+    /// noreturn extern symbols, see [NoreturnCalls].
+    pub fn with_noreturn_calls(mut self, noreturn_calls: &'a NoreturnCalls) -> Self {
+        self.noreturn_calls = Some(noreturn_calls);
+        self
+    }
+
+    /// Writes the constraints generated for each node or function to its own file in the debug directory, so that
+    /// the constraints contributed by a single instruction are easy to find. Constraints are written with their origins.
+    pub fn with_constraint_dumps(
+        mut self,
+        granularity: ConstraintDumpGranularity,
+        debug_dir: FileDebugLogger,
+    ) -> Self {
+        self.constraint_dumps = Some((granularity, debug_dir));
+        self
+    }
+
+    fn node_dump_name(nd: Node) -> String {
+        match nd {
+            Node::BlkStart(blk, _) => format!("node_constraints_{}_start", blk.tid.get_str_repr()),
+            Node::BlkEnd(blk, _) => format!("node_constraints_{}_end", blk.tid.get_str_repr()),
+            Node::CallReturn {
+                call: (call_blk, _),
+                return_: (_, return_proc),
+            } => format!(
+                "node_constraints_{}_return_from_{}",
+                call_blk.tid.get_str_repr(),
+                return_proc.tid.get_str_repr()
+            ),
+            Node::CallSource {
+                source: (source_blk, _),
+                target: (_, target_func),
+            } => format!(
+                "node_constraints_{}_call_{}",
+                source_blk.tid.get_str_repr(),
+                target_func.tid.get_str_repr()
+            ),
         }
     }
 
@@ -929,6 +1328,7 @@ where
             .defs
             .iter()
             .for_each(|x| info!("Has {} {:?}", x.tid, x.term));
+        let mut def_index = 0;
         fold_over_definition_states(
             nd_ctxt,
             blk,
@@ -936,7 +1336,8 @@ where
             &mut |df: &Term<Def>,
                   curr_ctxt: &NodeContext<R, P, S, C>,
                   mut curr_constraints: ConstraintSet| {
-                curr_constraints.insert_all(&curr_ctxt.handle_def(df, vman));
+                curr_constraints.insert_all(&curr_ctxt.handle_def(df, def_index, vman));
+                def_index += 1;
                 curr_constraints
             },
         )
@@ -989,6 +1390,9 @@ where
             let source_node: Node = self.graph[edge.source()];
             if let Edge::ExternCallStub(jmp) = edge.weight() {
                 if let Jmp::Call { target, .. } = &jmp.term {
+                    if !self.call_returns(target) {
+                        continue;
+                    }
                     if let Some(extern_symb) = self.extern_symbols.get(target) {
                         let term = Term {
                             term: extern_symb.clone(),
@@ -1025,6 +1429,38 @@ where
         .clone()
     }
 
+    fn call_returns(&self, target: &Tid) -> bool {
+        self.noreturn_calls
+            .map(|noreturn| noreturn.returns(target))
+            .unwrap_or(true)
+    }
+
+    fn is_reachable(&self, nd_ind: NodeIndex) -> bool {
+        self.noreturn_calls
+            .map(|noreturn| noreturn.is_reachable(nd_ind))
+            .unwrap_or(true)
+    }
+
+    fn node_block<'b>(nd: Node<'b>) -> &'b Term<Blk> {
+        match nd {
+            Node::BlkStart(blk, _) | Node::BlkEnd(blk, _) => blk,
+            Node::CallReturn {
+                call: (call_blk, _),
+                ..
+            } => call_blk,
+            Node::CallSource {
+                source: (source_blk, _),
+                ..
+            } => source_blk,
+        }
+    }
+
+    fn is_exception_path(&self, nd: Node) -> bool {
+        self.exception_paths
+            .map(|paths| paths.contains(&Self::node_block(nd).tid))
+            .unwrap_or(false)
+    }
+
     fn should_generate_for_block(&self, nd: Node) -> bool {
         self.function_filter
             .as_ref()
@@ -1039,7 +1475,7 @@ where
     ) -> ConstraintSet {
         let nd_cont = self.node_contexts.get(&nd_ind);
         let nd = self.graph[nd_ind];
-        if !self.should_generate_for_block(nd) {
+        if !self.should_generate_for_block(nd) || !self.is_reachable(nd_ind) {
             return ConstraintSet::default();
         }
 
@@ -1135,14 +1571,261 @@ where
     /// Walks all of the nodes and gather the inferred subtyping constraints.
     pub fn generate_constraints(&self, vman: &mut VariableManager) -> ConstraintSet {
         let mut cs: ConstraintSet = Default::default();
+        let mut by_function: BTreeMap<Tid, ConstraintSet> = BTreeMap::new();
 
+        let previous_scope = vman.get_scope().cloned();
         for nd_ind in self.graph.node_indices() {
-            cs.extend(
-                self.generate_constraints_for_node(nd_ind, vman)
-                    .0
-                    .into_iter(),
-            );
+            if vman.uses_function_scopes() {
+                vman.set_scope(Some(self.get_func_tid(self.graph[nd_ind])));
+            }
+            let mut node_cons = self.generate_constraints_for_node(nd_ind, vman);
+            if let Some(renaming) = self
+                .heap_lifetimes
+                .and_then(|lifetimes| lifetimes.renaming(&Self::node_block(self.graph[nd_ind]).tid))
+            {
+                node_cons = node_cons.rename(renaming);
+            }
+            if self.is_exception_path(self.graph[nd_ind]) {
+                node_cons = node_cons.in_namespace(&ExceptionPaths::namespace(
+                    &self.get_func_tid(self.graph[nd_ind]),
+                ));
+            }
+            match &self.constraint_dumps {
+                Some((ConstraintDumpGranularity::Node, debug_dir)) if !node_cons.is_empty() => {
+                    if let Err(err) = debug_dir.log_constraints(
+                        &Self::node_dump_name(self.graph[nd_ind]),
+                        &node_cons,
+                        true,
+                    ) {
+                        warn!("Failed to dump node constraints: {}", err);
+                    }
+                }
+                Some((ConstraintDumpGranularity::Function, _)) => by_function
+                    .entry(self.get_func_tid(self.graph[nd_ind]))
+                    .or_default()
+                    .insert_all(&node_cons),
+                _ => (),
+            }
+            cs.extend(node_cons.0.into_iter());
+        }
+        vman.set_scope(previous_scope);
+
+        if let Some((_, debug_dir)) = &self.constraint_dumps {
+            for (func, func_cons) in by_function.iter().filter(|(_, c)| !c.is_empty()) {
+                if let Err(err) = debug_dir.log_constraints(
+                    &format!("function_constraints_{}", func.get_str_repr()),
+                    func_cons,
+                    true,
+                ) {
+                    warn!("Failed to dump function constraints: {}", err);
+                }
+            }
         }
         cs
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use cwe_checker_lib::intermediate_representation::{
+        Arg, BinOpType, Bitvector, ByteSize, Def, Expression, Jmp, Term, Variable,
+    };
+
+    use crate::constraints::{
+        ConstraintSet, DerivedTypeVar, TyConstraint, TypeVariable, VariableManager,
+    };
+
+    use super::{
+        ArgTvar, ConstantResolver, NodeContext, NodeContextMapping, PointerIntegerTypes,
+        PointsToMapping, RegisterMapping, SubprocedureLocators, TypeVariableAccess,
+    };
+
+    /// Maps each register to a type variable of the same name. The registers in pointers point to an object.
+    #[derive(Clone, Default)]
+    struct MockMapping {
+        pointers: BTreeSet<String>,
+    }
+
+    impl NodeContextMapping for MockMapping {
+        fn apply_def(&self, _term: &Term<Def>) -> Self {
+            self.clone()
+        }
+
+        fn apply_return_node(&self, _call_term: &Term<Jmp>, _return_term: &Term<Jmp>) -> Self {
+            self.clone()
+        }
+    }
+
+    impl RegisterMapping for MockMapping {
+        fn access(&self, var: &Variable) -> BTreeSet<TypeVariable> {
+            std::iter::once(TypeVariable::new(var.name.clone())).collect()
+        }
+    }
+
+    impl PointsToMapping for MockMapping {
+        fn points_to(&self, address: &Expression, sz: ByteSize) -> BTreeSet<TypeVariableAccess> {
+            match address {
+                Expression::Var(var) if self.pointers.contains(&var.name) => {
+                    std::iter::once(TypeVariableAccess {
+                        ty_var: TypeVariable::new(format!("object_{}", var.name)),
+                        sz,
+                        offset: Some(0),
+                    })
+                    .collect()
+                }
+                _ => BTreeSet::new(),
+            }
+        }
+
+        fn get_pointer_variable(
+            &self,
+            _address: &Expression,
+            _constant_resolver: &impl ConstantResolver,
+        ) -> Option<DerivedTypeVar> {
+            None
+        }
+    }
+
+    impl SubprocedureLocators for MockMapping {
+        fn get_type_variables_and_constraints_for_arg(
+            &self,
+            _arg: &Arg,
+            _reg: &impl RegisterMapping,
+            _points_to: &impl PointsToMapping,
+            _vm: &mut VariableManager,
+        ) -> BTreeSet<ArgTvar> {
+            BTreeSet::new()
+        }
+    }
+
+    impl ConstantResolver for MockMapping {
+        fn maybe_resolve_constant_to_variable(
+            &self,
+            _target: &Bitvector,
+        ) -> Option<DerivedTypeVar> {
+            None
+        }
+    }
+
+    type MockContext = NodeContext<MockMapping, MockMapping, MockMapping, MockMapping>;
+
+    fn context(pointers: &[&str], type_pointer_integers: bool) -> MockContext {
+        let mapping = MockMapping {
+            pointers: pointers.iter().map(|name| name.to_string()).collect(),
+        };
+        NodeContext::new(
+            mapping.clone(),
+            mapping.clone(),
+            mapping.clone(),
+            mapping,
+            TypeVariable::new("weak_integer".to_owned()),
+        )
+        .with_pointer_integer_types(type_pointer_integers.then(|| PointerIntegerTypes {
+            size: TypeVariable::new("size_t".to_owned()),
+            difference: TypeVariable::new("ptrdiff_t".to_owned()),
+            word_size: 8,
+        }))
+    }
+
+    fn var(name: &str) -> Expression {
+        Expression::Var(Variable {
+            name: name.to_owned(),
+            size: ByteSize::new(8),
+            is_temp: false,
+        })
+    }
+
+    fn binop(op: BinOpType, lhs: Expression, rhs: Expression) -> Expression {
+        Expression::BinOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    fn constant(value: i64) -> Expression {
+        Expression::Const(Bitvector::from_i64(value))
+    }
+
+    fn evaluate(ctx: &MockContext, expr: &Expression) -> (DerivedTypeVar, ConstraintSet) {
+        ctx.evaluate_expression(expr, true, &mut VariableManager::new())
+    }
+
+    fn bounded_by(cons: &ConstraintSet, elem: &str) -> BTreeSet<DerivedTypeVar> {
+        let elem = DerivedTypeVar::new(TypeVariable::new(elem.to_owned()));
+        cons.iter()
+            .filter_map(|c| match c {
+                TyConstraint::SubTy(sub) if sub.rhs == elem => Some(sub.lhs.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn has_add_constraint(cons: &ConstraintSet) -> bool {
+        cons.iter().any(|c| matches!(c, TyConstraint::AddCons(_)))
+    }
+
+    #[test]
+    fn differences_of_pointers_are_bounded_by_ptrdiff_t() {
+        let ctx = context(&["RDI", "RSI"], true);
+
+        let difference = binop(BinOpType::IntSub, var("RDI"), var("RSI"));
+        let (repr, cons) = evaluate(&ctx, &difference);
+        assert!(bounded_by(&cons, "ptrdiff_t").contains(&repr));
+        // the operands are still related by the subtraction
+        assert!(has_add_constraint(&cons));
+
+        let count = binop(BinOpType::IntSRight, difference, constant(3));
+        let (repr, cons) = evaluate(&ctx, &count);
+        assert!(bounded_by(&cons, "ptrdiff_t").contains(&repr));
+        assert!(has_add_constraint(&cons));
+    }
+
+    #[test]
+    fn differences_need_both_operands_to_be_pointers() {
+        let ctx = context(&["RDI"], true);
+
+        let difference = binop(BinOpType::IntSub, var("RDI"), var("RSI"));
+        let (_, cons) = evaluate(&ctx, &difference);
+        assert!(bounded_by(&cons, "ptrdiff_t").is_empty());
+
+        let count = binop(BinOpType::IntSDiv, difference, constant(8));
+        let (_, cons) = evaluate(&ctx, &count);
+        assert!(bounded_by(&cons, "ptrdiff_t").is_empty());
+    }
+
+    #[test]
+    fn pointer_integers_are_only_typed_when_enabled() {
+        let ctx = context(&["RDI", "RSI"], false);
+
+        let difference = binop(BinOpType::IntSub, var("RDI"), var("RSI"));
+        let (_, cons) = evaluate(&ctx, &difference);
+        assert!(bounded_by(&cons, "ptrdiff_t").is_empty());
+
+        let offset = binop(BinOpType::IntMult, var("RCX"), constant(8));
+        let (_, cons) = evaluate(&ctx, &offset);
+        assert!(bounded_by(&cons, "size_t").is_empty());
+    }
+
+    #[test]
+    fn indices_scaled_by_the_pointer_size_are_sizes() {
+        let ctx = context(&[], true);
+        let index = DerivedTypeVar::new(TypeVariable::new("RCX".to_owned()));
+
+        for offset in [
+            binop(BinOpType::IntMult, var("RCX"), constant(8)),
+            binop(BinOpType::IntLeft, var("RCX"), constant(3)),
+        ]
+        .iter()
+        {
+            let (repr, cons) = evaluate(&ctx, offset);
+            assert!(bounded_by(&cons, "size_t").contains(&index));
+            assert!(bounded_by(&cons, "weak_integer").contains(&repr));
+        }
+
+        let (_, cons) = evaluate(&ctx, &binop(BinOpType::IntMult, var("RCX"), constant(4)));
+        assert!(bounded_by(&cons, "size_t").is_empty());
+    }
+}
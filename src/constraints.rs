@@ -7,14 +7,14 @@ use cwe_checker_lib::intermediate_representation::Tid;
 use nom::branch::alt;
 use nom::bytes::complete::take_while;
 use nom::character::complete::{digit1, space0};
-use nom::combinator::map_res;
+use nom::combinator::{map_res, opt, recognize};
 use nom::error::ParseError;
 use nom::multi::{many0, separated_list0};
 use nom::sequence::preceded;
 use nom::{bytes::complete::tag, combinator::map, sequence::tuple, IResult};
 use nom::{AsChar, InputTakeAtPosition};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::{TryFrom, TryInto};
 use std::fmt::{Debug, Display, Write};
 use std::iter::FromIterator;
@@ -22,6 +22,9 @@ use std::num::ParseIntError;
 use std::ops::{Deref, DerefMut};
 use std::vec::Vec;
 
+/// Conversion to and from the constraint json read by the retypd reference implementation.
+pub mod retypd;
+
 fn identifier_char<T, E: ParseError<T>>(input: T) -> IResult<T, T, E>
 where
     T: InputTakeAtPosition,
@@ -86,6 +89,14 @@ fn parse_field_field(input: &str) -> IResult<&str, FieldLabel> {
     )(input)
 }
 
+fn parse_element_field(input: &str) -> IResult<&str, FieldLabel> {
+    //σ{}@*
+    map_res::<_, _, _, _, ParseIntError, _, _>(
+        tuple((tag("σ"), digit1, tag("@*"))),
+        |(_, field_size, _): (&str, &str, &str)| Ok(FieldLabel::Element(field_size.parse()?)),
+    )(input)
+}
+
 fn parse_add_field(input: &str) -> IResult<&str, FieldLabel> {
     map_res::<_, _, _, _, ParseIntError, _, _>(preceded(tag("+"), digit1), |x: &str| {
         let cons = x.parse()?;
@@ -100,6 +111,33 @@ fn parse_in_field(input: &str) -> IResult<&str, FieldLabel> {
     })(input)
 }
 
+fn parse_custom_field(input: &str) -> IResult<&str, FieldLabel> {
+    //@kind(args) or @-kind(args)
+    map_res::<_, _, _, _, ParseIntError, _, _>(
+        tuple((
+            tag("@"),
+            opt(tag("-")),
+            parse_identifier,
+            tag("("),
+            separated_list0(tag(","), recognize(preceded(opt(tag("-")), digit1))),
+            tag(")"),
+        )),
+        |(_, contra, kind, _, args, _): (&str, Option<&str>, &str, &str, Vec<&str>, &str)| {
+            let lbl = CustomLabel::new(
+                kind.to_owned(),
+                args.into_iter()
+                    .map(|arg| arg.parse())
+                    .collect::<Result<Vec<i64>, _>>()?,
+            );
+            Ok(FieldLabel::Custom(if contra.is_some() {
+                lbl.contravariant()
+            } else {
+                lbl
+            }))
+        },
+    )(input)
+}
+
 /// Parses a field lable that occurs after a "." to create a derived type variable.
 pub fn parse_field_label(input: &str) -> IResult<&str, FieldLabel> {
     alt((
@@ -108,7 +146,9 @@ pub fn parse_field_label(input: &str) -> IResult<&str, FieldLabel> {
         map(tag("out"), |_| FieldLabel::Out(0)),
         parse_in_field,
         parse_field_field,
+        parse_element_field,
         parse_add_field,
+        parse_custom_field,
     ))(input)
 }
 
@@ -235,6 +275,23 @@ impl TypeVariable {
         }
     }
 
+    /// The namespace of the variable if it was generated by a [VariableManager] rather than named after the program, ie.
+    /// `τ` for `τ3` and `τ$sub_a$` for `τ$sub_a$3`, which was generated in the scope of `sub_a`.
+    fn generated_prefix(&self) -> Option<&str> {
+        [FRESH_PREFIX, LOOP_BREAKER_PREFIX]
+            .iter()
+            .find_map(|prefix| {
+                let rest = self.name.strip_prefix(prefix)?;
+                let idx = if rest.starts_with('$') {
+                    &rest[rest.rfind('$')? + 1..]
+                } else {
+                    rest
+                };
+                (!idx.is_empty() && idx.chars().all(|c| c.is_ascii_digit()))
+                    .then(|| &self.name[..self.name.len() - idx.len()])
+            })
+    }
+
     /// Gets the string of the identifier for this type variable.
     pub fn get_name(&self) -> String {
         if let Some(cs_tag) = &self.cs_tag {
@@ -252,10 +309,31 @@ impl Display for TypeVariable {
     }
 }
 
+const FRESH_PREFIX: &str = "τ";
+const LOOP_BREAKER_PREFIX: &str = "loop_breaker";
+
+/// The counts of the variables generated in the scope of a term.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ScopeCounter {
+    fresh: u64,
+    loop_breakers: u64,
+}
+
+fn scoped_name(prefix: &str, scope: &Tid, idx: u64) -> String {
+    format!("{}${}${}", prefix, scope.get_str_repr(), idx)
+}
+
 /// Manages ephemeral type variables
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VariableManager {
     curr_id: u64,
     lb_count: u64,
+    #[serde(default)]
+    function_scopes: bool,
+    #[serde(default)]
+    scopes: BTreeMap<Tid, ScopeCounter>,
+    #[serde(default)]
+    current_scope: Option<Tid>,
 }
 
 impl VariableManager {
@@ -264,21 +342,87 @@ impl VariableManager {
         VariableManager {
             curr_id: 0,
             lb_count: 0,
+            function_scopes: false,
+            scopes: BTreeMap::new(),
+            current_scope: None,
         }
     }
 
+    /// Sets whether constraint generation and simplification generate the variables of each function in the scope of
+    /// the function, see [VariableManager::set_scope].
+    pub fn with_function_scopes(mut self, function_scopes: bool) -> VariableManager {
+        self.function_scopes = function_scopes;
+        self
+    }
+
+    /// Whether the variables of each function are generated in the scope of the function.
+    pub fn uses_function_scopes(&self) -> bool {
+        self.function_scopes
+    }
+
+    /// Generates the following variables in the namespace of a term, ie. a function, or globally when None. Scoped
+    /// variables are named `τ$scope$n` and `loop_breaker$scope$n` and are counted per scope, so the names do not
+    /// depend on the variables generated for other terms. Returns the previous scope.
+    pub fn set_scope(&mut self, scope: Option<Tid>) -> Option<Tid> {
+        std::mem::replace(&mut self.current_scope, scope)
+    }
+
+    /// Gets the scope variables are currently generated in.
+    pub fn get_scope(&self) -> Option<&Tid> {
+        self.current_scope.as_ref()
+    }
+
+    /// Gets the variables generated in the scope of a term.
+    pub fn scope_variables(&self, scope: &Tid) -> Vec<TypeVariable> {
+        self.scopes
+            .get(scope)
+            .map(|counter| {
+                (0..counter.fresh)
+                    .map(|idx| scoped_name(FRESH_PREFIX, scope, idx))
+                    .chain(
+                        (0..counter.loop_breakers)
+                            .map(|idx| scoped_name(LOOP_BREAKER_PREFIX, scope, idx)),
+                    )
+                    .map(TypeVariable::new)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Frees the scope of a term, returning the variables generated in it. Variables generated in the scope
+    /// afterwards reuse the names, so constraints regenerated for the term are named as before.
+    pub fn free_scope(&mut self, scope: &Tid) -> Vec<TypeVariable> {
+        let variables = self.scope_variables(scope);
+        self.scopes.remove(scope);
+        variables
+    }
+
     /// Creates a fresh [TypeVariable] of the form τn where n is the count of fresh variables from this manager.
     pub fn fresh(&mut self) -> TypeVariable {
-        let next_name = format!("τ{}", self.curr_id);
+        if let Some(scope) = &self.current_scope {
+            let counter = self.scopes.entry(scope.clone()).or_default();
+            let name = scoped_name(FRESH_PREFIX, scope, counter.fresh);
+            counter.fresh += 1;
+            return TypeVariable::new(name);
+        }
+
+        let next_name = format!("{}{}", FRESH_PREFIX, self.curr_id);
         self.curr_id += 1;
         TypeVariable::new(next_name)
     }
 
     /// Generates a fresh [TypeVariable] in the loop breaker namespace. These are seperately counted identifiers.
     pub fn fresh_loop_breaker(&mut self) -> TypeVariable {
-        let next_name = format!("loop_breaker{}", self.curr_id);
-        self.curr_id += 1;
         self.lb_count += 1;
+        if let Some(scope) = &self.current_scope {
+            let counter = self.scopes.entry(scope.clone()).or_default();
+            let name = scoped_name(LOOP_BREAKER_PREFIX, scope, counter.loop_breakers);
+            counter.loop_breakers += 1;
+            return TypeVariable::new(name);
+        }
+
+        let next_name = format!("{}{}", LOOP_BREAKER_PREFIX, self.curr_id);
+        self.curr_id += 1;
         TypeVariable::new(next_name)
     }
 
@@ -316,6 +460,56 @@ impl Display for Field {
     }
 }
 
+/// An experimental label that the solver does not interpret, ie. an array index or a bit range. Custom labels let
+/// new capabilities be prototyped without extending every match over [FieldLabel]. The solver treats a custom label as
+/// an opaque capability with the given variance: two labels are the same capability only if their kind, arguments and
+/// variance are equal. Lowering does not reflect custom labels in the lowered types, except for the nullability
+/// capabilities of [crate::analysis::nullability].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct CustomLabel {
+    /// The kind of the label, ie. `index` or `bits`
+    pub kind: String,
+    /// The arguments of the label, ie. the first bit and the width of a bit range
+    pub args: Vec<i64>,
+    /// The variance of the label
+    pub variance: Variance,
+}
+
+impl CustomLabel {
+    /// Creates a covariant custom label.
+    pub fn new(kind: String, args: Vec<i64>) -> CustomLabel {
+        CustomLabel {
+            kind,
+            args,
+            variance: Variance::Covariant,
+        }
+    }
+
+    /// Makes the label contravariant.
+    pub fn contravariant(mut self) -> CustomLabel {
+        self.variance = Variance::Contravariant;
+        self
+    }
+}
+
+/// Custom labels are written `@kind(args)`, or `@-kind(args)` when contravariant.
+impl Display for CustomLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_char('@')?;
+        if self.variance == Variance::Contravariant {
+            f.write_char('-')?;
+        }
+        write!(f, "{}(", self.kind)?;
+        for (idx, arg) in self.args.iter().enumerate() {
+            if idx > 0 {
+                f.write_char(',')?;
+            }
+            write!(f, "{}", arg)?;
+        }
+        f.write_char(')')
+    }
+}
+
 /// This function has an input parameter at the location defined by the parameter index
 /// Note(Ian): In the future if we have our own solvers these locations should be extended to be more
 /// general than indeces.
@@ -338,8 +532,12 @@ pub enum FieldLabel {
     Out(usize),
     /// A field with the specified bit width and byte offset
     Field(Field),
+    /// An element with the specified bit width at an unknown byte offset, ie. an access through a variable index
+    Element(usize),
     ///The type variable with the addition of a constant offset
     Add(i128),
+    /// An experimental label the solver treats as an opaque capability, see [CustomLabel]
+    Custom(CustomLabel),
 }
 
 impl FieldLabel {
@@ -349,9 +547,11 @@ impl FieldLabel {
             Self::Load => Variance::Covariant,
             Self::Store => Variance::Contravariant,
             Self::Field(_) => Variance::Covariant,
+            Self::Element(_) => Variance::Covariant,
             Self::In(_) => Variance::Contravariant,
             Self::Out(_) => Variance::Covariant,
             Self::Add(_) => Variance::Covariant,
+            Self::Custom(lbl) => lbl.variance.clone(),
         }
     }
 }
@@ -365,10 +565,12 @@ impl Display for FieldLabel {
             FieldLabel::In(ind) => f.write_fmt(format_args!("in_{}", ind)),
             FieldLabel::Out(ind) => f.write_fmt(format_args!("out_{}", ind)),
             FieldLabel::Field(field) => write!(f, "{}", field),
+            FieldLabel::Element(size) => write!(f, "σ{}@*", size),
+            FieldLabel::Custom(lbl) => write!(f, "{}", lbl),
         }
     }
 }
-#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Alga, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Alga, Debug, Deserialize, Serialize)]
 #[alga_traits(Monoid(Multiplicative))]
 /// Variance describes the relation between a field and a subtyping relation. Variance forms a multiplicative sign monoid such that
 /// cov*cov = cov, contra*contra=cov, contra*cov=cov*contra=contra
@@ -584,6 +786,10 @@ impl TryFrom<pb_constraints::FieldLabel> for FieldLabel {
                 let fld = Field::try_from(fld)?;
                 Ok(FieldLabel::Field(fld))
             }
+            pb_constraints::field_label::InnerType::Element(size) => {
+                let size = size.try_into()?;
+                Ok(FieldLabel::Element(size))
+            }
         }
     }
 }
@@ -639,12 +845,128 @@ impl TryFrom<pb_constraints::AdditionalConstraint> for AdditionalConstraint {
 }
 
 /// Expresses a subtyping constraint of the form lhs ⊑ rhs
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SubtypeConstraint {
     /// The left hand side of the subtyping constraint
     pub lhs: DerivedTypeVar,
     /// The right hand side of the subtyping constraint
     pub rhs: DerivedTypeVar,
+    /// Where the constraint was generated, if known. The origin does not take part in comparisons, so a set keeps the
+    /// origin of the first copy of a constraint inserted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<ConstraintOrigin>,
+}
+
+impl PartialEq for SubtypeConstraint {
+    fn eq(&self, other: &Self) -> bool {
+        self.lhs == other.lhs && self.rhs == other.rhs
+    }
+}
+
+impl Eq for SubtypeConstraint {}
+
+impl PartialOrd for SubtypeConstraint {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SubtypeConstraint {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (&self.lhs, &self.rhs).cmp(&(&other.lhs, &other.rhs))
+    }
+}
+
+/// The kind of program construct a constraint was generated for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum ConstraintReason {
+    /// An assignment of an expression to a register
+    Assign,
+    /// A load from memory into a register
+    Load,
+    /// A store of a value to memory
+    Store,
+    /// A formal parameter of a subprocedure
+    FormalParameter,
+    /// A formal return of a subprocedure
+    FormalReturn,
+    /// An actual parameter passed at a callsite
+    CallActualParameter,
+    /// An actual return received at a callsite
+    CallActualReturn,
+    /// A constraint supplied as an additional constraint of a job
+    Additional,
+    /// A constraint translated from a type assumption
+    Assumption,
+    /// A constraint added by a constraint transformer
+    Transform,
+    /// A low confidence constraint guessed from the name of a symbol or parameter
+    Heuristic,
+    /// A constraint from the signature of a platform, syscall, or SDK function
+    Platform,
+    /// A constraint seeding a relocated word of the binary as a pointer
+    Relocation,
+}
+
+impl Display for ConstraintReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConstraintReason::Assign => "assign",
+            ConstraintReason::Load => "load",
+            ConstraintReason::Store => "store",
+            ConstraintReason::FormalParameter => "formal-param",
+            ConstraintReason::FormalReturn => "formal-return",
+            ConstraintReason::CallActualParameter => "call-actual-param",
+            ConstraintReason::CallActualReturn => "call-actual-return",
+            ConstraintReason::Additional => "additional",
+            ConstraintReason::Assumption => "assumption",
+            ConstraintReason::Transform => "transform",
+            ConstraintReason::Heuristic => "heuristic",
+            ConstraintReason::Platform => "platform",
+            ConstraintReason::Relocation => "relocation",
+        })
+    }
+}
+
+/// The term a constraint was generated for and why.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct ConstraintOrigin {
+    /// The term (def, block, or subprocedure) the constraint was generated for
+    pub tid: Tid,
+    /// The index of the def within its block, if the constraint was generated for a def
+    pub def_index: Option<usize>,
+    /// The kind of construct that produced the constraint
+    pub reason: ConstraintReason,
+}
+
+impl ConstraintOrigin {
+    /// Creates an origin for a term that is not a def.
+    pub fn new(tid: Tid, reason: ConstraintReason) -> ConstraintOrigin {
+        ConstraintOrigin {
+            tid,
+            def_index: None,
+            reason,
+        }
+    }
+
+    /// Creates an origin for the def at def_index in its block.
+    pub fn for_def(tid: Tid, def_index: usize, reason: ConstraintReason) -> ConstraintOrigin {
+        ConstraintOrigin {
+            tid,
+            def_index: Some(def_index),
+            reason,
+        }
+    }
+}
+
+impl Display for ConstraintOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.reason, self.tid)?;
+        if let Some(idx) = self.def_index {
+            write!(f, " (def {})", idx)?;
+        }
+        Ok(())
+    }
 }
 
 impl TryFrom<pb_constraints::SubtypingConstraint> for SubtypeConstraint {
@@ -660,20 +982,35 @@ impl TryFrom<pb_constraints::SubtypingConstraint> for SubtypeConstraint {
             .ok_or(anyhow::anyhow!("No rhs in dtv"))
             .and_then(DerivedTypeVar::try_from)?;
 
-        Ok(SubtypeConstraint { lhs, rhs })
+        Ok(SubtypeConstraint::new(lhs, rhs))
     }
 }
 
 impl SubtypeConstraint {
     /// Create a subtype constraint where lhs is a subtype of rhs.
     pub fn new(lhs: DerivedTypeVar, rhs: DerivedTypeVar) -> SubtypeConstraint {
-        SubtypeConstraint { lhs, rhs }
+        SubtypeConstraint {
+            lhs,
+            rhs,
+            origin: None,
+        }
+    }
+
+    /// Sets where this constraint was generated.
+    pub fn with_origin(mut self, origin: ConstraintOrigin) -> SubtypeConstraint {
+        self.origin = Some(origin);
+        self
     }
 }
 
+/// The alternate format (`{:#}`) also prints the origin of the constraint if it is known.
 impl Display for SubtypeConstraint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} ⊑ {}", self.lhs, self.rhs)
+        write!(f, "{} ⊑ {}", self.lhs, self.rhs)?;
+        match &self.origin {
+            Some(origin) if f.alternate() => write!(f, "  // {}", origin),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -751,9 +1088,42 @@ pub enum TyConstraint {
     AddCons(AddConstraint),
 }
 
+impl TyConstraint {
+    pub(crate) fn base_variables(&self) -> Vec<&TypeVariable> {
+        match self {
+            TyConstraint::SubTy(sub) => vec![&sub.lhs.var, &sub.rhs.var],
+            TyConstraint::AddCons(add_cons) => vec![
+                &add_cons.lhs_ty.var,
+                &add_cons.rhs_ty.var,
+                &add_cons.repr_ty.var,
+            ],
+        }
+    }
+
+    fn rename_variables(&self, rename: &impl Fn(&TypeVariable) -> TypeVariable) -> TyConstraint {
+        let rename_dtv = |dtv: &DerivedTypeVar| DerivedTypeVar {
+            var: rename(&dtv.var),
+            labels: dtv.labels.clone(),
+        };
+        match self {
+            TyConstraint::SubTy(sub) => TyConstraint::SubTy(SubtypeConstraint {
+                lhs: rename_dtv(&sub.lhs),
+                rhs: rename_dtv(&sub.rhs),
+                origin: sub.origin.clone(),
+            }),
+            TyConstraint::AddCons(add_cons) => TyConstraint::AddCons(AddConstraint::new(
+                rename_dtv(&add_cons.lhs_ty),
+                rename_dtv(&add_cons.rhs_ty),
+                rename_dtv(&add_cons.repr_ty),
+            )),
+        }
+    }
+}
+
 impl Display for TyConstraint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::SubTy(sub) if f.alternate() => write!(f, "{:#}", sub),
             Self::SubTy(sub) => write!(f, "{}", sub),
             Self::AddCons(add_cons) => write!(f, "{}", add_cons),
         }
@@ -768,6 +1138,21 @@ impl ConstraintSet {
         });
     }
 
+    /// Records the origin on every subtyping constraint that does not already have one.
+    pub fn with_origin(self, origin: &ConstraintOrigin) -> ConstraintSet {
+        ConstraintSet(
+            self.0
+                .into_iter()
+                .map(|cons| match cons {
+                    TyConstraint::SubTy(sub) if sub.origin.is_none() => {
+                        TyConstraint::SubTy(sub.with_origin(origin.clone()))
+                    }
+                    _ => cons,
+                })
+                .collect(),
+        )
+    }
+
     /// A singleton constraint set with one subtyping relation.
     pub fn singleton(cons: SubtypeConstraint) -> ConstraintSet {
         let mut emp = ConstraintSet::empty();
@@ -779,6 +1164,104 @@ impl ConstraintSet {
     pub fn empty() -> ConstraintSet {
         ConstraintSet(BTreeSet::new())
     }
+
+    /// Prefixes the names of the variables local to the constraints with namespace, so they no longer unify with the
+    /// variables of the same name generated elsewhere. Globals and variables tagged by a callsite are shared across
+    /// functions and keep their names.
+    pub fn in_namespace(&self, namespace: &str) -> ConstraintSet {
+        ConstraintSet(
+            self.0
+                .iter()
+                .map(|cons| {
+                    cons.rename_variables(&|tv| {
+                        if tv.is_global() || tv.get_cs_tag().is_some() {
+                            tv.clone()
+                        } else {
+                            TypeVariable {
+                                name: format!("{}{}", namespace, tv.name),
+                                ..tv.clone()
+                            }
+                        }
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// Renames the variables that are keys of renaming to the variable they map to, leaving the others as they are.
+    pub fn rename(&self, renaming: &BTreeMap<TypeVariable, TypeVariable>) -> ConstraintSet {
+        ConstraintSet(
+            self.0
+                .iter()
+                .map(|cons| {
+                    cons.rename_variables(&|tv| {
+                        renaming.get(tv).cloned().unwrap_or_else(|| tv.clone())
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// Renames the variables generated by a [VariableManager] to canonical names so that dumps of the same constraints
+    /// from different runs can be diffed. Generated variables are numbered in the order they are first used, visiting
+    /// constraints by the term they were generated for and then by their text with generated names masked, so the names
+    /// do not depend on the order variables happened to be generated in. Renaming is consistent within the set only.
+    pub fn canonicalize(&self) -> ConstraintSet {
+        let masked = |tv: &TypeVariable| match tv.generated_prefix() {
+            Some(prefix) => TypeVariable {
+                name: prefix.to_owned(),
+                ..tv.clone()
+            },
+            None => tv.clone(),
+        };
+
+        let mut ordered = self
+            .0
+            .iter()
+            .map(|cons| {
+                let origin = match cons {
+                    TyConstraint::SubTy(sub) => sub
+                        .origin
+                        .as_ref()
+                        .map(|origin| (origin.tid.clone(), origin.def_index)),
+                    TyConstraint::AddCons(_) => None,
+                };
+                ((origin, cons.rename_variables(&masked).to_string()), cons)
+            })
+            .collect::<Vec<_>>();
+        // constraints that differ only in generated names keep their relative order
+        ordered.sort_by(|(fst, _), (snd, _)| fst.cmp(snd));
+
+        let mut renaming = BTreeMap::new();
+        let mut counts = BTreeMap::new();
+        for tv in ordered
+            .iter()
+            .flat_map(|(_, cons)| cons.base_variables().into_iter())
+        {
+            if let (Some(prefix), false) = (tv.generated_prefix(), renaming.contains_key(tv)) {
+                let count = counts.entry(prefix).or_insert(0);
+                renaming.insert(
+                    tv.clone(),
+                    TypeVariable {
+                        name: format!("{}{}", prefix, count),
+                        ..tv.clone()
+                    },
+                );
+                *count += 1;
+            }
+        }
+
+        ConstraintSet(
+            self.0
+                .iter()
+                .map(|cons| {
+                    cons.rename_variables(&|tv| {
+                        renaming.get(tv).cloned().unwrap_or_else(|| tv.clone())
+                    })
+                })
+                .collect(),
+        )
+    }
 }
 
 impl From<BTreeSet<TyConstraint>> for ConstraintSet {
@@ -790,7 +1273,11 @@ impl From<BTreeSet<TyConstraint>> for ConstraintSet {
 impl Display for ConstraintSet {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for cons in self.0.iter() {
-            writeln!(f, "{}", cons)?;
+            if f.alternate() {
+                writeln!(f, "{:#}", cons)?;
+            } else {
+                writeln!(f, "{}", cons)?;
+            }
         }
 
         Ok(())
@@ -815,9 +1302,12 @@ impl DerefMut for ConstraintSet {
 mod test {
     use crate::constraints::TyConstraint;
 
+    use cwe_checker_lib::intermediate_representation::Tid;
+
     use super::{
-        parse_derived_type_variable, parse_subtype_cons, DerivedTypeVar, FieldLabel,
-        SubtypeConstraint, TypeVariable,
+        parse_constraint_set, parse_derived_type_variable, parse_subtype_cons, parse_type_variable,
+        ConstraintOrigin, ConstraintReason, ConstraintSet, CustomLabel, DerivedTypeVar, FieldLabel,
+        SubtypeConstraint, TypeVariable, VariableManager, Variance,
     };
 
     #[test]
@@ -835,6 +1325,29 @@ mod test {
         assert_eq!(Ok(("", dt)), parse_derived_type_variable("x.load"));
     }
 
+    #[test]
+    fn parse_element_dt_var() {
+        let mut dt = DerivedTypeVar::new(TypeVariable::new("x".to_owned()));
+        dt.add_field_label(FieldLabel::Element(32));
+        assert_eq!(dt.to_string(), "x.σ32@*");
+        assert_eq!(Ok(("", dt)), parse_derived_type_variable("x.σ32@*"));
+    }
+
+    #[test]
+    fn parse_custom_dt_var() {
+        let mut dt = DerivedTypeVar::new(TypeVariable::new("x".to_owned()));
+        dt.add_field_label(FieldLabel::Load);
+        dt.add_field_label(FieldLabel::Custom(
+            CustomLabel::new("bits".to_owned(), vec![0, -4]).contravariant(),
+        ));
+        assert_eq!(dt.to_string(), "x.load.@-bits(0,-4)");
+        assert_eq!(dt.path_variance(), Variance::Contravariant);
+        assert_eq!(
+            Ok(("", dt)),
+            parse_derived_type_variable("x.load.@-bits(0,-4)")
+        );
+    }
+
     #[test]
     fn parse_simple_constraint() {
         assert_eq!(
@@ -881,4 +1394,92 @@ mod test {
             parse_subtype_cons("file_descriptor <= sub_00001000.in_0"),
         );
     }
+
+    #[test]
+    fn origins_are_kept_but_not_compared() {
+        let cons = SubtypeConstraint::new(
+            DerivedTypeVar::new(TypeVariable::new("a".to_owned())),
+            DerivedTypeVar::new(TypeVariable::new("b".to_owned())),
+        );
+        let origin = ConstraintOrigin::for_def(
+            Tid::create("instr_1000_1".to_owned(), "1000".to_owned()),
+            1,
+            ConstraintReason::Load,
+        );
+
+        let with_origin = ConstraintSet::singleton(cons.clone()).with_origin(&origin);
+        assert_eq!(with_origin, ConstraintSet::singleton(cons));
+        assert_eq!(format!("{}", with_origin), "a ⊑ b\n");
+        assert_eq!(
+            format!("{:#}", with_origin),
+            format!("a ⊑ b  // load at {} (def 1)\n", origin.tid)
+        );
+    }
+
+    #[test]
+    fn canonical_names_do_not_depend_on_generation_order() {
+        let (_, fst) =
+            parse_constraint_set("τ5 <= sub_1.in_0 τ9.load <= τ5 loop_breaker7 <= τ9").unwrap();
+        let (_, snd) =
+            parse_constraint_set("τ2 <= sub_1.in_0 τ0.load <= τ2 loop_breaker1 <= τ0").unwrap();
+        let (_, expected) =
+            parse_constraint_set("loop_breaker0 <= τ0 τ0.load <= τ1 τ1 <= sub_1.in_0").unwrap();
+
+        assert_eq!(fst.canonicalize(), expected);
+        assert_eq!(snd.canonicalize(), expected);
+    }
+
+    #[test]
+    fn canonical_names_number_scoped_variables_within_their_scope() {
+        let mut vman = VariableManager::new().with_function_scopes(true);
+        vman.set_scope(Some(Tid::create("sub_a".to_owned(), "1000".to_owned())));
+        vman.fresh();
+        let fst = vman.fresh();
+        let snd = vman.fresh();
+        vman.fresh_loop_breaker();
+        let lb = vman.fresh_loop_breaker();
+
+        let (_, cs) = parse_constraint_set(&format!(
+            "{} <= sub_a.in_0 {}.load <= {} {} <= {}",
+            snd, fst, snd, lb, fst
+        ))
+        .unwrap();
+        let (_, expected) = parse_constraint_set(
+            "loop_breaker$sub_a$0 <= τ$sub_a$0 τ$sub_a$0.load <= τ$sub_a$1 τ$sub_a$1 <= sub_a.in_0",
+        )
+        .unwrap();
+        assert_eq!(cs.canonicalize(), expected);
+    }
+
+    #[test]
+    fn scoped_variables_are_counted_per_function() {
+        let sub_a = Tid::create("sub_a".to_owned(), "1000".to_owned());
+        let sub_b = Tid::create("sub_b".to_owned(), "2000".to_owned());
+        let mut vman = VariableManager::new();
+
+        assert_eq!(vman.fresh().get_name(), "τ0");
+        vman.set_scope(Some(sub_a.clone()));
+        assert_eq!(vman.fresh().get_name(), "τ$sub_a$0");
+        assert_eq!(vman.fresh_loop_breaker().get_name(), "loop_breaker$sub_a$0");
+        assert_eq!(vman.set_scope(Some(sub_b.clone())), Some(sub_a.clone()));
+        assert_eq!(vman.fresh().get_name(), "τ$sub_b$0");
+        vman.set_scope(None);
+        assert_eq!(vman.fresh().get_name(), "τ1");
+
+        // scoped names are still identifiers
+        let (rest, parsed) = parse_type_variable("τ$sub_a$0").unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed.get_name(), "τ$sub_a$0");
+
+        let freed = vman.free_scope(&sub_a);
+        assert_eq!(
+            freed.iter().map(|tv| tv.get_name()).collect::<Vec<_>>(),
+            vec!["τ$sub_a$0", "loop_breaker$sub_a$0"]
+        );
+        assert!(vman.scope_variables(&sub_a).is_empty());
+        assert_eq!(vman.scope_variables(&sub_b).len(), 1);
+        vman.set_scope(Some(sub_a));
+        assert_eq!(vman.fresh().get_name(), "τ$sub_a$0");
+        assert_eq!(vman.num_generated_loop_breakers(), 1);
+    }
 }
@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Context};
+use cwe_checker_lib::intermediate_representation::Tid;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ConstraintSet, DerivedTypeVar, Field, FieldLabel, SubtypeConstraint, TyConstraint, TypeVariable,
+};
+
+/// A constraint program in the json format produced by the retypd Ghidra plugin and read by retypd.
+/// Constraints are grouped by the procedure they were generated for, and the callgraph names the callees of each procedure
+/// so that retypd can instantiate callee schemes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetypdProgram {
+    /// The architecture the constraints were generated for
+    #[serde(default)]
+    pub language: String,
+    /// Constraints in retypd syntax, ie. "F.in_0.load.σ4@0 ⊑ int", by procedure
+    pub constraints: BTreeMap<String, Vec<String>>,
+    /// The callees of each procedure
+    #[serde(default)]
+    pub callgraph: BTreeMap<String, Vec<String>>,
+}
+
+/// Writes a field label in retypd syntax. Retypd measures field sizes in bytes and has no notion of
+/// elements at unknown offsets, additions or custom labels, so those labels cannot be exported.
+pub fn field_label_to_retypd(lbl: &FieldLabel) -> anyhow::Result<String> {
+    match lbl {
+        FieldLabel::Load => Ok("load".to_owned()),
+        FieldLabel::Store => Ok("store".to_owned()),
+        FieldLabel::In(idx) => Ok(format!("in_{}", idx)),
+        FieldLabel::Out(idx) => Ok(format!("out_{}", idx)),
+        FieldLabel::Field(fld) => {
+            if fld.size % 8 != 0 {
+                bail!("field {} is not a whole number of bytes", fld);
+            }
+            Ok(format!("σ{}@{}", fld.size / 8, fld.offset))
+        }
+        FieldLabel::Element(_) | FieldLabel::Add(_) | FieldLabel::Custom(_) => {
+            Err(anyhow!("{} has no retypd equivalent", lbl))
+        }
+    }
+}
+
+// Callsite tags are written after the name as `:id@address`, which keeps the instantiations of a callee at different
+// callsites distinct and lets the tag be read back.
+fn type_variable_to_retypd(tv: &TypeVariable) -> String {
+    let callee = tv.to_callee().get_name();
+    match tv.get_cs_tag() {
+        Some(tag) => format!("{}:{}@{}", callee, tag.get_str_repr(), tag.address),
+        None => callee,
+    }
+}
+
+fn parse_retypd_type_variable(base: &str) -> anyhow::Result<TypeVariable> {
+    match base.split_once(':') {
+        Some((name, tag)) => {
+            let (id, address) = tag
+                .split_once('@')
+                .ok_or_else(|| anyhow!("callsite tag {} has no address", tag))?;
+            Ok(TypeVariable::with_tag(
+                name.to_owned(),
+                Tid::create(id.to_owned(), address.to_owned()),
+            ))
+        }
+        None => Ok(TypeVariable::new(base.to_owned())),
+    }
+}
+
+/// Writes a derived type variable in retypd syntax. Callsite tags are kept as a `:id@address` suffix of the name.
+pub fn dtv_to_retypd(dtv: &DerivedTypeVar) -> anyhow::Result<String> {
+    let mut res = type_variable_to_retypd(dtv.get_base_variable());
+    for lbl in dtv.get_field_labels() {
+        res.push('.');
+        res.push_str(&field_label_to_retypd(lbl)?);
+    }
+    Ok(res)
+}
+
+/// Writes a subtyping constraint in retypd syntax.
+pub fn constraint_to_retypd(cons: &SubtypeConstraint) -> anyhow::Result<String> {
+    Ok(format!(
+        "{} ⊑ {}",
+        dtv_to_retypd(&cons.lhs)?,
+        dtv_to_retypd(&cons.rhs)?
+    ))
+}
+
+/// Parses a field label in retypd syntax.
+pub fn parse_retypd_field_label(lbl: &str) -> anyhow::Result<FieldLabel> {
+    match lbl {
+        "load" => return Ok(FieldLabel::Load),
+        "store" => return Ok(FieldLabel::Store),
+        "out" => return Ok(FieldLabel::Out(0)),
+        _ => (),
+    }
+
+    if let Some(idx) = lbl.strip_prefix("in_") {
+        return Ok(FieldLabel::In(idx.parse()?));
+    }
+
+    if let Some(idx) = lbl.strip_prefix("out_") {
+        return Ok(FieldLabel::Out(idx.parse()?));
+    }
+
+    if let Some((size, offset)) = lbl.strip_prefix('σ').and_then(|fld| fld.split_once('@')) {
+        let size: usize = size.parse()?;
+        return Ok(FieldLabel::Field(Field::new(offset.parse()?, size * 8)));
+    }
+
+    Err(anyhow!("unknown retypd field label {}", lbl))
+}
+
+/// Parses a derived type variable in retypd syntax.
+pub fn parse_retypd_dtv(dtv: &str) -> anyhow::Result<DerivedTypeVar> {
+    let mut parts = dtv.split('.');
+    let base = parts
+        .next()
+        .filter(|base| !base.is_empty())
+        .ok_or_else(|| anyhow!("missing base variable in {}", dtv))?;
+    let labels = parts
+        .map(parse_retypd_field_label)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(DerivedTypeVar::create_with_path(
+        parse_retypd_type_variable(base)?,
+        labels,
+    ))
+}
+
+/// Parses a subtyping constraint in retypd syntax, accepting either ⊑ or <= as the relation.
+pub fn parse_retypd_constraint(cons: &str) -> anyhow::Result<SubtypeConstraint> {
+    let (lhs, rhs) = cons
+        .split_once('⊑')
+        .or_else(|| cons.split_once("<="))
+        .ok_or_else(|| anyhow!("{} is not a subtyping constraint", cons))?;
+    Ok(SubtypeConstraint::new(
+        parse_retypd_dtv(lhs.trim())?,
+        parse_retypd_dtv(rhs.trim())?,
+    ))
+}
+
+impl RetypdProgram {
+    /// Exports constraint sets by procedure name. Fails if a constraint cannot be expressed in retypd, additive constraints
+    /// are skipped since retypd does not model them.
+    pub fn from_constraints(
+        language: &str,
+        constraints: &BTreeMap<String, ConstraintSet>,
+        callgraph: BTreeMap<String, Vec<String>>,
+    ) -> anyhow::Result<RetypdProgram> {
+        let constraints = constraints
+            .iter()
+            .map(|(proc, cons)| {
+                let exported = cons
+                    .iter()
+                    .filter_map(|c| match c {
+                        TyConstraint::SubTy(sub) => Some(constraint_to_retypd(sub)),
+                        TyConstraint::AddCons(_) => None,
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()
+                    .with_context(|| format!("exporting constraints of {}", proc))?;
+                Ok((proc.clone(), exported))
+            })
+            .collect::<anyhow::Result<BTreeMap<_, _>>>()?;
+
+        Ok(RetypdProgram {
+            language: language.to_owned(),
+            constraints,
+            callgraph,
+        })
+    }
+
+    /// Imports the constraints of each procedure.
+    pub fn to_constraints(&self) -> anyhow::Result<BTreeMap<String, ConstraintSet>> {
+        self.constraints
+            .iter()
+            .map(|(proc, cons)| {
+                let imported = cons
+                    .iter()
+                    .map(|c| parse_retypd_constraint(c).map(TyConstraint::SubTy))
+                    .collect::<anyhow::Result<_>>()
+                    .with_context(|| format!("importing constraints of {}", proc))?;
+                Ok((proc.clone(), ConstraintSet(imported)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use crate::constraints::{
+        parse_constraint_set, parse_derived_type_variable, DerivedTypeVar, FieldLabel, TypeVariable,
+    };
+
+    use super::{dtv_to_retypd, parse_retypd_constraint, parse_retypd_dtv, RetypdProgram};
+
+    #[test]
+    fn constraints_roundtrip_through_retypd() {
+        let (_, cons) = parse_constraint_set(
+            "
+            sub_1.in_0.load.σ32@4 <= int
+            sub_1.out <= sub_2.in_1.store
+            ",
+        )
+        .unwrap();
+        let mut by_proc = BTreeMap::new();
+        by_proc.insert("sub_1".to_owned(), cons);
+
+        let program = RetypdProgram::from_constraints("x86/64", &by_proc, BTreeMap::new()).unwrap();
+        assert!(program.constraints["sub_1"].contains(&"sub_1.in_0.load.σ4@4 ⊑ int".to_owned()));
+        assert_eq!(program.to_constraints().unwrap(), by_proc);
+
+        let parsed = parse_retypd_constraint("F.out <= G.in_2").unwrap();
+        assert_eq!(dtv_to_retypd(&parsed.lhs).unwrap(), "F.out_0");
+    }
+
+    #[test]
+    fn callsite_tags_roundtrip_through_retypd() {
+        let callsite = Tid::create("instr_00401150_1".to_owned(), "00401150".to_owned());
+        let tagged = DerivedTypeVar::create_with_path(
+            TypeVariable::with_tag("sub_2".to_owned(), callsite),
+            vec![FieldLabel::In(0)],
+        );
+
+        let exported = dtv_to_retypd(&tagged).unwrap();
+        assert_eq!(exported, "sub_2:instr_00401150_1@00401150.in_0");
+        assert_eq!(parse_retypd_dtv(&exported).unwrap(), tagged);
+    }
+
+    #[test]
+    fn elements_are_not_exported() {
+        let (_, dtv) = parse_derived_type_variable("x.load.σ32@*").unwrap();
+        assert!(dtv_to_retypd(&dtv).is_err());
+    }
+}
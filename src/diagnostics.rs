@@ -0,0 +1,218 @@
+use std::{cell::RefCell, collections::BTreeMap};
+
+use cwe_checker_lib::intermediate_representation::Tid;
+use serde::{Deserialize, Serialize};
+
+/// What kind of precision loss a diagnostic reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    /// An error reported by a cwe_checker analysis, ie. a negative offset or a stack size mismatch
+    CweChecker,
+    /// A load or store the points-to analysis could not apply
+    PointsTo,
+    /// An expression constraint generation does not model, which is represented by an unconstrained variable
+    UnhandledExpression,
+    /// A sketch node with conflicting lattice evidence
+    LatticeConflict,
+    /// A primitive whose size disagrees with the size of its accesses
+    SizeMismatch,
+    /// A lowered type breaking an invariant of lowering
+    InvariantViolation,
+    /// A type dropped when converting the lowered types to their output
+    DroppedConversion,
+    /// Constraints degraded to stay within the memory budget
+    Degradation,
+    /// An original constraint that its simplified constraints do not entail
+    UnentailedConstraint,
+    /// A union with more members than the maximum union arity collapsed to a primitive
+    CollapsedUnion,
+    /// A function with evidence for more parameters than it declares
+    ParameterCountMismatch,
+    /// An scc whose constraints failed to solve, so its subprocedures get default types
+    SolveFailure,
+    /// A stack slot typed by several fields or types that is output with only one of them
+    FrameSlotConflict,
+    /// Any other warning
+    Other,
+}
+
+impl DiagnosticKind {
+    /// Every kind of diagnostic.
+    pub const ALL: [DiagnosticKind; 14] = [
+        DiagnosticKind::CweChecker,
+        DiagnosticKind::PointsTo,
+        DiagnosticKind::UnhandledExpression,
+        DiagnosticKind::LatticeConflict,
+        DiagnosticKind::SizeMismatch,
+        DiagnosticKind::InvariantViolation,
+        DiagnosticKind::DroppedConversion,
+        DiagnosticKind::Degradation,
+        DiagnosticKind::UnentailedConstraint,
+        DiagnosticKind::CollapsedUnion,
+        DiagnosticKind::ParameterCountMismatch,
+        DiagnosticKind::SolveFailure,
+        DiagnosticKind::FrameSlotConflict,
+        DiagnosticKind::Other,
+    ];
+
+    /// The name the kind is serialized as.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiagnosticKind::CweChecker => "cwe_checker",
+            DiagnosticKind::PointsTo => "points_to",
+            DiagnosticKind::UnhandledExpression => "unhandled_expression",
+            DiagnosticKind::LatticeConflict => "lattice_conflict",
+            DiagnosticKind::SizeMismatch => "size_mismatch",
+            DiagnosticKind::InvariantViolation => "invariant_violation",
+            DiagnosticKind::DroppedConversion => "dropped_conversion",
+            DiagnosticKind::Degradation => "degradation",
+            DiagnosticKind::UnentailedConstraint => "unentailed_constraint",
+            DiagnosticKind::CollapsedUnion => "collapsed_union",
+            DiagnosticKind::ParameterCountMismatch => "parameter_count_mismatch",
+            DiagnosticKind::SolveFailure => "solve_failure",
+            DiagnosticKind::FrameSlotConflict => "frame_slot_conflict",
+            DiagnosticKind::Other => "other",
+        }
+    }
+}
+
+/// A warning of a run, counted over every time it was reported at the same location.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// What kind of precision loss is reported
+    pub kind: DiagnosticKind,
+    /// The term the warning is about, if any
+    pub location: Option<Tid>,
+    /// The text of the warning
+    pub message: String,
+    /// How many times the warning was reported
+    pub count: usize,
+}
+
+/// The warnings collected during a run, so unattended runs can be audited for precision loss.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostics {
+    /// The number of warnings of each kind
+    pub counts: BTreeMap<DiagnosticKind, usize>,
+    /// The distinct warnings, ordered by kind, location, and message
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Default)]
+struct Collector {
+    location: Option<Tid>,
+    reported: BTreeMap<(DiagnosticKind, Option<Tid>, String), usize>,
+}
+
+thread_local! {
+    static COLLECTOR: RefCell<Option<Collector>> = RefCell::new(None);
+}
+
+/// Starts collecting the diagnostics reported on this thread, discarding any collected so far. Diagnostics reported
+/// while not collecting are only logged.
+pub fn start_collecting() {
+    COLLECTOR.with(|collector| *collector.borrow_mut() = Some(Collector::default()));
+}
+
+/// Takes the diagnostics collected on this thread since collecting started or they were last taken.
+pub fn take() -> Diagnostics {
+    let reported = COLLECTOR.with(|collector| {
+        collector
+            .borrow_mut()
+            .as_mut()
+            .map(|collector| std::mem::take(&mut collector.reported))
+            .unwrap_or_default()
+    });
+
+    let mut counts = BTreeMap::new();
+    let diagnostics = reported
+        .into_iter()
+        .map(|((kind, location, message), count)| {
+            *counts.entry(kind).or_insert(0) += count;
+            Diagnostic {
+                kind,
+                location,
+                message,
+                count,
+            }
+        })
+        .collect();
+    Diagnostics {
+        counts,
+        diagnostics,
+    }
+}
+
+/// Runs a function, attributing the diagnostics it reports without a location to a term.
+pub fn with_location<T>(tid: &Tid, f: impl FnOnce() -> T) -> T {
+    let previous = COLLECTOR.with(|collector| {
+        collector
+            .borrow_mut()
+            .as_mut()
+            .and_then(|collector| collector.location.replace(tid.clone()))
+    });
+    let res = f();
+    COLLECTOR.with(|collector| {
+        if let Some(collector) = collector.borrow_mut().as_mut() {
+            collector.location = previous;
+        }
+    });
+    res
+}
+
+/// Reports a diagnostic at a term, or at the term of the enclosing [with_location] when None.
+pub fn report_at(kind: DiagnosticKind, location: Option<&Tid>, message: impl Into<String>) {
+    COLLECTOR.with(|collector| {
+        if let Some(collector) = collector.borrow_mut().as_mut() {
+            let location = location.cloned().or_else(|| collector.location.clone());
+            *collector
+                .reported
+                .entry((kind, location, message.into()))
+                .or_insert(0) += 1;
+        }
+    });
+}
+
+/// Reports a diagnostic at the term of the enclosing [with_location], if any.
+pub fn report(kind: DiagnosticKind, message: impl Into<String>) {
+    report_at(kind, None, message)
+}
+
+#[cfg(test)]
+mod test {
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use super::{report, report_at, start_collecting, take, with_location, DiagnosticKind};
+
+    #[test]
+    fn diagnostics_are_counted_per_location() {
+        // nothing is collected before collecting starts
+        report(DiagnosticKind::Other, "ignored");
+        start_collecting();
+
+        let def = Tid::create("instr_00401000_0".to_owned(), "00401000".to_owned());
+        for _ in 0..2 {
+            with_location(&def, || {
+                report(DiagnosticKind::UnhandledExpression, "Unhandled expression")
+            });
+        }
+        report_at(
+            DiagnosticKind::CweChecker,
+            None,
+            "Negative offset for stack access",
+        );
+
+        let diagnostics = take();
+        assert_eq!(diagnostics.counts[&DiagnosticKind::UnhandledExpression], 2);
+        assert_eq!(diagnostics.counts[&DiagnosticKind::CweChecker], 1);
+        assert!(!diagnostics.counts.contains_key(&DiagnosticKind::Other));
+        assert_eq!(diagnostics.diagnostics.len(), 2);
+        assert_eq!(diagnostics.diagnostics[0].kind, DiagnosticKind::CweChecker);
+        assert_eq!(diagnostics.diagnostics[0].location, None);
+        assert_eq!(diagnostics.diagnostics[1].location, Some(def));
+        assert_eq!(diagnostics.diagnostics[1].count, 2);
+
+        assert_eq!(take().diagnostics.len(), 0);
+    }
+}
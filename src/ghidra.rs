@@ -0,0 +1,302 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::Context;
+use cwe_checker_lib::intermediate_representation::Tid;
+
+use crate::{
+    inference_job::{InferenceJob, JobDefinition},
+    util::BareMetalLayout,
+};
+
+/// The environment variable pointing to the root of a Ghidra installation.
+pub const GHIDRA_INSTALL_DIR_VAR: &str = "GHIDRA_INSTALL_DIR";
+
+/// The name of the cwe_checker export script run after Ghidra's analysis.
+pub const PCODE_EXTRACTOR_SCRIPT: &str = "PcodeExtractor.java";
+
+/// The directory of the export script bundled with the cwe_checker submodule.
+pub const BUNDLED_SCRIPT_DIR: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/cwe_checker/src/ghidra/p_code_extractor"
+);
+
+/// The lattice used when none is provided. It only distinguishes integral values from everything else.
+pub const DEFAULT_LATTICE_JSON: &str = r#"{"top_handle":"T","bottom_handle":"bottom","less_than_relations_between_handles":[["bottom","weak_integer"],["weak_integer","T"],["bottom","T"]],"weakest_integral_type":"weak_integer"}"#;
+
+// Analysis of a single binary is bounded by Ghidra rather than running forever on pathological inputs.
+const ANALYSIS_TIMEOUT_SECONDS: u64 = 3600;
+
+/// Runs Ghidra headless with the cwe_checker export script to lift a binary to the Pcode json IR.
+pub struct GhidraHeadless {
+    analyze_headless: PathBuf,
+    script_dir: PathBuf,
+}
+
+impl GhidraHeadless {
+    /// Creates a driver for the Ghidra installation at install_dir that runs the export script in script_dir.
+    pub fn new(install_dir: &Path, script_dir: &Path) -> GhidraHeadless {
+        let mut analyze_headless = install_dir.to_path_buf();
+        analyze_headless.push("support");
+        analyze_headless.push("analyzeHeadless");
+
+        GhidraHeadless {
+            analyze_headless,
+            script_dir: script_dir.to_path_buf(),
+        }
+    }
+
+    /// Creates a driver for the installation in [GHIDRA_INSTALL_DIR_VAR] using the bundled export script.
+    pub fn from_env() -> anyhow::Result<GhidraHeadless> {
+        let install_dir = std::env::var(GHIDRA_INSTALL_DIR_VAR)
+            .with_context(|| format!("{} is not set", GHIDRA_INSTALL_DIR_VAR))?;
+        Ok(Self::new(
+            Path::new(&install_dir),
+            Path::new(BUNDLED_SCRIPT_DIR),
+        ))
+    }
+
+    /// Imports the binary into a temporary Ghidra project in work_dir, analyzes it, and writes the Pcode json to out_json.
+    /// Bare metal images are imported as raw binaries at the base address of the layout.
+    pub fn export_pcode(
+        &self,
+        binary: &Path,
+        out_json: &Path,
+        work_dir: &Path,
+        bare_metal: Option<&BareMetalLayout>,
+    ) -> anyhow::Result<()> {
+        let mut cmd = Command::new(&self.analyze_headless);
+        cmd.arg(work_dir)
+            .arg("PcodeExtractor")
+            .arg("-import")
+            .arg(binary)
+            .arg("-postScript")
+            .arg(PCODE_EXTRACTOR_SCRIPT)
+            .arg(out_json)
+            .arg("-scriptPath")
+            .arg(&self.script_dir)
+            .arg("-deleteProject")
+            .arg("-analysisTimeoutPerFile")
+            .arg(ANALYSIS_TIMEOUT_SECONDS.to_string());
+
+        if let Some(layout) = bare_metal {
+            cmd.arg("-loader")
+                .arg("BinaryLoader")
+                .arg("-loader-baseAddr")
+                .arg(format!("0x{:x}", layout.base_address))
+                .arg("-processor")
+                .arg(&layout.processor_id);
+        }
+
+        log::info!("Running {:?}", cmd);
+        let output = cmd
+            .output()
+            .with_context(|| format!("running {}", self.analyze_headless.display()))?;
+
+        if !output.status.success() || !out_json.exists() {
+            return Err(anyhow::anyhow!(
+                "ghidra failed to export pcode ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Lifts a binary with Ghidra and writes the remaining inputs of a job into work_dir: the lattice (the default lattice if none is given),
+/// no additional constraints, and every subprocedure of the binary as an interesting tid.
+/// The inputs are written as json so the job must be parsed with [JsonDef](crate::inference_job::JsonDef).
+pub fn prepare_job(
+    ghidra: &GhidraHeadless,
+    binary: &Path,
+    lattice_json: Option<&Path>,
+    work_dir: &Path,
+    bare_metal_layout: Option<&Path>,
+) -> anyhow::Result<JobDefinition> {
+    let layout = bare_metal_layout
+        .map(|pth| BareMetalLayout::from_file(&pth.to_string_lossy()))
+        .transpose()?;
+
+    let ir_json = work_dir.join("ir.json");
+    let ghidra_project_dir = work_dir.join("ghidra");
+    std::fs::create_dir_all(&ghidra_project_dir)?;
+    ghidra.export_pcode(binary, &ir_json, &ghidra_project_dir, layout.as_ref())?;
+
+    prepare_lifted_job(binary, &ir_json, lattice_json, work_dir, bare_metal_layout)
+}
+
+/// Writes the inputs of a job for a binary that was already lifted to the Pcode json IR into work_dir, as [prepare_job] does.
+pub fn prepare_lifted_job(
+    binary: &Path,
+    ir_json: &Path,
+    lattice_json: Option<&Path>,
+    work_dir: &Path,
+    bare_metal_layout: Option<&Path>,
+) -> anyhow::Result<JobDefinition> {
+    let push = |nm: &str| {
+        let mut pth = work_dir.to_path_buf();
+        pth.push(nm);
+        pth
+    };
+    let path_string = |pth: &Path| pth.to_string_lossy().into_owned();
+
+    let layout = bare_metal_layout
+        .map(|pth| BareMetalLayout::from_file(&path_string(pth)))
+        .transpose()?;
+
+    let lattice = match lattice_json {
+        Some(lattice) => lattice.to_path_buf(),
+        None => {
+            let lattice = push("lattice.json");
+            std::fs::write(&lattice, DEFAULT_LATTICE_JSON)?;
+            lattice
+        }
+    };
+
+    let bin_bytes = std::fs::read(binary)?;
+    let proj = match &layout {
+        Some(layout) => {
+            InferenceJob::parse_bare_metal_project(&path_string(ir_json), &bin_bytes, layout)
+        }
+        None => InferenceJob::parse_project(&path_string(ir_json), &bin_bytes),
+    }?;
+    let sub_tids = proj.program.term.subs.keys().cloned().collect::<Vec<Tid>>();
+
+    let interesting_tids = push("interesting_tids.json");
+    serde_json::to_writer(std::fs::File::create(&interesting_tids)?, &sub_tids)?;
+
+    let additional_constraints = push("additional_constraints.json");
+    std::fs::write(&additional_constraints, "[]")?;
+
+    Ok(JobDefinition {
+        binary_path: path_string(binary),
+        ir_json_path: path_string(ir_json),
+        lattice_json: path_string(&lattice),
+        additional_constraints_file: path_string(&additional_constraints),
+        interesting_tids: path_string(&interesting_tids),
+        bare_metal_layout: bare_metal_layout.map(path_string),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use crate::{
+        inference_job::InferenceJob,
+        test_utils::{get_test_data_path, test_file_to_bytes},
+        util::BareMetalLayout,
+    };
+
+    use super::{prepare_lifted_job, GhidraHeadless, DEFAULT_LATTICE_JSON};
+
+    // Installs a stand in for analyzeHeadless that records its arguments and writes the json named after the export
+    // script if succeed is set.
+    #[cfg(unix)]
+    fn fake_ghidra(install_dir: &Path, succeed: bool) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let support = install_dir.join("support");
+        std::fs::create_dir_all(&support).unwrap();
+        let script = support.join("analyzeHeadless");
+        let body = if succeed {
+            r#"while [ "$1" != "PcodeExtractor.java" ]; do shift; done; echo '{}' > "$2""#
+        } else {
+            "echo 'no processor' >&2; exit 1"
+        };
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho \"$@\" > \"$(dirname \"$0\")/args\"\n{}\n",
+                body
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bare_metal_images_are_imported_as_raw_binaries() {
+        let tmp = tempdir::TempDir::new("export").expect("should create a temporary directory");
+        let dir = tmp.path();
+        let install_dir = dir.join("ghidra_install");
+        fake_ghidra(&install_dir, true);
+        let ghidra = GhidraHeadless::new(&install_dir, Path::new("scripts"));
+        let layout = BareMetalLayout {
+            processor_id: "ARM:LE:32:v8".to_owned(),
+            base_address: 0x8000000,
+            ram_base_address: 0x20000000,
+            ram_size: 0x10000,
+        };
+
+        let out_json = dir.join("ir.json");
+        ghidra
+            .export_pcode(Path::new("firmware.bin"), &out_json, dir, Some(&layout))
+            .unwrap();
+        assert!(out_json.exists());
+        let args = std::fs::read_to_string(install_dir.join("support/args")).unwrap();
+        assert!(args.contains("-import firmware.bin"));
+        assert!(args.contains("-scriptPath scripts"));
+        assert!(args
+            .contains("-loader BinaryLoader -loader-baseAddr 0x8000000 -processor ARM:LE:32:v8"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_exports_report_ghidra_errors() {
+        let tmp =
+            tempdir::TempDir::new("failed_export").expect("should create a temporary directory");
+        let dir = tmp.path();
+        let install_dir = dir.join("ghidra_install");
+        fake_ghidra(&install_dir, false);
+        let ghidra = GhidraHeadless::new(&install_dir, Path::new("scripts"));
+
+        let err = ghidra
+            .export_pcode(Path::new("a.out"), &dir.join("ir.json"), dir, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("no processor"));
+        let args = std::fs::read_to_string(install_dir.join("support/args")).unwrap();
+        assert!(!args.contains("-loader"));
+    }
+
+    #[test]
+    fn lifted_jobs_solve_every_subprocedure_with_the_default_lattice() {
+        let tmp = tempdir::TempDir::new("prepare").expect("should create a temporary directory");
+        let dir = tmp.path();
+        let def = prepare_lifted_job(
+            &get_test_data_path("mooosl"),
+            &get_test_data_path("new_moosl.json"),
+            None,
+            dir,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&def.lattice_json).unwrap(),
+            DEFAULT_LATTICE_JSON
+        );
+        assert!(InferenceJob::parse_lattice_json(&def.lattice_json, Vec::new()).is_ok());
+        assert_eq!(
+            std::fs::read_to_string(&def.additional_constraints_file).unwrap(),
+            "[]"
+        );
+
+        let interesting: Vec<Tid> =
+            serde_json::from_reader(std::fs::File::open(&def.interesting_tids).unwrap()).unwrap();
+        let proj =
+            InferenceJob::parse_project(&def.ir_json_path, &test_file_to_bytes("mooosl")).unwrap();
+        assert_eq!(
+            interesting,
+            proj.program.term.subs.keys().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(def.bare_metal_layout, None);
+    }
+}
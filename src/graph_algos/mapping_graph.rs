@@ -23,8 +23,8 @@ use super::{explore_paths, find_node};
 #[derive(Clone, Serialize, Deserialize, Default)]
 pub struct MappingGraph<W, N: Ord + Hash + Eq, E> {
     grph: StableDiGraph<W, E>,
-    nodes: HashMap<N, NodeIndex>,
-    reprs_to_graph_node: HashMap<NodeIndex, BTreeSet<N>>,
+    nodes: BTreeMap<N, NodeIndex>,
+    reprs_to_graph_node: BTreeMap<NodeIndex, BTreeSet<N>>,
 }
 
 impl<W, N: Ord + Hash + Eq + Debug, E> MappingGraph<W, N, E> {
@@ -32,8 +32,8 @@ impl<W, N: Ord + Hash + Eq + Debug, E> MappingGraph<W, N, E> {
     pub fn from_dfa_and_labeling(dfa: StableDiGraph<W, E>) -> MappingGraph<W, N, E> {
         MappingGraph {
             grph: dfa,
-            nodes: HashMap::new(),
-            reprs_to_graph_node: HashMap::new(),
+            nodes: BTreeMap::new(),
+            reprs_to_graph_node: BTreeMap::new(),
         }
     }
 
@@ -476,9 +476,9 @@ impl<
 
                 (orig_label.clone(), *new_idx)
             })
-            .collect::<HashMap<_, _>>();
+            .collect::<BTreeMap<_, _>>();
 
-        let mut new_rev_mapping: HashMap<NodeIndex, BTreeSet<N>> = HashMap::new();
+        let mut new_rev_mapping: BTreeMap<NodeIndex, BTreeSet<N>> = BTreeMap::new();
 
         new_mapping.iter().for_each(|(n, idx)| {
             let b = new_rev_mapping
@@ -498,8 +498,8 @@ impl<
 impl<W: std::cmp::PartialEq + Clone, N: Clone + Hash + Eq + Ord, E: Hash + Eq + Clone>
     MappingGraph<W, N, E>
 {
-    fn inplace_relable_representative_nodes(&mut self, mapping: HashMap<N, NodeIndex>) {
-        let mut index_to_reprs = HashMap::new();
+    fn inplace_relable_representative_nodes(&mut self, mapping: BTreeMap<N, NodeIndex>) {
+        let mut index_to_reprs = BTreeMap::new();
         mapping.iter().for_each(|(nd, idx)| {
             index_to_reprs
                 .entry(*idx)
@@ -514,7 +514,7 @@ impl<W: std::cmp::PartialEq + Clone, N: Clone + Hash + Eq + Ord, E: Hash + Eq +
     /// Takes a mapping of reprs to node indices to relable the graph
     pub fn relable_representative_nodes(
         &self,
-        mapping: HashMap<N, NodeIndex>,
+        mapping: BTreeMap<N, NodeIndex>,
     ) -> MappingGraph<W, N, E> {
         // construct set
         let mut new_graph = self.clone();
@@ -528,8 +528,8 @@ impl<W: std::cmp::PartialEq, N: Clone + Hash + Eq + Ord, E: Hash + Eq> MappingGr
     pub fn new() -> MappingGraph<W, N, E> {
         MappingGraph {
             grph: StableDiGraph::new(),
-            nodes: HashMap::new(),
-            reprs_to_graph_node: HashMap::new(),
+            nodes: BTreeMap::new(),
+            reprs_to_graph_node: BTreeMap::new(),
         }
     }
 
@@ -552,7 +552,7 @@ impl<W: std::cmp::PartialEq, N: Clone + Hash + Eq + Ord, E: Hash + Eq> MappingGr
     }
 
     /// Gets the mapping from node key to [NodeIndex]
-    pub fn get_node_mapping(&self) -> &HashMap<N, NodeIndex> {
+    pub fn get_node_mapping(&self) -> &BTreeMap<N, NodeIndex> {
         &self.nodes
     }
 
@@ -0,0 +1,205 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cwe_checker_lib::intermediate_representation::Tid;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constraint_generation::tid_to_tvar,
+    constraints::{
+        ConstraintOrigin, ConstraintReason, ConstraintSet, DerivedTypeVar, Field, FieldLabel,
+        SubtypeConstraint, TyConstraint, TypeVariable,
+    },
+    lowering::primitives::PrimitiveTable,
+};
+
+/// The location a type assumption pins a type to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssumptionTarget {
+    /// The formal parameter at the given index of a subprocedure.
+    Parameter {
+        /// The subprocedure owning the parameter
+        subprocedure: Tid,
+        /// The index of the parameter
+        index: usize,
+    },
+    /// The formal return at the given index of a subprocedure.
+    Return {
+        /// The subprocedure owning the return
+        subprocedure: Tid,
+        /// The index of the return
+        index: usize,
+    },
+    /// A global variable. The assumption is injected into each of the listed subprocedures,
+    /// or into every subprocedure if none are listed.
+    Global {
+        /// The global variable's term
+        variable: Tid,
+        /// The subprocedures in which the assumption should be applied
+        #[serde(default)]
+        applies_in: Vec<Tid>,
+    },
+}
+
+/// A user supplied type for a parameter, return, or global. The type is written as a lattice element
+/// followed by zero or more '*', for instance "char*".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeAssumption {
+    /// Where the type applies
+    pub target: AssumptionTarget,
+    /// The assumed type
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// The size in bits of the lattice element when it is accessed through a pointer.
+    /// Defaults to the size in the primitive table.
+    #[serde(default)]
+    pub pointee_bit_size: Option<usize>,
+}
+
+fn parse_assumed_type(ty: &str) -> anyhow::Result<(&str, usize)> {
+    let trimmed = ty.trim();
+    let elem = trimmed.trim_end_matches(|c: char| c == '*' || c.is_whitespace());
+    if elem.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Assumed type {} has no lattice element",
+            ty
+        ));
+    }
+
+    let pointer_depth = trimmed[elem.len()..].chars().filter(|c| *c == '*').count();
+    Ok((elem, pointer_depth))
+}
+
+impl TypeAssumption {
+    fn target_dtv(&self) -> DerivedTypeVar {
+        match &self.target {
+            AssumptionTarget::Parameter {
+                subprocedure,
+                index,
+            } => DerivedTypeVar::create_with_label(
+                &DerivedTypeVar::new(tid_to_tvar(subprocedure)),
+                FieldLabel::In(*index),
+            ),
+            AssumptionTarget::Return {
+                subprocedure,
+                index,
+            } => DerivedTypeVar::create_with_label(
+                &DerivedTypeVar::new(tid_to_tvar(subprocedure)),
+                FieldLabel::Out(*index),
+            ),
+            AssumptionTarget::Global { variable, .. } => {
+                DerivedTypeVar::new(TypeVariable::new_global(variable.get_str_repr().to_owned()))
+            }
+        }
+    }
+
+    fn associated_subprocedures<'a>(&'a self, all_subs: &'a BTreeSet<Tid>) -> Vec<&'a Tid> {
+        match &self.target {
+            AssumptionTarget::Parameter { subprocedure, .. }
+            | AssumptionTarget::Return { subprocedure, .. } => vec![subprocedure],
+            AssumptionTarget::Global { applies_in, .. } if !applies_in.is_empty() => {
+                applies_in.iter().collect()
+            }
+            AssumptionTarget::Global { .. } => all_subs.iter().collect(),
+        }
+    }
+
+    /// Translates the assumption into constraints that equate the target with the assumed type.
+    /// Each pointer level is represented by a load of pointer_bit_size bits at offset 0.
+    pub fn to_constraints(
+        &self,
+        lattice_elems: &BTreeSet<String>,
+        primitives: &PrimitiveTable,
+        pointer_bit_size: usize,
+    ) -> anyhow::Result<ConstraintSet> {
+        let (elem, pointer_depth) = parse_assumed_type(&self.ty)?;
+        if !lattice_elems.contains(elem) {
+            return Err(anyhow::anyhow!(
+                "Assumed type {} is not an element of the lattice",
+                elem
+            ));
+        }
+
+        let mut dtv = self.target_dtv();
+        for level in 0..pointer_depth {
+            let bit_size = if level + 1 == pointer_depth {
+                self.pointee_bit_size
+                    .or_else(|| primitives.get_byte_size(elem).map(|sz| sz * 8))
+                    .ok_or_else(|| anyhow::anyhow!("No size known for pointed to type {}", elem))?
+            } else {
+                pointer_bit_size
+            };
+
+            dtv.add_field_label(FieldLabel::Load);
+            dtv.add_field_label(FieldLabel::Field(Field::new(0, bit_size)));
+        }
+
+        let elem_dtv = DerivedTypeVar::new(TypeVariable::new(elem.to_owned()));
+        let mut cons = ConstraintSet::default();
+        cons.insert(TyConstraint::SubTy(SubtypeConstraint::new(
+            dtv.clone(),
+            elem_dtv.clone(),
+        )));
+        cons.insert(TyConstraint::SubTy(SubtypeConstraint::new(elem_dtv, dtv)));
+        Ok(cons)
+    }
+}
+
+/// Translates assumptions to constraint sets keyed by the subprocedure they should be injected into.
+pub fn assumptions_to_constraints(
+    assumptions: &[TypeAssumption],
+    all_subs: &BTreeSet<Tid>,
+    lattice_elems: &BTreeSet<String>,
+    primitives: &PrimitiveTable,
+    pointer_bit_size: usize,
+) -> anyhow::Result<BTreeMap<Tid, ConstraintSet>> {
+    let mut total: BTreeMap<Tid, ConstraintSet> = BTreeMap::new();
+    for assumption in assumptions.iter() {
+        let cons = assumption.to_constraints(lattice_elems, primitives, pointer_bit_size)?;
+        for sub in assumption.associated_subprocedures(all_subs) {
+            total
+                .entry(sub.clone())
+                .or_insert_with(ConstraintSet::default)
+                .insert_all(&cons.clone().with_origin(&ConstraintOrigin::new(
+                    sub.clone(),
+                    ConstraintReason::Assumption,
+                )));
+        }
+    }
+
+    Ok(total)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use crate::{constraints::parse_constraint_set, lowering::primitives::PrimitiveTable};
+
+    use super::{AssumptionTarget, TypeAssumption};
+
+    #[test]
+    fn char_pointer_parameter() {
+        let assumption = TypeAssumption {
+            target: AssumptionTarget::Parameter {
+                subprocedure: Tid::create("sub_401000".to_owned(), "00401000".to_owned()),
+                index: 0,
+            },
+            ty: "char*".to_owned(),
+            pointee_bit_size: None,
+        };
+
+        let elems = vec!["char".to_owned()].into_iter().collect::<BTreeSet<_>>();
+        let cons = assumption
+            .to_constraints(&elems, &PrimitiveTable::default(), 64)
+            .unwrap();
+
+        let (_, expected) = parse_constraint_set(
+            "sub_401000.in_0.load.σ8@0 <= char\nchar <= sub_401000.in_0.load.σ8@0",
+        )
+        .unwrap();
+
+        assert_eq!(cons, expected);
+    }
+}
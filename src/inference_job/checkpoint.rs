@@ -0,0 +1,234 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::solver::{
+    scc_constraint_generation::{SCCConstraints, SimplificationCache},
+    type_lattice::{NamedLattice, NamedLatticeElement},
+    type_sketch::{LatticeBounds, SketchGraph, SketchGraphCheckpoint},
+};
+
+const INPUT_FINGERPRINT: &str = "input_fingerprint.json";
+const SIMPLIFICATION_JOURNAL: &str = "simplification_journal.jsonl";
+const SIMPLIFIED_CONSTRAINTS: &str = "simplified_constraints.json";
+const SKETCH_GRAPH: &str = "sketch_graph.json";
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// A stable hash of the inputs of an analysis, so that checkpointed state is only reused by an analysis of the same
+/// inputs. Inputs are hashed in order with 64 bit FNV-1a.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputFingerprint(u64);
+
+impl Default for InputFingerprint {
+    fn default() -> Self {
+        InputFingerprint(FNV_OFFSET_BASIS)
+    }
+}
+
+impl InputFingerprint {
+    /// Adds an input given as bytes.
+    pub fn add_bytes(&mut self, bytes: &[u8]) {
+        self.hash(&(bytes.len() as u64).to_le_bytes());
+        self.hash(bytes);
+    }
+
+    /// Adds an input by its json serialization.
+    pub fn add_json<T: Serialize>(&mut self, input: &T) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut *self, input)?;
+        // never part of json, so consecutive inputs cannot run into each other
+        self.hash(&[0xff]);
+        Ok(())
+    }
+
+    fn hash(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter() {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+impl std::io::Write for InputFingerprint {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.hash(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A directory holding the intermediate state of an analysis so that an interrupted analysis can be resumed.
+/// Simplified signatures and constraints are journaled per scc as they are computed, and the simplified constraints and
+/// the labeled sketch graph are saved once they are built. The state is only valid for the inputs it was computed from,
+/// so the directory records the fingerprint of those inputs.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    dir: PathBuf,
+    resumed: bool,
+}
+
+impl Checkpoint {
+    /// Opens a checkpoint directory for an analysis of inputs with the given fingerprint, creating it if needed. State left
+    /// by a previous run is only resumed if resume is set and the run had the same inputs, otherwise it is discarded.
+    pub fn open(
+        dir: &Path,
+        resume: bool,
+        fingerprint: InputFingerprint,
+    ) -> anyhow::Result<Checkpoint> {
+        std::fs::create_dir_all(dir).with_context(|| "creating checkpoint directory")?;
+        let mut checkpoint = Checkpoint {
+            dir: dir.to_path_buf(),
+            resumed: false,
+        };
+
+        let fingerprint_pth = checkpoint.path(INPUT_FINGERPRINT);
+        let saved_fingerprint = std::fs::File::open(&fingerprint_pth)
+            .ok()
+            .and_then(|fl| serde_json::from_reader::<_, InputFingerprint>(fl).ok());
+        checkpoint.resumed = resume && saved_fingerprint == Some(fingerprint);
+        if resume && !checkpoint.resumed {
+            log::warn!(
+                "The checkpoint in {} was not computed from the same inputs, starting over",
+                dir.display()
+            );
+        }
+
+        if !checkpoint.resumed {
+            for fname in [SIMPLIFICATION_JOURNAL, SIMPLIFIED_CONSTRAINTS, SKETCH_GRAPH].iter() {
+                let pth = checkpoint.path(fname);
+                if pth.exists() {
+                    std::fs::remove_file(pth)?;
+                }
+            }
+            checkpoint.save_json(INPUT_FINGERPRINT, &fingerprint)?;
+        }
+
+        Ok(checkpoint)
+    }
+
+    /// Checks whether the state of a previous run with the same inputs is resumed.
+    pub fn is_resumed(&self) -> bool {
+        self.resumed
+    }
+
+    fn path(&self, fname: &str) -> PathBuf {
+        let mut pth = self.dir.clone();
+        pth.push(fname);
+        pth
+    }
+
+    // Written to a temporary file first so an interruption cannot leave a truncated file.
+    fn save_json<T: Serialize>(&self, fname: &str, value: &T) -> anyhow::Result<()> {
+        let pth = self.path(fname);
+        let tmp_pth = pth.with_extension("json.tmp");
+        serde_json::to_writer(
+            std::io::BufWriter::new(std::fs::File::create(&tmp_pth)?),
+            value,
+        )?;
+        std::fs::rename(tmp_pth, pth)?;
+        Ok(())
+    }
+
+    /// Creates a simplification cache backed by the journal in this checkpoint.
+    pub fn simplification_cache(&self) -> anyhow::Result<SimplificationCache> {
+        SimplificationCache::with_journal(&self.path(SIMPLIFICATION_JOURNAL))
+            .with_context(|| "loading simplification journal")
+    }
+
+    /// Saves the simplified constraints of every scc.
+    pub fn save_simplified_constraints(&self, cons: &[SCCConstraints]) -> anyhow::Result<()> {
+        self.save_json(SIMPLIFIED_CONSTRAINTS, &cons)
+    }
+
+    /// Loads the saved simplified constraints, if there are any.
+    pub fn load_simplified_constraints(&self) -> anyhow::Result<Option<Vec<SCCConstraints>>> {
+        let pth = self.path(SIMPLIFIED_CONSTRAINTS);
+        if !pth.exists() {
+            return Ok(None);
+        }
+
+        serde_json::from_reader(std::io::BufReader::new(std::fs::File::open(pth)?))
+            .with_context(|| "parsing checkpointed simplified constraints")
+            .map(Some)
+    }
+
+    /// Saves a labeled sketch graph.
+    pub fn save_sketch_graph<U: NamedLatticeElement>(
+        &self,
+        sg: &SketchGraph<LatticeBounds<U>>,
+    ) -> anyhow::Result<()> {
+        self.save_json(SKETCH_GRAPH, &sg.to_checkpoint())
+    }
+
+    /// Loads the saved labeled sketch graph, if there is one.
+    pub fn load_sketch_graph<T: NamedLattice<U>, U: NamedLatticeElement>(
+        &self,
+        lattice: &T,
+    ) -> anyhow::Result<Option<SketchGraph<LatticeBounds<U>>>> {
+        let pth = self.path(SKETCH_GRAPH);
+        if !pth.exists() {
+            return Ok(None);
+        }
+
+        let saved: SketchGraphCheckpoint =
+            serde_json::from_reader(std::io::BufReader::new(std::fs::File::open(pth)?))
+                .with_context(|| "parsing checkpointed sketch graph")?;
+        SketchGraph::from_checkpoint(&saved, lattice).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use crate::solver::scc_constraint_generation::SCCConstraints;
+
+    use super::{Checkpoint, InputFingerprint};
+
+    fn fingerprint(input: &str) -> InputFingerprint {
+        let mut fingerprint = InputFingerprint::default();
+        fingerprint.add_bytes(input.as_bytes());
+        fingerprint
+    }
+
+    fn saved_constraints(checkpoint: &Checkpoint) -> Option<Vec<Tid>> {
+        checkpoint
+            .load_simplified_constraints()
+            .expect("should load constraints")
+            .map(|cons| cons.into_iter().flat_map(|scc| scc.scc).collect())
+    }
+
+    #[test]
+    fn checkpoints_are_only_resumed_for_the_same_inputs() {
+        let dir = tempdir::TempDir::new("checkpoint").expect("should create a temporary directory");
+        let sub = Tid::create("sub_1".to_owned(), "1000".to_owned());
+
+        let first = Checkpoint::open(dir.path(), false, fingerprint("binary")).unwrap();
+        assert!(!first.is_resumed());
+        first
+            .save_simplified_constraints(&[SCCConstraints {
+                scc: vec![sub.clone()],
+                constraints: BTreeSet::new(),
+            }])
+            .unwrap();
+
+        let resumed = Checkpoint::open(dir.path(), true, fingerprint("binary")).unwrap();
+        assert!(resumed.is_resumed());
+        assert_eq!(saved_constraints(&resumed), Some(vec![sub]));
+
+        let changed = Checkpoint::open(dir.path(), true, fingerprint("patched binary")).unwrap();
+        assert!(!changed.is_resumed());
+        assert_eq!(saved_constraints(&changed), None);
+
+        // the changed inputs are now the ones the directory is for
+        let resumed = Checkpoint::open(dir.path(), true, fingerprint("patched binary")).unwrap();
+        assert!(resumed.is_resumed());
+    }
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{allocators::AllocatorSpec, platform::Platform},
+    constraint_generation::ConstraintDumpGranularity,
+    lowering::LoweringOptions,
+    node_context::points_to::OffsetClustering,
+};
+
+/// Pipeline settings loaded from a toml configuration file (bti.toml). Every setting is optional
+/// so that command line flags can override or fill in values. Unknown keys are rejected so that
+/// typos are not silently ignored.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct BtiConfig {
+    /// Parse additional constraints and interesting tids as json rather than protobuf
+    pub human_readable_input: Option<bool>,
+    /// Emit the ctype mapping as json rather than protobuf
+    pub human_readable_output: Option<bool>,
+    /// Recover returns missed by ghidra due to tail calls before generating constraints
+    pub use_aggressive_shared_returns: Option<bool>,
+    /// The directory debug artifacts are written to
+    pub debug_out_dir: Option<String>,
+    /// Write the generated constraints of each cfg node or function to the debug directory
+    pub dump_constraints: Option<ConstraintDumpGranularity>,
+    /// The directory intermediate state is checkpointed to
+    pub checkpoint_dir: Option<String>,
+    /// A json file of type assumptions
+    pub assumptions: Option<String>,
+    /// Symbols treated as heap allocators by the points-to analysis
+    pub allocators: Option<Vec<AllocatorSpec>>,
+    /// Symbols that free the heap object passed as their first parameter
+    pub deallocation_symbols: Option<Vec<String>>,
+    /// Give the accesses to a heap object between each pair of allocation and free a variable of their own
+    pub heap_lifetimes: Option<bool>,
+    /// Detect thin wrappers around the allocators and treat them as allocators
+    pub detect_allocator_wrappers: Option<bool>,
+    /// Constrain the size parameters of calls to the allocators to be integral
+    pub allocation_size_constraints: Option<bool>,
+    /// Retarget calls to PLT stubs and tail call thunks to the functions they forward to
+    pub collapse_thunks: Option<bool>,
+    /// Remove assignments to registers that are never read before generating constraints
+    pub prune_dead_defs: Option<bool>,
+    /// The number of callsites that must read the return value of a subprocedure for it to keep its return type
+    pub return_usage_threshold: Option<usize>,
+    /// Combine the points-to and register contexts of each program point, typing accesses to frame slots directly
+    pub combined_context: Option<bool>,
+    /// Generate the temporary type variables of each function in a namespace of the function
+    pub function_scoped_variables: Option<bool>,
+    /// Dump the rules applied while saturating the constraint graph of each scc to the debug directory
+    pub trace_rules: Option<bool>,
+    /// Check that the simplified constraints of each scc entail up to this many of its original constraints over interesting variables
+    pub check_entailment: Option<usize>,
+    /// Fail the whole solve when an scc fails to solve rather than giving its subprocedures default types
+    pub fail_fast: Option<bool>,
+    /// The approximate number of megabytes the solver may use before it degrades constraints to stay within them
+    pub memory_budget: Option<usize>,
+    /// Only keep the bodies of the interesting subprocedures when reading the IR
+    pub only_interesting_bodies: Option<bool>,
+    /// The names of the passes used to simplify constraint graphs, in order
+    pub simplification_passes: Option<Vec<String>>,
+    /// How access offsets are folded into fields by the points-to analysis
+    pub offset_clustering: Option<OffsetClustering>,
+    /// The length of the call strings callee types are cloned for at callsites rather than merged
+    pub context_depth: Option<usize>,
+    /// Seed low confidence types guessed from the names of symbols
+    pub name_heuristics: Option<bool>,
+    /// A json file of the parameter names of symbols used by the name heuristics, implies name_heuristics
+    pub parameter_names: Option<String>,
+    /// The platform whose typedefs are added to the lattice and seeded on the wrapper functions it knows
+    pub platform: Option<Platform>,
+    /// Type the arguments and returns of syscall instructions with a constant syscall number
+    pub syscalls: Option<bool>,
+    /// A json freestanding profile of the SDK signatures and allocators of a kernel or firmware, disables the libc
+    /// allocators, platform, and syscalls
+    pub freestanding: Option<String>,
+    /// Recover the names and ABI of Go functions from the pclntab of the binary
+    pub go_metadata: Option<bool>,
+    /// Demangle Rust symbols, seed the allocators and signatures of core and alloc, and lower fat pointer parameters
+    /// as slices
+    pub rust: Option<bool>,
+    /// Recover stack frames and saved register slots from the `.eh_frame` section of the binary
+    pub unwind_frames: Option<bool>,
+    /// Seed the relocated words of global variables as pointers from the relocations of the binary
+    pub relocations: Option<bool>,
+    /// Type the imports of PE binaries from the bundled Windows API signatures
+    pub windows_imports: Option<bool>,
+    /// Type loads and stores relative to the thread pointer as fields of the thread-local storage block
+    pub tls: Option<bool>,
+    /// Isolate the constraints generated on the exception paths starting at C++ landing pads
+    pub landing_pads: Option<bool>,
+    /// Bound pointer-scaled indices by size_t and pointer differences by ptrdiff_t
+    pub pointer_integers: Option<bool>,
+    /// A json file of the memory layout of a bare metal binary
+    pub bare_metal: Option<String>,
+    /// Glob patterns of the names of the subprocedures and symbols to solve for, in addition to the interesting tids
+    pub interesting_globs: Option<Vec<String>>,
+    /// Regular expressions matching the names of the subprocedures and symbols to solve for
+    pub interesting_regexes: Option<Vec<String>>,
+    /// Address ranges, ie. `0x401000-0x402000`, of the subprocedures to solve for
+    pub interesting_ranges: Option<Vec<String>>,
+    /// Solve for every subprocedure
+    pub all_subs: Option<bool>,
+    /// Solve for every extern symbol
+    pub all_externs: Option<bool>,
+    /// Regular expressions matching the type variables whose constraints are dropped before simplification
+    pub drop_constraints_on: Option<Vec<String>>,
+    /// Label the type each callee is instantiated to at each callsite
+    pub callsite_signatures: Option<bool>,
+    /// Keep the stack frames of the interesting subprocedures so the types of their local variables are lowered
+    pub local_variable_types: Option<bool>,
+    /// Options controlling lowering to ctypes
+    pub lowering: Option<LoweringOptions>,
+    /// Run inference a second time and fail if the two runs produce different mappings
+    pub deterministic: Option<bool>,
+    /// Write the types of each interesting term as it is lowered rather than one mapping
+    pub stream_output: Option<bool>,
+    /// Compress the artifacts written to the debug directory with zstd
+    pub compress_debug: Option<bool>,
+    /// Compress the ctype mapping and sketch graph outputs with zstd
+    pub compress_output: Option<bool>,
+    /// Rename generated type variables canonically in constraint dumps so that runs can be diffed
+    pub canonical_names: Option<bool>,
+    /// Name declared types in IDA and HTML exports by a hash of their structure rather than their type id
+    pub structural_names: Option<bool>,
+}
+
+impl BtiConfig {
+    /// Parses a configuration from toml text.
+    pub fn parse(text: &str) -> anyhow::Result<BtiConfig> {
+        toml::from_str(text).map_err(|e| anyhow::Error::from(e).context("parsing bti config"))
+    }
+
+    /// Reads and parses a configuration file.
+    pub fn from_file(pth: &str) -> anyhow::Result<BtiConfig> {
+        let text = std::fs::read_to_string(pth)?;
+        Self::parse(&text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analysis::allocators::AllocatorSpec;
+
+    use super::BtiConfig;
+
+    #[test]
+    fn parses_lowering_table() {
+        let cfg = BtiConfig::parse(
+            "
+            debug_out_dir = \"/tmp/bti\"
+
+            [lowering]
+            emit_unions = false
+
+            [[allocators]]
+            symbol = \"malloc\"
+            size_params = [0]
+
+            [[allocators]]
+            symbol = \"g_malloc\"
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(cfg.debug_out_dir.as_deref(), Some("/tmp/bti"));
+        assert_eq!(
+            cfg.allocators,
+            Some(vec![
+                AllocatorSpec::new("malloc", vec![0]),
+                AllocatorSpec::new("g_malloc", vec![])
+            ])
+        );
+        assert!(!cfg.lowering.unwrap().emit_unions);
+    }
+
+    #[test]
+    fn parses_selection_and_output_knobs() {
+        let cfg = BtiConfig::parse(
+            "
+            interesting_globs = [\"parse_*\"]
+            interesting_ranges = [\"401000-402000\"]
+            all_externs = true
+            drop_constraints_on = [\"_FS_OFFSET$\"]
+            callsite_signatures = true
+            local_variable_types = false
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(cfg.interesting_globs, Some(vec!["parse_*".to_owned()]));
+        assert_eq!(cfg.interesting_regexes, None);
+        assert_eq!(
+            cfg.interesting_ranges,
+            Some(vec!["401000-402000".to_owned()])
+        );
+        assert_eq!(cfg.all_externs, Some(true));
+        assert_eq!(
+            cfg.drop_constraints_on,
+            Some(vec!["_FS_OFFSET$".to_owned()])
+        );
+        assert_eq!(cfg.callsite_signatures, Some(true));
+        assert_eq!(cfg.local_variable_types, Some(false));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(BtiConfig::parse("debug_dir = \"/tmp/bti\"").is_err());
+        assert!(BtiConfig::parse("[lowering]\nemit_union = false").is_err());
+    }
+}
@@ -1,13 +1,18 @@
 use std::{
+    cell::RefCell,
     collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     convert::TryFrom,
     iter::FromIterator,
+    rc::Rc,
 };
 
 use anyhow::Context;
 use cwe_checker_lib::{
-    analysis::graph::{Graph, Node},
-    intermediate_representation::{Arg, Project, RuntimeMemoryImage, Tid},
+    analysis::{
+        graph::{Graph, Node},
+        pointer_inference::Config,
+    },
+    intermediate_representation::{Arg, Project, RuntimeMemoryImage, Sub, Term, Tid},
     AnalysisResults,
 };
 
@@ -15,34 +20,120 @@ use petgraph::graph::NodeIndex;
 use serde::de::DeserializeOwned;
 
 use crate::{
-    analysis::{callgraph, fixup_returns},
-    constraint_generation::NodeContext,
+    analysis::{
+        allocators::{self, AllocatorSpec},
+        arm32,
+        array_extents::{self, ArrayLayout},
+        callgraph,
+        character_types::{self, CharacterClass},
+        dead_defs,
+        discriminants::{self, TaggedUnionLayout},
+        exceptions::ExceptionPaths,
+        field_accesses, fixup_returns,
+        freestanding::FreestandingProfile,
+        golang::{self, GoAbi, Pclntab},
+        lifetimes::{self, HeapLifetimes},
+        mips,
+        name_heuristics::{self, HintElements, ParameterNames},
+        noreturn::NoreturnCalls,
+        nullability::{self, Nullability},
+        object_file::{ElfFile, PeFile},
+        platform::PlatformProfile,
+        ppc, relocations, return_usage, riscv, rust_binaries,
+        stack_protector::StackGuard,
+        syscalls, thunks,
+        tls::TlsModel,
+        unwind::{self, FrameLayout},
+        windows_api,
+    },
+    constraint_generation::{ConstraintDumpGranularity, NodeContext, PointerIntegerTypes},
     constraints::{
-        AdditionalConstraint, ConstraintSet, SubtypeConstraint, TyConstraint, TypeVariable,
-        VariableManager,
+        AdditionalConstraint, ConstraintOrigin, ConstraintReason, ConstraintSet, DerivedTypeVar,
+        Field, SubtypeConstraint, TyConstraint, TypeVariable, VariableManager,
+    },
+    lowering::{
+        accessors::{build_field_name_table, find_accessors, FieldNameTable},
+        callsites::{collect_callsites, find_callsite_nodes, Callsite},
+        conflicts::{
+            find_lattice_conflicts, find_parameter_count_mismatches, find_size_mismatches,
+            LatticeConflict, ParameterCountMismatch, SizeMismatch,
+        },
+        linked::{detect_linked_structures, LinkedStructure},
+        locals::FrameSlot,
+        naming::find_constructed_structures,
+        primitives::{DataModel, PrimitiveTable},
+        CType, LoweringContext, LoweringOptions, RejectedAlternatives, TypeId,
     },
-    lowering::{CType, LoweringContext, TypeId},
     node_context::{
-        points_to::PointsToContext,
+        combined::CombinedContext,
+        points_to::{self, OffsetClustering, PointsToContext},
         register_map::{self, RegisterContext},
         subproc_loc::ProcedureContext,
         GhidraConstantResolver,
     },
     solver::{
         constraint_graph::RuleContext,
-        scc_constraint_generation::{self, LatticeInfo, ProgramInfo},
+        entailment::EntailmentFailure,
+        memory::{DegradationEvent, MemoryBudget},
+        observer::PipelineObserver,
+        passes::PassPipeline,
+        scc_constraint_generation::{
+            self, LatticeInfo, ProgramInfo, SimplificationCache, SolveFailure,
+        },
+        transform::ConstraintTransformer,
         type_lattice::{
             CustomLatticeElement, EnumeratedNamedLattice, LatticeDefinition, NamedLattice,
+            NamedLatticeElement,
+        },
+        type_scheme::{build_type_schemes, TypeScheme},
+        type_sketch::{
+            identity_element, LatticeBounds, SCCSketchsBuilder, SketchCache, SketchGraph,
         },
-        type_sketch::{identity_element, LatticeBounds, SCCSketchsBuilder, SketchGraph},
     },
-    util::FileDebugLogger,
+    util::{BareMetalLayout, FileDebugLogger},
+};
+use crate::{
+    ctypes,
+    diagnostics::{self, DiagnosticKind},
+    pb_constraints,
 };
-use crate::{ctypes, pb_constraints};
 use byteorder::{BigEndian, ReadBytesExt};
 use prost::Message;
 use std::io::Read;
 
+/// User supplied type assumptions that are translated to additional constraints.
+pub mod assumptions;
+
+/// Checkpointing of intermediate state so that long analyses can be resumed.
+pub mod checkpoint;
+
+/// Configuration file support for pipeline settings.
+pub mod config;
+
+/// Queries for the type at a program point once types are inferred.
+pub mod query;
+
+/// A JSON-RPC server that keeps a solved job in memory for plugins.
+pub mod server;
+
+/// Selection of interesting variables from the symbols of a project.
+pub mod selection;
+
+/// Slices of a project sufficient to infer the type of a single subprocedure.
+pub mod slice;
+
+/// Frontends that produce the IR project a job runs on.
+pub mod source;
+
+use assumptions::TypeAssumption;
+use checkpoint::{Checkpoint, InputFingerprint};
+use query::{
+    key_program_points, register_bindings, ProgramPoints, QueryPoint, RegisterBinding, TypeQuery,
+};
+use selection::RuleContextBuilder;
+use slice::FunctionSlice;
+use source::{PcodeJsonSource, ProjectSource};
+
 /// Defines a type inference job in terms of the input files.
 /// The interchange format can be protobuf or json depending on
 /// wether human readable input and output is required.
@@ -57,10 +148,13 @@ pub struct JobDefinition {
     pub additional_constraints_file: String,
     /// The interesting tids in the IR to solve types for.
     pub interesting_tids: String,
+    /// Path to a json [BareMetalLayout] if the binary is a bare metal image rather than an executable format.
+    pub bare_metal_layout: Option<String>,
 }
 
 /// A type inference job that has been parsed into its in memory representation.
 pub struct InferenceJob {
+    source: Box<dyn ProjectSource>,
     binary_bytes: Vec<u8>,
     proj: Project,
     lattice: EnumeratedNamedLattice,
@@ -70,6 +164,65 @@ pub struct InferenceJob {
     vman: VariableManager,
     debug_dir: FileDebugLogger,
     should_use_aggressive_shared_returns: bool,
+    lowering_options: LoweringOptions,
+    assumptions: Vec<TypeAssumption>,
+    simplification_cache: SimplificationCache,
+    sketch_cache: SketchCache<CustomLatticeElement>,
+    recovered_shared_returns: bool,
+    allocators: Vec<AllocatorSpec>,
+    deallocation_symbols: Vec<String>,
+    should_pair_heap_lifetimes: bool,
+    should_detect_allocator_wrappers: bool,
+    recovered_allocator_wrappers: bool,
+    should_constrain_allocation_sizes: bool,
+    should_collapse_thunks: bool,
+    collapsed_thunks: bool,
+    should_prune_dead_defs: bool,
+    pruned_dead_defs: bool,
+    return_usage_threshold: Option<usize>,
+    suppressed_unused_returns: bool,
+    prefer_pointer_facts: bool,
+    offset_clustering: OffsetClustering,
+    checkpoint: Option<Checkpoint>,
+    resuming: bool,
+    simplification_passes: Rc<PassPipeline>,
+    constraint_dumps: Option<ConstraintDumpGranularity>,
+    query_points: Vec<QueryPoint>,
+    local_variable_types: bool,
+    register_types: bool,
+    observers: Vec<Rc<dyn PipelineObserver>>,
+    transformers: Vec<Rc<dyn ConstraintTransformer>>,
+    callsite_signatures: bool,
+    context_depth: usize,
+    name_hints: Option<ParameterNames>,
+    platform: Option<PlatformProfile>,
+    should_type_syscalls: bool,
+    recovered_syscalls: bool,
+    freestanding: Option<FreestandingProfile>,
+    recovered_sdk_allocators: bool,
+    should_recover_go_metadata: bool,
+    recovered_go_metadata: bool,
+    should_apply_rust_heuristics: bool,
+    recovered_rust_symbols: bool,
+    should_use_unwind_frames: bool,
+    should_type_relocations: bool,
+    should_type_windows_imports: bool,
+    should_model_tls: bool,
+    should_isolate_landing_pads: bool,
+    should_type_pointer_integers: bool,
+    normalized_interworking_calls: bool,
+    normalized_mips_code: bool,
+    modeled_riscv_calling_convention: bool,
+    normalized_ppc_code: bool,
+    memory_budget: Option<usize>,
+    degradations: Vec<DegradationEvent>,
+    rule_traces: bool,
+    entailment_checks: Option<usize>,
+    entailment_failures: Vec<EntailmentFailure>,
+    isolate_solve_failures: bool,
+    solve_failures: Vec<SolveFailure>,
+    // The points-to context of the current solve, computed on first use, see [InferenceJob::points_to_context]
+    points_to: RefCell<Option<Rc<HashMap<NodeIndex, PointsToContext>>>>,
 }
 
 /// A way to parse readers into a given representation type
@@ -144,7 +297,7 @@ impl<T: DeserializeOwned> InferenceParsing<T> for JsonDef {
     }
 }
 
-type LoweredTypeMap = (HashMap<NodeIndex, TypeId>, BTreeMap<TypeId, CType>);
+type LoweredTypeMap = (BTreeMap<NodeIndex, TypeId>, BTreeMap<TypeId, CType>);
 type UserDefinedSketches = SketchGraph<LatticeBounds<CustomLatticeElement>>;
 
 fn parse_collection_from_file<T: Message + Default, R: Read>(mut r: R) -> anyhow::Result<Vec<T>> {
@@ -178,6 +331,481 @@ impl InferenceJob {
         &self.lattice
     }
 
+    /// Sets the options used when lowering sketches to ctypes.
+    pub fn set_lowering_options(&mut self, options: LoweringOptions) {
+        self.lowering_options = options;
+    }
+
+    /// Gets the options used when lowering sketches to ctypes.
+    pub fn get_lowering_options(&self) -> &LoweringOptions {
+        &self.lowering_options
+    }
+
+    fn data_model_of(proj: &Project, binary_bytes: &[u8]) -> DataModel {
+        DataModel::for_target(
+            (proj.stack_pointer_register.size.as_bit_length() / 8) as usize,
+            PeFile::parse(binary_bytes).is_some(),
+        )
+    }
+
+    /// Gets the data model of the target, which determines the sizes of primitives such as long and size_t.
+    pub fn get_data_model(&self) -> DataModel {
+        Self::data_model_of(&self.proj, &self.binary_bytes)
+    }
+
+    /// Sets the symbols the points-to analysis treats as heap allocators.
+    pub fn set_allocators(&mut self, allocators: Vec<AllocatorSpec>) {
+        self.allocators = allocators;
+    }
+
+    /// Gets the symbols treated as heap allocators, including detected wrappers.
+    pub fn get_allocators(&self) -> &[AllocatorSpec] {
+        &self.allocators
+    }
+
+    /// Sets the symbols that free the heap object passed as their first parameter.
+    pub fn set_deallocation_symbols(&mut self, deallocation_symbols: Vec<String>) {
+        self.deallocation_symbols = deallocation_symbols;
+    }
+
+    /// Sets whether the accesses to a heap object are split by the pairs of allocation and free delimiting its
+    /// lifetimes, see [InferenceJob::get_heap_lifetimes].
+    pub fn set_heap_lifetime_pairing(&mut self, should_pair: bool) {
+        self.should_pair_heap_lifetimes = should_pair;
+    }
+
+    /// Sets whether thin wrappers around allocators are detected and treated as allocators.
+    pub fn set_detect_allocator_wrappers(&mut self, should_detect: bool) {
+        self.should_detect_allocator_wrappers = should_detect;
+    }
+
+    /// Sets whether the size parameters of calls to allocators, see [AllocatorSpec::size_params], are constrained to be
+    /// integral.
+    pub fn set_allocation_size_constraints(&mut self, should_constrain: bool) {
+        self.should_constrain_allocation_sizes = should_constrain;
+    }
+
+    /// Sets whether calls to PLT stubs and tail call thunks are retargeted to the functions they forward to.
+    pub fn set_thunk_collapsing(&mut self, should_collapse: bool) {
+        self.should_collapse_thunks = should_collapse;
+    }
+
+    /// Sets whether assignments to registers that are never read are removed before generating constraints.
+    pub fn set_dead_def_pruning(&mut self, should_prune: bool) {
+        self.should_prune_dead_defs = should_prune;
+    }
+
+    /// Sets the number of callsites that must read the return registers of a subprocedure after calling it for the
+    /// subprocedure to keep its returns, otherwise it is typed as returning void. Returns are kept regardless of their
+    /// callers when unset.
+    pub fn set_return_usage_threshold(&mut self, threshold: Option<usize>) {
+        self.return_usage_threshold = threshold;
+    }
+
+    /// Sets whether the points-to context of each program point is combined with its register context, so loads and
+    /// stores the points-to analysis resolves to a slot of the current frame type the slot directly rather than through
+    /// the registers computing its address, see [CombinedContext].
+    pub fn set_combined_context(&mut self, prefer_pointer_facts: bool) {
+        self.prefer_pointer_facts = prefer_pointer_facts;
+    }
+
+    /// Sets whether the temporaries of each function are generated in the scope of the function rather than globally,
+    /// so they are named independently of the rest of the program and are renamed the same when an scc is resimplified,
+    /// see [VariableManager::set_scope].
+    pub fn set_function_scoped_variables(&mut self, function_scopes: bool) {
+        self.vman = std::mem::take(&mut self.vman).with_function_scopes(function_scopes);
+    }
+
+    /// Sets the approximate number of bytes the solver may use before it degrades the constraints of the sccs it simplifies,
+    /// see [MemoryBudget](crate::solver::memory::MemoryBudget). None never degrades.
+    pub fn set_memory_budget(&mut self, limit_bytes: Option<usize>) {
+        self.memory_budget = limit_bytes;
+    }
+
+    /// Gets the degradations applied during the last solve to stay within the memory budget.
+    pub fn get_degradations(&self) -> &[DegradationEvent] {
+        &self.degradations
+    }
+
+    /// Sets whether the rules applied to build and saturate the constraint graph of each scc are dumped to the
+    /// `constraints/rule_traces` directory, in full and per interesting variable.
+    pub fn set_rule_traces(&mut self, rule_traces: bool) {
+        self.rule_traces = rule_traces;
+    }
+
+    /// Sets how many original constraints over interesting variables of each scc are checked to be entailed by its simplified
+    /// constraints, see [unentailed_constraints](crate::solver::entailment::unentailed_constraints). None skips the check.
+    pub fn set_entailment_checks(&mut self, sample_size: Option<usize>) {
+        self.entailment_checks = sample_size;
+    }
+
+    /// Gets the sampled constraints that were not entailed by the simplified constraints of their scc during the last solve.
+    pub fn get_entailment_failures(&self) -> &[EntailmentFailure] {
+        &self.entailment_failures
+    }
+
+    /// Sets whether a failure to solve an scc only gives its subprocedures default types rather than failing the whole
+    /// solve. Enabled by default, see [InferenceJob::get_solve_failures].
+    pub fn set_solve_failure_isolation(&mut self, isolate: bool) {
+        self.isolate_solve_failures = isolate;
+    }
+
+    /// Gets the sccs that failed to solve during the last solve. Their subprocedures were solved as if they had no
+    /// constraints.
+    pub fn get_solve_failures(&self) -> &[SolveFailure] {
+        &self.solve_failures
+    }
+
+    /// Sets how the points-to analysis folds access offsets into fields.
+    pub fn set_offset_clustering(&mut self, clustering: OffsetClustering) {
+        self.offset_clustering = clustering;
+    }
+
+    /// Sets the passes used to simplify the constraint graph of each scc. Results cached from solves with other passes are not invalidated,
+    /// so the passes should be set before solving.
+    pub fn set_simplification_passes(&mut self, passes: PassPipeline) {
+        self.simplification_passes = Rc::new(passes);
+    }
+
+    /// Writes the constraints generated for each cfg node or function to its own file in the debug directory.
+    /// Has no effect unless a debug directory is set.
+    pub fn set_constraint_dumps(&mut self, granularity: Option<ConstraintDumpGranularity>) {
+        self.constraint_dumps = granularity;
+    }
+
+    /// Registers an observer that is notified as constraints are generated and simplified and sketches are built.
+    pub fn add_observer(&mut self, observer: Rc<dyn PipelineObserver>) {
+        self.observers.push(observer);
+    }
+
+    /// Registers a transformer that rewrites the constraints of each scc between generation and simplification.
+    /// Transformers run in the order they are registered.
+    pub fn add_transformer(&mut self, transformer: Rc<dyn ConstraintTransformer>) {
+        self.transformers.push(transformer);
+    }
+
+    /// If enabled, the sketch graph labels the type of each callee at each of its callsites so that it is lowered
+    /// alongside the merged type of the callee, see [InferenceJob::get_callsite_labeling].
+    pub fn set_callsite_signatures(&mut self, enabled: bool) {
+        self.callsite_signatures = enabled;
+    }
+
+    /// Sets the length of the call strings callee types are cloned for at callsites rather than merged, see
+    /// [SCCSketchsBuilder::with_context_depth]. Each level clones the types of more callees into every caller, so the
+    /// sketch graph and the lowered types grow with the depth.
+    pub fn set_context_depth(&mut self, k: usize) {
+        self.context_depth = k;
+    }
+
+    /// Enables seeding low confidence types guessed from the names of subprocedures, extern symbols, and the given
+    /// parameter names, see [name_heuristics::signature_hints]. Disabled when None.
+    pub fn set_name_heuristics(&mut self, parameter_names: Option<ParameterNames>) {
+        self.name_hints = parameter_names;
+    }
+
+    /// Seeds the typedefs of a platform profile on the signatures of the wrapper functions it knows, see
+    /// [PlatformProfile::signature_constraints]. The typedefs must be part of the lattice, ie. by parsing the job with
+    /// [PlatformProfile::lattice_definition] as an additional lattice, typedefs missing from it are skipped.
+    pub fn set_platform_profile(&mut self, profile: Option<PlatformProfile>) {
+        self.platform = profile;
+    }
+
+    /// Enables replacing syscall instructions with a constant syscall number by calls to stubs typed from a bundled
+    /// syscall table, see [syscalls::stub_syscalls]. The typedefs of [syscalls::syscall_profile] must be part of the
+    /// lattice, typedefs missing from it are skipped.
+    pub fn set_syscall_typing(&mut self, should_type_syscalls: bool) {
+        self.should_type_syscalls = should_type_syscalls;
+    }
+
+    /// Analyzes the project as a kernel or firmware without libc. The default libc allocators are replaced by the
+    /// allocation routines of the profile, platform profiles and syscall typing are disabled, and the SDK functions
+    /// of the profile are typed from their signatures, see [FreestandingProfile::sdk_constraints]. Allocators set with
+    /// [InferenceJob::set_allocators] are kept.
+    pub fn set_freestanding_profile(&mut self, profile: Option<FreestandingProfile>) {
+        if let Some(profile) = &profile {
+            let defaults = allocators::default_allocators();
+            self.allocators.retain(|alloc| !defaults.contains(alloc));
+            self.allocators.extend(profile.allocators.iter().cloned());
+            self.platform = None;
+            self.should_type_syscalls = false;
+        }
+        self.freestanding = profile;
+    }
+
+    /// Sets whether the names and ABI of Go functions are recovered from the pclntab of the binary before generating
+    /// constraints, see [InferenceJob::recover_go_metadata].
+    pub fn set_go_metadata(&mut self, should_recover: bool) {
+        self.should_recover_go_metadata = should_recover;
+    }
+
+    /// Enables the heuristics for Rust binaries: legacy mangled symbols are demangled, the allocators of the Rust
+    /// standard library are added to the allocators, the functions of core and alloc taking sizes and lengths are
+    /// typed from [rust_binaries::rust_profile], and the fat pointer parameters of Rust functions are lowered like
+    /// slices, see [rust_binaries::fat_pointer_parameters]. The typedefs of the profile must be part of the lattice,
+    /// typedefs missing from it are skipped.
+    pub fn set_rust_heuristics(&mut self, should_apply: bool) {
+        if should_apply {
+            for alloc in rust_binaries::rust_allocators() {
+                if !self.allocators.contains(&alloc) {
+                    self.allocators.push(alloc);
+                }
+            }
+        }
+        self.should_apply_rust_heuristics = should_apply;
+    }
+
+    /// Sets whether the stack frames of subprocedures are recovered from the `.eh_frame` section of the binary, see
+    /// [InferenceJob::get_frame_layouts].
+    pub fn set_unwind_frames(&mut self, should_use: bool) {
+        self.should_use_unwind_frames = should_use;
+    }
+
+    /// Sets whether the relocated words of global variables are seeded as pointers, see
+    /// [relocations::relocation_constraints].
+    pub fn set_relocation_typing(&mut self, should_type: bool) {
+        self.should_type_relocations = should_type;
+    }
+
+    /// Sets whether the imports of PE binaries are typed from the bundled Windows API signatures, see
+    /// [windows_api::import_constraints].
+    pub fn set_windows_imports(&mut self, should_type: bool) {
+        self.should_type_windows_imports = should_type;
+    }
+
+    /// Sets whether loads and stores relative to the thread pointer are typed as fields of a thread-local storage block,
+    /// see [TlsModel]. The block is the global `glb_tls_block`. Architectures without a model are unaffected.
+    pub fn set_tls_modeling(&mut self, should_model: bool) {
+        self.should_model_tls = should_model;
+    }
+
+    /// Sets whether the constraints generated on the exception paths of C++ functions are isolated from their normal
+    /// flow, see [InferenceJob::get_exception_paths].
+    pub fn set_landing_pad_isolation(&mut self, should_isolate: bool) {
+        self.should_isolate_landing_pads = should_isolate;
+    }
+
+    /// Sets whether indices scaled by the size of a pointer and differences of pointers are bounded by the `size_t` and
+    /// `ptrdiff_t` elements of the lattice during constraint generation. Lattices without both elements are unaffected.
+    pub fn set_pointer_integer_typing(&mut self, should_type: bool) {
+        self.should_type_pointer_integers = should_type;
+    }
+
+    /// Gets the exception paths starting at the landing pads of the call site tables in the `.gcc_except_table` section
+    /// of the binary, or nothing if landing pad isolation is disabled. The variables of the constraints generated on them
+    /// are renamed into a namespace per subprocedure, so the exception object and selector registers a landing pad reads
+    /// are not typed as parameters of the subprocedure.
+    pub fn get_exception_paths(&self) -> Option<ExceptionPaths> {
+        if !self.should_isolate_landing_pads {
+            return None;
+        }
+
+        let landing_pads = unwind::landing_pads(&self.proj, &self.binary_bytes)
+            .into_values()
+            .flatten()
+            .collect();
+        let paths = ExceptionPaths::new(&self.proj, &landing_pads);
+        log::info!(
+            "Isolated {} blocks on the exception paths of {} landing pads",
+            paths.len(),
+            landing_pads.len()
+        );
+        Some(paths)
+    }
+
+    /// Gets the lifetimes of the heap objects that are freed by a call to one of the deallocation symbols and accessed
+    /// again afterwards in the same subprocedure, ie. after the object is allocated again by calling the function that
+    /// allocated it, or nothing if heap lifetime pairing is disabled. The accesses of each lifetime get a variable of
+    /// their own, so the unrelated uses of a recycled object are not merged.
+    pub fn get_heap_lifetimes(&self, graph: &Graph) -> anyhow::Result<Option<HeapLifetimes>> {
+        if !self.should_pair_heap_lifetimes {
+            return Ok(None);
+        }
+
+        let points_to_context = self.points_to_context(graph)?;
+        let lifetimes = HeapLifetimes::new(
+            &self.proj,
+            graph,
+            &points_to_context,
+            &self.deallocation_symbols,
+        );
+        log::info!(
+            "Split the accesses to heap objects into {} lifetimes",
+            lifetimes.lifetimes().len()
+        );
+        self.debug_dir
+            .phase("constraints")
+            .log_to_fname("heap_lifetimes", &|| {
+                serde_json::to_string_pretty(lifetimes.lifetimes()).unwrap_or_default()
+            })?;
+        Ok(Some(lifetimes))
+    }
+
+    /// Gets the frame layouts of the subprocedures recovered from the call frame information of the binary, or
+    /// nothing if unwind frames are disabled. Stack accesses of these subprocedures are relative to their CFA and
+    /// accesses to the slots of saved registers do not generate constraints.
+    pub fn get_frame_layouts(&self) -> BTreeMap<Tid, FrameLayout> {
+        if !self.should_use_unwind_frames {
+            return BTreeMap::new();
+        }
+
+        let frames = unwind::frame_layouts(&self.proj, &self.binary_bytes);
+        log::info!(
+            "Recovered the frames of {} of {} subprocedures from unwind information",
+            frames.len(),
+            self.proj.program.term.subs.len()
+        );
+        frames
+    }
+
+    /// Adds the subprocedures and extern symbols selected from the project to the interesting tids.
+    pub fn select_interesting(&mut self, selection: &RuleContextBuilder) {
+        self.interesting_tids
+            .extend(selection.resolve_tids(&self.proj));
+    }
+
+    /// Sets the program points whose types will be queried with a [TypeQuery]. The variables representing these points
+    /// are kept while simplifying constraints, so the points should be set before solving.
+    pub fn set_query_points(&mut self, points: Vec<QueryPoint>) {
+        self.query_points = points;
+    }
+
+    // The points whose variables are kept while simplifying constraints: the points that were set, the frames of the
+    // interesting subprocedures if their local variables are typed, and their key registers if registers are typed
+    fn kept_query_points(&self) -> Vec<QueryPoint> {
+        let frames = self
+            .interesting_tids
+            .iter()
+            .filter(|_| self.local_variable_types)
+            .map(|sub| QueryPoint::StackSlot {
+                sub: sub.clone(),
+                offset: 0,
+            });
+        let registers = self
+            .interesting_subs()
+            .filter(|_| self.register_types)
+            .flat_map(|sub| {
+                key_program_points(&self.proj.program.term, &self.proj.cpu_architecture, sub)
+            });
+        self.query_points
+            .iter()
+            .cloned()
+            .chain(frames)
+            .chain(registers)
+            .collect()
+    }
+
+    /// Whether solving keeps the variables a [TypeQuery] needs to answer for a point. A stack slot is represented by the
+    /// objects of its subprocedure's frame, so it is kept if any slot of the same subprocedure is.
+    pub fn keeps_query_point(&self, point: &QueryPoint) -> bool {
+        self.kept_query_points()
+            .iter()
+            .any(|kept| match (kept, point) {
+                (QueryPoint::StackSlot { sub: kept, .. }, QueryPoint::StackSlot { sub, .. }) => {
+                    kept == sub
+                }
+                _ => kept == point,
+            })
+    }
+
+    /// If enabled, the stack frames of the interesting subprocedures are kept while simplifying constraints so the types
+    /// of their local variables can be lowered, see [InferenceJob::get_stack_frame_types].
+    pub fn set_local_variable_types(&mut self, enabled: bool) {
+        self.local_variable_types = enabled;
+    }
+
+    /// If enabled, the registers at the key program points of the interesting subprocedures are kept while simplifying
+    /// constraints so their types can be lowered, see [InferenceJob::get_register_bindings]. Keeping every reaching
+    /// definition of these registers makes the simplified constraints and the sketch graph larger.
+    pub fn set_register_types(&mut self, enabled: bool) {
+        self.register_types = enabled;
+    }
+
+    /// Compresses the artifacts written to the debug directory with zstd.
+    pub fn set_compress_debug_output(&mut self, compress: bool) {
+        self.debug_dir = self.debug_dir.clone().with_compression(compress);
+    }
+
+    /// Renames generated type variables canonically in the constraints written to the debug directory, so that dumps
+    /// from different runs can be diffed.
+    pub fn set_canonical_constraint_names(&mut self, canonical_names: bool) {
+        self.debug_dir = self.debug_dir.clone().with_canonical_names(canonical_names);
+    }
+
+    /// Saves intermediate state to a checkpoint directory while inferring types. When resuming, the state an interrupted run
+    /// with the same inputs left in the directory is reused, otherwise it is discarded. The inputs are the binary, its
+    /// project, the lattice, the additional constraints and the interesting tids as they are when the directory is set.
+    pub fn set_checkpoint_dir(&mut self, dir: &str, resume: bool) -> anyhow::Result<()> {
+        let checkpoint =
+            Checkpoint::open(std::path::Path::new(dir), resume, self.input_fingerprint()?)?;
+        self.simplification_cache = checkpoint.simplification_cache()?;
+        if let Some(vman) = self.simplification_cache.get_journaled_variables() {
+            self.vman = vman
+                .clone()
+                .with_function_scopes(self.vman.uses_function_scopes());
+        }
+        self.resuming = checkpoint.is_resumed();
+        self.checkpoint = Some(checkpoint);
+        Ok(())
+    }
+
+    fn input_fingerprint(&self) -> anyhow::Result<InputFingerprint> {
+        let mut fingerprint = InputFingerprint::default();
+        fingerprint.add_bytes(&self.binary_bytes);
+        fingerprint.add_json(&self.proj)?;
+
+        let mut elems = self.lattice.get_nds().iter().collect::<Vec<_>>();
+        elems.sort_by(|(x, _), (y, _)| x.cmp(y));
+        let orderings = elems
+            .iter()
+            .flat_map(|(x, x_elem)| {
+                elems
+                    .iter()
+                    .filter(move |(_, y_elem)| x_elem <= y_elem)
+                    .map(move |(y, _)| (x, y))
+            })
+            .collect::<Vec<_>>();
+        fingerprint.add_json(&orderings)?;
+
+        fingerprint.add_json(&self.additional_constraints.iter().collect::<Vec<_>>())?;
+        fingerprint.add_json(&self.interesting_tids.iter().collect::<BTreeSet<_>>())?;
+        Ok(fingerprint)
+    }
+
+    fn pointer_config(&self) -> Config {
+        Config {
+            allocation_symbols: self
+                .allocators
+                .iter()
+                .map(|alloc| alloc.symbol.clone())
+                .collect(),
+        }
+    }
+
+    /// Gets the points-to context of the cfg of the project, running the pointer analysis on first use only. The
+    /// analyses keying program points and lowering hints by abstract objects share it, and each solve drops it since
+    /// recoveries may rewrite the project.
+    fn points_to_context(
+        &self,
+        graph: &Graph,
+    ) -> anyhow::Result<Rc<HashMap<NodeIndex, PointsToContext>>> {
+        if let Some(cached) = self.points_to.borrow().as_ref() {
+            return Ok(cached.clone());
+        }
+
+        let analysis_results = AnalysisResults::new(&self.binary_bytes, graph, &self.proj);
+        let (res, logs) = analysis_results.compute_function_signatures();
+        logs.iter().for_each(crate::util::log_cwe_message);
+
+        let analysis_results = analysis_results.with_function_signatures(Some(&res));
+        let points_to_context = Rc::new(points_to::run_analysis(
+            &analysis_results,
+            self.pointer_config(),
+            self.offset_clustering,
+        )?);
+        self.points_to.replace(Some(points_to_context.clone()));
+        Ok(points_to_context)
+    }
+
     /// Gets the logger struct associated with this job.
     pub fn get_file_logger(&self) -> FileDebugLogger {
         self.debug_dir.clone()
@@ -188,9 +816,9 @@ impl InferenceJob {
         std::fs::read(bin_path).map_err(|err| anyhow::Error::from(err).context("parsing_binary"))
     }
 
-    /// Parses an IR json to a [Project]
+    /// Parses an IR json to a [Project]. The IR may be gzip or zstd compressed, and is read from stdin if the path is "-".
     pub fn parse_project(proj_path: &str, bin_bytes: &[u8]) -> anyhow::Result<Project> {
-        let json_file = std::fs::File::open(proj_path)?;
+        let json_file = crate::util::open_input(proj_path)?;
 
         let mut ir = crate::util::get_intermediate_representation_for_reader(json_file, bin_bytes)
             .context("parsing_project")?;
@@ -201,6 +829,52 @@ impl InferenceJob {
         Ok(ir)
     }
 
+    /// Parses an IR json to a [Project] for a bare metal image with the given memory layout.
+    pub fn parse_bare_metal_project(
+        proj_path: &str,
+        bin_bytes: &[u8],
+        layout: &BareMetalLayout,
+    ) -> anyhow::Result<Project> {
+        let json_file = crate::util::open_input(proj_path)?;
+
+        let mut ir = crate::util::get_intermediate_representation_for_bare_metal_reader(
+            json_file, bin_bytes, layout,
+        )
+        .context("parsing_bare_metal_project")?;
+        log::info!("Retrieved bare metal IR");
+        ir.normalize().iter().for_each(crate::util::log_cwe_message);
+        log::info!("Normalized IR");
+
+        Ok(ir)
+    }
+
+    /// Parses an IR json to a [Project], streaming subprocedures from the json and only keeping the bodies of those with
+    /// a tid id in selected_bodies. If a layout is provided the binary is treated as a bare metal image.
+    pub fn parse_selected_project(
+        proj_path: &str,
+        bin_bytes: &[u8],
+        selected_bodies: &BTreeSet<String>,
+        bare_metal: Option<&BareMetalLayout>,
+    ) -> anyhow::Result<Project> {
+        let json_file = crate::util::open_input(proj_path)?;
+
+        let mut ir = crate::util::get_intermediate_representation_for_selected_reader(
+            json_file,
+            bin_bytes,
+            selected_bodies,
+            bare_metal,
+        )
+        .context("parsing_selected_project")?;
+        log::info!(
+            "Retrieved IR keeping {} subprocedure bodies",
+            selected_bodies.len()
+        );
+        ir.normalize().iter().for_each(crate::util::log_cwe_message);
+        log::info!("Normalized IR");
+
+        Ok(ir)
+    }
+
     /// Parse lattice definition from lattice
     pub fn parse_lattice_json_to_lattice_def(
         lattice_json: &str,
@@ -240,14 +914,67 @@ impl InferenceJob {
         Ok(constraints
             .into_iter()
             .fold(BTreeMap::new(), |mut acc, add_cons| {
+                let origin = ConstraintOrigin::new(
+                    add_cons.associated_variable.clone(),
+                    ConstraintReason::Additional,
+                );
                 acc.entry(add_cons.associated_variable)
                     .or_insert_with(ConstraintSet::default)
-                    .insert(TyConstraint::SubTy(add_cons.constraint));
+                    .insert(TyConstraint::SubTy(add_cons.constraint.with_origin(origin)));
                 acc
             }))
     }
 
-    fn parse_tid_set<T: InferenceParsing<Tid>>(
+    /// Parses a json file containing a list of [TypeAssumption]s.
+    pub fn parse_assumptions(assumptions_file: &str) -> anyhow::Result<Vec<TypeAssumption>> {
+        let fl = std::fs::File::open(assumptions_file).context("assumptions file")?;
+        <JsonDef as InferenceParsing<TypeAssumption>>::parse_collection(fl)
+    }
+
+    fn assumptions_to_constraints(
+        &self,
+        assumptions: &[TypeAssumption],
+    ) -> anyhow::Result<BTreeMap<Tid, ConstraintSet>> {
+        let all_subs = self.proj.program.term.subs.keys().cloned().collect();
+        let lattice_elems = self.lattice.get_nds().keys().cloned().collect();
+        assumptions::assumptions_to_constraints(
+            assumptions,
+            &all_subs,
+            &lattice_elems,
+            &self.lowering_options.primitive_table,
+            self.proj.stack_pointer_register.size.as_bit_length(),
+        )
+    }
+
+    /// Adds type assumptions that are translated into additional constraints when constraints are simplified.
+    /// Assumptions can be added after a solve, the next solve will only resimplify the sccs affected by the new assumptions.
+    pub fn add_assumptions(&mut self, assumptions: &[TypeAssumption]) -> anyhow::Result<()> {
+        // check that the assumptions are translatable before accepting them
+        self.assumptions_to_constraints(assumptions)?;
+        self.assumptions.extend(assumptions.iter().cloned());
+        Ok(())
+    }
+
+    /// Replaces the type assumptions applied to this job, for instance with the ones returned by
+    /// [InferenceJob::get_assumptions] before a failed solve.
+    pub fn set_assumptions(&mut self, assumptions: Vec<TypeAssumption>) {
+        self.assumptions = assumptions;
+    }
+
+    /// Removes a previously added type assumption, returning whether the assumption was present.
+    pub fn remove_assumption(&mut self, assumption: &TypeAssumption) -> bool {
+        let prev_len = self.assumptions.len();
+        self.assumptions.retain(|x| x != assumption);
+        prev_len != self.assumptions.len()
+    }
+
+    /// Gets the type assumptions currently applied to this job.
+    pub fn get_assumptions(&self) -> &[TypeAssumption] {
+        &self.assumptions
+    }
+
+    /// Parses a set of tids, ie. the interesting tids of a job.
+    pub fn parse_tid_set<T: InferenceParsing<Tid>>(
         interesting_tid_file: &str,
     ) -> anyhow::Result<HashSet<Tid>> {
         let constraint_file =
@@ -272,7 +999,7 @@ impl InferenceJob {
 
     /// gets the cfg for this inference job
     pub fn get_graph(&self) -> Graph {
-        Self::graph_from_project(&self.proj)
+        self.source.control_flow_graph(&self.proj)
     }
 
     /// Creates a [RuntimeMemory] by loading a binary.
@@ -295,9 +1022,14 @@ impl InferenceJob {
         &self,
         graph: &'a Graph<'a>,
     ) -> anyhow::Result<
-        HashMap<
+        BTreeMap<
             NodeIndex,
-            NodeContext<RegisterContext, PointsToContext, ProcedureContext, GhidraConstantResolver>,
+            NodeContext<
+                RegisterContext,
+                CombinedContext<RegisterContext, PointsToContext>,
+                ProcedureContext,
+                GhidraConstantResolver,
+            >,
         >,
     > {
         let analysis_results = AnalysisResults::new(&self.binary_bytes, graph, &self.proj);
@@ -307,14 +1039,45 @@ impl InferenceJob {
 
         let analysis_results = analysis_results.with_function_signatures(Some(&res));
 
-        let nd_context = crate::node_context::create_default_context(
+        let nd_context = crate::node_context::create_combined_context(
             &analysis_results,
-            crate::node_context::points_to::DEFAULT_PTR_CONFIG.clone(),
+            self.pointer_config(),
+            self.offset_clustering,
             self.weakest_integral_type.clone(),
-            self.debug_dir.clone(),
+            self.pointer_integer_types(),
+            &self.get_frame_layouts(),
+            self.prefer_pointer_facts,
+            self.debug_dir.phase("node_context"),
         )?;
 
-        Ok(nd_context)
+        let tls = self
+            .should_model_tls
+            .then(|| TlsModel::for_architecture(&self.proj.cpu_architecture))
+            .flatten();
+        Ok(nd_context
+            .into_iter()
+            .map(|(idx, ctx)| (idx, ctx.with_tls_model(tls.clone())))
+            .collect())
+    }
+
+    /// The size_t and ptrdiff_t elements of the lattice, if pointer integers are typed and the lattice has both.
+    fn pointer_integer_types(&self) -> Option<PointerIntegerTypes> {
+        if !self.should_type_pointer_integers {
+            return None;
+        }
+
+        let elem = |name: &str| {
+            self.lattice
+                .get_nds()
+                .contains_key(name)
+                .then(|| TypeVariable::new(name.to_owned()))
+        };
+
+        Some(PointerIntegerTypes {
+            size: elem("size_t")?,
+            difference: elem("ptrdiff_t")?,
+            word_size: (self.proj.stack_pointer_register.size.as_bit_length() / 8) as u64,
+        })
     }
 
     /// Gets an iterator of type variables for each type constant
@@ -330,10 +1093,181 @@ impl InferenceJob {
         &self.additional_constraints
     }
 
+    /// Gets the additional constraints for this job combined with the constraints implied by the current type assumptions.
+    pub fn get_effective_additional_constraints(
+        &self,
+    ) -> anyhow::Result<BTreeMap<Tid, ConstraintSet>> {
+        let mut total = self.additional_constraints.clone();
+        let size_constraints = if self.should_constrain_allocation_sizes {
+            allocators::size_parameter_constraints(
+                &self.proj.program.term,
+                &self.allocators,
+                &self.weakest_integral_type,
+            )
+        } else {
+            BTreeMap::new()
+        };
+        let name_constraints = self
+            .name_hints
+            .as_ref()
+            .map(|parameter_names| {
+                name_heuristics::name_hint_constraints(
+                    &self.proj,
+                    parameter_names,
+                    &self.hint_elements(),
+                )
+            })
+            .unwrap_or_default();
+        let platform_constraints = self
+            .platform
+            .as_ref()
+            .map(|profile| {
+                profile.platform_constraints(&self.proj, |name| {
+                    self.lattice.get_nds().contains_key(name)
+                })
+            })
+            .unwrap_or_default();
+        let sdk_constraints = self
+            .freestanding
+            .as_ref()
+            .map(|profile| {
+                profile
+                    .sdk_constraints(&self.proj, |name| self.lattice.get_nds().contains_key(name))
+            })
+            .unwrap_or_default();
+        let rust_constraints = if self.should_apply_rust_heuristics {
+            rust_binaries::rust_profile()
+                .platform_constraints(&self.proj, |name| self.lattice.get_nds().contains_key(name))
+        } else {
+            BTreeMap::new()
+        };
+        let syscall_constraints = if self.should_type_syscalls {
+            syscalls::syscall_constraints(&self.proj, |name| {
+                self.lattice.get_nds().contains_key(name)
+            })
+        } else {
+            BTreeMap::new()
+        };
+        let relocation_constraints = if self.should_type_relocations {
+            let relocs = relocations::pointer_relocations(&self.binary_bytes);
+            log::info!("Found {} pointer relocations", relocs.len());
+            relocations::relocation_constraints(
+                &self.proj,
+                &relocs,
+                &TypeVariable::new(self.lattice.bot().get_name().to_owned()),
+            )
+        } else {
+            BTreeMap::new()
+        };
+        let import_constraints = if self.should_type_windows_imports {
+            let imports = PeFile::parse(&self.binary_bytes)
+                .map(|pe| pe.imports())
+                .unwrap_or_default();
+            windows_api::import_constraints(&self.proj, &imports, |name| {
+                self.lattice.get_nds().contains_key(name)
+            })
+        } else {
+            BTreeMap::new()
+        };
+        let nullability_constraints = if self.lowering_options.infer_nullability {
+            let graph = self.source.control_flow_graph(&self.proj);
+            let reg_context = register_map::run_analysis(&self.proj, &graph);
+            nullability::nullability_constraints(
+                &self.proj,
+                &graph,
+                &reg_context,
+                &TypeVariable::new(self.lattice.top().get_name().to_owned()),
+            )
+        } else {
+            BTreeMap::new()
+        };
+        for (tid, cons) in self
+            .assumptions_to_constraints(&self.assumptions)?
+            .into_iter()
+            .chain(size_constraints)
+            .chain(name_constraints)
+            .chain(platform_constraints)
+            .chain(syscall_constraints)
+            .chain(sdk_constraints)
+            .chain(rust_constraints)
+            .chain(relocation_constraints)
+            .chain(import_constraints)
+            .chain(nullability_constraints)
+        {
+            total
+                .entry(tid)
+                .or_insert_with(ConstraintSet::default)
+                .insert_all(&cons);
+        }
+        Ok(total)
+    }
+
+    fn hint_elements(&self) -> HintElements {
+        let elem_or_weakest_integral = |name: &str| {
+            if self.lattice.get_nds().contains_key(name) {
+                TypeVariable::new(name.to_owned())
+            } else {
+                self.weakest_integral_type.clone()
+            }
+        };
+
+        HintElements {
+            size: elem_or_weakest_integral("size_t"),
+            file_descriptor: elem_or_weakest_integral("int"),
+        }
+    }
+
+    /// Detects thin wrappers around the configured allocators and stubs calls to them so that
+    /// each call to a wrapper allocates a distinct heap object.
+    pub fn recover_allocator_wrappers(&mut self) {
+        let wrappers = allocators::find_malloc_wrappers(&self.proj.program.term, &self.allocators);
+        let retargeted =
+            allocators::stub_allocator_wrappers(&mut self.proj.program.term, &wrappers);
+        log::info!(
+            "Stubbed {} allocator wrappers called at {} callsites",
+            wrappers.len(),
+            retargeted
+        );
+        self.allocators.extend(wrappers);
+    }
+
+    /// Names the subprocedures of a Go binary after the functions of its pclntab and replaces their formals, which the
+    /// IR guesses from the C ABI, with those of the Go ABI the binary was built with. Does nothing if the binary has
+    /// no pclntab.
+    pub fn recover_go_metadata(&mut self) {
+        let pclntab = match Pclntab::find(&self.binary_bytes) {
+            Some(pclntab) => pclntab,
+            None => {
+                diagnostics::report(
+                    DiagnosticKind::Other,
+                    "No pclntab found, the binary is analyzed as a C binary",
+                );
+                log::warn!("No pclntab found, the binary is analyzed as a C binary");
+                return;
+            }
+        };
+
+        let abi = GoAbi::for_binary(
+            pclntab.version,
+            golang::go_minor_version(&self.binary_bytes),
+            &self.proj.cpu_architecture,
+        );
+        let matched = golang::apply_pclntab(&mut self.proj, &pclntab, &abi);
+        log::info!(
+            "Recovered {} Go functions with the {:?} pclntab and {} ABI",
+            matched,
+            pclntab.version,
+            match abi {
+                GoAbi::Stack => "stack",
+                GoAbi::Register(_) => "register",
+            }
+        );
+    }
+
     /// Fix up the returns for the project owned by this job by inserting returns
     /// Ghidra missed related to tail calls.
     pub fn recover_additional_shared_returns(&mut self) {
-        let grph = Self::graph_from_project(&self.proj);
+        let grph = self.source.control_flow_graph(&self.proj);
         let reg_context = register_map::run_analysis(&self.proj, &grph);
         let reaching_defs_start_of_block = reg_context
             .iter()
@@ -378,12 +1312,35 @@ impl InferenceJob {
     pub fn get_simplified_constraints(
         &mut self,
     ) -> anyhow::Result<Vec<scc_constraint_generation::SCCConstraints>> {
-        let grph = Self::graph_from_project(&self.proj);
+        let grph = self.source.control_flow_graph(&self.proj);
         let node_ctxt = self.get_node_context(&grph)?;
 
         let cg = callgraph::CGContext::new(&self.proj).get_graph();
-        let rule_context = self.get_rule_context();
+        let mut rule_context = self.get_rule_context();
+        let kept_points = self.kept_query_points();
+        if !kept_points.is_empty() {
+            let points = self.program_points_for(&grph)?;
+            for point in kept_points.iter() {
+                points
+                    .variables(point)
+                    .into_iter()
+                    .for_each(|tv| rule_context.insert_variable(tv));
+            }
+        }
+        // the lowering hints are keyed by abstract objects, which only get a sketch node if they are interesting
+        self.get_lowering_objects(&grph)?
+            .into_iter()
+            .for_each(|object| rule_context.insert_variable(object));
         let lattice_elems = self.get_lattice_elems().collect();
+        let additional_constraints = self.get_effective_additional_constraints()?;
+        let noreturn_calls = NoreturnCalls::new(&self.proj, &grph);
+        let exception_paths = self.get_exception_paths();
+        let heap_lifetimes = self.get_heap_lifetimes(&grph)?;
+        log::info!(
+            "{} noreturn functions leave {} unreachable cfg nodes",
+            noreturn_calls.functions().len(),
+            noreturn_calls.unreachable_count()
+        );
         let mut context: scc_constraint_generation::Context<
             _,
             _,
@@ -407,11 +1364,83 @@ impl InferenceJob {
                     .expect("the weak integer type is always in the lattice"),
             ),
             rule_context,
-            self.debug_dir.clone(),
-            &self.additional_constraints,
-        );
-        let res = context.get_simplified_constraints();
-        println!(
+            self.debug_dir.phase("constraints"),
+            &additional_constraints,
+        )
+        .with_simplification_passes(self.simplification_passes.clone())
+        .with_constraint_dumps(self.constraint_dumps)
+        .with_observers(self.observers.clone())
+        .with_transformers(self.transformers.clone())
+        .with_memory_budget(self.memory_budget)
+        .with_rule_traces(self.rule_traces)
+        .with_entailment_checks(self.entailment_checks)
+        .with_noreturn_calls(noreturn_calls)
+        .with_exception_paths(exception_paths)
+        .with_heap_lifetimes(heap_lifetimes)
+        .with_failure_isolation(self.isolate_solve_failures);
+        let res = context.get_simplified_constraints_cached(&mut self.simplification_cache);
+        self.solve_failures = context.get_solve_failures().to_vec();
+        for failure in self.solve_failures.iter() {
+            let message = format!(
+                "Failed to solve the {:?} of scc [{}], its subprocedures get default types: {}",
+                failure.stage,
+                failure
+                    .scc
+                    .iter()
+                    .map(|tid| tid.get_str_repr())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                failure.message
+            );
+            diagnostics::report_at(DiagnosticKind::SolveFailure, failure.scc.first(), &message);
+            log::warn!("{}", message);
+        }
+        self.debug_dir
+            .phase("constraints")
+            .log_to_fname("solve_failures", &|| {
+                serde_json::to_string_pretty(&self.solve_failures)
+                    .expect("should be able to serialize solve failures")
+            })?;
+        self.entailment_failures = context.get_entailment_failures().to_vec();
+        for failure in self.entailment_failures.iter() {
+            let message = format!(
+                "Simplified constraints do not entail {}",
+                failure.constraint
+            );
+            diagnostics::report_at(
+                DiagnosticKind::UnentailedConstraint,
+                failure.scc.first(),
+                &message,
+            );
+            log::warn!("{}", message);
+        }
+        if self.entailment_checks.is_some() {
+            self.debug_dir
+                .phase("constraints")
+                .log_to_fname("entailment_failures", &|| {
+                    serde_json::to_string_pretty(&self.entailment_failures)
+                        .expect("should be able to serialize entailment failures")
+                })?;
+        }
+        self.degradations = context.get_degradations().to_vec();
+        for event in self.degradations.iter() {
+            let message = format!(
+                "Degraded by {:?} at an estimated {} bytes, {} of {} constraints kept",
+                event.degradation,
+                event.estimated_bytes,
+                event.constraints_after,
+                event.constraints_before
+            );
+            diagnostics::report_at(DiagnosticKind::Degradation, event.scc.first(), &message);
+            log::warn!("{}", message);
+        }
+        self.debug_dir
+            .phase("constraints")
+            .log_to_fname("degradations", &|| {
+                serde_json::to_string_pretty(&self.degradations)
+                    .expect("should be able to serialize degradations")
+            })?;
+        log::info!(
             "Num generated recursive variables: {}",
             self.vman.num_generated_loop_breakers()
         );
@@ -420,29 +1449,59 @@ impl InferenceJob {
 
     /// Converts simplified scc constraints into a single type supergraph with labels
     pub fn get_labeled_sketch_graph(
-        &self,
+        &mut self,
         scc_constraints: Vec<scc_constraint_generation::SCCConstraints>,
     ) -> anyhow::Result<SketchGraph<LatticeBounds<CustomLatticeElement>>> {
         let cg = callgraph::CGContext::new(&self.proj).get_graph();
-        let _elems = self.get_lattice_elems();
         let mut bldr = SCCSketchsBuilder::new(
             cg,
             scc_constraints,
             &self.lattice,
             self.get_lattice_elems().collect(),
-            self.debug_dir.clone(),
-        );
-
-        bldr.build()?;
-        bldr.build_global_type_graph()
+            self.debug_dir.phase("sketches"),
+        )
+        .with_callsite_labels(self.callsite_signatures)
+        .with_context_depth(self.context_depth)
+        .with_sketch_cache(std::mem::take(&mut self.sketch_cache));
+
+        let built = bldr.build();
+        self.sketch_cache = bldr.take_sketch_cache();
+        built?;
+        for (var, sketch) in bldr.get_scc_sketches().iter() {
+            self.observers
+                .iter()
+                .for_each(|observer| observer.on_sketch_built(var, sketch));
+        }
+        let grph = bldr.build_global_type_graph()?;
+        if let Some(limit) = self.memory_budget {
+            let estimated_bytes = MemoryBudget::estimate_graph_size(
+                grph.get_graph().get_graph().node_count(),
+                grph.get_graph().get_graph().edge_count(),
+            );
+            if estimated_bytes > limit {
+                let message = format!(
+                    "The sketch graph is estimated at {} bytes, over the memory budget of {} bytes",
+                    estimated_bytes, limit
+                );
+                diagnostics::report(DiagnosticKind::Degradation, &message);
+                log::warn!("{}", message);
+            }
+        }
+        self.observers
+            .iter()
+            .for_each(|observer| observer.on_global_sketch_graph(&grph));
+        self.debug_dir
+            .phase("sketches")
+            .log_to_fname("global_sketch_paths", &|| grph.paths())?;
+        Ok(grph)
     }
 
     /// For a given sketch supergraph, build a mapping from interesting type variables to the node that represents them.
     pub fn get_graph_labeling(
         &self,
         grph: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
-    ) -> HashMap<Tid, NodeIndex> {
-        let mut tot = HashMap::new();
+    ) -> BTreeMap<Tid, NodeIndex> {
+        let mut tot = BTreeMap::new();
         self.get_interesting_tids().iter().for_each(|x| {
             let tvar = crate::constraint_generation::tid_to_tvar(x);
             if let Some(idx) =
@@ -454,47 +1513,913 @@ impl InferenceJob {
         tot
     }
 
-    fn get_out_parameter_mapping(&self) -> HashMap<Tid, Vec<Arg>> {
-        self.proj
-            .program
-            .term
-            .subs
+    /// Maps each callsite to the node representing the type of the callee instantiated at the callsite. The mapping is
+    /// empty unless callsite signatures were enabled when the graph was built.
+    pub fn get_callsite_labeling(
+        &self,
+        grph: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> BTreeMap<Callsite, NodeIndex> {
+        find_callsite_nodes(grph, &collect_callsites(&self.proj.program.term))
+    }
+
+    /// Maps each structure node with fields pointing to structures of the same shape to its pattern, see
+    /// [crate::lowering::linked]. The mapping is empty unless linked structure detection is enabled in the lowering options.
+    pub fn get_linked_structure_labeling(
+        &self,
+        grph: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> BTreeMap<NodeIndex, LinkedStructure> {
+        if !self.lowering_options.detect_linked_structures {
+            return BTreeMap::new();
+        }
+        detect_linked_structures(grph.get_graph().get_graph())
+    }
+
+    /// Maps the node each constructor returns a pointer to, to the name of the structure it is guessed to construct. The
+    /// mapping is empty unless name heuristics are enabled.
+    pub fn get_structure_name_labeling(
+        &self,
+        grph: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> BTreeMap<NodeIndex, String> {
+        if self.name_hints.is_none() {
+            return BTreeMap::new();
+        }
+        find_constructed_structures(grph, &name_heuristics::constructors(&self.proj))
+    }
+
+    /// Names the fields accessed by getters and setters, ie. `get_count`, after the accessor, see
+    /// [crate::lowering::accessors]. Accessors are found from the simplified signatures of the last solve. The table is
+    /// empty unless name heuristics are enabled.
+    pub fn get_field_name_table(
+        &self,
+        grph: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> anyhow::Result<FieldNameTable> {
+        if self.name_hints.is_none() {
+            return Ok(FieldNameTable::new());
+        }
+
+        let subs = &self.proj.program.term.subs;
+        let names = subs
+            .iter()
+            .map(|(tid, sub)| (tid.clone(), sub.term.name.clone()))
+            .collect();
+        let accessors = find_accessors(self.simplification_cache.get_signatures(), &names, |tid| {
+            subs.get(tid).map(|sub| sub.term.blocks.len()).unwrap_or(0)
+        });
+        self.debug_dir
+            .phase("lowering")
+            .log_to_fname("accessors", &|| {
+                accessors
+                    .iter()
+                    .map(|acc| {
+                        format!(
+                            "{} in_{} {} {}",
+                            acc.function, acc.parameter, acc.field, acc.name
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })?;
+        Ok(build_field_name_table(grph, &accessors))
+    }
+
+    fn get_out_parameter_mapping(&self) -> HashMap<Tid, Vec<Arg>> {
+        self.proj
+            .program
+            .term
+            .subs
             .iter()
             .map(|(k, sub)| (k.clone(), sub.term.formal_rets.clone()))
             .collect()
     }
 
-    /// Uses heuristics to lower a supergraph to a ctype for each node.
+    /// Uses heuristics to lower a supergraph to a ctype for each node. Lowered types that break an invariant are
+    /// errors when [LoweringOptions::strict_invariants] is set, otherwise they are written to
+    /// `lowering/invariant_violations` in the debug directory.
     pub fn lower_labeled_sketch_graph(
         &self,
         sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
     ) -> anyhow::Result<LoweredTypeMap> {
+        let lowered = self.lowering_context(sg)?.lower()?;
+        self.debug_dir
+            .phase("lowering")
+            .log_to_fname("invariant_violations", &|| {
+                serde_json::to_string_pretty(&lowered.violations).unwrap_or_default()
+            })?;
+        if self.lowering_options.single_type {
+            self.log_rejected_alternatives(&lowered.rejected)?;
+        }
+        Ok((lowered.node_types, lowered.types))
+    }
+
+    fn log_rejected_alternatives(&self, rejected: &[RejectedAlternatives]) -> anyhow::Result<()> {
+        self.debug_dir
+            .phase("lowering")
+            .log_to_fname("rejected_alternatives", &|| {
+                serde_json::to_string_pretty(rejected).unwrap_or_default()
+            })
+    }
+
+    /// Lowers the sketch graph again and gets the candidates rejected for each node lowered to a single type, which is
+    /// empty unless [LoweringOptions::single_type] is set. The candidates are also written to `lowering/rejected_alternatives`
+    /// in the debug directory.
+    pub fn get_rejected_alternatives(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> anyhow::Result<Vec<RejectedAlternatives>> {
+        let (_, _, rejected) = self
+            .lowering_context(sg)?
+            .collect_ctypes_and_alternatives()?;
+        self.log_rejected_alternatives(&rejected)?;
+        Ok(rejected)
+    }
+
+    /// Builds the type scheme of every subprocedure from the signatures simplified by the last solve, so a consumer can
+    /// instantiate the polymorphic type of a function at its own callsites rather than use the lowered monomorphic
+    /// type. The schemes are logged to `sketches/type_schemes` in the debug directory.
+    pub fn get_type_schemes(&self) -> anyhow::Result<Vec<TypeScheme>> {
+        let schemes = build_type_schemes(
+            self.simplification_cache.get_signatures(),
+            &self.lattice,
+            &self.get_lattice_elems().collect(),
+            self.debug_dir.phase("sketches"),
+        )?;
+        self.debug_dir
+            .phase("sketches")
+            .log_to_fname("type_schemes", &|| {
+                schemes
+                    .iter()
+                    .map(|scheme| scheme.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })?;
+        Ok(schemes)
+    }
+
+    /// Lowers the supergraph one interesting term at a time, writing the types of each term to out as they are lowered.
+    /// Returns out along with the type id of each lowered node. See [crate::lowering::stream] for the format.
+    pub fn stream_labeled_sketch_graph<W: std::io::Write>(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+        out: W,
+    ) -> anyhow::Result<(W, BTreeMap<NodeIndex, TypeId>)> {
+        crate::lowering::stream::stream_mapping(
+            self.lowering_context(sg)?,
+            &self.get_graph_labeling(sg),
+            out,
+        )
+    }
+
+    fn lowering_context<'a>(
+        &self,
+        sg: &'a SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> anyhow::Result<LoweringContext<'a, CustomLatticeElement>> {
         let id = identity_element(&self.lattice);
-        LoweringContext::new(
+        let tagged_unions = if self.lowering_options.detect_tagged_unions {
+            self.get_tagged_union_layouts(sg)?
+        } else {
+            BTreeMap::new()
+        };
+        let nullability = if self.lowering_options.infer_nullability {
+            self.get_nullability(sg)
+        } else {
+            BTreeMap::new()
+        };
+        let array_extents = if self.lowering_options.infer_array_extents {
+            self.get_array_extents(sg)?
+        } else {
+            BTreeMap::new()
+        };
+        let character_types = if self.lowering_options.infer_character_types {
+            self.get_character_types(sg)?
+        } else {
+            BTreeMap::new()
+        };
+        let field_accesses = if self.lowering_options.count_field_accesses {
+            self.get_field_access_counts(sg)?
+        } else {
+            BTreeMap::new()
+        };
+        let fat_pointers = if self.should_apply_rust_heuristics {
+            rust_binaries::fat_pointer_parameters(&self.proj, sg)
+        } else {
+            BTreeMap::new()
+        };
+
+        let conflicts = self.get_lattice_conflicts(sg)?;
+        for conflict in conflicts.iter() {
+            diagnostics::report(
+                DiagnosticKind::LatticeConflict,
+                format!(
+                    "{:?} conflict between {} and {} of {}",
+                    conflict.kind,
+                    conflict.lower,
+                    conflict.upper,
+                    conflict.variables.join(", ")
+                ),
+            );
+        }
+        if !conflicts.is_empty() {
+            log::warn!(
+                "{} sketch nodes have conflicting lattice evidence, resolved by {:?}",
+                conflicts.len(),
+                self.lowering_options.conflict_strategy
+            );
+        }
+
+        self.get_size_mismatches(sg)?;
+        let parameter_count_mismatches = self.get_parameter_count_mismatches(sg)?;
+        let labeling = self.get_graph_labeling(sg);
+        let ctx = LoweringContext::new(
             sg,
-            &self.get_graph_labeling(sg),
+            &labeling,
             &self.get_out_parameter_mapping(),
             id,
+            self.lowering_options.clone(),
+        )
+        .with_tagged_unions(tagged_unions)
+        .with_nullability(nullability)
+        .with_array_extents(array_extents)
+        .with_character_types(character_types)
+        .with_field_accesses(field_accesses)
+        .with_fat_pointers(fat_pointers)
+        .with_parameter_count_mismatches(parameter_count_mismatches);
+
+        Ok(if self.lowering_options.lower_reachable_only {
+            let mut roots = Self::interesting_roots(sg, &labeling);
+            roots.extend(self.get_callsite_labeling(sg).into_values());
+            ctx.with_roots(roots)
+        } else {
+            ctx
+        })
+    }
+
+    // The nodes of the interesting terms and of the globals, lattice elements are interesting but are only constants
+    fn interesting_roots(
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+        labeling: &BTreeMap<Tid, NodeIndex>,
+    ) -> BTreeSet<NodeIndex> {
+        labeling
+            .values()
+            .cloned()
+            .chain(
+                sg.get_graph()
+                    .get_node_mapping()
+                    .iter()
+                    .filter(|(dtv, _)| {
+                        dtv.get_field_labels().is_empty() && dtv.get_base_variable().is_global()
+                    })
+                    .map(|(_, idx)| *idx),
+            )
+            .collect()
+    }
+
+    fn program_points_for(&self, graph: &Graph) -> anyhow::Result<ProgramPoints> {
+        let points_to_context = self.points_to_context(graph)?;
+        Ok(ProgramPoints::new(
+            graph,
+            &register_map::run_analysis(&self.proj, graph),
+            &points_to_context,
+            self.offset_clustering,
+        ))
+    }
+
+    /// Collects the program points a [TypeQuery] maps back to sketch nodes. The points only depend on the program, so
+    /// they can be reused across solves.
+    pub fn get_program_points(&self) -> anyhow::Result<ProgramPoints> {
+        let graph = self.source.control_flow_graph(&self.proj);
+        self.program_points_for(&graph)
+    }
+
+    /// Creates a [TypeQuery] answering which lowered types represent a program point. Only the points set with
+    /// [InferenceJob::set_query_points] before solving, and the interesting terms, are guaranteed to be represented.
+    pub fn type_query<'a>(
+        &self,
+        points: &'a ProgramPoints,
+        sg: &'a SketchGraph<LatticeBounds<CustomLatticeElement>>,
+        node_to_type: &'a BTreeMap<NodeIndex, TypeId>,
+    ) -> TypeQuery<'a, LatticeBounds<CustomLatticeElement>> {
+        TypeQuery::new(points, sg, node_to_type)
+    }
+
+    /// Maps each interesting subprocedure to the types of its local variables keyed by their offset from the stack
+    /// pointer on entry. A slot typed by several types takes the smallest type id and is reported as a
+    /// `frame_slot_conflict` diagnostic. The mapping is empty unless local variable types are enabled.
+    pub fn get_stack_frame_types(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+        node_to_type: &BTreeMap<NodeIndex, TypeId>,
+    ) -> anyhow::Result<BTreeMap<Tid, BTreeMap<i64, TypeId>>> {
+        if !self.local_variable_types {
+            return Ok(BTreeMap::new());
+        }
+
+        let points = self.get_program_points()?;
+        let query = self.type_query(&points, sg, node_to_type);
+        Ok(self
+            .interesting_tids
+            .iter()
+            .filter_map(|sub| {
+                let slots = query
+                    .stack_frame_types(sub)
+                    .into_iter()
+                    .filter_map(|(offset, tys)| {
+                        if tys.len() > 1 {
+                            diagnostics::report_at(
+                                DiagnosticKind::FrameSlotConflict,
+                                Some(sub),
+                                format!(
+                                    "stack slot at {} has types {}",
+                                    offset,
+                                    tys.iter()
+                                        .map(|ty| ty.to_string())
+                                        .collect::<Vec<_>>()
+                                        .join(", ")
+                                ),
+                            );
+                        }
+                        Some((offset, tys.into_iter().next()?))
+                    })
+                    .collect::<BTreeMap<_, _>>();
+                (!slots.is_empty()).then(|| (sub.clone(), slots))
+            })
+            .collect())
+    }
+
+    /// Gets the local variables of each interesting subprocedure that are lowered to its `frame_<sub>` structure, see
+    /// [add_frame_structures](crate::lowering::locals::add_frame_structures). Slots at or above the stack pointer on entry
+    /// hold the return address and the arguments of the caller, slots overlapping a saved register are dropped when the frame
+    /// layout is known from unwind information, and the slot the entry block stores the stack protector guard to is the
+    /// canary, see [StackGuard]. A slot typed by several fields takes the widest of them and is reported as a
+    /// `frame_slot_conflict` diagnostic. The slots are empty unless local variable types are enabled.
+    pub fn get_stack_frame_slots(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+        node_to_type: &BTreeMap<NodeIndex, TypeId>,
+    ) -> anyhow::Result<BTreeMap<Tid, Vec<FrameSlot>>> {
+        if !self.local_variable_types {
+            return Ok(BTreeMap::new());
+        }
+
+        let points = self.get_program_points()?;
+        let query = self.type_query(&points, sg, node_to_type);
+        let layouts = self.get_frame_layouts();
+        let guard = StackGuard::new(&self.proj.cpu_architecture, &self.binary_bytes);
+        let word_size = (self.proj.stack_pointer_register.size.as_bit_length() / 8) as u64;
+        Ok(self
+            .interesting_subs()
+            .filter_map(|sub| {
+                let layout = layouts.get(&sub.tid);
+                let canary = guard.canary_offset(sub, &self.proj.stack_pointer_register);
+                let slots = query
+                    .stack_frame_fields(&sub.tid)
+                    .into_iter()
+                    .filter(|(offset, _)| *offset < 0)
+                    .filter_map(|(offset, fields)| {
+                        if fields.len() > 1 {
+                            diagnostics::report_at(
+                                DiagnosticKind::FrameSlotConflict,
+                                Some(&sub.tid),
+                                format!(
+                                    "stack slot at {} has fields {}",
+                                    offset,
+                                    fields
+                                        .iter()
+                                        .map(|(ty, bit_sz)| format!("{} of {} bits", ty, bit_sz))
+                                        .collect::<Vec<_>>()
+                                        .join(", ")
+                                ),
+                            );
+                        }
+                        let (type_id, bit_sz) = fields
+                            .into_iter()
+                            .max_by_key(|(ty, bit_sz)| (*bit_sz, std::cmp::Reverse(*ty)))?;
+                        Some(FrameSlot {
+                            offset,
+                            bit_sz,
+                            type_id,
+                        })
+                    })
+                    .filter(|slot| {
+                        !layout
+                            .map(|layout| {
+                                layout.is_save_slot(
+                                    layout.cfa_offset(slot.offset),
+                                    (slot.bit_sz as u64 + 7) / 8,
+                                    word_size,
+                                )
+                            })
+                            .unwrap_or(false)
+                    })
+                    .filter(|slot| {
+                        !canary.map_or(false, |canary| {
+                            slot.offset < canary + word_size as i64
+                                && canary < slot.offset + (slot.bit_sz as i64 + 7) / 8
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                (!slots.is_empty()).then(|| (sub.tid.clone(), slots))
+            })
+            .collect())
+    }
+
+    /// Types the registers at the key program points of each interesting subprocedure, see [key_program_points]. A
+    /// register with several reaching definitions of different types has a binding for each type. The bindings are empty
+    /// unless register types are enabled.
+    pub fn get_register_bindings(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+        node_to_type: &BTreeMap<NodeIndex, TypeId>,
+    ) -> anyhow::Result<Vec<RegisterBinding>> {
+        if !self.register_types {
+            return Ok(Vec::new());
+        }
+
+        let points = self.get_program_points()?;
+        let query = self.type_query(&points, sg, node_to_type);
+        Ok(register_bindings(
+            self.interesting_subs().flat_map(|sub| {
+                key_program_points(&self.proj.program.term, &self.proj.cpu_architecture, sub)
+            }),
+            |point| query.type_of(point),
+        ))
+    }
+
+    fn interesting_subs(&self) -> impl Iterator<Item = &Term<Sub>> + '_ {
+        self.interesting_tids
+            .iter()
+            .filter_map(move |tid| self.proj.program.term.subs.get(tid))
+    }
+
+    /// Finds the nodes of the sketch graph whose lattice bounds clash or that are both primitives and pointers. Lowering
+    /// resolves them with the conflict strategy of the lowering options.
+    pub fn get_lattice_conflicts(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> anyhow::Result<Vec<LatticeConflict>> {
+        let conflicts = find_lattice_conflicts(sg).into_values().collect::<Vec<_>>();
+        self.debug_dir
+            .phase("lowering")
+            .log_to_fname("lattice_conflicts", &|| {
+                serde_json::to_string_pretty(&conflicts).unwrap_or_default()
+            })?;
+        Ok(conflicts)
+    }
+
+    /// Finds the leaves of the sketch graph whose primitive has a different size than the accesses to them. Lowering
+    /// types these leaves by the primitive of the access size with the same signedness or floating point kind, and each
+    /// mismatch is reported at the nearest term that reaches it and logged along with the accesses it was observed on.
+    pub fn get_size_mismatches(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> anyhow::Result<Vec<SizeMismatch>> {
+        let mismatches = find_size_mismatches(
+            sg,
+            &self.lowering_options.primitive_table,
+            &self.get_graph_labeling(sg),
         )
-        .collect_ctypes()
+        .into_values()
+        .collect::<Vec<_>>();
+        mismatches.iter().for_each(|mismatch| {
+            diagnostics::report_at(
+                DiagnosticKind::SizeMismatch,
+                mismatch.origin.as_ref(),
+                mismatch.to_string(),
+            );
+            log::warn!("Primitive size mismatch: {}", mismatch)
+        });
+        self.debug_dir
+            .phase("lowering")
+            .log_to_fname("size_mismatches", &|| {
+                serde_json::to_string_pretty(&mismatches).unwrap_or_default()
+            })?;
+        Ok(mismatches)
+    }
+
+    /// Finds the interesting functions whose sketch has evidence for more parameters than the formal arguments declared for
+    /// them in the IR, reporting them and logging them to `lowering/parameter_count_mismatches` in the debug directory.
+    /// Lowering extends the signatures of these functions to the observed parameters.
+    pub fn get_parameter_count_mismatches(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> anyhow::Result<BTreeMap<NodeIndex, ParameterCountMismatch>> {
+        let term = &self.proj.program.term;
+        let mut node_to_tid = BTreeMap::new();
+        let mut declared = BTreeMap::new();
+        for (tid, nd) in self.get_graph_labeling(sg) {
+            let count = term
+                .subs
+                .get(&tid)
+                .map(|sub| sub.term.formal_args.len())
+                .or_else(|| {
+                    term.extern_symbols
+                        .get(&tid)
+                        .map(|ext| ext.parameters.len())
+                });
+            if let Some(count) = count {
+                declared.insert(nd, count);
+                node_to_tid.entry(nd).or_insert(tid);
+            }
+        }
+
+        let mismatches = find_parameter_count_mismatches(sg, &declared);
+        mismatches.values().for_each(|mismatch| {
+            diagnostics::report_at(
+                DiagnosticKind::ParameterCountMismatch,
+                node_to_tid.get(&mismatch.node),
+                mismatch.to_string(),
+            );
+            log::warn!("Parameter count mismatch: {}", mismatch)
+        });
+        self.debug_dir
+            .phase("lowering")
+            .log_to_fname("parameter_count_mismatches", &|| {
+                serde_json::to_string_pretty(&mismatches.values().collect::<Vec<_>>())
+                    .unwrap_or_default()
+            })?;
+        Ok(mismatches)
+    }
+
+    /// Reads the nullability of parameters and returns from the nullability constraints solved into the sketch.
+    pub fn get_nullability(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> BTreeMap<NodeIndex, Nullability> {
+        nullability::nullability_of_nodes(sg)
+    }
+
+    /// Infers array extents from loops indexing objects and maps the array layouts to the nodes representing the indexed objects.
+    pub fn get_array_extents(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> anyhow::Result<BTreeMap<NodeIndex, Vec<ArrayLayout>>> {
+        let graph = self.source.control_flow_graph(&self.proj);
+        let points_to_context = self.points_to_context(&graph)?;
+        let extents = array_extents::find_array_extents(&self.proj, &graph, &points_to_context);
+        self.debug_dir
+            .phase("lowering")
+            .log_to_fname("array_extents", &|| {
+                serde_json::to_string_pretty(&extents).unwrap_or_default()
+            })?;
+
+        let mut by_node: BTreeMap<NodeIndex, Vec<ArrayLayout>> = BTreeMap::new();
+        for extent in extents.into_iter() {
+            if let Some(nd) = sg.get_node_index_for_variable(&DerivedTypeVar::new(extent.object)) {
+                by_node.entry(nd).or_default().push(extent.layout);
+            }
+        }
+
+        Ok(by_node)
+    }
+
+    /// Classifies the buffers passed to wide string functions, indexed with a 2 byte stride, or stored in read only
+    /// memory, and maps the character elements of these buffers to their character type.
+    pub fn get_character_types(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> anyhow::Result<BTreeMap<NodeIndex, CharacterClass>> {
+        let mut buffers = character_types::literal_buffers(&self.proj, sg);
+        let classified = character_types::strided_buffers(&self.get_array_extents(sg)?)
+            .into_iter()
+            .chain(character_types::wide_string_buffers(&self.proj, sg));
+        for (nd, class) in classified {
+            let prev = buffers.entry(nd).or_insert(class);
+            *prev = (*prev).min(class);
+        }
+
+        let elements = character_types::character_elements(sg, &buffers);
+        self.debug_dir
+            .phase("lowering")
+            .log_to_fname("character_types", &|| {
+                serde_json::to_string_pretty(
+                    &elements
+                        .iter()
+                        .map(|(nd, class)| (nd.index(), *class))
+                        .collect::<BTreeMap<_, _>>(),
+                )
+                .unwrap_or_default()
+            })?;
+        Ok(elements)
+    }
+
+    /// Counts the loads and stores accessing each field of the abstract objects of the program and maps the counts to the
+    /// nodes representing the objects. Objects sharing a node add up their counts.
+    pub fn get_field_access_counts(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> anyhow::Result<BTreeMap<NodeIndex, BTreeMap<Field, usize>>> {
+        let graph = self.source.control_flow_graph(&self.proj);
+        let points_to_context = self.points_to_context(&graph)?;
+        let counts = field_accesses::count_field_accesses(&graph, &points_to_context);
+        self.debug_dir
+            .phase("lowering")
+            .log_to_fname("field_access_counts", &|| {
+                serde_json::to_string_pretty(&counts).unwrap_or_default()
+            })?;
+
+        let mut by_node: BTreeMap<NodeIndex, BTreeMap<Field, usize>> = BTreeMap::new();
+        for count in counts.into_iter() {
+            if let Some(nd) = sg.get_node_index_for_variable(&DerivedTypeVar::new(count.object)) {
+                *by_node
+                    .entry(nd)
+                    .or_default()
+                    .entry(count.field)
+                    .or_default() += count.count;
+            }
+        }
+
+        Ok(by_node)
+    }
+
+    /// Collects the loads and stores accessing each field of the abstract objects of the program and maps them to the
+    /// nodes representing the objects, merging the accesses of objects sharing a node.
+    pub fn get_field_xrefs(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> anyhow::Result<BTreeMap<NodeIndex, BTreeMap<Field, BTreeSet<Tid>>>> {
+        let graph = self.source.control_flow_graph(&self.proj);
+        let points_to_context = self.points_to_context(&graph)?;
+        let accesses = field_accesses::collect_field_accesses(&graph, &points_to_context);
+        self.debug_dir
+            .phase("lowering")
+            .log_to_fname("field_xrefs", &|| {
+                serde_json::to_string_pretty(&accesses).unwrap_or_default()
+            })?;
+
+        let mut by_node: BTreeMap<NodeIndex, BTreeMap<Field, BTreeSet<Tid>>> = BTreeMap::new();
+        for acc in accesses.into_iter() {
+            if let Some(nd) = sg.get_node_index_for_variable(&DerivedTypeVar::new(acc.object)) {
+                by_node
+                    .entry(nd)
+                    .or_default()
+                    .entry(acc.field)
+                    .or_default()
+                    .extend(acc.defs);
+            }
+        }
+
+        Ok(by_node)
+    }
+
+    /// Gets the abstract objects the enabled lowering hints are keyed by, ie. the objects checked against a
+    /// discriminant, the objects indexed as arrays, and the objects whose field accesses are counted. Abstract objects
+    /// are not interesting by themselves, so they are kept while simplifying constraints in order to be represented in
+    /// the sketch graph.
+    fn get_lowering_objects(&self, graph: &Graph) -> anyhow::Result<BTreeSet<TypeVariable>> {
+        let options = &self.lowering_options;
+        // strided character buffers are found from the array extents
+        let needs_extents = options.infer_array_extents || options.infer_character_types;
+        if !options.detect_tagged_unions && !needs_extents && !options.count_field_accesses {
+            return Ok(BTreeSet::new());
+        }
+
+        let points_to_context = self.points_to_context(graph)?;
+        let mut objects = BTreeSet::new();
+        if options.detect_tagged_unions {
+            objects.extend(
+                discriminants::find_tagged_unions(graph, &points_to_context)
+                    .into_iter()
+                    .map(|hint| hint.object),
+            );
+        }
+        if needs_extents {
+            objects.extend(
+                array_extents::find_array_extents(&self.proj, graph, &points_to_context)
+                    .into_iter()
+                    .map(|extent| extent.object),
+            );
+        }
+        if options.count_field_accesses {
+            objects.extend(
+                field_accesses::count_field_accesses(graph, &points_to_context)
+                    .into_iter()
+                    .map(|count| count.object),
+            );
+        }
+        Ok(objects)
+    }
+
+    /// Detects tagged unions from discriminant checks in the program and maps their layouts to the nodes
+    /// representing the checked objects.
+    pub fn get_tagged_union_layouts(
+        &self,
+        sg: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> anyhow::Result<BTreeMap<NodeIndex, TaggedUnionLayout>> {
+        let graph = self.source.control_flow_graph(&self.proj);
+        let points_to_context = self.points_to_context(&graph)?;
+        let hints = discriminants::find_tagged_unions(&graph, &points_to_context);
+        self.debug_dir
+            .phase("lowering")
+            .log_to_fname("tagged_unions", &|| {
+                serde_json::to_string_pretty(&hints).unwrap_or_default()
+            })?;
+
+        Ok(hints
+            .into_iter()
+            .filter_map(|hint| {
+                sg.get_node_index_for_variable(&DerivedTypeVar::new(hint.object))
+                    .map(|nd| (nd, hint.layout))
+            })
+            .collect())
+    }
+
+    /// Gets the subprocedures whose constraints were resimplified by the last solve.
+    /// After changing assumptions, only these subprocedures and their callers are resimplified.
+    pub fn get_last_resimplified_tids(&self) -> &BTreeSet<Tid> {
+        self.simplification_cache.get_last_resimplified()
+    }
+
+    /// Gets the subprocedures whose sketches were rebuilt by the last solve. The sketches of the other subprocedures are
+    /// reused from the previous solve.
+    pub fn get_last_rebuilt_sketch_tids(&self) -> &BTreeSet<Tid> {
+        self.sketch_cache.get_last_rebuilt()
+    }
+
+    /// Gets the sketch nodes that may have changed in the last solve, these are the nodes reachable from
+    /// a subprocedure that was resimplified.
+    pub fn get_affected_nodes(
+        &self,
+        grph: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) -> BTreeSet<NodeIndex> {
+        self.get_last_resimplified_tids()
+            .iter()
+            .filter_map(|tid| {
+                grph.get_node_index_for_variable(&crate::constraints::DerivedTypeVar::new(
+                    crate::constraint_generation::tid_to_tvar(tid),
+                ))
+            })
+            .flat_map(|idx| grph.get_graph().get_reachable_idxs(idx))
+            .collect()
     }
 
     /// Infer the universal type graph, joining all sketches together.
+    /// Inferring again after changing assumptions reuses the simplified constraints of unaffected sccs.
     pub fn infer_labeled_graph(
         &mut self,
         // debug_dir: &PathBuf,
     ) -> anyhow::Result<SketchGraph<LatticeBounds<CustomLatticeElement>>> {
-        if self.should_use_aggressive_shared_returns {
-            self.recover_additional_shared_returns();
+        self.recover_project_facts();
+        self.points_to.replace(None);
+        // Saved state is only used by the first solve after resuming, later solves may have different assumptions.
+        let resuming = std::mem::take(&mut self.resuming);
+        let resumed_checkpoint = self.checkpoint.as_ref().filter(|_| resuming);
+        if let Some(saved) = resumed_checkpoint
+            .map(|checkpoint| checkpoint.load_sketch_graph(&self.lattice))
+            .transpose()?
+            .flatten()
+        {
+            log::info!("Resumed labeled sketch graph from checkpoint");
+            return Ok(saved);
         }
-        let cons = self.get_simplified_constraints()?;
+
+        let cons = match resumed_checkpoint
+            .map(|checkpoint| checkpoint.load_simplified_constraints())
+            .transpose()?
+            .flatten()
+        {
+            Some(saved) => {
+                log::info!("Resumed simplified constraints from checkpoint");
+                saved
+            }
+            None => {
+                let cons = self.get_simplified_constraints()?;
+                if let Some(checkpoint) = &self.checkpoint {
+                    checkpoint.save_simplified_constraints(&cons)?;
+                }
+                cons
+            }
+        };
 
         // Insert additional constraints, additional constraints are now mapped to a tid, and inserted into the scc that has that tid.
 
         let labeled_graph = self.get_labeled_sketch_graph(cons)?;
+        if let Some(checkpoint) = &self.checkpoint {
+            checkpoint.save_sketch_graph(&labeled_graph)?;
+        }
         Ok(labeled_graph)
     }
 
+    /// Runs the enabled whole program recoveries that rewrite the project, once per job.
+    fn recover_project_facts(&mut self) {
+        // Later recoveries match subprocedures by name, so Go names are recovered first
+        if self.should_recover_go_metadata && !self.recovered_go_metadata {
+            self.recover_go_metadata();
+            self.recovered_go_metadata = true;
+        }
+        if !self.normalized_interworking_calls {
+            let retargeted = arm32::normalize_interworking_calls(&mut self.proj);
+            if retargeted > 0 {
+                log::info!(
+                    "Retargeted {} calls to Thumb interworking addresses",
+                    retargeted
+                );
+            }
+            self.normalized_interworking_calls = true;
+        }
+        if !self.normalized_mips_code {
+            let reordered = mips::order_delay_slots(&mut self.proj);
+            let proj = &mut self.proj;
+            let resolved = ElfFile::parse(&self.binary_bytes)
+                .map(|elf| mips::resolve_gp_relative(proj, &elf))
+                .unwrap_or(0);
+            if reordered > 0 || resolved > 0 {
+                log::info!(
+                    "Reordered {} delay slots and resolved {} gp relative accesses",
+                    reordered,
+                    resolved
+                );
+            }
+            self.normalized_mips_code = true;
+        }
+        if !self.modeled_riscv_calling_convention {
+            let modeled = riscv::model_calling_convention(&mut self.proj);
+            if modeled > 0 {
+                log::info!(
+                    "Modeled the RISC-V calling convention of {} subprocedures",
+                    modeled
+                );
+            }
+            self.modeled_riscv_calling_convention = true;
+        }
+        if !self.normalized_ppc_code {
+            let proj = &mut self.proj;
+            let resolved = ElfFile::parse(&self.binary_bytes)
+                .map(|elf| ppc::resolve_toc_relative(proj, &elf))
+                .unwrap_or(0);
+            let modeled = ppc::model_calling_convention(&mut self.proj);
+            if resolved > 0 || modeled > 0 {
+                log::info!(
+                    "Resolved {} TOC relative accesses and modeled the PowerPC calling convention of {} subprocedures",
+                    resolved,
+                    modeled
+                );
+            }
+            self.normalized_ppc_code = true;
+        }
+        // Thunks are collapsed before wrappers are detected so that wrappers calling through a PLT stub are found
+        if self.should_collapse_thunks && !self.collapsed_thunks {
+            let slots = relocations::import_slots(&self.binary_bytes);
+            let retargeted = thunks::collapse_thunks(&mut self.proj, &slots);
+            log::info!("Retargeted {} calls to thunks", retargeted);
+            self.collapsed_thunks = true;
+        }
+        if self.should_apply_rust_heuristics && !self.recovered_rust_symbols {
+            let demangled = rust_binaries::demangle_symbols(&mut self.proj);
+            log::info!("Demangled {} Rust symbols", demangled);
+            // Rust links its allocator shims statically, stubbing them lets the points-to analysis treat their calls
+            // as allocations.
+            allocators::stub_allocator_wrappers(
+                &mut self.proj.program.term,
+                &rust_binaries::rust_allocators(),
+            );
+            self.recovered_rust_symbols = true;
+        }
+        if let (Some(profile), false) = (&self.freestanding, self.recovered_sdk_allocators) {
+            // Firmware allocators are subprocedures rather than extern symbols, stubbing them lets the points-to
+            // analysis treat their calls as allocations.
+            allocators::stub_allocator_wrappers(&mut self.proj.program.term, &profile.allocators);
+            self.recovered_sdk_allocators = true;
+        }
+        if self.should_use_aggressive_shared_returns && !self.recovered_shared_returns {
+            self.recover_additional_shared_returns();
+            self.recovered_shared_returns = true;
+        }
+        // Runs after shared returns are recovered so that recovered returns nobody reads are removed as well
+        if let (Some(threshold), false) =
+            (self.return_usage_threshold, self.suppressed_unused_returns)
+        {
+            let suppressed = return_usage::suppress_unused_returns(&mut self.proj, threshold);
+            log::info!(
+                "Suppressed the returns of {} subprocedures read by fewer than {} callers",
+                suppressed.len(),
+                threshold
+            );
+            self.suppressed_unused_returns = true;
+        }
+        if self.should_detect_allocator_wrappers && !self.recovered_allocator_wrappers {
+            self.recover_allocator_wrappers();
+            self.recovered_allocator_wrappers = true;
+        }
+        if self.should_type_syscalls && !self.recovered_syscalls {
+            let stubbed = syscalls::stub_syscalls(&mut self.proj);
+            log::info!("Stubbed {} syscalls", stubbed);
+            self.recovered_syscalls = true;
+        }
+        // Pruning runs last so that the rewrites above see every def
+        if self.should_prune_dead_defs && !self.pruned_dead_defs {
+            let kept_blocks = self
+                .query_points
+                .iter()
+                .filter_map(|point| match point {
+                    QueryPoint::Register { block, .. } => Some(block.clone()),
+                    QueryPoint::StackSlot { .. } => None,
+                })
+                .collect();
+            let pruned = dead_defs::prune_dead_defs(&mut self.proj, &kept_blocks);
+            log::info!("Pruned {} dead defs", pruned);
+            self.pruned_dead_defs = true;
+        }
+    }
+
     /// Applies all default analyses to compute types. First, tailcall returns are fixed, then simplified scc constraints are generated.
     /// These constraints are transformed into a sketch supergraph where each type variable is represented by a node with edges for its capabilities.
     /// These nodes are then lowered to a mapping from node to ctype.
@@ -508,21 +2433,94 @@ impl InferenceJob {
         Ok((labeled_graph, lowered))
     }
 
+    /// Infers types for a single subprocedure without solving the whole program. Only the slice of the project made of
+    /// the target, its direct callees and its callers up to caller_depth calls away keeps its bodies, see [FunctionSlice].
+    /// Types of the target are therefore less precise than a full solve when actuals flow from further callers.
+    /// The simplification cache and checkpoint of the job are neither used nor updated by the slice.
+    pub fn infer_function(
+        &mut self,
+        target: &Tid,
+        caller_depth: usize,
+    ) -> anyhow::Result<(UserDefinedSketches, LoweredTypeMap)> {
+        // Recoveries look across the whole program so they run before slicing.
+        self.recover_project_facts();
+
+        let cg = callgraph::CGContext::new(&self.proj).get_graph();
+        let slice = FunctionSlice::from_callgraph(&cg, target, caller_depth)?;
+        log::info!(
+            "Inferring {} from a slice of {} subprocedures",
+            target,
+            slice.subprocedures().len()
+        );
+        self.debug_dir
+            .phase("slice")
+            .log_to_fname("function_slice", &|| {
+                serde_json::to_string_pretty(&slice).unwrap_or_default()
+            })?;
+
+        let sliced = slice.slice_project(&self.proj);
+        let full_proj = std::mem::replace(&mut self.proj, sliced);
+        let cache = std::mem::take(&mut self.simplification_cache);
+        let sketch_cache = std::mem::take(&mut self.sketch_cache);
+        let checkpoint = self.checkpoint.take();
+        let resuming = std::mem::take(&mut self.resuming);
+        let added_target = self.interesting_tids.insert(target.clone());
+
+        let res = self.infer_ctypes();
+
+        self.proj = full_proj;
+        self.points_to.replace(None);
+        self.simplification_cache = cache;
+        self.sketch_cache = sketch_cache;
+        self.checkpoint = checkpoint;
+        self.resuming = resuming;
+        if added_target {
+            self.interesting_tids.remove(target);
+        }
+        res
+    }
+
     /// Gets the set of interesting terms that are solved for.
     pub fn get_interesting_tids(&self) -> &HashSet<Tid> {
         &self.interesting_tids
     }
 
     /// Parses a job definition to an [InferenceJob] using a marker type that impelments the parsing.
+    /// The binary and IR are loaded from the Pcode json named by the definition.
     pub fn parse<T: InferenceParsing<AdditionalConstraint> + InferenceParsing<Tid>>(
         def: &JobDefinition,
         debug_dir: Option<String>,
         additional_lattices: Vec<LatticeDefinition>,
         should_use_aggressive_shared_returns: bool,
     ) -> anyhow::Result<InferenceJob> {
-        let bin = Self::parse_binary(&def.binary_path).with_context(|| "Trying to parse binary")?;
-        let proj = Self::parse_project(&def.ir_json_path, &bin)
+        let source = PcodeJsonSource::from_definition(def)?;
+        Self::parse_with_source::<T>(
+            Box::new(source),
+            def,
+            debug_dir,
+            additional_lattices,
+            should_use_aggressive_shared_returns,
+        )
+    }
+
+    /// Parses a job definition to an [InferenceJob], loading the binary and IR from the given frontend rather than
+    /// the paths in the definition.
+    pub fn parse_with_source<T: InferenceParsing<AdditionalConstraint> + InferenceParsing<Tid>>(
+        source: Box<dyn ProjectSource>,
+        def: &JobDefinition,
+        debug_dir: Option<String>,
+        additional_lattices: Vec<LatticeDefinition>,
+        should_use_aggressive_shared_returns: bool,
+    ) -> anyhow::Result<InferenceJob> {
+        let bin = source
+            .binary_bytes()
+            .with_context(|| "Trying to parse binary")?;
+        let mut proj = source
+            .project(&bin)
             .with_context(|| "Trying to parse project")?;
+        proj.runtime_memory_image = source
+            .runtime_memory_image(&proj, &bin)
+            .with_context(|| "Trying to load the runtime memory image")?;
         let (lat, weakest_integral_type) =
             Self::parse_lattice_json(&def.lattice_json, additional_lattices)
                 .with_context(|| "Trying to parse lattice")?;
@@ -531,8 +2529,10 @@ impl InferenceJob {
                 .with_context(|| "Trying to parse additional constraints")?;
         let interesting_tids = Self::parse_tid_set::<T>(&def.interesting_tids)
             .with_context(|| "Trying to parse interesting tids")?;
+        let data_model = Self::data_model_of(&proj, &bin);
 
         Ok(InferenceJob {
+            source,
             binary_bytes: bin,
             proj,
             lattice: lat,
@@ -542,6 +2542,338 @@ impl InferenceJob {
             vman: VariableManager::new(),
             debug_dir: FileDebugLogger::new(debug_dir),
             should_use_aggressive_shared_returns,
+            lowering_options: LoweringOptions {
+                primitive_table: PrimitiveTable::for_data_model(data_model).keeping_lattice_names(),
+                ..LoweringOptions::default()
+            },
+            assumptions: Vec::new(),
+            simplification_cache: SimplificationCache::default(),
+            sketch_cache: SketchCache::default(),
+            recovered_shared_returns: false,
+            allocators: allocators::default_allocators(),
+            deallocation_symbols: lifetimes::default_deallocation_symbols(),
+            should_pair_heap_lifetimes: false,
+            should_detect_allocator_wrappers: false,
+            recovered_allocator_wrappers: false,
+            should_constrain_allocation_sizes: false,
+            should_collapse_thunks: false,
+            collapsed_thunks: false,
+            should_prune_dead_defs: false,
+            pruned_dead_defs: false,
+            return_usage_threshold: None,
+            suppressed_unused_returns: false,
+            prefer_pointer_facts: false,
+            offset_clustering: OffsetClustering::default(),
+            checkpoint: None,
+            resuming: false,
+            simplification_passes: Rc::new(PassPipeline::default()),
+            constraint_dumps: None,
+            query_points: Vec::new(),
+            local_variable_types: false,
+            register_types: false,
+            observers: Vec::new(),
+            transformers: Vec::new(),
+            callsite_signatures: false,
+            context_depth: 0,
+            name_hints: None,
+            platform: None,
+            should_type_syscalls: false,
+            recovered_syscalls: false,
+            freestanding: None,
+            recovered_sdk_allocators: false,
+            should_recover_go_metadata: false,
+            recovered_go_metadata: false,
+            should_apply_rust_heuristics: false,
+            recovered_rust_symbols: false,
+            should_use_unwind_frames: false,
+            should_type_relocations: false,
+            should_type_windows_imports: false,
+            should_model_tls: false,
+            should_isolate_landing_pads: false,
+            should_type_pointer_integers: false,
+            normalized_interworking_calls: false,
+            normalized_mips_code: false,
+            modeled_riscv_calling_convention: false,
+            normalized_ppc_code: false,
+            memory_budget: None,
+            degradations: Vec::new(),
+            rule_traces: false,
+            entailment_checks: None,
+            entailment_failures: Vec::new(),
+            isolate_solve_failures: true,
+            solve_failures: Vec::new(),
+            points_to: RefCell::new(None),
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{cell::Cell, collections::BTreeSet, rc::Rc};
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use crate::{
+        analysis::nullability::Nullability,
+        constraints::{parse_constraint_set, ConstraintSet, FieldLabel, TyConstraint},
+        lowering::{CType, LoweringOptions},
+        solver::{
+            observer::PipelineObserver, scc_constraint_generation::SolveStage,
+            transform::ConstraintTransformer,
+        },
+        test_utils::golden::{infer_case, TAGGED_UNION},
+    };
+
+    use super::{
+        assumptions::{AssumptionTarget, TypeAssumption},
+        InferenceJob, JsonDef,
+    };
+
+    /// Fails the solve of every scc containing the subprocedure.
+    struct PanicsOn(&'static str);
+
+    impl ConstraintTransformer for PanicsOn {
+        fn name(&self) -> &str {
+            "panics_on"
+        }
+
+        fn transform(&self, scc: &[Tid], cs: ConstraintSet) -> ConstraintSet {
+            if scc.iter().any(|tid| tid.get_str_repr() == self.0) {
+                panic!("malformed constraints for {}", self.0);
+            }
+            cs
+        }
+    }
+
+    #[test]
+    fn discriminated_objects_are_lowered_to_tagged_unions() {
+        let (_, (_, _, types)) = infer_case::<JsonDef>(&TAGGED_UNION, |job| {
+            job.set_lowering_options(LoweringOptions {
+                detect_tagged_unions: true,
+                ..LoweringOptions::default()
+            })
+        });
+
+        // area checks the kind at offset 0 before reading the radius or the width and height at offset 8
+        let tagged = types.values().find_map(|ty| match ty {
+            CType::Structure(fields) => fields.iter().find(|fld| {
+                fld.byte_offset() == 8
+                    && matches!(types.get(&fld.type_index()), Some(CType::Union(_)))
+            }),
+            _ => None,
+        });
+        let union_field = tagged.expect("the shape should be lowered to a tagged union");
+        assert_eq!(union_field.bit_size(), 128);
+    }
+
+    #[test]
+    fn nullability_is_only_added_as_capability_constraints_when_enabled() {
+        let (mut job, _) = infer_case::<JsonDef>(&TAGGED_UNION, |_| ());
+        let flattened = |job: &InferenceJob| {
+            job.get_effective_additional_constraints()
+                .expect("should collect the additional constraints")
+                .into_iter()
+                .flat_map(|(_, cons)| cons.0.into_iter())
+                .collect::<BTreeSet<_>>()
+        };
+
+        let without = flattened(&job);
+        job.set_lowering_options(LoweringOptions {
+            infer_nullability: true,
+            ..LoweringOptions::default()
+        });
+        let with = flattened(&job);
+
+        assert!(without.is_subset(&with));
+        for cons in with.difference(&without) {
+            let sub = match cons {
+                TyConstraint::SubTy(sub) => sub,
+                TyConstraint::AddCons(_) => panic!("unexpected addition constraint"),
+            };
+            // the top element of the list test lattice
+            assert_eq!(sub.rhs.get_base_variable().get_name(), "T");
+            let labels = sub.lhs.get_field_labels();
+            assert!(matches!(
+                labels,
+                [FieldLabel::In(_), FieldLabel::Custom(lbl)] | [FieldLabel::Out(_), FieldLabel::Custom(lbl)]
+                    if Nullability::from_label(lbl).is_some()
+            ));
+        }
+    }
+
+    /// Adds a constraint on the first parameter of a subprocedure while enabled, as an observer or a transformer.
+    struct InjectsInto {
+        sub: &'static str,
+        enabled: Cell<bool>,
+    }
+
+    impl InjectsInto {
+        fn injected(&self, scc: &[Tid]) -> ConstraintSet {
+            if !self.enabled.get() || scc.iter().all(|tid| tid.get_str_repr() != self.sub) {
+                return ConstraintSet::default();
+            }
+            parse_constraint_set(&format!("{}.in_0 <= injected", self.sub))
+                .expect("should parse the injected constraint")
+                .1
+        }
+    }
+
+    impl PipelineObserver for InjectsInto {
+        fn on_constraints_generated(&self, scc: &[Tid], _cs: &ConstraintSet) -> ConstraintSet {
+            self.injected(scc)
+        }
+    }
+
+    impl ConstraintTransformer for InjectsInto {
+        fn name(&self) -> &str {
+            "injects_into"
+        }
+
+        fn transform(&self, scc: &[Tid], mut cs: ConstraintSet) -> ConstraintSet {
+            cs.insert_all(&self.injected(scc));
+            cs
+        }
+    }
+
+    // Checks that a cached solve only resimplifies the scc the injected constraint is added to once it is enabled.
+    fn check_resimplified_when_injecting(register: fn(&mut InferenceJob, Rc<InjectsInto>)) {
+        let injector = Rc::new(InjectsInto {
+            sub: "sub_00401126",
+            enabled: Cell::new(false),
+        });
+        let (mut job, _) =
+            infer_case::<JsonDef>(&TAGGED_UNION, |job| register(job, injector.clone()));
+        let resimplified = |job: &mut InferenceJob| {
+            job.infer_labeled_graph().expect("should solve again");
+            job.get_last_resimplified_tids()
+                .iter()
+                .map(|tid| tid.get_str_repr().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(resimplified(&mut job).is_empty());
+        injector.enabled.set(true);
+        assert!(resimplified(&mut job).contains(&"sub_00401126".to_owned()));
+        assert!(resimplified(&mut job).is_empty());
+    }
+
+    #[test]
+    fn sccs_are_resimplified_when_observers_inject_other_constraints() {
+        check_resimplified_when_injecting(|job, injector| job.add_observer(injector));
+    }
+
+    #[test]
+    fn sccs_are_resimplified_when_transformers_rewrite_them_differently() {
+        check_resimplified_when_injecting(|job, injector| job.add_transformer(injector));
+    }
+
+    #[test]
+    fn only_sccs_affected_by_an_assumption_are_resolved() {
+        let (mut job, (grph, _, _)) = infer_case::<JsonDef>(&TAGGED_UNION, |_| ());
+        let solve = |job: &mut InferenceJob| {
+            let grph = job.infer_labeled_graph().expect("should solve again");
+            let names = |tids: &BTreeSet<Tid>| {
+                tids.iter()
+                    .map(|tid| tid.get_str_repr().to_owned())
+                    .collect::<BTreeSet<_>>()
+            };
+            (
+                serde_json::to_value(grph.to_checkpoint()).unwrap(),
+                names(job.get_last_resimplified_tids()),
+                names(job.get_last_rebuilt_sketch_tids()),
+            )
+        };
+
+        // nothing changed so every scc and sketch is reused
+        let (unchanged, resimplified, rebuilt) = solve(&mut job);
+        assert_eq!(
+            unchanged,
+            serde_json::to_value(grph.to_checkpoint()).unwrap()
+        );
+        assert!(resimplified.is_empty());
+        assert!(rebuilt.is_empty());
+
+        // pinning the return of area resimplifies area, and rebuilds the sketch of area and of main which calls it
+        let assumption = TypeAssumption {
+            target: AssumptionTarget::Return {
+                subprocedure: Tid::create("sub_00401126".to_owned(), "00401126".to_owned()),
+                index: 0,
+            },
+            ty: "weak_integer".to_owned(),
+            pointee_bit_size: None,
+        };
+        job.add_assumptions(&[assumption.clone()])
+            .expect("should accept the assumption");
+        let (_, resimplified, rebuilt) = solve(&mut job);
+        assert!(resimplified.contains("sub_00401126"));
+        assert!(rebuilt.contains("sub_00401126"));
+        assert!(rebuilt.contains("sub_0040113e"));
+
+        // removing the assumption invalidates the cached results again and gives back the original graph
+        assert!(job.remove_assumption(&assumption));
+        let (restored, resimplified, rebuilt) = solve(&mut job);
+        assert!(resimplified.contains("sub_00401126"));
+        assert!(rebuilt.contains("sub_00401126"));
+        assert_eq!(restored, unchanged);
+    }
+
+    #[test]
+    fn resumed_checkpoints_give_the_same_types() {
+        let dir = tempdir::TempDir::new("resume").expect("should create a temporary directory");
+        let checkpoint_dir = dir.path().to_string_lossy().into_owned();
+        let infer = |resume: bool| {
+            let (_, (grph, node_to_type, types)) = infer_case::<JsonDef>(&TAGGED_UNION, |job| {
+                job.set_checkpoint_dir(&checkpoint_dir, resume)
+                    .expect("should open the checkpoint")
+            });
+            (
+                serde_json::to_value(grph.to_checkpoint()).unwrap(),
+                node_to_type.len(),
+                types.len(),
+            )
+        };
+
+        let original = infer(false);
+        assert!(dir.path().join("simplified_constraints.json").exists());
+        assert!(dir.path().join("sketch_graph.json").exists());
+        assert_eq!(infer(true), original);
+
+        // an analysis interrupted after simplifying resumes from the simplified constraints
+        std::fs::remove_file(dir.path().join("sketch_graph.json")).unwrap();
+        assert_eq!(infer(true), original);
+        assert!(dir.path().join("sketch_graph.json").exists());
+    }
+
+    #[test]
+    fn failed_sccs_do_not_fail_the_rest_of_the_solve() {
+        // area is sub_00401126 and main sub_0040113e
+        let (job, (grph, node_to_type, _)) = infer_case::<JsonDef>(&TAGGED_UNION, |job| {
+            job.add_transformer(Rc::new(PanicsOn("sub_00401126")))
+        });
+
+        let failures = job.get_solve_failures();
+        assert!(!failures.is_empty());
+        // both the signature and the constraints of area fail, nothing else does
+        assert_eq!(
+            failures
+                .iter()
+                .map(|failure| (failure.scc[0].get_str_repr(), failure.stage))
+                .collect::<Vec<_>>(),
+            vec![
+                ("sub_00401126", SolveStage::Signature),
+                ("sub_00401126", SolveStage::Constraints)
+            ]
+        );
+        assert!(failures[0].message.contains("malformed constraints"));
+
+        // main is still typed, and the failed scc gets default types
+        let labeling = job.get_graph_labeling(&grph);
+        for sub in ["sub_0040113e", "sub_00401126"].iter() {
+            let (_, idx) = labeling
+                .iter()
+                .find(|(tid, _)| tid.get_str_repr() == *sub)
+                .expect("every interesting subprocedure should be labelled");
+            assert!(node_to_type.contains_key(idx));
+        }
+    }
+}
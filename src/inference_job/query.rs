@@ -0,0 +1,622 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use cwe_checker_lib::{
+    analysis::graph::{Graph, Node},
+    intermediate_representation::{Arg, Blk, ByteSize, Expression, Jmp, Program, Sub, Term, Tid},
+};
+use petgraph::{graph::NodeIndex, visit::EdgeRef, EdgeDirection::Outgoing};
+use serde::Serialize;
+
+use crate::{
+    constraint_generation::{
+        argument_registers, NodeContextMapping, PointsToMapping, RegisterMapping,
+        TypeVariableAccess,
+    },
+    constraints::{DerivedTypeVar, FieldLabel, TypeVariable},
+    lowering::TypeId,
+    node_context::{
+        points_to::{OffsetClustering, PointsToContext},
+        register_map::RegisterContext,
+    },
+    solver::type_sketch::SketchGraph,
+};
+
+/// A program point whose type can be queried once types are inferred.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum QueryPoint {
+    /// The value of a register before the def at def_index of the block with the given tid executes. An index past the
+    /// last def refers to the value at the end of the block.
+    Register {
+        /// The tid of the block
+        block: Tid,
+        /// The index of the def in the block
+        def_index: usize,
+        /// The name of the register
+        register: String,
+    },
+    /// The stack slot at a byte offset from the stack pointer on entry to the subprocedure with the given tid.
+    StackSlot {
+        /// The tid of the subprocedure
+        sub: Tid,
+        /// The byte offset from the stack pointer on entry
+        offset: i64,
+    },
+}
+
+fn register_names(args: &[Arg], cpu_architecture: &str) -> Vec<String> {
+    args.iter()
+        .flat_map(|arg| match arg {
+            Arg::Register { expr, .. } => argument_registers(expr, cpu_architecture)
+                .into_iter()
+                .map(|var| var.name.clone())
+                .collect(),
+            Arg::Stack { .. } => Vec::new(),
+        })
+        .collect()
+}
+
+/// The points of a subprocedure decompilers bind variables at: the argument registers on entry, the argument registers
+/// of the callee at the end of each block ending in a call, and the return registers at the end of each returning
+/// block.
+pub fn key_program_points(
+    program: &Program,
+    cpu_architecture: &str,
+    sub: &Term<Sub>,
+) -> BTreeSet<QueryPoint> {
+    let at = |blk: &Term<Blk>, def_index: usize, registers: Vec<String>| {
+        registers
+            .into_iter()
+            .map(move |register| QueryPoint::Register {
+                block: blk.tid.clone(),
+                def_index,
+                register,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut points = BTreeSet::new();
+    if let Some(entry) = sub.term.blocks.first() {
+        points.extend(at(
+            entry,
+            0,
+            register_names(&sub.term.formal_args, cpu_architecture),
+        ));
+    }
+    for blk in sub.term.blocks.iter() {
+        let end = blk.term.defs.len();
+        for jmp in blk.term.jmps.iter() {
+            match &jmp.term {
+                Jmp::Call { target, .. } => {
+                    let params = program
+                        .subs
+                        .get(target)
+                        .map(|callee| callee.term.formal_args.as_slice())
+                        .or_else(|| {
+                            program
+                                .extern_symbols
+                                .get(target)
+                                .map(|ext| ext.parameters.as_slice())
+                        })
+                        .unwrap_or_default();
+                    points.extend(at(blk, end, register_names(params, cpu_architecture)));
+                }
+                Jmp::Return(_) => points.extend(at(
+                    blk,
+                    end,
+                    register_names(&sub.term.formal_rets, cpu_architecture),
+                )),
+                _ => (),
+            }
+        }
+    }
+    points
+}
+
+/// The type of a register at a program point, written for decompilers to type the variables they bind there.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct RegisterBinding {
+    /// The tid of the block
+    pub block: Tid,
+    /// The index of the def in the block the register is read before, the number of defs for the end of the block
+    pub def_index: usize,
+    /// The name of the register
+    pub register: String,
+    /// The lowered type of the register
+    pub type_id: TypeId,
+}
+
+/// Binds each register point to every type that represents it, so a register with several reaching definitions of
+/// different types gets a binding for each type. Stack slot points are skipped.
+pub fn register_bindings(
+    points: impl IntoIterator<Item = QueryPoint>,
+    type_of: impl Fn(&QueryPoint) -> BTreeSet<TypeId>,
+) -> Vec<RegisterBinding> {
+    let mut bindings = points
+        .into_iter()
+        .flat_map(|point| {
+            let types = type_of(&point);
+            match point {
+                QueryPoint::Register {
+                    block,
+                    def_index,
+                    register,
+                } => types
+                    .into_iter()
+                    .map(|type_id| RegisterBinding {
+                        block: block.clone(),
+                        def_index,
+                        register: register.clone(),
+                        type_id,
+                    })
+                    .collect(),
+                QueryPoint::StackSlot { .. } => Vec::new(),
+            }
+        })
+        .collect::<Vec<_>>();
+    bindings.sort();
+    bindings.dedup();
+    bindings
+}
+
+/// The register state at the start of each block and the stack frame of each subprocedure, used to map program points
+/// back to the type variables that represent them.
+pub struct ProgramPoints {
+    blocks: BTreeMap<Tid, (Term<Blk>, RegisterContext)>,
+    stack_frames: BTreeMap<Tid, BTreeSet<TypeVariableAccess>>,
+    clustering: OffsetClustering,
+}
+
+impl ProgramPoints {
+    /// Collects the states at the start of each block from the register and points-to contexts of the graph's nodes.
+    /// Stack offsets are folded by clustering so that they match the fields generated for stack accesses.
+    pub fn new(
+        graph: &Graph,
+        reg_context: &HashMap<NodeIndex, RegisterContext>,
+        points_to: &HashMap<NodeIndex, PointsToContext>,
+        clustering: OffsetClustering,
+    ) -> ProgramPoints {
+        let mut blocks = BTreeMap::new();
+        let mut stack_frames = BTreeMap::new();
+        for nd_idx in graph.node_indices() {
+            if let Node::BlkStart(blk_term, sub_term) = &graph[nd_idx] {
+                // A block shared by several subprocedures is only queried through the first context found for it
+                if let Some(regs) = reg_context.get(&nd_idx) {
+                    blocks
+                        .entry(blk_term.tid.clone())
+                        .or_insert_with(|| ((*blk_term).clone(), regs.clone()));
+                }
+
+                let is_entry = sub_term
+                    .term
+                    .blocks
+                    .first()
+                    .map(|entry| entry.tid == blk_term.tid)
+                    .unwrap_or(false);
+                if let (true, Some(ptr)) = (is_entry, points_to.get(&nd_idx)) {
+                    stack_frames.insert(
+                        sub_term.tid.clone(),
+                        ptr.points_to(
+                            &Expression::Var(ptr.stack_pointer.clone()),
+                            ByteSize::new(1),
+                        ),
+                    );
+                }
+            }
+        }
+
+        ProgramPoints {
+            blocks,
+            stack_frames,
+            clustering,
+        }
+    }
+
+    fn register_variables(
+        &self,
+        block: &Tid,
+        def_index: usize,
+        register: &str,
+    ) -> BTreeSet<TypeVariable> {
+        let (blk, regs) = match self.blocks.get(block) {
+            Some(state) => state,
+            None => return BTreeSet::new(),
+        };
+
+        let at_point = blk
+            .term
+            .defs
+            .iter()
+            .take(def_index)
+            .fold(regs.clone(), |ctx, df| ctx.apply_def(df));
+        let var = at_point
+            .get_register_context()
+            .keys()
+            .find(|var| var.name == register)
+            .cloned();
+        var.map(|var| at_point.access(&var)).unwrap_or_default()
+    }
+
+    // The objects representing the stack frame of sub, along with the offset of the slot within each object
+    fn stack_slot_fields(&self, sub: &Tid, offset: i64) -> Vec<(TypeVariable, i64)> {
+        self.stack_frames
+            .get(sub)
+            .into_iter()
+            .flat_map(|frame| frame.iter())
+            .filter_map(|acc| {
+                acc.offset.map(|base| {
+                    (
+                        acc.ty_var.clone(),
+                        self.clustering.cluster(base.saturating_add(offset)),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Gets the type variables that must be kept while solving for a point to be queried. A register is represented by
+    /// its reaching definitions, and a stack slot by the objects representing its subprocedure's stack frame.
+    pub fn variables(&self, point: &QueryPoint) -> BTreeSet<TypeVariable> {
+        match point {
+            QueryPoint::Register {
+                block,
+                def_index,
+                register,
+            } => self.register_variables(block, *def_index, register),
+            QueryPoint::StackSlot { sub, offset } => self
+                .stack_slot_fields(sub, *offset)
+                .into_iter()
+                .map(|(tv, _)| tv)
+                .collect(),
+        }
+    }
+}
+
+/// Answers queries for the lowered type at a program point by mapping the point through the register and points-to
+/// contexts to the sketch nodes that represent it. Only variables that are kept while solving are represented in the
+/// sketch graph, so points should be registered with [super::InferenceJob::set_query_points] before inferring types.
+pub struct TypeQuery<'a, U> {
+    points: &'a ProgramPoints,
+    sketches: &'a SketchGraph<U>,
+    node_to_type: &'a BTreeMap<NodeIndex, TypeId>,
+}
+
+impl<'a, U: std::cmp::PartialEq> TypeQuery<'a, U> {
+    /// Creates a query over a labeled sketch graph and the type ids its nodes were lowered to.
+    pub fn new(
+        points: &'a ProgramPoints,
+        sketches: &'a SketchGraph<U>,
+        node_to_type: &'a BTreeMap<NodeIndex, TypeId>,
+    ) -> TypeQuery<'a, U> {
+        TypeQuery {
+            points,
+            sketches,
+            node_to_type,
+        }
+    }
+
+    fn type_of_variable(&self, tv: TypeVariable) -> Option<TypeId> {
+        self.sketches
+            .get_node_index_for_variable(&DerivedTypeVar::new(tv))
+            .and_then(|nd| self.node_to_type.get(&nd).cloned())
+    }
+
+    /// Gets the types of a register before the def at def_index of block executes. There is a type for each reaching
+    /// definition of the register that is represented in the sketch graph.
+    pub fn type_of_register(
+        &self,
+        block: &Tid,
+        def_index: usize,
+        register: &str,
+    ) -> BTreeSet<TypeId> {
+        self.points
+            .register_variables(block, def_index, register)
+            .into_iter()
+            .filter_map(|tv| self.type_of_variable(tv))
+            .collect()
+    }
+
+    /// Gets the types of the stack slot at offset from the stack pointer on entry to sub. The slot is typed by the
+    /// fields at its offset in the sketches of the subprocedure's stack frame, regardless of the size of the field.
+    pub fn type_of_stack_slot(&self, sub: &Tid, offset: i64) -> BTreeSet<TypeId> {
+        let grph = self.sketches.get_graph().get_graph();
+        self.points
+            .stack_slot_fields(sub, offset)
+            .into_iter()
+            .filter_map(|(tv, field_offset)| {
+                self.sketches
+                    .get_node_index_for_variable(&DerivedTypeVar::new(tv))
+                    .map(|nd| (nd, field_offset))
+            })
+            .flat_map(|(nd, field_offset)| {
+                grph.edges_directed(nd, Outgoing)
+                    .filter(move |e| {
+                        matches!(e.weight(), FieldLabel::Field(fld) if fld.offset == field_offset)
+                    })
+                    .map(|e| e.target())
+            })
+            .filter_map(|tgt| self.node_to_type.get(&tgt).cloned())
+            .collect()
+    }
+
+    /// Gets the types of every stack slot of sub keyed by the slot's offset from the stack pointer on entry, one slot
+    /// for each field of the sketches of the subprocedure's stack frame.
+    pub fn stack_frame_types(&self, sub: &Tid) -> BTreeMap<i64, BTreeSet<TypeId>> {
+        self.stack_frame_fields(sub)
+            .into_iter()
+            .map(|(offset, fields)| (offset, fields.into_iter().map(|(ty, _)| ty).collect()))
+            .collect()
+    }
+
+    /// Gets the type and size in bits of every field of the sketches of the stack frame of sub, keyed by the offset of
+    /// the field from the stack pointer on entry.
+    pub fn stack_frame_fields(&self, sub: &Tid) -> BTreeMap<i64, BTreeSet<(TypeId, usize)>> {
+        let grph = self.sketches.get_graph().get_graph();
+        let mut slots: BTreeMap<i64, BTreeSet<(TypeId, usize)>> = BTreeMap::new();
+        for (tv, base) in self.points.stack_slot_fields(sub, 0) {
+            let nd = match self
+                .sketches
+                .get_node_index_for_variable(&DerivedTypeVar::new(tv))
+            {
+                Some(nd) => nd,
+                None => continue,
+            };
+            for e in grph.edges_directed(nd, Outgoing) {
+                if let (FieldLabel::Field(fld), Some(ty)) =
+                    (e.weight(), self.node_to_type.get(&e.target()))
+                {
+                    slots
+                        .entry(fld.offset.saturating_sub(base))
+                        .or_default()
+                        .insert((*ty, fld.size));
+                }
+            }
+        }
+        slots
+    }
+
+    /// Gets the types at a program point.
+    pub fn type_of(&self, point: &QueryPoint) -> BTreeSet<TypeId> {
+        match point {
+            QueryPoint::Register {
+                block,
+                def_index,
+                register,
+            } => self.type_of_register(block, *def_index, register),
+            QueryPoint::StackSlot { sub, offset } => self.type_of_stack_slot(sub, *offset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use cwe_checker_lib::intermediate_representation::{
+        Arg, Bitvector, Blk, ByteSize, Def, Expression, Jmp, Program, Sub, Term, Tid, Variable,
+    };
+    use petgraph::graph::NodeIndex;
+
+    use crate::{
+        constraint_generation::TypeVariableAccess,
+        constraints::{DerivedTypeVar, TypeVariable},
+        lowering::TypeId,
+        node_context::points_to::OffsetClustering,
+        test_utils::sketches::{int_lattice, sketch_from_json},
+    };
+
+    use super::{
+        key_program_points, register_bindings, ProgramPoints, QueryPoint, RegisterBinding,
+        TypeQuery,
+    };
+
+    fn register(name: &str) -> Arg {
+        Arg::Register {
+            expr: Expression::Var(Variable {
+                name: name.to_owned(),
+                size: ByteSize::new(8),
+                is_temp: false,
+            }),
+            data_type: None,
+        }
+    }
+
+    fn block(address: &str, defs: usize, jmp: Jmp) -> Term<Blk> {
+        let assign = |idx: usize| Term {
+            tid: Tid::create(format!("instr_{}_{}", address, idx), address.to_owned()),
+            term: Def::Assign {
+                var: Variable {
+                    name: "RCX".to_owned(),
+                    size: ByteSize::new(8),
+                    is_temp: false,
+                },
+                value: Expression::Const(Bitvector::from_i64(idx as i64)),
+            },
+        };
+        Term {
+            tid: Tid::create(format!("blk_{}", address), address.to_owned()),
+            term: Blk {
+                defs: (0..defs).map(assign).collect(),
+                jmps: vec![Term {
+                    tid: Tid::create(format!("instr_{}_jmp", address), address.to_owned()),
+                    term: jmp,
+                }],
+                indirect_jmp_targets: Vec::new(),
+            },
+        }
+    }
+
+    fn sub(
+        address: &str,
+        formal_args: Vec<Arg>,
+        formal_rets: Vec<Arg>,
+        blocks: Vec<Term<Blk>>,
+    ) -> Term<Sub> {
+        Term {
+            tid: Tid::create(format!("sub_{}", address), address.to_owned()),
+            term: Sub {
+                name: format!("sub_{}", address),
+                blocks,
+                formal_args,
+                formal_rets,
+                calling_convention: None,
+            },
+        }
+    }
+
+    fn register_point(block: &str, def_index: usize, register: &str) -> QueryPoint {
+        QueryPoint::Register {
+            block: Tid::create(format!("blk_{}", block), block.to_owned()),
+            def_index,
+            register: register.to_owned(),
+        }
+    }
+
+    #[test]
+    fn key_points_are_the_entry_callsites_and_returns() {
+        let callee = sub(
+            "00402000",
+            vec![register("RDI"), register("RSI")],
+            Vec::new(),
+            vec![block(
+                "00402000",
+                0,
+                Jmp::Return(Expression::Const(Bitvector::from_i64(0))),
+            )],
+        );
+        // caller(rdi) { rcx = 0; rcx = 1; callee(rdi, rsi); rcx = 2; return rax; }
+        let caller = sub(
+            "00401000",
+            vec![register("RDI")],
+            vec![register("RAX")],
+            vec![
+                block(
+                    "00401000",
+                    2,
+                    Jmp::Call {
+                        target: callee.tid.clone(),
+                        return_: Some(Tid::create(
+                            "blk_00401010".to_owned(),
+                            "00401010".to_owned(),
+                        )),
+                    },
+                ),
+                block(
+                    "00401010",
+                    1,
+                    Jmp::Return(Expression::Const(Bitvector::from_i64(0))),
+                ),
+            ],
+        );
+        let program = Program {
+            subs: BTreeMap::from([
+                (caller.tid.clone(), caller.clone()),
+                (callee.tid.clone(), callee),
+            ]),
+            extern_symbols: BTreeMap::new(),
+            entry_points: BTreeSet::new(),
+            address_base_offset: 0,
+            global_variables: BTreeMap::new(),
+        };
+
+        assert_eq!(
+            key_program_points(&program, "x86_64", &caller),
+            BTreeSet::from([
+                // the argument on entry
+                register_point("00401000", 0, "RDI"),
+                // the arguments of the callee after the last def of the calling block
+                register_point("00401000", 2, "RDI"),
+                register_point("00401000", 2, "RSI"),
+                // the return at the end of the returning block
+                register_point("00401010", 1, "RAX"),
+            ])
+        );
+    }
+
+    #[test]
+    fn registers_are_bound_to_every_type() {
+        let (int_ty, ptr_ty): (TypeId, TypeId) = (
+            serde_json::from_str("3").unwrap(),
+            serde_json::from_str("5").unwrap(),
+        );
+        let entry = register_point("00401000", 0, "RDI");
+        let ret = register_point("00401010", 1, "RAX");
+        let untyped = register_point("00401010", 1, "RDX");
+        let frame = QueryPoint::StackSlot {
+            sub: Tid::create("sub_00401000".to_owned(), "00401000".to_owned()),
+            offset: -8,
+        };
+
+        // rdi has an int and a pointer reaching definition, rax only an int, and rdx none
+        let bindings = register_bindings(
+            vec![entry.clone(), ret.clone(), untyped, frame],
+            |point| match point {
+                _ if *point == entry => BTreeSet::from([int_ty, ptr_ty]),
+                _ if *point == ret => BTreeSet::from([int_ty]),
+                QueryPoint::StackSlot { .. } => BTreeSet::from([ptr_ty]),
+                QueryPoint::Register { .. } => BTreeSet::new(),
+            },
+        );
+        let binding = |block: &str, def_index, register: &str, type_id| RegisterBinding {
+            block: Tid::create(format!("blk_{}", block), block.to_owned()),
+            def_index,
+            register: register.to_owned(),
+            type_id,
+        };
+        assert_eq!(
+            bindings,
+            vec![
+                binding("00401000", 0, "RDI", int_ty),
+                binding("00401000", 0, "RDI", ptr_ty),
+                binding("00401010", 1, "RAX", int_ty),
+            ]
+        );
+    }
+
+    #[test]
+    fn stack_slots_are_typed_by_frame_fields() {
+        let lattice = int_lattice();
+        let frame = TypeVariable::new("frame".to_owned());
+        // a frame with an int at offset 8 and an untyped slot at offset 16
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","int"],["bottom","top"]]"#,
+            r#"[[0,1,{"Field":{"offset":8,"size":32}}],[0,2,{"Field":{"offset":16,"size":64}}]]"#,
+            &[(DerivedTypeVar::new(frame.clone()), 0)],
+        );
+
+        let sub = Tid::create("sub_a".to_owned(), "1000".to_owned());
+        let points = ProgramPoints {
+            blocks: BTreeMap::new(),
+            stack_frames: BTreeMap::from([(
+                sub.clone(),
+                BTreeSet::from([TypeVariableAccess {
+                    ty_var: frame.clone(),
+                    sz: ByteSize::new(1),
+                    offset: Some(0),
+                }]),
+            )]),
+            clustering: OffsetClustering::default(),
+        };
+        assert_eq!(
+            points.variables(&QueryPoint::StackSlot {
+                sub: sub.clone(),
+                offset: 8
+            }),
+            BTreeSet::from([frame])
+        );
+
+        let int_ty: TypeId = serde_json::from_str("3").unwrap();
+        let node_to_type = BTreeMap::from([(NodeIndex::new(1), int_ty)]);
+        let query = TypeQuery::new(&points, &grph, &node_to_type);
+        assert_eq!(query.type_of_stack_slot(&sub, 8), BTreeSet::from([int_ty]));
+        assert!(query.type_of_stack_slot(&sub, 16).is_empty());
+        assert_eq!(
+            query.stack_frame_types(&sub),
+            BTreeMap::from([(8, BTreeSet::from([int_ty]))])
+        );
+        assert!(query.type_of_stack_slot(&sub, 4).is_empty());
+        assert!(query
+            .type_of_register(&Tid::create("blk".to_owned(), "1000".to_owned()), 0, "RAX")
+            .is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::BTreeSet;
+
+use cwe_checker_lib::intermediate_representation::{Project, Tid};
+use regex::Regex;
+
+use crate::{
+    constraint_generation::tid_to_tvar, constraints::TypeVariable,
+    solver::constraint_graph::RuleContext,
+};
+
+/// Translates a shell style glob, where `*` matches any run of characters and `?` any single character, to an anchored
+/// regex.
+pub fn glob_to_regex(glob: &str) -> anyhow::Result<Regex> {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            _ => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Ok(Regex::new(&pattern)?)
+}
+
+/// Selects the interesting variables of a [RuleContext] from the symbols of a [Project]. A subprocedure or extern symbol
+/// is selected if any selector matches it: a name pattern, an address range containing the address of its tid, or
+/// selecting all subprocedures or all externs.
+#[derive(Debug, Clone, Default)]
+pub struct RuleContextBuilder {
+    names: Vec<Regex>,
+    address_ranges: Vec<(u64, u64)>,
+    all_subs: bool,
+    all_externs: bool,
+    variables: BTreeSet<TypeVariable>,
+}
+
+impl RuleContextBuilder {
+    /// Creates a builder that selects nothing.
+    pub fn new() -> RuleContextBuilder {
+        RuleContextBuilder::default()
+    }
+
+    /// Selects symbols whose whole name matches a glob.
+    pub fn with_name_glob(self, glob: &str) -> anyhow::Result<RuleContextBuilder> {
+        Ok(self.with_name_pattern(glob_to_regex(glob)?))
+    }
+
+    /// Selects symbols whose name matches a regex. The regex is not anchored.
+    pub fn with_name_regex(self, regex: &str) -> anyhow::Result<RuleContextBuilder> {
+        Ok(self.with_name_pattern(Regex::new(regex)?))
+    }
+
+    /// Selects symbols whose name matches a compiled regex.
+    pub fn with_name_pattern(mut self, pattern: Regex) -> RuleContextBuilder {
+        self.names.push(pattern);
+        self
+    }
+
+    /// Selects symbols at addresses from start up to but not including end.
+    pub fn with_address_range(mut self, start: u64, end: u64) -> RuleContextBuilder {
+        self.address_ranges.push((start, end));
+        self
+    }
+
+    /// Selects the symbol at an address.
+    pub fn with_address(self, address: u64) -> RuleContextBuilder {
+        self.with_address_range(address, address.saturating_add(1))
+    }
+
+    /// Selects every subprocedure.
+    pub fn with_all_subs(mut self) -> RuleContextBuilder {
+        self.all_subs = true;
+        self
+    }
+
+    /// Selects every extern symbol.
+    pub fn with_all_externs(mut self) -> RuleContextBuilder {
+        self.all_externs = true;
+        self
+    }
+
+    /// Adds variables that are interesting regardless of the project, ie. lattice elements or globals.
+    pub fn with_variables(
+        mut self,
+        variables: impl IntoIterator<Item = TypeVariable>,
+    ) -> RuleContextBuilder {
+        self.variables.extend(variables);
+        self
+    }
+
+    /// Parses a range of the form `start-end` of hexadecimal addresses, with or without a `0x` prefix.
+    pub fn parse_address_range(range: &str) -> anyhow::Result<(u64, u64)> {
+        let parse = |addr: &str| {
+            let addr = addr.trim();
+            u64::from_str_radix(addr.strip_prefix("0x").unwrap_or(addr), 16)
+                .map_err(|err| anyhow::anyhow!("invalid address {}: {}", addr, err))
+        };
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| anyhow::anyhow!("expected an address range start-end, got {}", range))?;
+        Ok((parse(start)?, parse(end)?))
+    }
+
+    fn selects(&self, name: &str, tid: &Tid, is_extern: bool) -> bool {
+        let selected_kind = if is_extern {
+            self.all_externs
+        } else {
+            self.all_subs
+        };
+        // tids of symbols without a known address are not in any range
+        let in_range = || {
+            u64::from_str_radix(&tid.address, 16)
+                .map(|addr| {
+                    self.address_ranges
+                        .iter()
+                        .any(|(start, end)| *start <= addr && addr < *end)
+                })
+                .unwrap_or(false)
+        };
+        selected_kind || self.names.iter().any(|re| re.is_match(name)) || in_range()
+    }
+
+    /// Gets the tids of the subprocedures and extern symbols of the project that are selected.
+    pub fn resolve_tids(&self, proj: &Project) -> BTreeSet<Tid> {
+        let subs = proj
+            .program
+            .term
+            .subs
+            .iter()
+            .filter(|(tid, sub)| self.selects(&sub.term.name, tid, false))
+            .map(|(tid, _)| tid.clone());
+        let externs = proj
+            .program
+            .term
+            .extern_symbols
+            .iter()
+            .filter(|(tid, ext)| self.selects(&ext.name, tid, true))
+            .map(|(tid, _)| tid.clone());
+        subs.chain(externs).collect()
+    }
+
+    /// Builds a rule context where the selected symbols and added variables are interesting.
+    pub fn build(&self, proj: &Project) -> RuleContext {
+        RuleContext::new(
+            self.resolve_tids(proj)
+                .iter()
+                .map(tid_to_tvar)
+                .chain(self.variables.iter().cloned())
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use super::RuleContextBuilder;
+
+    #[test]
+    fn selectors_match_names_and_addresses() {
+        let tid = |name: &str, addr: &str| Tid::create(name.to_owned(), addr.to_owned());
+        let bldr = RuleContextBuilder::new()
+            .with_name_glob("str*cpy")
+            .unwrap()
+            .with_name_regex("^parse_")
+            .unwrap()
+            .with_address_range(0x1000, 0x2000);
+
+        assert!(bldr.selects("strncpy", &tid("sub_500", "00000500"), false));
+        assert!(!bldr.selects("strncpy_s", &tid("sub_500", "00000500"), false));
+        assert!(bldr.selects("parse_header", &tid("sub_500", "00000500"), false));
+        assert!(bldr.selects("main", &tid("sub_1800", "00001800"), false));
+        assert!(!bldr.selects("main", &tid("sub_2000", "00002000"), false));
+        assert!(!bldr.selects("malloc", &tid("malloc", "UNKNOWN"), true));
+        assert!(RuleContextBuilder::new().with_all_externs().selects(
+            "malloc",
+            &tid("malloc", "UNKNOWN"),
+            true
+        ));
+
+        assert_eq!(
+            RuleContextBuilder::parse_address_range("0x1000-2000").unwrap(),
+            (0x1000, 0x2000)
+        );
+        assert!(RuleContextBuilder::parse_address_range("1000").is_err());
+    }
+}
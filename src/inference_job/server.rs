@@ -0,0 +1,558 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+};
+
+use cwe_checker_lib::intermediate_representation::Tid;
+use petgraph::graph::NodeIndex;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+use crate::{
+    lowering::{naming::TypeNamer, CType, TypeId},
+    solver::{
+        type_lattice::CustomLatticeElement,
+        type_sketch::{LatticeBounds, SketchGraph},
+    },
+};
+
+use super::{
+    assumptions::TypeAssumption,
+    query::{ProgramPoints, QueryPoint},
+    InferenceJob,
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// The longest message body that is read. The length is sent by the client, so longer bodies are skipped rather than
+/// allocated.
+pub const MAX_MESSAGE_LEN: usize = 256 * 1024 * 1024;
+
+/// A message read from a stream framed by Content-Length headers.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Frame {
+    /// The body of the message
+    Body(Vec<u8>),
+    /// A message that was skipped because its length, given here, exceeds [MAX_MESSAGE_LEN]
+    TooLong(usize),
+}
+
+/// Reads a message framed by a Content-Length header, as in the language server protocol. Returns None once the input
+/// is exhausted.
+pub fn read_message(rdr: &mut impl BufRead) -> anyhow::Result<Option<Frame>> {
+    read_frame(rdr, MAX_MESSAGE_LEN)
+}
+
+fn read_frame(rdr: &mut impl BufRead, max_len: usize) -> anyhow::Result<Option<Frame>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if rdr.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = Some(value.trim().parse::<usize>()?);
+            }
+        }
+    }
+
+    let content_length = content_length.expect("the header loop only ends with a length");
+    if content_length > max_len {
+        std::io::copy(
+            &mut std::io::Read::take(rdr, content_length as u64),
+            &mut std::io::sink(),
+        )?;
+        return Ok(Some(Frame::TooLong(content_length)));
+    }
+
+    let mut body = vec![0; content_length];
+    rdr.read_exact(&mut body)?;
+    Ok(Some(Frame::Body(body)))
+}
+
+/// Writes a message framed by a Content-Length header.
+pub fn write_message(out: &mut impl Write, msg: &Value) -> anyhow::Result<()> {
+    let body = serde_json::to_vec(msg)?;
+    write!(out, "Content-Length: {}\r\n\r\n", body.len())?;
+    out.write_all(&body)?;
+    out.flush()?;
+    Ok(())
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(err: anyhow::Error) -> RpcError {
+        RpcError {
+            code: INTERNAL_ERROR,
+            message: format!("{:#}", err),
+        }
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|err| RpcError {
+        code: INVALID_PARAMS,
+        message: err.to_string(),
+    })
+}
+
+#[derive(Deserialize)]
+struct TermParams {
+    term: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TypeParams {
+    type_id: TypeId,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RegisterParams {
+    block: Tid,
+    def_index: usize,
+    register: String,
+}
+
+#[derive(Deserialize)]
+struct StackSlotParams {
+    sub: Tid,
+    offset: i64,
+}
+
+#[derive(Deserialize)]
+struct AssumptionParams {
+    assumptions: Vec<TypeAssumption>,
+}
+
+#[derive(Deserialize)]
+struct RemoveAssumptionParams {
+    assumption: TypeAssumption,
+}
+
+struct Solved {
+    sketches: SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    node_to_type: BTreeMap<NodeIndex, TypeId>,
+    types: BTreeMap<TypeId, CType>,
+    labeling: BTreeMap<Tid, NodeIndex>,
+}
+
+/// A long lived server that keeps a solved [InferenceJob] in memory and answers JSON-RPC 2.0 requests over a pair of
+/// streams. Messages are framed with Content-Length headers as in the language server protocol.
+///
+/// Requests:
+/// * `solve`: solves (or re-solves) the job
+/// * `typeOf {term}`: the type of a labeled term
+/// * `getType {typeId}`: a lowered type
+/// * `typeAtRegister {block, defIndex, register}` and `typeAtStackSlot {sub, offset}`: the types at a program point,
+///   which must have been registered with [InferenceJob::set_query_points] before the job was handed to the server
+/// * `addAssumptions {assumptions}` and `removeAssumption {assumption}`: update assumptions and re-solve
+/// * `shutdown`: stops the server
+///
+/// Every solve is bracketed by `bti/solveStarted` and `bti/solveFinished` notifications, the latter listing the
+/// subprocedures that were resimplified. The job is solved on the first request that needs types.
+pub struct Server {
+    job: InferenceJob,
+    points: Option<ProgramPoints>,
+    solved: Option<Solved>,
+}
+
+impl Server {
+    /// Creates a server for a job that has not been solved.
+    pub fn new(job: InferenceJob) -> Server {
+        Server {
+            job,
+            points: None,
+            solved: None,
+        }
+    }
+
+    /// Serves requests read from rdr until a shutdown request or the end of input, writing responses and
+    /// notifications to out.
+    pub fn serve(&mut self, mut rdr: impl BufRead, mut out: impl Write) -> anyhow::Result<()> {
+        while let Some(frame) = read_message(&mut rdr)? {
+            let body = match frame {
+                Frame::Body(body) => body,
+                Frame::TooLong(len) => {
+                    let message = format!(
+                        "message of {} bytes exceeds the limit of {} bytes",
+                        len, MAX_MESSAGE_LEN
+                    );
+                    write_message(
+                        &mut out,
+                        &json!({"jsonrpc": "2.0", "id": null, "error": {"code": INVALID_REQUEST, "message": message}}),
+                    )?;
+                    continue;
+                }
+            };
+            let msg: Value = match serde_json::from_slice(&body) {
+                Ok(msg) => msg,
+                Err(err) => {
+                    write_message(
+                        &mut out,
+                        &json!({"jsonrpc": "2.0", "id": null, "error": {"code": PARSE_ERROR, "message": err.to_string()}}),
+                    )?;
+                    continue;
+                }
+            };
+
+            let id = msg.get("id").cloned();
+            let method = msg
+                .get("method")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_owned();
+            let params = msg.get("params").cloned().unwrap_or(Value::Null);
+
+            let res = self.handle(&method, params, &mut out);
+            // requests without an id are notifications and are not answered
+            if let Some(id) = id {
+                let response = match res {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    Err(err) => {
+                        json!({"jsonrpc": "2.0", "id": id, "error": {"code": err.code, "message": err.message}})
+                    }
+                };
+                write_message(&mut out, &response)?;
+            }
+
+            if method == "shutdown" {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn notify(out: &mut impl Write, method: &str, params: Value) -> anyhow::Result<()> {
+        write_message(
+            out,
+            &json!({"jsonrpc": "2.0", "method": method, "params": params}),
+        )
+    }
+
+    fn solve(&mut self, out: &mut impl Write) -> anyhow::Result<&Solved> {
+        Self::notify(out, "bti/solveStarted", Value::Null)?;
+        let (sketches, (node_to_type, types)) = self.job.infer_ctypes()?;
+        let labeling = self.job.get_graph_labeling(&sketches);
+        Self::notify(
+            out,
+            "bti/solveFinished",
+            json!({
+                "resimplified": self
+                    .job
+                    .get_last_resimplified_tids()
+                    .iter()
+                    .map(|tid| tid.get_str_repr())
+                    .collect::<Vec<_>>(),
+                "terms": labeling.len(),
+            }),
+        )?;
+
+        Ok(self.solved.insert(Solved {
+            sketches,
+            node_to_type,
+            types,
+            labeling,
+        }))
+    }
+
+    fn get_solved(&mut self, out: &mut impl Write) -> anyhow::Result<&Solved> {
+        if self.solved.is_none() {
+            self.solve(out)?;
+        }
+        Ok(self.solved.as_ref().expect("the job was just solved"))
+    }
+
+    fn describe_type(solved: &Solved, ty: TypeId) -> Value {
+        json!({
+            "typeId": ty,
+            "name": TypeNamer::new(&solved.types, &solved.node_to_type).type_name(ty),
+            "type": solved.types.get(&ty),
+        })
+    }
+
+    fn type_at_point(
+        &mut self,
+        out: &mut impl Write,
+        point: QueryPoint,
+    ) -> Result<Value, RpcError> {
+        // the variables of other points may have been simplified away, so the answer would silently be empty
+        if !self.job.keeps_query_point(&point) {
+            return Err(RpcError {
+                code: INVALID_PARAMS,
+                message: format!(
+                    "{:?} was not registered as a query point before solving",
+                    point
+                ),
+            });
+        }
+        if self.points.is_none() {
+            self.points = Some(self.job.get_program_points()?);
+        }
+        self.get_solved(out)?;
+
+        let solved = self.solved.as_ref().expect("the job was solved");
+        let points = self.points.as_ref().expect("points were collected");
+        let tys = self
+            .job
+            .type_query(points, &solved.sketches, &solved.node_to_type)
+            .type_of(&point);
+        Ok(Value::Array(
+            tys.into_iter()
+                .map(|ty| Self::describe_type(solved, ty))
+                .collect(),
+        ))
+    }
+
+    fn handle(
+        &mut self,
+        method: &str,
+        params: Value,
+        out: &mut impl Write,
+    ) -> Result<Value, RpcError> {
+        match method {
+            "solve" => {
+                let solved = self.solve(out)?;
+                Ok(json!({"terms": solved.labeling.len()}))
+            }
+            "typeOf" => {
+                let TermParams { term } = parse_params(params)?;
+                let solved = self.get_solved(out)?;
+                let ty = solved
+                    .labeling
+                    .iter()
+                    .find(|(tid, _)| tid.get_str_repr() == term)
+                    .and_then(|(_, nd)| solved.node_to_type.get(nd));
+                Ok(ty
+                    .map(|ty| Self::describe_type(solved, *ty))
+                    .unwrap_or(Value::Null))
+            }
+            "getType" => {
+                let TypeParams { type_id } = parse_params(params)?;
+                let solved = self.get_solved(out)?;
+                if !solved.types.contains_key(&type_id) {
+                    return Err(RpcError {
+                        code: INVALID_PARAMS,
+                        message: format!("no type with id {}", type_id),
+                    });
+                }
+                Ok(Self::describe_type(solved, type_id))
+            }
+            "typeAtRegister" => {
+                let RegisterParams {
+                    block,
+                    def_index,
+                    register,
+                } = parse_params(params)?;
+                self.type_at_point(
+                    out,
+                    QueryPoint::Register {
+                        block,
+                        def_index,
+                        register,
+                    },
+                )
+            }
+            "typeAtStackSlot" => {
+                let StackSlotParams { sub, offset } = parse_params(params)?;
+                self.type_at_point(out, QueryPoint::StackSlot { sub, offset })
+            }
+            "addAssumptions" => {
+                let AssumptionParams { assumptions } = parse_params(params)?;
+                let previous = self.job.get_assumptions().to_vec();
+                self.job.add_assumptions(&assumptions)?;
+                if let Err(err) = self.solve(out) {
+                    // the previous solution still reflects the previous assumptions
+                    self.job.set_assumptions(previous);
+                    return Err(err.into());
+                }
+                Ok(json!({ "assumptions": self.job.get_assumptions().len() }))
+            }
+            "removeAssumption" => {
+                let RemoveAssumptionParams { assumption } = parse_params(params)?;
+                let removed = self.job.remove_assumption(&assumption);
+                if removed {
+                    self.solve(out)?;
+                }
+                Ok(json!({ "removed": removed }))
+            }
+            "shutdown" => Ok(Value::Null),
+            _ => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("unknown method {}", method),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cwe_checker_lib::intermediate_representation::Tid;
+    use serde_json::{json, Value};
+
+    use crate::{
+        inference_job::{
+            assumptions::{AssumptionTarget, TypeAssumption},
+            JsonDef,
+        },
+        test_utils::golden::{infer_case, TAGGED_UNION},
+    };
+
+    use super::{Frame, Server, INTERNAL_ERROR, INVALID_PARAMS, METHOD_NOT_FOUND};
+
+    fn body(frame: Option<Frame>) -> Value {
+        match frame {
+            Some(Frame::Body(body)) => serde_json::from_slice(&body).unwrap(),
+            frame => panic!("expected a message body, got {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn messages_round_trip_through_framing() {
+        let mut buf = Vec::new();
+        super::write_message(
+            &mut buf,
+            &json!({"jsonrpc": "2.0", "id": 1, "method": "solve"}),
+        )
+        .unwrap();
+        super::write_message(&mut buf, &json!({"jsonrpc": "2.0", "method": "shutdown"})).unwrap();
+
+        let mut rdr = buf.as_slice();
+        let fst = body(super::read_message(&mut rdr).unwrap());
+        let snd = body(super::read_message(&mut rdr).unwrap());
+        assert_eq!(fst["method"], "solve");
+        assert_eq!(snd["method"], "shutdown");
+        assert!(super::read_message(&mut rdr).unwrap().is_none());
+    }
+
+    #[test]
+    fn overlong_messages_are_skipped_without_allocating() {
+        let solve = json!({"jsonrpc": "2.0", "id": 1, "method": "solve"});
+        let mut buf = Vec::new();
+        super::write_message(&mut buf, &solve).unwrap();
+        super::write_message(&mut buf, &json!({"jsonrpc": "2.0", "method": "shutdown"})).unwrap();
+
+        // the solve request is 41 bytes long and the shutdown request 37
+        let mut rdr = buf.as_slice();
+        assert_eq!(
+            super::read_frame(&mut rdr, 40).unwrap(),
+            Some(Frame::TooLong(serde_json::to_vec(&solve).unwrap().len()))
+        );
+        assert_eq!(
+            body(super::read_frame(&mut rdr, 40).unwrap())["method"],
+            "shutdown"
+        );
+    }
+
+    // Deleting the checkpoint directory while its journal is open is only allowed on unix
+    #[cfg(unix)]
+    #[test]
+    fn requests_are_dispatched_and_failed_solves_keep_the_previous_assumptions() {
+        let dir = tempdir::TempDir::new("server").expect("should create a temporary directory");
+        let checkpoint_dir = dir.path().to_string_lossy().into_owned();
+        let (job, _) = infer_case::<JsonDef>(&TAGGED_UNION, |job| {
+            job.set_checkpoint_dir(&checkpoint_dir, false)
+                .expect("should open the checkpoint")
+        });
+        let mut server = Server::new(job);
+        let mut out = Vec::new();
+
+        assert_eq!(
+            server
+                .handle("frobnicate", Value::Null, &mut out)
+                .unwrap_err()
+                .code,
+            METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            server
+                .handle("getType", json!({}), &mut out)
+                .unwrap_err()
+                .code,
+            INVALID_PARAMS
+        );
+
+        // area is sub_00401126
+        let area = Tid::create("sub_00401126".to_owned(), "00401126".to_owned());
+        let ty = server
+            .handle("typeOf", json!({"term": "sub_00401126"}), &mut out)
+            .unwrap();
+        assert!(ty["typeId"].is_number());
+        // no stack slots were registered before solving
+        assert_eq!(
+            server
+                .handle(
+                    "typeAtStackSlot",
+                    json!({"sub": serde_json::to_value(&area).unwrap(), "offset": 0}),
+                    &mut out
+                )
+                .unwrap_err()
+                .code,
+            INVALID_PARAMS
+        );
+
+        let assumption = TypeAssumption {
+            target: AssumptionTarget::Return {
+                subprocedure: area,
+                index: 0,
+            },
+            ty: "weak_integer".to_owned(),
+            pointee_bit_size: None,
+        };
+        let params = json!({ "assumptions": [serde_json::to_value(&assumption).unwrap()] });
+        assert_eq!(
+            server
+                .handle("addAssumptions", params.clone(), &mut out)
+                .unwrap(),
+            json!({"assumptions": 1})
+        );
+
+        // the next solve fails to save its checkpoint, adding the assumption again must not drop the first copy
+        dir.close().unwrap();
+        assert_eq!(
+            server
+                .handle("addAssumptions", params, &mut out)
+                .unwrap_err()
+                .code,
+            INTERNAL_ERROR
+        );
+        assert_eq!(server.job.get_assumptions(), &[assumption]);
+
+        let mut rdr = out.as_slice();
+        let mut notifications = Vec::new();
+        while let Some(frame) = super::read_message(&mut rdr).unwrap() {
+            notifications.push(body(Some(frame))["method"].as_str().unwrap().to_owned());
+        }
+        // typeOf and both assumption changes solve, the failed solve does not finish
+        assert_eq!(
+            notifications,
+            vec![
+                "bti/solveStarted",
+                "bti/solveFinished",
+                "bti/solveStarted",
+                "bti/solveFinished",
+                "bti/solveStarted"
+            ]
+        );
+    }
+}
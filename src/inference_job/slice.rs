@@ -0,0 +1,140 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use cwe_checker_lib::intermediate_representation::{Project, Tid};
+use petgraph::EdgeDirection;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::callgraph::CallGraph;
+
+/// The subprocedures whose bodies are needed to infer the type of a single target subprocedure: the target, its direct
+/// callees, which provide the summaries instantiated at its calls, and its callers up to a bound, which provide the
+/// actuals passed to it. Every other subprocedure is kept without a body so calls to it are left unconstrained.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionSlice {
+    target: Tid,
+    callees: BTreeSet<Tid>,
+    callers: BTreeSet<Tid>,
+}
+
+impl FunctionSlice {
+    /// Computes the slice of target in a callgraph, following callers up to caller_depth calls away from the target.
+    /// Fails if the target is not in the callgraph.
+    pub fn from_callgraph(
+        cg: &CallGraph,
+        target: &Tid,
+        caller_depth: usize,
+    ) -> anyhow::Result<FunctionSlice> {
+        let target_idx = cg
+            .node_indices()
+            .find(|idx| &cg[*idx] == target)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a subprocedure of the project", target))?;
+
+        let callees = cg
+            .neighbors_directed(target_idx, EdgeDirection::Outgoing)
+            .map(|idx| cg[idx].clone())
+            .filter(|callee| callee != target)
+            .collect();
+
+        let mut callers = BTreeSet::new();
+        let mut seen = BTreeSet::from([target_idx]);
+        let mut worklist = VecDeque::from([(target_idx, 0)]);
+        while let Some((idx, depth)) = worklist.pop_front() {
+            if depth == caller_depth {
+                continue;
+            }
+            for caller in cg.neighbors_directed(idx, EdgeDirection::Incoming) {
+                if seen.insert(caller) {
+                    callers.insert(cg[caller].clone());
+                    worklist.push_back((caller, depth + 1));
+                }
+            }
+        }
+
+        Ok(FunctionSlice {
+            target: target.clone(),
+            callees,
+            callers,
+        })
+    }
+
+    /// The subprocedure the slice is computed for
+    pub fn get_target(&self) -> &Tid {
+        &self.target
+    }
+
+    /// The subprocedures called by the target
+    pub fn get_callees(&self) -> &BTreeSet<Tid> {
+        &self.callees
+    }
+
+    /// The subprocedures that reach the target within the caller depth
+    pub fn get_callers(&self) -> &BTreeSet<Tid> {
+        &self.callers
+    }
+
+    /// Every subprocedure whose body is kept in the slice.
+    pub fn subprocedures(&self) -> BTreeSet<Tid> {
+        std::iter::once(self.target.clone())
+            .chain(self.callees.iter().cloned())
+            .chain(self.callers.iter().cloned())
+            .collect()
+    }
+
+    /// Copies the project, removing the blocks of every subprocedure outside of the slice.
+    pub fn slice_project(&self, proj: &Project) -> Project {
+        let kept = self.subprocedures();
+        let mut sliced = proj.clone();
+        sliced
+            .program
+            .term
+            .subs
+            .iter_mut()
+            .filter(|(tid, _)| !kept.contains(tid))
+            .for_each(|(_, sub)| sub.term.blocks.clear());
+        sliced
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+    use petgraph::graph::DiGraph;
+
+    use crate::analysis::callgraph::CallGraph;
+
+    use super::FunctionSlice;
+
+    #[test]
+    fn slice_follows_callers_up_to_depth() {
+        let tid = |name: &str| Tid::create(name.to_owned(), "0".to_owned());
+        let mut cg: CallGraph = DiGraph::new();
+        let main = cg.add_node(tid("sub_main"));
+        let wrapper = cg.add_node(tid("sub_wrapper"));
+        let target = cg.add_node(tid("sub_target"));
+        let helper = cg.add_node(tid("sub_helper"));
+        let leaf = cg.add_node(tid("sub_leaf"));
+        cg.add_edge(main, wrapper, ());
+        cg.add_edge(wrapper, target, ());
+        cg.add_edge(target, helper, ());
+        cg.add_edge(helper, leaf, ());
+
+        let slice = FunctionSlice::from_callgraph(&cg, &tid("sub_target"), 1).unwrap();
+        assert_eq!(slice.get_callees(), &BTreeSet::from([tid("sub_helper")]));
+        assert_eq!(slice.get_callers(), &BTreeSet::from([tid("sub_wrapper")]));
+
+        let deeper = FunctionSlice::from_callgraph(&cg, &tid("sub_target"), 2).unwrap();
+        assert_eq!(
+            deeper.subprocedures(),
+            BTreeSet::from([
+                tid("sub_main"),
+                tid("sub_wrapper"),
+                tid("sub_target"),
+                tid("sub_helper")
+            ])
+        );
+
+        assert!(FunctionSlice::from_callgraph(&cg, &tid("sub_missing"), 1).is_err());
+    }
+}
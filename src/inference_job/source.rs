@@ -0,0 +1,106 @@
+use std::collections::BTreeSet;
+
+use anyhow::Context;
+use cwe_checker_lib::{
+    analysis::graph::Graph,
+    intermediate_representation::{Project, RuntimeMemoryImage},
+};
+
+use crate::util::BareMetalLayout;
+
+use super::{InferenceJob, JobDefinition};
+
+/// A frontend that produces the IR the pipeline runs on. Implementations lift a binary (or load an already lifted representation)
+/// into a normalized [Project], and may override how the memory image and control flow graph are built.
+pub trait ProjectSource {
+    /// Loads the bytes of the analyzed binary.
+    fn binary_bytes(&self) -> anyhow::Result<Vec<u8>>;
+
+    /// Produces the normalized IR project for the binary.
+    fn project(&self, binary: &[u8]) -> anyhow::Result<Project>;
+
+    /// Produces the memory image used to resolve accesses to global memory. By default this is the image the project was created with.
+    fn runtime_memory_image(
+        &self,
+        proj: &Project,
+        _binary: &[u8],
+    ) -> anyhow::Result<RuntimeMemoryImage> {
+        Ok(proj.runtime_memory_image.clone())
+    }
+
+    /// Builds the interprocedural control flow graph of the project.
+    fn control_flow_graph<'a>(&self, proj: &'a Project) -> Graph<'a> {
+        InferenceJob::graph_from_project(proj)
+    }
+}
+
+/// Loads the Pcode json IR exported by the cwe_checker Ghidra plugin.
+#[derive(Debug, Clone)]
+pub struct PcodeJsonSource {
+    binary_path: String,
+    ir_json_path: String,
+    bare_metal_layout: Option<BareMetalLayout>,
+    selected_bodies: Option<BTreeSet<String>>,
+}
+
+impl PcodeJsonSource {
+    /// Creates a source for a binary and its exported IR.
+    pub fn new(binary_path: &str, ir_json_path: &str) -> PcodeJsonSource {
+        PcodeJsonSource {
+            binary_path: binary_path.to_owned(),
+            ir_json_path: ir_json_path.to_owned(),
+            bare_metal_layout: None,
+            selected_bodies: None,
+        }
+    }
+
+    /// Treats the binary as a bare metal image with the given layout.
+    pub fn with_bare_metal_layout(mut self, layout: BareMetalLayout) -> PcodeJsonSource {
+        self.bare_metal_layout = Some(layout);
+        self
+    }
+
+    /// Streams the IR and only keeps the bodies of the subprocedures with a tid id in selected_bodies, reducing peak memory
+    /// on large IR dumps. Unselected subprocedures remain in the project without blocks.
+    pub fn with_selected_bodies(mut self, selected_bodies: BTreeSet<String>) -> PcodeJsonSource {
+        self.selected_bodies = Some(selected_bodies);
+        self
+    }
+
+    /// Creates a source for the binary and IR of a job definition, reading the bare metal layout if the definition has one.
+    pub fn from_definition(def: &JobDefinition) -> anyhow::Result<PcodeJsonSource> {
+        let source = Self::new(&def.binary_path, &def.ir_json_path);
+        match &def.bare_metal_layout {
+            Some(layout_file) => {
+                let layout = BareMetalLayout::from_file(layout_file)
+                    .with_context(|| "Trying to parse bare metal layout")?;
+                Ok(source.with_bare_metal_layout(layout))
+            }
+            None => Ok(source),
+        }
+    }
+}
+
+impl ProjectSource for PcodeJsonSource {
+    fn binary_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        InferenceJob::parse_binary(&self.binary_path)
+    }
+
+    fn project(&self, binary: &[u8]) -> anyhow::Result<Project> {
+        if let Some(selected_bodies) = &self.selected_bodies {
+            return InferenceJob::parse_selected_project(
+                &self.ir_json_path,
+                binary,
+                selected_bodies,
+                self.bare_metal_layout.as_ref(),
+            );
+        }
+
+        match &self.bare_metal_layout {
+            Some(layout) => {
+                InferenceJob::parse_bare_metal_project(&self.ir_json_path, binary, layout)
+            }
+            None => InferenceJob::parse_project(&self.ir_json_path, binary),
+        }
+    }
+}
@@ -46,6 +46,12 @@ pub mod pb_constraints {
     include!(concat!(env!("OUT_DIR"), "/constraints.rs"));
 }
 
+/// Drives Ghidra headless to lift binaries to the Pcode json IR so a job can be run from a binary alone.
+pub mod ghidra;
+
+/// Collects the warnings of a run into a structured report of where precision was lost.
+pub mod diagnostics;
+
 /// Parses a context of file inputs into an inference job which can be run to retrieve generated constraints,
 /// simplified constraints, and lowered types.
 pub mod inference_job;
@@ -346,6 +352,7 @@ mod tests {
                     interesting_tids: Self::test_data_dir(
                         self.interesting_tids_file.expect("need initeresting tids"),
                     ),
+                    bare_metal_layout: None,
                 },
                 expected_outputs: ExpectedOutputFiles {
                     constraint_gen: self
@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cwe_checker_lib::intermediate_representation::Tid;
+use petgraph::{graph::NodeIndex, visit::EdgeRef, EdgeDirection};
+
+use crate::{
+    constraint_generation::tid_to_tvar,
+    constraints::{DerivedTypeVar, Field, FieldLabel, SubtypeConstraint},
+    ctypes::{self, CTypeMapping},
+    solver::type_sketch::SketchGraph,
+};
+
+use super::{convert_typeid, TypeId};
+
+/// The largest number of blocks a subprocedure can have and still be considered a getter or setter.
+pub const MAX_ACCESSOR_BLOCKS: usize = 4;
+
+/// A subprocedure that only loads or stores a single field of one of its pointer parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Accessor {
+    /// The accessing subprocedure
+    pub function: Tid,
+    /// The index of the pointer parameter
+    pub parameter: usize,
+    /// The accessed field
+    pub field: Field,
+    /// The name of the field taken from the name of the subprocedure
+    pub name: String,
+}
+
+/// The field named by a getter or setter, ie. `count` for `get_count`, `list_set_count`, or `List::get_count`. The
+/// prefix only names a field at the start of the name or after a `_` or `::` separator, so `reset_state` is not a
+/// setter.
+pub fn accessor_field_name(name: &str) -> Option<&str> {
+    ["get_", "set_"]
+        .iter()
+        .flat_map(|prefix| {
+            name.match_indices(prefix)
+                .filter(|(pos, _)| {
+                    let before = &name[..*pos];
+                    before.is_empty() || before.ends_with('_') || before.ends_with("::")
+                })
+                .map(move |(pos, _)| &name[pos + prefix.len()..])
+        })
+        .filter(|field| !field.is_empty())
+        .min_by_key(|field| field.len())
+}
+
+/// Finds the single field the signature of a subprocedure loads or stores through a parameter, if it accesses exactly one.
+pub fn accessed_field(
+    function: &Tid,
+    signature: &BTreeSet<SubtypeConstraint>,
+) -> Option<(usize, Field)> {
+    let base = tid_to_tvar(function);
+    let accessed = signature
+        .iter()
+        .flat_map(|cons| vec![&cons.lhs, &cons.rhs])
+        .filter(|dtv| dtv.get_base_variable() == &base)
+        .filter_map(|dtv| match dtv.get_field_labels() {
+            [FieldLabel::In(idx), FieldLabel::Load | FieldLabel::Store, FieldLabel::Field(fld), ..] => {
+                Some((*idx, fld.clone()))
+            }
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>();
+
+    if accessed.len() == 1 {
+        accessed.into_iter().next()
+    } else {
+        None
+    }
+}
+
+/// Finds the accessors among the simplified signatures of single subprocedure sccs. Subprocedures with more than
+/// [MAX_ACCESSOR_BLOCKS] blocks, as reported by block_count, are not considered accessors.
+pub fn find_accessors<'a>(
+    signatures: impl Iterator<Item = (&'a [Tid], &'a BTreeSet<SubtypeConstraint>)>,
+    names: &BTreeMap<Tid, String>,
+    block_count: impl Fn(&Tid) -> usize,
+) -> Vec<Accessor> {
+    signatures
+        .filter_map(|(scc, signature)| match scc {
+            [function] => Some((function, signature)),
+            _ => None,
+        })
+        .filter(|(function, _)| block_count(function) <= MAX_ACCESSOR_BLOCKS)
+        .filter_map(|(function, signature)| {
+            let name = accessor_field_name(names.get(function)?)?;
+            let (parameter, field) = accessed_field(function, signature)?;
+            Some(Accessor {
+                function: function.clone(),
+                parameter,
+                field,
+                name: name.to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// The names of fields keyed by the node of their structure in a sketch graph and their byte offset.
+pub type FieldNameTable = BTreeMap<NodeIndex, BTreeMap<i64, String>>;
+
+/// Finds the structure node each accessor accesses in a sketch graph and names the accessed field. When accessors
+/// disagree about the name of a field the name that sorts first is used, so the table is deterministic.
+pub fn build_field_name_table<U: std::cmp::PartialEq>(
+    grph: &SketchGraph<U>,
+    accessors: &[Accessor],
+) -> FieldNameTable {
+    let graph = grph.get_graph().get_graph();
+    let follow = |nd: NodeIndex, label: &FieldLabel| {
+        graph
+            .edges_directed(nd, EdgeDirection::Outgoing)
+            .find(|e| e.weight() == label)
+            .map(|e| e.target())
+    };
+
+    let mut table = FieldNameTable::new();
+    for accessor in accessors.iter() {
+        let structure = grph
+            .get_node_index_for_variable(&DerivedTypeVar::new(tid_to_tvar(&accessor.function)))
+            .and_then(|nd| follow(nd, &FieldLabel::In(accessor.parameter)))
+            .and_then(|param| {
+                [FieldLabel::Load, FieldLabel::Store]
+                    .iter()
+                    .filter_map(|label| follow(param, label))
+                    .find(|nd| follow(*nd, &FieldLabel::Field(accessor.field.clone())).is_some())
+            });
+
+        if let Some(structure) = structure {
+            let names = table.entry(structure).or_default();
+            let name = names
+                .entry(accessor.field.offset)
+                .or_insert_with(|| accessor.name.clone());
+            if accessor.name < *name {
+                *name = accessor.name.clone();
+            }
+        }
+    }
+    table
+}
+
+/// Names the fields of the lowered structures in the mapping from the table. Fields of nodes that were not lowered
+/// to a structure are skipped.
+pub fn annotate_field_names(
+    mapping: &mut CTypeMapping,
+    table: &FieldNameTable,
+    node_to_ty: &BTreeMap<NodeIndex, TypeId>,
+) -> anyhow::Result<()> {
+    for (nd, names) in table.iter() {
+        if let Some(ty) = node_to_ty.get(nd) {
+            let type_id = convert_typeid(*ty)?.type_id;
+            if let Some(ctypes::c_type::InnerType::Structure(structure)) = mapping
+                .type_id_to_ctype
+                .get_mut(&type_id)
+                .and_then(|ct| ct.inner_type.as_mut())
+            {
+                for fld in structure.fields.iter_mut() {
+                    if let Some(name) = names.get(&i64::from(fld.byte_offset)) {
+                        fld.name = name.clone();
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use crate::constraints::{parse_constraint_set, Field, TyConstraint};
+
+    use super::{accessor_field_name, find_accessors};
+
+    #[test]
+    fn accessors_name_their_single_field() {
+        assert_eq!(accessor_field_name("list_get_count"), Some("count"));
+        assert_eq!(accessor_field_name("set_"), None);
+        assert_eq!(accessor_field_name("count"), None);
+        assert_eq!(accessor_field_name("List::set_count"), Some("count"));
+        assert_eq!(accessor_field_name("reset_state"), None);
+        assert_eq!(accessor_field_name("offset_table"), None);
+        assert_eq!(accessor_field_name("budget_count"), None);
+        assert_eq!(accessor_field_name("reset_get_state"), Some("state"));
+
+        let signature = |text: &str| {
+            parse_constraint_set(text)
+                .unwrap()
+                .1
+                .iter()
+                .filter_map(|cons| match cons {
+                    TyConstraint::SubTy(sub) => Some(sub.clone()),
+                    _ => None,
+                })
+                .collect::<BTreeSet<_>>()
+        };
+        let getter = Tid::create("sub_getter".to_owned(), "1000".to_owned());
+        let other = Tid::create("sub_other".to_owned(), "2000".to_owned());
+        let getter_sig = signature("sub_getter.in_0.load.σ32@8 <= sub_getter.out");
+        let other_sig = signature(
+            "sub_other.in_0.load.σ32@8 <= sub_other.out\nsub_other.in_0.load.σ32@0 <= sub_other.out",
+        );
+        let (getter_scc, other_scc) = (vec![getter.clone()], vec![other.clone()]);
+        let names = BTreeMap::from([
+            (getter.clone(), "get_count".to_owned()),
+            (other.clone(), "get_size".to_owned()),
+        ]);
+
+        let accessors = find_accessors(
+            vec![
+                (getter_scc.as_slice(), &getter_sig),
+                (other_scc.as_slice(), &other_sig),
+            ]
+            .into_iter(),
+            &names,
+            |_| 1,
+        );
+        assert_eq!(accessors.len(), 1);
+        assert_eq!(accessors[0].function, getter);
+        assert_eq!(accessors[0].parameter, 0);
+        assert_eq!(accessors[0].field, Field::new(8, 32));
+        assert_eq!(accessors[0].name, "count");
+    }
+}
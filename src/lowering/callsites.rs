@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+
+use cwe_checker_lib::intermediate_representation::{Jmp, Program, Tid};
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constraint_generation::tid_to_tvar,
+    constraints::TypeVariable,
+    ctypes::{self, CTypeMapping},
+    solver::type_sketch::SketchGraph,
+};
+
+use super::{convert_typeid, TypeId};
+
+/// A call of a subprocedure or extern symbol.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Callsite {
+    /// The subprocedure containing the call
+    pub caller: Tid,
+    /// The call term, whose address is the address of the callsite
+    pub call: Tid,
+    /// The called subprocedure or extern symbol
+    pub callee: Tid,
+}
+
+/// Finds the calls in a program keyed the way the variables of a callee are tagged at a callsite: by the block
+/// containing the call and the variable of the callee.
+pub fn collect_callsites(program: &Program) -> BTreeMap<(Tid, TypeVariable), Callsite> {
+    let mut callsites = BTreeMap::new();
+    for (caller, sub) in program.subs.iter() {
+        for blk in sub.term.blocks.iter() {
+            for jmp in blk.term.jmps.iter() {
+                if let Jmp::Call { target, .. } = &jmp.term {
+                    callsites.insert(
+                        (blk.tid.clone(), tid_to_tvar(target)),
+                        Callsite {
+                            caller: caller.clone(),
+                            call: jmp.tid.clone(),
+                            callee: target.clone(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+    callsites
+}
+
+/// Finds the node representing the type of the callee at each callsite in a sketch graph whose callsite variables are
+/// labeled, see [crate::solver::type_sketch::SCCSketchsBuilder::with_callsite_labels].
+pub fn find_callsite_nodes<U: std::cmp::PartialEq>(
+    grph: &SketchGraph<U>,
+    callsites: &BTreeMap<(Tid, TypeVariable), Callsite>,
+) -> BTreeMap<Callsite, NodeIndex> {
+    grph.get_graph()
+        .get_node_mapping()
+        .iter()
+        .filter(|(dtv, _)| dtv.get_field_labels().is_empty())
+        .filter_map(|(dtv, idx)| {
+            let tag = dtv.get_base_variable().get_cs_tag().as_ref()?;
+            callsites
+                .get(&(tag.clone(), dtv.get_base_variable().to_callee()))
+                .map(|callsite| (callsite.clone(), *idx))
+        })
+        .collect()
+}
+
+pub(crate) fn tid_to_protobuf(tid: &Tid) -> ctypes::Tid {
+    ctypes::Tid {
+        name: tid.get_str_repr().to_owned(),
+        address: tid.address.clone(),
+    }
+}
+
+/// Records the type of the callee at each callsite in the mapping. Callsites whose node was not lowered are skipped.
+/// Fails if a type id cannot be converted.
+pub fn annotate_callsite_signatures(
+    mapping: &mut CTypeMapping,
+    callsite_nodes: &BTreeMap<Callsite, NodeIndex>,
+    node_to_ty: &BTreeMap<NodeIndex, TypeId>,
+) -> anyhow::Result<()> {
+    for (callsite, nd) in callsite_nodes.iter() {
+        if let Some(ty) = node_to_ty.get(nd) {
+            mapping.callsite_signatures.push(ctypes::CallsiteSignature {
+                caller: Some(tid_to_protobuf(&callsite.caller)),
+                call: Some(tid_to_protobuf(&callsite.call)),
+                callee: Some(tid_to_protobuf(&callsite.callee)),
+                type_id: Some(convert_typeid(*ty)?),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use crate::{
+        constraint_generation::tid_to_tvar,
+        constraints::{DerivedTypeVar, TypeVariable},
+        test_utils::sketches::{int_lattice, sketch_from_json},
+    };
+
+    use super::{find_callsite_nodes, Callsite};
+
+    #[test]
+    fn callsite_variables_are_found_by_block_and_callee() {
+        let lattice = int_lattice();
+        let callee = Tid::create("sub_id".to_owned(), "1000".to_owned());
+        let blk = Tid::create("blk_1".to_owned(), "1004".to_owned());
+        let at_callsite = DerivedTypeVar::new(TypeVariable::with_tag(
+            callee.get_str_repr().to_owned(),
+            blk.clone(),
+        ));
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","top"],["bottom","int"]]"#,
+            r#"[[0,2,{"In":0}],[1,2,{"In":0}]]"#,
+            &[
+                (DerivedTypeVar::new(tid_to_tvar(&callee)), 0),
+                (at_callsite, 1),
+            ],
+        );
+
+        let callsite = Callsite {
+            caller: Tid::create("sub_main".to_owned(), "2000".to_owned()),
+            call: Tid::create("call_1".to_owned(), "1008".to_owned()),
+            callee: callee.clone(),
+        };
+        let callsites = BTreeMap::from([((blk, tid_to_tvar(&callee)), callsite.clone())]);
+
+        let found = find_callsite_nodes(&grph, &callsites);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[&callsite].index(), 1);
+    }
+}
@@ -0,0 +1,461 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::Display,
+};
+
+use cwe_checker_lib::intermediate_representation::Tid;
+use itertools::Itertools;
+use petgraph::{graph::NodeIndex, visit::EdgeRef, EdgeDirection};
+use serde::{Deserialize, Serialize};
+
+use super::primitives::{sized_primitive_name, PrimitiveTable};
+use crate::{
+    constraints::FieldLabel,
+    solver::{
+        type_lattice::NamedLatticeElement,
+        type_sketch::{LatticeBounds, SketchGraph},
+    },
+};
+
+/// How lowering resolves a node whose evidence conflicts. Conflicts are reported by [find_lattice_conflicts] under
+/// every strategy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictStrategy {
+    /// Lower to whichever of the pointer or primitive has more evidence, preferring the pointer on ties
+    PreferMostEvidence,
+    /// Lower to the pointer whenever a node can be loaded from or stored to
+    PreferPointer,
+    /// Lower a leaf with clashing bounds to a union of its bounds other than bottom so that the conflict stays visible
+    /// in the lowered types, and lower other nodes by their capabilities
+    ReportConflict,
+}
+
+impl Default for ConflictStrategy {
+    fn default() -> Self {
+        ConflictStrategy::PreferPointer
+    }
+}
+
+impl std::str::FromStr for ConflictStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prefer-most-evidence" => Ok(ConflictStrategy::PreferMostEvidence),
+            "prefer-pointer" => Ok(ConflictStrategy::PreferPointer),
+            "report-conflict" => Ok(ConflictStrategy::ReportConflict),
+            _ => Err(anyhow::anyhow!(
+                "unknown conflict strategy {}, expected prefer-most-evidence, prefer-pointer or report-conflict",
+                s
+            )),
+        }
+    }
+}
+
+/// The kind of evidence that conflicts at a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    /// The lower bound is not below the upper bound, or the values flowing in or the uses of the node have no common
+    /// lattice element so their bound degraded to top or bottom
+    Bounds,
+    /// The node is loaded from or stored to but also has a primitive lattice bound
+    PrimitivePointer,
+}
+
+/// A node whose lattice bounds or capabilities disagree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatticeConflict {
+    /// The conflicting node
+    pub node: NodeIndex,
+    /// The variables represented by the node
+    pub variables: Vec<String>,
+    /// What conflicts
+    pub kind: ConflictKind,
+    /// The name of the lower bound of the node
+    pub lower: String,
+    /// The name of the upper bound of the node
+    pub upper: String,
+    /// The number of load and store capabilities of the node and of the capabilities of their targets
+    pub pointer_evidence: usize,
+    /// The number of bounds of the node that are not trivial
+    pub primitive_evidence: usize,
+}
+
+impl LatticeConflict {
+    /// Whether the node of a [ConflictKind::PrimitivePointer] conflict is lowered as a primitive rather than a pointer
+    /// under a strategy.
+    pub fn prefers_primitive(&self, strategy: ConflictStrategy) -> bool {
+        match strategy {
+            ConflictStrategy::PreferMostEvidence => {
+                self.kind == ConflictKind::PrimitivePointer
+                    && self.primitive_evidence > self.pointer_evidence
+            }
+            ConflictStrategy::PreferPointer | ConflictStrategy::ReportConflict => false,
+        }
+    }
+}
+
+fn pointer_evidence<U: NamedLatticeElement>(
+    grph: &SketchGraph<LatticeBounds<U>>,
+    nd: NodeIndex,
+) -> usize {
+    let grph = grph.get_graph().get_graph();
+    grph.edges_directed(nd, EdgeDirection::Outgoing)
+        .filter(|e| matches!(e.weight(), FieldLabel::Load | FieldLabel::Store))
+        .map(|e| {
+            1 + grph
+                .edges_directed(e.target(), EdgeDirection::Outgoing)
+                .count()
+        })
+        .sum()
+}
+
+/// Finds the nodes of a sketch graph whose evidence conflicts. A conflict is found when the bounds of a node clash or
+/// degraded, or when a node with a primitive bound can also be loaded from or stored to.
+pub fn find_lattice_conflicts<U: NamedLatticeElement>(
+    grph: &SketchGraph<LatticeBounds<U>>,
+) -> BTreeMap<NodeIndex, LatticeConflict> {
+    let act_graph = grph.get_graph().get_graph();
+    act_graph
+        .node_indices()
+        .filter_map(|nd| {
+            let bounds = &act_graph[nd];
+            let (lower, upper) = (bounds.get_lower(), bounds.get_upper());
+            let primitive_evidence = usize::from(!lower.is_bot()) + usize::from(!upper.is_top());
+            let pointer_evidence = pointer_evidence(grph, nd);
+
+            let ordered = matches!(
+                lower.partial_cmp(upper),
+                Some(Ordering::Less | Ordering::Equal)
+            );
+            let kind = if !ordered || lower.is_top() || upper.is_bot() {
+                ConflictKind::Bounds
+            } else if pointer_evidence > 0 && primitive_evidence > 0 {
+                ConflictKind::PrimitivePointer
+            } else {
+                return None;
+            };
+
+            Some((
+                nd,
+                LatticeConflict {
+                    node: nd,
+                    variables: grph
+                        .get_graph()
+                        .get_group_for_node(nd)
+                        .into_iter()
+                        .map(|dtv| dtv.to_string())
+                        .collect(),
+                    kind,
+                    lower: lower.get_name().to_owned(),
+                    upper: upper.get_name().to_owned(),
+                    pointer_evidence,
+                    primitive_evidence,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// A node whose lattice bound is spelled as a primitive of a different size than the accesses to the node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeMismatch {
+    /// The node whose size disagrees
+    pub node: NodeIndex,
+    /// The variables represented by the node
+    pub variables: Vec<String>,
+    /// The lattice element the node is bounded by
+    pub lattice_elem: String,
+    /// The size in bytes of the primitive the lattice element is spelled as
+    pub primitive_size: usize,
+    /// The size in bytes of every access to the node
+    pub access_size: usize,
+    /// The accesses the size was observed on, as a variable of the accessing node followed by the access label
+    pub accesses: Vec<String>,
+    /// The primitive of the access size that the node is lowered to, signed, unsigned, or floating point like the
+    /// primitive of the lattice element
+    pub lowered_to: String,
+    /// The nearest term whose variable reaches the node, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<Tid>,
+}
+
+impl Display for SizeMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is bounded by {} of {} bytes but accessed with {} bytes by {}, lowered to {}",
+            self.variables.join(", "),
+            self.lattice_elem,
+            self.primitive_size,
+            self.access_size,
+            self.accesses.join(", "),
+            self.lowered_to
+        )?;
+        if let Some(origin) = &self.origin {
+            write!(f, " (reached from {})", origin)?;
+        }
+        Ok(())
+    }
+}
+
+/// A function whose sketch has in-parameters beyond the formal arguments declared for it in the IR, ie. callers pass
+/// more arguments than the function was lifted with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParameterCountMismatch {
+    /// The node of the function
+    pub node: NodeIndex,
+    /// The variables represented by the node
+    pub variables: Vec<String>,
+    /// The number of formal arguments declared in the IR
+    pub declared: usize,
+    /// The indices of the in-parameters observed beyond the declared formal arguments
+    pub extra: Vec<usize>,
+}
+
+impl ParameterCountMismatch {
+    /// The number of parameters of the function once its signature is extended by the observed in-parameters.
+    pub fn extended_count(&self) -> usize {
+        self.extra
+            .iter()
+            .max()
+            .map(|idx| idx + 1)
+            .unwrap_or(self.declared)
+    }
+}
+
+impl Display for ParameterCountMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} declares {} parameters but has evidence for parameters {}, extended to {} parameters",
+            self.variables.join(", "),
+            self.declared,
+            self.extra.iter().map(|idx| idx.to_string()).join(", "),
+            self.extended_count()
+        )
+    }
+}
+
+fn node_name<U>(grph: &SketchGraph<U>, nd: NodeIndex) -> String {
+    grph.get_graph()
+        .get_group_for_node(nd)
+        .into_iter()
+        .next()
+        .map(|dtv| dtv.to_string())
+        .unwrap_or_else(|| format!("node {}", nd.index()))
+}
+
+// The term of the labeled node closest to nd along incoming edges.
+fn nearest_labeled_term<U>(
+    grph: &SketchGraph<U>,
+    node_to_tid: &BTreeMap<NodeIndex, &Tid>,
+    nd: NodeIndex,
+) -> Option<Tid> {
+    let act_graph = grph.get_graph().get_graph();
+    let mut seen = BTreeSet::from([nd]);
+    let mut worklist = VecDeque::from([nd]);
+    while let Some(curr) = worklist.pop_front() {
+        if let Some(tid) = node_to_tid.get(&curr) {
+            return Some((*tid).clone());
+        }
+        for pred in act_graph.neighbors_directed(curr, EdgeDirection::Incoming) {
+            if seen.insert(pred) {
+                worklist.push_back(pred);
+            }
+        }
+    }
+    None
+}
+
+/// Finds the leaves of a sketch graph whose upper bound is spelled as a primitive of a different size than the fields
+/// and elements the leaf is accessed as. Leaves accessed with several sizes, or with sizes that are not whole bytes or
+/// have no primitive of the kind of the bound, are left alone. Each mismatch is tagged with the nearest term of
+/// tid_to_node_index whose node reaches the leaf.
+pub fn find_size_mismatches<U: NamedLatticeElement>(
+    grph: &SketchGraph<LatticeBounds<U>>,
+    primitives: &PrimitiveTable,
+    tid_to_node_index: &BTreeMap<Tid, NodeIndex>,
+) -> BTreeMap<NodeIndex, SizeMismatch> {
+    let act_graph = grph.get_graph().get_graph();
+    let mut node_to_tid = BTreeMap::new();
+    for (tid, nd) in tid_to_node_index.iter() {
+        node_to_tid.entry(*nd).or_insert(tid);
+    }
+    act_graph
+        .node_indices()
+        .filter(|nd| {
+            act_graph
+                .edges_directed(*nd, EdgeDirection::Outgoing)
+                .next()
+                .is_none()
+        })
+        .filter_map(|nd| {
+            let lattice_elem = act_graph[nd].get_upper().get_name();
+            let primitive_size = primitives.get_byte_size(lattice_elem)?;
+
+            let accesses = act_graph
+                .edges_directed(nd, EdgeDirection::Incoming)
+                .filter_map(|e| match e.weight() {
+                    FieldLabel::Field(fld) => Some((fld.size, e)),
+                    FieldLabel::Element(size) => Some((*size, e)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let bit_size = accesses
+                .iter()
+                .map(|(size, _)| *size)
+                .sorted()
+                .dedup()
+                .exactly_one()
+                .ok()?;
+            if bit_size % 8 != 0 || bit_size / 8 == primitive_size {
+                return None;
+            }
+            let access_size = bit_size / 8;
+            let kind = primitives.get_kind(lattice_elem);
+            let lowered_to = sized_primitive_name(access_size, kind)?.to_owned();
+
+            Some((
+                nd,
+                SizeMismatch {
+                    node: nd,
+                    variables: grph
+                        .get_graph()
+                        .get_group_for_node(nd)
+                        .into_iter()
+                        .map(|dtv| dtv.to_string())
+                        .collect(),
+                    lattice_elem: lattice_elem.to_owned(),
+                    primitive_size,
+                    access_size,
+                    accesses: accesses
+                        .iter()
+                        .map(|(_, e)| format!("{}.{}", node_name(grph, e.source()), e.weight()))
+                        .collect(),
+                    lowered_to,
+                    origin: nearest_labeled_term(grph, &node_to_tid, nd),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Finds the functions whose sketch has in-parameters at indices beyond the number of formal arguments declared for the
+/// function, given the declared count of each function node.
+pub fn find_parameter_count_mismatches<U>(
+    grph: &SketchGraph<U>,
+    declared: &BTreeMap<NodeIndex, usize>,
+) -> BTreeMap<NodeIndex, ParameterCountMismatch> {
+    let act_graph = grph.get_graph().get_graph();
+    declared
+        .iter()
+        .filter_map(|(nd, declared)| {
+            let extra = act_graph
+                .edges_directed(*nd, EdgeDirection::Outgoing)
+                .filter_map(|e| match e.weight() {
+                    FieldLabel::In(idx) if idx >= declared => Some(*idx),
+                    _ => None,
+                })
+                .sorted()
+                .dedup()
+                .collect::<Vec<_>>();
+            if extra.is_empty() {
+                return None;
+            }
+
+            Some((
+                *nd,
+                ParameterCountMismatch {
+                    node: *nd,
+                    variables: grph
+                        .get_graph()
+                        .get_group_for_node(*nd)
+                        .into_iter()
+                        .map(|dtv| dtv.to_string())
+                        .collect(),
+                    declared: *declared,
+                    extra,
+                },
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+    use petgraph::graph::NodeIndex;
+
+    use crate::{
+        lowering::primitives::PrimitiveTable,
+        test_utils::sketches::{flat_lattice, sketch_from_json, var},
+    };
+
+    use super::{find_lattice_conflicts, find_size_mismatches, ConflictKind, ConflictStrategy};
+
+    #[test]
+    fn conflicts_are_found_and_resolved_by_strategy() {
+        let lattice = flat_lattice(&["int", "float"]);
+        // x is bounded by int but loaded from, y has an int flowing in and is used as a float
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["int","int"],["bottom","top"],["int","float"]]"#,
+            r#"[[0,1,"Load"]]"#,
+            &[(var("x"), 0), (var("y"), 2)],
+        );
+
+        let conflicts = find_lattice_conflicts(&grph);
+        assert_eq!(conflicts.len(), 2);
+
+        let x = &conflicts[&NodeIndex::new(0)];
+        assert_eq!(x.kind, ConflictKind::PrimitivePointer);
+        assert_eq!(x.variables, vec!["x".to_owned()]);
+        assert_eq!((x.pointer_evidence, x.primitive_evidence), (1, 2));
+        assert!(x.prefers_primitive(ConflictStrategy::PreferMostEvidence));
+        assert!(!x.prefers_primitive(ConflictStrategy::PreferPointer));
+
+        let y = &conflicts[&NodeIndex::new(2)];
+        assert_eq!(y.kind, ConflictKind::Bounds);
+        assert_eq!((y.lower.as_str(), y.upper.as_str()), ("int", "float"));
+
+        assert_eq!(
+            "report-conflict".parse::<ConflictStrategy>().unwrap(),
+            ConflictStrategy::ReportConflict
+        );
+    }
+
+    #[test]
+    fn leaves_accessed_with_another_size_are_found() {
+        let lattice = flat_lattice(&["int", "uint"]);
+        // x points to a byte bounded by int, to a 4 byte int, and to a 2 byte field bounded by uint
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","top"],["bottom","int"],["bottom","int"],["bottom","uint"]]"#,
+            r#"[[0,1,"Load"],[1,2,{"Field":{"offset":0,"size":8}}],[1,3,{"Field":{"offset":4,"size":32}}],[1,4,{"Field":{"offset":8,"size":16}}]]"#,
+            &[(var("x"), 0)],
+        );
+
+        let x = Tid::create("x".to_owned(), "0x1000".to_owned());
+        let mismatches = find_size_mismatches(
+            &grph,
+            &PrimitiveTable::default(),
+            &BTreeMap::from([(x.clone(), NodeIndex::new(0))]),
+        );
+        assert_eq!(mismatches.len(), 2);
+        let byte = &mismatches[&NodeIndex::new(2)];
+        assert_eq!((byte.primitive_size, byte.access_size), (4, 1));
+        assert_eq!(byte.lowered_to, "int8_t");
+        assert_eq!(byte.accesses, vec!["node 1.σ8@0".to_owned()]);
+        assert_eq!(byte.origin, Some(x.clone()));
+
+        let short = &mismatches[&NodeIndex::new(4)];
+        assert_eq!(short.lowered_to, "uint16_t");
+        assert_eq!(short.origin, Some(x));
+    }
+}
@@ -0,0 +1,435 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use cwe_checker_lib::intermediate_representation::Tid;
+use petgraph::{graph::NodeIndex, visit::EdgeRef};
+use serde::Serialize;
+
+use crate::solver::type_sketch::SketchGraph;
+
+use super::{ida::aggregate_body, naming::TypeNamer, CType, TypeId};
+
+// The placeholder in the page template that is replaced with the report data
+const DATA_PLACEHOLDER: &str = "__BTI_REPORT_DATA__";
+
+#[derive(Serialize)]
+struct ReportNode {
+    id: usize,
+    bounds: String,
+    vars: Vec<String>,
+    type_id: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ReportEdge {
+    source: usize,
+    target: usize,
+    label: String,
+}
+
+#[derive(Serialize)]
+struct ReportParam {
+    index: usize,
+    type_id: usize,
+    type_name: String,
+}
+
+#[derive(Serialize)]
+struct ReportFunction {
+    name: String,
+    address: String,
+    node: usize,
+    prototype: Option<String>,
+    params: Vec<ReportParam>,
+    return_type: Option<ReportParam>,
+}
+
+#[derive(Serialize)]
+struct ReportType {
+    name: String,
+    kind: &'static str,
+    declaration: String,
+    references: Vec<usize>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    nodes: Vec<ReportNode>,
+    edges: Vec<ReportEdge>,
+    functions: Vec<ReportFunction>,
+    types: BTreeMap<usize, ReportType>,
+}
+
+fn kind_name(ct: &CType) -> &'static str {
+    match ct {
+        CType::Primitive(_) => "primitive",
+        CType::Pointer { .. } => "pointer",
+        CType::Alias(_) => "alias",
+        CType::Structure(_) => "struct",
+        CType::Function { .. } => "function",
+        CType::Union(_) => "union",
+        CType::Array { .. } => "array",
+    }
+}
+
+fn report_type(namer: &TypeNamer, ty: TypeId, ct: &CType) -> ReportType {
+    let name = namer
+        .declared_name(ty)
+        .unwrap_or_else(|| namer.type_name(ty));
+    let declaration = match ct {
+        CType::Structure(_) => aggregate_body(namer, "struct", &name, ct),
+        CType::Union(_) => aggregate_body(namer, "union", &name, ct),
+        CType::Function { .. } => namer
+            .function_prototype(ty, &name, "?")
+            .unwrap_or_else(|| name.clone()),
+        _ => namer.type_name(ty),
+    };
+
+    ReportType {
+        name,
+        kind: kind_name(ct),
+        declaration,
+        references: ct
+            .referenced_type_ids()
+            .into_iter()
+            .filter_map(|referenced| namer.resolve(referenced))
+            .map(|resolved| resolved.0)
+            .collect(),
+    }
+}
+
+fn report_function(
+    namer: &TypeNamer,
+    tid: &Tid,
+    node: NodeIndex,
+    ty: Option<TypeId>,
+) -> ReportFunction {
+    let as_param = |index: usize, type_id: TypeId| {
+        namer.resolve(type_id).map(|resolved| ReportParam {
+            index,
+            type_id: resolved.0,
+            type_name: namer.type_name(type_id),
+        })
+    };
+
+    let (params, return_type) = match ty.and_then(|ty| namer.get_resolved_type(ty)) {
+        Some(CType::Function { params, return_ty }) => {
+            let mut params = params
+                .iter()
+                .filter_map(|p| as_param(p.index, p.type_index))
+                .collect::<Vec<_>>();
+            params.sort_by_key(|p| p.index);
+            (params, return_ty.and_then(|ret| as_param(0, ret)))
+        }
+        _ => (Vec::new(), None),
+    };
+
+    ReportFunction {
+        name: tid.get_str_repr().to_owned(),
+        address: tid.address.clone(),
+        node: node.index(),
+        prototype: ty.and_then(|ty| namer.function_prototype(ty, tid.get_str_repr(), "?")),
+        params,
+        return_type,
+    }
+}
+
+/// Builds a self-contained HTML page for exploring a sketch graph and the types lowered from it. Functions can be searched by name,
+/// their parameters link to the layouts of their types, and the neighbourhood of the selected node is drawn as a force directed graph.
+/// The page embeds its data and script so it can be opened without network access. Declared types are named by their structure
+/// if structural_names is set.
+pub fn build_html_report<U: Display + PartialEq>(
+    sg: &SketchGraph<U>,
+    types: &BTreeMap<TypeId, CType>,
+    node_to_ty: &BTreeMap<NodeIndex, TypeId>,
+    tid_to_node: &BTreeMap<Tid, NodeIndex>,
+    structural_names: bool,
+) -> String {
+    let namer = TypeNamer::new(types, node_to_ty).with_structural_names(structural_names);
+    let grph = sg.get_graph().get_graph();
+
+    let nodes = grph
+        .node_indices()
+        .map(|idx| ReportNode {
+            id: idx.index(),
+            bounds: grph[idx].to_string(),
+            vars: sg
+                .get_graph()
+                .get_group_for_node(idx)
+                .iter()
+                .map(|dtv| dtv.to_string())
+                .collect(),
+            type_id: node_to_ty
+                .get(&idx)
+                .and_then(|ty| namer.resolve(*ty))
+                .map(|ty| ty.0),
+        })
+        .collect();
+
+    let edges = grph
+        .edge_references()
+        .map(|e| ReportEdge {
+            source: e.source().index(),
+            target: e.target().index(),
+            label: e.weight().to_string(),
+        })
+        .collect();
+
+    let mut functions = tid_to_node
+        .iter()
+        .map(|(tid, nd)| report_function(&namer, tid, *nd, node_to_ty.get(nd).cloned()))
+        .collect::<Vec<_>>();
+    functions.sort_by(|x, y| x.name.cmp(&y.name));
+
+    let report = Report {
+        nodes,
+        edges,
+        functions,
+        types: types
+            .iter()
+            .filter(|(_, ct)| !matches!(ct, CType::Alias(_)))
+            .map(|(ty, ct)| (ty.0, report_type(&namer, *ty, ct)))
+            .collect(),
+    };
+
+    embed_report(&report)
+}
+
+fn embed_report(report: &Report) -> String {
+    // the data is embedded in a script tag so it must not be able to close the tag
+    let data = serde_json::to_string(report)
+        .expect("reports are serializable")
+        .replace("</", "<\\/");
+    REPORT_TEMPLATE.replace(DATA_PLACEHOLDER, &data)
+}
+
+const REPORT_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Type inference report</title>
+<style>
+body { margin: 0; display: flex; height: 100vh; font-family: sans-serif; font-size: 13px; }
+#side { width: 420px; overflow: auto; border-right: 1px solid #ccc; padding: 8px; box-sizing: border-box; }
+#main { flex: 1; position: relative; }
+#graph { width: 100%; height: 100%; display: block; }
+#search { width: 100%; box-sizing: border-box; margin-bottom: 6px; }
+.fn, .link { cursor: pointer; color: #0645ad; }
+.fn:hover, .link:hover { text-decoration: underline; }
+pre { background: #f6f6f6; padding: 6px; white-space: pre-wrap; }
+#hover { position: absolute; top: 6px; left: 6px; background: rgba(255,255,255,0.9); max-width: 60%; }
+</style>
+</head>
+<body>
+<div id="side">
+<input id="search" placeholder="search functions">
+<div id="functions"></div>
+<div id="details"></div>
+</div>
+<div id="main"><canvas id="graph"></canvas><pre id="hover"></pre></div>
+<script id="data" type="application/json">__BTI_REPORT_DATA__</script>
+<script>
+"use strict";
+const data = JSON.parse(document.getElementById("data").textContent);
+const nodesById = new Map(data.nodes.map(n => [n.id, n]));
+const outEdges = new Map();
+for (const e of data.edges) {
+  if (!outEdges.has(e.source)) outEdges.set(e.source, []);
+  outEdges.get(e.source).push(e);
+}
+const MAX_DRAWN = 250;
+const canvas = document.getElementById("graph");
+const ctx = canvas.getContext("2d");
+let layout = { nodes: [], edges: [], selected: null };
+
+function el(tag, text, cls, onclick) {
+  const e = document.createElement(tag);
+  if (text !== undefined) e.textContent = text;
+  if (cls) e.className = cls;
+  if (onclick) e.onclick = onclick;
+  return e;
+}
+
+function listFunctions() {
+  const query = document.getElementById("search").value.toLowerCase();
+  const list = document.getElementById("functions");
+  list.innerHTML = "";
+  data.functions.filter(f => f.name.toLowerCase().includes(query)).slice(0, 200)
+    .forEach(f => list.appendChild(el("div", f.name + " @ " + f.address, "fn", () => showFunction(f))));
+}
+
+function typeLink(label, typeId) {
+  return el("span", label, "link", () => showType(typeId));
+}
+
+function showFunction(f) {
+  const details = document.getElementById("details");
+  details.innerHTML = "";
+  details.appendChild(el("h3", f.name));
+  details.appendChild(el("pre", f.prototype || "no function type"));
+  for (const p of f.params) {
+    const row = el("div", "param " + p.index + ": ");
+    row.appendChild(typeLink(p.type_name, p.type_id));
+    details.appendChild(row);
+  }
+  if (f.return_type) {
+    const row = el("div", "returns: ");
+    row.appendChild(typeLink(f.return_type.type_name, f.return_type.type_id));
+    details.appendChild(row);
+  }
+  drawAround(f.node);
+}
+
+function showType(typeId) {
+  const ty = data.types[typeId];
+  if (!ty) return;
+  const details = document.getElementById("details");
+  const section = el("div");
+  section.appendChild(el("h4", ty.kind + " " + ty.name));
+  section.appendChild(el("pre", ty.declaration));
+  for (const r of ty.references) {
+    if (data.types[r]) {
+      const row = el("div", "references ");
+      row.appendChild(typeLink(data.types[r].name, r));
+      section.appendChild(row);
+    }
+  }
+  details.appendChild(section);
+  section.scrollIntoView();
+  const nd = data.nodes.find(n => n.type_id === typeId);
+  if (nd) drawAround(nd.id);
+}
+
+// breadth first neighbourhood of the root, bounded so that the layout stays responsive
+function drawAround(root) {
+  const seen = new Map([[root, 0]]);
+  const queue = [root];
+  while (queue.length && seen.size < MAX_DRAWN) {
+    const curr = queue.shift();
+    for (const e of outEdges.get(curr) || []) {
+      if (!seen.has(e.target) && seen.size < MAX_DRAWN) {
+        seen.set(e.target, seen.get(curr) + 1);
+        queue.push(e.target);
+      }
+    }
+  }
+  const nodes = [...seen.keys()].map((id, i) => ({
+    id, x: Math.cos(i) * (20 + 8 * i), y: Math.sin(i) * (20 + 8 * i), vx: 0, vy: 0
+  }));
+  const index = new Map(nodes.map((n, i) => [n.id, i]));
+  const edges = data.edges.filter(e => index.has(e.source) && index.has(e.target))
+    .map(e => ({ s: index.get(e.source), t: index.get(e.target), label: e.label }));
+  layout = { nodes, edges, selected: root };
+  for (let i = 0; i < 300; i++) step();
+  draw();
+}
+
+function step() {
+  const ns = layout.nodes;
+  for (let i = 0; i < ns.length; i++) {
+    for (let j = i + 1; j < ns.length; j++) {
+      const dx = ns[j].x - ns[i].x, dy = ns[j].y - ns[i].y;
+      const d2 = Math.max(dx * dx + dy * dy, 1);
+      const f = 800 / d2;
+      ns[i].vx -= f * dx; ns[i].vy -= f * dy;
+      ns[j].vx += f * dx; ns[j].vy += f * dy;
+    }
+  }
+  for (const e of layout.edges) {
+    const a = ns[e.s], b = ns[e.t];
+    const dx = b.x - a.x, dy = b.y - a.y;
+    a.vx += dx * 0.01; a.vy += dy * 0.01;
+    b.vx -= dx * 0.01; b.vy -= dy * 0.01;
+  }
+  for (const n of ns) {
+    n.vx -= n.x * 0.002; n.vy -= n.y * 0.002;
+    n.x += n.vx * 0.5; n.y += n.vy * 0.5;
+    n.vx *= 0.6; n.vy *= 0.6;
+  }
+}
+
+function toScreen(n) {
+  return [n.x + canvas.width / 2, n.y + canvas.height / 2];
+}
+
+function draw() {
+  canvas.width = canvas.clientWidth;
+  canvas.height = canvas.clientHeight;
+  ctx.clearRect(0, 0, canvas.width, canvas.height);
+  ctx.strokeStyle = "#999";
+  ctx.fillStyle = "#555";
+  ctx.font = "10px sans-serif";
+  for (const e of layout.edges) {
+    const [x1, y1] = toScreen(layout.nodes[e.s]);
+    const [x2, y2] = toScreen(layout.nodes[e.t]);
+    ctx.beginPath(); ctx.moveTo(x1, y1); ctx.lineTo(x2, y2); ctx.stroke();
+    ctx.fillText(e.label, (x1 + x2) / 2, (y1 + y2) / 2);
+  }
+  for (const n of layout.nodes) {
+    const [x, y] = toScreen(n);
+    const info = nodesById.get(n.id);
+    ctx.fillStyle = n.id === layout.selected ? "#d33" : (info.type_id !== null && data.types[info.type_id] &&
+      ["struct", "union"].includes(data.types[info.type_id].kind) ? "#36c" : "#888");
+    ctx.beginPath(); ctx.arc(x, y, 6, 0, 2 * Math.PI); ctx.fill();
+  }
+}
+
+canvas.onmousemove = ev => {
+  const r = canvas.getBoundingClientRect();
+  const mx = ev.clientX - r.left, my = ev.clientY - r.top;
+  const hit = layout.nodes.find(n => {
+    const [x, y] = toScreen(n);
+    return (x - mx) ** 2 + (y - my) ** 2 < 64;
+  });
+  const hover = document.getElementById("hover");
+  if (!hit) { hover.textContent = ""; return; }
+  const info = nodesById.get(hit.id);
+  const ty = info.type_id !== null ? data.types[info.type_id] : null;
+  hover.textContent = "node " + info.id + " " + info.bounds + "\n" + info.vars.slice(0, 8).join("\n") +
+    (ty ? "\n" + ty.declaration : "");
+};
+
+canvas.onclick = () => {
+  const hover = document.getElementById("hover");
+  const hit = layout.nodes.find(n => hover.textContent.startsWith("node " + n.id + " "));
+  if (hit) {
+    const info = nodesById.get(hit.id);
+    if (info.type_id !== null) showType(info.type_id); else drawAround(hit.id);
+  }
+};
+
+document.getElementById("search").oninput = listFunctions;
+window.onresize = draw;
+listFunctions();
+</script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{Report, ReportFunction};
+
+    #[test]
+    fn embeds_escaped_report_data() {
+        let report = Report {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            functions: vec![ReportFunction {
+                name: "</script><script>alert(1)".to_owned(),
+                address: "1000".to_owned(),
+                node: 0,
+                prototype: None,
+                params: Vec::new(),
+                return_type: None,
+            }],
+            types: BTreeMap::new(),
+        };
+
+        let page = super::embed_report(&report);
+        assert!(!page.contains(super::DATA_PLACEHOLDER));
+        assert!(page.contains("<\\/script><script>alert(1)"));
+        assert_eq!(page.matches("</script>").count(), 2);
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cwe_checker_lib::intermediate_representation::Tid;
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    naming::{c_body, TypeNamer},
+    CType, TypeId,
+};
+
+/// The type IDA uses for parameters that have no inferred type.
+const IDA_UNKNOWN_PARAM: &str = "__int64";
+
+/// A function prototype to apply at an address.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdaPrototype {
+    /// The address of the function's entry
+    pub address: u64,
+    /// The name of the function
+    pub name: String,
+    /// The C declaration of the function
+    pub declaration: String,
+}
+
+/// Type information in a form that can be applied in IDA Pro: a C header declaring every structure, union, and function type,
+/// and the prototypes to apply at function addresses.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdaExport {
+    /// C declarations for the inferred types, parseable by IDA's parse_decls
+    pub declarations: String,
+    /// Prototypes for each function with a known address
+    pub prototypes: Vec<IdaPrototype>,
+}
+
+// Declared types are referred to by name and arrays are stored inline, with the pointer spacing of the C declarations
+// rendered by the namer.
+fn value_decl(namer: &TypeNamer, ty: TypeId, name: String) -> String {
+    let (type_name, declarator) = match namer.get_resolved_type(ty) {
+        Some(CType::Array { element, count }) => {
+            (namer.type_name(*element), format!("{}[{}]", name, count))
+        }
+        _ => (namer.type_name(ty), name),
+    };
+    if type_name.ends_with('*') {
+        format!("{}{}", type_name, declarator)
+    } else {
+        format!("{} {}", type_name, declarator)
+    }
+}
+
+/// Declares the body of a structure or union with the given name, ie. `struct struct_1 {\n  int field_0;\n};`.
+pub(super) fn aggregate_body(namer: &TypeNamer, keyword: &str, name: &str, ct: &CType) -> String {
+    let members = namer.member_decls(ct, |ty, member| value_decl(namer, ty, member));
+    format!("{} {} {};\n", keyword, name, c_body(members.into_iter()))
+}
+
+// Types that must be complete before the given type's body is declared. Pointers do not require completeness.
+fn by_value_dependencies(namer: &TypeNamer, ct: &CType) -> Vec<TypeId> {
+    let members = match ct {
+        CType::Structure(fields) => fields.iter().map(|fld| fld.type_index).collect(),
+        CType::Union(members) => members.iter().cloned().collect(),
+        _ => Vec::new(),
+    };
+
+    members
+        .into_iter()
+        .filter_map(|ty| namer.resolve(ty))
+        // arrays are stored inline so their elements must be complete
+        .filter_map(|ty| match namer.get_resolved_type(ty) {
+            Some(CType::Array { element, .. }) => namer.resolve(*element),
+            _ => Some(ty),
+        })
+        .filter(|ty| {
+            matches!(
+                namer.get_resolved_type(*ty),
+                Some(CType::Structure(_)) | Some(CType::Union(_))
+            )
+        })
+        .collect()
+}
+
+fn declare_body(
+    namer: &TypeNamer,
+    types: &BTreeMap<TypeId, CType>,
+    ty: TypeId,
+    declared: &mut BTreeSet<TypeId>,
+    declared_names: &mut BTreeSet<String>,
+    out: &mut String,
+) {
+    if !declared.insert(ty) {
+        return;
+    }
+
+    let ct = &types[&ty];
+    for dep in by_value_dependencies(namer, ct) {
+        declare_body(namer, types, dep, declared, declared_names, out);
+    }
+
+    let name = namer
+        .declared_name(ty)
+        .expect("only structures and unions have bodies");
+    // with structural names, types with the same structure share a name and are declared once
+    if !declared_names.insert(name.clone()) {
+        return;
+    }
+    match ct {
+        CType::Structure(_) => out.push_str(&aggregate_body(namer, "struct", &name, ct)),
+        CType::Union(_) => out.push_str(&aggregate_body(namer, "union", &name, ct)),
+        _ => (),
+    }
+}
+
+/// Builds the declarations of all structure, union, and function types. Declared types are named by their structure
+/// rather than their [TypeId] if structural_names is set, see [TypeNamer::with_structural_names].
+pub fn build_declarations(
+    types: &BTreeMap<TypeId, CType>,
+    node_to_ty: &BTreeMap<NodeIndex, TypeId>,
+    structural_names: bool,
+) -> String {
+    let namer = TypeNamer::new(types, node_to_ty).with_structural_names(structural_names);
+    let mut out = String::new();
+
+    let mut forward_declared = BTreeSet::new();
+    for (ty, ct) in types.iter() {
+        let keyword = match ct {
+            CType::Structure(_) => "struct",
+            CType::Union(_) => "union",
+            _ => continue,
+        };
+        let name = namer
+            .declared_name(*ty)
+            .expect("structures and unions are named");
+        if forward_declared.insert(name.clone()) {
+            out.push_str(&format!("{} {};\n", keyword, name));
+        }
+    }
+
+    let mut typedefs = BTreeSet::new();
+    for (ty, ct) in types.iter() {
+        if let CType::Function { .. } = ct {
+            let name = namer.declared_name(*ty).expect("functions are named");
+            if !typedefs.insert(name.clone()) {
+                continue;
+            }
+            if let Some(proto) = namer.function_prototype(*ty, &name, IDA_UNKNOWN_PARAM) {
+                out.push_str(&format!("typedef {};\n", proto));
+            }
+        }
+    }
+
+    let mut declared = BTreeSet::new();
+    let mut declared_names = BTreeSet::new();
+    for (ty, ct) in types.iter() {
+        if matches!(ct, CType::Structure(_) | CType::Union(_)) {
+            declare_body(
+                &namer,
+                types,
+                *ty,
+                &mut declared,
+                &mut declared_names,
+                &mut out,
+            );
+        }
+    }
+
+    out
+}
+
+/// Builds an [IdaExport] for the lowered types, applying prototypes to each labeled term
+/// that has a function type and a parseable address.
+pub fn build_ida_export(
+    types: &BTreeMap<TypeId, CType>,
+    node_to_ty: &BTreeMap<NodeIndex, TypeId>,
+    tid_to_node: &BTreeMap<Tid, NodeIndex>,
+    structural_names: bool,
+) -> IdaExport {
+    let namer = TypeNamer::new(types, node_to_ty).with_structural_names(structural_names);
+    let mut prototypes = tid_to_node
+        .iter()
+        .filter_map(|(tid, nd)| {
+            let address = u64::from_str_radix(&tid.address, 16).ok()?;
+            let ty = node_to_ty.get(nd)?;
+            let name = tid.get_str_repr().to_owned();
+            namer
+                .function_prototype(*ty, &name, IDA_UNKNOWN_PARAM)
+                .map(|declaration| IdaPrototype {
+                    address,
+                    name,
+                    declaration: declaration + ";",
+                })
+        })
+        .collect::<Vec<_>>();
+    prototypes.sort_by_key(|p| p.address);
+
+    IdaExport {
+        declarations: build_declarations(types, node_to_ty, structural_names),
+        prototypes,
+    }
+}
+
+impl IdaExport {
+    /// Renders the export as an IDAPython script that parses the declarations and applies the prototypes.
+    pub fn to_idapython(&self) -> String {
+        let mut script = String::new();
+        script.push_str("import idc\n\n");
+        script.push_str(&format!(
+            "DECLARATIONS = {}\n\n",
+            serde_json::to_string(&self.declarations).expect("strings are serializable")
+        ));
+        script.push_str("idc.parse_decls(DECLARATIONS, idc.PT_SILENT)\n\n");
+        for proto in self.prototypes.iter() {
+            script.push_str(&format!(
+                "idc.SetType(0x{:x}, {})\n",
+                proto.address,
+                serde_json::to_string(&proto.declaration).expect("strings are serializable")
+            ));
+        }
+        script
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        analysis::nullability::Nullability,
+        lowering::{CType, Field, Parameter, TypeId},
+    };
+
+    use super::build_declarations;
+
+    #[test]
+    fn struct_with_gap_and_function() {
+        let mut types = BTreeMap::new();
+        types.insert(TypeId(0), CType::Primitive("int".to_owned()));
+        types.insert(
+            TypeId(1),
+            CType::Structure(vec![
+                Field {
+                    byte_offset: 0,
+                    bit_sz: 32,
+                    type_index: TypeId(0),
+                    access_count: 0,
+                },
+                Field {
+                    byte_offset: 8,
+                    bit_sz: 64,
+                    type_index: TypeId(2),
+                    access_count: 0,
+                },
+            ]),
+        );
+        types.insert(
+            TypeId(2),
+            CType::Pointer {
+                target: TypeId(1),
+                is_const: false,
+                nullability: Nullability::Unknown,
+            },
+        );
+        types.insert(
+            TypeId(3),
+            CType::Function {
+                params: vec![Parameter {
+                    index: 0,
+                    type_index: TypeId(2),
+                    is_const: false,
+                }],
+                return_ty: Some(TypeId(0)),
+            },
+        );
+
+        let decls = build_declarations(&types, &BTreeMap::new(), false);
+        assert_eq!(
+            decls,
+            "struct struct_1;\n\
+             typedef int func_3(struct struct_1 *);\n\
+             struct struct_1 {\n  int field_0;\n  char pad_4[4];\n  struct struct_1 *field_8;\n};\n"
+        );
+    }
+
+    #[test]
+    fn structural_names_are_shared_by_identical_layouts() {
+        let mut types = BTreeMap::new();
+        types.insert(TypeId(0), CType::Primitive("int".to_owned()));
+        for id in [1, 5] {
+            types.insert(
+                TypeId(id),
+                CType::Structure(vec![Field {
+                    byte_offset: 4,
+                    bit_sz: 32,
+                    type_index: TypeId(0),
+                    access_count: 0,
+                }]),
+            );
+        }
+
+        let decls = build_declarations(&types, &BTreeMap::new(), true);
+        let mut renumbered = types.clone();
+        let moved = renumbered.remove(&TypeId(5)).unwrap();
+        renumbered.insert(TypeId(9), moved);
+
+        assert_eq!(
+            decls,
+            build_declarations(&renumbered, &BTreeMap::new(), true)
+        );
+        assert_eq!(decls.matches("struct struct_s_").count(), 2);
+        assert!(decls.contains("  char pad_0[4];\n  int field_4;\n"));
+    }
+}
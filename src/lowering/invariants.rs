@@ -0,0 +1,161 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
+
+use crate::diagnostics::{self, DiagnosticKind};
+
+use super::{CType, TypeId};
+
+/// An invariant of lowered types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Invariant {
+    /// The fields of a structure do not overlap
+    DisjointFields,
+    /// Every alias refers to a node that was lowered
+    AliasTargetLowered,
+}
+
+/// A lowered type that breaks an invariant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvariantViolation {
+    /// The invariant that is broken
+    pub invariant: Invariant,
+    /// The type that breaks the invariant
+    pub type_id: TypeId,
+    /// What breaks the invariant
+    pub message: String,
+}
+
+impl Display for InvariantViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "type {} breaks {:?}: {}",
+            self.type_id, self.invariant, self.message
+        )
+    }
+}
+
+/// Checks the invariants of a set of lowered types, where node_types maps each lowered node to its type.
+pub fn check_lowered_types(
+    node_types: &BTreeMap<NodeIndex, TypeId>,
+    types: &BTreeMap<TypeId, CType>,
+) -> Vec<InvariantViolation> {
+    let mut violations = Vec::new();
+    for (ty, ctype) in types.iter() {
+        let mut violation = |invariant, message| {
+            violations.push(InvariantViolation {
+                invariant,
+                type_id: *ty,
+                message,
+            })
+        };
+
+        match ctype {
+            CType::Alias(tgt) if !node_types.contains_key(tgt) => violation(
+                Invariant::AliasTargetLowered,
+                format!("alias to node {} that was not lowered", tgt.index()),
+            ),
+            CType::Structure(fields) => {
+                let mut sorted = fields.iter().collect::<Vec<_>>();
+                sorted.sort_by_key(|fld| fld.byte_offset);
+                for (fst, snd) in sorted.iter().zip(sorted.iter().skip(1)) {
+                    let fst_end = fst.byte_offset + fst.bit_sz / 8;
+                    if fst_end > snd.byte_offset {
+                        violation(
+                            Invariant::DisjointFields,
+                            format!(
+                                "field at {} of {} bits overlaps the field at {}",
+                                fst.byte_offset, fst.bit_sz, snd.byte_offset
+                            ),
+                        );
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    violations
+}
+
+/// Reports each violation as a diagnostic and logs it as a warning, then fails on the first violation when strict.
+/// Returns the violations.
+pub fn enforce(
+    violations: Vec<InvariantViolation>,
+    strict: bool,
+) -> anyhow::Result<Vec<InvariantViolation>> {
+    violations.iter().for_each(|violation| {
+        diagnostics::report(DiagnosticKind::InvariantViolation, violation.to_string());
+        log::warn!("Lowering invariant violated: {}", violation)
+    });
+
+    if let (true, Some(violation)) = (strict, violations.first()) {
+        return Err(anyhow::anyhow!("{}", violation).context(format!(
+            "lowered types break {} invariants",
+            violations.len()
+        )));
+    }
+    Ok(violations)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use petgraph::graph::NodeIndex;
+
+    use crate::{
+        diagnostics::{self, DiagnosticKind},
+        lowering::{CType, Field, TypeId},
+    };
+
+    use super::{check_lowered_types, enforce, Invariant};
+
+    #[test]
+    fn violations_are_errors_only_when_strict() {
+        let types = BTreeMap::from([
+            (TypeId(1), CType::Alias(NodeIndex::new(7))),
+            (
+                TypeId(2),
+                CType::Structure(vec![
+                    Field {
+                        byte_offset: 0,
+                        bit_sz: 64,
+                        type_index: TypeId(3),
+                        access_count: 0,
+                    },
+                    Field {
+                        byte_offset: 4,
+                        bit_sz: 32,
+                        type_index: TypeId(3),
+                        access_count: 0,
+                    },
+                ]),
+            ),
+            (TypeId(3), CType::Primitive("int".to_owned())),
+        ]);
+        let node_types = BTreeMap::from([(NodeIndex::new(0), TypeId(2))]);
+
+        let violations = check_lowered_types(&node_types, &types);
+        assert_eq!(
+            violations
+                .iter()
+                .map(|violation| (violation.type_id, violation.invariant))
+                .collect::<Vec<_>>(),
+            vec![
+                (TypeId(1), Invariant::AliasTargetLowered),
+                (TypeId(2), Invariant::DisjointFields)
+            ]
+        );
+
+        diagnostics::start_collecting();
+        assert!(enforce(violations.clone(), true).is_err());
+        assert_eq!(enforce(violations, false).unwrap().len(), 2);
+        assert_eq!(
+            diagnostics::take().counts[&DiagnosticKind::InvariantViolation],
+            4
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use petgraph::{graph::NodeIndex, stable_graph::StableDiGraph, visit::EdgeRef, EdgeDirection};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constraints::{Field, FieldLabel},
+    ctypes::{self, CTypeMapping},
+};
+
+use super::{convert_typeid, TypeId};
+
+/// The pattern of a structure that contains pointers to structures of the same shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum LinkedStructureKind {
+    /// A single recursive field, ie. a singly linked list.
+    List,
+    /// Multiple recursive fields, ie. a tree or doubly linked list.
+    Tree,
+}
+
+/// A structure sketch with recursive pointer fields.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkedStructure {
+    /// The pattern of the structure
+    pub kind: LinkedStructureKind,
+    /// The fields that point to a structure of the same shape
+    pub recursive_fields: BTreeSet<Field>,
+}
+
+fn field_shape<N>(grph: &StableDiGraph<N, FieldLabel>, nd: NodeIndex) -> BTreeSet<Field> {
+    grph.edges_directed(nd, EdgeDirection::Outgoing)
+        .filter_map(|e| {
+            if let FieldLabel::Field(fld) = e.weight() {
+                Some(fld.clone())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn pointer_targets<N>(
+    grph: &StableDiGraph<N, FieldLabel>,
+    nd: NodeIndex,
+) -> impl Iterator<Item = NodeIndex> + '_ {
+    grph.edges_directed(nd, EdgeDirection::Outgoing)
+        .filter(|e| matches!(e.weight(), FieldLabel::Load | FieldLabel::Store))
+        .map(|e| e.target())
+}
+
+/// Finds structure nodes with fields that point back to the structure itself or to a structure with the same fields.
+/// Only nodes with a field at a non-zero offset are considered structures, matching lowering.
+pub fn detect_linked_structures<N>(
+    grph: &StableDiGraph<N, FieldLabel>,
+) -> BTreeMap<NodeIndex, LinkedStructure> {
+    let mut linked = BTreeMap::new();
+    for nd in grph.node_indices() {
+        let shape = field_shape(grph, nd);
+        if !shape.iter().any(|fld| fld.offset != 0) {
+            continue;
+        }
+
+        let recursive_fields = grph
+            .edges_directed(nd, EdgeDirection::Outgoing)
+            .filter_map(|e| match e.weight() {
+                FieldLabel::Field(fld)
+                    if pointer_targets(grph, e.target())
+                        .any(|tgt| tgt == nd || field_shape(grph, tgt) == shape) =>
+                {
+                    Some(fld.clone())
+                }
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+
+        let kind = match recursive_fields.len() {
+            0 => continue,
+            1 => LinkedStructureKind::List,
+            _ => LinkedStructureKind::Tree,
+        };
+
+        linked.insert(
+            nd,
+            LinkedStructure {
+                kind,
+                recursive_fields,
+            },
+        );
+    }
+
+    linked
+}
+
+pub(super) fn kind_to_protobuf(kind: LinkedStructureKind) -> ctypes::LinkedStructureKind {
+    match kind {
+        LinkedStructureKind::List => ctypes::LinkedStructureKind::List,
+        LinkedStructureKind::Tree => ctypes::LinkedStructureKind::Tree,
+    }
+}
+
+/// Records the pattern of each linked structure in the mapping, keyed by the type id of the lowered structure.
+/// Nodes that were not lowered to a structure are skipped. Fails if a type id cannot be converted.
+pub fn annotate_linked_structures(
+    mapping: &mut CTypeMapping,
+    linked: &BTreeMap<NodeIndex, LinkedStructure>,
+    node_to_ty: &BTreeMap<NodeIndex, TypeId>,
+) -> anyhow::Result<()> {
+    for (nd, structure) in linked.iter() {
+        if let Some(ty) = node_to_ty.get(nd) {
+            let type_id = convert_typeid(*ty)?.type_id;
+            let is_structure = matches!(
+                mapping
+                    .type_id_to_ctype
+                    .get(&type_id)
+                    .and_then(|ct| ct.inner_type.as_ref()),
+                Some(ctypes::c_type::InnerType::Structure(_))
+            );
+
+            if is_structure {
+                mapping
+                    .linked_structures
+                    .insert(type_id, kind_to_protobuf(structure.kind) as i32);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use petgraph::stable_graph::StableDiGraph;
+
+    use crate::constraints::{Field, FieldLabel};
+
+    use super::{detect_linked_structures, LinkedStructureKind};
+
+    #[test]
+    fn list_and_tree() {
+        let mut grph: StableDiGraph<(), FieldLabel> = StableDiGraph::new();
+        let node = grph.add_node(());
+        let value = grph.add_node(());
+        let next = grph.add_node(());
+        grph.add_edge(node, value, FieldLabel::Field(Field::new(0, 32)));
+        grph.add_edge(node, next, FieldLabel::Field(Field::new(8, 64)));
+        grph.add_edge(next, node, FieldLabel::Load);
+
+        let tree = grph.add_node(());
+        let left = grph.add_node(());
+        let right = grph.add_node(());
+        let child = grph.add_node(());
+        grph.add_edge(tree, left, FieldLabel::Field(Field::new(0, 64)));
+        grph.add_edge(tree, right, FieldLabel::Field(Field::new(8, 64)));
+        grph.add_edge(left, tree, FieldLabel::Load);
+        grph.add_edge(right, child, FieldLabel::Load);
+        grph.add_edge(child, value, FieldLabel::Field(Field::new(0, 64)));
+        grph.add_edge(child, value, FieldLabel::Field(Field::new(8, 64)));
+
+        let linked = detect_linked_structures(&grph);
+        assert_eq!(linked[&node].kind, LinkedStructureKind::List);
+        assert_eq!(
+            linked[&node].recursive_fields.iter().collect::<Vec<_>>(),
+            vec![&Field::new(8, 64)]
+        );
+        assert_eq!(linked[&tree].kind, LinkedStructureKind::Tree);
+        assert!(!linked.contains_key(&child));
+    }
+}
@@ -0,0 +1,216 @@
+use std::{collections::BTreeMap, convert::TryFrom};
+
+use cwe_checker_lib::intermediate_representation::Tid;
+use serde::{Deserialize, Serialize};
+
+use crate::ctypes::{self, CTypeMapping};
+
+use super::{callsites::tid_to_protobuf, convert_typeid, CType, Field, TypeId};
+
+/// A local variable of a stack frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FrameSlot {
+    /// The offset of the slot from the stack pointer on entry
+    pub offset: i64,
+    /// The size of the slot in bits
+    pub bit_sz: usize,
+    /// The type of the slot
+    pub type_id: TypeId,
+}
+
+/// The structure the local variables of a subprocedure are lowered to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameStructure {
+    /// The name of the structure, `frame_` followed by the name of the subprocedure
+    pub name: String,
+    /// The offset of the start of the structure from the stack pointer on entry, which is the offset of the lowest local
+    pub base_offset: i64,
+    /// The type of the structure
+    pub type_id: TypeId,
+}
+
+/// Lowers the local variables of each subprocedure to a `frame_<sub>` structure that is added to types. Locals are laid out
+/// from the lowest offset, and a local overlapping a lower local is left out. Subprocedures without locals get no structure.
+pub fn add_frame_structures(
+    types: &mut BTreeMap<TypeId, CType>,
+    frames: &BTreeMap<Tid, Vec<FrameSlot>>,
+) -> BTreeMap<Tid, FrameStructure> {
+    let mut next_id = types.keys().next_back().map(|id| id.0 + 1).unwrap_or(0);
+    frames
+        .iter()
+        .filter_map(|(sub, slots)| {
+            let mut slots = slots.clone();
+            slots.sort();
+            let base_offset = slots.first()?.offset;
+
+            let mut fields: Vec<Field> = Vec::new();
+            for slot in slots.iter() {
+                let byte_offset = usize::try_from(slot.offset - base_offset).ok()?;
+                let overlaps = fields
+                    .last()
+                    .map(|prev| byte_offset < prev.byte_offset + (prev.bit_sz + 7) / 8)
+                    .unwrap_or(false);
+                if !overlaps {
+                    fields.push(Field {
+                        byte_offset,
+                        bit_sz: slot.bit_sz,
+                        type_index: slot.type_id,
+                        access_count: 0,
+                    });
+                }
+            }
+
+            let type_id = TypeId(next_id);
+            next_id += 1;
+            types.insert(type_id, CType::Structure(fields));
+            Some((
+                sub.clone(),
+                FrameStructure {
+                    name: format!("frame_{}", sub.get_str_repr()),
+                    base_offset,
+                    type_id,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Records the `frame_<sub>` structure of each subprocedure in its stack frame and names the structure. Fails if a type
+/// id cannot be converted.
+pub fn annotate_frame_structures(
+    mapping: &mut CTypeMapping,
+    structures: &BTreeMap<Tid, FrameStructure>,
+) -> anyhow::Result<()> {
+    for (sub, structure) in structures.iter() {
+        let type_id = convert_typeid(structure.type_id)?;
+        mapping
+            .structure_names
+            .insert(type_id.type_id, structure.name.clone());
+
+        let sub = tid_to_protobuf(sub);
+        match mapping
+            .stack_frames
+            .iter_mut()
+            .find(|frame| frame.sub.as_ref() == Some(&sub))
+        {
+            Some(frame) => {
+                frame.frame_type_id = Some(type_id);
+                frame.frame_base_offset = structure.base_offset;
+            }
+            None => mapping.stack_frames.push(ctypes::StackFrame {
+                sub: Some(sub),
+                offset_to_type_id: BTreeMap::new(),
+                frame_type_id: Some(type_id),
+                frame_base_offset: structure.base_offset,
+            }),
+        }
+    }
+
+    Ok(())
+}
+
+/// Records the types of the local variables of each subprocedure in the mapping, keyed by the offset of each stack
+/// slot from the stack pointer on entry. Fails if a type id cannot be converted.
+pub fn annotate_stack_frames(
+    mapping: &mut CTypeMapping,
+    frames: &BTreeMap<Tid, BTreeMap<i64, TypeId>>,
+) -> anyhow::Result<()> {
+    for (sub, slots) in frames.iter() {
+        mapping.stack_frames.push(ctypes::StackFrame {
+            sub: Some(tid_to_protobuf(sub)),
+            offset_to_type_id: slots
+                .iter()
+                .map(|(offset, ty)| Ok((*offset, convert_typeid(*ty)?)))
+                .collect::<anyhow::Result<_>>()?,
+            frame_type_id: None,
+            frame_base_offset: 0,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use crate::{
+        ctypes::CTypeMapping,
+        lowering::{CType, TypeId},
+    };
+
+    use super::{
+        add_frame_structures, annotate_frame_structures, annotate_stack_frames, FrameSlot,
+    };
+
+    #[test]
+    fn frames_are_keyed_by_entry_offset() {
+        let sub = Tid::create("sub_main".to_owned(), "1000".to_owned());
+        let int_ty: TypeId = serde_json::from_str("3").unwrap();
+        let ptr_ty: TypeId = serde_json::from_str("5").unwrap();
+        let frames = BTreeMap::from([(sub, BTreeMap::from([(-24, int_ty), (-16, ptr_ty)]))]);
+
+        let mut mapping = CTypeMapping::default();
+        annotate_stack_frames(&mut mapping, &frames).unwrap();
+        assert_eq!(mapping.stack_frames.len(), 1);
+        let frame = &mapping.stack_frames[0];
+        assert_eq!(frame.sub.as_ref().unwrap().name, "sub_main");
+        assert_eq!(frame.offset_to_type_id[&-24].type_id, 3);
+        assert_eq!(frame.offset_to_type_id[&-16].type_id, 5);
+    }
+
+    #[test]
+    fn locals_are_lowered_to_a_named_frame_structure() {
+        let sub = Tid::create("sub_main".to_owned(), "1000".to_owned());
+        let int_ty: TypeId = serde_json::from_str("3").unwrap();
+        let ptr_ty: TypeId = serde_json::from_str("5").unwrap();
+        let slot = |offset, bit_sz, type_id| FrameSlot {
+            offset,
+            bit_sz,
+            type_id,
+        };
+        // the second int overlaps the pointer below it
+        let frames = BTreeMap::from([(
+            sub.clone(),
+            vec![
+                slot(-16, 32, int_ty),
+                slot(-24, 64, ptr_ty),
+                slot(-20, 32, int_ty),
+            ],
+        )]);
+
+        let mut types = BTreeMap::from([
+            (int_ty, CType::Primitive("int".to_owned())),
+            (ptr_ty, CType::Primitive("void*".to_owned())),
+        ]);
+        let structures = add_frame_structures(&mut types, &frames);
+        let structure = &structures[&sub];
+        assert_eq!(structure.name, "frame_sub_main");
+        assert_eq!(structure.base_offset, -24);
+        match &types[&structure.type_id] {
+            CType::Structure(fields) => assert_eq!(
+                fields
+                    .iter()
+                    .map(|fld| (fld.byte_offset(), fld.type_index()))
+                    .collect::<Vec<_>>(),
+                vec![(0, ptr_ty), (8, int_ty)]
+            ),
+            ty => panic!("expected a structure, got {:?}", ty),
+        }
+
+        let mut mapping = CTypeMapping::default();
+        annotate_stack_frames(
+            &mut mapping,
+            &BTreeMap::from([(sub, BTreeMap::from([(-24, ptr_ty)]))]),
+        )
+        .unwrap();
+        annotate_frame_structures(&mut mapping, &structures).unwrap();
+        assert_eq!(mapping.stack_frames.len(), 1);
+        let frame = &mapping.stack_frames[0];
+        let frame_ty = frame.frame_type_id.as_ref().unwrap().type_id;
+        assert_eq!(frame.frame_base_offset, -24);
+        assert_eq!(mapping.structure_names[&frame_ty], "frame_sub_main");
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+};
+
+use anyhow::Context;
+use prost::Message;
+
+use crate::{
+    analysis::nullability::Nullability,
+    ctypes::{self, CTypeMapping},
+};
+
+use super::{CType, Field, Parameter, TypeId, CTYPES_SCHEMA_VERSION};
+
+/// Types read back from a serialized [CTypeMapping]. Aliases are resolved while reading, so every reference is to the
+/// aliased type and no [CType::Alias] remains.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeMapping {
+    /// The types by id
+    pub types: BTreeMap<TypeId, CType>,
+    /// The type of each labeled term keyed by the term's tid string
+    pub term_types: BTreeMap<String, TypeId>,
+}
+
+fn read_typeid(ty: Option<&ctypes::TypeId>) -> anyhow::Result<TypeId> {
+    let ty = ty.ok_or_else(|| anyhow::anyhow!("missing type id"))?;
+    Ok(TypeId(usize::try_from(ty.type_id).with_context(|| {
+        format!("type id {} exceeds usize", ty.type_id)
+    })?))
+}
+
+fn read_nullability(nullability: i32) -> Nullability {
+    match ctypes::Nullability::from_i32(nullability) {
+        Some(ctypes::Nullability::Nullable) => Nullability::Nullable,
+        Some(ctypes::Nullability::Nonnull) => Nullability::NonNull,
+        _ => Nullability::Unknown,
+    }
+}
+
+// Aliases are kept apart so they can be resolved once all types are read
+enum ReadType {
+    Type(CType),
+    Alias(Option<TypeId>),
+}
+
+fn read_inner_type(inner: &ctypes::c_type::InnerType) -> anyhow::Result<ReadType> {
+    Ok(ReadType::Type(match inner {
+        ctypes::c_type::InnerType::Alias(alias) => {
+            return Ok(ReadType::Alias(
+                alias
+                    .to_type
+                    .as_ref()
+                    .map(|ty| read_typeid(Some(ty)))
+                    .transpose()?,
+            ))
+        }
+        ctypes::c_type::InnerType::Function(func) => CType::Function {
+            params: func
+                .parameters
+                .iter()
+                .map(|param| {
+                    Ok(Parameter {
+                        index: usize::try_from(param.parameter_index)?,
+                        type_index: read_typeid(param.type_index.as_ref())?,
+                        is_const: param.is_const,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            return_ty: if func.has_return {
+                Some(read_typeid(func.return_type.as_ref())?)
+            } else {
+                None
+            },
+        },
+        ctypes::c_type::InnerType::Structure(st) => CType::Structure(
+            st.fields
+                .iter()
+                .map(|fld| {
+                    Ok(Field {
+                        byte_offset: usize::try_from(fld.byte_offset)?,
+                        bit_sz: usize::try_from(fld.bit_size)?,
+                        type_index: read_typeid(fld.type_id.as_ref())?,
+                        access_count: usize::try_from(fld.access_count)?,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+        ),
+        ctypes::c_type::InnerType::Primitive(prim) => CType::Primitive(prim.type_constant.clone()),
+        ctypes::c_type::InnerType::Pointer(ptr) => CType::Pointer {
+            target: read_typeid(ptr.to_type_id.as_ref())?,
+            is_const: ptr.is_const,
+            nullability: read_nullability(ptr.nullability),
+        },
+        ctypes::c_type::InnerType::Union(union) => CType::Union(
+            union
+                .target_type_ids
+                .iter()
+                .map(|ty| read_typeid(Some(ty)))
+                .collect::<anyhow::Result<BTreeSet<_>>>()?,
+        ),
+        ctypes::c_type::InnerType::Array(arr) => CType::Array {
+            element: read_typeid(arr.element_type.as_ref())?,
+            count: usize::try_from(arr.count)?,
+        },
+    }))
+}
+
+/// Reads the types and term types of a [CTypeMapping]. Fails if the mapping was written with a newer schema, or if an
+/// alias has no target or is part of an alias cycle.
+pub fn read_mapping(pb: &CTypeMapping) -> anyhow::Result<TypeMapping> {
+    if pb.schema_version > CTYPES_SCHEMA_VERSION {
+        return Err(anyhow::anyhow!(
+            "mapping has schema version {} but only versions up to {} can be read",
+            pb.schema_version,
+            CTYPES_SCHEMA_VERSION
+        ));
+    }
+
+    let mut types = BTreeMap::new();
+    let mut aliases = BTreeMap::new();
+    for (id, ct) in pb.type_id_to_ctype.iter() {
+        let idx = read_typeid(Some(&ctypes::TypeId { type_id: *id }))?;
+        let inner = ct
+            .inner_type
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("type {} has no definition", id))?;
+        match read_inner_type(inner).with_context(|| format!("reading type {}", id))? {
+            ReadType::Type(ct) => {
+                types.insert(idx, ct);
+            }
+            ReadType::Alias(tgt) => {
+                aliases.insert(idx, tgt);
+            }
+        }
+    }
+
+    let resolve = |ty: TypeId| -> anyhow::Result<TypeId> {
+        let mut seen = BTreeSet::new();
+        let mut curr = ty;
+        while let Some(tgt) = aliases.get(&curr) {
+            if !seen.insert(curr) {
+                return Err(anyhow::anyhow!("alias {} is part of a cycle", ty.0));
+            }
+            curr = tgt.ok_or_else(|| anyhow::anyhow!("alias {} has no target", curr.0))?;
+        }
+        Ok(curr)
+    };
+
+    let resolved = aliases
+        .keys()
+        .map(|ty| resolve(*ty).map(|tgt| (*ty, tgt)))
+        .collect::<anyhow::Result<BTreeMap<_, _>>>()?;
+    let lookup = |ty: TypeId| *resolved.get(&ty).unwrap_or(&ty);
+    types.values_mut().for_each(|ct| ct.map_type_ids(&lookup));
+
+    let term_types = pb
+        .tid_to_type_id
+        .iter()
+        .map(|(tid, ty)| Ok((tid.clone(), lookup(read_typeid(Some(ty))?))))
+        .collect::<anyhow::Result<BTreeMap<_, _>>>()?;
+
+    Ok(TypeMapping { types, term_types })
+}
+
+/// Decodes and reads a protobuf encoded [CTypeMapping].
+pub fn decode_mapping(bytes: &[u8]) -> anyhow::Result<TypeMapping> {
+    read_mapping(&CTypeMapping::decode(bytes)?)
+}
+
+/// Merges several mappings, eg. from separate binaries or from incremental runs of the same binary, into one.
+/// Type ids are renumbered so that ids from different mappings do not collide, and structurally identical types
+/// (including recursive types) are merged into a single id. When several mappings type the same term the type from the
+/// latest mapping is kept.
+pub fn merge_mappings(mappings: impl IntoIterator<Item = TypeMapping>) -> TypeMapping {
+    // Place every type in one id space
+    let mut all_types: Vec<CType> = Vec::new();
+    let mut all_terms: BTreeMap<String, usize> = BTreeMap::new();
+    for mapping in mappings {
+        let remap = mapping
+            .types
+            .keys()
+            .enumerate()
+            .map(|(i, ty)| (*ty, TypeId(all_types.len() + i)))
+            .collect::<BTreeMap<_, _>>();
+        // References to types missing from the mapping are left dangling
+        let lookup = |ty: TypeId| *remap.get(&ty).unwrap_or(&TypeId(usize::MAX));
+        for (_, mut ct) in mapping.types.into_iter() {
+            ct.map_type_ids(&lookup);
+            all_types.push(ct);
+        }
+        for (tid, ty) in mapping.term_types.into_iter() {
+            if let Some(new_ty) = remap.get(&ty) {
+                all_terms.insert(tid, new_ty.0);
+            }
+        }
+    }
+
+    // Partition refinement: types start in a single class and are split by their shape over the classes of the
+    // types they reference until the partition is stable. Types left in the same class are structurally identical.
+    let mut classes = vec![0; all_types.len()];
+    let mut class_count = 1;
+    loop {
+        let mut signatures: BTreeMap<(usize, CType), usize> = BTreeMap::new();
+        let next = all_types
+            .iter()
+            .zip(classes.iter())
+            .map(|(ct, class)| {
+                let mut shape = ct.clone();
+                shape.map_type_ids(&|ty: TypeId| {
+                    TypeId(classes.get(ty.0).cloned().unwrap_or(usize::MAX))
+                });
+                let num_classes = signatures.len();
+                *signatures.entry((*class, shape)).or_insert(num_classes)
+            })
+            .collect::<Vec<_>>();
+
+        let done = signatures.len() == class_count;
+        class_count = signatures.len();
+        classes = next;
+        if done {
+            break;
+        }
+    }
+
+    let mut types = BTreeMap::new();
+    for (ct, class) in all_types.iter().zip(classes.iter()) {
+        types.entry(TypeId(*class)).or_insert_with(|| {
+            let mut ct = ct.clone();
+            ct.map_type_ids(&|ty: TypeId| TypeId(classes.get(ty.0).cloned().unwrap_or(usize::MAX)));
+            ct
+        });
+    }
+
+    TypeMapping {
+        types,
+        term_types: all_terms
+            .into_iter()
+            .map(|(tid, ty)| (tid, TypeId(classes[ty])))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use petgraph::graph::NodeIndex;
+
+    use crate::analysis::nullability::Nullability;
+
+    use super::{CType, Field, TypeId, TypeMapping};
+
+    fn linked_list(ptr: usize, node: usize, int: usize) -> BTreeMap<TypeId, CType> {
+        BTreeMap::from([
+            (
+                TypeId(node),
+                CType::Structure(vec![
+                    Field {
+                        byte_offset: 0,
+                        bit_sz: 32,
+                        type_index: TypeId(int),
+                        access_count: 0,
+                    },
+                    Field {
+                        byte_offset: 8,
+                        bit_sz: 64,
+                        type_index: TypeId(ptr),
+                        access_count: 0,
+                    },
+                ]),
+            ),
+            (
+                TypeId(ptr),
+                CType::Pointer {
+                    target: TypeId(node),
+                    is_const: false,
+                    nullability: Nullability::Unknown,
+                },
+            ),
+            (TypeId(int), CType::Primitive("int".to_owned())),
+        ])
+    }
+
+    #[test]
+    fn merging_dedups_recursive_types() {
+        let fst = TypeMapping {
+            types: linked_list(0, 1, 2),
+            term_types: BTreeMap::from([("sub_a".to_owned(), TypeId(0))]),
+        };
+        let mut snd_types = linked_list(7, 3, 5);
+        snd_types.insert(TypeId(9), CType::Primitive("char".to_owned()));
+        let snd = TypeMapping {
+            types: snd_types,
+            term_types: BTreeMap::from([
+                ("sub_b".to_owned(), TypeId(7)),
+                ("sub_c".to_owned(), TypeId(9)),
+            ]),
+        };
+
+        let merged = super::merge_mappings(vec![fst, snd]);
+        assert_eq!(merged.types.len(), 4);
+        assert_eq!(
+            merged.term_types["sub_a"], merged.term_types["sub_b"],
+            "both lists should be merged into one type"
+        );
+        assert_ne!(merged.term_types["sub_a"], merged.term_types["sub_c"]);
+    }
+
+    #[test]
+    fn reading_resolves_aliases() {
+        let mut types = BTreeMap::new();
+        types.insert(TypeId(0), CType::Primitive("int".to_owned()));
+        types.insert(
+            TypeId(2),
+            CType::Pointer {
+                target: TypeId(0),
+                is_const: true,
+                nullability: Nullability::NonNull,
+            },
+        );
+        let node_to_ty = BTreeMap::from([(NodeIndex::new(0), TypeId(2))]);
+        types.insert(TypeId(1), CType::Alias(NodeIndex::new(0)));
+        types.insert(
+            TypeId(3),
+            CType::Array {
+                element: TypeId(1),
+                count: 4,
+            },
+        );
+
+        let pb = super::super::convert_mapping_to_profobuf(types, &node_to_ty, &BTreeMap::new())
+            .unwrap();
+        let read = super::read_mapping(&pb).unwrap();
+        assert_eq!(read.types.len(), 3);
+        assert_eq!(
+            read.types[&TypeId(3)],
+            CType::Array {
+                element: TypeId(2),
+                count: 4,
+            }
+        );
+        assert_eq!(
+            read.types[&TypeId(2)],
+            CType::Pointer {
+                target: TypeId(0),
+                is_const: true,
+                nullability: Nullability::NonNull,
+            }
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use anyhow::Context;
 use cwe_checker_lib::intermediate_representation::{Arg, Tid};
 
 use std::collections::{BTreeMap, BTreeSet, HashMap};
@@ -5,26 +6,93 @@ use std::collections::{BTreeMap, BTreeSet, HashMap};
 use crate::{
     constraints,
     ctypes::{self, CTypeMapping},
+    diagnostics::{self, DiagnosticKind},
     solver::type_sketch::LatticeBounds,
 };
 use std::convert::TryInto;
 
+use alga::general::JoinSemilattice;
 use serde::{Deserialize, Serialize};
 
 use petgraph::{graph::NodeIndex, visit::EdgeRef, EdgeDirection};
 
 use crate::{
+    analysis::{
+        array_extents::ArrayLayout, character_types::CharacterClass,
+        discriminants::TaggedUnionLayout, nullability::Nullability, rust_binaries::FatPointer,
+    },
     constraints::FieldLabel,
     solver::{type_lattice::NamedLatticeElement, type_sketch::SketchGraph},
 };
 
+use self::conflicts::{
+    ConflictKind, ConflictStrategy, LatticeConflict, ParameterCountMismatch, SizeMismatch,
+};
+
+use std::cmp::Reverse;
 use std::collections::BinaryHeap;
 use std::convert::TryFrom;
 
-#[derive(Debug, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone)]
+/// Translation of lattice element names to C primitive spellings.
+pub mod primitives;
+
+/// Names for lowered types shared by exporters.
+pub mod naming;
+
+/// Export of lowered types as declarations and prototypes for IDA Pro.
+pub mod ida;
+
+/// A self-contained HTML report for exploring sketches and lowered types.
+pub mod html;
+
+/// Detection of linked data structures (lists, trees) in sketches.
+pub mod linked;
+
+/// Streaming output of lowered types one labeled term at a time.
+pub mod stream;
+
+/// Reading serialized mappings back and merging mappings from several runs.
+pub mod mapping;
+
+/// Detection and resolution of nodes whose lattice bounds, capabilities, access sizes, or parameter counts conflict.
+pub mod conflicts;
+
+/// The types callees are instantiated to at each of their callsites.
+pub mod callsites;
+
+/// Checks of the invariants lowered types must uphold, reported as warnings or enforced as errors.
+pub mod invariants;
+
+/// Names of structure fields taken from the getters and setters that access them.
+pub mod accessors;
+
+/// The types of the local variables of each function, keyed by stack offset.
+pub mod locals;
+
+/// Cross-references from lowered types and their fields to the instructions accessing them.
+pub mod xrefs;
+
+use linked::LinkedStructure;
+use primitives::PrimitiveTable;
+
+#[derive(Debug, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 /// A unique identifier for a type
 pub struct TypeId(usize);
 
+impl std::fmt::Display for TypeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::str::FromStr for TypeId {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(TypeId(s.parse()?))
+    }
+}
+
 /// Representation of a automata type lowered to a ctype
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub enum CType {
@@ -34,6 +102,12 @@ pub enum CType {
     Pointer {
         /// The target type
         target: TypeId,
+        /// True if the target is only ever loaded through this pointer
+        #[serde(default)]
+        is_const: bool,
+        /// Whether the pointer may be null
+        #[serde(default)]
+        nullability: Nullability,
     },
     /// An alias to the type of a different node
     Alias(NodeIndex),
@@ -48,13 +122,256 @@ pub enum CType {
     },
     /// A union of several ctypes
     Union(BTreeSet<TypeId>),
+    /// A fixed number of consecutive elements of the same type
+    Array {
+        /// The type of each element
+        element: TypeId,
+        /// The number of elements, 0 if the extent is unknown
+        count: usize,
+    },
+}
+
+impl CType {
+    /// Gets the [TypeId]s directly referenced by this type.
+    pub fn referenced_type_ids(&self) -> Vec<TypeId> {
+        match self {
+            CType::Primitive(_) | CType::Alias(_) => Vec::new(),
+            CType::Pointer { target, .. } => vec![*target],
+            CType::Structure(fields) => fields.iter().map(|fld| fld.type_index).collect(),
+            CType::Function { params, return_ty } => params
+                .iter()
+                .map(|param| param.type_index)
+                .chain(return_ty.iter().cloned())
+                .collect(),
+            CType::Union(children) => children.iter().cloned().collect(),
+            CType::Array { element, .. } => vec![*element],
+        }
+    }
+
+    /// Renders a single C declaration of a value of this type with the given name, expanding the types it refers to
+    /// from the mapping inline. See [naming::TypeNamer::c_decl].
+    pub fn to_c_decl(&self, mapping: &mapping::TypeMapping, name: &str) -> String {
+        naming::TypeNamer::new(&mapping.types, &BTreeMap::new()).c_decl(self, name)
+    }
+
+    /// Applies a rewrite to every [TypeId] directly referenced by this type.
+    pub fn map_type_ids(&mut self, f: &impl Fn(TypeId) -> TypeId) {
+        match self {
+            CType::Primitive(_) | CType::Alias(_) => (),
+            CType::Pointer { target, .. } => *target = f(*target),
+            CType::Structure(fields) => fields
+                .iter_mut()
+                .for_each(|fld| fld.type_index = f(fld.type_index)),
+            CType::Function { params, return_ty } => {
+                params
+                    .iter_mut()
+                    .for_each(|param| param.type_index = f(param.type_index));
+                *return_ty = return_ty.map(f);
+            }
+            CType::Union(children) => {
+                *children = children.iter().map(|x| f(*x)).collect();
+            }
+            CType::Array { element, .. } => *element = f(*element),
+        }
+    }
+}
+
+/// Options controlling the heuristics used when lowering a sketch graph to ctypes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoweringOptions {
+    /// If true, nodes with multiple candidate types are lowered to a union of the candidates,
+    /// otherwise the best candidate is picked.
+    pub emit_unions: bool,
+    /// The maximum number of members in an emitted union. Nodes with more candidates than this
+    /// are lowered according to union_overflow.
+    pub max_union_arity: Option<usize>,
+    /// How nodes with more candidates than max_union_arity are lowered.
+    pub union_overflow: UnionOverflow,
+    /// If true, gaps between structure fields are filled with explicit padding fields.
+    pub synthesize_padding: bool,
+    /// Translates lattice element names to C spellings when they are lowered to primitives.
+    pub primitive_table: PrimitiveTable,
+    /// If true, aliases are replaced by the type of the node they refer to.
+    pub compress_aliases: bool,
+    /// If true, nodes lowered to a primitive or to an alias of one map directly to a single shared type per primitive,
+    /// and the duplicate primitives and aliases are removed.
+    pub canonical_primitives: bool,
+    /// If true, objects whose fields are accessed depending on a discriminant check are lowered
+    /// to a structure containing the discriminant and a union of the variants.
+    pub detect_tagged_unions: bool,
+    /// If true, structures with fields pointing to structures of the same shape are annotated as lists or trees, and
+    /// their recursive fields are lowered as pointers to the structure itself.
+    pub detect_linked_structures: bool,
+    /// If true, pointer parameters and returns are annotated with whether they may be null.
+    pub infer_nullability: bool,
+    /// If true, pointer parameters that are only ever loaded from are lowered as pointers to const.
+    pub infer_const_parameters: bool,
+    /// If true, objects indexed in loops bounded by a constant are lowered with an array of the
+    /// inferred element count.
+    pub infer_array_extents: bool,
+    /// If true, the elements of buffers passed to wide string functions, indexed with a 2 byte stride, or holding
+    /// read only literals are lowered to wchar_t, char16_t, char, or uint8_t.
+    pub infer_character_types: bool,
+    /// If true, the loads and stores accessing each structure field are counted and recorded in the lowered fields.
+    pub count_field_accesses: bool,
+    /// The maximum number of distinct field offsets of a structure. The fields of an object accessed at more offsets, from
+    /// the first offset past the threshold onward, are widened into a single array of bytes.
+    pub max_field_offsets: Option<usize>,
+    /// If true, only the nodes reachable from the interesting variables are lowered rather than
+    /// every node of the sketch graph.
+    pub lower_reachable_only: bool,
+    /// How nodes whose lattice bounds clash or that are both primitives and pointers are lowered.
+    pub conflict_strategy: ConflictStrategy,
+    /// If true, lowered types that break an invariant (overlapping structure fields, aliases to nodes that were not
+    /// lowered, type ids exceeding 64 bits) are errors, otherwise they are logged as warnings.
+    pub strict_invariants: bool,
+    /// If true, every node is lowered to its candidate with the most evidence rather than a union of its candidates, and
+    /// the candidates that were not picked are recorded as [RejectedAlternatives], so no union is emitted.
+    pub single_type: bool,
+}
+
+impl Default for LoweringOptions {
+    fn default() -> LoweringOptions {
+        LoweringOptions {
+            emit_unions: true,
+            max_union_arity: None,
+            union_overflow: UnionOverflow::default(),
+            synthesize_padding: false,
+            primitive_table: PrimitiveTable::default(),
+            compress_aliases: false,
+            canonical_primitives: false,
+            detect_tagged_unions: false,
+            detect_linked_structures: true,
+            infer_nullability: false,
+            infer_const_parameters: false,
+            infer_array_extents: false,
+            infer_character_types: false,
+            count_field_accesses: false,
+            max_field_offsets: None,
+            lower_reachable_only: false,
+            conflict_strategy: ConflictStrategy::default(),
+            strict_invariants: false,
+            single_type: false,
+        }
+    }
+}
+
+impl LoweringOptions {
+    fn should_emit_union(&self, num_members: usize) -> bool {
+        self.emit_unions
+            && !self.single_type
+            && self
+                .max_union_arity
+                .map(|max_arity| num_members <= max_arity)
+                .unwrap_or(true)
+    }
+
+    fn exceeds_union_arity(&self, num_members: usize) -> bool {
+        self.emit_unions
+            && !self.single_type
+            && self
+                .max_union_arity
+                .map(|max_arity| num_members > max_arity)
+                .unwrap_or(false)
+    }
+}
+
+/// A candidate type of a node and the evidence for it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ScoredType {
+    /// The candidate type
+    pub ty: CType,
+    /// The number of capabilities, fields, parameters, or bounds supporting the candidate
+    pub evidence: usize,
+}
+
+/// The candidate types of a node that was lowered to a single type rather than a union.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RejectedAlternatives {
+    /// The node
+    pub node: NodeIndex,
+    /// The index of the parameter or return of the function node the candidates are for, if any
+    pub parameter: Option<usize>,
+    /// The candidate the node was lowered to
+    pub chosen: ScoredType,
+    /// The candidates that were not picked, in order of preference
+    pub rejected: Vec<ScoredType>,
+}
+
+/// The types lowered by a [LoweringContext] along with what was found while lowering them.
+#[derive(Debug, Clone)]
+pub struct LoweredTypes {
+    /// The type of each lowered node
+    pub node_types: BTreeMap<NodeIndex, TypeId>,
+    /// The lowered types
+    pub types: BTreeMap<TypeId, CType>,
+    /// The candidates rejected for the nodes lowered to a single type, see [LoweringOptions::single_type]
+    pub rejected: Vec<RejectedAlternatives>,
+    /// The lowered types that break an invariant
+    pub violations: Vec<invariants::InvariantViolation>,
+}
+
+/// How a node with more candidate types than the maximum union arity is lowered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnionOverflow {
+    /// Lower to the most preferred candidate, as if unions were disabled
+    PickPreferred,
+    /// Lower to the default lattice element, reported as a diagnostic
+    Top,
+    /// Lower to the primitive that is the least upper bound of the lattice bounds of the candidate nodes, reported as a diagnostic
+    LeastUpperBound,
+}
+
+impl Default for UnionOverflow {
+    fn default() -> Self {
+        UnionOverflow::PickPreferred
+    }
+}
+
+impl std::str::FromStr for UnionOverflow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pick-preferred" => Ok(UnionOverflow::PickPreferred),
+            "top" => Ok(UnionOverflow::Top),
+            "least-upper-bound" => Ok(UnionOverflow::LeastUpperBound),
+            _ => Err(anyhow::anyhow!(
+                "unknown union overflow {}, expected pick-preferred, top or least-upper-bound",
+                s
+            )),
+        }
+    }
 }
 
+/// The name of the primitive used for synthesized structure padding.
+pub const PADDING_PRIMITIVE: &str = "padding";
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone)]
 /// Represents a parameter at a given index.
 pub struct Parameter {
     index: usize,
     type_index: TypeId,
+    is_const: bool,
+}
+
+impl Parameter {
+    /// The index of the parameter
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The type of the parameter
+    pub fn type_index(&self) -> TypeId {
+        self.type_index
+    }
+
+    /// True if the function never stores through the parameter
+    pub fn is_const(&self) -> bool {
+        self.is_const
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -63,6 +380,31 @@ pub struct Field {
     byte_offset: usize,
     bit_sz: usize,
     type_index: TypeId,
+    #[serde(default)]
+    access_count: usize,
+}
+
+impl Field {
+    /// The byte offset of the field in its structure
+    pub fn byte_offset(&self) -> usize {
+        self.byte_offset
+    }
+
+    /// The size of the field in bits
+    pub fn bit_size(&self) -> usize {
+        self.bit_sz
+    }
+
+    /// The type of the field
+    pub fn type_index(&self) -> TypeId {
+        self.type_index
+    }
+
+    /// The number of distinct loads and stores accessing the field, 0 if accesses were not counted or the field is
+    /// synthesized, like padding. Arrays and unions of tagged unions sum the accesses of the fields they cover.
+    pub fn access_count(&self) -> usize {
+        self.access_count
+    }
 }
 
 #[derive(PartialEq, Eq)]
@@ -134,11 +476,12 @@ impl PartialOrd for Classroom {
     }
 }
 
-fn translate_field(field: &constraints::Field, idx: TypeId) -> Option<Field> {
+fn translate_field(field: &constraints::Field, idx: TypeId, access_count: usize) -> Option<Field> {
     usize::try_from(field.offset).ok().map(|off| Field {
         byte_offset: off,
         bit_sz: field.size,
         type_index: idx,
+        access_count,
     })
 }
 
@@ -215,45 +558,80 @@ fn build_alias_types<U: NamedLatticeElement>(
     unique_tgts.into_iter().map(CType::Alias).collect()
 }
 
-fn field_to_protobuf(internal_field: Field) -> ctypes::Field {
-    ctypes::Field {
-        bit_size: internal_field.bit_sz.try_into().unwrap(),
-        byte_offset: internal_field.byte_offset.try_into().unwrap(),
-        type_id: Some(convert_typeid(internal_field.type_index)),
-    }
+fn field_to_protobuf(internal_field: Field) -> anyhow::Result<ctypes::Field> {
+    Ok(ctypes::Field {
+        bit_size: internal_field
+            .bit_sz
+            .try_into()
+            .with_context(|| format!("bit size {} exceeds u32", internal_field.bit_sz))?,
+        byte_offset: internal_field
+            .byte_offset
+            .try_into()
+            .with_context(|| format!("byte offset {} exceeds u32", internal_field.byte_offset))?,
+        type_id: Some(convert_typeid(internal_field.type_index)?),
+        name: String::new(),
+        access_count: internal_field
+            .access_count
+            .try_into()
+            .with_context(|| format!("access count {} exceeds u32", internal_field.access_count))?,
+    })
+}
+
+fn param_to_protofbuf(internal_param: Parameter) -> anyhow::Result<ctypes::Parameter> {
+    Ok(ctypes::Parameter {
+        parameter_index: internal_param
+            .index
+            .try_into()
+            .with_context(|| format!("parameter index {} exceeds u32", internal_param.index))?,
+        type_index: Some(convert_typeid(internal_param.type_index)?),
+        is_const: internal_param.is_const,
+    })
 }
 
-fn param_to_protofbuf(internal_param: Parameter) -> ctypes::Parameter {
-    ctypes::Parameter {
-        parameter_index: internal_param.index.try_into().unwrap(),
-        type_index: Some(convert_typeid(internal_param.type_index)),
+fn nullability_to_protobuf(nullability: Nullability) -> ctypes::Nullability {
+    match nullability {
+        Nullability::Unknown => ctypes::Nullability::Unknown,
+        Nullability::Nullable => ctypes::Nullability::Nullable,
+        Nullability::NonNull => ctypes::Nullability::Nonnull,
     }
 }
 
+/// The version of the ctypes schema written by [convert_mapping_to_profobuf].
+/// Version 2 widened type ids to 64 bits.
+pub const CTYPES_SCHEMA_VERSION: u32 = 2;
+
 /// Converts a type id to protobuf
-pub fn convert_typeid(type_id: TypeId) -> ctypes::TypeId {
-    ctypes::TypeId {
-        type_id: u32::try_from(type_id.0).unwrap(),
-    }
+pub fn convert_typeid(type_id: TypeId) -> anyhow::Result<ctypes::TypeId> {
+    Ok(ctypes::TypeId {
+        type_id: u64::try_from(type_id.0)
+            .with_context(|| format!("type id {} exceeds u64", type_id.0))?,
+    })
 }
 
 /// Converts an in memory [CType] to a protobuf representation of the enum
 pub fn produce_inner_types(
     ct: CType,
-    mp: &HashMap<NodeIndex, TypeId>,
-) -> ctypes::c_type::InnerType {
-    match ct {
-        CType::Alias(tgt) => ctypes::c_type::InnerType::Alias(ctypes::Alias {
-            to_type: mp.get(&tgt).map(|tyid| convert_typeid(*tyid)),
-        }),
+    mp: &BTreeMap<NodeIndex, TypeId>,
+) -> anyhow::Result<ctypes::c_type::InnerType> {
+    Ok(match ct {
+        CType::Alias(tgt) => {
+            if !mp.contains_key(&tgt) {
+                let message = format!("Alias to node {} that was not lowered", tgt.index());
+                diagnostics::report(DiagnosticKind::DroppedConversion, message.clone());
+                log::warn!("{}", message);
+            }
+            ctypes::c_type::InnerType::Alias(ctypes::Alias {
+                to_type: mp.get(&tgt).map(|tyid| convert_typeid(*tyid)).transpose()?,
+            })
+        }
         CType::Function { params, return_ty } => {
             let mut func = ctypes::Function::default();
-            params
-                .into_iter()
-                .for_each(|x| func.parameters.push(param_to_protofbuf(x)));
+            for x in params.into_iter() {
+                func.parameters.push(param_to_protofbuf(x)?);
+            }
 
             if let Some(return_ty) = return_ty {
-                func.return_type = Some(convert_typeid(return_ty));
+                func.return_type = Some(convert_typeid(return_ty)?);
                 func.has_return = true;
             } else {
                 func.has_return = false;
@@ -261,51 +639,207 @@ pub fn produce_inner_types(
 
             ctypes::c_type::InnerType::Function(func)
         }
-        CType::Pointer { target } => ctypes::c_type::InnerType::Pointer(ctypes::Pointer {
-            to_type_id: Some(convert_typeid(target)),
+        CType::Pointer {
+            target,
+            is_const,
+            nullability,
+        } => ctypes::c_type::InnerType::Pointer(ctypes::Pointer {
+            to_type_id: Some(convert_typeid(target)?),
+            is_const,
+            nullability: nullability_to_protobuf(nullability) as i32,
         }),
         CType::Primitive(val) => {
             ctypes::c_type::InnerType::Primitive(ctypes::Primitive { type_constant: val })
         }
         CType::Structure(fields) => {
             let mut st = ctypes::Structure::default();
-            fields
-                .into_iter()
-                .for_each(|x| st.fields.push(field_to_protobuf(x)));
+            for x in fields.into_iter() {
+                st.fields.push(field_to_protobuf(x)?);
+            }
 
             ctypes::c_type::InnerType::Structure(st)
         }
         CType::Union(children) => {
             let mut union = ctypes::Union::default();
-            children
-                .into_iter()
-                .for_each(|x| union.target_type_ids.push(convert_typeid(x)));
+            for x in children.into_iter() {
+                union.target_type_ids.push(convert_typeid(x)?);
+            }
 
             ctypes::c_type::InnerType::Union(union)
         }
-    }
+        CType::Array { element, count } => ctypes::c_type::InnerType::Array(ctypes::Array {
+            element_type: Some(convert_typeid(element)?),
+            count: u32::try_from(count)
+                .with_context(|| format!("array count {} exceeds u32", count))?,
+        }),
+    })
 }
 
-// TODO(ian): dont unwrap u32s
 /// Converts a mapping from NodeIndex's to CTypes to a protobuf representation [CTypeMapping].
+/// The labeling from terms to nodes is used to key the type of each term (eg. the prototype of a subprocedure) by its tid.
+/// A type with a value that does not fit in the width of its protobuf field is converted to an alias to no type, and a
+/// type id that does not fit is dropped along with the terms of that type. Each failure is reported as a
+/// [DiagnosticKind::DroppedConversion] naming the terms of the type, and the rest of the mapping is kept.
 pub fn convert_mapping_to_profobuf(
     mp: BTreeMap<TypeId, CType>,
-    node_to_ty: &HashMap<NodeIndex, TypeId>,
-) -> CTypeMapping {
-    let mut mapping = CTypeMapping::default();
-
-    mp.into_iter().for_each(|(idx, ctype)| {
-        let ctype = produce_inner_types(ctype, node_to_ty);
-        mapping.type_id_to_ctype.insert(
-            convert_typeid(idx).type_id,
-            ctypes::CType {
-                type_id: Some(convert_typeid(idx)),
-                inner_type: Some(ctype),
+    node_to_ty: &BTreeMap<NodeIndex, TypeId>,
+    tid_to_node: &BTreeMap<Tid, NodeIndex>,
+) -> anyhow::Result<CTypeMapping> {
+    let mut mapping = CTypeMapping {
+        schema_version: CTYPES_SCHEMA_VERSION,
+        ..CTypeMapping::default()
+    };
+
+    let dropped = |location: Option<&Tid>, err: anyhow::Error| {
+        let message = format!("{:#}", err);
+        diagnostics::report_at(DiagnosticKind::DroppedConversion, location, message.clone());
+        log::warn!("{}", message);
+    };
+
+    let mut type_terms: BTreeMap<TypeId, Vec<&Tid>> = BTreeMap::new();
+    for (tid, nd_idx) in tid_to_node.iter() {
+        if let Some(ty) = node_to_ty.get(nd_idx) {
+            match convert_typeid(*ty)
+                .with_context(|| format!("converting the type of {}", tid.get_str_repr()))
+            {
+                Ok(type_id) => {
+                    mapping
+                        .tid_to_type_id
+                        .insert(tid.get_str_repr().to_owned(), type_id);
+                }
+                Err(err) => dropped(Some(tid), err),
+            }
+            type_terms.entry(*ty).or_default().push(tid);
+        }
+    }
+
+    for (idx, ctype) in mp.into_iter() {
+        let terms = type_terms.get(&idx);
+        let describe = || match terms {
+            Some(tids) => format!(
+                "converting the type of {}",
+                tids.iter()
+                    .map(|tid| tid.get_str_repr())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            None => format!("converting type {} which is not the type of a term", idx),
+        };
+        let location = terms.and_then(|tids| tids.first().copied());
+
+        let type_id = match convert_typeid(idx).with_context(describe) {
+            Ok(type_id) => type_id,
+            Err(err) => {
+                dropped(location, err);
+                continue;
+            }
+        };
+        let ctype = convert_ctype(idx, ctype, node_to_ty)
+            .with_context(describe)
+            .unwrap_or_else(|err| {
+                dropped(location, err);
+                ctypes::CType {
+                    type_id: Some(type_id.clone()),
+                    inner_type: Some(ctypes::c_type::InnerType::Alias(ctypes::Alias {
+                        to_type: None,
+                    })),
+                }
+            });
+        mapping.type_id_to_ctype.insert(type_id.type_id, ctype);
+    }
+
+    Ok(mapping)
+}
+
+fn convert_ctype(
+    idx: TypeId,
+    ctype: CType,
+    node_to_ty: &BTreeMap<NodeIndex, TypeId>,
+) -> anyhow::Result<ctypes::CType> {
+    let inner_type = produce_inner_types(ctype, node_to_ty)
+        .with_context(|| format!("converting type {}", idx.0))?;
+    Ok(ctypes::CType {
+        type_id: Some(convert_typeid(idx)?),
+        inner_type: Some(inner_type),
+    })
+}
+
+fn resolve_alias(
+    ty: TypeId,
+    node_types: &BTreeMap<NodeIndex, TypeId>,
+    types: &BTreeMap<TypeId, CType>,
+) -> TypeId {
+    let mut seen = BTreeSet::new();
+    let mut curr = ty;
+    while let Some(CType::Alias(tgt)) = types.get(&curr) {
+        if !seen.insert(curr) {
+            // alias cycle, keep the original alias
+            return ty;
+        }
+
+        match node_types.get(tgt) {
+            Some(tgt_ty) => curr = *tgt_ty,
+            None => return curr,
+        }
+    }
+
+    curr
+}
+
+/// Replaces every reference to an alias type with the type of the node the alias refers to,
+/// removing aliases that are no longer referenced.
+fn compress_aliases(
+    node_types: &mut BTreeMap<NodeIndex, TypeId>,
+    types: &mut BTreeMap<TypeId, CType>,
+) {
+    let resolved: HashMap<TypeId, TypeId> = types
+        .keys()
+        .map(|ty| (*ty, resolve_alias(*ty, node_types, types)))
+        .collect();
+
+    let lookup = |ty: TypeId| *resolved.get(&ty).unwrap_or(&ty);
+
+    types.values_mut().for_each(|ct| ct.map_type_ids(&lookup));
+    node_types.values_mut().for_each(|ty| *ty = lookup(*ty));
+
+    let referenced: BTreeSet<TypeId> = node_types
+        .values()
+        .cloned()
+        .chain(types.values().flat_map(|ct| ct.referenced_type_ids()))
+        .collect();
+
+    types.retain(|ty, ct| !matches!(ct, CType::Alias(_)) || referenced.contains(ty));
+}
+
+/// Replaces every reference to a primitive type, or to an alias that resolves to one, with the lowest type id of a
+/// primitive of the same name, removing the duplicates. Terminal nodes with the same lattice bounds already share a
+/// cached primitive, but the nodes and fields that alias them still carry an alias type of their own.
+fn canonicalize_primitives(
+    node_types: &mut BTreeMap<NodeIndex, TypeId>,
+    types: &mut BTreeMap<TypeId, CType>,
+) {
+    let mut canonical: BTreeMap<&str, TypeId> = BTreeMap::new();
+    for (ty, ct) in types.iter() {
+        if let CType::Primitive(nm) = ct {
+            canonical.entry(nm.as_str()).or_insert(*ty);
+        }
+    }
+
+    let resolved: HashMap<TypeId, TypeId> = types
+        .keys()
+        .filter_map(
+            |ty| match types.get(&resolve_alias(*ty, node_types, types)) {
+                Some(CType::Primitive(nm)) => canonical.get(nm.as_str()).map(|canon| (*ty, *canon)),
+                _ => None,
             },
-        );
-    });
+        )
+        .collect();
+
+    let lookup = |ty: TypeId| *resolved.get(&ty).unwrap_or(&ty);
 
-    mapping
+    types.values_mut().for_each(|ct| ct.map_type_ids(&lookup));
+    node_types.values_mut().for_each(|ty| *ty = lookup(*ty));
+    types.retain(|ty, _| lookup(*ty) == *ty);
 }
 
 /// The context needed to attempt to lower a node to a ctype.
@@ -318,6 +852,19 @@ pub struct LoweringContext<'a, U: NamedLatticeElement> {
     ephemeral_types: BTreeMap<TypeId, CType>,
     cached_primitivies: BTreeMap<String, TypeId>,
     curr_id: usize,
+    options: LoweringOptions,
+    linked_structures: BTreeMap<NodeIndex, LinkedStructure>,
+    tagged_unions: BTreeMap<NodeIndex, TaggedUnionLayout>,
+    nullability: BTreeMap<NodeIndex, Nullability>,
+    array_extents: BTreeMap<NodeIndex, Vec<ArrayLayout>>,
+    field_accesses: BTreeMap<NodeIndex, BTreeMap<constraints::Field, usize>>,
+    character_types: BTreeMap<NodeIndex, CharacterClass>,
+    fat_pointers: BTreeMap<NodeIndex, Vec<FatPointer>>,
+    roots: Option<BTreeSet<NodeIndex>>,
+    conflicts: BTreeMap<NodeIndex, LatticeConflict>,
+    size_mismatches: BTreeMap<NodeIndex, SizeMismatch>,
+    parameter_count_mismatches: BTreeMap<NodeIndex, ParameterCountMismatch>,
+    rejected_alternatives: Vec<RejectedAlternatives>,
 }
 
 impl<'a, U: NamedLatticeElement> LoweringContext<'a, U> {
@@ -326,30 +873,282 @@ impl<'a, U: NamedLatticeElement> LoweringContext<'a, U> {
         nd: NodeIndex,
         grph: &SketchGraph<LatticeBounds<U>>,
     ) -> Vec<CType> {
-        // check if this is an actual  structure
-        if !has_non_zero_fields(nd, grph) {
+        let arrays = self.array_extents.get(&nd).cloned().unwrap_or_default();
+        // check if this is an actual  structure, objects indexed as arrays are structures even if only the first element is accessed
+        if !has_non_zero_fields(nd, grph) && arrays.is_empty() {
             return Vec::new();
         }
 
-        schedule_structures(
-            &grph
-                .get_graph()
-                .get_graph()
-                .edges_directed(nd, EdgeDirection::Outgoing)
-                .filter_map(|e| {
-                    if let constraints::FieldLabel::Field(fld) = e.weight() {
-                        translate_field(fld, self.add_type(CType::Alias(e.target())))
+        // recursive fields of linked structures point to the structure itself rather than whatever the field node lowers to
+        let recursive_fields = self
+            .linked_structures
+            .get(&nd)
+            .map(|linked| linked.recursive_fields.clone())
+            .unwrap_or_default();
+        let accesses = self.field_accesses.get(&nd).cloned().unwrap_or_default();
+        let fields = grph
+            .get_graph()
+            .get_graph()
+            .edges_directed(nd, EdgeDirection::Outgoing)
+            .filter_map(|e| {
+                if let constraints::FieldLabel::Field(fld) = e.weight() {
+                    let fld_ty = if recursive_fields.contains(fld) {
+                        let self_ty = self.add_type(CType::Alias(nd));
+                        self.add_type(CType::Pointer {
+                            target: self_ty,
+                            is_const: false,
+                            nullability: Nullability::Unknown,
+                        })
                     } else {
-                        None
-                    }
+                        self.add_type(CType::Alias(e.target()))
+                    };
+                    translate_field(fld, fld_ty, accesses.get(fld).copied().unwrap_or(0))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let fields = if arrays.is_empty() {
+            fields
+        } else {
+            let fields = self.build_arrays(fields, &arrays);
+            // an object that is only an array is the array itself rather than a structure wrapping it
+            if let [fld] = fields.as_slice() {
+                let is_array = matches!(
+                    self.ephemeral_types.get(&fld.type_index),
+                    Some(CType::Array { .. })
+                );
+                if fld.byte_offset == 0 && is_array {
+                    let arr = self.ephemeral_types.remove(&fld.type_index);
+                    return arr.into_iter().collect();
+                }
+            }
+            fields
+        };
+        let fields = match self.options.max_field_offsets {
+            Some(max_offsets) => self.widen_fields(fields, max_offsets),
+            None => fields,
+        };
+
+        // a tagged union would be a union member of the structure
+        let tagged_union = self
+            .tagged_unions
+            .get(&nd)
+            .filter(|_| !self.options.single_type)
+            .cloned()
+            .and_then(|layout| self.build_tagged_union(&fields, &layout));
+        let structs = match tagged_union {
+            Some(st) => vec![st],
+            None => schedule_structures(&fields),
+        };
+
+        if self.options.synthesize_padding {
+            structs
+                .into_iter()
+                .map(|st| match st {
+                    CType::Structure(flds) => CType::Structure(self.pad_fields(flds)),
+                    _ => st,
                 })
-                .collect::<Vec<_>>(),
-        )
+                .collect()
+        } else {
+            structs
+        }
     }
 
-    fn build_terminal_type(&mut self, nd_bounds: &LatticeBounds<U>) -> TypeId {
-        // TODO(Ian): be more clever
-        let nm = nd_bounds.get_upper().get_name();
+    // Builds a structure with the fields outside of the variants and a union of the variants. Fields overlapping the variants
+    // that were not accessed in any variant are collected into an additional variant.
+    fn build_tagged_union(
+        &mut self,
+        fields: &[Field],
+        layout: &TaggedUnionLayout,
+    ) -> Option<CType> {
+        let in_variant = |fld: &Field, variant: &BTreeSet<constraints::Field>| {
+            i64::try_from(fld.byte_offset)
+                .map(|off| variant.contains(&constraints::Field::new(off, fld.bit_sz)))
+                .unwrap_or(false)
+        };
+
+        let mut variants = layout
+            .variants
+            .iter()
+            .map(|variant| {
+                fields
+                    .iter()
+                    .filter(|fld| in_variant(*fld, variant))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .filter(|variant| !variant.is_empty())
+            .collect::<Vec<_>>();
+
+        if variants.len() < 2 {
+            return None;
+        }
+
+        let union_start = variants.iter().flatten().map(|fld| fld.byte_offset).min()?;
+        let union_end = variants
+            .iter()
+            .flatten()
+            .map(Classroom::compute_fld_upper_bound_exlcusive)
+            .max()?;
+
+        let (in_union, mut common): (Vec<Field>, Vec<Field>) = fields
+            .iter()
+            .filter(|fld| {
+                !layout
+                    .variants
+                    .iter()
+                    .any(|variant| in_variant(*fld, variant))
+            })
+            .cloned()
+            .partition(|fld| {
+                fld.byte_offset < union_end
+                    && Classroom::compute_fld_upper_bound_exlcusive(fld) > union_start
+            });
+        if !in_union.is_empty() {
+            variants.push(in_union);
+        }
+
+        let union_accesses = variants.iter().flatten().map(|fld| fld.access_count).sum();
+        let mut members = BTreeSet::new();
+        for variant in variants.into_iter() {
+            let rebased = variant
+                .into_iter()
+                .map(|fld| Field {
+                    byte_offset: fld.byte_offset - union_start,
+                    ..fld
+                })
+                .collect::<Vec<_>>();
+            for st in schedule_structures(&rebased) {
+                members.insert(self.add_type(st));
+            }
+        }
+
+        let union_ty = self.add_type(CType::Union(members));
+        common.push(Field {
+            byte_offset: union_start,
+            bit_sz: (union_end - union_start) * 8,
+            type_index: union_ty,
+            access_count: union_accesses,
+        });
+
+        // the discriminant and other common fields should not conflict with each other
+        let mut scheduled = schedule_structures(&common);
+        if scheduled.len() == 1 {
+            scheduled.pop()
+        } else {
+            None
+        }
+    }
+
+    // Replaces the fields covered by each array with a single array field. The element type is the type of the field
+    // at the start of the array if it has the size of an element.
+    fn build_arrays(&mut self, fields: Vec<Field>, arrays: &[ArrayLayout]) -> Vec<Field> {
+        let mut fields = fields;
+        for layout in arrays.iter() {
+            let start = match usize::try_from(layout.offset) {
+                Ok(start) => start,
+                Err(_) => continue,
+            };
+            // the counts come from loop bounds, a layout past the end of the address space is not an array
+            let (end, bit_sz) = match layout
+                .stride
+                .checked_mul(layout.count)
+                .and_then(|size| Some((start.checked_add(size)?, size.checked_mul(8)?)))
+            {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+
+            let (covered, rest): (Vec<Field>, Vec<Field>) = fields.into_iter().partition(|fld| {
+                fld.byte_offset >= start && Classroom::compute_fld_upper_bound_exlcusive(fld) <= end
+            });
+            fields = rest;
+
+            let element = match covered.iter().find(|fld| {
+                fld.byte_offset == start && layout.stride.checked_mul(8) == Some(fld.bit_sz)
+            }) {
+                Some(fld) => fld.type_index,
+                None => {
+                    let default_elem = self.default_lattice_elem.clone();
+                    self.build_terminal_type(&default_elem)
+                }
+            };
+
+            let array_ty = self.add_type(CType::Array {
+                element,
+                count: layout.count,
+            });
+            fields.push(Field {
+                byte_offset: start,
+                bit_sz,
+                type_index: array_ty,
+                access_count: covered.iter().map(|fld| fld.access_count).sum(),
+            });
+        }
+
+        fields
+    }
+
+    // Replaces the fields starting at or past the first offset beyond the max_offsets distinct offsets of fields with a
+    // single array of bytes, so buffers parsed at many constant offsets do not get a field per offset.
+    fn widen_fields(&mut self, fields: Vec<Field>, max_offsets: usize) -> Vec<Field> {
+        let offsets = fields
+            .iter()
+            .map(|fld| fld.byte_offset)
+            .collect::<BTreeSet<_>>();
+        let start = match offsets.into_iter().nth(max_offsets) {
+            Some(start) => start,
+            None => return fields,
+        };
+
+        let (widened, mut kept): (Vec<Field>, Vec<Field>) =
+            fields.into_iter().partition(|fld| fld.byte_offset >= start);
+        let end = widened
+            .iter()
+            .map(Classroom::compute_fld_upper_bound_exlcusive)
+            .max()
+            .unwrap_or(start);
+
+        let element = self.build_named_primitive("uint8_t");
+        let array_ty = self.add_type(CType::Array {
+            element,
+            count: end - start,
+        });
+        kept.push(Field {
+            byte_offset: start,
+            bit_sz: (end - start) * 8,
+            type_index: array_ty,
+            access_count: widened.iter().map(|fld| fld.access_count).sum(),
+        });
+        kept
+    }
+
+    fn pad_fields(&mut self, mut flds: Vec<Field>) -> Vec<Field> {
+        flds.sort_by_key(|x| x.byte_offset);
+
+        let mut padded = Vec::new();
+        let mut curr_off = 0;
+        for fld in flds.into_iter() {
+            if fld.byte_offset > curr_off {
+                let padding_ty = self.build_named_primitive(PADDING_PRIMITIVE);
+                padded.push(Field {
+                    byte_offset: curr_off,
+                    bit_sz: (fld.byte_offset - curr_off) * 8,
+                    type_index: padding_ty,
+                    access_count: 0,
+                });
+            }
+
+            curr_off = Classroom::compute_fld_upper_bound_exlcusive(&fld);
+            padded.push(fld);
+        }
+
+        padded
+    }
+
+    fn build_named_primitive(&mut self, nm: &str) -> TypeId {
         if let Some(id) = self.cached_primitivies.get(nm) {
             return *id;
         }
@@ -360,6 +1159,188 @@ impl<'a, U: NamedLatticeElement> LoweringContext<'a, U> {
         res
     }
 
+    fn build_terminal_type(&mut self, nd_bounds: &LatticeBounds<U>) -> TypeId {
+        // TODO(Ian): be more clever
+        let nm = self
+            .options
+            .primitive_table
+            .get_c_name(nd_bounds.get_upper().get_name())
+            .to_owned();
+        self.build_named_primitive(&nm)
+    }
+
+    // The primitive a node that is also a pointer is bounded by, its upper bound unless only its lower bound is known.
+    // Bottom is never picked since it has no values to lower to.
+    fn primitive_bound_name(&self, nd_bounds: &LatticeBounds<U>) -> String {
+        let bound = [nd_bounds.get_upper(), nd_bounds.get_lower()]
+            .iter()
+            .find(|bound| !bound.is_top() && !bound.is_bot())
+            .copied()
+            .unwrap_or_else(|| self.default_lattice_elem.get_upper());
+        self.options
+            .primitive_table
+            .get_c_name(bound.get_name())
+            .to_owned()
+    }
+
+    // Clashing bounds are lowered by the upper bound unless conflicts are reported, in which case both bounds are kept.
+    // A bound degraded to bottom is dropped from the reported union rather than lowered.
+    fn build_conflicting_terminal_type(&mut self, nd_bounds: &LatticeBounds<U>) -> TypeId {
+        if self.options.conflict_strategy != ConflictStrategy::ReportConflict {
+            return self.build_terminal_type(nd_bounds);
+        }
+
+        let bounds = [nd_bounds.get_lower(), nd_bounds.get_upper()]
+            .iter()
+            .filter(|bound| !bound.is_bot())
+            .map(|bound| {
+                self.options
+                    .primitive_table
+                    .get_c_name(bound.get_name())
+                    .to_owned()
+            })
+            .collect::<Vec<_>>();
+        let union = bounds
+            .iter()
+            .map(|nm| self.build_named_primitive(nm))
+            .collect::<BTreeSet<_>>();
+        match union.len() {
+            0 => {
+                let nm = self.primitive_bound_name(nd_bounds);
+                self.build_named_primitive(&nm)
+            }
+            // the upper bound is kept last so it is picked when unions are disabled
+            n if n == 1 || !self.options.should_emit_union(n) => {
+                self.build_named_primitive(bounds.last().unwrap())
+            }
+            _ => self.add_type(CType::Union(union)),
+        }
+    }
+
+    // Collapses num_candidates candidates for the type of nodes to a single primitive if they exceed the maximum union arity
+    // and the overflow is not lowered to the preferred candidate.
+    fn collapse_union(
+        &mut self,
+        nodes: &[NodeIndex],
+        num_candidates: usize,
+        grph: &SketchGraph<LatticeBounds<U>>,
+    ) -> Option<TypeId> {
+        if num_candidates <= 1 || !self.options.exceeds_union_arity(num_candidates) {
+            return None;
+        }
+
+        let bound = match self.options.union_overflow {
+            UnionOverflow::PickPreferred => return None,
+            UnionOverflow::Top => self.default_lattice_elem.get_upper().clone(),
+            UnionOverflow::LeastUpperBound => {
+                let act_graph = grph.get_graph().get_graph();
+                nodes
+                    .iter()
+                    .map(|nd| {
+                        let bounds = &act_graph[*nd];
+                        if bounds.get_upper().is_top() {
+                            bounds.get_lower().clone()
+                        } else {
+                            bounds.get_upper().clone()
+                        }
+                    })
+                    .reduce(|lhs, rhs| lhs.join(&rhs))
+                    .unwrap_or_else(|| self.default_lattice_elem.get_upper().clone())
+            }
+        };
+        let nm = self
+            .options
+            .primitive_table
+            .get_c_name(bound.get_name())
+            .to_owned();
+
+        diagnostics::report(
+            DiagnosticKind::CollapsedUnion,
+            &format!(
+                "Collapsed {} candidate types of {} to {}",
+                num_candidates,
+                nodes
+                    .iter()
+                    .map(|nd| nd.index().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                nm
+            ),
+        );
+        Some(self.build_named_primitive(&nm))
+    }
+
+    // The evidence for a candidate type of nd: the fields of structures, the parameters and return of functions, the
+    // capabilities of the nodes pointers, element regions, and aliases refer to, and the bounds of conflicting primitives.
+    fn candidate_evidence(
+        &self,
+        nd: NodeIndex,
+        ty: &CType,
+        grph: &SketchGraph<LatticeBounds<U>>,
+    ) -> usize {
+        let act_graph = grph.get_graph().get_graph();
+        let node_evidence = |tgt: NodeIndex| {
+            1 + act_graph
+                .edges_directed(tgt, EdgeDirection::Outgoing)
+                .count()
+        };
+        let referenced_evidence = |id: &TypeId| match self.ephemeral_types.get(id) {
+            Some(CType::Alias(tgt)) => node_evidence(*tgt),
+            _ => 1,
+        };
+
+        match ty {
+            CType::Structure(fields) => {
+                fields.len() + fields.iter().map(|fld| fld.access_count).sum::<usize>()
+            }
+            CType::Function { params, return_ty } => {
+                params.len() + usize::from(return_ty.is_some())
+            }
+            CType::Pointer { target, .. } => referenced_evidence(target),
+            CType::Array { element, .. } => referenced_evidence(element),
+            CType::Alias(tgt) => node_evidence(*tgt),
+            CType::Union(members) => members.len(),
+            CType::Primitive(_) => self
+                .conflicts
+                .get(&nd)
+                .map(|conflict| conflict.primitive_evidence)
+                .unwrap_or(1),
+        }
+    }
+
+    // Lowers nd to the candidate with the most evidence, preferring earlier candidates on ties, and records the others.
+    fn pick_single_type(
+        &mut self,
+        nd: NodeIndex,
+        parameter: Option<usize>,
+        candidates: Vec<CType>,
+        grph: &SketchGraph<LatticeBounds<U>>,
+    ) -> TypeId {
+        let mut scored = candidates
+            .into_iter()
+            .map(|ty| ScoredType {
+                evidence: self.candidate_evidence(nd, &ty, grph),
+                ty,
+            })
+            .collect::<Vec<_>>();
+        let best = scored
+            .iter()
+            .enumerate()
+            .max_by_key(|(i, scored)| (scored.evidence, Reverse(*i)))
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+
+        let chosen = scored.remove(best);
+        let ty = self.add_type(chosen.ty.clone());
+        self.rejected_alternatives.push(RejectedAlternatives {
+            node: nd,
+            parameter,
+            chosen,
+            rejected: scored,
+        });
+        ty
+    }
+
     fn build_pointer_types(
         &mut self,
         nd: NodeIndex,
@@ -379,18 +1360,52 @@ impl<'a, U: NamedLatticeElement> LoweringContext<'a, U> {
             .into_iter()
             .map(|tgt| CType::Pointer {
                 target: self.add_type(CType::Alias(tgt)),
+                is_const: false,
+                nullability: Nullability::Unknown,
+            })
+            .collect()
+    }
+
+    // Elements accessed at an unknown offset make the node a region of elements with an unknown extent.
+    fn build_element_types(
+        &mut self,
+        nd: NodeIndex,
+        grph: &SketchGraph<LatticeBounds<U>>,
+    ) -> Vec<CType> {
+        let element_targets = grph
+            .get_graph()
+            .get_graph()
+            .edges_directed(nd, EdgeDirection::Outgoing)
+            .filter(|e| matches!(e.weight(), FieldLabel::Element(_)))
+            .map(|e| e.target())
+            .collect::<BTreeSet<_>>();
+
+        element_targets
+            .into_iter()
+            .map(|tgt| CType::Array {
+                element: self.add_type(CType::Alias(tgt)),
+                count: 0,
             })
             .collect()
     }
 
     /// Creates a new type lowering context from a mapping from term to node,
-    /// a mapping from subprocedure term to out parameters and a defualt lattice element.
+    /// a mapping from subprocedure term to out parameters, a defualt lattice element,
+    /// and the options controlling lowering heuristics.
     pub fn new<'b>(
         grph: &'b SketchGraph<LatticeBounds<U>>,
-        tid_to_node_index: &HashMap<Tid, NodeIndex>,
+        tid_to_node_index: &BTreeMap<Tid, NodeIndex>,
         out_param_mapping: &HashMap<Tid, Vec<Arg>>,
         default_lattice_elem: LatticeBounds<U>,
+        options: LoweringOptions,
     ) -> LoweringContext<'b, U> {
+        let size_mismatches =
+            conflicts::find_size_mismatches(grph, &options.primitive_table, tid_to_node_index);
+        let linked_structures = if options.detect_linked_structures {
+            linked::detect_linked_structures(grph.get_graph().get_graph())
+        } else {
+            BTreeMap::new()
+        };
         LoweringContext {
             grph,
             out_params: out_param_mapping
@@ -408,9 +1423,98 @@ impl<'a, U: NamedLatticeElement> LoweringContext<'a, U> {
                 .max()
                 .unwrap_or(0)
                 + 1,
+            options,
+            linked_structures,
+            conflicts: conflicts::find_lattice_conflicts(grph),
+            size_mismatches,
+            parameter_count_mismatches: BTreeMap::new(),
+            tagged_unions: BTreeMap::new(),
+            nullability: BTreeMap::new(),
+            array_extents: BTreeMap::new(),
+            field_accesses: BTreeMap::new(),
+            character_types: BTreeMap::new(),
+            fat_pointers: BTreeMap::new(),
+            roots: None,
+            rejected_alternatives: Vec::new(),
         }
     }
 
+    /// Restricts [LoweringContext::collect_ctypes] to the nodes reachable from roots.
+    pub fn with_roots(mut self, roots: BTreeSet<NodeIndex>) -> LoweringContext<'a, U> {
+        self.roots = Some(roots);
+        self
+    }
+
+    /// Sets the array layouts of nodes. The fields of these nodes covered by an array are lowered to a single array field,
+    /// and a node holding only an array at offset 0 is lowered to the array itself.
+    pub fn with_array_extents(
+        mut self,
+        array_extents: BTreeMap<NodeIndex, Vec<ArrayLayout>>,
+    ) -> LoweringContext<'a, U> {
+        self.array_extents = array_extents;
+        self
+    }
+
+    /// Sets the number of loads and stores accessing each field of nodes. The counts are carried into the lowered fields
+    /// and weigh the structures of a node by how well their fields are evidenced when a single type is picked.
+    pub fn with_field_accesses(
+        mut self,
+        field_accesses: BTreeMap<NodeIndex, BTreeMap<constraints::Field, usize>>,
+    ) -> LoweringContext<'a, U> {
+        self.field_accesses = field_accesses;
+        self
+    }
+
+    /// Sets the character types of the elements of character buffers. These nodes are lowered to the C character type
+    /// of their class rather than their lattice bounds.
+    pub fn with_character_types(
+        mut self,
+        character_types: BTreeMap<NodeIndex, CharacterClass>,
+    ) -> LoweringContext<'a, U> {
+        self.character_types = character_types;
+        self
+    }
+
+    /// Sets the functions with evidence for more parameters than they declare. The signatures of these functions are
+    /// extended to the observed parameters, with the parameters that have no evidence lowered to the default type.
+    pub fn with_parameter_count_mismatches(
+        mut self,
+        parameter_count_mismatches: BTreeMap<NodeIndex, ParameterCountMismatch>,
+    ) -> LoweringContext<'a, U> {
+        self.parameter_count_mismatches = parameter_count_mismatches;
+        self
+    }
+
+    /// Sets the fat pointer parameters of function nodes. Each pair of a pointer and a length parameter is lowered to
+    /// a single parameter at the index of the pointer, a structure holding the pointer and the length like a slice.
+    pub fn with_fat_pointers(
+        mut self,
+        fat_pointers: BTreeMap<NodeIndex, Vec<FatPointer>>,
+    ) -> LoweringContext<'a, U> {
+        self.fat_pointers = fat_pointers;
+        self
+    }
+
+    /// Sets the nullability of parameter and return nodes. Pointer parameters and returns with a known
+    /// nullability are lowered to a pointer annotated with the nullability.
+    pub fn with_nullability(
+        mut self,
+        nullability: BTreeMap<NodeIndex, Nullability>,
+    ) -> LoweringContext<'a, U> {
+        self.nullability = nullability;
+        self
+    }
+
+    /// Sets the tagged union layouts of nodes. These nodes are lowered to a structure holding the discriminant
+    /// and a union of the variants.
+    pub fn with_tagged_unions(
+        mut self,
+        tagged_unions: BTreeMap<NodeIndex, TaggedUnionLayout>,
+    ) -> LoweringContext<'a, U> {
+        self.tagged_unions = tagged_unions;
+        self
+    }
+
     fn add_type(&mut self, ty: CType) -> TypeId {
         let id = self.curr_id;
         self.curr_id += 1;
@@ -419,11 +1523,43 @@ impl<'a, U: NamedLatticeElement> LoweringContext<'a, U> {
         ty_id
     }
 
+    // Gets the target of a node that is only a pointer (every edge is a load or store to the same node), and whether the node is
+    // only ever loaded from. The sketch node of a parameter merges all aliases of the parameter in the function so a missing store edge
+    // means no store happens through any alias.
+    fn pointer_target(
+        nd: NodeIndex,
+        grph: &SketchGraph<LatticeBounds<U>>,
+    ) -> Option<(NodeIndex, bool)> {
+        // custom labels carry metadata such as nullability rather than a layout
+        let mut edges = grph
+            .get_graph()
+            .get_graph()
+            .edges_directed(nd, EdgeDirection::Outgoing)
+            .filter(|e| !matches!(e.weight(), FieldLabel::Custom(_)));
+        let first = edges.next()?;
+        if !matches!(first.weight(), FieldLabel::Load | FieldLabel::Store) {
+            return None;
+        }
+
+        let tgt = first.target();
+        let mut only_loads = matches!(first.weight(), FieldLabel::Load);
+        for e in edges {
+            match e.weight() {
+                FieldLabel::Load if e.target() == tgt => (),
+                FieldLabel::Store if e.target() == tgt => only_loads = false,
+                _ => return None,
+            }
+        }
+
+        Some((tgt, only_loads))
+    }
+
     fn collect_params(
         &mut self,
         nd: NodeIndex,
         grph: &SketchGraph<LatticeBounds<U>>,
         get_label_idx: &impl Fn(&FieldLabel) -> Option<usize>,
+        infer_const: bool,
     ) -> Vec<Parameter> {
         let in_params: BTreeMap<usize, Vec<NodeIndex>> = grph
             .get_graph()
@@ -446,9 +1582,42 @@ impl<'a, U: NamedLatticeElement> LoweringContext<'a, U> {
                     return None;
                 }
 
+                if types.len() == 1 {
+                    if let Some((tgt, only_loads)) = Self::pointer_target(types[0], grph) {
+                        let is_const = infer_const && only_loads;
+                        let nullability =
+                            self.nullability.get(&types[0]).cloned().unwrap_or_default();
+                        if is_const || nullability != Nullability::Unknown {
+                            let target = self.add_type(CType::Alias(tgt));
+                            return Some(Parameter {
+                                index: idx,
+                                type_index: self.add_type(CType::Pointer {
+                                    target,
+                                    is_const,
+                                    nullability,
+                                }),
+                                is_const,
+                            });
+                        }
+                    }
+                }
+
+                if self.options.single_type && types.len() > 1 {
+                    let candidates = types.into_iter().map(CType::Alias).collect();
+                    return Some(Parameter {
+                        index: idx,
+                        is_const: false,
+                        type_index: self.pick_single_type(nd, Some(idx), candidates, grph),
+                    });
+                }
+
+                let collapsed = self.collapse_union(&types, types.len(), grph);
                 Some(Parameter {
                     index: idx,
-                    type_index: if types.len() == 1 {
+                    is_const: false,
+                    type_index: if let Some(collapsed) = collapsed {
+                        collapsed
+                    } else if types.len() == 1 || !self.options.should_emit_union(types.len()) {
                         let ty = types.remove(0);
                         self.add_type(CType::Alias(ty))
                     } else {
@@ -487,6 +1656,7 @@ impl<'a, U: NamedLatticeElement> LoweringContext<'a, U> {
                     .get(&i)
                     .map(|x| x.type_index)
                     .unwrap_or_else(|| self.build_terminal_type(default_lattice_elem)),
+                access_count: 0,
             });
             // TODO(Ian) doesnt seem like there is a non bit length accessor on the private field?
             curr_off += arg.bytesize().as_bit_length() / 8;
@@ -495,6 +1665,73 @@ impl<'a, U: NamedLatticeElement> LoweringContext<'a, U> {
         CType::Structure(flds)
     }
 
+    // Extends the in-parameters of a function with more evidence than declared parameters so the signature is contiguous up to
+    // the last observed parameter.
+    fn extend_parameters(&mut self, nd: NodeIndex, mut params: Vec<Parameter>) -> Vec<Parameter> {
+        let count = match self.parameter_count_mismatches.get(&nd) {
+            Some(mismatch) => mismatch.extended_count(),
+            None => return params,
+        };
+
+        let present = params.iter().map(|p| p.index).collect::<BTreeSet<_>>();
+        let default_lattice_elem = self.default_lattice_elem.clone();
+        for index in (0..count).filter(|idx| !present.contains(idx)) {
+            params.push(Parameter {
+                index,
+                type_index: self.build_terminal_type(&default_lattice_elem),
+                is_const: false,
+            });
+        }
+        params.sort_by_key(|p| p.index);
+        params
+    }
+
+    // Replaces the pointer and length parameters of each fat pointer by a two field structure at the pointer's index.
+    fn merge_fat_pointers(&mut self, nd: NodeIndex, params: Vec<Parameter>) -> Vec<Parameter> {
+        let fat_pointers = match self.fat_pointers.get(&nd) {
+            Some(fat_pointers) => fat_pointers.clone(),
+            None => return params,
+        };
+
+        let mut by_index = params
+            .into_iter()
+            .map(|param| (param.index, param))
+            .collect::<BTreeMap<_, _>>();
+        for fat in fat_pointers {
+            let len_index = fat.pointer_index + 1;
+            if !by_index.contains_key(&fat.pointer_index) || !by_index.contains_key(&len_index) {
+                continue;
+            }
+
+            let ptr = by_index.remove(&fat.pointer_index).unwrap();
+            let len = by_index.remove(&len_index).unwrap();
+            let slice = self.add_type(CType::Structure(vec![
+                Field {
+                    byte_offset: 0,
+                    bit_sz: fat.word_bits,
+                    type_index: ptr.type_index,
+                    access_count: 0,
+                },
+                Field {
+                    byte_offset: fat.word_bits / 8,
+                    bit_sz: fat.word_bits,
+                    type_index: len.type_index,
+                    access_count: 0,
+                },
+            ]));
+            by_index.insert(
+                fat.pointer_index,
+                Parameter {
+                    index: fat.pointer_index,
+                    type_index: slice,
+                    is_const: false,
+                },
+            );
+        }
+
+        by_index.into_values().collect()
+    }
+
     // unions outs and ins at same parameters if we have multiple conflicting params
     fn build_function_types(
         &mut self,
@@ -502,21 +1739,33 @@ impl<'a, U: NamedLatticeElement> LoweringContext<'a, U> {
         grph: &SketchGraph<LatticeBounds<U>>,
     ) -> Vec<CType> {
         // index to vector of targets
-        let in_params = self.collect_params(nd, grph, &|lbl| {
-            if let FieldLabel::In(idx) = lbl {
-                Some(*idx)
-            } else {
-                None
-            }
-        });
+        let in_params = self.collect_params(
+            nd,
+            grph,
+            &|lbl| {
+                if let FieldLabel::In(idx) = lbl {
+                    Some(*idx)
+                } else {
+                    None
+                }
+            },
+            self.options.infer_const_parameters,
+        );
+        let in_params = self.extend_parameters(nd, in_params);
+        let in_params = self.merge_fat_pointers(nd, in_params);
 
-        let mut out_params = self.collect_params(nd, grph, &|lbl| {
-            if let FieldLabel::Out(idx) = lbl {
-                Some(*idx)
-            } else {
-                None
-            }
-        });
+        let mut out_params = self.collect_params(
+            nd,
+            grph,
+            &|lbl| {
+                if let FieldLabel::Out(idx) = lbl {
+                    Some(*idx)
+                } else {
+                    None
+                }
+            },
+            false,
+        );
 
         out_params.sort_by_key(|p| p.index);
         let def = vec![];
@@ -549,17 +1798,40 @@ impl<'a, U: NamedLatticeElement> LoweringContext<'a, U> {
     // We shall always give a type... even if it is undef
     fn build_type(&mut self, nd: NodeIndex, grph: &SketchGraph<LatticeBounds<U>>) -> TypeId {
         let act_graph = grph.get_graph().get_graph();
-        if act_graph
+        let conflict = self.conflicts.get(&nd).cloned();
+        let is_terminal = act_graph
             .edges_directed(nd, EdgeDirection::Outgoing)
-            .count()
-            == 0
-        {
-            return self.build_terminal_type(&act_graph[nd]);
+            .all(|e| matches!(e.weight(), FieldLabel::Custom(_)));
+        if let (true, Some(class)) = (is_terminal, self.character_types.get(&nd)) {
+            return self.build_named_primitive(class.c_name());
+        }
+
+        if let (true, Some(mismatch)) = (is_terminal, self.size_mismatches.get(&nd)) {
+            let nm = mismatch.lowered_to.clone();
+            return self.build_named_primitive(&nm);
+        }
+
+        match &conflict {
+            Some(conflict) if conflict.kind == ConflictKind::Bounds && is_terminal => {
+                return self.build_conflicting_terminal_type(&act_graph[nd]);
+            }
+            Some(conflict) if conflict.prefers_primitive(self.options.conflict_strategy) => {
+                let nm = self.primitive_bound_name(&act_graph[nd]);
+                return self.build_named_primitive(&nm);
+            }
+            _ if is_terminal => return self.build_terminal_type(&act_graph[nd]),
+            _ => (),
         }
 
         let struct_types = self.build_structure_types(nd, grph);
-        // alias types, alias and struct are mutually exclusive, by checking if we only have zero fields in both
-        let alias_types = build_alias_types(nd, grph);
+        let element_types = self.build_element_types(nd, grph);
+        // alias types, alias and struct are mutually exclusive, by checking if we only have zero fields in both.
+        // Arrays are built from nodes with only zero fields so the alias is skipped for them.
+        let alias_types = if struct_types.is_empty() {
+            build_alias_types(nd, grph)
+        } else {
+            Vec::new()
+        };
         // pointer types
         let pointer_types = self.build_pointer_types(nd, grph);
 
@@ -570,11 +1842,26 @@ impl<'a, U: NamedLatticeElement> LoweringContext<'a, U> {
         let mut total_types = Vec::new();
 
         total_types.extend(struct_types);
+        total_types.extend(element_types);
         total_types.extend(alias_types);
         total_types.extend(pointer_types);
         total_types.extend(function_types);
+        if total_types.is_empty() {
+            // none of the edges of the node, ie. additions or custom labels, give it a shape
+            let nm = self.primitive_bound_name(&act_graph[nd]);
+            return self.build_named_primitive(&nm);
+        }
+
+        if self.options.single_type && total_types.len() > 1 {
+            return self.pick_single_type(nd, None, total_types, grph);
+        }
 
-        if total_types.len() == 1 {
+        if let Some(collapsed) = self.collapse_union(&[nd], total_types.len(), grph) {
+            return collapsed;
+        }
+
+        if total_types.len() == 1 || !self.options.should_emit_union(total_types.len()) {
+            // Candidates are ordered by preference: structures, element regions, aliases, pointers, then functions.
             self.add_type(total_types.into_iter().next().unwrap())
         } else {
             let union = total_types.into_iter().map(|x| self.add_type(x)).collect();
@@ -582,18 +1869,686 @@ impl<'a, U: NamedLatticeElement> LoweringContext<'a, U> {
         }
     }
 
+    // Lowers the nodes reachable from root that are not in lowered yet, recording their types in lowered.
+    // The types built since the last call are handed back rather than kept in the context.
+    fn lower_reachable(
+        &mut self,
+        root: NodeIndex,
+        lowered: &mut BTreeMap<NodeIndex, TypeId>,
+    ) -> BTreeMap<TypeId, CType> {
+        for nd in self.grph.get_graph().get_reachable_idxs(root) {
+            if !lowered.contains_key(&nd) {
+                let ty = self.build_type(nd, self.grph);
+                lowered.insert(nd, ty);
+            }
+        }
+
+        std::mem::take(&mut self.ephemeral_types)
+    }
+
     // TODO(Ian) newtype typeids
 
-    /// Collects ctypes for a graph
+    /// Collects ctypes for a graph, or for the nodes reachable from the roots if roots were set.
     pub fn collect_ctypes(
-        mut self,
-    ) -> anyhow::Result<(HashMap<NodeIndex, TypeId>, BTreeMap<TypeId, CType>)> {
+        self,
+    ) -> anyhow::Result<(BTreeMap<NodeIndex, TypeId>, BTreeMap<TypeId, CType>)> {
+        let (types, ctypes, _) = self.collect_ctypes_and_alternatives()?;
+        Ok((types, ctypes))
+    }
+
+    /// Collects ctypes like [LoweringContext::collect_ctypes] along with the candidates rejected for the nodes lowered to a
+    /// single type, see [LoweringOptions::single_type].
+    pub fn collect_ctypes_and_alternatives(
+        self,
+    ) -> anyhow::Result<(
+        BTreeMap<NodeIndex, TypeId>,
+        BTreeMap<TypeId, CType>,
+        Vec<RejectedAlternatives>,
+    )> {
+        let lowered = self.lower()?;
+        Ok((lowered.node_types, lowered.types, lowered.rejected))
+    }
+
+    /// Collects ctypes like [LoweringContext::collect_ctypes_and_alternatives] along with the lowered types that break an
+    /// invariant. The invariants are checked once, failing when [LoweringOptions::strict_invariants] is set.
+    pub fn lower(mut self) -> anyhow::Result<LoweredTypes> {
+        let nodes = match &self.roots {
+            // types only refer to the types of successors so the reachable nodes are closed under references
+            Some(roots) => roots
+                .iter()
+                .flat_map(|root| self.grph.get_graph().get_reachable_idxs(*root))
+                .collect::<BTreeSet<_>>(),
+            None => self.grph.get_graph().get_graph().node_indices().collect(),
+        };
+
         // types are local decisions so we dont care what order types are built in
-        let mut types = HashMap::new();
-        for nd in self.grph.get_graph().get_graph().node_indices() {
+        let mut types = BTreeMap::new();
+        for nd in nodes {
             types.insert(nd, self.build_type(nd, self.grph));
         }
 
-        Ok((types, self.ephemeral_types))
+        if self.options.compress_aliases {
+            compress_aliases(&mut types, &mut self.ephemeral_types);
+        }
+
+        if self.options.canonical_primitives {
+            canonicalize_primitives(&mut types, &mut self.ephemeral_types);
+        }
+
+        let violations = invariants::enforce(
+            invariants::check_lowered_types(&types, &self.ephemeral_types),
+            self.options.strict_invariants,
+        )?;
+
+        Ok(LoweredTypes {
+            node_types: types,
+            types: self.ephemeral_types,
+            rejected: self.rejected_alternatives,
+            violations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        convert::TryFrom,
+    };
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+    use petgraph::graph::NodeIndex;
+
+    use crate::{
+        analysis::array_extents::ArrayLayout,
+        constraints::Field,
+        ctypes::{c_type::InnerType, Alias},
+        diagnostics::{self, DiagnosticKind},
+        test_utils::sketches::{flat_lattice, int_lattice, lower, sketch_from_json, var},
+    };
+
+    use super::{
+        conflicts::ConflictStrategy, CType, LoweringOptions, TypeId, UnionOverflow,
+        CTYPES_SCHEMA_VERSION, PADDING_PRIMITIVE,
+    };
+
+    #[test]
+    fn mapping_is_versioned_and_keeps_wide_type_ids() {
+        let wide = TypeId(usize::try_from(u64::from(u32::MAX) + 1).unwrap());
+        let mut types = BTreeMap::new();
+        types.insert(TypeId(0), CType::Primitive("int".to_owned()));
+        types.insert(
+            wide,
+            CType::Array {
+                element: TypeId(0),
+                count: 4,
+            },
+        );
+
+        let mapping =
+            super::convert_mapping_to_profobuf(types, &BTreeMap::new(), &BTreeMap::new()).unwrap();
+        assert_eq!(mapping.schema_version, CTYPES_SCHEMA_VERSION);
+        assert_eq!(
+            mapping.type_id_to_ctype[&(u64::from(u32::MAX) + 1)]
+                .type_id
+                .as_ref()
+                .map(|ty| ty.type_id),
+            Some(u64::from(u32::MAX) + 1)
+        );
+
+        let mut too_long = BTreeMap::new();
+        too_long.insert(TypeId(0), CType::Primitive("int".to_owned()));
+        too_long.insert(
+            TypeId(1),
+            CType::Array {
+                element: TypeId(0),
+                count: usize::try_from(u64::from(u32::MAX) + 1).unwrap(),
+            },
+        );
+        let tid_to_node = BTreeMap::from([(
+            Tid::create("sub_huge".to_owned(), "1000".to_owned()),
+            NodeIndex::new(0),
+        )]);
+        let node_to_ty = BTreeMap::from([(NodeIndex::new(0), TypeId(1))]);
+
+        diagnostics::start_collecting();
+        let mapping =
+            super::convert_mapping_to_profobuf(too_long, &node_to_ty, &tid_to_node).unwrap();
+        let reported = diagnostics::take();
+
+        // the array that does not fit becomes an alias to no type, the rest is kept
+        assert_eq!(
+            mapping.type_id_to_ctype[&1].inner_type,
+            Some(InnerType::Alias(Alias { to_type: None }))
+        );
+        assert!(matches!(
+            mapping.type_id_to_ctype[&0].inner_type,
+            Some(InnerType::Primitive(_))
+        ));
+        assert_eq!(mapping.tid_to_type_id["sub_huge"].type_id, 1);
+
+        assert_eq!(reported.counts[&DiagnosticKind::DroppedConversion], 1);
+        let dropped = &reported.diagnostics[0];
+        assert_eq!(
+            dropped.location.as_ref().map(|tid| tid.get_str_repr()),
+            Some("sub_huge")
+        );
+        assert!(dropped.message.contains("sub_huge"));
+        assert!(dropped.message.contains("array count"));
+    }
+
+    #[test]
+    fn nodes_lowered_to_the_same_primitive_share_its_type() {
+        let int_ty = TypeId(0);
+        let dup_ty = TypeId(1);
+        let alias_ty = TypeId(2);
+        let ptr_ty = TypeId(3);
+        let mut types = BTreeMap::from([
+            (int_ty, CType::Primitive("int".to_owned())),
+            (dup_ty, CType::Primitive("int".to_owned())),
+            (alias_ty, CType::Alias(NodeIndex::new(1))),
+            (
+                ptr_ty,
+                CType::Pointer {
+                    target: alias_ty,
+                    is_const: false,
+                    nullability: Default::default(),
+                },
+            ),
+        ]);
+        let mut node_types = BTreeMap::from([
+            (NodeIndex::new(0), alias_ty),
+            (NodeIndex::new(1), dup_ty),
+            (NodeIndex::new(2), ptr_ty),
+        ]);
+
+        super::canonicalize_primitives(&mut node_types, &mut types);
+        assert_eq!(node_types[&NodeIndex::new(0)], int_ty);
+        assert_eq!(node_types[&NodeIndex::new(1)], int_ty);
+        assert_eq!(node_types[&NodeIndex::new(2)], ptr_ty);
+        assert_eq!(
+            types.keys().cloned().collect::<Vec<_>>(),
+            vec![int_ty, ptr_ty]
+        );
+        assert!(matches!(types[&ptr_ty], CType::Pointer { target, .. } if target == int_ty));
+    }
+
+    #[test]
+    fn lowering_can_be_restricted_to_reachable_nodes() {
+        let lattice = int_lattice();
+        // sub_a points to an int, the temporary τ3 is not reachable from it
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","int"],["bottom","top"]]"#,
+            r#"[[0,1,"Load"],[2,1,"Store"]]"#,
+            &[(var("sub_a"), 0), (var("τ3"), 2)],
+        );
+        let ctx = || lower(&grph, &lattice, LoweringOptions::default());
+
+        let (all, _) = ctx().collect_ctypes().unwrap();
+        assert_eq!(all.len(), 3);
+
+        let (reachable, _) = ctx()
+            .with_roots(BTreeSet::from([NodeIndex::new(0)]))
+            .collect_ctypes()
+            .unwrap();
+        assert_eq!(
+            reachable.keys().cloned().collect::<Vec<_>>(),
+            vec![NodeIndex::new(0), NodeIndex::new(1)]
+        );
+    }
+
+    #[test]
+    fn overflowing_unions_collapse_to_a_primitive() {
+        let lattice = int_lattice();
+        // sub_a is an int that is loaded from and stored to with different targets, so it has two pointer candidates
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","int"],["bottom","top"],["bottom","top"]]"#,
+            r#"[[0,1,"Load"],[0,2,"Store"]]"#,
+            &[(var("sub_a"), 0)],
+        );
+        let lowered = |union_overflow| {
+            let options = LoweringOptions {
+                max_union_arity: Some(1),
+                union_overflow,
+                ..LoweringOptions::default()
+            };
+            let (types, ctypes) = lower(&grph, &lattice, options.clone())
+                .collect_ctypes()
+                .unwrap();
+            (ctypes[&types[&NodeIndex::new(0)]].clone(), options)
+        };
+
+        assert!(matches!(
+            lowered(UnionOverflow::PickPreferred).0,
+            CType::Pointer { .. }
+        ));
+
+        crate::diagnostics::start_collecting();
+        let (top, options) = lowered(UnionOverflow::Top);
+        assert_eq!(
+            top,
+            CType::Primitive(options.primitive_table.get_c_name("top").to_owned())
+        );
+        let (lub, options) = lowered(UnionOverflow::LeastUpperBound);
+        assert_eq!(
+            lub,
+            CType::Primitive(options.primitive_table.get_c_name("int").to_owned())
+        );
+        assert_eq!(
+            crate::diagnostics::take().counts[&crate::diagnostics::DiagnosticKind::CollapsedUnion],
+            2
+        );
+    }
+
+    #[test]
+    fn recursive_fields_point_to_their_structure_unless_disabled() {
+        let lattice = int_lattice();
+        // a list node with a value at offset 0 and a pointer to the next node at offset 8
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","int"],["bottom","top"]]"#,
+            r#"[[0,1,{"Field":{"offset":0,"size":32}}],[0,2,{"Field":{"offset":8,"size":64}}],[2,0,"Load"]]"#,
+            &[(var("list"), 0)],
+        );
+
+        let next_field = |detect_linked_structures| {
+            let (types, ctypes) = lower(
+                &grph,
+                &lattice,
+                LoweringOptions {
+                    detect_linked_structures,
+                    ..LoweringOptions::default()
+                },
+            )
+            .collect_ctypes()
+            .unwrap();
+            let next = match &ctypes[&types[&NodeIndex::new(0)]] {
+                CType::Structure(fields) => fields
+                    .iter()
+                    .find(|fld| fld.byte_offset() == 8)
+                    .expect("the list should have a next field")
+                    .type_index(),
+                ty => panic!("expected a structure, got {:?}", ty),
+            };
+            match &ctypes[&next] {
+                CType::Pointer { target, .. } => Some(ctypes[target].clone()),
+                _ => None,
+            }
+        };
+
+        assert_eq!(next_field(true), Some(CType::Alias(NodeIndex::new(0))));
+        assert_eq!(next_field(false), None);
+    }
+
+    #[test]
+    fn lowering_options_default_missing_fields() {
+        let options: LoweringOptions =
+            serde_json::from_str(r#"{"emit_unions":false,"max_union_arity":2}"#).unwrap();
+        assert_eq!(
+            options,
+            LoweringOptions {
+                emit_unions: false,
+                max_union_arity: Some(2),
+                ..LoweringOptions::default()
+            }
+        );
+        assert!(serde_json::from_str::<LoweringOptions>(r#"{"emit_union":false}"#).is_err());
+    }
+
+    #[test]
+    fn nodes_without_candidates_lower_to_their_primitive_bound() {
+        let lattice = int_lattice();
+        // sub_a is an int whose only edge is an addition, which is neither a field, a pointer, nor a parameter
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","int"],["bottom","top"]]"#,
+            r#"[[0,1,{"Add":4}]]"#,
+            &[(var("sub_a"), 0)],
+        );
+
+        for options in [
+            LoweringOptions::default(),
+            LoweringOptions {
+                emit_unions: false,
+                ..LoweringOptions::default()
+            },
+        ]
+        .iter()
+        {
+            let (types, ctypes) = lower(&grph, &lattice, options.clone())
+                .collect_ctypes()
+                .unwrap();
+            assert_eq!(
+                ctypes[&types[&NodeIndex::new(0)]],
+                CType::Primitive(options.primitive_table.get_c_name("int").to_owned())
+            );
+        }
+    }
+
+    #[test]
+    fn disabled_unions_pick_the_preferred_candidate() {
+        let lattice = int_lattice();
+        // sub_a is a structure with fields at 0 and 8 that is also called with an int
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","int"],["bottom","int"]]"#,
+            r#"[[0,1,{"Field":{"offset":0,"size":32}}],[0,1,{"Field":{"offset":8,"size":32}}],[0,2,{"In":0}]]"#,
+            &[(var("sub_a"), 0)],
+        );
+        let lowered = |options: LoweringOptions| {
+            let (types, ctypes) = lower(&grph, &lattice, options).collect_ctypes().unwrap();
+            let root = ctypes[&types[&NodeIndex::new(0)]].clone();
+            (root, ctypes)
+        };
+
+        let (union, _) = lowered(LoweringOptions::default());
+        assert!(matches!(union, CType::Union(members) if members.len() == 2));
+
+        let (structure, ctypes) = lowered(LoweringOptions {
+            emit_unions: false,
+            synthesize_padding: true,
+            ..LoweringOptions::default()
+        });
+        let fields = match structure {
+            CType::Structure(fields) => fields,
+            other => panic!("expected the structure candidate, got {:?}", other),
+        };
+        assert_eq!(
+            fields
+                .iter()
+                .map(|fld| (fld.byte_offset, fld.bit_sz))
+                .collect::<Vec<_>>(),
+            vec![(0, 32), (4, 32), (8, 32)]
+        );
+        assert_eq!(
+            ctypes[&fields[1].type_index],
+            CType::Primitive(PADDING_PRIMITIVE.to_owned())
+        );
+    }
+
+    #[test]
+    fn signatures_are_extended_to_parameters_with_caller_evidence() {
+        let lattice = int_lattice();
+        // sub_a declares one parameter but callers pass an int as its fourth
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","int"]]"#,
+            r#"[[0,1,{"In":0}],[0,1,{"In":3}]]"#,
+            &[(var("sub_a"), 0)],
+        );
+
+        let mismatches = super::conflicts::find_parameter_count_mismatches(
+            &grph,
+            &BTreeMap::from([(NodeIndex::new(0), 1)]),
+        );
+        let mismatch = &mismatches[&NodeIndex::new(0)];
+        assert_eq!((mismatch.declared, mismatch.extra.clone()), (1, vec![3]));
+        assert_eq!(mismatch.extended_count(), 4);
+        assert!(super::conflicts::find_parameter_count_mismatches(
+            &grph,
+            &BTreeMap::from([(NodeIndex::new(0), 4)]),
+        )
+        .is_empty());
+
+        let (types, ctypes) = lower(&grph, &lattice, LoweringOptions::default())
+            .with_parameter_count_mismatches(mismatches)
+            .collect_ctypes()
+            .unwrap();
+        match &ctypes[&types[&NodeIndex::new(0)]] {
+            CType::Function { params, .. } => assert_eq!(
+                params.iter().map(|p| p.index()).collect::<Vec<_>>(),
+                vec![0, 1, 2, 3]
+            ),
+            ty => panic!("expected a function, got {:?}", ty),
+        }
+    }
+
+    #[test]
+    fn load_only_pointer_parameters_are_const_when_enabled() {
+        let lattice = int_lattice();
+        // sub_a only loads through its first parameter and also stores through its second
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","top"],["bottom","int"],["bottom","top"],["bottom","int"]]"#,
+            r#"[[0,1,{"In":0}],[1,2,"Load"],[0,3,{"In":1}],[3,4,"Load"],[3,4,"Store"]]"#,
+            &[(var("sub_a"), 0)],
+        );
+
+        let lowered = |infer_const_parameters| {
+            let (types, ctypes) = lower(
+                &grph,
+                &lattice,
+                LoweringOptions {
+                    infer_const_parameters,
+                    ..LoweringOptions::default()
+                },
+            )
+            .collect_ctypes()
+            .unwrap();
+            match &ctypes[&types[&NodeIndex::new(0)]] {
+                CType::Function { params, .. } => params
+                    .iter()
+                    .map(|p| {
+                        let pointer_is_const = matches!(
+                            ctypes.get(&p.type_index()),
+                            Some(CType::Pointer { is_const: true, .. })
+                        );
+                        (p.index(), p.is_const(), pointer_is_const)
+                    })
+                    .collect::<Vec<_>>(),
+                ty => panic!("expected a function, got {:?}", ty),
+            }
+        };
+
+        assert_eq!(lowered(true), vec![(0, true, true), (1, false, false)]);
+        assert_eq!(lowered(false), vec![(0, false, false), (1, false, false)]);
+    }
+
+    #[test]
+    fn single_type_picks_the_candidate_with_most_evidence() {
+        let lattice = int_lattice();
+        // sub_a loads a leaf and stores a pointer, the pointer to the pointer has more evidence
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","top"],["bottom","top"],["bottom","int"]]"#,
+            r#"[[0,1,"Load"],[0,2,"Store"],[2,3,"Load"]]"#,
+            &[(var("sub_a"), 0)],
+        );
+        let (types, ctypes, rejected) = lower(
+            &grph,
+            &lattice,
+            LoweringOptions {
+                single_type: true,
+                ..LoweringOptions::default()
+            },
+        )
+        .collect_ctypes_and_alternatives()
+        .unwrap();
+
+        assert!(!ctypes.values().any(|ty| matches!(ty, CType::Union(_))));
+        match &ctypes[&types[&NodeIndex::new(0)]] {
+            CType::Pointer { target, .. } => {
+                assert_eq!(ctypes[target], CType::Alias(NodeIndex::new(2)))
+            }
+            ty => panic!("expected a pointer, got {:?}", ty),
+        }
+
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].node, NodeIndex::new(0));
+        assert_eq!(rejected[0].chosen.evidence, 2);
+        assert_eq!(
+            rejected[0]
+                .rejected
+                .iter()
+                .map(|alt| alt.evidence)
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn accessed_fields_carry_their_count_and_evidence() {
+        let lattice = int_lattice();
+        // an object read as a 64 bit word at offset 0 and as a 32 bit field overlapping it at offset 4
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","int"],["bottom","int"]]"#,
+            r#"[[0,1,{"Field":{"offset":0,"size":64}}],[0,2,{"Field":{"offset":4,"size":32}}]]"#,
+            &[(var("obj"), 0)],
+        );
+        let (types, ctypes, rejected) = lower(
+            &grph,
+            &lattice,
+            LoweringOptions {
+                single_type: true,
+                ..LoweringOptions::default()
+            },
+        )
+        .with_field_accesses(BTreeMap::from([(
+            NodeIndex::new(0),
+            BTreeMap::from([(Field::new(4, 32), 5)]),
+        )]))
+        .collect_ctypes_and_alternatives()
+        .unwrap();
+
+        match &ctypes[&types[&NodeIndex::new(0)]] {
+            CType::Structure(fields) => {
+                assert_eq!(fields.len(), 1);
+                assert_eq!(fields[0].byte_offset(), 4);
+                assert_eq!(fields[0].access_count(), 5);
+            }
+            ty => panic!("expected a structure, got {:?}", ty),
+        }
+        let rejected = rejected
+            .iter()
+            .find(|alts| alts.node == NodeIndex::new(0))
+            .unwrap();
+        assert_eq!(rejected.chosen.evidence, 6);
+        assert_eq!(
+            rejected
+                .rejected
+                .iter()
+                .map(|alt| alt.evidence)
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn array_extents_past_the_address_space_are_skipped() {
+        let lattice = int_lattice();
+        // an object read as 32 bit fields at offsets 0 and 4
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","int"]]"#,
+            r#"[[0,1,{"Field":{"offset":0,"size":32}}],[0,1,{"Field":{"offset":4,"size":32}}]]"#,
+            &[(var("obj"), 0)],
+        );
+        let lowered = |count| {
+            let (types, ctypes) = lower(&grph, &lattice, LoweringOptions::default())
+                .with_array_extents(BTreeMap::from([(
+                    NodeIndex::new(0),
+                    vec![ArrayLayout {
+                        offset: 0,
+                        stride: 4,
+                        count,
+                    }],
+                )]))
+                .collect_ctypes()
+                .unwrap();
+            ctypes[&types[&NodeIndex::new(0)]].clone()
+        };
+
+        assert!(matches!(lowered(2), CType::Array { count: 2, .. }));
+        assert!(matches!(lowered(usize::MAX), CType::Structure(fields) if fields.len() == 2));
+    }
+
+    #[test]
+    fn fields_past_the_offset_threshold_are_widened_to_bytes() {
+        let lattice = int_lattice();
+        // a buffer read as 32 bit fields at offsets 0, 4, 8 and 14
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","int"]]"#,
+            r#"[[0,1,{"Field":{"offset":0,"size":32}}],[0,1,{"Field":{"offset":4,"size":32}}],[0,1,{"Field":{"offset":8,"size":32}}],[0,1,{"Field":{"offset":14,"size":32}}]]"#,
+            &[(var("buf"), 0)],
+        );
+        let (types, ctypes) = lower(
+            &grph,
+            &lattice,
+            LoweringOptions {
+                single_type: true,
+                max_field_offsets: Some(2),
+                ..LoweringOptions::default()
+            },
+        )
+        .collect_ctypes()
+        .unwrap();
+
+        match &ctypes[&types[&NodeIndex::new(0)]] {
+            CType::Structure(fields) => {
+                assert_eq!(
+                    fields
+                        .iter()
+                        .map(|fld| (fld.byte_offset(), fld.bit_size()))
+                        .collect::<Vec<_>>(),
+                    vec![(0, 32), (4, 32), (8, 80)]
+                );
+                match &ctypes[&fields[2].type_index()] {
+                    CType::Array { element, count } => {
+                        assert_eq!(*count, 10);
+                        assert_eq!(ctypes[element], CType::Primitive("uint8_t".to_owned()));
+                    }
+                    ty => panic!("expected an array, got {:?}", ty),
+                }
+            }
+            ty => panic!("expected a structure, got {:?}", ty),
+        }
+    }
+
+    #[test]
+    fn reported_conflicts_keep_the_primitive_bounds_of_terminals_only() {
+        let lattice = flat_lattice(&["int", "float"]);
+        // x is bounded by int but loaded from, y has an int flowing in and a use that degraded to bottom, z has an int
+        // flowing in and is used as a float
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["int","int"],["bottom","top"],["int","bottom"],["int","float"]]"#,
+            r#"[[0,1,"Load"]]"#,
+            &[(var("x"), 0), (var("y"), 2), (var("z"), 3)],
+        );
+        let (types, ctypes) = lower(
+            &grph,
+            &lattice,
+            LoweringOptions {
+                conflict_strategy: ConflictStrategy::ReportConflict,
+                ..LoweringOptions::default()
+            },
+        )
+        .collect_ctypes()
+        .unwrap();
+
+        assert!(matches!(
+            &ctypes[&types[&NodeIndex::new(0)]],
+            CType::Pointer { .. }
+        ));
+        assert_eq!(
+            ctypes[&types[&NodeIndex::new(2)]],
+            CType::Primitive("int".to_owned())
+        );
+        match &ctypes[&types[&NodeIndex::new(3)]] {
+            CType::Union(members) => {
+                let members = members.iter().map(|ty| &ctypes[ty]).collect::<Vec<_>>();
+                assert_eq!(members.len(), 2);
+                assert!(members.contains(&&CType::Primitive("int".to_owned())));
+                assert!(members.contains(&&CType::Primitive("float".to_owned())));
+            }
+            ty => panic!("expected a union, got {:?}", ty),
+        }
+        assert!(!ctypes
+            .values()
+            .any(|ty| *ty == CType::Primitive("bottom".to_owned())));
     }
 }
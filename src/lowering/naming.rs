@@ -0,0 +1,594 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+};
+
+use cwe_checker_lib::intermediate_representation::Tid;
+use petgraph::{graph::NodeIndex, visit::EdgeRef, EdgeDirection};
+
+use super::{convert_typeid, CType, TypeId};
+use crate::{
+    constraint_generation::tid_to_tvar,
+    constraints::{DerivedTypeVar, FieldLabel},
+    ctypes::{self, CTypeMapping},
+    solver::type_sketch::SketchGraph,
+};
+
+/// The C spelling used for a type that cannot be resolved (ie. an alias to a node without a type, or an alias cycle).
+pub const UNKNOWN_TYPE_NAME: &str = "void";
+
+/// Renders members as the body of a structure or union, one member per line.
+pub(super) fn c_body(members: impl Iterator<Item = String>) -> String {
+    let mut body = "{\n".to_owned();
+    for member in members {
+        body.push_str("  ");
+        body.push_str(&member.replace('\n', "\n  "));
+        body.push_str(";\n");
+    }
+    body.push('}');
+    body
+}
+
+fn join_declarator(specifier: &str, declarator: &str) -> String {
+    if declarator.is_empty() {
+        specifier.to_owned()
+    } else {
+        format!("{} {}", specifier, declarator)
+    }
+}
+
+/// Assigns C names to lowered types so that exporters agree on how a [TypeId] is referred to.
+/// Aliases are resolved to the type they refer to, structures, unions, and functions
+/// receive declared names, and pointers and primitives are spelled inline.
+pub struct TypeNamer<'a> {
+    types: &'a BTreeMap<TypeId, CType>,
+    node_to_ty: &'a BTreeMap<NodeIndex, TypeId>,
+    structural_names: bool,
+    structural_hashes: RefCell<BTreeMap<TypeId, u64>>,
+    recursive_references: RefCell<BTreeSet<TypeId>>,
+}
+
+// FNV-1a, which unlike the hasher of the standard library is specified, so hashes are stable across builds.
+fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl<'a> TypeNamer<'a> {
+    /// Creates a namer for the lowered types and the mapping from sketch nodes to types.
+    pub fn new(
+        types: &'a BTreeMap<TypeId, CType>,
+        node_to_ty: &'a BTreeMap<NodeIndex, TypeId>,
+    ) -> TypeNamer<'a> {
+        TypeNamer {
+            types,
+            node_to_ty,
+            structural_names: false,
+            structural_hashes: RefCell::new(BTreeMap::new()),
+            recursive_references: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    /// Names declared types by a hash of their structural description instead of their [TypeId], ie. `struct_s_9f3ab2c41d07`,
+    /// so that identical layouts get identical names across binaries and runs. Types with the same name have the same
+    /// structure, so exporters declare a name once.
+    pub fn with_structural_names(mut self, structural_names: bool) -> TypeNamer<'a> {
+        self.structural_names = structural_names;
+        self
+    }
+
+    fn structural_hash(&self, ty: TypeId) -> u64 {
+        *self
+            .structural_hashes
+            .borrow_mut()
+            .entry(ty)
+            .or_insert_with(|| {
+                // 48 bits keeps names short while collisions stay unlikely for the number of types in a binary
+                stable_hash(self.structural_description(ty).as_bytes()) & 0xffff_ffff_ffff
+            })
+    }
+
+    /// Follows aliases until a non alias type is reached. Returns None if the alias chain is cyclic
+    /// or reaches a node without a type.
+    pub fn resolve(&self, ty: TypeId) -> Option<TypeId> {
+        let mut seen = BTreeSet::new();
+        let mut curr = ty;
+        loop {
+            if !seen.insert(curr) {
+                return None;
+            }
+
+            match self.types.get(&curr)? {
+                CType::Alias(tgt) => curr = *self.node_to_ty.get(tgt)?,
+                _ => return Some(curr),
+            }
+        }
+    }
+
+    /// Gets the resolved type for a [TypeId].
+    pub fn get_resolved_type(&self, ty: TypeId) -> Option<&'a CType> {
+        self.resolve(ty)
+            .and_then(|resolved| self.types.get(&resolved))
+    }
+
+    /// Gets the name a structure, union or function type is declared with. Other types are not declared.
+    pub fn declared_name(&self, ty: TypeId) -> Option<String> {
+        let prefix = match self.types.get(&ty)? {
+            CType::Structure(_) => "struct",
+            CType::Union(_) => "union",
+            CType::Function { .. } => "func",
+            _ => return None,
+        };
+        Some(if self.structural_names {
+            format!("{}_s_{:012x}", prefix, self.structural_hash(ty))
+        } else {
+            format!("{}_{}", prefix, ty.0)
+        })
+    }
+
+    fn type_name_with_visited(&self, ty: TypeId, visited: &mut BTreeSet<TypeId>) -> String {
+        let resolved = match self.resolve(ty) {
+            Some(resolved) => resolved,
+            None => return UNKNOWN_TYPE_NAME.to_owned(),
+        };
+
+        if !visited.insert(resolved) {
+            return UNKNOWN_TYPE_NAME.to_owned();
+        }
+
+        let nm = match &self.types[&resolved] {
+            CType::Primitive(nm) => nm.clone(),
+            CType::Pointer {
+                target, is_const, ..
+            } => format!(
+                "{}{} *",
+                if *is_const { "const " } else { "" },
+                self.type_name_with_visited(*target, visited)
+            ),
+            CType::Structure(_) => format!(
+                "struct {}",
+                self.declared_name(resolved)
+                    .expect("structures have declared names")
+            ),
+            CType::Union(_) => format!(
+                "union {}",
+                self.declared_name(resolved)
+                    .expect("unions have declared names")
+            ),
+            // a function referenced as a value is a function pointer
+            CType::Function { .. } => format!(
+                "{} *",
+                self.declared_name(resolved)
+                    .expect("functions have declared names")
+            ),
+            // an array referenced as a value decays to a pointer to its first element
+            CType::Array { element, .. } => {
+                format!("{} *", self.type_name_with_visited(*element, visited))
+            }
+            CType::Alias(_) => unreachable!("resolved types are not aliases"),
+        };
+
+        visited.remove(&resolved);
+        nm
+    }
+
+    /// Gets the C spelling used to refer to a type.
+    pub fn type_name(&self, ty: TypeId) -> String {
+        self.type_name_with_visited(ty, &mut BTreeSet::new())
+    }
+
+    /// Renders a function prototype with the given name if the type resolves to a function.
+    /// Missing parameters are given the unknown parameter type.
+    pub fn function_prototype(
+        &self,
+        ty: TypeId,
+        name: &str,
+        unknown_param_type: &str,
+    ) -> Option<String> {
+        if let CType::Function { params, return_ty } = self.get_resolved_type(ty)? {
+            let by_index = params
+                .iter()
+                .map(|p| (p.index, p.type_index))
+                .collect::<BTreeMap<_, _>>();
+            let num_params = by_index.keys().next_back().map(|x| x + 1).unwrap_or(0);
+            let param_names = (0..num_params)
+                .map(|idx| {
+                    by_index
+                        .get(&idx)
+                        .map(|ty| self.type_name(*ty))
+                        .unwrap_or_else(|| unknown_param_type.to_owned())
+                })
+                .collect::<Vec<_>>();
+
+            let ret_name = return_ty
+                .map(|ty| self.type_name(ty))
+                .unwrap_or_else(|| "void".to_owned());
+
+            Some(format!(
+                "{} {}({})",
+                ret_name,
+                name,
+                if param_names.is_empty() {
+                    "void".to_owned()
+                } else {
+                    param_names.join(", ")
+                }
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn named_reference(&self, ty: TypeId, decl: &str) -> String {
+        match (self.types.get(&ty), self.declared_name(ty)) {
+            (Some(CType::Structure(_)), Some(nm)) => {
+                join_declarator(&format!("struct {}", nm), decl)
+            }
+            (Some(CType::Union(_)), Some(nm)) => join_declarator(&format!("union {}", nm), decl),
+            (Some(CType::Function { .. }), Some(nm)) => join_declarator(&nm, decl),
+            _ => join_declarator(UNKNOWN_TYPE_NAME, decl),
+        }
+    }
+
+    fn c_decl_of_id(&self, ty: TypeId, decl: String, stack: &mut Vec<TypeId>) -> String {
+        let resolved = match self.resolve(ty) {
+            Some(resolved) => resolved,
+            None => return join_declarator(UNKNOWN_TYPE_NAME, &decl),
+        };
+
+        // a recursive reference refers to the enclosing type by its declared name instead of expanding it again, a
+        // cycle through only pointers and arrays has no name to refer to it by
+        if let Some(pos) = stack.iter().rposition(|x| *x == resolved) {
+            if self.declared_name(resolved).is_some()
+                || !stack[pos..]
+                    .iter()
+                    .any(|x| self.declared_name(*x).is_some())
+            {
+                self.recursive_references.borrow_mut().insert(resolved);
+                return self.named_reference(resolved, &decl);
+            }
+        }
+
+        stack.push(resolved);
+        let res = self.c_decl_with_stack(&self.types[&resolved], decl, stack);
+        stack.pop();
+        self.recursive_references.borrow_mut().remove(&resolved);
+        res
+    }
+
+    // A structure or union referred to from its own body is defined with its declared name, so that the references
+    // inside the body name a declared type. The type being defined is the innermost one on the stack.
+    fn aggregate_specifier(&self, keyword: &str, body: String, stack: &[TypeId]) -> String {
+        match stack.last() {
+            Some(ty) if self.recursive_references.borrow_mut().remove(ty) => format!(
+                "{} {} {}",
+                keyword,
+                self.declared_name(*ty)
+                    .expect("recursively referenced types have declared names"),
+                body
+            ),
+            _ => format!("{} {}", keyword, body),
+        }
+    }
+
+    // void cant be used as a value so members of unknown type, and arrays of unknown extent or element, are declared as
+    // an array of bytes with the size of the access
+    fn member_decl(
+        &self,
+        ty: TypeId,
+        bit_sz: usize,
+        name: String,
+        declare: &mut impl FnMut(TypeId, String) -> String,
+    ) -> String {
+        let is_unknown = match self.get_resolved_type(ty) {
+            None | Some(CType::Array { count: 0, .. }) => true,
+            Some(CType::Array { element, .. }) => self.resolve(*element).is_none(),
+            Some(_) => false,
+        };
+        if is_unknown {
+            format!("char {}[{}]", name, std::cmp::max(bit_sz / 8, 1))
+        } else {
+            declare(ty, name)
+        }
+    }
+
+    /// Declares the members of a structure or union in layout order, rendering each member from its type and name with
+    /// declare. Structure fields are named by their offset with byte array padding between them, union members by
+    /// their index. Other types have no members.
+    pub(super) fn member_decls(
+        &self,
+        ct: &CType,
+        mut declare: impl FnMut(TypeId, String) -> String,
+    ) -> Vec<String> {
+        let mut members = Vec::new();
+        match ct {
+            CType::Structure(_) | CType::Union(_) => {
+                let members = self.member_decls(ct, |ty, name| self.c_decl_of_id(ty, name, stack));
+                let keyword = if let CType::Structure(_) = ct {
+                    "struct"
+                } else {
+                    "union"
+                };
+                join_declarator(
+                    &self.aggregate_specifier(keyword, c_body(members.into_iter()), stack),
+                    &decl,
+                )
+            }
+            CType::Function { params, return_ty } => {
+                let by_index = params
+                    .iter()
+                    .map(|p| (p.index, p.type_index))
+                    .collect::<BTreeMap<_, _>>();
+                let num_params = by_index.keys().next_back().map(|x| x + 1).unwrap_or(0);
+                let param_decls = (0..num_params)
+                    .map(|idx| match by_index.get(&idx) {
+                        Some(ty) => self.c_decl_of_id(*ty, String::new(), stack),
+                        // a missing parameter is only known to be passed
+                        None => format!("{} *", UNKNOWN_TYPE_NAME),
+                    })
+                    .collect::<Vec<_>>();
+
+                let fn_decl = format!(
+                    "{}({})",
+                    decl,
+                    if param_decls.is_empty() {
+                        "void".to_owned()
+                    } else {
+                        param_decls.join(", ")
+                    }
+                );
+                match return_ty {
+                    Some(ret) => self.c_decl_of_id(*ret, fn_decl, stack),
+                    None => join_declarator("void", &fn_decl),
+                }
+            }
+            CType::Array { element, count } => self.c_decl_of_id(
+                *element,
+                if *count == 0 {
+                    format!("{}[]", decl)
+                } else {
+                    format!("{}[{}]", decl, count)
+                },
+                stack,
+            ),
+        }
+    }
+
+    /// Renders a single C declaration of a value of type ct with the given name. Aliases and pointers are resolved
+    /// inline, and structures and unions are declared with anonymous bodies, so the declaration does not depend on other
+    /// declarations. A structure or union with recursive references is defined with its declared name, which the
+    /// references inside its body refer to.
+    pub fn c_decl(&self, ct: &CType, name: &str) -> String {
+        format!(
+            "{};",
+            self.c_decl_with_stack(ct, name.to_owned(), &mut Vec::new())
+        )
+    }
+
+    fn describe_with_stack(&self, ty: TypeId, stack: &mut Vec<TypeId>) -> String {
+        let resolved = match self.resolve(ty) {
+            Some(resolved) => resolved,
+            None => return UNKNOWN_TYPE_NAME.to_owned(),
+        };
+
+        // recursive references are named by how many enclosing types up they refer to
+        if let Some(pos) = stack.iter().rposition(|x| *x == resolved) {
+            return format!("rec^{}", stack.len() - pos);
+        }
+
+        stack.push(resolved);
+        let desc = match &self.types[&resolved] {
+            CType::Primitive(nm) => nm.clone(),
+            CType::Pointer {
+                target, is_const, ..
+            } => format!(
+                "{}ptr({})",
+                if *is_const { "const " } else { "" },
+                self.describe_with_stack(*target, stack)
+            ),
+            CType::Structure(fields) => format!(
+                "struct{{{}}}",
+                fields
+                    .iter()
+                    .map(|fld| format!(
+                        "{}:{}:{}",
+                        fld.byte_offset,
+                        fld.bit_sz,
+                        self.describe_with_stack(fld.type_index, stack)
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            CType::Union(members) => {
+                let mut members = members
+                    .iter()
+                    .map(|member| self.describe_with_stack(*member, stack))
+                    .collect::<Vec<_>>();
+                members.sort();
+                format!("union{{{}}}", members.join(" | "))
+            }
+            CType::Function { params, return_ty } => {
+                let mut params = params
+                    .iter()
+                    .map(|param| {
+                        (
+                            param.index,
+                            format!(
+                                "{}{}",
+                                if param.is_const { "const " } else { "" },
+                                self.describe_with_stack(param.type_index, stack)
+                            ),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                params.sort();
+                format!(
+                    "fn({}) -> {}",
+                    params
+                        .into_iter()
+                        .map(|(idx, desc)| format!("{}:{}", idx, desc))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    return_ty
+                        .map(|ret| self.describe_with_stack(ret, stack))
+                        .unwrap_or_else(|| "void".to_owned())
+                )
+            }
+            CType::Array { element, count } => {
+                format!(
+                    "array[{}]({})",
+                    count,
+                    self.describe_with_stack(*element, stack)
+                )
+            }
+            CType::Alias(_) => unreachable!("resolved types are not aliases"),
+        };
+        stack.pop();
+        desc
+    }
+
+    /// Describes the full structure of a type without referring to [TypeId]s, so that the description of a type is the same
+    /// regardless of the order ids were allocated in. Recursive references are written as rec^n, referring to the nth enclosing type.
+    pub fn structural_description(&self, ty: TypeId) -> String {
+        self.describe_with_stack(ty, &mut Vec::new())
+    }
+}
+
+/// Finds the node pointed to by the return of each constructor in a sketch graph, mapped to the name of the structure
+/// the constructor is named after, see [crate::analysis::name_heuristics::constructors].
+pub fn find_constructed_structures<U: std::cmp::PartialEq>(
+    grph: &SketchGraph<U>,
+    constructors: &BTreeMap<Tid, String>,
+) -> BTreeMap<NodeIndex, String> {
+    let graph = grph.get_graph().get_graph();
+    let follow = |nd: NodeIndex, label: FieldLabel| {
+        graph
+            .edges_directed(nd, EdgeDirection::Outgoing)
+            .find(|e| *e.weight() == label)
+            .map(|e| e.target())
+    };
+
+    constructors
+        .iter()
+        .filter_map(|(tid, name)| {
+            let nd = grph.get_node_index_for_variable(&DerivedTypeVar::new(tid_to_tvar(tid)))?;
+            let pointee = follow(follow(nd, FieldLabel::Out(0))?, FieldLabel::Load)?;
+            Some((pointee, name.clone()))
+        })
+        .collect()
+}
+
+/// Records the guessed name of each constructed node in the mapping. Names are only recorded for nodes that were
+/// lowered to a structure, so the evidence of how the object is accessed decides whether the guess applies.
+pub fn annotate_structure_names(
+    mapping: &mut CTypeMapping,
+    names: &BTreeMap<NodeIndex, String>,
+    node_to_ty: &BTreeMap<NodeIndex, TypeId>,
+) -> anyhow::Result<()> {
+    for (nd, name) in names.iter() {
+        if let Some(ty) = node_to_ty.get(nd) {
+            let type_id = convert_typeid(*ty)?.type_id;
+            let is_structure = matches!(
+                mapping
+                    .type_id_to_ctype
+                    .get(&type_id)
+                    .and_then(|ct| ct.inner_type.as_ref()),
+                Some(ctypes::c_type::InnerType::Structure(_))
+            );
+
+            if is_structure {
+                mapping.structure_names.insert(type_id, name.clone());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        analysis::nullability::Nullability,
+        lowering::{mapping::TypeMapping, CType, Field, Parameter, TypeId},
+    };
+
+    #[test]
+    fn declarations_expand_anonymous_bodies() {
+        let mut types = BTreeMap::new();
+        types.insert(TypeId(0), CType::Primitive("int".to_owned()));
+        // a list node with an int and a pointer to the next node
+        types.insert(
+            TypeId(1),
+            CType::Structure(vec![
+                Field {
+                    byte_offset: 0,
+                    bit_sz: 32,
+                    type_index: TypeId(0),
+                    access_count: 0,
+                },
+                Field {
+                    byte_offset: 8,
+                    bit_sz: 64,
+                    type_index: TypeId(2),
+                    access_count: 0,
+                },
+            ]),
+        );
+        types.insert(
+            TypeId(2),
+            CType::Pointer {
+                target: TypeId(1),
+                is_const: false,
+                nullability: Nullability::Unknown,
+            },
+        );
+        types.insert(
+            TypeId(3),
+            CType::Function {
+                params: vec![Parameter {
+                    index: 1,
+                    type_index: TypeId(2),
+                    is_const: false,
+                }],
+                return_ty: Some(TypeId(0)),
+            },
+        );
+        let mapping = TypeMapping {
+            types,
+            term_types: BTreeMap::new(),
+        };
+
+        assert_eq!(
+            mapping.types[&TypeId(2)].to_c_decl(&mapping, "head"),
+            "struct struct_1 {\n  int field_0;\n  char pad_4[4];\n  struct struct_1 *field_8;\n} *head;"
+        );
+        assert_eq!(
+            mapping.types[&TypeId(3)].to_c_decl(&mapping, "sub_401000"),
+            "int sub_401000(void *, struct struct_1 {\n  int field_0;\n  char pad_4[4];\n  struct struct_1 *field_8;\n} *);"
+        );
+        assert_eq!(
+            CType::Pointer {
+                target: TypeId(3),
+                is_const: false,
+                nullability: Nullability::Unknown,
+            }
+            .to_c_decl(&mapping, "callback"),
+            "int (*callback)(void *, struct struct_1 {\n  int field_0;\n  char pad_4[4];\n  struct struct_1 *field_8;\n} *);"
+        );
+
+        // structures without recursive references keep anonymous bodies
+        assert_eq!(
+            CType::Structure(vec![Field {
+                byte_offset: 0,
+                bit_sz: 32,
+                type_index: TypeId(0),
+                access_count: 0,
+            }])
+            .to_c_decl(&mapping, "pair"),
+            "struct {\n  int field_0;\n} pair;"
+        );
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The prefix ghidra uses when exporting a lattice element for a builtin datatype with a display name.
+const GHIDRA_DISPLAY_NAME_PREFIX: &str = "data_type_with_display_name";
+
+/// A concrete C spelling for a lattice element along with the size of the type if it is known.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PrimitiveSpelling {
+    /// The name of the type in C
+    pub c_name: String,
+    /// The size of the type in bytes, if it is sized
+    pub byte_size: Option<usize>,
+}
+
+impl PrimitiveSpelling {
+    /// Creates a new spelling with the given C name and size.
+    pub fn new(c_name: &str, byte_size: Option<usize>) -> PrimitiveSpelling {
+        PrimitiveSpelling {
+            c_name: c_name.to_owned(),
+            byte_size,
+        }
+    }
+}
+
+/// The sizes a target gives to the C integer types whose size is not fixed by the standard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum DataModel {
+    /// 32-bit targets, where int, long and pointers are 4 bytes
+    Ilp32,
+    /// 64-bit unix targets, where long and pointers are 8 bytes
+    Lp64,
+    /// 64-bit Windows, where long stays 4 bytes while long long and pointers are 8 bytes
+    Llp64,
+}
+
+impl DataModel {
+    /// The data model of a target with the given pointer size in bytes. 64-bit PE binaries are LLP64.
+    pub fn for_target(pointer_size: usize, is_pe: bool) -> DataModel {
+        match (pointer_size, is_pe) {
+            (8, true) => DataModel::Llp64,
+            (8, false) => DataModel::Lp64,
+            _ => DataModel::Ilp32,
+        }
+    }
+
+    /// The size of a pointer, and so of size_t and ptrdiff_t, in bytes.
+    pub fn pointer_size(self) -> usize {
+        match self {
+            DataModel::Ilp32 => 4,
+            DataModel::Lp64 | DataModel::Llp64 => 8,
+        }
+    }
+
+    /// The size of long in bytes.
+    pub fn long_size(self) -> usize {
+        match self {
+            DataModel::Ilp32 | DataModel::Llp64 => 4,
+            DataModel::Lp64 => 8,
+        }
+    }
+
+    /// The size of wchar_t in bytes, which is 2 on Windows.
+    pub fn wchar_size(self) -> usize {
+        match self {
+            DataModel::Llp64 => 2,
+            DataModel::Ilp32 | DataModel::Lp64 => 4,
+        }
+    }
+}
+
+/// A translation table from lattice element names to the C spelling of the primitive.
+/// Lattice elements without an entry are emitted with their lattice name. A table that keeps lattice names still
+/// knows the sizes and kinds of the primitives but emits every lattice element with its lattice name.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PrimitiveTable {
+    spellings: BTreeMap<String, PrimitiveSpelling>,
+    #[serde(default)]
+    keep_lattice_names: bool,
+}
+
+impl Default for PrimitiveTable {
+    /// The default table knows the sizes of the names used by the builtin lattices on an LP64 target, but keeps
+    /// lattice names in the lowered types.
+    fn default() -> PrimitiveTable {
+        PrimitiveTable::for_data_model(DataModel::Lp64).keeping_lattice_names()
+    }
+}
+
+impl PrimitiveTable {
+    /// Creates a table with no translations so that lattice names are emitted unchanged.
+    pub fn empty() -> PrimitiveTable {
+        PrimitiveTable {
+            spellings: BTreeMap::new(),
+            keep_lattice_names: false,
+        }
+    }
+
+    /// Creates a table translating the names used by the builtin lattices, with sizes for the given data model.
+    pub fn for_data_model(model: DataModel) -> PrimitiveTable {
+        let (ptr, long, wchar) = (
+            Some(model.pointer_size()),
+            Some(model.long_size()),
+            Some(model.wchar_size()),
+        );
+        let mut tbl = PrimitiveTable::empty();
+        tbl.insert("T", PrimitiveSpelling::new("void", None));
+        tbl.insert("bottom", PrimitiveSpelling::new("void", None));
+        tbl.insert("void", PrimitiveSpelling::new("void", None));
+        tbl.insert("bool", PrimitiveSpelling::new("_Bool", Some(1)));
+        tbl.insert("char", PrimitiveSpelling::new("char", Some(1)));
+        tbl.insert("uint8_t", PrimitiveSpelling::new("uint8_t", Some(1)));
+        tbl.insert("char16_t", PrimitiveSpelling::new("char16_t", Some(2)));
+        tbl.insert("wchar_t", PrimitiveSpelling::new("wchar_t", wchar));
+        tbl.insert("uchar", PrimitiveSpelling::new("unsigned char", Some(1)));
+        tbl.insert("short", PrimitiveSpelling::new("short", Some(2)));
+        tbl.insert("ushort", PrimitiveSpelling::new("unsigned short", Some(2)));
+        tbl.insert("int", PrimitiveSpelling::new("int", Some(4)));
+        tbl.insert("uint", PrimitiveSpelling::new("unsigned int", Some(4)));
+        tbl.insert("weakint", PrimitiveSpelling::new("int", Some(4)));
+        tbl.insert("weak_integer", PrimitiveSpelling::new("int", Some(4)));
+        tbl.insert("file_descriptor", PrimitiveSpelling::new("int", Some(4)));
+        tbl.insert("size_t", PrimitiveSpelling::new("size_t", ptr));
+        tbl.insert("usize", PrimitiveSpelling::new("size_t", ptr));
+        tbl.insert("ptrdiff_t", PrimitiveSpelling::new("ptrdiff_t", ptr));
+        tbl.insert("ssize_t", PrimitiveSpelling::new("ssize_t", ptr));
+        tbl.insert("off_t", PrimitiveSpelling::new("off_t", None));
+        tbl.insert("time_t", PrimitiveSpelling::new("time_t", None));
+        tbl.insert("pid_t", PrimitiveSpelling::new("pid_t", Some(4)));
+        tbl.insert("errno_t", PrimitiveSpelling::new("int", Some(4)));
+        tbl.insert("HANDLE", PrimitiveSpelling::new("HANDLE", ptr));
+        tbl.insert("long", PrimitiveSpelling::new("long", long));
+        tbl.insert("ulong", PrimitiveSpelling::new("unsigned long", long));
+        tbl.insert("longlong", PrimitiveSpelling::new("long long", Some(8)));
+        tbl.insert(
+            "ulonglong",
+            PrimitiveSpelling::new("unsigned long long", Some(8)),
+        );
+        tbl.insert("float", PrimitiveSpelling::new("float", Some(4)));
+        tbl.insert("double", PrimitiveSpelling::new("double", Some(8)));
+        tbl
+    }
+
+    /// Makes the table emit lattice names unchanged while keeping the sizes and kinds of its spellings.
+    pub fn keeping_lattice_names(mut self) -> PrimitiveTable {
+        self.keep_lattice_names = true;
+        self
+    }
+
+    /// Adds or replaces the spelling for a lattice element.
+    pub fn insert(&mut self, lattice_name: &str, spelling: PrimitiveSpelling) {
+        self.spellings.insert(lattice_name.to_owned(), spelling);
+    }
+
+    /// Gets the spelling for a lattice element. Ghidra display name elements (data_type_with_display_nameX_X)
+    /// are looked up by their display name if the full name has no entry.
+    pub fn get_spelling(&self, lattice_name: &str) -> Option<&PrimitiveSpelling> {
+        self.spellings.get(lattice_name).or_else(|| {
+            lattice_name
+                .strip_prefix(GHIDRA_DISPLAY_NAME_PREFIX)
+                .and_then(|display| display.rsplit('_').next())
+                .and_then(|display| self.spellings.get(display))
+        })
+    }
+
+    /// Gets the C name for a lattice element, defaulting to the lattice name. The lattice name is always used if the
+    /// table keeps lattice names.
+    pub fn get_c_name<'a>(&'a self, lattice_name: &'a str) -> &'a str {
+        if self.keep_lattice_names {
+            return lattice_name;
+        }
+        self.get_spelling(lattice_name)
+            .map(|x| x.c_name.as_str())
+            .unwrap_or(lattice_name)
+    }
+
+    /// Gets the size in bytes of a lattice element if it is known.
+    pub fn get_byte_size(&self, lattice_name: &str) -> Option<usize> {
+        self.get_spelling(lattice_name).and_then(|x| x.byte_size)
+    }
+
+    /// Classifies a lattice element by its C spelling, even if the table keeps lattice names.
+    pub fn get_kind(&self, lattice_name: &str) -> NumericKind {
+        NumericKind::of_c_name(
+            self.get_spelling(lattice_name)
+                .map(|x| x.c_name.as_str())
+                .unwrap_or(lattice_name),
+        )
+    }
+}
+
+/// Whether a primitive holds signed integers, unsigned integers, or floating point values. The kind is kept when a
+/// primitive is respelled with another size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NumericKind {
+    /// A signed integer, also assumed for spellings that are not known to be unsigned or floating point
+    Signed,
+    /// An unsigned integer or boolean
+    Unsigned,
+    /// A floating point value
+    Float,
+}
+
+impl NumericKind {
+    /// Classifies the C spelling of a primitive.
+    pub fn of_c_name(c_name: &str) -> NumericKind {
+        if matches!(c_name, "float" | "double" | "long double") || c_name.starts_with("_Float") {
+            NumericKind::Float
+        } else if c_name.starts_with("unsigned")
+            || c_name.starts_with("uint")
+            || matches!(c_name, "_Bool" | "size_t" | "char16_t")
+        {
+            NumericKind::Unsigned
+        } else {
+            NumericKind::Signed
+        }
+    }
+}
+
+/// The C spelling of the primitive of a size and kind, used when the primitive a lattice element is spelled as has a
+/// different size than the accesses to a value. Floating point values accessed with a size that has no floating point
+/// type are spelled as unsigned integers of that size.
+pub fn sized_primitive_name(byte_size: usize, kind: NumericKind) -> Option<&'static str> {
+    match (kind, byte_size) {
+        (NumericKind::Float, 4) => Some("float"),
+        (NumericKind::Float, 8) => Some("double"),
+        (NumericKind::Float, 10) => Some("long double"),
+        (NumericKind::Signed, 1) => Some("int8_t"),
+        (NumericKind::Signed, 2) => Some("int16_t"),
+        (NumericKind::Signed, 4) => Some("int32_t"),
+        (NumericKind::Signed, 8) => Some("int64_t"),
+        (NumericKind::Signed, 16) => Some("__int128"),
+        (_, 1) => Some("uint8_t"),
+        (_, 2) => Some("uint16_t"),
+        (_, 4) => Some("uint32_t"),
+        (_, 8) => Some("uint64_t"),
+        (_, 16) => Some("unsigned __int128"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sized_primitive_name, DataModel, NumericKind, PrimitiveTable};
+
+    #[test]
+    fn ghidra_display_names_use_display_spelling() {
+        let tbl = PrimitiveTable::for_data_model(DataModel::Lp64);
+        assert_eq!(
+            tbl.get_c_name("data_type_with_display_namelonglong_longlong"),
+            "long long"
+        );
+        assert_eq!(
+            tbl.get_byte_size("data_type_with_display_namechar_char"),
+            Some(1)
+        );
+        assert_eq!(
+            tbl.get_c_name("data_type_with_id_3193700096609183017"),
+            "data_type_with_id_3193700096609183017"
+        );
+    }
+
+    #[test]
+    fn resized_primitives_keep_their_kind() {
+        let tbl = PrimitiveTable::default();
+        let kind = |nm| tbl.get_kind(nm);
+        assert_eq!(kind("int"), NumericKind::Signed);
+        assert_eq!(kind("ulong"), NumericKind::Unsigned);
+        assert_eq!(kind("double"), NumericKind::Float);
+
+        assert_eq!(sized_primitive_name(1, kind("long")), Some("int8_t"));
+        assert_eq!(sized_primitive_name(2, kind("uint")), Some("uint16_t"));
+        assert_eq!(sized_primitive_name(4, kind("double")), Some("float"));
+        assert_eq!(sized_primitive_name(2, kind("float")), Some("uint16_t"));
+        assert_eq!(sized_primitive_name(3, kind("int")), None);
+    }
+
+    #[test]
+    fn sizes_follow_the_data_model() {
+        let size = |model, nm| PrimitiveTable::for_data_model(model).get_byte_size(nm);
+        assert_eq!(size(DataModel::Lp64, "long"), Some(8));
+        assert_eq!(size(DataModel::Llp64, "ulong"), Some(4));
+        assert_eq!(size(DataModel::Llp64, "size_t"), Some(8));
+        assert_eq!(size(DataModel::Ilp32, "long"), Some(4));
+        assert_eq!(size(DataModel::Ilp32, "size_t"), Some(4));
+        assert_eq!(size(DataModel::Llp64, "wchar_t"), Some(2));
+
+        assert_eq!(DataModel::for_target(8, true), DataModel::Llp64);
+        assert_eq!(DataModel::for_target(8, false), DataModel::Lp64);
+        assert_eq!(DataModel::for_target(4, true), DataModel::Ilp32);
+    }
+
+    #[test]
+    fn default_table_keeps_lattice_names() {
+        let tbl = PrimitiveTable::default();
+        assert_eq!(tbl.get_c_name("weakint"), "weakint");
+        assert_eq!(tbl.get_c_name("bottom"), "bottom");
+        assert_eq!(tbl.get_byte_size("weakint"), Some(4));
+        assert_eq!(tbl.get_kind("ulong"), NumericKind::Unsigned);
+    }
+}
@@ -0,0 +1,176 @@
+use std::{collections::BTreeMap, io::Write};
+
+use anyhow::Context;
+
+use cwe_checker_lib::intermediate_representation::Tid;
+use petgraph::graph::NodeIndex;
+use prost::Message;
+
+use crate::{ctypes, solver::type_lattice::NamedLatticeElement};
+
+use super::{
+    convert_ctype, convert_typeid, invariants, linked::kind_to_protobuf, CType, LoweringContext,
+    TypeId, CTYPES_SCHEMA_VERSION,
+};
+
+/// Writes lowered types as a stream of length-delimited messages: a [ctypes::CTypeStreamHeader] followed by a
+/// [ctypes::TermTypes] for each labeled term. Each term's types are lowered, written, and dropped before the next term
+/// is lowered, so only the mapping from nodes to type ids stays resident. Nodes that are not reachable from a labeled
+/// term are not lowered.
+pub struct CTypeStreamWriter<W: Write> {
+    out: W,
+    lowered: BTreeMap<NodeIndex, TypeId>,
+}
+
+impl<W: Write> CTypeStreamWriter<W> {
+    /// Creates a writer, writing the stream header to out.
+    pub fn new(mut out: W) -> anyhow::Result<CTypeStreamWriter<W>> {
+        let header = ctypes::CTypeStreamHeader {
+            schema_version: CTYPES_SCHEMA_VERSION,
+        };
+        out.write_all(&header.encode_length_delimited_to_vec())?;
+        Ok(CTypeStreamWriter {
+            out,
+            lowered: BTreeMap::new(),
+        })
+    }
+
+    /// Lowers the types needed by the term represented by root and writes them along with the term's type.
+    pub fn write_term<U: NamedLatticeElement>(
+        &mut self,
+        ctx: &mut LoweringContext<U>,
+        tid: &Tid,
+        root: NodeIndex,
+    ) -> anyhow::Result<()> {
+        let types = ctx.lower_reachable(root, &mut self.lowered);
+        invariants::enforce(
+            invariants::check_lowered_types(&self.lowered, &types),
+            ctx.options.strict_invariants,
+        )
+        .with_context(|| format!("while lowering the types of {}", tid))?;
+
+        let mut linked_structures = BTreeMap::new();
+        for (nd, structure) in ctx.linked_structures.iter() {
+            if let Some(ty) = self.lowered.get(nd) {
+                if let Some(CType::Structure(_)) = types.get(ty) {
+                    linked_structures.insert(
+                        convert_typeid(*ty)?.type_id,
+                        kind_to_protobuf(structure.kind) as i32,
+                    );
+                }
+            }
+        }
+
+        let mut msg = ctypes::TermTypes {
+            tid: Some(ctypes::Tid {
+                name: tid.get_str_repr().to_owned(),
+                address: tid.address.clone(),
+            }),
+            type_id: Some(convert_typeid(self.lowered[&root])?),
+            types: Vec::new(),
+            linked_structures,
+        };
+        for (idx, ctype) in types.into_iter() {
+            msg.types.push(convert_ctype(idx, ctype, &self.lowered)?);
+        }
+
+        self.out.write_all(&msg.encode_length_delimited_to_vec())?;
+        Ok(())
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(self) -> anyhow::Result<W> {
+        self.finish_with_types().map(|(out, _)| out)
+    }
+
+    /// Flushes and returns the underlying writer along with the type id of each node lowered while writing.
+    pub fn finish_with_types(mut self) -> anyhow::Result<(W, BTreeMap<NodeIndex, TypeId>)> {
+        self.out.flush()?;
+        Ok((self.out, self.lowered))
+    }
+}
+
+/// Streams the types of every labeled term in tid_to_node to out, returning out along with the type id of each lowered
+/// node. Alias compression and primitive canonicalization need the whole mapping, so streaming fails if either is
+/// enabled in the context's options.
+pub fn stream_mapping<U: NamedLatticeElement, W: Write>(
+    mut ctx: LoweringContext<U>,
+    tid_to_node: &BTreeMap<Tid, NodeIndex>,
+    out: W,
+) -> anyhow::Result<(W, BTreeMap<NodeIndex, TypeId>)> {
+    if ctx.options.compress_aliases {
+        return Err(anyhow::anyhow!(
+            "alias compression cannot be used when streaming types"
+        ));
+    }
+    if ctx.options.canonical_primitives {
+        return Err(anyhow::anyhow!(
+            "primitive canonicalization cannot be used when streaming types"
+        ));
+    }
+
+    let mut writer = CTypeStreamWriter::new(out)?;
+    for (tid, nd) in tid_to_node.iter() {
+        writer.write_term(&mut ctx, tid, *nd)?;
+    }
+    writer.finish_with_types()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+    use petgraph::graph::NodeIndex;
+    use prost::Message;
+
+    use crate::{
+        ctypes,
+        lowering::{LoweringOptions, CTYPES_SCHEMA_VERSION},
+        test_utils::sketches::{int_lattice, lower, sketch_from_json, var},
+    };
+
+    #[test]
+    fn shared_types_are_written_once() {
+        let lattice = int_lattice();
+        // two pointers to the same integer
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","int"],["bottom","top"]]"#,
+            r#"[[0,1,"Load"],[2,1,"Load"]]"#,
+            &[(var("sub_a"), 0), (var("sub_b"), 2)],
+        );
+
+        let tid_to_node = BTreeMap::from([
+            (
+                Tid::create("sub_a".to_owned(), "1000".to_owned()),
+                NodeIndex::new(0),
+            ),
+            (
+                Tid::create("sub_b".to_owned(), "2000".to_owned()),
+                NodeIndex::new(2),
+            ),
+        ]);
+        let ctx = lower(&grph, &lattice, LoweringOptions::default());
+        let (out, lowered) = super::stream_mapping(ctx, &tid_to_node, Vec::new()).unwrap();
+        assert!(tid_to_node.values().all(|nd| lowered.contains_key(nd)));
+
+        let mut buf = out.as_slice();
+        let header = ctypes::CTypeStreamHeader::decode_length_delimited(&mut buf).unwrap();
+        assert_eq!(header.schema_version, CTYPES_SCHEMA_VERSION);
+
+        let count_primitives = |term: &ctypes::TermTypes| {
+            term.types
+                .iter()
+                .filter(|ct| matches!(ct.inner_type, Some(ctypes::c_type::InnerType::Primitive(_))))
+                .count()
+        };
+        let fst = ctypes::TermTypes::decode_length_delimited(&mut buf).unwrap();
+        let snd = ctypes::TermTypes::decode_length_delimited(&mut buf).unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(fst.tid.unwrap().name, "sub_a");
+        assert_eq!(snd.tid.unwrap().name, "sub_b");
+        assert_eq!(count_primitives(&fst), 1);
+        assert_eq!(count_primitives(&snd), 0);
+    }
+}
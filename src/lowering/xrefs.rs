@@ -0,0 +1,159 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+
+use anyhow::Context;
+use cwe_checker_lib::intermediate_representation::Tid;
+use petgraph::graph::NodeIndex;
+
+use crate::{constraints, ctypes};
+
+use super::{callsites::tid_to_protobuf, convert_typeid, CType, TypeId, CTYPES_SCHEMA_VERSION};
+
+// Finds the type of fld in the structure ty was lowered to, or in the structure members of a union of candidates.
+fn field_type(
+    types: &BTreeMap<TypeId, CType>,
+    ty: TypeId,
+    fld: &constraints::Field,
+) -> Option<TypeId> {
+    let in_structure = |ty: &TypeId| match types.get(ty) {
+        Some(CType::Structure(fields)) => fields
+            .iter()
+            .find(|field| {
+                i64::try_from(field.byte_offset()) == Ok(fld.offset) && field.bit_size() == fld.size
+            })
+            .map(|field| field.type_index()),
+        _ => None,
+    };
+
+    match types.get(&ty) {
+        Some(CType::Union(members)) => members.iter().find_map(in_structure),
+        _ => in_structure(&ty),
+    }
+}
+
+/// Builds the cross-reference table from each lowered type and its fields to the loads and stores accessing them.
+/// Nodes that were not lowered are skipped, and the accesses of nodes lowered to the same type are merged. Fails if a
+/// type id or field size cannot be converted.
+pub fn build_xref_table(
+    node_types: &BTreeMap<NodeIndex, TypeId>,
+    types: &BTreeMap<TypeId, CType>,
+    accesses: &BTreeMap<NodeIndex, BTreeMap<constraints::Field, BTreeSet<Tid>>>,
+) -> anyhow::Result<ctypes::XrefTable> {
+    let mut by_type: BTreeMap<TypeId, BTreeMap<&constraints::Field, BTreeSet<&Tid>>> =
+        BTreeMap::new();
+    for (nd, fields) in accesses.iter() {
+        if let Some(ty) = node_types.get(nd) {
+            let type_fields = by_type.entry(*ty).or_default();
+            for (fld, defs) in fields.iter() {
+                type_fields.entry(fld).or_default().extend(defs.iter());
+            }
+        }
+    }
+
+    let mut table = ctypes::XrefTable {
+        schema_version: CTYPES_SCHEMA_VERSION,
+        types: Vec::new(),
+    };
+    for (ty, fields) in by_type.into_iter() {
+        let fields = fields
+            .into_iter()
+            .map(|(fld, defs)| {
+                Ok(ctypes::FieldXrefs {
+                    byte_offset: fld.offset,
+                    bit_size: u32::try_from(fld.size)
+                        .with_context(|| format!("bit size {} exceeds u32", fld.size))?,
+                    field_type_id: field_type(types, ty, fld).map(convert_typeid).transpose()?,
+                    accesses: defs.into_iter().map(tid_to_protobuf).collect(),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        table.types.push(ctypes::TypeXrefs {
+            type_id: Some(convert_typeid(ty)?),
+            fields,
+        });
+    }
+
+    Ok(table)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+    use petgraph::graph::NodeIndex;
+
+    use crate::{
+        constraints,
+        lowering::{CType, Field, TypeId},
+    };
+
+    use super::build_xref_table;
+
+    #[test]
+    fn fields_are_cross_referenced_to_their_accesses() {
+        let types = BTreeMap::from([
+            (TypeId(0), CType::Primitive("int".to_owned())),
+            (
+                TypeId(1),
+                CType::Structure(vec![Field {
+                    byte_offset: 8,
+                    bit_sz: 32,
+                    type_index: TypeId(0),
+                    access_count: 0,
+                }]),
+            ),
+        ]);
+        let load = Tid::create("instr_00401004_0".to_owned(), "00401004".to_owned());
+        let store = Tid::create("instr_00401010_1".to_owned(), "00401010".to_owned());
+        let accesses = BTreeMap::from([
+            (
+                NodeIndex::new(3),
+                BTreeMap::from([(
+                    constraints::Field::new(8, 32),
+                    BTreeSet::from([load.clone()]),
+                )]),
+            ),
+            // a node lowered to the same structure adds its accesses
+            (
+                NodeIndex::new(4),
+                BTreeMap::from([
+                    (
+                        constraints::Field::new(8, 32),
+                        BTreeSet::from([store.clone()]),
+                    ),
+                    (constraints::Field::new(12, 32), BTreeSet::from([store])),
+                ]),
+            ),
+            // accesses to nodes that were not lowered are left out
+            (
+                NodeIndex::new(5),
+                BTreeMap::from([(constraints::Field::new(0, 64), BTreeSet::from([load]))]),
+            ),
+        ]);
+        let node_types = BTreeMap::from([
+            (NodeIndex::new(3), TypeId(1)),
+            (NodeIndex::new(4), TypeId(1)),
+        ]);
+
+        let table = build_xref_table(&node_types, &types, &accesses).unwrap();
+        assert_eq!(table.types.len(), 1);
+        let fields = &table.types[0].fields;
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].byte_offset, 8);
+        assert_eq!(
+            fields[0].field_type_id.as_ref().map(|ty| ty.type_id),
+            Some(0)
+        );
+        assert_eq!(
+            fields[0]
+                .accesses
+                .iter()
+                .map(|tid| tid.address.as_str())
+                .collect::<Vec<_>>(),
+            vec!["00401004", "00401010"]
+        );
+        assert_eq!(fields[1].byte_offset, 12);
+        assert!(fields[1].field_type_id.is_none());
+    }
+}
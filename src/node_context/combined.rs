@@ -0,0 +1,221 @@
+use std::collections::BTreeSet;
+
+use cwe_checker_lib::intermediate_representation::{ByteSize, Def, Expression, Jmp, Term};
+use log::debug;
+
+use crate::{
+    analysis::mips::read_registers,
+    constraint_generation::{
+        ConstantResolver, NodeContextMapping, PointsToMapping, RegisterMapping, TypeVariableAccess,
+    },
+    constraints::DerivedTypeVar,
+};
+
+/// A points-to mapping that is checked against the register mapping of the same program point. The two analyses run
+/// independently, so the reaching definitions of a register may not reflect that the register holds a stack address
+/// the points-to analysis knows. Once combined with a register mapping, pointer facts are preferred: accesses the
+/// points-to analysis resolves to a slot of the current frame type the slot directly, and the registers whose reaching
+/// definitions disagree are logged.
+#[derive(Clone)]
+pub struct CombinedContext<R: RegisterMapping, P: PointsToMapping> {
+    reg_map: Option<R>,
+    points_to: P,
+}
+
+impl<R: RegisterMapping, P: PointsToMapping> CombinedContext<R, P> {
+    /// Wraps the points-to mapping of a program point without a register mapping, so it behaves as the points-to
+    /// mapping alone.
+    pub fn new(points_to: P) -> CombinedContext<R, P> {
+        CombinedContext {
+            reg_map: None,
+            points_to,
+        }
+    }
+
+    /// Combines the points-to mapping with the register mapping of the same program point, preferring pointer facts
+    /// for loads and stores.
+    pub fn with_register_mapping(mut self, reg_map: Option<R>) -> CombinedContext<R, P> {
+        self.reg_map = reg_map;
+        self
+    }
+
+    /// Gets the register mapping of this program point, if combined with one.
+    pub fn get_register_mapping(&self) -> Option<&R> {
+        self.reg_map.as_ref()
+    }
+
+    /// Gets the points-to mapping of this program point.
+    pub fn get_points_to_mapping(&self) -> &P {
+        &self.points_to
+    }
+
+    /// The registers of an address without exactly one reaching definition. The register mapping represents such a
+    /// register by a fresh or joined type variable, which loses the single location the points-to analysis found.
+    fn inconsistent_registers(&self, reg_map: &R, address: &Expression) -> Vec<String> {
+        let mut registers = Vec::new();
+        read_registers(address, &mut registers);
+        registers
+            .into_iter()
+            .filter(|var| reg_map.access(var).len() != 1)
+            .map(|var| var.name.clone())
+            .collect()
+    }
+}
+
+impl<R: RegisterMapping, P: PointsToMapping> NodeContextMapping for CombinedContext<R, P> {
+    fn apply_def(&self, term: &Term<Def>) -> Self {
+        CombinedContext {
+            reg_map: self.reg_map.as_ref().map(|reg_map| reg_map.apply_def(term)),
+            points_to: self.points_to.apply_def(term),
+        }
+    }
+
+    fn apply_return_node(&self, call_term: &Term<Jmp>, return_term: &Term<Jmp>) -> Self {
+        CombinedContext {
+            reg_map: self
+                .reg_map
+                .as_ref()
+                .map(|reg_map| reg_map.apply_return_node(call_term, return_term)),
+            points_to: self.points_to.apply_return_node(call_term, return_term),
+        }
+    }
+}
+
+impl<R: RegisterMapping, P: PointsToMapping> PointsToMapping for CombinedContext<R, P> {
+    fn points_to(&self, address: &Expression, sz: ByteSize) -> BTreeSet<TypeVariableAccess> {
+        self.points_to.points_to(address, sz)
+    }
+
+    fn get_pointer_variable(
+        &self,
+        address: &Expression,
+        constant_resolver: &impl ConstantResolver,
+    ) -> Option<DerivedTypeVar> {
+        self.points_to
+            .get_pointer_variable(address, constant_resolver)
+    }
+
+    fn frame_slot(&self, address: &Expression, sz: ByteSize) -> Option<TypeVariableAccess> {
+        let reg_map = self.reg_map.as_ref()?;
+        let slot = self.points_to.frame_slot(address, sz)?;
+        let inconsistent = self.inconsistent_registers(reg_map, address);
+        if !inconsistent.is_empty() {
+            debug!(
+                "Preferring the frame slot {} at {:?} over the reaching definitions of {}",
+                slot.ty_var,
+                slot.offset,
+                inconsistent.join(", ")
+            );
+        }
+        Some(slot)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use cwe_checker_lib::intermediate_representation::{
+        BinOpType, Bitvector, ByteSize, Def, Expression, Jmp, Term, Variable,
+    };
+
+    use crate::{
+        constraint_generation::{
+            ConstantResolver, NodeContextMapping, PointsToMapping, RegisterMapping,
+            TypeVariableAccess,
+        },
+        constraints::{DerivedTypeVar, TypeVariable},
+    };
+
+    use super::CombinedContext;
+
+    /// A register mapping without any reaching definition.
+    #[derive(Clone)]
+    struct NoDefinitions;
+
+    impl NodeContextMapping for NoDefinitions {
+        fn apply_def(&self, _term: &Term<Def>) -> Self {
+            self.clone()
+        }
+
+        fn apply_return_node(&self, _call_term: &Term<Jmp>, _return_term: &Term<Jmp>) -> Self {
+            self.clone()
+        }
+    }
+
+    impl RegisterMapping for NoDefinitions {
+        fn access(&self, _var: &Variable) -> BTreeSet<TypeVariable> {
+            BTreeSet::new()
+        }
+    }
+
+    /// A points-to mapping resolving every address to the slot at offset -16 of the frame.
+    #[derive(Clone)]
+    struct FrameSlot;
+
+    impl FrameSlot {
+        fn slot(sz: ByteSize) -> TypeVariableAccess {
+            TypeVariableAccess {
+                ty_var: TypeVariable::new("stack_frame".to_owned()),
+                sz,
+                offset: Some(-16),
+            }
+        }
+    }
+
+    impl NodeContextMapping for FrameSlot {
+        fn apply_def(&self, _term: &Term<Def>) -> Self {
+            self.clone()
+        }
+
+        fn apply_return_node(&self, _call_term: &Term<Jmp>, _return_term: &Term<Jmp>) -> Self {
+            self.clone()
+        }
+    }
+
+    impl PointsToMapping for FrameSlot {
+        fn points_to(&self, _address: &Expression, sz: ByteSize) -> BTreeSet<TypeVariableAccess> {
+            BTreeSet::from([FrameSlot::slot(sz)])
+        }
+
+        fn get_pointer_variable(
+            &self,
+            _address: &Expression,
+            _constant_resolver: &impl ConstantResolver,
+        ) -> Option<DerivedTypeVar> {
+            None
+        }
+
+        fn frame_slot(&self, _address: &Expression, sz: ByteSize) -> Option<TypeVariableAccess> {
+            Some(FrameSlot::slot(sz))
+        }
+    }
+
+    #[test]
+    fn pointer_facts_are_preferred_when_enabled() {
+        let rax = Variable {
+            name: "RAX".to_owned(),
+            size: ByteSize::new(8),
+            is_temp: false,
+        };
+        let address = Expression::BinOp {
+            op: BinOpType::IntAdd,
+            lhs: Box::new(Expression::Var(rax)),
+            rhs: Box::new(Expression::Const(Bitvector::from_i64(8))),
+        };
+
+        let independent: CombinedContext<NoDefinitions, _> = CombinedContext::new(FrameSlot);
+        assert_eq!(independent.frame_slot(&address, ByteSize::new(4)), None);
+        assert_eq!(independent.points_to(&address, ByteSize::new(4)).len(), 1);
+
+        let combined = independent.with_register_mapping(Some(NoDefinitions));
+        assert_eq!(
+            combined.inconsistent_registers(&NoDefinitions, &address),
+            vec!["RAX"]
+        );
+        assert_eq!(
+            combined.frame_slot(&address, ByteSize::new(4)),
+            Some(FrameSlot::slot(ByteSize::new(4)))
+        );
+    }
+}
@@ -2,10 +2,11 @@ use std::{
     borrow::Borrow,
     collections::{BTreeMap, HashMap},
     rc::Rc,
+    sync::Arc,
 };
 
 use cwe_checker_lib::{
-    analysis::pointer_inference::Config,
+    analysis::{graph::Node, pointer_inference::Config},
     intermediate_representation::{Program, Tid},
     AnalysisResults,
 };
@@ -13,14 +14,18 @@ use cwe_checker_lib::{
 use petgraph::graph::NodeIndex;
 
 use crate::{
+    analysis::unwind::FrameLayout,
     constraint_generation::{
-        ConstantResolver, NodeContext, NodeContextMapping, PointsToMapping, RegisterMapping,
-        SubprocedureLocators,
+        ConstantResolver, NodeContext, NodeContextMapping, PointerIntegerTypes, PointsToMapping,
+        RegisterMapping, SubprocedureLocators,
     },
     constraints::{DerivedTypeVar, TypeVariable},
     util::FileDebugLogger,
 };
 
+/// Checks the points-to mapping of a program point against its register mapping and prefers pointer facts.
+pub mod combined;
+
 /// Wraps the cwe_checker points to analysis to generate type variables related to stores and loads based on the [cwe_checker_lib::abstract_domain::AbstractIdentifier].
 pub mod points_to;
 
@@ -34,7 +39,10 @@ use anyhow::Result;
 use std::iter::Iterator;
 
 use self::{
-    points_to::PointsToContext, register_map::RegisterContext, subproc_loc::ProcedureContext,
+    combined::CombinedContext,
+    points_to::{OffsetClustering, PointsToContext},
+    register_map::RegisterContext,
+    subproc_loc::ProcedureContext,
 };
 
 /// Joins mappings from [NodeIndex] to each analysis result into a singular map of [NodeContext].  
@@ -50,7 +58,7 @@ pub fn make_node_contexts<
     mut constant_contexts: HashMap<NodeIndex, C>,
     nodes: impl Iterator<Item = NodeIndex>,
     weakest_integral_type: TypeVariable,
-) -> HashMap<NodeIndex, NodeContext<R, P, S, C>> {
+) -> BTreeMap<NodeIndex, NodeContext<R, P, S, C>> {
     nodes
         .filter_map(|idx| {
             let r = register_contexts.remove(&idx);
@@ -134,18 +142,22 @@ impl ConstantResolver for GhidraConstantResolver {
     }
 }
 
-/// Creates a default context with the default analyses [register_map], [points_to], and [subproc_loc]
-pub fn create_default_context<'a>(
+/// The register, points-to, subprocedure, and constant contexts of each program point computed by the default
+/// analyses.
+type DefaultMappings = (
+    HashMap<NodeIndex, RegisterContext>,
+    HashMap<NodeIndex, PointsToContext>,
+    HashMap<NodeIndex, ProcedureContext>,
+    HashMap<NodeIndex, GhidraConstantResolver>,
+);
+
+fn run_default_analyses<'a>(
     proj: &'a AnalysisResults<'a>,
     config: Config,
-    weakest_integral_type: TypeVariable,
+    clustering: OffsetClustering,
+    frames: &BTreeMap<Tid, FrameLayout>,
     debug_dir: FileDebugLogger,
-) -> Result<
-    HashMap<
-        NodeIndex,
-        NodeContext<RegisterContext, PointsToContext, ProcedureContext, GhidraConstantResolver>,
-    >,
-> {
+) -> Result<DefaultMappings> {
     let reg_context = register_map::run_analysis(proj.project, proj.control_flow_graph);
 
     for nd_idx in proj.control_flow_graph.node_indices() {
@@ -163,10 +175,27 @@ pub fn create_default_context<'a>(
         }
     }
 
-    let points_to_context = points_to::run_analysis(proj, config)?;
+    let frames = frames
+        .iter()
+        .map(|(tid, frame)| (tid, Arc::new(frame.clone())))
+        .collect::<BTreeMap<_, _>>();
+    let points_to_context = points_to::run_analysis(proj, config, clustering)?
+        .into_iter()
+        .map(|(idx, ctx)| {
+            let sub = match &proj.control_flow_graph[idx] {
+                Node::BlkStart(_, sub) | Node::BlkEnd(_, sub) => sub,
+                Node::CallReturn { call: (_, sub), .. } => sub,
+                Node::CallSource {
+                    source: (_, sub), ..
+                } => sub,
+            };
+            (idx, ctx.with_frame(frames.get(&sub.tid).cloned()))
+        })
+        .collect::<HashMap<_, _>>();
 
     let proc_handler = ProcedureContext {
         stack_pointer: proj.project.stack_pointer_register.clone(),
+        cpu_architecture: proj.project.cpu_architecture.clone(),
     };
     let proc_context: HashMap<NodeIndex, ProcedureContext> = proj
         .control_flow_graph
@@ -175,16 +204,106 @@ pub fn create_default_context<'a>(
         .collect();
 
     let const_context = GhidraConstantResolver::from(&proj.project.program.term);
+    let const_context = proj
+        .control_flow_graph
+        .node_indices()
+        .map(|idx| (idx, const_context.clone()))
+        .collect();
+
+    Ok((reg_context, points_to_context, proc_context, const_context))
+}
+
+/// Creates a default context with the default analyses [register_map], [points_to], and [subproc_loc].
+/// The points-to contexts of the subprocedures with a frame layout are normalized by it, see [PointsToContext::with_frame].
+pub fn create_default_context<'a>(
+    proj: &'a AnalysisResults<'a>,
+    config: Config,
+    clustering: OffsetClustering,
+    weakest_integral_type: TypeVariable,
+    pointer_integers: Option<PointerIntegerTypes>,
+    frames: &BTreeMap<Tid, FrameLayout>,
+    debug_dir: FileDebugLogger,
+) -> Result<
+    BTreeMap<
+        NodeIndex,
+        NodeContext<RegisterContext, PointsToContext, ProcedureContext, GhidraConstantResolver>,
+    >,
+> {
+    let (reg_context, points_to_context, proc_context, const_context) =
+        run_default_analyses(proj, config, clustering, frames, debug_dir)?;
 
     Ok(make_node_contexts(
         reg_context,
         points_to_context,
         proc_context,
-        proj.control_flow_graph
-            .node_indices()
-            .map(|idx| (idx, const_context.clone()))
-            .collect(),
+        const_context,
+        proj.control_flow_graph.node_indices(),
+        weakest_integral_type,
+    )
+    .into_iter()
+    .map(|(idx, ctx)| {
+        (
+            idx,
+            ctx.with_pointer_integer_types(pointer_integers.clone()),
+        )
+    })
+    .collect())
+}
+
+/// Creates a context with the default analyses where the points-to context of each program point is combined with its
+/// register context, see [CombinedContext]. Without `prefer_pointer_facts` the points-to contexts are not combined,
+/// so the constraints are those of [create_default_context].
+#[allow(clippy::too_many_arguments)]
+pub fn create_combined_context<'a>(
+    proj: &'a AnalysisResults<'a>,
+    config: Config,
+    clustering: OffsetClustering,
+    weakest_integral_type: TypeVariable,
+    pointer_integers: Option<PointerIntegerTypes>,
+    frames: &BTreeMap<Tid, FrameLayout>,
+    prefer_pointer_facts: bool,
+    debug_dir: FileDebugLogger,
+) -> Result<
+    BTreeMap<
+        NodeIndex,
+        NodeContext<
+            RegisterContext,
+            CombinedContext<RegisterContext, PointsToContext>,
+            ProcedureContext,
+            GhidraConstantResolver,
+        >,
+    >,
+> {
+    let (reg_context, points_to_context, proc_context, const_context) =
+        run_default_analyses(proj, config, clustering, frames, debug_dir)?;
+    let combined_context = points_to_context
+        .into_iter()
+        .map(|(idx, points_to)| {
+            let reg_map = reg_context
+                .get(&idx)
+                .filter(|_| prefer_pointer_facts)
+                .cloned();
+            (
+                idx,
+                CombinedContext::new(points_to).with_register_mapping(reg_map),
+            )
+        })
+        .collect();
+
+    Ok(make_node_contexts(
+        reg_context,
+        combined_context,
+        proc_context,
+        const_context,
         proj.control_flow_graph.node_indices(),
         weakest_integral_type,
-    ))
+    )
+    .into_iter()
+    .map(|(idx, ctx)| {
+        (
+            idx,
+            ctx.with_pointer_integer_types(pointer_integers.clone()),
+        )
+    })
+    .collect())
 }
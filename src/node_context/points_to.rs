@@ -1,7 +1,9 @@
+use crate::analysis::unwind::FrameLayout;
 use crate::constraint_generation::{
     ConstantResolver, NodeContextMapping, PointsToMapping, TypeVariableAccess,
 };
 use crate::constraints::{DerivedTypeVar, TypeVariable};
+use crate::diagnostics::{self, DiagnosticKind};
 
 use anyhow::Result;
 use cwe_checker_lib::abstract_domain::{
@@ -16,6 +18,7 @@ use cwe_checker_lib::AnalysisResults;
 use cwe_checker_lib::intermediate_representation::RuntimeMemoryImage;
 use log::warn;
 use petgraph::graph::NodeIndex;
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeSet, HashMap};
 use std::sync::Arc;
 
@@ -33,6 +36,40 @@ lazy_static! {
     };
 }
 
+/// Folds the offsets of memory accesses so that walking a large buffer does not produce a distinct field per offset.
+/// Offsets with a magnitude below the threshold are kept exactly. Offsets beyond it are folded onto the first `stride` bytes past the
+/// threshold if a stride is given, and otherwise collapsed onto the threshold itself. A stride without a threshold folds every offset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OffsetClustering {
+    /// The magnitude at which offsets start being folded
+    pub max_offset: Option<i64>,
+    /// The size of the repeating element offsets are folded into
+    pub stride: Option<i64>,
+}
+
+impl OffsetClustering {
+    /// Maps an offset to the representative offset of its cluster. Negative offsets (ie. into the stack frame) are folded symmetrically.
+    pub fn cluster(&self, offset: i64) -> i64 {
+        // the magnitude of i64::MIN does not fit an i64, so magnitudes are unsigned
+        let magnitude = offset.unsigned_abs();
+        let threshold = match (self.max_offset, self.stride) {
+            (Some(max), _) if magnitude >= max.unsigned_abs() => max.unsigned_abs(),
+            (None, Some(_)) => 0,
+            _ => return offset,
+        };
+
+        let beyond = magnitude - threshold;
+        let folded = match self.stride {
+            Some(stride) if stride > 0 => threshold + beyond % stride.unsigned_abs(),
+            _ => threshold,
+        };
+
+        // the folded magnitude is at most the magnitude of the offset, so the signed result fits an i64
+        (i128::from(offset.signum()) * i128::from(folded)) as i64
+    }
+}
+
 /// Wraps a pointer state such that successor states can be generated.
 #[derive(Clone)]
 pub struct PointerState {
@@ -49,11 +86,21 @@ impl NodeContextMapping for PointerState {
             // TODO(ian): dont unwrap
             Def::Load { var, address } => {
                 if let Err(x) = new_ptr_state.handle_load(var, address, &self.rt_mem) {
+                    diagnostics::report_at(
+                        DiagnosticKind::PointsTo,
+                        Some(&term.tid),
+                        x.to_string(),
+                    );
                     warn!("{}", x.to_string());
                 }
             }
             Def::Store { address, value } => {
                 if let Err(x) = new_ptr_state.handle_store(address, value, &self.rt_mem) {
+                    diagnostics::report_at(
+                        DiagnosticKind::PointsTo,
+                        Some(&term.tid),
+                        x.to_string(),
+                    );
                     warn!("{}", x.to_string());
                 }
             }
@@ -87,15 +134,40 @@ pub struct PointsToContext {
     pointer_state: PointerState,
     /// Stack pointer for the program, used to determine the stack offset
     pub stack_pointer: Variable,
+    clustering: OffsetClustering,
+    frame: Option<Arc<FrameLayout>>,
 }
 
 impl PointsToContext {
-    fn new(st: PointerState, stack_pointer: Variable) -> PointsToContext {
+    fn new(
+        st: PointerState,
+        stack_pointer: Variable,
+        clustering: OffsetClustering,
+    ) -> PointsToContext {
         PointsToContext {
             pointer_state: st,
             stack_pointer,
+            clustering,
+            frame: None,
         }
     }
+
+    /// Sets the frame layout of the function of this program point, recovered from unwind information. Accesses to
+    /// the stack frame of the function then have offsets relative to the CFA rather than to the stack pointer at the
+    /// entry, and accesses to the slots of saved registers and the return address are dropped so that spilling a
+    /// callee-saved register does not type the frame.
+    pub fn with_frame(mut self, frame: Option<Arc<FrameLayout>>) -> PointsToContext {
+        self.frame = frame;
+        self
+    }
+
+    fn is_current_frame(&self, object_id: &AbstractIdentifier) -> bool {
+        self.pointer_state
+            .state
+            .get_register(&self.stack_pointer)
+            .get_if_unique_target()
+            .map_or(false, |(stack_id, _)| stack_id == object_id)
+    }
 }
 
 impl PointsToContext {
@@ -120,19 +192,35 @@ impl PointsToContext {
     ///
 
     /// We assume that abstract identifiers are unique.
+    /// Returns None for accesses to the save slots of the frame of the function, if its layout is known.
     fn memory_access_into_tvar(
         &self,
         object_id: &AbstractIdentifier,
         offset: &IntervalDomain,
         sz: ByteSize,
-    ) -> TypeVariableAccess {
+    ) -> Option<TypeVariableAccess> {
         // TODO(ian): we may want to normalize this offset to the abstract object offset
-        TypeVariableAccess {
-            offset: offset.try_to_offset().ok(),
+        let mut offset = offset.try_to_offset().ok();
+        if let (Some(frame), Some(off)) = (&self.frame, offset) {
+            if self.is_current_frame(object_id) {
+                let cfa_offset = frame.cfa_offset(off);
+                if frame.is_save_slot(
+                    cfa_offset,
+                    (sz.as_bit_length() / 8) as u64,
+                    (self.stack_pointer.size.as_bit_length() / 8) as u64,
+                ) {
+                    return None;
+                }
+                offset = Some(cfa_offset);
+            }
+        }
+
+        Some(TypeVariableAccess {
+            offset: offset.map(|off| self.clustering.cluster(off)),
             ty_var: Self::type_variable_from_abstract_id(object_id),
 
             sz,
-        }
+        })
     }
 
     fn dom_val_to_tvars(
@@ -143,7 +231,7 @@ impl PointsToContext {
         dom_val
             .get_relative_values()
             .iter()
-            .map(|(a_id, offset)| self.memory_access_into_tvar(a_id, offset, sz))
+            .filter_map(|(a_id, offset)| self.memory_access_into_tvar(a_id, offset, sz))
             .collect()
     }
 }
@@ -157,7 +245,8 @@ impl NodeContextMapping for PointsToContext {
     ) -> Self {
         let new_ptr_state = self.pointer_state.apply_def(term);
 
-        PointsToContext::new(new_ptr_state, self.stack_pointer.clone())
+        PointsToContext::new(new_ptr_state, self.stack_pointer.clone(), self.clustering)
+            .with_frame(self.frame.clone())
     }
 
     fn apply_return_node(
@@ -172,6 +261,8 @@ impl NodeContextMapping for PointsToContext {
         PointsToContext {
             pointer_state: self.pointer_state.apply_return_node(call_term, return_term),
             stack_pointer: self.stack_pointer.clone(),
+            clustering: self.clustering,
+            frame: self.frame.clone(),
         }
     }
 }
@@ -206,12 +297,25 @@ impl PointsToMapping for PointsToContext {
         }
         None
     }
+
+    /// An address is a slot of the current frame if its only target is the stack frame of the function at an exact
+    /// offset, without any absolute value.
+    fn frame_slot(&self, address: &Expression, sz: ByteSize) -> Option<TypeVariableAccess> {
+        let dom_val = self.pointer_state.state.eval(address);
+        let (object_id, offset) = dom_val.get_if_unique_target()?;
+        if !self.is_current_frame(object_id) {
+            return None;
+        }
+        self.memory_access_into_tvar(object_id, offset, sz)
+            .filter(|access| access.offset.is_some())
+    }
 }
 
-/// Runs analysis on the project to generate a [PointsToMapping]
+/// Runs analysis on the project to generate a [PointsToMapping]. Access offsets are folded by the given clustering.
 pub fn run_analysis<'a>(
     analysis_results: &'a AnalysisResults<'a>,
     config: pointer_inference::Config,
+    clustering: OffsetClustering,
 ) -> Result<HashMap<NodeIndex, PointsToContext>> {
     let pointer_res = pointer_inference::run(analysis_results, config, false, false);
 
@@ -257,7 +361,11 @@ pub fn run_analysis<'a>(
         .map(|(idx, ps)| {
             (
                 idx,
-                PointsToContext::new(ps, analysis_results.project.stack_pointer_register.clone()),
+                PointsToContext::new(
+                    ps,
+                    analysis_results.project.stack_pointer_register.clone(),
+                    clustering,
+                ),
             )
         })
         .collect())
@@ -283,7 +391,7 @@ mod test {
         node_context::points_to::PointsToContext,
     };
 
-    use super::{run_analysis, DEFAULT_PTR_CONFIG};
+    use super::{run_analysis, OffsetClustering, DEFAULT_PTR_CONFIG};
 
     fn test_data_dir<P: AsRef<Path>>(pth: P) -> String {
         let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -372,8 +480,12 @@ mod test {
 
         let analysis_results = analysis_results.with_function_signatures(Some(&res));
 
-        let pts_to_ctx = run_analysis(&analysis_results, DEFAULT_PTR_CONFIG.clone())
-            .expect("analysis should succeed");
+        let pts_to_ctx = run_analysis(
+            &analysis_results,
+            DEFAULT_PTR_CONFIG.clone(),
+            OffsetClustering::default(),
+        )
+        .expect("analysis should succeed");
         let (ndidx, nd_body) =
             find_ndidx_for_block("001015bf", &cfg).expect("should have allocating block");
         let ctx = pts_to_ctx
@@ -470,4 +582,32 @@ mod test {
             0
         );
     }
+
+    #[test]
+    fn clusters_offsets_beyond_threshold() {
+        let collapse = OffsetClustering {
+            max_offset: Some(64),
+            stride: None,
+        };
+        assert_eq!(collapse.cluster(8), 8);
+        assert_eq!(collapse.cluster(1024), 64);
+        assert_eq!(collapse.cluster(-1024), -64);
+        assert_eq!(collapse.cluster(i64::MIN), -64);
+
+        let fold = OffsetClustering {
+            max_offset: Some(64),
+            stride: Some(16),
+        };
+        assert_eq!(fold.cluster(60), 60);
+        assert_eq!(fold.cluster(64 + 16 * 40 + 4), 68);
+        assert_eq!(fold.cluster(i64::MIN), -64);
+        assert_eq!(fold.cluster(i64::MIN + 1), -(64 + 15));
+
+        let stride_only = OffsetClustering {
+            max_offset: None,
+            stride: Some(12),
+        };
+        assert_eq!(stride_only.cluster(12 * 7 + 8), 8);
+        assert_eq!(OffsetClustering::default().cluster(4096), 4096);
+    }
 }
@@ -1,15 +1,19 @@
 use std::collections::BTreeSet;
 
 use cwe_checker_lib::intermediate_representation::Arg;
-use cwe_checker_lib::intermediate_representation::{Expression, Variable};
+use cwe_checker_lib::intermediate_representation::Variable;
 
-use crate::constraint_generation::{ArgTvar, NodeContextMapping, SubprocedureLocators};
+use crate::constraint_generation::{
+    argument_registers, ArgTvar, NodeContextMapping, SubprocedureLocators,
+};
 
 #[derive(Clone)]
 /// The context for a node needed to evaluate an argument specification.
 pub struct ProcedureContext {
     /// The procedure context doesnt change. It only needs to know about the stack variable for this project.
     pub stack_pointer: Variable,
+    /// The architecture of the project, which decides the registers holding a register argument.
+    pub cpu_architecture: String,
 }
 
 impl NodeContextMapping for ProcedureContext {
@@ -44,14 +48,11 @@ impl SubprocedureLocators for ProcedureContext {
         _vm: &mut crate::constraints::VariableManager,
     ) -> BTreeSet<ArgTvar> {
         match arg {
-            Arg::Register { expr, .. } => {
-                if let Expression::Var(var) = expr {
-                    let var_set = reg.access(var);
-                    var_set.into_iter().map(ArgTvar::VariableTvar).collect()
-                } else {
-                    BTreeSet::new()
-                }
-            }
+            Arg::Register { expr, .. } => argument_registers(expr, &self.cpu_architecture)
+                .into_iter()
+                .flat_map(|var| reg.access(var))
+                .map(ArgTvar::VariableTvar)
+                .collect(),
             Arg::Stack { address, size, .. } => {
                 // Reason this is ok in the case of actuals: at an actual call site the stack pointer is going to be below the newly pushed EIP which is the 0 offset of the new frame.
                 // Therefore the offset is safe for our current frame.
@@ -1,3 +1,4 @@
+use super::passes::{PassContext, PassPipeline};
 use crate::constraint_generation;
 use crate::constraints::{
     parse_field_label, parse_fields, parse_type_variable, parse_whitespace_delim, ConstraintSet,
@@ -447,6 +448,17 @@ impl Display for FiniteState {
 }
 
 impl FiniteState {
+    /// Gets the type variable of the state, None for the start and end states.
+    pub fn get_base_variable(&self) -> Option<&TypeVariable> {
+        match &self {
+            Self::Start | Self::End => None,
+            Self::Tv(tv) => Some(match &tv.base_var.dt_var {
+                VHat::Interesting(iv) => &iv.tv,
+                VHat::Uninteresting(uv) => uv,
+            }),
+        }
+    }
+
     /// A finite state can be negated which finds the opposite node in the graph (LHS becomes RHS and the variance flips).
     pub fn not(&self) -> FiniteState {
         match &self {
@@ -508,6 +520,7 @@ pub struct FSA {
     grph: StableDiGraph<FiniteState, FSAEdge>,
     mp: BTreeMap<FiniteState, NodeIndex>,
     cant_pop_nodes: BTreeMap<FiniteState, NodeIndex>,
+    trace: Option<RuleTrace>,
 }
 
 impl Display for FSA {
@@ -524,6 +537,125 @@ pub struct EdgeDefinition {
     edge_weight: FSAEdge,
 }
 
+impl Display for EdgeDefinition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} -{}-> {}", self.src, self.edge_weight, self.dst)
+    }
+}
+
+/// A rule of the pushdown system or of saturation that adds a transition to an [FSA], see [RuleTrace].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TraceRule {
+    /// A subtyping constraint becomes a transition between the states of its sides
+    Constraint,
+    /// A state with an access path pops and pushes its labels to and from the states of its prefixes
+    Label,
+    /// An interesting variable is entered from the start or exits to the end
+    StartEnd,
+    /// A pop of a symbol reached by a push of the same symbol becomes a transition from the pushing state
+    Saturation,
+    /// A store reaching a contravariant state reaches the covariant state as a load
+    StoreToLoad,
+    /// A load reaching a contravariant state reaches the covariant state as a store
+    LoadToStore,
+}
+
+impl Display for TraceRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match &self {
+            Self::Constraint => "constraint",
+            Self::Label => "label",
+            Self::StartEnd => "start_end",
+            Self::Saturation => "saturation",
+            Self::StoreToLoad => "store_to_load",
+            Self::LoadToStore => "load_to_store",
+        })
+    }
+}
+
+/// One application of a [TraceRule] while building or saturating an [FSA].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleApplication {
+    /// The rule applied
+    pub rule: TraceRule,
+    /// The premises the rule was applied to, ie. a constraint or an edge
+    pub inputs: Vec<String>,
+    /// The states the premises are about
+    pub states: Vec<FiniteState>,
+    /// The edge the rule added, None for the pointer rules which only propagate reaching pushes
+    pub produced: Option<EdgeDefinition>,
+}
+
+impl RuleApplication {
+    /// Checks if the rule read or produced a state of the type variable.
+    pub fn mentions(&self, tv: &TypeVariable) -> bool {
+        self.states
+            .iter()
+            .chain(
+                self.produced
+                    .iter()
+                    .flat_map(|edge| vec![&edge.src, &edge.dst]),
+            )
+            .any(|st| st.get_base_variable() == Some(tv))
+    }
+}
+
+impl Display for RuleApplication {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.rule, self.inputs.join("; "))?;
+        if let Some(edge) = &self.produced {
+            write!(f, " => {}", edge)?;
+        }
+        Ok(())
+    }
+}
+
+/// The rule applications recorded while building and saturating a traced [FSA], in the order they were applied. Rules
+/// that only rederive an edge or a reaching push are recorded once.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleTrace {
+    applications: Vec<RuleApplication>,
+}
+
+impl RuleTrace {
+    fn record(
+        &mut self,
+        rule: TraceRule,
+        inputs: Vec<String>,
+        states: Vec<FiniteState>,
+        produced: Option<EdgeDefinition>,
+    ) {
+        self.applications.push(RuleApplication {
+            rule,
+            inputs,
+            states,
+            produced,
+        });
+    }
+
+    /// Gets every recorded application.
+    pub fn get_applications(&self) -> &[RuleApplication] {
+        &self.applications
+    }
+
+    /// Gets the applications that read or produced a state of the type variable.
+    pub fn for_variable<'a>(
+        &'a self,
+        tv: &'a TypeVariable,
+    ) -> impl Iterator<Item = &'a RuleApplication> + 'a {
+        self.applications.iter().filter(move |app| app.mentions(tv))
+    }
+}
+
+impl Display for RuleTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for app in self.applications.iter() {
+            writeln!(f, "{}", app)?;
+        }
+        Ok(())
+    }
+}
+
 impl EdgeDefinition {
     /// Flips the direction of the edge and the weight.
     pub fn flip_edge(&self) -> EdgeDefinition {
@@ -814,6 +946,10 @@ impl FSA {
 
     /// Gets edge definitions for all edges that should be inserted by saturation.
     pub fn get_saturation_edges(&self) -> BTreeSet<EdgeDefinition> {
+        self.saturation_edges(None)
+    }
+
+    fn saturation_edges(&self, mut trace: Option<&mut RuleTrace>) -> BTreeSet<EdgeDefinition> {
         let mut new_edges = BTreeSet::new();
         let mut reaching_pushes: BTreeMap<
             FiniteState,
@@ -878,7 +1014,16 @@ impl FSA {
                             dst: edg.dst.clone(),
                             edge_weight: FSAEdge::Success,
                         };
-                        new_edges.insert(new_edge.clone());
+                        if new_edges.insert(new_edge.clone()) && new_edge.src != new_edge.dst {
+                            if let Some(trace) = trace.as_deref_mut() {
+                                trace.record(
+                                    TraceRule::Saturation,
+                                    vec![format!("{} pushes {}", definer, l), edg.to_string()],
+                                    vec![definer.clone(), edg.src.clone()],
+                                    Some(new_edge.clone()),
+                                );
+                            }
+                        }
                         additional_edges.insert(new_edge);
                     }
                 }
@@ -909,10 +1054,18 @@ impl FSA {
                 {
                     let equiv_ptr = v_contra.not();
                     let def_map = reaching_pushes.get_mut(&equiv_ptr).unwrap();
-                    def_map
+                    let inserted = def_map
                         .entry(StackSymbol::Label(FieldLabel::Load))
                         .or_insert_with(BTreeSet::new)
-                        .insert(definer);
+                        .insert(definer.clone());
+                    if let (true, Some(trace)) = (inserted, trace.as_deref_mut()) {
+                        trace.record(
+                            TraceRule::StoreToLoad,
+                            vec![format!("{} pushes store reaching {}", definer, v_contra)],
+                            vec![definer, v_contra.clone(), equiv_ptr],
+                            None,
+                        );
+                    }
                 }
 
                 for definer in reaching_pushes
@@ -926,10 +1079,18 @@ impl FSA {
                 {
                     let equiv_ptr = v_contra.not();
                     let def_map = reaching_pushes.get_mut(&equiv_ptr).unwrap();
-                    def_map
+                    let inserted = def_map
                         .entry(StackSymbol::Label(FieldLabel::Store))
                         .or_insert_with(BTreeSet::new)
-                        .insert(definer);
+                        .insert(definer.clone());
+                    if let (true, Some(trace)) = (inserted, trace.as_deref_mut()) {
+                        trace.record(
+                            TraceRule::LoadToStore,
+                            vec![format!("{} pushes load reaching {}", definer, v_contra)],
+                            vec![definer, v_contra.clone(), equiv_ptr],
+                            None,
+                        );
+                    }
                 }
             }
 
@@ -1017,32 +1178,132 @@ impl FSA {
     /// This process involves saturating the graph with edges for pointer rules, as well as removing unproductive transitions.
     /// The process then removes the language where pops occur after pushes.
     /// Looping type variables are removed and represented with a fresh interesting type variable.
-    /// Finally, unreachable nodes that can neither be reached from the start or end are removed.
+    /// Finally, unreachable nodes that can neither be reached from the start or end are removed and equivalent states are collapsed.
     pub fn simplify_graph(
         &mut self,
         sub_name: &str,
         debug_dir: &mut FileDebugLogger,
         vman: &mut VariableManager,
     ) -> anyhow::Result<()> {
-        self.saturate();
-        self.intersect_with_pop_push();
-        self.remove_unreachable();
-        debug_dir.log_to_fname(
-            &format!("{}_simplified_proof_with_cycles", sub_name),
-            &|| &self,
-        )?;
-        self.generate_recursive_type_variables(vman);
-        self.remove_unreachable();
-        Ok(())
+        self.simplify_graph_with(&PassPipeline::default(), sub_name, debug_dir, vman)
+    }
+
+    /// Simplifies this FSA by running the passes of a [PassPipeline] in order rather than the default simplification.
+    pub fn simplify_graph_with(
+        &mut self,
+        passes: &PassPipeline,
+        sub_name: &str,
+        debug_dir: &mut FileDebugLogger,
+        vman: &mut VariableManager,
+    ) -> anyhow::Result<()> {
+        passes.run(
+            self,
+            &mut PassContext {
+                sub_name,
+                debug_dir,
+                vman,
+            },
+        )
+    }
+
+    /// Collapses states that are bisimilar, ie. states with transitions on the same edges to equivalent states.
+    /// Walking constraints only depends on the edges along each path, so the collapsed FSA generates the same constraints from fewer paths.
+    /// States are only collapsed in acyclic FSAs: in an acyclic FSA a state never reaches an equivalent state, so simple paths
+    /// are preserved, which does not hold once there are cycles.
+    pub fn minimize(&mut self) {
+        if petgraph::algo::is_cyclic_directed(&self.grph) {
+            return;
+        }
+
+        let nodes = self.grph.node_indices().collect::<Vec<_>>();
+        let start = self.mp.get(&FiniteState::Start).cloned();
+        let end = self.cant_pop_nodes.get(&FiniteState::End).cloned();
+
+        // start and end are distinguished from every other state, all other states begin in the same block
+        let mut blocks: BTreeMap<NodeIndex, usize> = nodes
+            .iter()
+            .map(|idx| {
+                let blk = if Some(*idx) == start {
+                    0
+                } else if Some(*idx) == end {
+                    1
+                } else {
+                    2
+                };
+                (*idx, blk)
+            })
+            .collect();
+
+        let mut num_blocks = blocks.values().collect::<BTreeSet<_>>().len();
+        loop {
+            let mut signatures: BTreeMap<(usize, BTreeSet<(FSAEdge, usize)>), usize> =
+                BTreeMap::new();
+            let mut refined = BTreeMap::new();
+            for idx in nodes.iter() {
+                let sig = (
+                    blocks[idx],
+                    self.grph
+                        .edges_directed(*idx, petgraph::EdgeDirection::Outgoing)
+                        .map(|e| (e.weight().clone(), blocks[&e.target()]))
+                        .collect::<BTreeSet<_>>(),
+                );
+                let next_blk = signatures.len();
+                refined.insert(*idx, *signatures.entry(sig).or_insert(next_blk));
+            }
+
+            blocks = refined;
+            if signatures.len() == num_blocks {
+                break;
+            }
+            num_blocks = signatures.len();
+        }
+
+        let mut reprs: BTreeMap<usize, NodeIndex> = BTreeMap::new();
+        for idx in nodes.iter() {
+            reprs.entry(blocks[idx]).or_insert(*idx);
+        }
+        if reprs.len() == nodes.len() {
+            return;
+        }
+        let repr = |idx: &NodeIndex| reprs[&blocks[idx]];
+
+        let edges = self
+            .grph
+            .edge_references()
+            .filter(|e| repr(&e.source()) == e.source())
+            .map(|e| (e.source(), e.weight().clone(), repr(&e.target())))
+            .collect::<BTreeSet<_>>();
+
+        self.grph.clear_edges();
+        for idx in nodes.iter() {
+            if repr(idx) != *idx {
+                self.grph.remove_node(*idx);
+            }
+        }
+        for (src, weight, dst) in edges {
+            self.grph.add_edge(src, dst, weight);
+        }
+
+        for idx in self.mp.values_mut().chain(self.cant_pop_nodes.values_mut()) {
+            if let Some(blk) = blocks.get(idx) {
+                *idx = reprs[blk];
+            }
+        }
     }
 
     /// Removes unproductive transitions in the FSA. ie. transitions of the form pop y push x where x/=y
     /// Productive transitions are transitions in the FSA in the state machine is a series of transitions in the semiring stack domain where
     /// x * y ... * z != 0
     pub fn saturate(&mut self) {
-        self.get_saturation_edges()
-            .into_iter()
-            .for_each(|x| self.insert_edge(x));
+        let mut trace = self.trace.take();
+        let edges = self.saturation_edges(trace.as_mut());
+        self.trace = trace;
+        edges.into_iter().for_each(|x| self.insert_edge(x));
+    }
+
+    /// Gets the rules applied to build and saturate this FSA if it was created with [FSA::new_traced].
+    pub fn get_rule_trace(&self) -> Option<&RuleTrace> {
+        self.trace.as_ref()
     }
 
     fn add_cant_push_node(&mut self, old_node: &FiniteState) -> NodeIndex {
@@ -1195,6 +1456,72 @@ impl FSA {
         ConstraintSet::from(cons_set)
     }
 
+    /// Checks whether the constraints of this FSA entail a constraint between two interesting variables, ie. whether some path from
+    /// the start to the end pops the left hand side and pushes the right hand side. The FSA must be saturated and intersected with pop push.
+    /// Unlike [FSA::walk_constraints] the search follows cycles, so it also holds after recursive type variables are generated.
+    pub fn entails(&self, cons: &SubtypeConstraint) -> bool {
+        let lhs = constraint_generation::simplify_path(&cons.lhs);
+        let rhs = constraint_generation::simplify_path(&cons.rhs);
+        let pops = lhs.get_field_labels();
+        // pushes occur in reverse order
+        let pushes = rhs.get_field_labels().iter().rev().collect::<Vec<_>>();
+        let end = self.get_end();
+
+        let mut worklist = self
+            .grph
+            .edges_directed(self.get_start(), petgraph::EdgeDirection::Outgoing)
+            .filter(|e| match e.weight() {
+                FSAEdge::Pop(StackSymbol::InterestingVar(iv, var)) => {
+                    &iv.tv == lhs.get_base_variable()
+                        && lhs.path_variance().operate(var) == Variance::Covariant
+                }
+                _ => false,
+            })
+            .map(|e| (e.target(), 0, 0))
+            .collect::<Vec<_>>();
+        let mut seen = HashSet::new();
+        while let Some((nd, popped, pushed)) = worklist.pop() {
+            if !seen.insert((nd, popped, pushed)) {
+                continue;
+            }
+
+            for e in self
+                .grph
+                .edges_directed(nd, petgraph::EdgeDirection::Outgoing)
+            {
+                let next = match e.weight() {
+                    FSAEdge::Success => Some((popped, pushed)),
+                    FSAEdge::Pop(StackSymbol::Label(fl))
+                        if pushed == 0 && pops.get(popped) == Some(fl) =>
+                    {
+                        Some((popped + 1, pushed))
+                    }
+                    FSAEdge::Push(StackSymbol::Label(fl))
+                        if popped == pops.len() && pushes.get(pushed) == Some(&fl) =>
+                    {
+                        Some((popped, pushed + 1))
+                    }
+                    FSAEdge::Push(StackSymbol::InterestingVar(iv, var))
+                        if e.target() == end
+                            && popped == pops.len()
+                            && pushed == pushes.len()
+                            && &iv.tv == rhs.get_base_variable()
+                            && rhs.path_variance().operate(var) == Variance::Covariant =>
+                    {
+                        return true;
+                    }
+                    _ => None,
+                };
+
+                if let Some((popped, pushed)) = next {
+                    worklist.push((e.target(), popped, pushed));
+                }
+            }
+        }
+
+        false
+    }
+
     fn generate_push_pop_edges(tv: TypeVarNode) -> Vec<EdgeDefinition> {
         let base_var = tv.base_var.dt_var;
         let mut curr_stack_variance = tv.base_var.variance;
@@ -1282,8 +1609,34 @@ impl FSA {
         None
     }
 
+    fn trace_derived_edges(
+        trace: &mut RuleTrace,
+        rule: TraceRule,
+        from: &[EdgeDefinition],
+        seen: &mut BTreeSet<EdgeDefinition>,
+        derive: impl Fn(&FiniteState) -> Vec<EdgeDefinition>,
+    ) {
+        for st in from.iter().flat_map(|e| vec![&e.src, &e.dst]) {
+            for edge in derive(st) {
+                if seen.insert(edge.clone()) {
+                    trace.record(rule, vec![st.to_string()], vec![st.clone()], Some(edge));
+                }
+            }
+        }
+    }
+
     /// Create a non-saturated FSA for the constraint set and RuleContext
     pub fn new(cons: &ConstraintSet, context: &RuleContext) -> Result<FSA> {
+        Self::build(cons, context, false)
+    }
+
+    /// Creates a non-saturated FSA like [FSA::new] that records every rule applied while building and saturating it,
+    /// see [FSA::get_rule_trace].
+    pub fn new_traced(cons: &ConstraintSet, context: &RuleContext) -> Result<FSA> {
+        Self::build(cons, context, true)
+    }
+
+    fn build(cons: &ConstraintSet, context: &RuleContext, traced: bool) -> Result<FSA> {
         let subs: Vec<&SubtypeConstraint> = cons
             .iter()
             .filter_map(|x| {
@@ -1299,6 +1652,36 @@ impl FSA {
 
         let mut total_edges = Self::get_direct_rule_edges(&constraint_rules)?;
 
+        let mut trace = if traced {
+            Some(RuleTrace::default())
+        } else {
+            None
+        };
+        let mut traced_edges = BTreeSet::new();
+        if let Some(trace) = trace.as_mut() {
+            // each constraint generates a covariant and a contravariant rule, in order
+            for (sub, edge) in subs
+                .iter()
+                .flat_map(|sub| vec![sub, sub])
+                .zip(total_edges.iter())
+            {
+                traced_edges.insert(edge.clone());
+                trace.record(
+                    TraceRule::Constraint,
+                    vec![sub.to_string()],
+                    vec![],
+                    Some(edge.clone()),
+                );
+            }
+            Self::trace_derived_edges(
+                trace,
+                TraceRule::Label,
+                &total_edges,
+                &mut traced_edges,
+                Self::generate_push_pop_edges_for_state,
+            );
+        }
+
         let indirect_edges = total_edges
             .iter()
             .flat_map(|e| {
@@ -1310,6 +1693,20 @@ impl FSA {
 
         total_edges.extend(indirect_edges);
 
+        if let Some(trace) = trace.as_mut() {
+            Self::trace_derived_edges(
+                trace,
+                TraceRule::StartEnd,
+                &total_edges,
+                &mut traced_edges,
+                |st| {
+                    Self::generate_start_and_stop_edges_for_state(st)
+                        .into_iter()
+                        .collect()
+                },
+            );
+        }
+
         let start_stop_edges = total_edges
             .iter()
             .flat_map(|e| {
@@ -1325,6 +1722,7 @@ impl FSA {
             grph: StableDiGraph::new(),
             mp: BTreeMap::new(),
             cant_pop_nodes: BTreeMap::new(),
+            trace,
         };
 
         let mut edges = BTreeSet::new();
@@ -1359,7 +1757,7 @@ mod tests {
         },
         solver::constraint_graph::{
             Direction, EdgeDefinition, FSAEdge, FiniteState, InterestingVar, PushDownState,
-            RuleContext, TypeVarNode, FSA,
+            RuleContext, TraceRule, TypeVarNode, FSA,
         },
     };
 
@@ -1849,6 +2247,45 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn traces_rule_applications() {
+        let (constraints, context) = get_constraint_set();
+
+        assert!(FSA::new(&constraints, &context)
+            .unwrap()
+            .get_rule_trace()
+            .is_none());
+
+        let mut fsa = FSA::new_traced(&constraints, &context).unwrap();
+        let expected_saturation = fsa.get_saturation_edges();
+        fsa.saturate();
+        let trace = fsa.get_rule_trace().unwrap();
+
+        let applied = |rule: TraceRule| {
+            trace
+                .get_applications()
+                .iter()
+                .filter(move |app| app.rule == rule)
+        };
+        assert_eq!(
+            applied(TraceRule::Constraint).count(),
+            2 * get_subtys(&constraints).len()
+        );
+        assert_eq!(
+            applied(TraceRule::Saturation)
+                .filter_map(|app| app.produced.clone())
+                .collect::<BTreeSet<_>>(),
+            expected_saturation
+        );
+        assert!(
+            applied(TraceRule::StoreToLoad).count() + applied(TraceRule::LoadToStore).count() > 0
+        );
+
+        let x = TypeVariable::new("x".to_owned());
+        assert!(trace.for_variable(&x).count() > 0);
+        assert!(trace.for_variable(&x).all(|app| app.mentions(&x)));
+    }
+
     #[test]
     fn indirect_constraint_edges() {
         /*
@@ -2077,6 +2514,7 @@ mod tests {
         assert_eq!(actual_edges, expected_edges)
     }
     use crate::constraints::{self, VariableManager};
+    use crate::solver::passes::PassPipeline;
     #[test]
     fn constraints_simple_pointer_passing() {
         /*
@@ -2226,6 +2664,49 @@ mod tests {
             .expect("simplification should not fail");
     }
 
+    #[test]
+    fn minimization_preserves_constraints() {
+        let (_, cs_set) = constraints::parse_constraint_set(
+            "
+            a <= x
+            a <= y
+            x.load.σ32@0 <= b
+            y.load.σ32@0 <= b
+            x.store <= b
+            ",
+        )
+        .unwrap();
+
+        let rc = RuleContext::new(BTreeSet::from_iter(
+            vec![
+                TypeVariable::new("a".to_owned()),
+                TypeVariable::new("b".to_owned()),
+            ]
+            .into_iter(),
+        ));
+
+        let mut fsa_res = FSA::new(&cs_set, &rc).unwrap();
+        let mut vman = VariableManager::new();
+        fsa_res
+            .simplify_graph_with(
+                &PassPipeline::parse(
+                    "saturate,intersect_pop_push,remove_unreachable,recursive_type_variables,remove_unreachable",
+                )
+                .unwrap(),
+                "",
+                &mut FileDebugLogger::default(),
+                &mut vman,
+            )
+            .expect("simplification should not fail");
+
+        let unminimized_cons = fsa_res.walk_constraints();
+        let unminimized_nodes = fsa_res.get_graph().node_count();
+
+        fsa_res.minimize();
+        assert_eq!(fsa_res.walk_constraints(), unminimized_cons);
+        assert!(fsa_res.get_graph().node_count() <= unminimized_nodes);
+    }
+
     #[test]
     fn func_store_variance_transitivity_regression() {
         let (remaining, cs_set) = constraints::parse_constraint_set(
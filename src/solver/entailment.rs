@@ -0,0 +1,141 @@
+use cwe_checker_lib::intermediate_representation::Tid;
+use serde::{Deserialize, Serialize};
+
+use crate::constraints::{ConstraintSet, SubtypeConstraint, TyConstraint};
+
+use super::constraint_graph::{RuleContext, FSA};
+
+/// An original constraint of an scc that its simplified constraints do not entail, which means simplification lost
+/// or changed a fact about the interesting variables of the scc.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntailmentFailure {
+    /// The subprocedures of the scc
+    pub scc: Vec<Tid>,
+    /// The original constraint that is not entailed
+    pub constraint: SubtypeConstraint,
+}
+
+/// Selects up to sample_size subtype constraints that only relate interesting variables, evenly spaced over the
+/// ordered constraint set so the same constraints are checked on every run.
+pub fn sample_constraints(
+    cs: &ConstraintSet,
+    context: &RuleContext,
+    sample_size: usize,
+) -> Vec<SubtypeConstraint> {
+    let candidates = cs
+        .iter()
+        .filter_map(|cons| match cons {
+            TyConstraint::SubTy(sub)
+                if context
+                    .get_interesting()
+                    .contains(sub.lhs.get_base_variable())
+                    && context
+                        .get_interesting()
+                        .contains(sub.rhs.get_base_variable()) =>
+            {
+                Some(sub)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    if candidates.len() <= sample_size {
+        return candidates.into_iter().cloned().collect();
+    }
+
+    (0..sample_size)
+        .map(|i| candidates[i * candidates.len() / sample_size].clone())
+        .collect()
+}
+
+/// Checks that the simplified constraints of an scc entail a sample of its original constraints over interesting variables,
+/// by saturating the constraint graph of the simplified constraints and searching it for each sampled constraint.
+/// Returns the sampled constraints that are not entailed.
+pub fn unentailed_constraints(
+    original: &ConstraintSet,
+    simplified: &ConstraintSet,
+    context: &RuleContext,
+    sample_size: usize,
+) -> anyhow::Result<Vec<SubtypeConstraint>> {
+    let sampled = sample_constraints(original, context, sample_size);
+    if sampled.is_empty() {
+        return Ok(sampled);
+    }
+
+    let mut fsa = FSA::new(simplified, context)?;
+    fsa.saturate();
+    fsa.intersect_with_pop_push();
+
+    Ok(sampled
+        .into_iter()
+        .filter(|cons| !fsa.entails(cons))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use crate::{
+        constraints::{parse_constraint_set, parse_subtype_cons, TyConstraint, TypeVariable},
+        solver::constraint_graph::RuleContext,
+    };
+
+    use super::{sample_constraints, unentailed_constraints};
+
+    fn context() -> RuleContext {
+        RuleContext::new(
+            vec![
+                TypeVariable::new("sub_1".to_owned()),
+                TypeVariable::new("int".to_owned()),
+            ]
+            .into_iter()
+            .collect::<BTreeSet<_>>(),
+        )
+    }
+
+    #[test]
+    fn detects_constraints_lost_by_simplification() {
+        let (_, original) = parse_constraint_set(
+            "
+            sub_1.in_0.load.σ32@0 <= int
+            sub_1.in_0 <= t1
+            t1 <= sub_1.out
+            ",
+        )
+        .unwrap();
+        assert_eq!(sample_constraints(&original, &context(), 8).len(), 1);
+
+        let (_, sound) = parse_constraint_set(
+            "
+            sub_1.in_0 <= t2
+            t2.load.σ32@0 <= int
+            sub_1.in_0 <= sub_1.out
+            ",
+        )
+        .unwrap();
+        assert!(unentailed_constraints(&original, &sound, &context(), 8)
+            .unwrap()
+            .is_empty());
+
+        let (_, unsound) = parse_constraint_set(
+            "
+            sub_1.in_0.load.σ32@4 <= int
+            sub_1.in_0 <= sub_1.out
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            unentailed_constraints(&original, &unsound, &context(), 8)
+                .unwrap()
+                .into_iter()
+                .map(TyConstraint::SubTy)
+                .collect::<Vec<_>>(),
+            vec![
+                parse_subtype_cons("sub_1.in_0.load.σ32@0 <= int")
+                    .unwrap()
+                    .1
+            ]
+        );
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use cwe_checker_lib::intermediate_representation::Tid;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constraints::{
+        AddConstraint, ConstraintSet, DerivedTypeVar, Field, FieldLabel, SubtypeConstraint,
+        TyConstraint, TypeVariable,
+    },
+    node_context::points_to::OffsetClustering,
+};
+
+use super::constraint_graph::RuleContext;
+
+/// The approximate size of a node of a constraint graph, including its entry in the node map.
+const GRAPH_NODE_BYTES: usize = 160;
+/// The approximate size of an edge of a constraint graph.
+const GRAPH_EDGE_BYTES: usize = 64;
+/// How much larger a constraint graph grows while it is saturated than when it is built.
+const SATURATION_FACTOR: usize = 4;
+/// The approximate size of a constraint that is kept after simplification, excluding its labels.
+const CONSTRAINT_BYTES: usize = 192;
+/// The approximate size of a label of a derived type variable.
+const LABEL_BYTES: usize = 40;
+
+/// Field offsets are folded onto the first word past this offset once offsets are widened.
+const WIDENED_MAX_OFFSET: i64 = 64;
+/// The number of lower and upper bounds kept for a derived type variable once bounds are capped.
+const MAX_BOUNDS: usize = 8;
+
+/// A way of shrinking the constraints of an scc, from the least to the most precision lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Degradation {
+    /// Field offsets past a small threshold are folded onto a single word, so large buffers get few fields
+    WidenOffsets,
+    /// Each derived type variable keeps at most a few lower and upper bounds, which bounds the size of the unions lowered from them
+    CapBounds,
+    /// Constraints that relate no interesting, global, or callsite variable are dropped
+    DropUninteresting,
+}
+
+impl Degradation {
+    /// Every degradation, in the order they are applied.
+    pub const ALL: [Degradation; 3] = [
+        Degradation::WidenOffsets,
+        Degradation::CapBounds,
+        Degradation::DropUninteresting,
+    ];
+
+    fn apply(&self, cs: &ConstraintSet, context: &RuleContext) -> ConstraintSet {
+        match self {
+            Degradation::WidenOffsets => widen_offsets(cs),
+            Degradation::CapBounds => cap_bounds(cs),
+            Degradation::DropUninteresting => drop_uninteresting(cs, context),
+        }
+    }
+}
+
+/// Records that the constraints of an scc were degraded to stay within the memory budget.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DegradationEvent {
+    /// The subprocedures of the scc
+    pub scc: Vec<Tid>,
+    /// The degradation applied
+    pub degradation: Degradation,
+    /// The estimated memory use in bytes when the degradation was applied
+    pub estimated_bytes: usize,
+    /// The number of constraints before the degradation
+    pub constraints_before: usize,
+    /// The number of constraints after the degradation
+    pub constraints_after: usize,
+}
+
+/// Tracks the approximate memory used while simplifying the constraints of each scc. When simplifying an scc would
+/// exceed the budget its constraints are degraded, trading precision for memory instead of running out of it.
+/// Degradations are cumulative and sticky: once an scc needed a degradation, every later scc is degraded at least as much.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryBudget {
+    limit_bytes: usize,
+    retained_bytes: usize,
+    applied: usize,
+    events: Vec<DegradationEvent>,
+}
+
+impl MemoryBudget {
+    /// Creates a budget of limit_bytes.
+    pub fn new(limit_bytes: usize) -> MemoryBudget {
+        MemoryBudget {
+            limit_bytes,
+            retained_bytes: 0,
+            applied: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Gets the budget in bytes.
+    pub fn get_limit(&self) -> usize {
+        self.limit_bytes
+    }
+
+    /// Gets the estimated bytes held by the results simplified so far.
+    pub fn get_retained(&self) -> usize {
+        self.retained_bytes
+    }
+
+    /// Gets the degradations applied so far, in order.
+    pub fn get_events(&self) -> &[DegradationEvent] {
+        &self.events
+    }
+
+    /// Estimates the bytes of the saturated constraint graph of a constraint set. Each label of a constraint adds a node and
+    /// an edge, and the graph holds a flipped copy of every constraint.
+    pub fn estimate_graph_bytes(cs: &ConstraintSet) -> usize {
+        cs.iter()
+            .map(|cons| {
+                let labels = constraint_labels(cons);
+                2 * ((labels + 2) * GRAPH_NODE_BYTES + (labels + 1) * GRAPH_EDGE_BYTES)
+            })
+            .sum::<usize>()
+            * SATURATION_FACTOR
+    }
+
+    /// Estimates the bytes of a graph with the given number of nodes and edges, ie. a sketch graph.
+    pub fn estimate_graph_size(nodes: usize, edges: usize) -> usize {
+        nodes * GRAPH_NODE_BYTES + edges * GRAPH_EDGE_BYTES
+    }
+
+    /// Estimates the bytes held by simplified constraints.
+    pub fn estimate_retained_bytes<'a>(
+        cons: impl IntoIterator<Item = &'a SubtypeConstraint>,
+    ) -> usize {
+        cons.into_iter()
+            .map(|cons| {
+                CONSTRAINT_BYTES
+                    + (cons.lhs.get_field_labels().len() + cons.rhs.get_field_labels().len())
+                        * LABEL_BYTES
+            })
+            .sum()
+    }
+
+    /// Degrades the constraints of an scc until simplifying them is estimated to fit in the budget, or every degradation
+    /// is applied. The degradations applied to earlier sccs are applied first without being recorded again.
+    pub fn fit(&mut self, scc: &[Tid], cs: ConstraintSet, context: &RuleContext) -> ConstraintSet {
+        let mut curr = Degradation::ALL[..self.applied]
+            .iter()
+            .fold(cs, |curr, degradation| degradation.apply(&curr, context));
+
+        loop {
+            let estimated_bytes = self.retained_bytes + Self::estimate_graph_bytes(&curr);
+            if estimated_bytes <= self.limit_bytes || self.applied >= Degradation::ALL.len() {
+                return curr;
+            }
+
+            let degradation = Degradation::ALL[self.applied];
+            let degraded = degradation.apply(&curr, context);
+            self.events.push(DegradationEvent {
+                scc: scc.to_vec(),
+                degradation,
+                estimated_bytes,
+                constraints_before: curr.len(),
+                constraints_after: degraded.len(),
+            });
+            self.applied += 1;
+            curr = degraded;
+        }
+    }
+
+    /// Accounts for simplified constraints that are kept until the end of the solve.
+    pub fn retain<'a>(&mut self, cons: impl IntoIterator<Item = &'a SubtypeConstraint>) {
+        self.retained_bytes += Self::estimate_retained_bytes(cons);
+    }
+}
+
+fn constraint_labels(cons: &TyConstraint) -> usize {
+    match cons {
+        TyConstraint::SubTy(sub) => {
+            sub.lhs.get_field_labels().len() + sub.rhs.get_field_labels().len()
+        }
+        TyConstraint::AddCons(add_cons) => {
+            add_cons.lhs_ty.get_field_labels().len()
+                + add_cons.rhs_ty.get_field_labels().len()
+                + add_cons.repr_ty.get_field_labels().len()
+        }
+    }
+}
+
+fn widen_dtv(dtv: &DerivedTypeVar, clustering: &OffsetClustering) -> DerivedTypeVar {
+    DerivedTypeVar::create_with_path(
+        dtv.get_base_variable().clone(),
+        dtv.get_field_labels()
+            .iter()
+            .map(|lab| match lab {
+                FieldLabel::Field(fld) => {
+                    FieldLabel::Field(Field::new(clustering.cluster(fld.offset), fld.size))
+                }
+                _ => lab.clone(),
+            })
+            .collect(),
+    )
+}
+
+fn widen_offsets(cs: &ConstraintSet) -> ConstraintSet {
+    let clustering = OffsetClustering {
+        max_offset: Some(WIDENED_MAX_OFFSET),
+        stride: Some(8),
+    };
+    ConstraintSet::from(
+        cs.iter()
+            .map(|cons| match cons {
+                TyConstraint::SubTy(sub) => TyConstraint::SubTy(SubtypeConstraint {
+                    lhs: widen_dtv(&sub.lhs, &clustering),
+                    rhs: widen_dtv(&sub.rhs, &clustering),
+                    origin: sub.origin.clone(),
+                }),
+                TyConstraint::AddCons(add_cons) => TyConstraint::AddCons(AddConstraint::new(
+                    widen_dtv(&add_cons.lhs_ty, &clustering),
+                    widen_dtv(&add_cons.rhs_ty, &clustering),
+                    widen_dtv(&add_cons.repr_ty, &clustering),
+                )),
+            })
+            .collect::<BTreeSet<_>>(),
+    )
+}
+
+fn cap_bounds(cs: &ConstraintSet) -> ConstraintSet {
+    let mut lower_bounds: BTreeMap<&DerivedTypeVar, usize> = BTreeMap::new();
+    let mut upper_bounds: BTreeMap<&DerivedTypeVar, usize> = BTreeMap::new();
+    ConstraintSet::from(
+        cs.iter()
+            .filter(|cons| match cons {
+                TyConstraint::SubTy(sub) => {
+                    let lower = lower_bounds.entry(&sub.rhs).or_insert(0);
+                    let upper = upper_bounds.entry(&sub.lhs).or_insert(0);
+                    *lower += 1;
+                    *upper += 1;
+                    *lower <= MAX_BOUNDS && *upper <= MAX_BOUNDS
+                }
+                TyConstraint::AddCons(_) => true,
+            })
+            .cloned()
+            .collect::<BTreeSet<_>>(),
+    )
+}
+
+fn is_kept_variable(tv: &TypeVariable, context: &RuleContext) -> bool {
+    tv.is_global() || tv.get_cs_tag().is_some() || context.get_interesting().contains(tv)
+}
+
+fn drop_uninteresting(cs: &ConstraintSet, context: &RuleContext) -> ConstraintSet {
+    ConstraintSet::from(
+        cs.iter()
+            .filter(|cons| {
+                cons.base_variables()
+                    .into_iter()
+                    .any(|tv| is_kept_variable(tv, context))
+            })
+            .cloned()
+            .collect::<BTreeSet<_>>(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use crate::{
+        constraints::{parse_constraint_set, TypeVariable},
+        solver::constraint_graph::RuleContext,
+    };
+
+    use super::{Degradation, MemoryBudget};
+
+    #[test]
+    fn degrades_in_order_until_within_budget() {
+        let (_, cs) = parse_constraint_set(
+            "
+            sub_1.in_0.load.σ32@0 <= int
+            sub_1.in_0.load.σ32@128 <= int
+            sub_1.in_0.load.σ32@136 <= int
+            t1 <= t2
+            t2 <= sub_1.out
+            ",
+        )
+        .unwrap();
+        let context = RuleContext::new(
+            vec![
+                TypeVariable::new("sub_1".to_owned()),
+                TypeVariable::new("int".to_owned()),
+            ]
+            .into_iter()
+            .collect::<BTreeSet<_>>(),
+        );
+        let scc = vec![Tid::create("sub_1".to_owned(), "1000".to_owned())];
+
+        let mut unlimited = MemoryBudget::new(usize::MAX);
+        assert_eq!(unlimited.fit(&scc, cs.clone(), &context), cs);
+        assert!(unlimited.get_events().is_empty());
+
+        let mut exhausted = MemoryBudget::new(0);
+        let degraded = exhausted.fit(&scc, cs, &context);
+        assert_eq!(
+            exhausted
+                .get_events()
+                .iter()
+                .map(|event| event.degradation)
+                .collect::<Vec<_>>(),
+            Degradation::ALL.to_vec()
+        );
+        // both far offsets fold onto the same field, and the constraint between temporaries is dropped
+        assert_eq!(
+            degraded,
+            parse_constraint_set(
+                "
+                sub_1.in_0.load.σ32@0 <= int
+                sub_1.in_0.load.σ32@64 <= int
+                t2 <= sub_1.out
+                "
+            )
+            .unwrap()
+            .1
+        );
+        assert_eq!(exhausted.get_events()[0].constraints_after, 4);
+    }
+}
@@ -7,8 +7,29 @@ pub mod type_lattice;
 /// The main workhorse of the solver that describes a constraint set as a graph that admits type variables that satisfies the constraints.
 pub mod constraint_graph;
 
+/// Configurable pipelines of passes that simplify constraint graphs.
+pub mod passes;
+
+/// Callbacks for observing and extending the pipeline as it solves a job.
+pub mod observer;
+
+/// Plugins that rewrite the constraints of an scc between generation and simplification.
+pub mod transform;
+
+/// Approximate memory accounting that degrades constraints to stay within a budget.
+pub mod memory;
+
+/// Checks that simplified constraints still entail the original constraints over interesting variables.
+pub mod entailment;
+
 /// Generates constraints that are simplified with respect to SCCs
 pub mod scc_constraint_generation;
 
+/// Polymorphic summaries of functions that can be instantiated at a callsite.
+pub mod type_scheme;
+
 /// Provides oeprations on dfas that support type sketch solving
 pub mod dfa_operations;
+
+/// Structural differences between the sketch graphs of two runs.
+pub mod sketch_diff;
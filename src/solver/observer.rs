@@ -0,0 +1,38 @@
+use cwe_checker_lib::intermediate_representation::Tid;
+
+use crate::constraints::{ConstraintSet, TypeVariable};
+
+use super::{
+    type_lattice::CustomLatticeElement,
+    type_sketch::{LatticeBounds, SketchGraph},
+};
+
+/// Callbacks invoked as the pipeline solves a job, for logging, metrics, or injecting constraints without changing
+/// the driver. Every callback does nothing by default. Observers take themselves by shared reference so the same
+/// observer can be registered on several stages, observers that record state use interior mutability.
+///
+/// The constraints of an scc are generated once to compute its signature and again for the constraints of the scc,
+/// so the generation callback sees each scc twice. Sccs whose simplification is cached are not generated again and are
+/// not observed, and injected constraints are not part of the cache key.
+pub trait PipelineObserver {
+    /// Called with the constraints generated for the subprocedures of an scc before they are simplified. The returned
+    /// constraints are added to the generated constraints.
+    fn on_constraints_generated(&self, _scc: &[Tid], _cs: &ConstraintSet) -> ConstraintSet {
+        ConstraintSet::default()
+    }
+
+    /// Called with the simplified constraints of an scc.
+    fn on_simplified(&self, _scc: &[Tid], _cs: &ConstraintSet) {}
+
+    /// Called with the sketch graph built for each scc, keyed by the variable of each subprocedure in the scc.
+    fn on_sketch_built(
+        &self,
+        _var: &TypeVariable,
+        _sketch: &SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    ) {
+    }
+
+    /// Called with the global sketch graph once sketches are bound and globals are collected.
+    fn on_global_sketch_graph(&self, _sketches: &SketchGraph<LatticeBounds<CustomLatticeElement>>) {
+    }
+}
@@ -0,0 +1,217 @@
+use std::fmt::Display;
+
+use anyhow::anyhow;
+
+use crate::{constraints::VariableManager, util::FileDebugLogger};
+
+use super::constraint_graph::FSA;
+
+/// The state shared by the passes of a [PassPipeline] while simplifying the [FSA] of a single scc.
+pub struct PassContext<'a> {
+    /// The name of the scc being simplified, used to name debug output
+    pub sub_name: &'a str,
+    /// Logs debug output
+    pub debug_dir: &'a mut FileDebugLogger,
+    /// Generates fresh type variables
+    pub vman: &'a mut VariableManager,
+}
+
+/// A transformation of an [FSA] run as part of simplification.
+pub trait SimplificationPass {
+    /// The name the pass is selected by and reported as.
+    fn name(&self) -> &str;
+
+    /// Transforms the fsa.
+    fn run(&self, fsa: &mut FSA, ctx: &mut PassContext) -> anyhow::Result<()>;
+}
+
+/// Adds edges for the pointer rules and removes unproductive transitions, see [FSA::saturate].
+pub struct Saturate;
+
+impl SimplificationPass for Saturate {
+    fn name(&self) -> &str {
+        "saturate"
+    }
+
+    fn run(&self, fsa: &mut FSA, _ctx: &mut PassContext) -> anyhow::Result<()> {
+        fsa.saturate();
+        Ok(())
+    }
+}
+
+/// Restricts the language of the fsa to pops followed by pushes, see [FSA::intersect_with_pop_push].
+pub struct IntersectWithPopPush;
+
+impl SimplificationPass for IntersectWithPopPush {
+    fn name(&self) -> &str {
+        "intersect_pop_push"
+    }
+
+    fn run(&self, fsa: &mut FSA, _ctx: &mut PassContext) -> anyhow::Result<()> {
+        fsa.intersect_with_pop_push();
+        Ok(())
+    }
+}
+
+/// Removes nodes that are not both reachable from the start and able to reach the end, see [FSA::remove_unreachable].
+pub struct RemoveUnreachable;
+
+impl SimplificationPass for RemoveUnreachable {
+    fn name(&self) -> &str {
+        "remove_unreachable"
+    }
+
+    fn run(&self, fsa: &mut FSA, _ctx: &mut PassContext) -> anyhow::Result<()> {
+        fsa.remove_unreachable();
+        Ok(())
+    }
+}
+
+/// Breaks cycles by representing looping type variables with fresh interesting variables, see [FSA::generate_recursive_type_variables].
+pub struct GenerateRecursiveTypeVariables;
+
+impl SimplificationPass for GenerateRecursiveTypeVariables {
+    fn name(&self) -> &str {
+        "recursive_type_variables"
+    }
+
+    fn run(&self, fsa: &mut FSA, ctx: &mut PassContext) -> anyhow::Result<()> {
+        fsa.generate_recursive_type_variables(ctx.vman);
+        Ok(())
+    }
+}
+
+/// Collapses bisimilar states so that fewer paths are walked, see [FSA::minimize].
+pub struct Minimize;
+
+impl SimplificationPass for Minimize {
+    fn name(&self) -> &str {
+        "minimize"
+    }
+
+    fn run(&self, fsa: &mut FSA, _ctx: &mut PassContext) -> anyhow::Result<()> {
+        fsa.minimize();
+        Ok(())
+    }
+}
+
+/// Logs the fsa to the debug directory in a file suffixed with the given name. Does not modify the fsa.
+pub struct DebugDump(pub String);
+
+impl SimplificationPass for DebugDump {
+    fn name(&self) -> &str {
+        "debug_dump"
+    }
+
+    fn run(&self, fsa: &mut FSA, ctx: &mut PassContext) -> anyhow::Result<()> {
+        let fsa: &FSA = fsa;
+        ctx.debug_dir
+            .log_to_fname(&format!("{}_{}", ctx.sub_name, self.0), &|| fsa)
+    }
+}
+
+/// An ordered sequence of simplification passes. The default pipeline is the simplification described in [FSA::simplify_graph].
+pub struct PassPipeline {
+    passes: Vec<Box<dyn SimplificationPass>>,
+}
+
+impl Default for PassPipeline {
+    fn default() -> Self {
+        PassPipeline::new()
+            .with_pass(Saturate)
+            .with_pass(IntersectWithPopPush)
+            .with_pass(RemoveUnreachable)
+            .with_pass(DebugDump("simplified_proof_with_cycles".to_owned()))
+            .with_pass(GenerateRecursiveTypeVariables)
+            .with_pass(RemoveUnreachable)
+            .with_pass(Minimize)
+    }
+}
+
+impl PassPipeline {
+    /// Creates an empty pipeline that leaves the fsa unchanged.
+    pub fn new() -> PassPipeline {
+        PassPipeline { passes: Vec::new() }
+    }
+
+    /// Appends a pass to the end of the pipeline. Custom passes are registered this way.
+    pub fn with_pass(mut self, pass: impl SimplificationPass + 'static) -> PassPipeline {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    /// Looks up a builtin pass by name.
+    pub fn builtin_pass(name: &str) -> Option<Box<dyn SimplificationPass>> {
+        match name {
+            "saturate" => Some(Box::new(Saturate)),
+            "intersect_pop_push" => Some(Box::new(IntersectWithPopPush)),
+            "remove_unreachable" => Some(Box::new(RemoveUnreachable)),
+            "recursive_type_variables" => Some(Box::new(GenerateRecursiveTypeVariables)),
+            "minimize" => Some(Box::new(Minimize)),
+            _ => None,
+        }
+    }
+
+    /// Builds a pipeline of builtin passes in the order they are named.
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> anyhow::Result<PassPipeline> {
+        let passes = names
+            .iter()
+            .map(|name| {
+                Self::builtin_pass(name.as_ref())
+                    .ok_or_else(|| anyhow!("unknown simplification pass {}", name.as_ref()))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(PassPipeline { passes })
+    }
+
+    /// Builds a pipeline from a comma separated list of builtin pass names, ie. "saturate,intersect_pop_push,remove_unreachable".
+    pub fn parse(spec: &str) -> anyhow::Result<PassPipeline> {
+        Self::from_names(
+            &spec
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Gets the names of the passes in the order they run.
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    /// Runs each pass on the fsa in order.
+    pub fn run(&self, fsa: &mut FSA, ctx: &mut PassContext) -> anyhow::Result<()> {
+        for pass in self.passes.iter() {
+            log::debug!(
+                "Running simplification pass {} on {}",
+                pass.name(),
+                ctx.sub_name
+            );
+            pass.run(fsa, ctx)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for PassPipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.pass_names().join(","))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PassPipeline;
+
+    #[test]
+    fn parses_builtin_passes_in_order() {
+        let pipeline =
+            PassPipeline::parse("saturate, remove_unreachable,intersect_pop_push").unwrap();
+        assert_eq!(
+            pipeline.pass_names(),
+            vec!["saturate", "remove_unreachable", "intersect_pop_push"]
+        );
+        assert!(PassPipeline::parse("saturate,unknown_pass").is_err());
+    }
+}
@@ -1,6 +1,9 @@
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt::Display,
+    io::{BufRead, Write},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
     rc::Rc,
     vec,
 };
@@ -12,23 +15,34 @@ use cwe_checker_lib::{
 };
 use itertools::Itertools;
 use petgraph::{graph::NodeIndex, EdgeDirection::Outgoing};
+use serde::{Deserialize, Serialize};
 
 use super::{
     constraint_graph::{RuleContext, FSA},
+    entailment::{unentailed_constraints, EntailmentFailure},
+    memory::{DegradationEvent, MemoryBudget},
+    observer::PipelineObserver,
+    passes::PassPipeline,
+    transform::{apply_transformers, ConstraintTransformer},
     type_lattice::{NamedLattice, NamedLatticeElement},
     type_sketch::{insert_dtv, LatticeBounds, SketchBuilder, SketchGraph},
 };
 use crate::{
-    analysis::callgraph::{self, CallGraph},
+    analysis::{
+        callgraph::{self, CallGraph},
+        exceptions::ExceptionPaths,
+        lifetimes::HeapLifetimes,
+        noreturn::NoreturnCalls,
+    },
     constraint_generation::{
-        self, tid_to_tvar, ConstantResolver, NodeContext, PointsToMapping, RegisterMapping,
-        SubprocedureLocators,
+        self, tid_to_tvar, ConstantResolver, ConstraintDumpGranularity, NodeContext,
+        PointsToMapping, RegisterMapping, SubprocedureLocators,
     },
     constraints::{
         AddConstraint, ConstraintSet, DerivedTypeVar, FieldLabel, SubtypeConstraint, TyConstraint,
         TypeVariable, VariableManager,
     },
-    util::FileDebugLogger,
+    util::{panic_message, FileDebugLogger},
 };
 
 // TODO(ian): dont use the tid filter and instead lookup the set of target nodes to traverse or use intraproc graphs. This is ineffecient
@@ -42,16 +56,151 @@ where
 {
     cg: CallGraph,
     graph: &'a Graph<'a>,
-    node_contexts: HashMap<NodeIndex, NodeContext<R, P, S, C>>,
+    node_contexts: BTreeMap<NodeIndex, NodeContext<R, P, S, C>>,
     extern_symbols: &'a BTreeMap<Tid, ExternSymbol>,
     vman: &'b mut VariableManager,
     lattice_def: LatticeInfo<'c, T, U>,
     all_interesting_variables: RuleContext,
     debug_dir: FileDebugLogger,
     additional_constraints: &'d BTreeMap<Tid, ConstraintSet>,
+    simplification_passes: Rc<PassPipeline>,
+    constraint_dumps: Option<ConstraintDumpGranularity>,
+    observers: Vec<Rc<dyn PipelineObserver>>,
+    transformers: Vec<Rc<dyn ConstraintTransformer>>,
+    memory_budget: Option<MemoryBudget>,
+    rule_traces: bool,
+    entailment_sample: Option<usize>,
+    entailment_failures: Vec<EntailmentFailure>,
+    noreturn_calls: Option<NoreturnCalls>,
+    exception_paths: Option<ExceptionPaths>,
+    heap_lifetimes: Option<HeapLifetimes>,
+    isolate_failures: bool,
+    solve_failures: Vec<SolveFailure>,
+    extension_records: HashMap<Vec<Tid>, ExtensionRecord>,
+}
+
+/// The constraints generated for an scc, the constraints the observers injected into them, and the constraints before and
+/// after the transformers rewrote them, so that a later solve can check whether the observers and transformers still
+/// change the constraints of the scc the same way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ExtensionRecord {
+    generated: ConstraintSet,
+    injected: ConstraintSet,
+    untransformed: ConstraintSet,
+    transformed: ConstraintSet,
+}
+
+/// Caches the simplified signature and constraints of each scc along with the additional constraints that were applied to it.
+/// Resolving with a cache only resimplifies sccs whose additional constraints changed, whose observers inject different
+/// constraints or transformers rewrite them differently, or that call an scc whose signature changed.
+#[derive(Default)]
+pub struct SimplificationCache {
+    applied_constraints: HashMap<Vec<Tid>, ConstraintSet>,
+    extension_records: HashMap<Vec<Tid>, ExtensionRecord>,
+    signatures: HashMap<Vec<Tid>, Rc<Signature>>,
+    constraints: HashMap<Vec<Tid>, BTreeSet<SubtypeConstraint>>,
+    last_resimplified: BTreeSet<Tid>,
+    journal: Option<std::fs::File>,
+    journaled_variables: Option<VariableManager>,
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
+/// A result recorded in the journal of a [SimplificationCache]. Each entry also records the state of the variable manager
+/// so that variables generated after resuming do not collide with variables in the recorded constraints.
+#[derive(Serialize, Deserialize)]
+enum JournalEntry {
+    Signature {
+        scc: Vec<Tid>,
+        applied: ConstraintSet,
+        signature: BTreeSet<SubtypeConstraint>,
+        variables: VariableManager,
+    },
+    Constraints {
+        scc: Vec<Tid>,
+        constraints: BTreeSet<SubtypeConstraint>,
+        variables: VariableManager,
+    },
+}
+
+impl SimplificationCache {
+    /// Gets the subprocedures whose constraints were resimplified during the last solve.
+    pub fn get_last_resimplified(&self) -> &BTreeSet<Tid> {
+        &self.last_resimplified
+    }
+
+    /// Gets the polymorphic signature of each cached scc, which is simplified with respect to the scc's own
+    /// subprocedures and the lattice rather than every interesting variable.
+    pub fn get_signatures(&self) -> impl Iterator<Item = (&[Tid], &BTreeSet<SubtypeConstraint>)> {
+        self.signatures
+            .iter()
+            .map(|(scc, sig)| (scc.as_slice(), &sig.cs_set))
+    }
+
+    /// Creates a cache that appends every simplified signature and scc to the journal at pth as it is computed.
+    /// Results already in the journal are loaded, so a solve with the same inputs only simplifies the sccs that were not finished.
+    /// A partially written final entry (ie. from a crash) is ignored.
+    pub fn with_journal(pth: &Path) -> anyhow::Result<SimplificationCache> {
+        let mut cache = SimplificationCache::default();
+        if pth.exists() {
+            let rdr = std::io::BufReader::new(std::fs::File::open(pth)?);
+            for line in rdr.lines() {
+                let entry: JournalEntry = match serde_json::from_str(&line?) {
+                    Ok(entry) => entry,
+                    Err(_) => break,
+                };
+                cache.apply_journal_entry(entry);
+            }
+        }
+
+        cache.journal = Some(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(pth)?,
+        );
+        Ok(cache)
+    }
+
+    /// Gets the state of the variable manager recorded with the last journal entry that was loaded.
+    pub fn get_journaled_variables(&self) -> Option<&VariableManager> {
+        self.journaled_variables.as_ref()
+    }
+
+    fn apply_journal_entry(&mut self, entry: JournalEntry) {
+        match entry {
+            JournalEntry::Signature {
+                scc,
+                applied,
+                signature,
+                variables,
+            } => {
+                self.applied_constraints.insert(scc.clone(), applied);
+                self.signatures
+                    .insert(scc, Rc::new(Signature { cs_set: signature }));
+                self.journaled_variables = Some(variables);
+            }
+            JournalEntry::Constraints {
+                scc,
+                constraints,
+                variables,
+            } => {
+                self.constraints.insert(scc, constraints);
+                self.journaled_variables = Some(variables);
+            }
+        }
+    }
+
+    fn record(&mut self, entry: &JournalEntry) -> anyhow::Result<()> {
+        if let Some(journal) = &mut self.journal {
+            let mut line = serde_json::to_vec(entry)?;
+            line.push(b'\n');
+            journal.write_all(&line)?;
+            journal.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 /// The subtyping constraints for a single SCC.
 /// Hold the [Tid] of the subprocedure terms in this scc and
 /// the constraints.
@@ -62,6 +211,28 @@ pub struct SCCConstraints {
     pub constraints: BTreeSet<SubtypeConstraint>,
 }
 
+/// The step of solving an scc that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SolveStage {
+    /// Simplifying the polymorphic signature of the scc that its callers instantiate
+    Signature,
+    /// Simplifying the constraints of the scc over the interesting variables
+    Constraints,
+}
+
+/// An scc whose solve returned an error or panicked. The scc is solved as if it had no constraints, so its subprocedures
+/// get default types while the rest of the program is solved as usual.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolveFailure {
+    /// The subprocedures of the scc
+    pub scc: Vec<Tid>,
+    /// The step that failed
+    pub stage: SolveStage,
+    /// The error or panic message
+    pub message: String,
+}
+
 // There are only two types in the world :)
 #[derive(Clone, Copy, Debug)]
 enum TypeLabels {
@@ -211,9 +382,8 @@ where
         label_update
             .type_source
             .iter()
-            .map(|src| SubtypeConstraint {
-                lhs: tgt_ty.clone(),
-                rhs: self.get_affecting_type(add_cons, *src),
+            .map(|src| {
+                SubtypeConstraint::new(tgt_ty.clone(), self.get_affecting_type(add_cons, *src))
             })
             .collect()
     }
@@ -487,7 +657,7 @@ where
     /// Creates a new scc constraint generation context.
     pub fn new<'a, 'b, 'c, 'd>(
         prog_info: ProgramInfo<'a>,
-        node_contexts: HashMap<NodeIndex, NodeContext<R, P, S, C>>,
+        node_contexts: BTreeMap<NodeIndex, NodeContext<R, P, S, C>>,
         vman: &'b mut VariableManager,
         lattice: LatticeInfo<'c, T, U>,
         all_interesting_variables: RuleContext,
@@ -504,9 +674,142 @@ where
             debug_dir,
             all_interesting_variables,
             additional_constraints,
+            simplification_passes: Rc::new(PassPipeline::default()),
+            constraint_dumps: None,
+            observers: Vec::new(),
+            transformers: Vec::new(),
+            memory_budget: None,
+            rule_traces: false,
+            entailment_sample: None,
+            entailment_failures: Vec::new(),
+            noreturn_calls: None,
+            exception_paths: None,
+            heap_lifetimes: None,
+            isolate_failures: false,
+            solve_failures: Vec::new(),
+            extension_records: HashMap::new(),
         }
     }
 
+    /// Captures the errors and panics of solving each scc rather than failing the whole solve. A failed scc is solved as
+    /// if it had no constraints and is recorded as a [SolveFailure].
+    pub fn with_failure_isolation(mut self, isolate_failures: bool) -> Self {
+        self.isolate_failures = isolate_failures;
+        self
+    }
+
+    /// Gets the sccs that failed to solve so far.
+    pub fn get_solve_failures(&self) -> &[SolveFailure] {
+        &self.solve_failures
+    }
+
+    // Runs one step of solving an scc, returning None if the step failed and failures are isolated.
+    fn isolated<T>(
+        &mut self,
+        scc: &[Tid],
+        stage: SolveStage,
+        solve: impl FnOnce(&mut Self) -> anyhow::Result<T>,
+    ) -> anyhow::Result<Option<T>> {
+        if !self.isolate_failures {
+            return solve(self).map(Some);
+        }
+
+        // the failed step may have left variables, entailment failures or degradations of the scc behind
+        let variables = self.vman.clone();
+        let entailment_failures = self.entailment_failures.len();
+        let memory_budget = self.memory_budget.clone();
+        let message = match panic::catch_unwind(AssertUnwindSafe(|| solve(self))) {
+            Ok(Ok(res)) => return Ok(Some(res)),
+            Ok(Err(err)) => format!("{:#}", err),
+            Err(payload) => panic_message(payload.as_ref()),
+        };
+        *self.vman = variables;
+        self.entailment_failures.truncate(entailment_failures);
+        self.memory_budget = memory_budget;
+        self.solve_failures.push(SolveFailure {
+            scc: scc.to_vec(),
+            stage,
+            message,
+        });
+        Ok(None)
+    }
+
+    /// Isolates the constraints generated on the exception paths of each subprocedure from its normal flow.
+    pub fn with_exception_paths(mut self, exception_paths: Option<ExceptionPaths>) -> Self {
+        self.exception_paths = exception_paths;
+        self
+    }
+
+    /// Gives the heap objects accessed in several lifetimes of a subprocedure a variable per lifetime.
+    pub fn with_heap_lifetimes(mut self, heap_lifetimes: Option<HeapLifetimes>) -> Self {
+        self.heap_lifetimes = heap_lifetimes;
+        self
+    }
+
+    /// Suppresses the constraints generated along the dead fall-through edges of calls to noreturn functions.
+    pub fn with_noreturn_calls(mut self, noreturn_calls: NoreturnCalls) -> Self {
+        self.noreturn_calls = Some(noreturn_calls);
+        self
+    }
+
+    /// Checks that the simplified constraints of each scc entail up to sample_size of its original constraints over interesting
+    /// variables, see [unentailed_constraints]. Constraints that are not entailed point at a bug in simplification.
+    pub fn with_entailment_checks(mut self, sample_size: Option<usize>) -> Self {
+        self.entailment_sample = sample_size;
+        self
+    }
+
+    /// Gets the sampled constraints that were not entailed by the simplified constraints of their scc so far.
+    pub fn get_entailment_failures(&self) -> &[EntailmentFailure] {
+        &self.entailment_failures
+    }
+
+    /// Records the rules applied while building and saturating the constraint graph of each scc and dumps them to the
+    /// `rule_traces` directory, along with the applications involving each interesting variable, see [RuleTrace](super::constraint_graph::RuleTrace).
+    pub fn with_rule_traces(mut self, rule_traces: bool) -> Self {
+        self.rule_traces = rule_traces;
+        self
+    }
+
+    /// Degrades the constraints of sccs that are estimated to exceed limit_bytes while simplifying, see [MemoryBudget].
+    pub fn with_memory_budget(mut self, limit_bytes: Option<usize>) -> Self {
+        self.memory_budget = limit_bytes.map(MemoryBudget::new);
+        self
+    }
+
+    /// Gets the degradations applied to stay within the memory budget so far.
+    pub fn get_degradations(&self) -> &[DegradationEvent] {
+        self.memory_budget
+            .as_ref()
+            .map(|budget| budget.get_events())
+            .unwrap_or_default()
+    }
+
+    /// Notifies the observers as constraints are generated and simplified, see [PipelineObserver].
+    pub fn with_observers(mut self, observers: Vec<Rc<dyn PipelineObserver>>) -> Self {
+        self.observers = observers;
+        self
+    }
+
+    /// Rewrites the constraints of each scc with the transformers, in order, before they are simplified. The changes
+    /// made by each transformer are logged to the debug directory.
+    pub fn with_transformers(mut self, transformers: Vec<Rc<dyn ConstraintTransformer>>) -> Self {
+        self.transformers = transformers;
+        self
+    }
+
+    /// Simplifies the constraint graph of each scc with the given passes rather than the default pipeline.
+    pub fn with_simplification_passes(mut self, passes: Rc<PassPipeline>) -> Self {
+        self.simplification_passes = passes;
+        self
+    }
+
+    /// Dumps the generated constraints of each cfg node or function to the debug directory.
+    pub fn with_constraint_dumps(mut self, granularity: Option<ConstraintDumpGranularity>) -> Self {
+        self.constraint_dumps = granularity;
+        self
+    }
+
     fn simplify_scc(
         &mut self,
         scc: &[Tid],
@@ -514,12 +817,30 @@ where
         base_interesting_variables: BTreeSet<TypeVariable>,
     ) -> anyhow::Result<Signature> {
         let tid_filter: HashSet<Tid> = scc.iter().cloned().collect();
-        let cont = constraint_generation::Context::new(
+        if self.vman.uses_function_scopes() {
+            // The temporaries of a resimplified scc are regenerated under the names they had before
+            for tid in scc.iter() {
+                self.vman.free_scope(tid);
+            }
+        }
+        let mut cont = constraint_generation::Context::new(
             self.graph,
             &self.node_contexts,
             self.extern_symbols,
             Some(tid_filter.clone()),
         );
+        if let Some(granularity) = self.constraint_dumps {
+            cont = cont.with_constraint_dumps(granularity, self.debug_dir.phase("generated"));
+        }
+        if let Some(noreturn_calls) = &self.noreturn_calls {
+            cont = cont.with_noreturn_calls(noreturn_calls);
+        }
+        if let Some(exception_paths) = &self.exception_paths {
+            cont = cont.with_exception_paths(exception_paths);
+        }
+        if let Some(heap_lifetimes) = &self.heap_lifetimes {
+            cont = cont.with_heap_lifetimes(heap_lifetimes);
+        }
 
         let genned_cons = cont.generate_constraints(self.vman);
         // remove basic block tags for internal variable references.
@@ -534,13 +855,19 @@ where
                 .collect::<BTreeSet<_>>(),
         );
 
+        let has_extensions = !self.observers.is_empty() || !self.transformers.is_empty();
+        let generated = has_extensions.then(|| basic_cons.clone());
+        let injected = self.observe_generated(scc, &basic_cons);
+        basic_cons.insert_all(&injected);
+
         let repr_tid = tid_filter
             .iter()
             .next()
             .expect("every scc must have a node");
-        self.debug_dir.log_to_fname(
+        self.debug_dir.log_constraints(
             &format!("{}_basic_cons_no_sigs", repr_tid.get_str_repr()),
-            &|| &basic_cons,
+            &basic_cons,
+            false,
         )?;
 
         instantiate_callee_signatures(&mut basic_cons, state);
@@ -551,14 +878,45 @@ where
             }
         }
 
-        self.debug_dir
-            .log_to_fname(&format!("{}_basic_cons", repr_tid.get_str_repr()), &|| {
-                &basic_cons
-            })?;
+        let untransformed = has_extensions.then(|| basic_cons.clone());
+        if !self.transformers.is_empty() {
+            let (transformed, notes) = apply_transformers(&self.transformers, scc, basic_cons);
+            basic_cons = transformed;
+            self.debug_dir.log_to_fname(
+                &format!("{}_transforms", repr_tid.get_str_repr()),
+                &|| {
+                    notes
+                        .iter()
+                        .map(|note| note.to_string())
+                        .collect::<String>()
+                },
+            )?;
+        }
+
+        if let (Some(generated), Some(untransformed)) = (generated, untransformed) {
+            self.extension_records.insert(
+                scc.to_vec(),
+                ExtensionRecord {
+                    generated,
+                    injected,
+                    untransformed,
+                    transformed: basic_cons.clone(),
+                },
+            );
+        }
+
+        self.debug_dir.log_constraints(
+            &format!("{}_basic_cons", repr_tid.get_str_repr()),
+            &basic_cons,
+            true,
+        )?;
 
         self.debug_dir.log_to_fname(
             &format!("{}_basic_cons_repro_file", repr_tid.get_str_repr()),
-            &|| serde_json::to_string(&basic_cons).expect("should be able to serialize cons"),
+            &|| {
+                serde_json::to_string(&self.debug_dir.loggable_constraints(&basic_cons))
+                    .expect("should be able to serialize cons")
+            },
         )?;
 
         let resolved_cs_set = self
@@ -572,12 +930,16 @@ where
                 .collect::<BTreeSet<_>>(),
         );
 
-        self.debug_dir
-            .log_to_fname(&format!("{}_ptr_diff", repr_tid.get_str_repr()), &|| &diff)?;
+        self.debug_dir.log_constraints(
+            &format!("{}_ptr_diff", repr_tid.get_str_repr()),
+            &diff,
+            false,
+        )?;
 
-        self.debug_dir.log_to_fname(
+        self.debug_dir.log_constraints(
             &format!("{}_ptr_resolved_cons", repr_tid.get_str_repr()),
-            &|| &resolved_cs_set,
+            &resolved_cs_set,
+            false,
         )?;
 
         // TODO(Ian): I dislike this collaboration but constraint generation is when we discover which globals we are going to need. Ideally when we lift constraint
@@ -606,20 +968,64 @@ where
             },
         )?;
 
-        let mut fsa = FSA::new(&resolved_cs_set, &new_rcontext)?;
+        let resolved_cs_set = match self.memory_budget.as_mut() {
+            Some(budget) => budget.fit(scc, resolved_cs_set, &new_rcontext),
+            None => resolved_cs_set,
+        };
+
+        let mut fsa = if self.rule_traces {
+            FSA::new_traced(&resolved_cs_set, &new_rcontext)?
+        } else {
+            FSA::new(&resolved_cs_set, &new_rcontext)?
+        };
 
         self.debug_dir.log_to_fname(
             &format!("{}_fsa_unsimplified.dot", repr_tid.get_str_repr()),
             &|| &fsa,
         )?;
 
-        fsa.simplify_graph(repr_tid.get_str_repr(), &mut self.debug_dir, self.vman)?;
+        // Loop breakers are generated in the scope of the first subprocedure of the scc
+        let previous_scope = if self.vman.uses_function_scopes() {
+            self.vman.set_scope(scc.iter().min().cloned())
+        } else {
+            self.vman.get_scope().cloned()
+        };
+        let simplified = fsa.simplify_graph_with(
+            &self.simplification_passes,
+            repr_tid.get_str_repr(),
+            &mut self.debug_dir,
+            self.vman,
+        );
+        self.vman.set_scope(previous_scope);
+        simplified?;
 
         self.debug_dir.log_to_fname(
             &format!("{}_fsa_simplified.dot", repr_tid.get_str_repr()),
             &|| &fsa,
         )?;
 
+        if let Some(trace) = fsa.get_rule_trace() {
+            let trace_dir = self.debug_dir.phase("rule_traces");
+            trace_dir.log_to_fname(repr_tid.get_str_repr(), &|| trace)?;
+            for var in new_rcontext
+                .get_interesting()
+                .iter()
+                .filter(|var| !self.lattice_def.type_lattice_elements.contains(var))
+            {
+                if trace.for_variable(var).next().is_some() {
+                    trace_dir.log_to_fname(
+                        &format!("{}_{}", repr_tid.get_str_repr(), var.get_name()),
+                        &|| {
+                            trace
+                                .for_variable(var)
+                                .map(|app| format!("{}\n", app))
+                                .join("")
+                        },
+                    )?;
+                }
+            }
+        }
+
         let cons = fsa.walk_constraints();
         // forget add constraints at scc barriers
         let mut cons = cons.forget_add_constraints();
@@ -627,12 +1033,29 @@ where
         // Adds var constraint simulations so if we know about parameters but werent able to relate them to interesting variables we still remember they exist
         insert_missed_formals(&mut cons, &resolved_cs_set);
 
-        self.debug_dir.log_to_fname(
+        self.debug_dir.log_constraints(
             &format!("{}_simplified_constraints", repr_tid.get_str_repr()),
-            &|| &cons,
+            &cons,
+            false,
         )?;
 
-        let sub_cons = cons
+        if let Some(sample_size) = self.entailment_sample {
+            let unentailed =
+                unentailed_constraints(&resolved_cs_set, &cons, &new_rcontext, sample_size)?;
+            if !unentailed.is_empty() {
+                self.debug_dir.log_to_fname(
+                    &format!("{}_unentailed_constraints", repr_tid.get_str_repr()),
+                    &|| unentailed.iter().map(|cons| format!("{}\n", cons)).join(""),
+                )?;
+            }
+            self.entailment_failures
+                .extend(unentailed.into_iter().map(|constraint| EntailmentFailure {
+                    scc: scc.to_vec(),
+                    constraint,
+                }));
+        }
+
+        let sub_cons: BTreeSet<SubtypeConstraint> = cons
             .iter()
             .filter_map(|x| {
                 if let TyConstraint::SubTy(x) = x {
@@ -642,9 +1065,44 @@ where
                 }
             })
             .collect();
+        if let Some(budget) = self.memory_budget.as_mut() {
+            budget.retain(&sub_cons);
+        }
         Ok(Signature { cs_set: sub_cons })
     }
 
+    // Gets the constraints the observers inject into the generated constraints of an scc, each observer sees the
+    // constraints injected by the observers before it.
+    fn observe_generated(&self, scc: &[Tid], generated: &ConstraintSet) -> ConstraintSet {
+        let mut injected = ConstraintSet::default();
+        if self.observers.is_empty() {
+            return injected;
+        }
+        let mut observed = generated.clone();
+        for observer in self.observers.iter() {
+            let new_cons = observer.on_constraints_generated(scc, &observed);
+            observed.insert_all(&new_cons);
+            injected.insert_all(&new_cons);
+        }
+        injected
+    }
+
+    // Checks whether the observers and transformers change the constraints of an scc differently than when it was
+    // cached. The generated constraints of an scc cached without observers and transformers are unknown, so it is only
+    // clean if there are still none.
+    // An extension that panics resimplifies the scc, where the failure is isolated.
+    fn extensions_changed(&self, cache: &SimplificationCache, scc: &[Tid]) -> bool {
+        match cache.extension_records.get(scc) {
+            Some(record) => panic::catch_unwind(AssertUnwindSafe(|| {
+                self.observe_generated(scc, &record.generated) != record.injected
+                    || apply_transformers(&self.transformers, scc, record.untransformed.clone()).0
+                        != record.transformed
+            }))
+            .unwrap_or(true),
+            None => !self.observers.is_empty() || !self.transformers.is_empty(),
+        }
+    }
+
     fn simplify_signature(
         &mut self,
         scc: &[Tid],
@@ -658,11 +1116,24 @@ where
         scc: &[Tid],
         state: &HashMap<TypeVariable, Rc<Signature>>,
     ) -> anyhow::Result<Signature> {
-        self.simplify_scc(
+        let sig = self.simplify_scc(
             scc,
             state,
             self.all_interesting_variables.get_interesting().clone(),
-        )
+        )?;
+        if !self.observers.is_empty() {
+            let cons = ConstraintSet::from(
+                sig.cs_set
+                    .iter()
+                    .cloned()
+                    .map(TyConstraint::SubTy)
+                    .collect::<BTreeSet<_>>(),
+            );
+            self.observers
+                .iter()
+                .for_each(|observer| observer.on_simplified(scc, &cons));
+        }
+        Ok(sig)
     }
 
     /// Runs the computation, generating FSA simplified scc constraints for each.
@@ -675,14 +1146,142 @@ where
             .iter()
             .map(|ndidx| {
                 let scc = &condensed_cg.condensed_cg[*ndidx];
-                self.simplify_scc_cons(scc, &sigs).map(|s| SCCConstraints {
-                    constraints: s.cs_set,
+                let s = self.isolated(scc, SolveStage::Constraints, |ctx| {
+                    ctx.simplify_scc_cons(scc, &sigs)
+                })?;
+                Ok(SCCConstraints {
+                    constraints: s.map(|s| s.cs_set).unwrap_or_default(),
                     scc: scc.clone(),
                 })
             })
             .collect()
     }
 
+    fn applied_constraints_for_scc(&self, scc: &[Tid]) -> ConstraintSet {
+        let mut applied = ConstraintSet::default();
+        for tid in scc.iter() {
+            if let Some(to_insert) = self.additional_constraints.get(tid) {
+                applied.insert_all(to_insert);
+            }
+        }
+        applied
+    }
+
+    /// Computes the same constraints as [Context::get_simplified_constraints] but reuses the results in the cache for sccs
+    /// whose inputs have not changed since the cache was populated. The cache is updated with the new results.
+    pub fn get_simplified_constraints_cached(
+        &mut self,
+        cache: &mut SimplificationCache,
+    ) -> anyhow::Result<Vec<SCCConstraints>> {
+        let condensed_cg = callgraph::CGOrdering::new(&self.cg)?;
+        let mut changed_sigs: HashSet<NodeIndex> = HashSet::new();
+        let mut dirty: HashSet<NodeIndex> = HashSet::new();
+        let mut state: HashMap<TypeVariable, Rc<Signature>> = HashMap::new();
+
+        for nd in condensed_cg.get_reverse_topo() {
+            let scc = &condensed_cg.condensed_cg[nd];
+            let applied = self.applied_constraints_for_scc(scc);
+            let callee_changed = condensed_cg
+                .condensed_cg
+                .neighbors_directed(nd, Outgoing)
+                .any(|callee| changed_sigs.contains(&callee));
+
+            let is_dirty = callee_changed
+                || cache.applied_constraints.get(scc) != Some(&applied)
+                || !cache.signatures.contains_key(scc)
+                || !cache.constraints.contains_key(scc)
+                || self.extensions_changed(cache, scc);
+
+            let sig = if is_dirty {
+                dirty.insert(nd);
+                let sig = match self.isolated(scc, SolveStage::Signature, |ctx| {
+                    ctx.simplify_signature(scc, &state)
+                })? {
+                    Some(sig) => Rc::from(sig),
+                    None => {
+                        // failed signatures are not cached so that the next solve retries them
+                        changed_sigs.insert(nd);
+                        cache.signatures.remove(scc);
+                        for tid in scc {
+                            state.insert(tid_to_tvar(tid), Rc::new(Signature::default()));
+                        }
+                        continue;
+                    }
+                };
+                if cache.signatures.get(scc).map(|old| old.as_ref()) != Some(sig.as_ref()) {
+                    changed_sigs.insert(nd);
+                }
+                cache.signatures.insert(scc.clone(), sig.clone());
+                match self.extension_records.remove(scc) {
+                    Some(record) => cache.extension_records.insert(scc.clone(), record),
+                    None => cache.extension_records.remove(scc),
+                };
+                if cache.journal.is_some() {
+                    cache.record(&JournalEntry::Signature {
+                        scc: scc.clone(),
+                        applied: applied.clone(),
+                        signature: sig.cs_set.clone(),
+                        variables: self.vman.clone(),
+                    })?;
+                }
+                cache.applied_constraints.insert(scc.clone(), applied);
+                sig
+            } else {
+                cache
+                    .signatures
+                    .get(scc)
+                    .expect("clean sccs have a cached signature")
+                    .clone()
+            };
+
+            for tid in scc {
+                state.insert(tid_to_tvar(tid), sig.clone());
+            }
+        }
+
+        cache.last_resimplified = BTreeSet::new();
+        let mut total = Vec::new();
+        for ndidx in condensed_cg.topo_order.iter() {
+            let scc = &condensed_cg.condensed_cg[*ndidx];
+            if dirty.contains(ndidx) {
+                let s = match self.isolated(scc, SolveStage::Constraints, |ctx| {
+                    ctx.simplify_scc_cons(scc, &state)
+                })? {
+                    Some(s) => s,
+                    None => {
+                        // failed constraints are not cached so that the next solve retries them
+                        cache.constraints.remove(scc);
+                        total.push(SCCConstraints {
+                            constraints: BTreeSet::new(),
+                            scc: scc.clone(),
+                        });
+                        continue;
+                    }
+                };
+                if cache.journal.is_some() {
+                    cache.record(&JournalEntry::Constraints {
+                        scc: scc.clone(),
+                        constraints: s.cs_set.clone(),
+                        variables: self.vman.clone(),
+                    })?;
+                }
+                cache.constraints.insert(scc.clone(), s.cs_set);
+                cache.last_resimplified.extend(scc.iter().cloned());
+            }
+
+            total.push(SCCConstraints {
+                constraints: cache
+                    .constraints
+                    .get(scc)
+                    .expect("all sccs should have cached constraints")
+                    .clone(),
+                scc: scc.clone(),
+            });
+        }
+
+        Ok(total)
+    }
+
     fn get_signatures(
         &mut self,
         condensed_cg: &callgraph::CGOrdering,
@@ -691,7 +1290,12 @@ where
         let mut state: HashMap<TypeVariable, Rc<Signature>> = HashMap::new();
         for nd in condensed_cg.get_reverse_topo() {
             let scc = &condensed_cg.condensed_cg[nd];
-            let sig = Rc::from(self.simplify_signature(scc, &state)?);
+            let sig = self
+                .isolated(scc, SolveStage::Signature, |ctx| {
+                    ctx.simplify_signature(scc, &state)
+                })?
+                .unwrap_or_default();
+            let sig = Rc::from(sig);
             for tid in scc {
                 state.insert(tid_to_tvar(tid), sig.clone());
             }
@@ -702,6 +1306,7 @@ where
 }
 
 /// Signatures present an external view of a function as type constants, formals, and globals as base variables
+#[derive(PartialEq, Eq, Default)]
 struct Signature {
     cs_set: BTreeSet<SubtypeConstraint>,
 }
@@ -0,0 +1,303 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::Display,
+};
+
+use anyhow::anyhow;
+use itertools::Itertools;
+
+use crate::constraints::{DerivedTypeVar, FieldLabel};
+
+use super::type_sketch::SketchGraphCheckpoint;
+
+/// The (lower, upper) bounds of a node by lattice element name.
+pub type NamedBounds = (String, String);
+
+/// A difference between the sketches of a variable in two runs. Paths are the field labels followed from the variable's node.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SketchChange {
+    /// The variable is only represented in the old graph
+    Removed,
+    /// The variable is only represented in the new graph
+    Added,
+    /// The bounds of the node reached by the path changed
+    BoundsChanged {
+        /// The path to the node
+        path: Vec<FieldLabel>,
+        /// The bounds in the old graph
+        old: NamedBounds,
+        /// The bounds in the new graph
+        new: NamedBounds,
+    },
+    /// The node reached by the path lost a capability
+    EdgeRemoved {
+        /// The path to the node
+        path: Vec<FieldLabel>,
+        /// The label of the removed edge
+        label: FieldLabel,
+    },
+    /// The node reached by the path gained a capability
+    EdgeAdded {
+        /// The path to the node
+        path: Vec<FieldLabel>,
+        /// The label of the added edge
+        label: FieldLabel,
+    },
+}
+
+fn path_string(var: &DerivedTypeVar, path: &[FieldLabel]) -> String {
+    std::iter::once(var.to_string())
+        .chain(path.iter().map(|lbl| lbl.to_string()))
+        .join(".")
+}
+
+/// The differences between the sketches of a single variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableDiff {
+    /// The variable whose sketches differ
+    pub variable: DerivedTypeVar,
+    /// The differences found by walking both sketches from the variable's node
+    pub changes: Vec<SketchChange>,
+}
+
+impl Display for VariableDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}:", self.variable)?;
+        for change in self.changes.iter() {
+            match change {
+                SketchChange::Removed => writeln!(f, "  - only in old")?,
+                SketchChange::Added => writeln!(f, "  + only in new")?,
+                SketchChange::BoundsChanged { path, old, new } => writeln!(
+                    f,
+                    "  ~ {}: [{},{}] -> [{},{}]",
+                    path_string(&self.variable, path),
+                    old.0,
+                    old.1,
+                    new.0,
+                    new.1
+                )?,
+                SketchChange::EdgeRemoved { path, label } => writeln!(
+                    f,
+                    "  - {}",
+                    path_string(
+                        &self.variable,
+                        &[path.as_slice(), &[label.clone()]].concat()
+                    )
+                )?,
+                SketchChange::EdgeAdded { path, label } => writeln!(
+                    f,
+                    "  + {}",
+                    path_string(
+                        &self.variable,
+                        &[path.as_slice(), &[label.clone()]].concat()
+                    )
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Indexes a checkpoint by outgoing edges and root variables
+struct IndexedSketch<'a> {
+    checkpoint: &'a SketchGraphCheckpoint,
+    out_edges: BTreeMap<usize, BTreeMap<FieldLabel, usize>>,
+    roots: BTreeMap<&'a DerivedTypeVar, usize>,
+}
+
+impl<'a> IndexedSketch<'a> {
+    fn new(checkpoint: &'a SketchGraphCheckpoint) -> IndexedSketch<'a> {
+        let mut out_edges: BTreeMap<usize, BTreeMap<FieldLabel, usize>> = BTreeMap::new();
+        for (src, dst, lbl) in checkpoint.edges() {
+            out_edges.entry(*src).or_default().insert(lbl.clone(), *dst);
+        }
+
+        IndexedSketch {
+            checkpoint,
+            out_edges,
+            roots: checkpoint
+                .representations()
+                .iter()
+                .filter(|(dtv, _)| dtv.get_field_labels().is_empty())
+                .map(|(dtv, idx)| (dtv, *idx))
+                .collect(),
+        }
+    }
+
+    // Edges and representations of a malformed checkpoint can refer to nodes it does not have.
+    fn bounds(&self, nd: usize) -> anyhow::Result<&NamedBounds> {
+        self.checkpoint.nodes().get(nd).ok_or_else(|| {
+            anyhow!(
+                "node {} is out of bounds of a sketch graph with {} nodes",
+                nd,
+                self.checkpoint.nodes().len()
+            )
+        })
+    }
+
+    fn labels(&self, nd: usize) -> BTreeSet<&FieldLabel> {
+        self.out_edges
+            .get(&nd)
+            .map(|edges| edges.keys().collect())
+            .unwrap_or_default()
+    }
+}
+
+// Walks both sketches in lockstep from the roots, following the labels both nodes have in common
+fn diff_from_roots(
+    old: &IndexedSketch,
+    new: &IndexedSketch,
+    old_root: usize,
+    new_root: usize,
+) -> anyhow::Result<Vec<SketchChange>> {
+    let mut changes = Vec::new();
+    let mut visited = BTreeSet::new();
+    let mut worklist = VecDeque::new();
+    worklist.push_back((old_root, new_root, Vec::new()));
+
+    while let Some((old_nd, new_nd, path)) = worklist.pop_front() {
+        if !visited.insert((old_nd, new_nd)) {
+            continue;
+        }
+
+        let old_bounds = old.bounds(old_nd)?;
+        let new_bounds = new.bounds(new_nd)?;
+        if old_bounds != new_bounds {
+            changes.push(SketchChange::BoundsChanged {
+                path: path.clone(),
+                old: old_bounds.clone(),
+                new: new_bounds.clone(),
+            });
+        }
+
+        let old_labels = old.labels(old_nd);
+        let new_labels = new.labels(new_nd);
+        for lbl in old_labels.difference(&new_labels) {
+            changes.push(SketchChange::EdgeRemoved {
+                path: path.clone(),
+                label: (*lbl).clone(),
+            });
+        }
+        for lbl in new_labels.difference(&old_labels) {
+            changes.push(SketchChange::EdgeAdded {
+                path: path.clone(),
+                label: (*lbl).clone(),
+            });
+        }
+        for lbl in old_labels.intersection(&new_labels) {
+            let mut next_path = path.clone();
+            next_path.push((*lbl).clone());
+            worklist.push_back((
+                old.out_edges[&old_nd][*lbl],
+                new.out_edges[&new_nd][*lbl],
+                next_path,
+            ));
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Compares the sketches of every variable represented in either graph. Sketches are compared structurally by walking both from
+/// the variable's node, so the graphs do not need to agree on node indices. Only variables whose sketches differ are reported.
+/// Fails if either checkpoint refers to a node it does not have.
+pub fn diff_sketches(
+    old: &SketchGraphCheckpoint,
+    new: &SketchGraphCheckpoint,
+) -> anyhow::Result<Vec<VariableDiff>> {
+    let old = IndexedSketch::new(old);
+    let new = IndexedSketch::new(new);
+
+    let variables = old
+        .roots
+        .keys()
+        .chain(new.roots.keys())
+        .cloned()
+        .collect::<BTreeSet<_>>();
+
+    let mut diffs = Vec::new();
+    for var in variables {
+        let changes = match (old.roots.get(var), new.roots.get(var)) {
+            (Some(old_root), Some(new_root)) => diff_from_roots(&old, &new, *old_root, *new_root)?,
+            (Some(_), None) => vec![SketchChange::Removed],
+            (None, Some(_)) => vec![SketchChange::Added],
+            (None, None) => Vec::new(),
+        };
+
+        if !changes.is_empty() {
+            diffs.push(VariableDiff {
+                variable: var.clone(),
+                changes,
+            });
+        }
+    }
+
+    Ok(diffs)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        constraints::{DerivedTypeVar, FieldLabel, TypeVariable},
+        solver::type_sketch::SketchGraphCheckpoint,
+    };
+
+    use super::SketchChange;
+
+    fn checkpoint(json: &str) -> SketchGraphCheckpoint {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn reports_changes_along_paths() {
+        let var = serde_json::to_string(&DerivedTypeVar::new(TypeVariable::new(
+            "sub_1000".to_owned(),
+        )))
+        .unwrap();
+        let old = checkpoint(&format!(
+            r#"{{"default_label":["bottom","top"],"nodes":[["bottom","top"],["bottom","int"]],"edges":[[0,1,"Load"]],"representations":[[{},0]]}}"#,
+            var
+        ));
+        let new = checkpoint(&format!(
+            r#"{{"default_label":["bottom","top"],"nodes":[["bottom","top"],["bottom","char"]],"edges":[[0,1,"Load"],[0,1,"Store"]],"representations":[[{},0]]}}"#,
+            var
+        ));
+
+        let diffs = super::diff_sketches(&old, &new).unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(
+            diffs[0].changes,
+            vec![
+                SketchChange::EdgeAdded {
+                    path: vec![],
+                    label: FieldLabel::Store
+                },
+                SketchChange::BoundsChanged {
+                    path: vec![FieldLabel::Load],
+                    old: ("bottom".to_owned(), "int".to_owned()),
+                    new: ("bottom".to_owned(), "char".to_owned()),
+                },
+            ]
+        );
+        assert!(super::diff_sketches(&old, &old).unwrap().is_empty());
+    }
+
+    #[test]
+    fn out_of_bounds_nodes_are_errors() {
+        let var = serde_json::to_string(&DerivedTypeVar::new(TypeVariable::new(
+            "sub_1000".to_owned(),
+        )))
+        .unwrap();
+        let valid = checkpoint(&format!(
+            r#"{{"default_label":["bottom","top"],"nodes":[["bottom","top"]],"edges":[],"representations":[[{},0]]}}"#,
+            var
+        ));
+        let dangling = checkpoint(&format!(
+            r#"{{"default_label":["bottom","top"],"nodes":[["bottom","top"]],"edges":[[0,3,"Load"]],"representations":[[{},0]]}}"#,
+            var
+        ));
+
+        assert!(super::diff_sketches(&dangling, &dangling).is_err());
+        assert!(super::diff_sketches(&valid, &dangling).is_ok());
+    }
+}
@@ -0,0 +1,177 @@
+use std::{collections::BTreeSet, fmt::Display, rc::Rc};
+
+use cwe_checker_lib::intermediate_representation::Tid;
+use regex::Regex;
+
+use crate::constraints::{ConstraintOrigin, ConstraintReason, ConstraintSet, TyConstraint};
+
+/// Rewrites the constraints of an scc after they are generated and before they are simplified, ie. to drop constraints
+/// involving certain registers, rewrite vendor specific intrinsics, or strengthen the constraints of known functions.
+/// Transformers see the generated constraints along with the instantiated callee signatures and additional constraints.
+pub trait ConstraintTransformer {
+    /// A name identifying the transformer in provenance notes.
+    fn name(&self) -> &str;
+
+    /// Transforms the constraints of the subprocedures in scc.
+    fn transform(&self, scc: &[Tid], cs: ConstraintSet) -> ConstraintSet;
+}
+
+/// Records the constraints a transformer removed and added for an scc.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransformNote {
+    /// The name of the transformer
+    pub transformer: String,
+    /// The constraints the transformer removed
+    pub removed: ConstraintSet,
+    /// The constraints the transformer added
+    pub added: ConstraintSet,
+}
+
+impl Display for TransformNote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}:", self.transformer)?;
+        for cons in self.removed.iter() {
+            writeln!(f, "  - {}", cons)?;
+        }
+        for cons in self.added.iter() {
+            writeln!(f, "  + {}", cons)?;
+        }
+        Ok(())
+    }
+}
+
+/// Applies the transformers in order to the constraints of an scc. Added subtyping constraints without an origin are
+/// attributed to a transform of the first subprocedure in the scc. Returns the transformed constraints and a note for
+/// each transformer that changed them.
+pub fn apply_transformers(
+    transformers: &[Rc<dyn ConstraintTransformer>],
+    scc: &[Tid],
+    cs: ConstraintSet,
+) -> (ConstraintSet, Vec<TransformNote>) {
+    let mut notes = Vec::new();
+    let mut curr = cs;
+    for transformer in transformers.iter() {
+        let transformed = transformer.transform(scc, curr.clone());
+        let removed = ConstraintSet::from(
+            curr.difference(&transformed)
+                .cloned()
+                .collect::<BTreeSet<_>>(),
+        );
+        let mut added = ConstraintSet::from(
+            transformed
+                .difference(&curr)
+                .cloned()
+                .collect::<BTreeSet<_>>(),
+        );
+        if let Some(repr_tid) = scc.first() {
+            added = added.with_origin(&ConstraintOrigin::new(
+                repr_tid.clone(),
+                ConstraintReason::Transform,
+            ));
+        }
+
+        if removed.is_empty() && added.is_empty() {
+            continue;
+        }
+        curr = ConstraintSet::from(
+            curr.0
+                .into_iter()
+                .filter(|cons| !removed.contains(cons))
+                .collect::<BTreeSet<_>>(),
+        );
+        curr.insert_all(&added);
+        notes.push(TransformNote {
+            transformer: transformer.name().to_owned(),
+            removed,
+            added,
+        });
+    }
+    (curr, notes)
+}
+
+/// Drops every constraint that mentions a base variable whose name matches a regex, ie. `_FS_OFFSET$` to ignore
+/// constraints on the thread local segment register.
+pub struct DropVariables {
+    name: String,
+    pattern: Regex,
+}
+
+impl DropVariables {
+    /// Creates a transformer dropping constraints on variables whose name matches pattern.
+    pub fn new(pattern: Regex) -> DropVariables {
+        DropVariables {
+            name: format!("drop_variables({})", pattern),
+            pattern,
+        }
+    }
+}
+
+impl ConstraintTransformer for DropVariables {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn transform(&self, _scc: &[Tid], cs: ConstraintSet) -> ConstraintSet {
+        ConstraintSet::from(
+            cs.0.into_iter()
+                .filter(|cons: &TyConstraint| {
+                    !cons
+                        .base_variables()
+                        .iter()
+                        .any(|tv| self.pattern.is_match(&tv.get_name()))
+                })
+                .collect::<BTreeSet<_>>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+    use regex::Regex;
+
+    use crate::constraints::{parse_constraint_set, ConstraintReason, ConstraintSet, TyConstraint};
+
+    use super::{apply_transformers, ConstraintTransformer, DropVariables};
+
+    struct Strengthen;
+
+    impl ConstraintTransformer for Strengthen {
+        fn name(&self) -> &str {
+            "strengthen"
+        }
+
+        fn transform(&self, _scc: &[Tid], mut cs: ConstraintSet) -> ConstraintSet {
+            cs.insert_all(&parse_constraint_set("sub_1_RAX <= int").unwrap().1);
+            cs
+        }
+    }
+
+    #[test]
+    fn transforms_are_noted_and_attributed() {
+        let (_, cs) =
+            parse_constraint_set("sub_1_RAX <= sub_1_FS_OFFSET\nsub_1_RAX <= sub_1_RDI").unwrap();
+        let sub = Tid::create("sub_1".to_owned(), "1000".to_owned());
+        let transformers: Vec<Rc<dyn ConstraintTransformer>> = vec![
+            Rc::new(DropVariables::new(Regex::new("_FS_OFFSET$").unwrap())),
+            Rc::new(Strengthen),
+        ];
+        let (transformed, notes) = apply_transformers(&transformers, &[sub.clone()], cs);
+
+        assert_eq!(
+            transformed,
+            parse_constraint_set("sub_1_RAX <= sub_1_RDI\nsub_1_RAX <= int")
+                .unwrap()
+                .1
+        );
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0].removed.len(), 1);
+        assert!(notes[0].added.is_empty());
+        assert!(notes[1].added.iter().all(|cons| matches!(
+            cons,
+            TyConstraint::SubTy(sub_cons) if sub_cons.origin.as_ref().map(|origin| (&origin.tid, origin.reason)) == Some((&sub, ConstraintReason::Transform))
+        )));
+    }
+}
@@ -103,6 +103,23 @@ impl LatticeDefinition {
         &self.weakest_integral_type
     }
 
+    /// Gets the name of the top element.
+    pub fn get_top_handle(&self) -> &str {
+        &self.top_handle
+    }
+
+    /// Gets the name of the bottom element.
+    pub fn get_bottom_handle(&self) -> &str {
+        &self.bottom_handle
+    }
+
+    /// Checks whether an element is related to any other element in the definition.
+    pub fn has_element(&self, name: &str) -> bool {
+        self.less_than_relations_between_handles
+            .iter()
+            .any(|(x, y)| x == name || y == name)
+    }
+
     fn get_lt_graph(&self) -> Graph<String, (), Directed> {
         let mut lt_grph = petgraph::Graph::new();
         let mut temp_node_holder = HashMap::new();
@@ -163,54 +180,113 @@ impl LatticeDefinition {
         self.create_table(&self.get_gt_graph())
     }
 
-    // TODO(ian): I suspect this could be done around n^2 with a dynamic programming approach rather than just doing random node indeces.
-    // The current iteration order demands a linear pass to determine the intersection
+    // The least of a set of elements, where up_sets maps each element to the elements greater than or equal to it. In a
+    // lattice the common upper bounds of two elements have a least element. Elements of a definition that is not a
+    // lattice may have several minimal common upper bounds, in which case the least element above all of them is taken
+    // so that the result is still an upper bound; the top element bounds every set.
+    fn least_element(
+        mut candidates: HashSet<NodeIndex>,
+        up_sets: &HashMap<NodeIndex, HashSet<NodeIndex>>,
+    ) -> Option<NodeIndex> {
+        loop {
+            let minimal = candidates
+                .iter()
+                .filter(|x| !candidates.iter().any(|y| y != *x && up_sets[y].contains(x)))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            match minimal.as_slice() {
+                [] => return None,
+                [least] => return Some(*least),
+                _ => {
+                    candidates = minimal
+                        .iter()
+                        .map(|x| up_sets[x].clone())
+                        .reduce(|acc, up| acc.intersection(&up).cloned().collect())
+                        .unwrap_or_default();
+                    // the minimal elements are incomparable so none of them is above the others
+                    minimal.iter().for_each(|x| {
+                        candidates.remove(x);
+                    });
+                }
+            }
+        }
+    }
+
+    fn up_sets(graph: &Graph<String, (), Directed>) -> HashMap<NodeIndex, HashSet<NodeIndex>> {
+        graph
+            .node_indices()
+            .map(|x| (x, Self::collect_reachable_nds(graph, x)))
+            .collect()
+    }
 
-    // we can just pregenerate each gt or lt list then check the minimum of the intersection between each node's set but in the worst case this is still n^3.
+    // Maps each pair of elements to the least element reachable from both, which is the join in the less than graph
+    // and the meet in the greater than graph. Elements may have several parents.
     fn create_table(
         &self,
         graph: &Graph<String, (), Directed>,
     ) -> HashMap<(String, String), String> {
-        let sort = petgraph::algo::toposort(graph, None)
-            .expect("A lattice will allow for a topological sort")
-            .into_iter()
-            .enumerate()
-            .map(|x| (x.1, x.0))
-            .collect::<HashMap<_, _>>();
+        let up_sets = Self::up_sets(graph);
 
-        let mut join_res: Vec<((NodeIndex, NodeIndex), NodeIndex)> = Vec::new();
-        for (id1, id2) in graph
+        graph
             .node_indices()
-            .into_iter()
-            .cartesian_product(graph.node_indices().into_iter())
-        {
-            let mut gt_set1 = Self::collect_reachable_nds(graph, id1);
-            gt_set1.insert(id1);
-
-            let mut gt_set2 = Self::collect_reachable_nds(graph, id2);
-            gt_set2.insert(id2);
-            let shared_reachable = gt_set1.intersection(&gt_set2);
-
-            let lub = shared_reachable
-                .min_by_key(|x| sort.get(x))
-                .expect("every member should have a lub");
-            join_res.push(((id1, id2), *lub));
-        }
-
-        join_res
-            .into_iter()
-            .map(|((k1, k2), v)| {
+            .cartesian_product(graph.node_indices())
+            .map(|(id1, id2)| {
+                let shared_reachable = up_sets[&id1]
+                    .intersection(&up_sets[&id2])
+                    .cloned()
+                    .collect::<HashSet<_>>();
+                let lub = Self::least_element(shared_reachable, &up_sets)
+                    .expect("every member should have a lub");
                 (
-                    (
-                        graph.node_weight(k1).unwrap().to_string(),
-                        graph.node_weight(k2).unwrap().to_string(),
-                    ),
-                    graph.node_weight(v).unwrap().to_string(),
+                    (graph[id1].to_string(), graph[id2].to_string()),
+                    graph[lub].to_string(),
                 )
             })
             .collect()
     }
 
+    // The pairs of elements with more than one minimal common bound in a graph
+    fn ambiguous_pairs(graph: &Graph<String, (), Directed>) -> Vec<(String, String)> {
+        let up_sets = Self::up_sets(graph);
+        graph
+            .node_indices()
+            .tuple_combinations()
+            .filter(|(id1, id2)| {
+                let shared = up_sets[id1]
+                    .intersection(&up_sets[id2])
+                    .cloned()
+                    .collect::<HashSet<_>>();
+                shared
+                    .iter()
+                    .filter(|x| !shared.iter().any(|y| y != *x && up_sets[y].contains(x)))
+                    .count()
+                    > 1
+            })
+            .map(|(id1, id2)| {
+                let mut pair = (graph[id1].to_string(), graph[id2].to_string());
+                if pair.0 > pair.1 {
+                    pair = (pair.1, pair.0);
+                }
+                pair
+            })
+            .sorted()
+            .collect()
+    }
+
+    /// Finds the pairs of elements that have no least upper bound or no greatest lower bound, so the definition is not a
+    /// lattice. Elements may have several parents (ie. `size_t` below both `uint64` and `arith`) as long as every pair
+    /// of elements has a unique join and meet. Joins and meets of the pairs found are approximated by the least element
+    /// above (or greatest below) all of their minimal bounds.
+    pub fn find_non_lattice_pairs(&self) -> Vec<(String, String)> {
+        Self::ambiguous_pairs(&self.get_lt_graph())
+            .into_iter()
+            .chain(Self::ambiguous_pairs(&self.get_gt_graph()))
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
     fn create_reachable_sets(g: &Graph<String, (), Directed>) -> HashMap<String, HashSet<String>> {
         g.node_indices()
             .map(|x| (x, Self::collect_reachable_nds(&g, x)))
@@ -232,6 +308,15 @@ impl LatticeDefinition {
 
     /// From a user definition generates a named lattice that has joins, meets, and a lookup table for the less than relation.
     pub fn generate_lattice(&self) -> EnumeratedNamedLattice {
+        let non_lattice_pairs = self.find_non_lattice_pairs();
+        if !non_lattice_pairs.is_empty() {
+            log::warn!(
+                "The lattice definition is not a lattice, joins and meets of {} pairs are approximated: {:?}",
+                non_lattice_pairs.len(),
+                non_lattice_pairs
+            );
+        }
+
         let join = Rc::new(self.create_join_table());
         let meet = Rc::new(self.create_meet_table());
         let lt_set = Rc::new(self.create_less_than_sets());
@@ -383,3 +468,70 @@ impl MeetSemilattice for CustomLatticeElement {
             .expect("All relations should be defined in table")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use alga::general::{JoinSemilattice, MeetSemilattice};
+
+    use super::{LatticeDefinition, NamedLattice};
+
+    fn definition(relations: &[(&str, &str)]) -> LatticeDefinition {
+        LatticeDefinition::new(
+            relations
+                .iter()
+                .map(|(x, y)| (x.to_string(), y.to_string()))
+                .collect(),
+            "top".to_owned(),
+            "bottom".to_owned(),
+            "arith".to_owned(),
+        )
+    }
+
+    #[test]
+    fn elements_may_have_several_parents() {
+        let def = definition(&[
+            ("bottom", "size_t"),
+            ("bottom", "int64"),
+            ("size_t", "uint64"),
+            ("size_t", "arith"),
+            ("int64", "arith"),
+            ("uint64", "top"),
+            ("arith", "top"),
+        ]);
+        assert!(def.find_non_lattice_pairs().is_empty());
+
+        let lattice = def.generate_lattice();
+        let elem = |name: &str| lattice.get_elem(name).unwrap();
+        assert_eq!(elem("size_t").join(&elem("int64")), elem("arith"));
+        assert_eq!(elem("uint64").join(&elem("int64")), elem("top"));
+        assert_eq!(elem("uint64").meet(&elem("arith")), elem("size_t"));
+        assert_eq!(elem("uint64").meet(&elem("int64")), elem("bottom"));
+        assert!(elem("size_t") < elem("uint64") && elem("size_t") < elem("arith"));
+    }
+
+    #[test]
+    fn ambiguous_bounds_are_reported_and_approximated() {
+        let def = definition(&[
+            ("bottom", "a"),
+            ("bottom", "b"),
+            ("a", "c"),
+            ("a", "d"),
+            ("b", "c"),
+            ("b", "d"),
+            ("c", "top"),
+            ("d", "top"),
+        ]);
+        assert_eq!(
+            def.find_non_lattice_pairs(),
+            vec![
+                ("a".to_owned(), "b".to_owned()),
+                ("c".to_owned(), "d".to_owned())
+            ]
+        );
+
+        let lattice = def.generate_lattice();
+        let elem = |name: &str| lattice.get_elem(name).unwrap();
+        assert_eq!(elem("a").join(&elem("b")), elem("top"));
+        assert_eq!(elem("c").meet(&elem("d")), elem("bottom"));
+    }
+}
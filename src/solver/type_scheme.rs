@@ -0,0 +1,195 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt::Display,
+};
+
+use cwe_checker_lib::intermediate_representation::Tid;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constraint_generation::tid_to_tvar,
+    constraints::{DerivedTypeVar, FieldLabel, SubtypeConstraint, TypeVariable},
+    graph_algos::mapping_graph::MappingGraph,
+    solver::{
+        type_lattice::{NamedLattice, NamedLatticeElement},
+        type_sketch::{insert_dtv, LatticeBounds, SketchBuilder},
+    },
+    util::FileDebugLogger,
+};
+
+/// The polymorphic type of a function: its signature constraints quantified over every variable that is neither the
+/// function nor a lattice element, ie. `∀ τ. sub_1.in_0 ⊑ τ, τ ⊑ sub_1.out ⇒ sub_1`. Instantiating the scheme at a
+/// callsite with fresh variables for the quantified ones yields the constraints of that call.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeScheme {
+    /// The function whose type is generalized
+    pub function: Tid,
+    /// The variables the scheme is quantified over
+    pub quantified: BTreeSet<TypeVariable>,
+    /// The constraints relating the function to the quantified variables and lattice elements
+    pub constraints: BTreeSet<SubtypeConstraint>,
+    /// The label paths of the sketch built from the constraints, formatted as by
+    /// [crate::solver::type_sketch::SketchPaths]
+    pub sketch: String,
+}
+
+impl TypeScheme {
+    /// Creates the scheme of function from the signature of its scc, quantifying every variable that is not a lattice
+    /// element or a subprocedure in the scc. The scheme has no sketch until one is added by [TypeScheme::with_sketch].
+    pub fn new(
+        function: Tid,
+        scc: &[Tid],
+        constraints: BTreeSet<SubtypeConstraint>,
+        lattice_elems: &HashSet<TypeVariable>,
+    ) -> TypeScheme {
+        let scc_vars = scc.iter().map(tid_to_tvar).collect::<BTreeSet<_>>();
+        let quantified = constraints
+            .iter()
+            .flat_map(|cons| vec![cons.lhs.get_base_variable(), cons.rhs.get_base_variable()])
+            .filter(|tv| !lattice_elems.contains(tv) && !scc_vars.contains(tv))
+            .cloned()
+            .collect();
+
+        TypeScheme {
+            function,
+            quantified,
+            constraints,
+            sketch: String::new(),
+        }
+    }
+
+    /// Sets the sketch paths of the scheme.
+    pub fn with_sketch(mut self, sketch: String) -> TypeScheme {
+        self.sketch = sketch;
+        self
+    }
+}
+
+impl Display for TypeScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.quantified.is_empty() {
+            write!(f, "∀ ")?;
+            for (i, tv) in self.quantified.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", tv)?;
+            }
+            write!(f, ". ")?;
+        }
+
+        for (i, cons) in self.constraints.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", cons)?;
+        }
+        write!(f, " ⇒ {}", tid_to_tvar(&self.function))
+    }
+}
+
+/// Builds the scheme of each subprocedure from the signature of its scc. The sketch of each scheme is built from the
+/// signature alone, so variables of callees, whose signatures are already instantiated into the signature, are left
+/// opaque rather than copied from the callee's sketch.
+pub fn build_type_schemes<'a, T, U>(
+    signatures: impl Iterator<Item = (&'a [Tid], &'a BTreeSet<SubtypeConstraint>)>,
+    lattice: &T,
+    lattice_elems: &HashSet<TypeVariable>,
+    debug_dir: FileDebugLogger,
+) -> anyhow::Result<Vec<TypeScheme>>
+where
+    T: NamedLattice<U>,
+    U: NamedLatticeElement + Display,
+{
+    let add_new_var = |dtv: &DerivedTypeVar,
+                       grph: &mut MappingGraph<LatticeBounds<U>, DerivedTypeVar, FieldLabel>|
+     -> anyhow::Result<()> {
+        insert_dtv(lattice, grph, dtv.clone());
+        Ok(())
+    };
+    let bldr: SketchBuilder<U, T, _> =
+        SketchBuilder::new(lattice, lattice_elems, &add_new_var, debug_dir);
+
+    let mut schemes = Vec::new();
+    for (scc, signature) in signatures {
+        let sketch = bldr.build_and_label_constraints_representing(scc, signature)?;
+        for tid in scc.iter() {
+            let paths = sketch
+                .paths_of(&DerivedTypeVar::new(tid_to_tvar(tid)))
+                .to_string();
+            schemes.push(
+                TypeScheme::new(tid.clone(), scc, signature.clone(), lattice_elems)
+                    .with_sketch(paths),
+            );
+        }
+    }
+
+    schemes.sort_by(|fst, snd| fst.function.cmp(&snd.function));
+    Ok(schemes)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeSet, HashSet};
+
+    use cwe_checker_lib::intermediate_representation::Tid;
+
+    use crate::{
+        constraints::{parse_constraint_set, TyConstraint, TypeVariable},
+        solver::type_lattice::LatticeDefinition,
+        util::FileDebugLogger,
+    };
+
+    use super::build_type_schemes;
+
+    #[test]
+    fn schemes_quantify_local_variables() {
+        let (_, cs) = parse_constraint_set(
+            "sub_id.in_0 <= tmp_0\ntmp_0 <= sub_id.out\nsub_id.in_0.load <= int",
+        )
+        .unwrap();
+        let signature = cs
+            .iter()
+            .filter_map(|cons| match cons {
+                TyConstraint::SubTy(sub) => Some(sub.clone()),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+
+        let lattice = LatticeDefinition::new(
+            vec![
+                ("int".to_owned(), "top".to_owned()),
+                ("bottom".to_owned(), "int".to_owned()),
+            ],
+            "top".to_owned(),
+            "bottom".to_owned(),
+            "int".to_owned(),
+        )
+        .generate_lattice();
+        let lattice_elems = lattice
+            .get_nds()
+            .keys()
+            .map(|name| TypeVariable::new(name.clone()))
+            .collect::<HashSet<_>>();
+
+        let scc = vec![Tid::create("sub_id".to_owned(), "1000".to_owned())];
+        let schemes = build_type_schemes(
+            std::iter::once((scc.as_slice(), &signature)),
+            &lattice,
+            &lattice_elems,
+            FileDebugLogger::new(None),
+        )
+        .unwrap();
+
+        assert_eq!(schemes.len(), 1);
+        assert_eq!(
+            schemes[0].quantified,
+            BTreeSet::from([TypeVariable::new("tmp_0".to_owned())])
+        );
+        assert!(schemes[0].to_string().starts_with("∀ tmp_0. "));
+        assert!(schemes[0]
+            .sketch
+            .lines()
+            .any(|path| path.starts_with("sub_id.in_0.load : ")));
+    }
+}
@@ -1,5 +1,5 @@
 use std::cmp::Reverse;
-use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashSet, VecDeque};
 use std::fmt::Display;
 use std::iter::FromIterator;
 use std::marker::PhantomData;
@@ -330,7 +330,7 @@ where
         })
         .map(|(ndidx, repr)| (NodeIndex::new(ndidx), repr))
         .fold(
-            HashMap::<usize, BTreeSet<NodeIndex>>::new(),
+            BTreeMap::<usize, BTreeSet<NodeIndex>>::new(),
             |mut total, (nd_ind, repr_group)| {
                 total.entry(repr_group).or_default().insert(nd_ind);
                 total
@@ -566,20 +566,20 @@ where
 
 /// Refers to some type node in a sketch graph within a program
 /// Can always find because sketch graphs are prefix closed
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
 struct SCCLocation {
     scc: Vec<Tid>,
     target_path: NodeIndex,
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
 struct GlobalLocation {
     globvar: TypeVariable,
     target_path: NodeIndex,
 }
 
 /// We describe two types of locations that can be aliased. Type members in SCCs and glopals
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
 enum TypeLocation {
     SCCLoc(SCCLocation),
     GlobalLoc(GlobalLocation),
@@ -594,6 +594,9 @@ impl TypeLocation {
     }
 }
 
+/// A callsite in the global graph, the scc containing the call and the tag of the callee's variables at the call.
+type Callsite = (Vec<Tid>, Tid);
+
 /// Creates a structured and labeled sketch graph
 /// This algorithm creates polymorphic function types.
 /// Type information flows up to callers but not down to callees (callees wont be unified).
@@ -602,17 +605,54 @@ pub struct SCCSketchsBuilder<'a, U: NamedLatticeElement, T: NamedLattice<U>> {
     // Allows us to map any tid to the correct constraintset
     scc_signatures: HashMap<Tid, Rc<BTreeSet<SubtypeConstraint>>>,
     // Collects a shared sketchgraph representing the functions in the SCC
-    scc_repr: HashMap<TypeVariable, Rc<SketchGraph<LatticeBounds<U>>>>,
-    global_repr: HashMap<TypeVariable, (NodeIndex, Sketch<LatticeBounds<U>>)>,
+    scc_repr: BTreeMap<TypeVariable, Rc<SketchGraph<LatticeBounds<U>>>>,
+    global_repr: BTreeMap<TypeVariable, (NodeIndex, Sketch<LatticeBounds<U>>)>,
     cg: CallGraph,
     lattice: &'a T,
     type_lattice_elements: HashSet<TypeVariable>,
     /// Aliases some type nodes accross sccs to bind polymorphic parameters loc->loc
     parameter_aliases: BTreeMap<TypeLocation, TypeLocation>,
+    /// Labels the type of each callee at a callsite in the global graph by its callsite tagged variable
+    label_callsites: bool,
+    /// Aliases the types of callees at callsites to their callee, loc->(callsite tag, loc). Used instead of
+    /// parameter_aliases when callsites are cloned.
+    callsite_aliases: BTreeMap<TypeLocation, (Tid, TypeLocation)>,
+    /// The length of the call strings that keep their own clone of the callee's type
+    context_depth: usize,
+    sketch_cache: SketchCache<U>,
 
     debug_dir: FileDebugLogger,
 }
 
+/// Caches the sketch built for each scc from its signature before polymorphic types are bound. A later build only
+/// rebuilds the sketches of sccs whose signature changed or that call an scc whose sketch was rebuilt.
+pub struct SketchCache<U: NamedLatticeElement> {
+    sketches: HashMap<
+        Vec<Tid>,
+        (
+            Rc<BTreeSet<SubtypeConstraint>>,
+            Rc<SketchGraph<LatticeBounds<U>>>,
+        ),
+    >,
+    last_rebuilt: BTreeSet<Tid>,
+}
+
+impl<U: NamedLatticeElement> Default for SketchCache<U> {
+    fn default() -> Self {
+        SketchCache {
+            sketches: HashMap::new(),
+            last_rebuilt: BTreeSet::new(),
+        }
+    }
+}
+
+impl<U: NamedLatticeElement> SketchCache<U> {
+    /// Gets the subprocedures whose sketches were rebuilt during the last build.
+    pub fn get_last_rebuilt(&self) -> &BTreeSet<Tid> {
+        &self.last_rebuilt
+    }
+}
+
 #[derive(Debug)]
 struct SketchSCCInfo {
     /// the path to an entry of this scc
@@ -644,16 +684,39 @@ where
 
         SCCSketchsBuilder {
             scc_signatures,
-            scc_repr: HashMap::new(),
+            scc_repr: BTreeMap::new(),
             cg,
             lattice,
             type_lattice_elements,
             parameter_aliases: BTreeMap::new(),
+            label_callsites: false,
+            callsite_aliases: BTreeMap::new(),
+            context_depth: 0,
+            sketch_cache: SketchCache::default(),
             debug_dir,
-            global_repr: HashMap::new(),
+            global_repr: BTreeMap::new(),
         }
     }
 
+    /// Reuses the sketches in cache for sccs whose signature and callees' sketches have not changed since the cache was
+    /// populated. The cache is updated with the sketches of this build and can be taken back with [Self::take_sketch_cache].
+    pub fn with_sketch_cache(mut self, cache: SketchCache<U>) -> SCCSketchsBuilder<'a, U, T> {
+        self.sketch_cache = cache;
+        self
+    }
+
+    /// Takes the sketch cache, updated with the sketches of the last build.
+    pub fn take_sketch_cache(&mut self) -> SketchCache<U> {
+        std::mem::take(&mut self.sketch_cache)
+    }
+
+    /// If label is true the global type graph also labels the type each callee is instantiated to at a callsite by the
+    /// callee's variable tagged with the callsite, ie. `sub_id:blk_1`, otherwise only untagged variables are labeled.
+    pub fn with_callsite_labels(mut self, label: bool) -> SCCSketchsBuilder<'a, U, T> {
+        self.label_callsites = label;
+        self
+    }
+
     fn build_and_label_scc_sketch(&mut self, to_reprs: &[Tid]) -> anyhow::Result<()> {
         let sig = self
             .scc_signatures
@@ -736,6 +799,21 @@ where
         Ok(())
     }
 
+    /// Limits context sensitivity to call strings of length k. The type of a callee at a callsite is normally bound to the
+    /// callee's type in the global graph, so every use of the callee shares it. With a depth of k each callsite is bound
+    /// to its own clone of the callee's type instead, and the callsites within a clone are bound to clones of their own
+    /// callees until the call string reaches k calls, past which they share the type of the callee. A depth of 0 shares
+    /// the callee's type at every callsite.
+    pub fn with_context_depth(mut self, k: usize) -> SCCSketchsBuilder<'a, U, T> {
+        self.context_depth = k;
+        self
+    }
+
+    /// Gets the sketch graph of each scc keyed by the variable of each subprocedure in the scc.
+    pub fn get_scc_sketches(&self) -> &BTreeMap<TypeVariable, Rc<SketchGraph<LatticeBounds<U>>>> {
+        &self.scc_repr
+    }
+
     /// Builds and refines scc sketches by first translating constraint sets to sketches,
     /// then binding polymorphic types when thye can be refiend, and finally binding and refining globals.
     pub fn build(&mut self) -> anyhow::Result<()> {
@@ -743,12 +821,48 @@ where
 
         let sorted = cg_ordering.get_reverse_topo();
 
+        let mut rebuilt: HashSet<NodeIndex> = HashSet::new();
+        self.sketch_cache.last_rebuilt.clear();
         for idx in sorted {
             let associated_tids = &cg_ordering.condensed_cg[idx];
             // condensation shouldnt produce a node that doesnt represent any of the original nodes
             assert!(!associated_tids.is_empty());
 
-            self.build_and_label_scc_sketch(associated_tids)?;
+            let sig = self
+                .scc_signatures
+                .get(&associated_tids[0])
+                .expect("scc should have a sig")
+                .clone();
+            let callee_rebuilt = cg_ordering
+                .condensed_cg
+                .neighbors_directed(idx, Outgoing)
+                .any(|callee| rebuilt.contains(&callee));
+            let cached = self
+                .sketch_cache
+                .sketches
+                .get(associated_tids)
+                .filter(|(cached_sig, _)| !callee_rebuilt && *cached_sig == sig)
+                .map(|(_, sketch)| sketch.clone());
+
+            match cached {
+                Some(sketch) => {
+                    for repr in associated_tids.iter() {
+                        self.scc_repr
+                            .insert(constraint_generation::tid_to_tvar(repr), sketch.clone());
+                    }
+                }
+                None => {
+                    self.build_and_label_scc_sketch(associated_tids)?;
+                    rebuilt.insert(idx);
+                    self.sketch_cache
+                        .last_rebuilt
+                        .extend(associated_tids.iter().cloned());
+                    let sketch = self.scc_repr[&tid_to_tvar(&associated_tids[0])].clone();
+                    self.sketch_cache
+                        .sketches
+                        .insert(associated_tids.clone(), (sig, sketch));
+                }
+            }
         }
 
         self.display_sketches("before_polybind")?;
@@ -762,58 +876,116 @@ where
         Ok(())
     }
 
-    fn copy_built_sketch_into_global(
+    /// Copies the sketch of an scc into the global graph as the instance of the scc reached through a call string. The root
+    /// instance of each scc has an empty call string. Edges into a node aliased to another type point to that type instead.
+    /// Callsites of an instance reached through fewer than context_depth calls are bound to a clone of their callee
+    /// reached through the extended call string, the others to the root instance of the callee.
+    fn copy_scc_instance(
         &self,
         curr_scc: &[Tid],
-        location_to_index: &mut BTreeMap<TypeLocation, NodeIndex>,
+        call_string: &[Callsite],
+        locations: &mut BTreeMap<(Vec<Callsite>, TypeLocation), NodeIndex>,
         resulting_graph: &mut StableDiGraph<LatticeBounds<U>, FieldLabel>,
-        resulting_labeling: &mut HashMap<DerivedTypeVar, NodeIndex>,
-        sg: SketchGraph<LatticeBounds<U>>,
     ) {
+        let sg = self
+            .scc_repr
+            .get(&tid_to_tvar(&curr_scc[0]))
+            .expect("all type var representations should be built")
+            .clone();
+        let scc_loc = |target_path| {
+            TypeLocation::SCCLoc(SCCLocation {
+                scc: curr_scc.to_vec(),
+                target_path,
+            })
+        };
+
         for nd_idx in sg.quotient_graph.get_graph().node_indices() {
             let weight = &sg.quotient_graph.get_graph()[nd_idx];
             let new_idx = resulting_graph.add_node(weight.clone());
-            location_to_index.insert(
-                TypeLocation::SCCLoc(SCCLocation {
-                    scc: curr_scc.to_vec(),
-                    target_path: nd_idx,
-                }),
-                new_idx,
-            );
+            locations.insert((call_string.to_vec(), scc_loc(nd_idx)), new_idx);
         }
 
         for edge in sg.quotient_graph.get_graph().edge_references() {
-            let source_loc = TypeLocation::SCCLoc(SCCLocation {
-                scc: curr_scc.to_vec(),
-                target_path: edge.source(),
-            });
-            // Todo these should probably be shared RC vecs for cheap cloning
-            let maybe_dst_loc = TypeLocation::SCCLoc(SCCLocation {
-                scc: curr_scc.to_vec(),
-                target_path: edge.target(),
-            });
-
-            let dst_loc = if let Some(alias_dst_loc) = self.parameter_aliases.get(&maybe_dst_loc) {
-                alias_dst_loc
+            let maybe_dst_loc = scc_loc(edge.target());
+            let dst_key = if let Some(alias_dst_loc) = self.parameter_aliases.get(&maybe_dst_loc) {
+                (Vec::new(), alias_dst_loc.clone())
+            } else if let Some((tag, callee_loc)) = self.callsite_aliases.get(&maybe_dst_loc) {
+                match callee_loc {
+                    TypeLocation::SCCLoc(callee) if call_string.len() < self.context_depth => {
+                        let mut callee_string = call_string.to_vec();
+                        callee_string.push((curr_scc.to_vec(), tag.clone()));
+                        let key = (callee_string, callee_loc.clone());
+                        if !locations.contains_key(&key) {
+                            self.copy_scc_instance(&callee.scc, &key.0, locations, resulting_graph);
+                            self.bound_clone_by_callsite(
+                                &sg,
+                                tag,
+                                &key.0,
+                                locations,
+                                resulting_graph,
+                            );
+                        }
+                        key
+                    }
+                    _ => (Vec::new(), callee_loc.clone()),
+                }
             } else {
-                &maybe_dst_loc
+                (call_string.to_vec(), maybe_dst_loc)
             };
 
-            let src_idx = location_to_index
-                .get(&source_loc)
+            let src_idx = locations
+                .get(&(call_string.to_vec(), scc_loc(edge.source())))
                 .expect("All graph locations should have been added");
-            let dst_idx = location_to_index
-                .get(dst_loc)
+            let dst_idx = locations
+                .get(&dst_key)
                 .expect("Alias targets should be built before alias due to reverse topo order");
 
             resulting_graph.add_edge(*src_idx, *dst_idx, edge.weight().clone());
         }
+    }
+
+    /// A clone of a callee takes the bounds of the types at its callsite, the callee's own type merges every callsite.
+    fn bound_clone_by_callsite(
+        &self,
+        caller_sketch: &SketchGraph<LatticeBounds<U>>,
+        callsite: &Tid,
+        callee_string: &[Callsite],
+        locations: &BTreeMap<(Vec<Callsite>, TypeLocation), NodeIndex>,
+        resulting_graph: &mut StableDiGraph<LatticeBounds<U>, FieldLabel>,
+    ) {
+        let (caller_scc, _) = callee_string
+            .last()
+            .expect("clones are reached through at least one callsite");
+        for (from, (tag, to)) in self.callsite_aliases.iter() {
+            if let TypeLocation::SCCLoc(from_loc) = from {
+                if tag != callsite || &from_loc.scc != caller_scc {
+                    continue;
+                }
+
+                if let Some(clone_idx) = locations.get(&(callee_string.to_vec(), to.clone())) {
+                    resulting_graph[*clone_idx] =
+                        caller_sketch.quotient_graph.get_graph()[from_loc.target_path].clone();
+                }
+            }
+        }
+    }
+
+    fn label_root_instance(
+        &self,
+        curr_scc: &[Tid],
+        locations: &BTreeMap<(Vec<Callsite>, TypeLocation), NodeIndex>,
+        resulting_labeling: &mut BTreeMap<DerivedTypeVar, NodeIndex>,
+    ) {
+        let sg = self
+            .scc_repr
+            .get(&tid_to_tvar(&curr_scc[0]))
+            .expect("all type var representations should be built");
 
         // resulting labeling is tricky because we could end up referencing the in parameter within a bound type which wont actually have that dtv label since we dont copy them down.
         // We should only label base variables imo. This means we look through the scc and find the sccs base variables within the graph
         for (dtv, tgt_idx) in sg.quotient_graph.get_node_mapping().iter() {
             if dtv.get_field_labels().is_empty()
-                && dtv.get_base_variable().get_cs_tag().is_none()
+                && (self.label_callsites || dtv.get_base_variable().get_cs_tag().is_none())
                 // Dont want labels for concrete types, no need to solve for them if not used.
                 && !self.type_lattice_elements.contains(dtv.get_base_variable())
                 && !dtv.is_global()
@@ -821,11 +993,14 @@ where
             {
                 resulting_labeling.insert(
                     dtv.clone(),
-                    *location_to_index
-                        .get(&TypeLocation::SCCLoc(SCCLocation {
-                            scc: curr_scc.to_vec(),
-                            target_path: *tgt_idx,
-                        }))
+                    *locations
+                        .get(&(
+                            Vec::new(),
+                            TypeLocation::SCCLoc(SCCLocation {
+                                scc: curr_scc.to_vec(),
+                                target_path: *tgt_idx,
+                            }),
+                        ))
                         .expect("All nodes should be added to the location map"),
                 );
             }
@@ -835,8 +1010,8 @@ where
     fn add_globals_to_global_graph(
         &self,
         resulting_graph: &mut StableDiGraph<LatticeBounds<U>, FieldLabel>,
-        labeling: &mut HashMap<DerivedTypeVar, NodeIndex>,
-        location_to_index: &mut BTreeMap<TypeLocation, NodeIndex>,
+        labeling: &mut BTreeMap<DerivedTypeVar, NodeIndex>,
+        location_to_index: &mut BTreeMap<(Vec<Callsite>, TypeLocation), NodeIndex>,
     ) -> anyhow::Result<()> {
         for (tv, (_, repr_sketch)) in self.global_repr.iter() {
             let (entry_node, old_idx_to_new_idx) =
@@ -844,10 +1019,13 @@ where
             labeling.insert(DerivedTypeVar::new(tv.clone()), entry_node);
             for (old_idx, new_idx) in old_idx_to_new_idx {
                 location_to_index.insert(
-                    TypeLocation::GlobalLoc(GlobalLocation {
-                        globvar: tv.clone(),
-                        target_path: old_idx,
-                    }),
+                    (
+                        Vec::new(),
+                        TypeLocation::GlobalLoc(GlobalLocation {
+                            globvar: tv.clone(),
+                            target_path: old_idx,
+                        }),
+                    ),
                     new_idx,
                 );
             }
@@ -857,9 +1035,10 @@ where
 
     /// After binding polymorphic types we can build a singular sketch graph representing all types. The only labels that are preserved are scc internal dtvs
     pub fn build_global_type_graph(&self) -> anyhow::Result<SketchGraph<LatticeBounds<U>>> {
-        let mut location_to_index: BTreeMap<TypeLocation, NodeIndex> = BTreeMap::new();
+        let mut location_to_index: BTreeMap<(Vec<Callsite>, TypeLocation), NodeIndex> =
+            BTreeMap::new();
         let mut resulting_graph: StableDiGraph<LatticeBounds<U>, FieldLabel> = StableDiGraph::new();
-        let mut resulting_labeling: HashMap<DerivedTypeVar, NodeIndex> = HashMap::new();
+        let mut resulting_labeling: BTreeMap<DerivedTypeVar, NodeIndex> = BTreeMap::new();
 
         self.add_globals_to_global_graph(
             &mut resulting_graph,
@@ -873,23 +1052,20 @@ where
 
         for idx in sorted {
             let scc = &condensed_cg.condensed_cg[idx];
-            let built_sg = self.get_built_sketch_from_scc(scc);
-            self.copy_built_sketch_into_global(
-                scc,
-                &mut location_to_index,
-                &mut resulting_graph,
-                &mut resulting_labeling,
-                built_sg,
-            )
+            self.copy_scc_instance(scc, &[], &mut location_to_index, &mut resulting_graph);
+            self.label_root_instance(scc, &location_to_index, &mut resulting_labeling);
         }
 
         let mp = MappingGraph::from_dfa_and_labeling(resulting_graph);
         let mut final_graph = mp.relable_representative_nodes(resulting_labeling);
 
-        assert!(final_graph
-            .get_node_mapping()
-            .iter()
-            .all(|(x, _)| x.get_base_variable().get_cs_tag().is_none()));
+        assert!(
+            self.label_callsites
+                || final_graph
+                    .get_node_mapping()
+                    .iter()
+                    .all(|(x, _)| x.get_base_variable().get_cs_tag().is_none())
+        );
 
         final_graph.remove_nodes_unreachable_from_label();
         Ok(SketchGraph {
@@ -1132,7 +1308,7 @@ where
         target_scc_sketch: &SketchGraph<LatticeBounds<U>>,
         target_dtv: &DerivedTypeVar,
         scc_idx: NodeIndex,
-    ) -> BTreeMap<TypeLocation, TypeLocation> {
+    ) -> BTreeMap<TypeLocation, (Option<Tid>, TypeLocation)> {
         // There should only be one representation of a formal in an SCC
 
         assert_eq!(
@@ -1154,6 +1330,11 @@ where
             .flat_map(|(old_callsite_type, callsite_loc)| {
                 let aliases = Self::find_shared_subgraphs(&old_callsite_type, callee_type)
                     .expect("should be able to compute aliases");
+                let callsite = old_callsite_type
+                    .representing
+                    .get_base_variable()
+                    .get_cs_tag()
+                    .clone();
 
                 aliases
                     .into_iter()
@@ -1180,10 +1361,13 @@ where
                                         scc: from_scc,
                                         target_path: from_node,
                                     }),
-                                    TypeLocation::SCCLoc(SCCLocation {
-                                        scc: repr_scc.to_vec(),
-                                        target_path: repr_node,
-                                    }),
+                                    (
+                                        callsite.clone(),
+                                        TypeLocation::SCCLoc(SCCLocation {
+                                            scc: repr_scc.to_vec(),
+                                            target_path: repr_node,
+                                        }),
+                                    ),
                                 )
                             })
                         })
@@ -1295,7 +1479,7 @@ where
         condensed: &Graph<Vec<Tid>, (), Directed>,
         associated_scc_tids: &[Tid],
         target_idx: NodeIndex,
-    ) -> BTreeMap<TypeLocation, TypeLocation> {
+    ) -> BTreeMap<TypeLocation, (Option<Tid>, TypeLocation)> {
         let orig_repr = self.get_built_sketch_from_scc(associated_scc_tids);
 
         let mut param_aliases = orig_repr
@@ -1323,14 +1507,17 @@ where
                         target_path: *src_idx,
                         scc: associated_scc_tids.to_vec(),
                     }),
-                    TypeLocation::GlobalLoc(GlobalLocation {
-                        globvar: gdtv.get_base_variable().clone(),
-                        target_path: self
-                            .global_repr
-                            .get(gdtv.get_base_variable())
-                            .expect("all global variables should have a representative sketch")
-                            .0,
-                    }),
+                    (
+                        None,
+                        TypeLocation::GlobalLoc(GlobalLocation {
+                            globvar: gdtv.get_base_variable().clone(),
+                            target_path: self
+                                .global_repr
+                                .get(gdtv.get_base_variable())
+                                .expect("all global variables should have a representative sketch")
+                                .0,
+                        }),
+                    ),
                 )
             })
         });
@@ -1460,17 +1647,36 @@ where
 
     fn collect_aliases(&mut self) -> anyhow::Result<()> {
         let ordering = callgraph::CGOrdering::new(&self.cg)?;
-        ordering.iter().for_each(|(ccg, target_scc_idx, scc_tids)| {
-            self.parameter_aliases.extend(
+        let aliases = ordering
+            .iter()
+            .flat_map(|(ccg, target_scc_idx, scc_tids)| {
                 self.collect_aliases_for_scc(ccg, scc_tids, target_scc_idx)
-                    .into_iter(),
-            );
-        });
+            })
+            .collect::<Vec<_>>();
+        for (from, (callsite, to)) in aliases {
+            match callsite {
+                // callsites are bound to clones of the callee when building the global graph
+                Some(callsite) if self.context_depth > 0 => {
+                    self.callsite_aliases.insert(from, (callsite, to));
+                }
+                _ => {
+                    self.parameter_aliases.insert(from, to);
+                }
+            }
+        }
 
         self.save_aliased_types("before_extension")?;
 
         let extended_aliases = self.extend_aliasing_relations(&self.parameter_aliases)?;
         self.parameter_aliases.extend(extended_aliases);
+
+        let mut extended_callsite_aliases = BTreeMap::new();
+        for (from, (callsite, to)) in self.callsite_aliases.iter() {
+            for (implied_from, implied_to) in self.get_implied_aliasing_relations(from, to)? {
+                extended_callsite_aliases.insert(implied_from, (callsite.clone(), implied_to));
+            }
+        }
+        self.callsite_aliases.extend(extended_callsite_aliases);
         Ok(())
     }
 
@@ -1529,6 +1735,7 @@ where
             .log_to_fname(&format!("global_graph_aliases_at_{}", event_time), &|| {
                 self.parameter_aliases
                     .iter()
+                    .chain(self.callsite_aliases.iter().map(|(t1, (_, t2))| (t1, t2)))
                     .map(|(t1, t2)| {
                         format!(
                             "{}:{}",
@@ -1550,7 +1757,7 @@ where
 
     fn insert_global_sketches(
         scc_repr: &TypeVariable,
-        global_sketches: &mut HashMap<TypeVariable, Sketch<LatticeBounds<U>>>,
+        global_sketches: &mut BTreeMap<TypeVariable, Sketch<LatticeBounds<U>>>,
         sg: &SketchGraph<LatticeBounds<U>>,
         debug_dir: &FileDebugLogger,
     ) {
@@ -1611,8 +1818,8 @@ where
     /// Find the sketch representing the type for each global variable.
     pub fn collect_global_instantiations(
         &self,
-    ) -> anyhow::Result<HashMap<TypeVariable, Sketch<LatticeBounds<U>>>> {
-        let mut global_sketches: HashMap<TypeVariable, Sketch<LatticeBounds<U>>> = HashMap::new();
+    ) -> anyhow::Result<BTreeMap<TypeVariable, Sketch<LatticeBounds<U>>>> {
+        let mut global_sketches: BTreeMap<TypeVariable, Sketch<LatticeBounds<U>>> = BTreeMap::new();
 
         self.scc_repr.iter().for_each(|(scc_repr, sg)| {
             Self::insert_global_sketches(scc_repr, &mut global_sketches, sg, &self.debug_dir);
@@ -1693,6 +1900,201 @@ impl<U: std::cmp::PartialEq> SketchGraph<U> {
     }
 }
 
+/// A serializable form of a [SketchGraph] labeled with lattice bounds. Bounds are stored by the name of their lattice elements,
+/// so a checkpoint can only be restored with the lattice it was created with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SketchGraphCheckpoint {
+    default_label: (String, String),
+    nodes: Vec<(String, String)>,
+    edges: Vec<(usize, usize, FieldLabel)>,
+    representations: Vec<(DerivedTypeVar, usize)>,
+}
+
+impl SketchGraphCheckpoint {
+    /// Gets the (lower, upper) lattice bounds of each node by name, indexed by node.
+    pub fn nodes(&self) -> &[(String, String)] {
+        &self.nodes
+    }
+
+    /// Gets the labeled edges between nodes.
+    pub fn edges(&self) -> &[(usize, usize, FieldLabel)] {
+        &self.edges
+    }
+
+    /// Gets the variables represented by each node.
+    pub fn representations(&self) -> &[(DerivedTypeVar, usize)] {
+        &self.representations
+    }
+}
+
+fn bounds_names<U: NamedLatticeElement>(bounds: &LatticeBounds<U>) -> (String, String) {
+    (
+        bounds.get_lower().get_name().to_owned(),
+        bounds.get_upper().get_name().to_owned(),
+    )
+}
+
+fn bounds_from_names<T: NamedLattice<U>, U: NamedLatticeElement>(
+    names: &(String, String),
+    lattice: &T,
+) -> anyhow::Result<LatticeBounds<U>> {
+    let lookup = |name: &str| {
+        lattice
+            .get_elem(name)
+            .ok_or_else(|| anyhow::anyhow!("checkpoint refers to unknown lattice element {}", name))
+    };
+    Ok(LatticeBounds {
+        lower_bound: lookup(&names.0)?,
+        upper_bound: lookup(&names.1)?,
+    })
+}
+
+impl<U: NamedLatticeElement> SketchGraph<LatticeBounds<U>> {
+    /// Converts this graph to a serializable checkpoint. Node indices are compacted, so a restored graph may index its nodes differently.
+    pub fn to_checkpoint(&self) -> SketchGraphCheckpoint {
+        let grph = self.quotient_graph.get_graph();
+        let compact: HashMap<NodeIndex, usize> = grph
+            .node_indices()
+            .enumerate()
+            .map(|(i, idx)| (idx, i))
+            .collect();
+
+        SketchGraphCheckpoint {
+            default_label: bounds_names(&self.default_label),
+            nodes: grph
+                .node_indices()
+                .map(|idx| bounds_names(&grph[idx]))
+                .collect(),
+            edges: grph
+                .edge_references()
+                .map(|e| {
+                    (
+                        compact[&e.source()],
+                        compact[&e.target()],
+                        e.weight().clone(),
+                    )
+                })
+                .collect(),
+            representations: self
+                .quotient_graph
+                .get_node_mapping()
+                .iter()
+                .map(|(dtv, idx)| (dtv.clone(), compact[idx]))
+                .sorted()
+                .collect(),
+        }
+    }
+
+    /// Restores a graph from a checkpoint, looking up the lattice bounds of each node by name in lattice.
+    pub fn from_checkpoint<T: NamedLattice<U>>(
+        checkpoint: &SketchGraphCheckpoint,
+        lattice: &T,
+    ) -> anyhow::Result<SketchGraph<LatticeBounds<U>>> {
+        let mut quotient_graph = MappingGraph::new();
+        let mut indices = Vec::new();
+        for names in checkpoint.nodes.iter() {
+            let bounds = bounds_from_names(names, lattice)?;
+            indices.push(quotient_graph.get_graph_mut().add_node(bounds));
+        }
+
+        let index = |i: usize| {
+            indices
+                .get(i)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("checkpoint refers to missing node {}", i))
+        };
+        for (src, dst, lbl) in checkpoint.edges.iter() {
+            quotient_graph
+                .get_graph_mut()
+                .add_edge(index(*src)?, index(*dst)?, lbl.clone());
+        }
+
+        let mut mapping = BTreeMap::new();
+        for (dtv, i) in checkpoint.representations.iter() {
+            mapping.insert(dtv.clone(), index(*i)?);
+        }
+
+        Ok(SketchGraph {
+            quotient_graph: quotient_graph.relable_representative_nodes(mapping),
+            default_label: bounds_from_names(&checkpoint.default_label, lattice)?,
+        })
+    }
+}
+
+/// A human readable listing of the label paths of variables in a [SketchGraph] with the lattice bounds at the end of each
+/// path, ie. `sub_401000.in_0.load.σ32@8 : int ⊑ τ ⊑ num`. Paths are explored breadth first, and a node reached again,
+/// including through a recursive type, is listed as equal to the first path that reached it.
+pub struct SketchPaths<'a, U: std::cmp::PartialEq> {
+    graph: &'a SketchGraph<U>,
+    roots: Vec<(DerivedTypeVar, NodeIndex)>,
+}
+
+impl<'a, T> Display for SketchPaths<'a, LatticeBounds<T>>
+where
+    T: Display + Clone + Lattice,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let grph = self.graph.quotient_graph.get_graph();
+        for (root, root_idx) in self.roots.iter() {
+            let mut first_paths = HashMap::new();
+            first_paths.insert(*root_idx, root.to_string());
+            let mut worklist = VecDeque::from(vec![(*root_idx, root.to_string())]);
+            while let Some((nd, path)) = worklist.pop_front() {
+                writeln!(
+                    f,
+                    "{} : {} ⊑ τ ⊑ {}",
+                    path,
+                    grph[nd].get_lower(),
+                    grph[nd].get_upper()
+                )?;
+
+                for e in grph
+                    .edges_directed(nd, Outgoing)
+                    .sorted_by_key(|e| e.weight().clone())
+                {
+                    let child_path = format!("{}.{}", path, e.weight());
+                    match first_paths.get(&e.target()) {
+                        Some(first) => writeln!(f, "{} = {}", child_path, first)?,
+                        None => {
+                            first_paths.insert(e.target(), child_path.clone());
+                            worklist.push_back((e.target(), child_path));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<U: std::cmp::PartialEq> SketchGraph<U> {
+    /// Lists the paths of every variable without field labels that is represented in this graph.
+    pub fn paths(&self) -> SketchPaths<U> {
+        SketchPaths {
+            graph: self,
+            roots: self
+                .quotient_graph
+                .get_node_mapping()
+                .iter()
+                .filter(|(dtv, _)| dtv.get_field_labels().is_empty())
+                .map(|(dtv, idx)| (dtv.clone(), *idx))
+                .collect(),
+        }
+    }
+
+    /// Lists the paths of a single variable, which lists nothing if the variable is not represented in this graph.
+    pub fn paths_of(&self, dtv: &DerivedTypeVar) -> SketchPaths<U> {
+        SketchPaths {
+            graph: self,
+            roots: self
+                .get_node_index_for_variable(dtv)
+                .map(|idx| (dtv.clone(), idx))
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
 impl<U: Display + Clone + std::cmp::PartialEq + AbstractMagma<Additive>> SketchGraph<U> {
     fn replace_dtv(&mut self, dtv: &DerivedTypeVar, sketch: Sketch<U>) {
         self.quotient_graph
@@ -1817,7 +2219,7 @@ impl<U: std::cmp::PartialEq + Clone> Sketch<U> {
             root.map(|root| {
                 // TODO(Ian) this is so hacky but we only rely on entry computation for structural equality so this is ok ish.
                 let dummy_dtv = DerivedTypeVar::new(TypeVariable::new("dummy".to_owned()));
-                let mut new_mapping = HashMap::new();
+                let mut new_mapping = BTreeMap::new();
                 new_mapping.insert(dummy_dtv.clone(), root);
                 let relab = subgraph.relable_representative_nodes(new_mapping);
 
@@ -1869,7 +2271,7 @@ impl<U: std::cmp::PartialEq + Clone> Sketch<U> {
     /// Also copies the repr.
     // We can actually label without caring about the node weights
     pub fn label_dtvs<V: std::cmp::PartialEq>(&mut self, other_sketch: &Sketch<V>) {
-        let mapping: HashMap<DerivedTypeVar, NodeIndex> =
+        let mapping: BTreeMap<DerivedTypeVar, NodeIndex> =
             explore_paths(self.quotient_graph.get_graph(), self.get_entry())
                 .filter_map(|(pth, tgt)| {
                     let pth_as_weights = pth
@@ -2051,7 +2453,7 @@ impl<U: std::cmp::PartialEq + Clone + Lattice + AbstractMagma<Additive> + Displa
         }
 
         let relab = weight_mapping
-            .relable_representative_nodes(HashMap::from([(self.representing.clone(), entry)]));
+            .relable_representative_nodes(BTreeMap::from([(self.representing.clone(), entry)]));
         // At this point we have a new graph but it's not guarenteed to be a DFA so the last thing to do is quotient it.
         // We dont need to make anything equal via constraints that's already done, we just let edges sets do the work
         let quot_groups = generate_quotient_groups::<U>(
@@ -2533,7 +2935,11 @@ mod test {
     };
 
     use cwe_checker_lib::intermediate_representation::Tid;
-    use petgraph::{graph::DiGraph, visit::EdgeRef, EdgeDirection::Outgoing};
+    use petgraph::{
+        graph::{DiGraph, NodeIndex},
+        visit::EdgeRef,
+        EdgeDirection::Outgoing,
+    };
 
     use crate::{
         analysis::callgraph::CallGraph,
@@ -2550,10 +2956,14 @@ mod test {
                 NamedLatticeElement,
             },
         },
+        test_utils::sketches::{int_lattice, sketch_from_json},
         util::{constraint_set_to_subtys, FileDebugLogger},
     };
 
-    use super::{insert_dtv, LatticeBounds, SCCSketchsBuilder, Sketch, SketchBuilder};
+    use super::{
+        insert_dtv, LatticeBounds, SCCSketchsBuilder, Sketch, SketchBuilder, SketchGraph,
+        SketchGraphCheckpoint,
+    };
 
     fn test_data_dir<P: AsRef<Path>>(pth: P) -> String {
         let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -2809,6 +3219,123 @@ mod test {
         assert_eq!(target.upper_bound.get_name(), "char");
     }
 
+    #[test]
+    fn context_sensitive_callsites_are_bound_to_clones_of_callee() {
+        init();
+        let ids_tid = Tid::create("sub_id".to_owned(), "0x1000".to_owned());
+        let caller1_tid = Tid::create("sub_caller1".to_owned(), "0x3000".to_owned());
+        let caller2_tid = Tid::create("sub_caller2".to_owned(), "0x4000".to_owned());
+
+        let lat = LatticeDefinition::new(
+            vec![
+                ("char".to_owned(), "bytetype".to_owned()),
+                ("int".to_owned(), "bytetype".to_owned()),
+                ("bottom".to_owned(), "char".to_owned()),
+                ("bottom".to_owned(), "int".to_owned()),
+                ("bytetype".to_owned(), "top".to_owned()),
+            ],
+            "top".to_owned(),
+            "bottom".to_owned(),
+            "int".to_owned(),
+        )
+        .generate_lattice();
+        let nd_set = lat
+            .get_nds()
+            .iter()
+            .map(|x| TypeVariable::new(x.0.clone()))
+            .collect::<HashSet<TypeVariable>>();
+
+        // the node reached by the in_0 edge of each function in the global graph
+        let in_params_with_depth = |k: usize| {
+            let mut cg: CallGraph = DiGraph::new();
+            let id_node = cg.add_node(ids_tid.clone());
+            let c1_node = cg.add_node(caller1_tid.clone());
+            let c2_node = cg.add_node(caller2_tid.clone());
+            cg.add_edge(c1_node, id_node, ());
+            cg.add_edge(c2_node, id_node, ());
+
+            let mut skb = SCCSketchsBuilder::new(
+                cg,
+                vec![
+                    SCCConstraints {
+                        constraints: parse_cons_set("sub_id.in_0 <= sub_id.out"),
+                        scc: vec![ids_tid.clone()],
+                    },
+                    SCCConstraints {
+                        constraints: parse_cons_set(
+                            "
+                        sub_caller1.in_0 <= sub_id:0.in_0
+                        sub_id:0.out <= sub_caller1.out
+                        sub_caller1.in_0.load <= char
+                        ",
+                        ),
+                        scc: vec![caller1_tid.clone()],
+                    },
+                    SCCConstraints {
+                        constraints: parse_cons_set(
+                            "
+                        sub_caller2.in_0 <= sub_id:0.in_0
+                        sub_id:0.out <= sub_caller2.out
+                        sub_caller2.in_0.load <= int
+                        ",
+                        ),
+                        scc: vec![caller2_tid.clone()],
+                    },
+                ],
+                &lat,
+                nd_set.clone(),
+                FileDebugLogger::default(),
+            )
+            .with_context_depth(k);
+            skb.build().expect("Should succeed in building sketch");
+            let global_graph = skb
+                .build_global_type_graph()
+                .expect("Global graph should build");
+
+            let in_param = |name: &str| {
+                let grph = global_graph.get_graph();
+                let root = *grph
+                    .get_node_mapping()
+                    .get(&DerivedTypeVar::new(TypeVariable::new(name.to_owned())))
+                    .expect("functions should be labelled");
+                let in_edge = grph
+                    .get_graph()
+                    .edges_directed(root, Outgoing)
+                    .find(|e| matches!(e.weight(), FieldLabel::In(0)))
+                    .expect("functions should have an in_0 parameter");
+                in_edge.target()
+            };
+            let load_bound = |nd: NodeIndex| {
+                let grph = global_graph.get_graph().get_graph();
+                grph.edges_directed(nd, Outgoing)
+                    .find(|e| matches!(e.weight(), FieldLabel::Load))
+                    .map(|e| grph[e.target()].upper_bound.get_name().to_owned())
+            };
+
+            ["sub_caller1", "sub_caller2", "sub_id"]
+                .iter()
+                .map(|name| {
+                    let nd = in_param(name);
+                    (nd, load_bound(nd))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // without context every callsite shares the callee's type
+        let shared = in_params_with_depth(0);
+        assert_eq!(shared[0].0, shared[2].0);
+        assert_eq!(shared[1].0, shared[2].0);
+
+        // each callsite gets its own clone bounded by the types known at the callsite
+        let cloned = in_params_with_depth(1);
+        assert_ne!(cloned[0].0, cloned[1].0);
+        assert_ne!(cloned[0].0, cloned[2].0);
+        assert_ne!(cloned[1].0, cloned[2].0);
+        assert_eq!(cloned[0].1.as_deref(), Some("char"));
+        assert_eq!(cloned[1].1.as_deref(), Some("int"));
+        assert_eq!(cloned[2].1, shared[2].1);
+    }
+
     #[test]
     fn test_intersected_pointer_should_be_applied_to_callee() {
         init();
@@ -3342,6 +3869,42 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_sketch_graph_checkpoint_roundtrip() {
+        let (lat, nd_set) = generate_simple_test_lattice_and_elems();
+        let add_new_var = |dtv: &DerivedTypeVar,
+                           mpgrph: &mut MappingGraph<
+            LatticeBounds<CustomLatticeElement>,
+            DerivedTypeVar,
+            FieldLabel,
+        >| {
+            insert_dtv(&lat, mpgrph, dtv.clone());
+            Ok(())
+        };
+        let skb = SketchBuilder::new(&lat, &nd_set, &add_new_var, FileDebugLogger::default());
+
+        let cons = parse_cons_set(
+            "
+        y <= x
+        x.load.σ64@0 <= int
+        x.load.σ64@8 <= x
+        ",
+        );
+        let sketch = skb
+            .build_without_pointer_simplification(None, &cons)
+            .expect("Should be able to build");
+
+        let checkpoint = sketch.to_checkpoint();
+        let encoded = serde_json::to_string(&checkpoint).unwrap();
+        let restored = SketchGraph::from_checkpoint(
+            &serde_json::from_str::<SketchGraphCheckpoint>(&encoded).unwrap(),
+            &lat,
+        )
+        .unwrap();
+
+        assert_eq!(restored.to_checkpoint(), checkpoint);
+    }
+
     #[test]
     fn test_intersection_with_nonnode_self_preserves_lat_elem() {
         let (lat, _nd_set) = generate_simple_test_lattice_and_elems();
@@ -3462,4 +4025,29 @@ mod test {
         let missing_lang = expected_sketch.difference(&actual_sketch);
         assert!(!missing_lang.empty_language_or_epsilon());
     }
+
+    #[test]
+    fn paths_list_bounds_and_recursion() {
+        let lattice = int_lattice();
+        let list = DerivedTypeVar::new(TypeVariable::new("sub_a".to_owned()));
+        // sub_a.in_0 is a pointer to a node with an int at offset 8 and a pointer back to itself at offset 0
+        let grph = sketch_from_json(
+            &lattice,
+            r#"[["bottom","top"],["bottom","top"],["bottom","top"],["bottom","int"]]"#,
+            r#"[[0,1,{"In":0}],[1,2,"Load"],[2,3,{"Field":{"offset":8,"size":32}}],[2,1,{"Field":{"offset":0,"size":64}}]]"#,
+            &[(list.clone(), 0)],
+        );
+
+        let expected = "sub_a : bottom ⊑ τ ⊑ top\n\
+                        sub_a.in_0 : bottom ⊑ τ ⊑ top\n\
+                        sub_a.in_0.load : bottom ⊑ τ ⊑ top\n\
+                        sub_a.in_0.load.σ64@0 = sub_a.in_0\n\
+                        sub_a.in_0.load.σ32@8 : bottom ⊑ τ ⊑ int\n";
+        assert_eq!(grph.paths().to_string(), expected);
+        assert_eq!(grph.paths_of(&list).to_string(), expected);
+        assert!(grph
+            .paths_of(&DerivedTypeVar::new(TypeVariable::new("sub_b".to_owned())))
+            .to_string()
+            .is_empty());
+    }
 }
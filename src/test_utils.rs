@@ -1,7 +1,16 @@
 use std::path::PathBuf;
 use std::vec::Vec;
 
-fn get_test_data_path(fname: &str) -> PathBuf {
+/// Compares the lowered types of full pipeline runs against stored snapshots.
+pub mod golden;
+
+/// Random well formed constraint sets and a reference closure for checking the solver against.
+pub mod constraint_gen;
+
+/// Small lattices and sketch graphs restored from checkpoint json, for tests that need a solved sketch.
+pub mod sketches;
+
+pub fn get_test_data_path(fname: &str) -> PathBuf {
     let mut pbuf = PathBuf::new();
     pbuf.push(env!("CARGO_MANIFEST_DIR"));
     pbuf.push("test_data");
@@ -0,0 +1,253 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use proptest::{collection, prelude::*};
+
+use crate::{
+    constraints::{
+        ConstraintSet, DerivedTypeVar, Field, FieldLabel, SubtypeConstraint, TyConstraint,
+        TypeVariable, VariableManager, Variance,
+    },
+    solver::constraint_graph::{RuleContext, FSA},
+    util::FileDebugLogger,
+};
+
+/// The number of variables generated sets draw from. The first [NUM_INTERESTING] are interesting.
+pub const NUM_VARIABLES: usize = 4;
+
+/// The number of interesting variables in generated sets.
+pub const NUM_INTERESTING: usize = 2;
+
+/// Gets the name of the ith generated variable.
+pub fn variable(idx: usize) -> TypeVariable {
+    TypeVariable::new(format!("v{}", idx))
+}
+
+/// The interesting variables of generated sets.
+pub fn interesting_variables() -> BTreeSet<TypeVariable> {
+    (0..NUM_INTERESTING).map(variable).collect()
+}
+
+/// Generates field labels of every variance. Additions are not generated since they are removed before solving.
+pub fn field_label() -> impl Strategy<Value = FieldLabel> {
+    prop_oneof![
+        Just(FieldLabel::Load),
+        Just(FieldLabel::Store),
+        (0..2usize).prop_map(FieldLabel::In),
+        Just(FieldLabel::Out(0)),
+        (0..2i64).prop_map(|idx| FieldLabel::Field(Field::new(idx * 4, 32))),
+    ]
+}
+
+/// Generates derived type variables with at most max_depth labels.
+pub fn derived_type_var(max_depth: usize) -> impl Strategy<Value = DerivedTypeVar> {
+    (
+        0..NUM_VARIABLES,
+        collection::vec(field_label(), 0..=max_depth),
+    )
+        .prop_map(|(idx, labels)| DerivedTypeVar::create_with_path(variable(idx), labels))
+}
+
+/// Generates constraint sets of up to max_constraints non reflexive subtyping constraints.
+pub fn constraint_set(
+    max_depth: usize,
+    max_constraints: usize,
+) -> impl Strategy<Value = ConstraintSet> {
+    collection::btree_set(
+        (derived_type_var(max_depth), derived_type_var(max_depth))
+            .prop_filter("reflexive constraints are trivial", |(lhs, rhs)| lhs != rhs)
+            .prop_map(|(lhs, rhs)| TyConstraint::SubTy(SubtypeConstraint::new(lhs, rhs))),
+        1..=max_constraints,
+    )
+    .prop_map(ConstraintSet::from)
+}
+
+fn with_label(dtv: &DerivedTypeVar, lbl: &FieldLabel) -> DerivedTypeVar {
+    let mut dtv = dtv.clone();
+    dtv.add_field_label(lbl.clone());
+    dtv
+}
+
+fn prefixes(dtv: &DerivedTypeVar) -> impl Iterator<Item = DerivedTypeVar> + '_ {
+    (0..=dtv.get_field_labels().len()).map(move |len| {
+        DerivedTypeVar::create_with_path(
+            dtv.get_base_variable().clone(),
+            dtv.get_field_labels()[..len].to_vec(),
+        )
+    })
+}
+
+/// Computes the subtyping constraints entailed by a constraint set under transitivity, the field rules, and the pointer rule,
+/// over derived type variables with at most max_depth labels. This is a slow but direct reading of the inference rules that
+/// the solver is checked against.
+pub fn entailed_constraints(cons: &ConstraintSet, max_depth: usize) -> BTreeSet<SubtypeConstraint> {
+    let mut closure: BTreeSet<(DerivedTypeVar, DerivedTypeVar)> = cons
+        .iter()
+        .filter_map(|c| match c {
+            TyConstraint::SubTy(sub) => Some((sub.lhs.clone(), sub.rhs.clone())),
+            _ => None,
+        })
+        .collect();
+    let mut exists: BTreeSet<DerivedTypeVar> = closure
+        .iter()
+        .flat_map(|(lhs, rhs)| prefixes(lhs).chain(prefixes(rhs)).collect::<Vec<_>>())
+        .collect();
+
+    loop {
+        let prev_size = (closure.len(), exists.len());
+
+        // capabilities of a dtv are inherited by its subtypes and supertypes, and the field rules relate them
+        let mut labels: BTreeMap<DerivedTypeVar, BTreeSet<FieldLabel>> = BTreeMap::new();
+        for dtv in exists.iter() {
+            if let Some((lbl, parent)) = dtv.get_field_labels().split_last() {
+                labels
+                    .entry(DerivedTypeVar::create_with_path(
+                        dtv.get_base_variable().clone(),
+                        parent.to_vec(),
+                    ))
+                    .or_default()
+                    .insert(lbl.clone());
+            }
+        }
+
+        for (lhs, rhs) in closure.clone().iter() {
+            if lhs
+                .get_field_labels()
+                .len()
+                .max(rhs.get_field_labels().len())
+                >= max_depth
+            {
+                continue;
+            }
+
+            let shared = labels
+                .get(lhs)
+                .into_iter()
+                .chain(labels.get(rhs).into_iter())
+                .flatten()
+                .cloned()
+                .collect::<BTreeSet<_>>();
+            for lbl in shared {
+                let (lhs_l, rhs_l) = (with_label(lhs, &lbl), with_label(rhs, &lbl));
+                exists.insert(lhs_l.clone());
+                exists.insert(rhs_l.clone());
+                match lbl.variance() {
+                    Variance::Covariant => closure.insert((lhs_l, rhs_l)),
+                    Variance::Contravariant => closure.insert((rhs_l, lhs_l)),
+                };
+            }
+        }
+
+        for (dtv, lbls) in labels.iter() {
+            if lbls.contains(&FieldLabel::Load) && lbls.contains(&FieldLabel::Store) {
+                closure.insert((
+                    with_label(dtv, &FieldLabel::Store),
+                    with_label(dtv, &FieldLabel::Load),
+                ));
+            }
+        }
+
+        let mut succs: BTreeMap<DerivedTypeVar, BTreeSet<DerivedTypeVar>> = BTreeMap::new();
+        for (lhs, rhs) in closure.iter() {
+            succs.entry(lhs.clone()).or_default().insert(rhs.clone());
+        }
+        for (lhs, rhs) in closure.clone().iter() {
+            if let Some(nexts) = succs.get(rhs) {
+                for next in nexts.iter() {
+                    if next != lhs {
+                        closure.insert((lhs.clone(), next.clone()));
+                    }
+                }
+            }
+        }
+
+        if prev_size == (closure.len(), exists.len()) {
+            break;
+        }
+    }
+
+    closure
+        .into_iter()
+        .map(|(lhs, rhs)| SubtypeConstraint::new(lhs, rhs))
+        .collect()
+}
+
+/// Simplifies a constraint set with respect to the interesting variables using the default pass pipeline.
+pub fn simplify(cons: &ConstraintSet, interesting: &BTreeSet<TypeVariable>) -> ConstraintSet {
+    let mut fsa = FSA::new(cons, &RuleContext::new(interesting.clone()))
+        .expect("should build fsa for generated constraints");
+    fsa.simplify_graph(
+        "",
+        &mut FileDebugLogger::default(),
+        &mut VariableManager::new(),
+    )
+    .expect("simplification should not fail");
+    fsa.walk_constraints()
+}
+
+/// Checks that every constraint the solver derives between the interesting variables is entailed by the original set.
+/// Only constraints between derived type variables with at most max_depth labels are checked, constraints on fresh
+/// recursive type variables are skipped. Returns the first constraint that is not entailed.
+pub fn check_simplification_sound(
+    cons: &ConstraintSet,
+    max_depth: usize,
+) -> Result<(), SubtypeConstraint> {
+    let interesting = interesting_variables();
+    // derivations may pass through derived variables longer than the ones being checked
+    let entailed = entailed_constraints(cons, max_depth + 2);
+
+    for simplified in simplify(cons, &interesting).iter() {
+        if let TyConstraint::SubTy(sub) = simplified {
+            let checkable = [&sub.lhs, &sub.rhs].iter().all(|dtv| {
+                interesting.contains(dtv.get_base_variable())
+                    && dtv.get_field_labels().len() <= max_depth
+            });
+            if checkable && sub.lhs != sub.rhs && !entailed.contains(sub) {
+                return Err(sub.clone());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use crate::constraints::{self, DerivedTypeVar, SubtypeConstraint};
+
+    use super::{check_simplification_sound, constraint_set, entailed_constraints, variable};
+
+    #[test]
+    fn closure_applies_field_and_pointer_rules() {
+        let (_, cons) = constraints::parse_constraint_set(
+            "
+            v0 <= v2
+            v2.store <= v1
+            v2.load <= v1
+            ",
+        )
+        .unwrap();
+
+        let entailed = entailed_constraints(&cons, 2);
+        let v0_store =
+            DerivedTypeVar::create_with_path(variable(0), vec![constraints::FieldLabel::Store]);
+        let v2_store =
+            DerivedTypeVar::create_with_path(variable(2), vec![constraints::FieldLabel::Store]);
+        let v2_load =
+            DerivedTypeVar::create_with_path(variable(2), vec![constraints::FieldLabel::Load]);
+        assert!(entailed.contains(&SubtypeConstraint::new(v2_store.clone(), v0_store)));
+        assert!(entailed.contains(&SubtypeConstraint::new(v2_store, v2_load)));
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn simplification_only_derives_entailed_constraints(cons in constraint_set(2, 6)) {
+            if let Err(unentailed) = check_simplification_sound(&cons, 2) {
+                prop_assert!(false, "{} is not entailed by\n{}", unentailed, cons);
+            }
+        }
+    }
+}
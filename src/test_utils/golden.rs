@@ -0,0 +1,177 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::{
+    constraints::AdditionalConstraint,
+    inference_job::{InferenceJob, InferenceParsing, JobDefinition},
+    lowering::{naming::TypeNamer, CType, TypeId},
+    solver::{
+        type_lattice::CustomLatticeElement,
+        type_sketch::{LatticeBounds, SketchGraph},
+    },
+};
+use cwe_checker_lib::intermediate_representation::Tid;
+use petgraph::graph::NodeIndex;
+use pretty_assertions::assert_eq;
+
+use super::get_test_data_path;
+
+/// Setting this environment variable rewrites the snapshots with the current outputs instead of comparing against them.
+pub const UPDATE_GOLDEN_VAR: &str = "BTI_UPDATE_GOLDEN";
+
+/// A fixture that is run through the full pipeline. Paths are relative to the test data directory.
+pub struct GoldenCase {
+    /// The name of the snapshot file
+    pub name: &'static str,
+    /// The binary
+    pub binary: &'static str,
+    /// The Pcode json IR of the binary
+    pub ir_json: &'static str,
+    /// The lattice json
+    pub lattice_json: &'static str,
+    /// The additional constraints
+    pub additional_constraints: &'static str,
+    /// The tids to type
+    pub interesting_tids: &'static str,
+}
+
+/// A small program that checks a discriminant before accessing either member of a union, solved with the list lattice.
+pub const TAGGED_UNION: GoldenCase = GoldenCase {
+    name: "tagged_union",
+    binary: "tagged_union/tagged_union",
+    ir_json: "tagged_union/ir.json",
+    lattice_json: "list_test_lattice.json",
+    additional_constraints: "tagged_union/additional_constraints.json",
+    interesting_tids: "tagged_union/interesting_tids.json",
+};
+
+fn snapshot_path(name: &str) -> PathBuf {
+    let mut pbuf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    pbuf.push("expected_output");
+    pbuf.push("golden");
+    pbuf.push(format!("{}.json", name));
+    pbuf
+}
+
+fn test_data_string(fname: &str) -> String {
+    get_test_data_path(fname).to_string_lossy().into_owned()
+}
+
+/// The sketch graph a case was solved to, the type each node was lowered to, and the lowered types.
+pub type InferredCase = (
+    SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    BTreeMap<NodeIndex, TypeId>,
+    BTreeMap<TypeId, CType>,
+);
+
+/// Runs the full pipeline on the case, after configure sets up the job, and returns the job with the types it inferred.
+pub fn infer_case<P>(
+    case: &GoldenCase,
+    configure: impl FnOnce(&mut InferenceJob),
+) -> (InferenceJob, InferredCase)
+where
+    P: InferenceParsing<AdditionalConstraint> + InferenceParsing<Tid>,
+{
+    let def = JobDefinition {
+        binary_path: test_data_string(case.binary),
+        ir_json_path: test_data_string(case.ir_json),
+        lattice_json: test_data_string(case.lattice_json),
+        additional_constraints_file: test_data_string(case.additional_constraints),
+        interesting_tids: test_data_string(case.interesting_tids),
+        bare_metal_layout: None,
+    };
+
+    let mut job =
+        InferenceJob::parse::<P>(&def, std::env::var("BTI_DEBUG_DIR").ok(), vec![], false)
+            .expect("should parse golden case");
+    configure(&mut job);
+    let (grph, (node_to_type_id, types)) = job.infer_ctypes().expect("should infer types");
+    (job, (grph, node_to_type_id, types))
+}
+
+/// Runs the full pipeline on the case and describes the lowered type of each interesting tid. Descriptions do not depend on
+/// type ids or node indices, so the snapshot only changes when the inferred types do.
+pub fn lowered_snapshot<P>(case: &GoldenCase) -> BTreeMap<String, String>
+where
+    P: InferenceParsing<AdditionalConstraint> + InferenceParsing<Tid>,
+{
+    let (job, (grph, node_to_type_id, types)) = infer_case::<P>(case, |_| ());
+    let namer = TypeNamer::new(&types, &node_to_type_id);
+
+    job.get_graph_labeling(&grph)
+        .into_iter()
+        .filter_map(|(tid, idx)| {
+            node_to_type_id.get(&idx).map(|ty| {
+                (
+                    tid.get_str_repr().to_owned(),
+                    namer.structural_description(*ty),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Compares the lowered types of a case against its snapshot in expected_output/golden. If [UPDATE_GOLDEN_VAR] is set, the
+/// snapshot is written instead. A missing snapshot fails the comparison, so a case is never checked against nothing.
+pub fn check_golden<P>(case: &GoldenCase)
+where
+    P: InferenceParsing<AdditionalConstraint> + InferenceParsing<Tid>,
+{
+    let actual = lowered_snapshot::<P>(case);
+    let pth = snapshot_path(case.name);
+
+    if std::env::var(UPDATE_GOLDEN_VAR).is_ok() {
+        std::fs::create_dir_all(pth.parent().expect("snapshots are in a directory")).unwrap();
+        let mut serialized = serde_json::to_string_pretty(&actual).unwrap();
+        serialized.push('\n');
+        std::fs::write(&pth, serialized).unwrap();
+        log::warn!("Wrote golden snapshot {}", pth.display());
+        return;
+    }
+
+    assert!(
+        pth.exists(),
+        "missing golden snapshot {}, run with {} set to record it",
+        pth.display(),
+        UPDATE_GOLDEN_VAR
+    );
+    let expected: BTreeMap<String, String> =
+        serde_json::from_reader(std::fs::File::open(&pth).unwrap()).unwrap();
+    assert_eq!(
+        expected,
+        actual,
+        "lowered types differ from {}, rerun with {} set if the change is intended",
+        pth.display(),
+        UPDATE_GOLDEN_VAR
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use crate::inference_job::{JsonDef, ProtobufDef};
+
+    use super::{check_golden, GoldenCase};
+
+    #[test]
+    fn golden_mooosl() {
+        check_golden::<ProtobufDef>(&GoldenCase {
+            name: "mooosl",
+            binary: "mooosl",
+            ir_json: "mooosl_artifacts/ir.json",
+            lattice_json: "mooosl_artifacts/lattice.json",
+            additional_constraints: "mooosl_artifacts/additional_constraints.pb",
+            interesting_tids: "mooosl_artifacts/interesting_tids.pb",
+        });
+    }
+
+    #[test]
+    fn golden_cwe_560() {
+        check_golden::<JsonDef>(&GoldenCase {
+            name: "cwe_560",
+            binary: "cwe_560_aarch64_gcc.out",
+            ir_json: "cwe_560_aarch64_gcc_ir.json",
+            lattice_json: "list_test_lattice.json",
+            additional_constraints: "cwe_560_golden/additional_constraints.json",
+            interesting_tids: "cwe_560_golden/interesting_tids.json",
+        });
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    constraints::{DerivedTypeVar, TypeVariable},
+    lowering::{LoweringContext, LoweringOptions},
+    solver::{
+        type_lattice::{CustomLatticeElement, EnumeratedNamedLattice, LatticeDefinition},
+        type_sketch::{identity_element, LatticeBounds, SketchGraph, SketchGraphCheckpoint},
+    },
+};
+
+/// A lattice where each of the given primitives sits directly between `bottom` and `top`. The first primitive is the
+/// weakest integer.
+pub fn flat_lattice(primitives: &[&str]) -> EnumeratedNamedLattice {
+    let mut order = Vec::new();
+    for prim in primitives {
+        order.push(((*prim).to_owned(), "top".to_owned()));
+        order.push(("bottom".to_owned(), (*prim).to_owned()));
+    }
+    LatticeDefinition::new(
+        order,
+        "top".to_owned(),
+        "bottom".to_owned(),
+        primitives[0].to_owned(),
+    )
+    .generate_lattice()
+}
+
+/// The lattice with a single `int` between `bottom` and `top`.
+pub fn int_lattice() -> EnumeratedNamedLattice {
+    flat_lattice(&["int"])
+}
+
+/// A derived type variable for a plain variable name.
+pub fn var(name: &str) -> DerivedTypeVar {
+    DerivedTypeVar::new(TypeVariable::new(name.to_owned()))
+}
+
+/// Restores a sketch graph from the nodes and edges of a checkpoint, written as json, where each variable represents
+/// the node at the paired index. The default label is `bottom ⊑ τ ⊑ top`.
+pub fn sketch_from_json(
+    lattice: &EnumeratedNamedLattice,
+    nodes: &str,
+    edges: &str,
+    representations: &[(DerivedTypeVar, usize)],
+) -> SketchGraph<LatticeBounds<CustomLatticeElement>> {
+    let checkpoint: SketchGraphCheckpoint = serde_json::from_str(&format!(
+        r#"{{"default_label":["bottom","top"],"nodes":{},"edges":{},"representations":{}}}"#,
+        nodes,
+        edges,
+        serde_json::to_string(representations).unwrap()
+    ))
+    .unwrap();
+    SketchGraph::from_checkpoint(&checkpoint, lattice).unwrap()
+}
+
+/// A context lowering a sketch graph of the lattice with the given options, without terms or out parameters. Unlabeled
+/// nodes get the identity element of the lattice.
+pub fn lower<'a>(
+    grph: &'a SketchGraph<LatticeBounds<CustomLatticeElement>>,
+    lattice: &EnumeratedNamedLattice,
+    options: LoweringOptions,
+) -> LoweringContext<'a, CustomLatticeElement> {
+    LoweringContext::new(
+        grph,
+        &BTreeMap::new(),
+        &HashMap::new(),
+        identity_element(lattice),
+        options,
+    )
+}
@@ -1,32 +1,58 @@
 use anyhow::Result;
 use cwe_checker_lib::{
-    intermediate_representation::{Project, Tid},
-    utils::log::{LogLevel, LogMessage},
+    intermediate_representation::{Project, RuntimeMemoryImage, Tid},
+    utils::{
+        binary::BareMetalConfig,
+        log::{LogLevel, LogMessage},
+    },
 };
 use log::{debug, error, info};
+use serde::{
+    de::{DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_json::Value;
 use std::{
+    borrow::Cow,
     collections::{BTreeSet, HashMap},
-    fmt::Display,
+    fmt::{self, Display},
     io::Read,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use crate::{
     constraint_generation,
     constraints::{ConstraintSet, SubtypeConstraint, TyConstraint, TypeVariable},
+    diagnostics::{self, DiagnosticKind},
 };
 
+/// Gets the message a panic was raised with.
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|msg| msg.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panicked without a message".to_owned())
+}
+
 /// Convert cwe logs into our logging infra
 pub fn log_cwe_message(msg: &LogMessage) {
     match msg.level {
-        LogLevel::Error => error!("{}", msg.text),
+        LogLevel::Error => {
+            diagnostics::report_at(
+                DiagnosticKind::CweChecker,
+                msg.location.as_ref(),
+                msg.text.clone(),
+            );
+            error!("{}", msg.text)
+        }
         LogLevel::Info => info!("{}", msg.text),
         LogLevel::Debug => debug!("{}", msg.text),
     }
 }
 
 /// Gets the [Project] IR for a reader of exported JSON IR and the binary as a slice of bytes. This function does not
-/// handle bare metal binaries.
+/// handle bare metal binaries, see [get_intermediate_representation_for_bare_metal_reader].
 pub fn get_intermediate_representation_for_reader(
     rdr: impl Read,
     binary: &[u8],
@@ -41,6 +67,274 @@ pub fn get_intermediate_representation_for_reader(
     Ok(ir)
 }
 
+// The location of the subprocedure array in exported JSON IR
+const SUBS_PATH: &[&str] = &["program", "term", "subs"];
+
+/// Reads exported JSON IR one subprocedure at a time, dropping the blocks of every subprocedure whose tid id is not in
+/// selected_bodies as soon as it is read. Only the selected bodies are held in memory while the [cwe_checker_lib::pcode::Project]
+/// is built. Unselected subprocedures are kept with an empty body, so calls to them are treated as calls to an unknown target.
+pub fn stream_pcode_project(
+    rdr: impl Read,
+    selected_bodies: &BTreeSet<String>,
+) -> Result<cwe_checker_lib::pcode::Project> {
+    let mut de = serde_json::Deserializer::from_reader(rdr);
+    let filtered = BodyFilter {
+        path: SUBS_PATH,
+        selected_bodies,
+    }
+    .deserialize(&mut de)?;
+    de.end()?;
+
+    Ok(serde_json::from_value(filtered)?)
+}
+
+/// Deserializes a json value, descending along path to the subprocedure array and filtering subprocedure bodies there.
+struct BodyFilter<'a> {
+    path: &'a [&'a str],
+    selected_bodies: &'a BTreeSet<String>,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for BodyFilter<'a> {
+    type Value = Value;
+
+    fn deserialize<D>(self, deserializer: D) -> std::result::Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if self.path.is_empty() {
+            deserializer.deserialize_seq(self)
+        } else {
+            deserializer.deserialize_map(self)
+        }
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for BodyFilter<'a> {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(formatter, "an array of subprocedures")
+        } else {
+            write!(formatter, "an object containing {}", self.path.join("."))
+        }
+    }
+
+    fn visit_map<A>(self, mut map: A) -> std::result::Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut obj = serde_json::Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let value = if key == self.path[0] {
+                map.next_value_seed(BodyFilter {
+                    path: &self.path[1..],
+                    selected_bodies: self.selected_bodies,
+                })?
+            } else {
+                map.next_value::<Value>()?
+            };
+            obj.insert(key, value);
+        }
+        Ok(Value::Object(obj))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut subs = Vec::new();
+        while let Some(mut sub) = seq.next_element::<Value>()? {
+            let is_selected = sub
+                .pointer("/tid/id")
+                .and_then(Value::as_str)
+                .map(|id| self.selected_bodies.contains(id))
+                .unwrap_or(false);
+
+            if !is_selected {
+                if let Some(blocks) = sub.pointer_mut("/term/blocks") {
+                    *blocks = Value::Array(Vec::new());
+                }
+            }
+            subs.push(sub);
+        }
+        Ok(Value::Array(subs))
+    }
+}
+
+/// Gets the [Project] IR for a reader of exported JSON IR, only keeping the bodies of the subprocedures with a tid id in
+/// selected_bodies (see [stream_pcode_project]). If a layout is provided the binary is treated as a bare metal image.
+pub fn get_intermediate_representation_for_selected_reader(
+    rdr: impl Read,
+    binary: &[u8],
+    selected_bodies: &BTreeSet<String>,
+    bare_metal: Option<&BareMetalLayout>,
+) -> Result<Project> {
+    let mut pcode_proj = stream_pcode_project(rdr, selected_bodies)?;
+    let base_addr = match bare_metal {
+        Some(layout) => layout.base_address,
+        None => cwe_checker_lib::utils::get_binary_base_address(binary)?,
+    };
+    let msgs = pcode_proj.normalize();
+
+    msgs.iter().for_each(log_cwe_message);
+
+    let mut ir = pcode_proj.into_ir_project(base_addr);
+    if let Some(layout) = bare_metal {
+        ir.runtime_memory_image =
+            RuntimeMemoryImage::new_from_bare_metal(binary, &layout.to_cwe_config())?;
+    }
+    Ok(ir)
+}
+
+/// The memory layout of a bare metal binary (ie. a firmware image). Raw images have no headers to read the layout from,
+/// so the address the image is loaded at and the location of RAM must be provided.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BareMetalLayout {
+    /// The ghidra processor id the image was lifted with (ie. "ARM:LE:32:v8")
+    pub processor_id: String,
+    /// The address the first byte of the image is loaded at
+    pub base_address: u64,
+    /// The start address of RAM
+    pub ram_base_address: u64,
+    /// The size of RAM in bytes
+    pub ram_size: u64,
+}
+
+impl BareMetalLayout {
+    /// Reads a layout from a json file.
+    pub fn from_file(pth: &str) -> Result<BareMetalLayout> {
+        let fl = std::fs::File::open(pth)?;
+        serde_json::from_reader(fl)
+            .map_err(|e| anyhow::Error::from(e).context("parsing bare metal layout"))
+    }
+
+    fn to_cwe_config(&self) -> BareMetalConfig {
+        BareMetalConfig {
+            processor_id: self.processor_id.clone(),
+            flash_base_address: format!("0x{:x}", self.base_address),
+            ram_base_address: format!("0x{:x}", self.ram_base_address),
+            ram_size: format!("0x{:x}", self.ram_size),
+        }
+    }
+}
+
+/// Gets the [Project] IR for a reader of exported JSON IR and a bare metal image. The base address is taken from the layout
+/// rather than the binary, and the runtime memory image maps the image at the base address followed by the described RAM.
+pub fn get_intermediate_representation_for_bare_metal_reader(
+    rdr: impl Read,
+    binary: &[u8],
+    layout: &BareMetalLayout,
+) -> Result<Project> {
+    let mut pcode_proj: cwe_checker_lib::pcode::Project = serde_json::from_reader(rdr)?;
+    let msgs = pcode_proj.normalize();
+
+    msgs.iter().for_each(log_cwe_message);
+
+    let mut ir = pcode_proj.into_ir_project(layout.base_address);
+    ir.runtime_memory_image =
+        RuntimeMemoryImage::new_from_bare_metal(binary, &layout.to_cwe_config())?;
+    Ok(ir)
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Wraps a reader so that gzip or zstd compressed input is transparently decompressed.
+/// The compression format is detected from the leading magic bytes, other input is passed through unchanged.
+pub fn decompressing_reader<'a>(mut rdr: impl Read + 'a) -> Result<Box<dyn Read + 'a>> {
+    let mut head = Vec::new();
+    (&mut rdr)
+        .take(ZSTD_MAGIC.len() as u64)
+        .read_to_end(&mut head)?;
+
+    let rejoined = std::io::Cursor::new(head.clone()).chain(rdr);
+    if head.starts_with(GZIP_MAGIC) {
+        Ok(Box::new(flate2::read::MultiGzDecoder::new(rejoined)))
+    } else if head.starts_with(ZSTD_MAGIC) {
+        Ok(Box::new(zstd::stream::read::Decoder::new(rejoined)?))
+    } else {
+        Ok(Box::new(rejoined))
+    }
+}
+
+/// Opens an input path for reading, decompressing it if required. The path "-" reads from stdin
+/// so that large inputs can be piped without being written to disk.
+pub fn open_input(pth: &str) -> Result<Box<dyn Read>> {
+    if pth == "-" {
+        decompressing_reader(std::io::stdin())
+    } else {
+        decompressing_reader(std::io::BufReader::new(std::fs::File::open(pth)?))
+    }
+}
+
+/// The extension appended to the name of files compressed with zstd.
+pub const ZSTD_EXTENSION: &str = "zst";
+
+// Sequence prefixes are padded to the width of the largest sequence number so that names always sort in sequence order.
+const SEQUENCE_DIGITS: usize = 20;
+
+fn artifact_file_name(sequence: u64, fname: &str, compress: bool) -> String {
+    if compress {
+        format!(
+            "{:0width$}_{}.{}",
+            sequence,
+            fname,
+            ZSTD_EXTENSION,
+            width = SEQUENCE_DIGITS
+        )
+    } else {
+        format!("{:0width$}_{}", sequence, fname, width = SEQUENCE_DIGITS)
+    }
+}
+
+/// A file being written, optionally compressed with zstd. Compressed files can be read back with [open_input].
+pub enum OutputFile {
+    /// Writes directly to the file
+    Plain(std::io::BufWriter<std::fs::File>),
+    /// Compresses into the file
+    Zstd(zstd::stream::write::Encoder<'static, std::io::BufWriter<std::fs::File>>),
+}
+
+impl OutputFile {
+    /// Creates the file at pth, compressing what is written to it if compress is set.
+    pub fn create(pth: impl AsRef<Path>, compress: bool) -> Result<OutputFile> {
+        let out = std::io::BufWriter::new(std::fs::File::create(pth)?);
+        Ok(if compress {
+            OutputFile::Zstd(zstd::stream::write::Encoder::new(out, 0)?)
+        } else {
+            OutputFile::Plain(out)
+        })
+    }
+
+    /// Ends the compressed stream, if any, and flushes the file. Dropping an [OutputFile] without finishing it
+    /// leaves a truncated compressed stream.
+    pub fn finish(self) -> Result<()> {
+        match self {
+            OutputFile::Plain(mut out) => out.flush()?,
+            OutputFile::Zstd(enc) => enc.finish()?.flush()?,
+        }
+        Ok(())
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputFile::Plain(out) => out.write(buf),
+            OutputFile::Zstd(enc) => enc.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputFile::Plain(out) => out.flush(),
+            OutputFile::Zstd(enc) => enc.flush(),
+        }
+    }
+}
+
 /// Maps procedure type variables to tids
 pub fn procedure_type_variable_map(proj: &Project) -> HashMap<TypeVariable, Tid> {
     let tids = proj.program.term.subs.iter().map(|sub| {
@@ -60,14 +354,67 @@ pub fn procedure_type_variable_map(proj: &Project) -> HashMap<TypeVariable, Tid>
     tids.chain(extern_tids).collect()
 }
 
-use std::rc::Rc;
+use std::{cell::RefCell, rc::Rc};
+
+/// The name of the index of artifacts written at the root of a debug directory.
+pub const DEBUG_MANIFEST: &str = "manifest.json";
+
+/// Describes an artifact written by a [FileDebugLogger].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebugArtifact {
+    /// The position of the artifact in the order artifacts were written
+    pub sequence: u64,
+    /// The phase of the pipeline that wrote the artifact, if any
+    pub phase: Option<String>,
+    /// The name the artifact was logged with
+    pub name: String,
+    /// The path of the artifact relative to the debug directory
+    pub path: String,
+}
+
+#[derive(Default)]
+struct DebugManifest {
+    root: Option<PathBuf>,
+    artifacts: Vec<DebugArtifact>,
+}
+
+impl DebugManifest {
+    fn write(&self) -> anyhow::Result<()> {
+        if let Some(root) = &self.root {
+            std::fs::create_dir_all(root)?;
+            serde_json::to_writer_pretty(
+                std::fs::File::create(root.join(DEBUG_MANIFEST))?,
+                &self.artifacts,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// The manifest is written once the last logger sharing it is dropped.
+impl Drop for DebugManifest {
+    fn drop(&mut self) {
+        if !self.artifacts.is_empty() {
+            if let Err(err) = self.write() {
+                error!("Failed to write debug manifest: {}", err);
+            }
+        }
+    }
+}
 
 #[derive(Clone, Default)]
-/// Manages optional logging of displayable types to a file in a debug directory
+/// Manages optional logging of displayable types to a file in a debug directory.
+/// Each phase of the pipeline logs into its own subdirectory, and files are prefixed with a sequence number shared by
+/// all loggers derived from the same root so that sorting files by name matches the order they were written in.
+/// Every artifact is recorded in a manifest written to the root of the debug directory.
+/// Artifacts can be compressed with zstd, in which case their file names end in `.zst`.
 pub struct FileDebugLogger {
     debug_dir: Rc<Option<String>>,
+    phase: Option<String>,
+    compress: bool,
+    canonical_names: bool,
+    manifest: Rc<RefCell<DebugManifest>>,
 }
-
 use std::io::Write;
 
 /// Filters add constraints to only utilize subtype constraints
@@ -88,7 +435,43 @@ impl FileDebugLogger {
     /// If the target directory is [None] then no logging will occur.
     pub fn new(debug_dir: Option<String>) -> FileDebugLogger {
         FileDebugLogger {
+            manifest: Rc::new(RefCell::new(DebugManifest {
+                root: debug_dir.as_ref().map(PathBuf::from),
+                artifacts: Vec::new(),
+            })),
             debug_dir: Rc::new(debug_dir),
+            phase: None,
+            compress: false,
+            canonical_names: false,
+        }
+    }
+
+    /// Sets whether artifacts are compressed with zstd. Loggers for phases inherit the setting of the logger they were
+    /// created from.
+    pub fn with_compression(mut self, compress: bool) -> FileDebugLogger {
+        self.compress = compress;
+        self
+    }
+
+    /// Sets whether constraints logged with [FileDebugLogger::log_constraints] have their generated type variables
+    /// renamed canonically, so that dumps from different runs can be diffed. Loggers for phases inherit the setting.
+    pub fn with_canonical_names(mut self, canonical_names: bool) -> FileDebugLogger {
+        self.canonical_names = canonical_names;
+        self
+    }
+
+    /// Creates a logger for a phase of the pipeline that writes into a subdirectory named by the phase.
+    /// Phases of a phase are nested subdirectories.
+    pub fn phase(&self, name: &str) -> FileDebugLogger {
+        FileDebugLogger {
+            debug_dir: self.debug_dir.clone(),
+            phase: Some(match &self.phase {
+                Some(parent) => format!("{}/{}", parent, name),
+                None => name.to_owned(),
+            }),
+            compress: self.compress,
+            canonical_names: self.canonical_names,
+            manifest: self.manifest.clone(),
         }
     }
 
@@ -100,15 +483,69 @@ impl FileDebugLogger {
         dispalyable: &impl Fn() -> V,
     ) -> anyhow::Result<()> {
         if let Some(debug_dir) = self.debug_dir.as_ref() {
-            let mut pth = PathBuf::from(debug_dir);
-            pth.push(fname);
+            // rendered before the manifest is borrowed in case rendering logs as well
+            let content = dispalyable().to_string();
+            let mut manifest = self.manifest.borrow_mut();
+            let sequence = manifest.artifacts.len() as u64;
 
-            let mut out_file = std::fs::File::create(pth)?;
-            writeln!(&mut out_file, "{}", dispalyable())?;
+            let mut rel_pth = PathBuf::new();
+            if let Some(phase) = &self.phase {
+                rel_pth.push(phase);
+            }
+            rel_pth.push(artifact_file_name(sequence, fname, self.compress));
+
+            let pth = PathBuf::from(debug_dir).join(&rel_pth);
+            if let Some(parent) = pth.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            let mut out_file = OutputFile::create(pth, self.compress)?;
+            writeln!(&mut out_file, "{}", content)?;
+            out_file.finish()?;
+
+            manifest.artifacts.push(DebugArtifact {
+                sequence,
+                phase: self.phase.clone(),
+                name: fname.to_owned(),
+                path: rel_pth.to_string_lossy().into_owned(),
+            });
         }
         Ok(())
     }
 
+    /// The constraints as they should appear in logged artifacts: with generated type variables renamed canonically if
+    /// canonical names are enabled.
+    pub fn loggable_constraints<'a>(&self, cs: &'a ConstraintSet) -> Cow<'a, ConstraintSet> {
+        if self.canonical_names {
+            Cow::Owned(cs.canonicalize())
+        } else {
+            Cow::Borrowed(cs)
+        }
+    }
+
+    /// Logs a constraint set into a file with name fname if logging is enabled, annotating each constraint with its
+    /// origin if show_origins is set.
+    pub fn log_constraints(
+        &self,
+        fname: &str,
+        cs: &ConstraintSet,
+        show_origins: bool,
+    ) -> anyhow::Result<()> {
+        self.log_to_fname(fname, &|| {
+            let cs = self.loggable_constraints(cs);
+            if show_origins {
+                format!("{:#}", cs)
+            } else {
+                cs.to_string()
+            }
+        })
+    }
+
+    /// Writes the manifest of the artifacts logged so far. The manifest is also written when the last logger is dropped.
+    pub fn write_manifest(&self) -> anyhow::Result<()> {
+        self.manifest.borrow().write()
+    }
+
     /// Check if logging will have an effect, useful to prevent expensive operations that cant be conducted in the displayable closure.
     pub fn is_logging(&self) -> bool {
         self.debug_dir.is_some()
@@ -139,4 +576,170 @@ mod tests {
 
         ir_res.unwrap();
     }
+
+    #[test]
+    pub fn bare_metal_layouts_are_read_from_json() {
+        let dir = tempdir::TempDir::new("bare_metal").expect("should create a temporary directory");
+        let pth = dir.path().join("layout.json");
+        std::fs::write(
+            &pth,
+            r#"{"processor_id":"ARM:LE:32:v8","base_address":134217728,"ram_base_address":536870912,"ram_size":65536}"#,
+        )
+        .unwrap();
+        let layout = super::BareMetalLayout::from_file(pth.to_str().unwrap()).unwrap();
+        let config = layout.to_cwe_config();
+        assert_eq!(config.processor_id, "ARM:LE:32:v8");
+        assert_eq!(config.flash_base_address, "0x8000000");
+        assert_eq!(config.ram_base_address, "0x20000000");
+        assert_eq!(config.ram_size, "0x10000");
+
+        std::fs::write(
+            &pth,
+            r#"{"processor_id":"ARM:LE:32:v8","base_address":0,"ram_base_address":0,"ram_size":0,"flash_size":0}"#,
+        )
+        .unwrap();
+        assert!(super::BareMetalLayout::from_file(pth.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    pub fn test_get_ir_for_bare_metal_moosl() {
+        let mooosl_bin = test_utils::test_file_to_bytes("mooosl");
+        let layout = super::BareMetalLayout {
+            processor_id: "x86:LE:64:default".to_owned(),
+            base_address: 0x100000,
+            ram_base_address: 0x2000_0000,
+            ram_size: 0x1000,
+        };
+
+        let bare_metal = super::get_intermediate_representation_for_bare_metal_reader(
+            test_utils::open_test_file("new_moosl.json"),
+            &mooosl_bin[..],
+            &layout,
+        )
+        .unwrap();
+        let hosted = super::get_intermediate_representation_for_reader(
+            test_utils::open_test_file("new_moosl.json"),
+            &mooosl_bin[..],
+        )
+        .unwrap();
+
+        // the layout only changes where the image is mapped, not which subprocedures are lifted
+        assert!(!bare_metal.program.term.subs.is_empty());
+        assert_eq!(
+            bare_metal.program.term.subs.keys().collect::<Vec<_>>(),
+            hosted.program.term.subs.keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    pub fn test_get_ir_for_gzipped_moosl() {
+        use std::io::Write;
+
+        let moosljson = test_utils::test_file_to_bytes("new_moosl.json");
+        let mooosl_bin = test_utils::test_file_to_bytes("mooosl");
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&moosljson).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let rdr = super::decompressing_reader(&compressed[..]).unwrap();
+        let ir_res = super::get_intermediate_representation_for_reader(rdr, &mooosl_bin[..]);
+
+        assert!(ir_res.is_ok());
+    }
+
+    #[test]
+    pub fn test_streaming_drops_unselected_bodies() {
+        let moosljson = test_utils::open_test_file("new_moosl.json");
+        let mooosl_bin = test_utils::test_file_to_bytes("mooosl");
+        let selected = vec!["sub_001011e9".to_owned()].into_iter().collect();
+
+        let proj = super::get_intermediate_representation_for_selected_reader(
+            moosljson,
+            &mooosl_bin[..],
+            &selected,
+            None,
+        )
+        .unwrap();
+
+        assert!(proj
+            .program
+            .term
+            .subs
+            .keys()
+            .any(|tid| tid.get_str_repr() == "sub_001011e9"));
+        for (tid, sub) in proj.program.term.subs.iter() {
+            assert_eq!(
+                !sub.term.blocks.is_empty(),
+                tid.get_str_repr() == "sub_001011e9"
+            );
+        }
+    }
+
+    #[test]
+    fn debug_logger_numbers_artifacts_by_phase() {
+        let tmp =
+            tempdir::TempDir::new("debug_logger").expect("should create a temporary directory");
+        let dir = tmp.path().join("debug");
+
+        let logger = super::FileDebugLogger::new(Some(dir.to_string_lossy().into_owned()));
+        logger.phase("first").log_to_fname("a", &|| "a").unwrap();
+        logger
+            .phase("second")
+            .phase("nested")
+            .log_to_fname("a", &|| "b")
+            .unwrap();
+        drop(logger);
+
+        let manifest: Vec<super::DebugArtifact> =
+            serde_json::from_reader(std::fs::File::open(dir.join(super::DEBUG_MANIFEST)).unwrap())
+                .unwrap();
+        assert_eq!(
+            manifest.iter().map(|a| a.path.as_str()).collect::<Vec<_>>(),
+            vec![
+                "first/00000000000000000000_a",
+                "second/nested/00000000000000000001_a"
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("second/nested/00000000000000000001_a")).unwrap(),
+            "b\n"
+        );
+    }
+
+    #[test]
+    fn artifact_names_sort_in_sequence_order() {
+        let names = [9_u64, 99_999, 100_000, u64::MAX]
+            .iter()
+            .map(|seq| super::artifact_file_name(*seq, "a", false))
+            .collect::<Vec<_>>();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+    }
+
+    #[test]
+    fn compressed_debug_artifacts_read_back() {
+        use std::io::Read;
+
+        let tmp = tempdir::TempDir::new("compressed_logger")
+            .expect("should create a temporary directory");
+        let dir = tmp.path().join("debug");
+
+        let logger = super::FileDebugLogger::new(Some(dir.to_string_lossy().into_owned()))
+            .with_compression(true);
+        logger
+            .phase("first")
+            .log_to_fname("a.dot", &|| "a")
+            .unwrap();
+        drop(logger);
+
+        let pth = dir.join("first/00000000000000000000_a.dot.zst");
+        let mut content = String::new();
+        super::open_input(pth.to_str().unwrap())
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "a\n");
+    }
 }
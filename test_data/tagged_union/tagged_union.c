@@ -0,0 +1,29 @@
+#include <stdlib.h>
+
+struct shape {
+  int kind;
+  union {
+    struct {
+      int radius;
+    } circle;
+    struct {
+      long width;
+      long height;
+    } rect;
+  } u;
+};
+
+long area(struct shape *s) {
+  if (s->kind == 0) {
+    return s->u.circle.radius * 3;
+  }
+  return s->u.rect.width * s->u.rect.height;
+}
+
+int main(int argc, char **argv) {
+  struct shape *s = malloc(sizeof(struct shape));
+  s->kind = argc;
+  s->u.rect.width = 2;
+  s->u.rect.height = 3;
+  return (int)area(s);
+}